@@ -0,0 +1,118 @@
+//! Writing files so that an interrupted write never leaves them half-written.
+
+use std::{
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use tempfile::NamedTempFile;
+
+/// Writes a file so that readers only ever see either the previous or the new contents.
+///
+/// The content is first written to a temporary file in the same directory as `path` and
+/// then renamed over `path`. If `write_contents` fails, or the process is interrupted
+/// before the rename, the original file at `path` is left untouched.
+pub fn write_atomically<F>(path: &Path, write_contents: F) -> io::Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    // NOTE: the temporary file must be on the same filesystem as the target for the
+    // rename to be atomic, hence it is created in the same directory.
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let temp_file = NamedTempFile::new_in(directory)?;
+
+    {
+        let mut writer = BufWriter::new(temp_file.as_file());
+        write_contents(&mut writer)?;
+        writer.flush()?;
+    }
+    temp_file.as_file().sync_all()?;
+
+    // NOTE: the temporary file is created with 0600, which would otherwise replace the mode
+    // of the file that is being rewritten
+    match std::fs::metadata(path) {
+        Ok(metadata) => temp_file
+            .as_file()
+            .set_permissions(metadata.permissions())?,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => return Err(error),
+    }
+
+    temp_file.persist(path).map_err(|error| error.error)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn replaces_existing_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("page.md");
+        fs::write(&path, "old content").unwrap();
+
+        write_atomically(&path, |writer| writer.write_all(b"new content")).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new content");
+    }
+
+    #[test]
+    fn creates_missing_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("page.md");
+
+        write_atomically(&path, |writer| writer.write_all(b"new content")).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new content");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_permissions_of_existing_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("page.md");
+        fs::write(&path, "old content").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_atomically(&path, |writer| writer.write_all(b"new content")).unwrap();
+
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o644
+        );
+    }
+
+    #[test]
+    fn keeps_original_file_when_writing_fails_midway() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("page.md");
+        fs::write(&path, "old content").unwrap();
+
+        let result = write_atomically(&path, |writer| {
+            writer.write_all(b"partial ")?;
+            Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "simulated failure",
+            ))
+        });
+
+        assert!(result.is_err(), "the error from the writer is propagated");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old content");
+        assert_eq!(
+            fs::read_dir(directory.path()).unwrap().count(),
+            1,
+            "the temporary file is cleaned up"
+        );
+    }
+}
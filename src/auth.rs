@@ -0,0 +1,222 @@
+use std::fmt;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::config::Config;
+
+/// The profile used when `--profile` is not given.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The service the API keys are stored under in the keyring of the OS.
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "notion-edit";
+
+/// The secret of a Notion integration. It is never printed, not even in debug output.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct ApiKey(String);
+
+impl ApiKey {
+    pub fn new(key: impl Into<String>) -> Self {
+        Self(key.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ApiKey(<redacted>)")
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ApiKeyError {
+    #[error("no Notion API key for profile {profile}: pass --token, set NOTION_API_KEY, set `api_key` in the config file, or store one with `notion-edit auth set`")]
+    Missing { profile: String },
+
+    #[cfg(feature = "keyring")]
+    #[error("cannot use the keyring of the OS")]
+    Keyring(#[source] keyring::Error),
+
+    #[cfg(not(feature = "keyring"))]
+    #[error("notion-edit was built without the `keyring` feature, set `api_key` in the config file instead")]
+    KeyringUnsupported,
+}
+
+/// Where the API key can come from, in the order they are tried.
+pub struct ApiKeySources<'a> {
+    /// The key given with `--token`.
+    pub flag: Option<&'a str>,
+    /// The `NOTION_API_KEY` environment variable.
+    pub env: Option<String>,
+    pub config: &'a Config,
+    /// The profile given with `--profile`.
+    pub profile: Option<&'a str>,
+}
+
+/// The API key from the first source that has one: `--token`, `NOTION_API_KEY`, the config
+/// file (the table of the profile, or the top level without `--profile`), and then the
+/// keyring of the OS, read with `keyring` for the profile. Empty values are skipped.
+pub fn resolve_api_key(
+    sources: ApiKeySources,
+    keyring: impl FnOnce(&str) -> Result<Option<ApiKey>, ApiKeyError>,
+) -> Result<ApiKey, ApiKeyError> {
+    let profile = sources.profile.unwrap_or(DEFAULT_PROFILE);
+    let config_key = match sources.profile {
+        Some(profile) => sources
+            .config
+            .profiles
+            .get(profile)
+            .and_then(|profile| profile.api_key.clone()),
+        None => sources.config.api_key.clone(),
+    };
+
+    let key = sources
+        .flag
+        .map(ApiKey::new)
+        .into_iter()
+        .chain(sources.env.map(ApiKey::new))
+        .chain(config_key)
+        .find(|key| !key.expose().is_empty());
+    if let Some(key) = key {
+        return Ok(key);
+    }
+
+    keyring(profile)?
+        .filter(|key| !key.expose().is_empty())
+        .ok_or_else(|| ApiKeyError::Missing {
+            profile: profile.to_string(),
+        })
+}
+
+/// The API key stored in the keyring of the OS for the profile.
+#[cfg(feature = "keyring")]
+pub fn keyring_api_key(profile: &str) -> Result<Option<ApiKey>, ApiKeyError> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, profile).map_err(ApiKeyError::Keyring)?;
+    match entry.get_password() {
+        Ok(key) => Ok(Some(ApiKey::new(key))),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(error) => Err(ApiKeyError::Keyring(error)),
+    }
+}
+
+/// Without the `keyring` feature, no keys are stored in the keyring.
+#[cfg(not(feature = "keyring"))]
+pub fn keyring_api_key(_profile: &str) -> Result<Option<ApiKey>, ApiKeyError> {
+    Ok(None)
+}
+
+/// Stores the API key in the keyring of the OS for the profile.
+#[cfg(feature = "keyring")]
+pub fn store_api_key(profile: &str, key: &ApiKey) -> Result<(), ApiKeyError> {
+    keyring::Entry::new(KEYRING_SERVICE, profile)
+        .and_then(|entry| entry.set_password(key.expose()))
+        .map_err(ApiKeyError::Keyring)
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn store_api_key(_profile: &str, _key: &ApiKey) -> Result<(), ApiKeyError> {
+    Err(ApiKeyError::KeyringUnsupported)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::config::Profile;
+
+    use super::*;
+
+    fn get_config() -> Config {
+        Config {
+            api_key: Some(ApiKey::new("config-key")),
+            profiles: HashMap::from([(
+                "work".to_string(),
+                Profile {
+                    api_key: Some(ApiKey::new("work-key")),
+                },
+            )]),
+            ..Default::default()
+        }
+    }
+
+    fn no_keyring(_profile: &str) -> Result<Option<ApiKey>, ApiKeyError> {
+        Ok(None)
+    }
+
+    fn resolve(
+        flag: Option<&str>,
+        env: Option<&str>,
+        config: &Config,
+        profile: Option<&str>,
+    ) -> Result<ApiKey, ApiKeyError> {
+        resolve_api_key(
+            ApiKeySources {
+                flag,
+                env: env.map(str::to_string),
+                config,
+                profile,
+            },
+            |profile| Ok(Some(ApiKey::new(format!("keyring-{profile}")))),
+        )
+    }
+
+    #[test]
+    fn prefers_the_flag_then_the_environment_then_the_config() {
+        let config = get_config();
+
+        assert_eq!(
+            resolve(Some("flag-key"), Some("env-key"), &config, None).unwrap(),
+            ApiKey::new("flag-key")
+        );
+        assert_eq!(
+            resolve(None, Some("env-key"), &config, Some("work")).unwrap(),
+            ApiKey::new("env-key")
+        );
+        assert_eq!(
+            resolve(None, Some(""), &config, None).unwrap(),
+            ApiKey::new("config-key")
+        );
+        assert_eq!(
+            resolve(None, None, &config, Some("work")).unwrap(),
+            ApiKey::new("work-key")
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_keyring_of_the_profile() {
+        let config = get_config();
+
+        assert_eq!(
+            resolve(None, None, &config, Some("home")).unwrap(),
+            ApiKey::new("keyring-home")
+        );
+        assert_eq!(
+            resolve(None, None, &Config::default(), None).unwrap(),
+            ApiKey::new("keyring-default")
+        );
+    }
+
+    #[test]
+    fn reports_missing_keys_without_secrets() {
+        let error = resolve_api_key(
+            ApiKeySources {
+                flag: None,
+                env: None,
+                config: &get_config(),
+                profile: Some("home"),
+            },
+            no_keyring,
+        )
+        .unwrap_err();
+
+        assert!(matches!(&error, ApiKeyError::Missing { profile } if profile == "home"));
+        assert!(!format!("{:?}", get_config()).contains("config-key"));
+    }
+}
@@ -1,127 +1,733 @@
-use std::{path::PathBuf, str::FromStr};
-
-use clap::{Parser, Subcommand};
-use notion::ids::PageId;
+use std::{path::PathBuf, time::Duration};
+
+use clap::{builder::PathBufValueParser, builder::TypedValueParser, Args, Parser, Subcommand};
+use notion::ids::{BlockId, DatabaseId, PageId};
+use notion_edit::{
+    ids::{
+        parse_block_id_arg, parse_database_id_arg, parse_page_id_arg, parse_target_arg, IdArg,
+        Target,
+    },
+    markdown::{
+        from_cmark::ParseOptions,
+        notion_interop::ListSeparatorPolicy,
+        to_cmark::{ListNumbering, SerializeOptions},
+        Flavor, NewlineBehavior,
+    },
+    notion_api::retry::{DEFAULT_MAX_CONCURRENCY, DEFAULT_MAX_RETRIES},
+};
 use reqwest::Url;
-use thiserror::Error;
-use url::Host;
-use uuid::Uuid;
+
+use crate::files::{FileArg, InputFormat, OutputFormat};
 
 #[derive(Parser)]
-#[clap(author, version, about)]
+#[clap(
+    author,
+    version,
+    about,
+    after_help = "Exit codes: 1 for other failures (and differences found by `diff`), 2 for invalid arguments, 3 for a missing or rejected API key, 4 for pages or databases that do not exist or are not shared with the integration, 5 when Notion cannot be reached, 6 when the document cannot be converted, 7 when the conversion would lose something with `--strict`, 8 when `sync` wrote conflicts to resolve"
+)]
 pub struct Cli {
     #[clap(subcommand)]
     pub command: Command,
+
+    /// Directory for backups, caches, locks, and sync state.
+    /// Defaults to $NOTION_EDIT_STATE_DIR or `.notion-edit` in the current directory
+    #[clap(long = "state-dir", global = true)]
+    pub state_dir: Option<PathBuf>,
+
+    /// Path to the config file. Defaults to `~/.config/notion-edit/config.toml`
+    #[clap(long = "config", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// The Notion API key. Defaults to $NOTION_API_KEY, `api_key` in the config file, or the
+    /// key stored with `notion-edit auth set`
+    #[clap(long = "token", global = true)]
+    pub token: Option<String>,
+
+    /// The profile from the `[profiles.<name>]` table of the config file to take the API key
+    /// from
+    #[clap(long = "profile", global = true)]
+    pub profile: Option<String>,
+
+    /// Base URL of the Notion API, for example a gateway that re-signs the requests
+    /// (`http://localhost:8080/notion`). Defaults to $NOTION_API_BASE_URL, `api_base_url` in
+    /// the config file, or the public API
+    #[clap(long = "api-base-url", global = true)]
+    pub api_base_url: Option<String>,
+
+    /// Fail without changing anything when the conversion would lose something, listing
+    /// every lossy block or construct
+    #[clap(long = "strict", global = true)]
+    pub strict: bool,
+
+    /// The dialect of markdown that files are written in and read from
+    #[clap(
+        long = "flavor",
+        global = true,
+        value_enum,
+        default_value = "commonmark"
+    )]
+    pub flavor: Flavor,
+
+    /// With `--flavor obsidian`, a YAML file that maps the page names of `[[wikilinks]]` to
+    /// the URLs of the Notion pages they link to
+    #[clap(long = "wikilinks", global = true, value_name = "FILE")]
+    pub wikilinks: Option<PathBuf>,
+
+    /// Number a numbered list that follows another one, with other blocks between them, as
+    /// the continuation of that list. Pass it to both fetch and push, so the numbers written
+    /// by fetch are not reported as lost by push
+    #[clap(long = "merge-adjacent-lists", global = true)]
+    pub merge_adjacent_lists: bool,
+
+    /// Drop the empty paragraphs that Notion uses for vertical space. Fetch skips them instead
+    /// of writing `<br>` lines, and push skips empty paragraphs and standalone `<br>` lines
+    /// instead of creating empty Notion paragraphs
+    #[clap(long = "compact", global = true)]
+    pub compact: bool,
+
+    /// Log the requests sent to Notion to stderr. Repeat (`-vv`) for more details.
+    /// $RUST_LOG takes precedence
+    #[clap(short = 'v', long = "verbose", global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// The most requests sent to Notion at the same time. Higher values fetch large pages
+    /// faster, but Notion rate-limits more of the requests
+    #[clap(long = "max-concurrency", global = true, default_value_t = DEFAULT_MAX_CONCURRENCY)]
+    pub max_concurrency: usize,
+
+    /// How many times a rate limited or failed request is retried
+    #[clap(long = "max-retries", global = true, default_value_t = DEFAULT_MAX_RETRIES)]
+    pub max_retries: u32,
+
+    /// How long a single request to Notion may take, for example `30s`.
+    /// Defaults to $NOTION_EDIT_TIMEOUT or 30 seconds
+    #[clap(long = "timeout", global = true, value_parser = humantime::parse_duration)]
+    pub timeout: Option<Duration>,
+
+    /// The proxy for the requests to Notion.
+    /// Defaults to $HTTPS_PROXY, $HTTP_PROXY or $ALL_PROXY
+    #[clap(long = "proxy", global = true)]
+    pub proxy: Option<Url>,
+
+    /// The version of the Notion API to use.
+    /// Defaults to $NOTION_VERSION or the version this tool was written for
+    #[clap(long = "notion-version", global = true)]
+    pub notion_version: Option<String>,
+
+    /// Accept page and database URLs on this custom domain of a workspace, besides `notion.so`
+    /// and `*.notion.site`. Can be repeated, and adds to `allowed_hosts` in the config file
+    #[clap(long = "allow-host", global = true, value_name = "DOMAIN")]
+    pub allow_hosts: Vec<String>,
 }
 
 #[derive(Subcommand)]
 pub enum Command {
+    /// Fetch a page as markdown into a file, or to stdout with `-`
     Fetch {
+        /// The URL or ID of the page. Without it, the page is picked interactively. With a block
+        /// after `#` in the URL, only that block and the blocks nested in it are fetched
+        #[clap(value_name = "PAGE", value_parser = target_parser, conflicts_with = "page-ids")]
+        page: Option<IdArg<Target>>,
+
+        /// The file to write the page to, or `-` for stdout.
+        /// Defaults to the title of the page, for example `my-page-title.md`
+        #[clap(
+            value_name = "FILE",
+            value_parser = PathBufValueParser::new().map(FileArg::from),
+            conflicts_with = "file"
+        )]
+        output: Option<FileArg>,
+
+        /// The URL or ID of a page. Repeat it to fetch several pages at once into the
+        /// `--output-dir`
+        #[clap(short = 'p', long = "page-id", value_parser = target_parser)]
+        page_ids: Vec<IdArg<Target>>,
+
+        #[clap(
+            short = 'f',
+            long = "file",
+            value_parser = PathBufValueParser::new().map(FileArg::from)
+        )]
+        file: Option<FileArg>,
+
+        #[clap(flatten)]
+        serialize: SerializeArgs,
+
+        /// The format the page is written in
+        #[clap(long = "format", value_enum, default_value = "markdown")]
+        format: OutputFormat,
+
+        /// With `--format html`, write a complete HTML document with the title of the page
+        /// instead of a fragment
+        #[clap(long = "standalone")]
+        standalone: bool,
+
+        /// Inline the stylesheet from the file in the HTML document
+        #[clap(long = "css", value_name = "FILE", requires = "standalone")]
+        css: Option<PathBuf>,
+
+        /// With `--format text`, write the URL of links after their text
+        #[clap(long = "show-urls")]
+        show_urls: bool,
+
+        /// Download images and files into the directory and link to the local copies,
+        /// as the links to files uploaded to Notion expire after an hour
+        #[clap(long = "download-assets", value_name = "DIR")]
+        download_assets: Option<PathBuf>,
+
+        /// List the children of every block again, instead of reusing the ones cached in the
+        /// state directory for blocks that did not change
+        #[clap(long = "no-cache")]
+        no_cache: bool,
+
+        /// Write the title of the page as a `# heading` at the start of the file,
+        /// instead of `title` in the front matter
+        #[clap(long = "title-heading")]
+        title_heading: bool,
+
+        /// Keep checking the page for changes and refresh the file when it changed.
+        /// A file with local changes that were not pushed is never overwritten
+        #[clap(long = "watch")]
+        watch: bool,
+
+        /// How often the page is checked for changes with `--watch`, for example `60s`
+        #[clap(
+            long = "interval",
+            value_parser = humantime::parse_duration,
+            default_value = "60s",
+            requires = "watch"
+        )]
+        interval: Duration,
+
+        /// Also fetch the child pages of the page, and their child pages, into a directory,
+        /// with a file for each page
+        #[clap(
+            long = "recursive",
+            requires = "output-dir",
+            conflicts_with_all = &["output", "file", "watch", "format"]
+        )]
+        recursive: bool,
+
+        /// The directory to fetch the pages into, with a file for each page named after its
+        /// title. Without `--page-id` or `--recursive`, the URLs or IDs of the pages are read
+        /// from stdin, one per line
+        #[clap(
+            short = 'o',
+            long = "output-dir",
+            conflicts_with_all = &["output", "file", "watch"]
+        )]
+        output_dir: Option<PathBuf>,
+
+        /// Only fetch the blocks under the heading with this text, up to the next heading of
+        /// the same or a higher level. The file gets no front matter
+        #[clap(long = "section", conflicts_with_all = &["recursive", "watch", "title-heading"])]
+        section: Option<String>,
+
+        /// Shift the level of every heading by this many levels, for example `1` to make the
+        /// h1 of the page an h2 when the file is embedded in a larger document. Levels are
+        /// clamped to h1 and h6
+        #[clap(
+            long = "heading-offset",
+            default_value_t = 0,
+            allow_hyphen_values = true
+        )]
+        heading_offset: i8,
+
+        /// Link to the pages synced to the markdown files in the directory by the relative
+        /// paths of their files
+        #[clap(long = "links-from", value_name = "DIR")]
+        links_from: Option<PathBuf>,
+    },
+    /// Replace the content of a page with a markdown file, or with stdin with `-`
+    Push {
+        /// The URL or ID of the page. Defaults to `notion_page_id` from the front matter
+        /// of the file
+        #[clap(short = 'p', long = "page-id", value_parser = page_id_parser)]
+        page_id: Option<IdArg<PageId>>,
+
+        /// The file to push, or `-` for stdin
+        #[clap(
+            short = 'f',
+            long = "file",
+            value_parser = PathBufValueParser::new().map(FileArg::from),
+            required_unless_present = "directory"
+        )]
+        file: Option<FileArg>,
+
+        #[clap(flatten)]
+        parse: ParseArgs,
+
+        #[clap(flatten)]
+        push: PushArgs,
+
+        /// How the file should be interpreted
+        #[clap(
+            long = "input-format",
+            alias = "format",
+            value_enum,
+            default_value = "markdown"
+        )]
+        input_format: InputFormat,
+
+        /// Write a markdown summary of the structural changes made by the push to this file
+        #[clap(long = "changelog")]
+        changelog: Option<PathBuf>,
+
+        /// Keep watching the file and push it again whenever it is saved
+        #[clap(long = "watch")]
+        watch: bool,
+
+        /// Push the markdown files in a directory as child pages of the page, with
+        /// subdirectories as pages in between. Files with `notion_page_id` in the front matter
+        /// update that page, and the others get a new page
+        #[clap(
+            long = "recursive",
+            requires_all = &["directory", "page-id"],
+            conflicts_with_all = &["file", "watch", "changelog", "input-format"]
+        )]
+        recursive: bool,
+
+        /// The directory to push with `--recursive`
+        #[clap(short = 'd', long = "directory", requires = "recursive")]
+        directory: Option<PathBuf>,
+
+        /// Only replace the blocks under the heading with this text, up to the next heading of
+        /// the same or a higher level, and leave the rest of the page as it is. The title and
+        /// the properties of the page are not changed
+        #[clap(
+            long = "section",
+            conflicts_with_all = &["recursive", "title-heading", "changelog", "safe-erase"]
+        )]
+        section: Option<String>,
+
+        /// Turn the relative links to the markdown files in the directory into links to their
+        /// pages, from the page IDs in their front matter
+        #[clap(long = "links-from", value_name = "DIR")]
+        links_from: Option<PathBuf>,
+
+        /// Add the file after the content of the page instead of replacing it, so nothing is
+        /// erased. The title and the properties of the page are not changed
+        #[clap(
+            long = "append",
+            conflicts_with_all = &["prepend", "section", "recursive", "watch", "title-heading", "changelog", "safe-erase"]
+        )]
+        append: bool,
+
+        /// Add the file before the content of the page instead of replacing it. Notion only
+        /// inserts blocks after another block, so the first block of the page is recreated
+        /// after the file
+        #[clap(
+            long = "prepend",
+            conflicts_with_all = &["section", "recursive", "watch", "title-heading", "changelog", "safe-erase"]
+        )]
+        prepend: bool,
+
+        /// Continue the push of the file that failed while the blocks were being created,
+        /// instead of erasing the page and starting over
+        #[clap(
+            long = "resume",
+            conflicts_with_all = &["append", "prepend", "section", "recursive", "watch"]
+        )]
+        resume: bool,
+
+        /// Replace only the blocks nested in this block, like a toggle, and leave the rest of
+        /// the page as it is. Takes the ID of the block or its URL, with the ID after `#`.
+        /// With `--append` or `--prepend`, the file is added to the nested blocks instead
+        #[clap(
+            long = "to-block",
+            value_name = "BLOCK",
+            value_parser = block_id_parser,
+            conflicts_with_all = &["section", "recursive", "watch", "title-heading", "changelog", "safe-erase", "resume"]
+        )]
+        to_block: Option<IdArg<BlockId>>,
+
+        /// Restore the page from the trash before pushing to it, instead of refusing to push
+        #[clap(long = "unarchive")]
+        unarchive: bool,
+
+        /// Print the blocks that the push created, with their new IDs, and how many blocks
+        /// were deleted and requests sent
+        #[clap(long = "report", value_enum, conflicts_with = "recursive")]
+        report: Option<ReportFormat>,
+    },
+    /// Show how the page differs from the file, exiting with 1 when they differ
+    Diff {
+        /// The URL or ID of the page. Defaults to `notion_page_id` from the front matter
+        /// of the file
         #[clap(short = 'p', long = "page-id", value_parser = page_id_parser)]
-        page_id: PageId,
+        page_id: Option<IdArg<PageId>>,
+
+        /// The file to compare with the page, or `-` for stdin
+        #[clap(
+            short = 'f',
+            long = "file",
+            value_parser = PathBufValueParser::new().map(FileArg::from)
+        )]
+        file: FileArg,
+
+        #[clap(flatten)]
+        serialize: SerializeArgs,
+
+        /// Write the title of the page as a `# heading` at the start of the file,
+        /// instead of `title` in the front matter
+        #[clap(long = "title-heading")]
+        title_heading: bool,
+
+        /// Only print the number of added and removed lines
+        #[clap(long = "stat")]
+        stat: bool,
+    },
+    /// Check that the file can be pushed, without connecting to Notion. Prints the problems
+    /// with their line and column, exiting with 1 on errors, or on warnings with `--strict`
+    Check {
+        /// The file to check, or `-` for stdin
+        #[clap(
+            short = 'f',
+            long = "file",
+            value_parser = PathBufValueParser::new().map(FileArg::from)
+        )]
+        file: FileArg,
+
+        #[clap(flatten)]
+        parse: ParseArgs,
+    },
+    /// Fetch the page into a temporary file, open it in $VISUAL or $EDITOR, and push it when
+    /// the editor exits, if it changed
+    Edit {
+        /// The URL or ID of the page
+        #[clap(short = 'p', long = "page-id", value_parser = page_id_parser)]
+        page_id: IdArg<PageId>,
 
-        #[clap(short = 'f', long = "file")]
-        file: PathBuf,
+        /// Do not save the current content of the page to the state directory before pushing
+        /// the edits
+        #[clap(long = "no-backup")]
+        no_backup: bool,
     },
-    Push {
+    /// Create a child page from a markdown template, replacing its `{{variable}}` placeholders
+    /// with the values given with `--var`, and print the URL of the new page
+    New {
+        /// The URL or ID of the page to create the page in
+        #[clap(short = 'p', long = "parent", value_parser = page_id_parser)]
+        parent_page_id: IdArg<PageId>,
+
+        /// The markdown template. Write `\{{name}}` for a literal `{{name}}`
+        #[clap(short = 't', long = "template")]
+        template: PathBuf,
+
+        /// The value of a variable of the template, as `name=value`. Can be repeated
+        #[clap(long = "var", value_parser = parse_template_variable)]
+        variables: Vec<(String, String)>,
+
+        /// The title of the page. Defaults to the `# heading` at the start of the template,
+        /// which is then not pushed as content
+        #[clap(long = "title")]
+        title: Option<String>,
+    },
+    /// Fetch or push the pages listed in a manifest, and print the status of each page.
+    ///
+    /// When both the file and the page of a two-way entry changed, their edits are merged
+    /// and the result is written to the file and pushed. Conflicting edits are written to
+    /// the file between `<<<<<<< file` and `>>>>>>> notion` markers, and the command exits
+    /// with code 8. Resolve them and sync again to push the file.
+    Sync {
+        /// The TOML file that lists the pages, with `[[pages]]` entries of `page`, `file`
+        /// and `direction` (`fetch`, `push` or `two-way`)
+        #[clap(long = "manifest")]
+        manifest: PathBuf,
+    },
+    /// Fetch the pages of a database into a directory, with a file for each page
+    FetchDb {
+        /// The URL or ID of the database
+        #[clap(short = 'd', long = "database", value_parser = database_id_parser)]
+        database_id: IdArg<DatabaseId>,
+
+        /// The directory to fetch the pages into
+        #[clap(short = 'o', long = "output-dir")]
+        output_dir: PathBuf,
+
+        /// The name of the file of each page. `{title}` is the slugified title of the page,
+        /// `{id}` its ID and `{id8}` the first 8 characters of the ID
+        #[clap(long = "name-template", default_value = "{title}-{id8}.md")]
+        name_template: String,
+
+        /// Only fetch the pages where the property has the value, as `property=value`.
+        /// Can be repeated
+        #[clap(long = "filter", value_parser = parse_property_filter)]
+        filters: Vec<(String, String)>,
+
+        /// Only fetch the pages edited on or after the date, for example `2022-08-01`
+        #[clap(long = "since")]
+        since: Option<String>,
+
+        #[clap(flatten)]
+        serialize: SerializeArgs,
+
+        /// Write the title of each page as a `# heading` at the start of its file,
+        /// instead of `title` in the front matter
+        #[clap(long = "title-heading")]
+        title_heading: bool,
+    },
+    /// Push markdown files into a database. Files without `notion_page_id` in the front matter
+    /// become new pages, and the others update their pages
+    PushDb {
+        /// The URL or ID of the database
+        #[clap(short = 'd', long = "database", value_parser = database_id_parser)]
+        database_id: IdArg<DatabaseId>,
+
+        /// The file to push
+        #[clap(short = 'f', long = "file", required_unless_present = "directory")]
+        file: Option<PathBuf>,
+
+        /// Push all the markdown files in the directory, and the directories inside it
+        #[clap(long = "directory", conflicts_with = "file")]
+        directory: Option<PathBuf>,
+
+        #[clap(flatten)]
+        parse: ParseArgs,
+
+        #[clap(flatten)]
+        push: PushArgs,
+    },
+    /// Replace the content of a page with one of its local backups, taken before each push
+    Restore {
+        /// The URL or ID of the page
         #[clap(short = 'p', long = "page-id", value_parser = page_id_parser)]
-        page_id: PageId,
+        page_id: IdArg<PageId>,
+
+        /// The timestamp of the backup to restore. Defaults to the newest backup
+        #[clap(long = "backup")]
+        backup: Option<String>,
 
-        #[clap(short = 'f', long = "file")]
-        file: PathBuf,
+        /// Do not save the current content of the page to the state directory before restoring
+        /// the backup
+        #[clap(long = "no-backup")]
+        no_backup: bool,
+    },
+    /// Print the integration the API key belongs to, to check that the key is valid
+    Whoami,
+    /// List the pages and databases shared with the integration, with their IDs
+    List {
+        /// Only list pages and databases whose title contains the text
+        #[clap(long = "query")]
+        query: Option<String>,
+
+        /// Only list pages or only databases
+        #[clap(long = "type", value_enum)]
+        object_type: Option<ObjectType>,
+
+        #[clap(long = "format", value_enum, default_value = "table")]
+        format: ListFormat,
+    },
+    /// Manage the API keys stored in the keyring of the OS
+    Auth {
+        #[clap(subcommand)]
+        command: AuthCommand,
+    },
+    /// Manage the state directory
+    State {
+        #[clap(subcommand)]
+        command: StateCommand,
+    },
+    /// Manage the cache of fetched blocks
+    Cache {
+        #[clap(subcommand)]
+        command: CacheCommand,
+    },
+    /// Inspect the users of the workspace
+    Users {
+        #[clap(subcommand)]
+        command: UsersCommand,
     },
 }
 
-fn page_id_parser(s: &str) -> Result<PageId, String> {
-    parse_page_id(s).map_err(|error| error.to_string())
+/// How the commands that write pages as markdown convert them.
+#[derive(Args)]
+pub struct SerializeArgs {
+    /// How newlines inside Notion text are written in markdown. The default trailing
+    /// backslash is a line break that leaves no trailing whitespace for editors to trim
+    #[clap(long = "newline-behavior", value_enum, default_value_t)]
+    pub newline_behavior: NewlineBehavior,
+
+    /// What to do with an empty paragraph between two numbered lists
+    #[clap(long = "list-separators", value_enum, default_value = "split")]
+    pub list_separator_policy: ListSeparatorPolicy,
+
+    /// How the items of numbered lists are numbered
+    #[clap(long = "list-numbering", value_enum, default_value = "repeated")]
+    pub list_numbering: ListNumbering,
+
+    /// Write curly quotes, dashes and ellipses as ASCII punctuation (`'`, `"`, `--`, `---`, `...`)
+    #[clap(long = "ascii-punctuation")]
+    pub ascii_punctuation: bool,
 }
 
-#[derive(Error, Debug, PartialEq, Eq)]
-enum ParsePageIdError {
-    #[error("Invalid URL: {0}")]
-    Url(#[from] ParsePageIdFromUrlError),
+impl From<&SerializeArgs> for SerializeOptions {
+    fn from(args: &SerializeArgs) -> Self {
+        Self {
+            newline_behavior: args.newline_behavior,
+            list_numbering: args.list_numbering,
+            ascii_punctuation: args.ascii_punctuation,
+            ..Default::default()
+        }
+    }
+}
 
-    #[error("Invalid UUID: {0}")]
-    Uuid(#[from] ParsePageIdFromUuidError),
+/// How the commands that read markdown files convert them.
+#[derive(Args)]
+pub struct ParseArgs {
+    /// Which markdown line breaks become newlines inside Notion text. By default only
+    /// hard breaks do, and soft breaks are read as spaces
+    #[clap(long = "newline-behavior", value_enum, default_value_t)]
+    pub newline_behavior: NewlineBehavior,
+
+    /// Fail on headings deeper than h3 instead of converting them to h3
+    #[clap(long = "strict-headings")]
+    pub strict_headings: bool,
+
+    /// Shift the level of every heading by this many levels, for example `-1` to push
+    /// a file fetched with `--heading-offset 1`. Levels are clamped to h1 and h3
+    #[clap(
+        long = "heading-offset",
+        default_value_t = 0,
+        allow_hyphen_values = true
+    )]
+    pub heading_offset: i8,
+
+    /// Skip HTML that has no Notion equivalent instead of pushing it as an html code block
+    #[clap(long = "skip-html")]
+    pub skip_html: bool,
+
+    /// Convert straight quotes, `--`, `---` and `...` into curly quotes, dashes and ellipses
+    #[clap(long = "smart-punctuation")]
+    pub smart_punctuation: bool,
+
+    /// Deprecated: empty paragraphs and standalone `<br>` lines are pushed as empty Notion
+    /// paragraphs by default, pass `--compact` to skip them
+    #[clap(long = "keep-empty-paragraphs", hide = true)]
+    pub keep_empty_paragraphs: bool,
 }
 
-fn parse_page_id(s: &str) -> Result<PageId, ParsePageIdError> {
-    if let Ok(url) = Url::parse(s) {
-        parse_page_id_from_url(url).map_err(Into::into)
-    } else {
-        parse_page_id_from_uuid(s).map_err(Into::into)
+impl From<&ParseArgs> for ParseOptions {
+    fn from(args: &ParseArgs) -> Self {
+        Self {
+            newline_behavior: args.newline_behavior,
+            strict_headings: args.strict_headings,
+            skip_html: args.skip_html,
+            smart_punctuation: args.smart_punctuation,
+            heading_offset: args.heading_offset,
+            ..Default::default()
+        }
     }
 }
 
-#[derive(Error, Debug, PartialEq, Eq)]
-enum ParsePageIdFromUrlError {
-    #[error("Missing hostname")]
-    MissingHostname,
+/// How the commands that replace the content of pages go about it.
+#[derive(Args)]
+pub struct PushArgs {
+    /// Take the title of the page from the `# heading` at the start of the file, instead of
+    /// `title` in the front matter. The heading is not pushed as content
+    #[clap(long = "title-heading")]
+    pub title_heading: bool,
+
+    /// Keep deleting the old blocks of the page when some of them cannot be deleted,
+    /// and report all the failures at the end
+    #[clap(long = "keep-going")]
+    pub keep_going: bool,
+
+    /// Copy the current content of the page to a new child page before erasing it,
+    /// so it can be recovered if the push fails
+    #[clap(long = "safe-erase")]
+    pub safe_erase: bool,
+
+    /// Do not save the current content of the page to the state directory before erasing
+    /// it. Without it, the push stops when the page cannot be backed up
+    #[clap(long = "no-backup")]
+    pub no_backup: bool,
+
+    /// Push even when the page was edited in Notion since it was last fetched or pushed,
+    /// erasing these edits, or when the page already has the content of the file
+    #[clap(long = "force")]
+    pub force: bool,
+}
 
-    #[error("Not a Notion URL: {0}")]
-    NotNotionHostname(Host<String>),
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ObjectType {
+    Page,
+    Database,
+}
 
-    #[error("Empty path")]
-    NoPathSegments,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ListFormat {
+    Table,
+    /// An array of objects with the `object`, `id`, `title`, `parent` and `last_edited_time`
+    /// of each result, for scripts.
+    Json,
+}
 
-    #[error("Page ID missing in the URL. Expected page ID to be the 2nd segment in the path")]
-    NotEnoughPathSegments,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    Text,
+    /// An object with the `created_blocks`, each with its `block_id`, `type`, `preview` and
+    /// number of `children`, and the numbers of `deleted_blocks`, `requests` and `retries`.
+    Json,
+}
 
-    #[error("Invalid UUID in path. {0}")]
-    InvalidUuidInPath(#[source] ParsePageIdFromUuidError),
+#[derive(Subcommand)]
+pub enum UsersCommand {
+    /// Print the users of the workspace with their IDs, to help build the `[users]` config table
+    List,
+}
 
-    #[error("Invalid UUID {uuid_candidate} in query parameters. {source}")]
-    InvalidUuidInQuery {
-        #[source]
-        source: ParsePageIdFromUuidError,
+#[derive(Subcommand)]
+pub enum AuthCommand {
+    /// Store the API key read from stdin for the profile given with `--profile`
+    Set,
+}
 
-        uuid_candidate: String,
+#[derive(Subcommand)]
+pub enum StateCommand {
+    /// Remove old backups and stale locks from the state directory
+    Gc {
+        #[clap(long = "max-backup-age", value_parser = humantime::parse_duration, default_value = "30days")]
+        max_backup_age: Duration,
+
+        #[clap(long = "max-lock-age", value_parser = humantime::parse_duration, default_value = "1h")]
+        max_lock_age: Duration,
     },
 }
 
-fn parse_page_id_from_url<'a>(url: Url) -> Result<PageId, ParsePageIdFromUrlError> {
-    match url.host() {
-        None => return Err(ParsePageIdFromUrlError::MissingHostname),
-        Some(Host::Domain("www.notion.so")) => {}
-        Some(host) => return Err(ParsePageIdFromUrlError::NotNotionHostname(host.to_owned())),
-    };
-
-    let second_segment = match url.path_segments() {
-        None => Err(ParsePageIdFromUrlError::NoPathSegments),
-        Some(mut path_segments) => path_segments
-            .nth(1)
-            .ok_or(ParsePageIdFromUrlError::NotEnoughPathSegments),
-    }?;
+#[derive(Subcommand)]
+pub enum CacheCommand {
+    /// Remove the cached blocks, so the next fetches list every block again
+    Clear,
+}
 
-    let path_uuid_candidate = second_segment
-        .split('-')
-        .last()
-        .ok_or(ParsePageIdFromUrlError::NotEnoughPathSegments)?;
+fn page_id_parser(s: &str) -> Result<IdArg<PageId>, String> {
+    parse_page_id_arg(s).map_err(|error| error.to_string())
+}
 
-    let path_uuid = parse_page_id_from_uuid(path_uuid_candidate)
-        .map_err(ParsePageIdFromUrlError::InvalidUuidInPath)?;
+fn target_parser(s: &str) -> Result<IdArg<Target>, String> {
+    parse_target_arg(s).map_err(|error| error.to_string())
+}
 
-    match url.query_pairs().find(|(key, _value)| key == "p") {
-        None => Ok(path_uuid),
-        Some((_key, query_uuid_candidate)) => parse_page_id_from_uuid(&query_uuid_candidate)
-            .map_err(|error| ParsePageIdFromUrlError::InvalidUuidInQuery {
-                source: error,
-                uuid_candidate: query_uuid_candidate.to_string(),
-            }),
-    }
+fn block_id_parser(s: &str) -> Result<IdArg<BlockId>, String> {
+    parse_block_id_arg(s).map_err(|error| error.to_string())
 }
 
-#[derive(Error, Debug, PartialEq, Eq)]
-enum ParsePageIdFromUuidError {
-    #[error("Cannot parse UUID: {0}")]
-    CannotParse(#[from] uuid::Error),
+fn database_id_parser(s: &str) -> Result<IdArg<DatabaseId>, String> {
+    parse_database_id_arg(s).map_err(|error| error.to_string())
 }
 
-fn parse_page_id_from_uuid(s: &str) -> Result<PageId, ParsePageIdFromUuidError> {
-    let parsed_uuid = Uuid::try_parse(s).map_err(ParsePageIdFromUuidError::CannotParse)?;
+fn parse_property_filter(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(property, value)| (property.trim().to_string(), value.trim().to_string()))
+        .ok_or_else(|| format!("expected property=value, got {s:?}"))
+}
 
-    Ok(PageId::from_str(&parsed_uuid.hyphenated().to_string())
-        .expect("notion crate PageId does not do any validation when parsing"))
+fn parse_template_variable(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(name, value)| (name.trim().to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected name=value, got {s:?}"))
 }
 
 #[cfg(test)]
@@ -131,93 +737,186 @@ mod tests {
     use super::*;
 
     #[test]
-    fn uuid_from_valid_notion_urls() {
-        {
-            let url_with_hash = "https://www.notion.so/gregorr/Test-page-0b89a6e8f0064acc8ec6e6902b039e3a#951424222c544b4896fc599a043f0c3d";
-            assert_eq!(
-                parse_page_id(url_with_hash).unwrap().to_string(),
-                "0b89a6e8-f006-4acc-8ec6-e6902b039e3a"
-            );
-        }
-
-        {
-            let url_without_hash =
-                "https://www.notion.so/gregorr/Test-page-0b89a6e8f0064acc8ec6e6902b039e3a";
-            assert_eq!(
-                parse_page_id(url_without_hash).unwrap().to_string(),
-                "0b89a6e8-f006-4acc-8ec6-e6902b039e3a"
-            );
-        }
+    fn fetch_accepts_positional_page_and_file() {
+        let cli = Cli::try_parse_from([
+            "notion-edit",
+            "fetch",
+            "0b89a6e8f0064acc8ec6e6902b039e3a",
+            "notes.md",
+        ])
+        .unwrap();
+        let Command::Fetch {
+            page,
+            output,
+            page_ids,
+            file,
+            ..
+        } = cli.command
+        else {
+            panic!("expected the fetch command");
+        };
 
-        {
-            let url_without_page_name =
-                "https://www.notion.so/gregorr/0b89a6e8f0064acc8ec6e6902b039e3a";
-            assert_eq!(
-                parse_page_id(url_without_page_name).unwrap().to_string(),
-                "0b89a6e8-f006-4acc-8ec6-e6902b039e3a"
-            );
-        }
+        assert_eq!(
+            page.map(|page| page.check_host(&[]).unwrap().page_id.to_string()),
+            Some("0b89a6e8-f006-4acc-8ec6-e6902b039e3a".to_string())
+        );
+        assert_eq!(output, Some(FileArg::Path(PathBuf::from("notes.md"))));
+        assert!(page_ids.is_empty());
+        assert_eq!(file, None);
+
+        assert!(Cli::try_parse_from([
+            "notion-edit",
+            "fetch",
+            "0b89a6e8f0064acc8ec6e6902b039e3a",
+            "-p",
+            "0b89a6e8f0064acc8ec6e6902b039e3a",
+        ])
+        .is_err());
+    }
 
-        {
-            let database_url_with_center_peek = "https://www.notion.so/gregorr/7659d7a185384403a1d603b828a21561?v=1156113b60bd45a48187e2fb5448f5ec&p=0b89a6e8f0064acc8ec6e6902b039e3a&pm=c";
+    #[test]
+    fn fetch_diff_and_fetch_db_share_the_serialize_flags() {
+        for command in [
+            &["fetch", "0b89a6e8f0064acc8ec6e6902b039e3a"][..],
+            &["diff", "-f", "notes.md"],
+            &[
+                "fetch-db",
+                "-d",
+                "0b89a6e8f0064acc8ec6e6902b039e3a",
+                "-o",
+                "notes",
+            ],
+        ] {
+            let cli = Cli::try_parse_from(["notion-edit"].iter().chain(command).chain(&[
+                "--list-numbering",
+                "incrementing",
+                "--ascii-punctuation",
+            ]))
+            .unwrap();
+            let serialize = match cli.command {
+                Command::Fetch { serialize, .. }
+                | Command::Diff { serialize, .. }
+                | Command::FetchDb { serialize, .. } => serialize,
+                _ => panic!("expected a command that writes markdown"),
+            };
+
+            let options = SerializeOptions::from(&serialize);
             assert_eq!(
-                parse_page_id(database_url_with_center_peek)
-                    .unwrap()
-                    .to_string(),
-                "0b89a6e8-f006-4acc-8ec6-e6902b039e3a"
+                options.list_numbering,
+                ListNumbering::Incrementing,
+                "{command:?}"
             );
+            assert!(options.ascii_punctuation, "{command:?}");
         }
     }
 
     #[test]
-    fn invalid_notion_urls() {
-        {
-            let github_url = "https://github.com/";
-            assert_eq!(
-                parse_page_id(github_url),
-                Err(ParsePageIdError::Url(
-                    ParsePageIdFromUrlError::NotNotionHostname(Host::Domain(
-                        "github.com".to_string()
-                    ))
-                ))
-            );
+    fn push_check_and_push_db_share_the_parse_flags() {
+        for command in [
+            &["push", "-f", "notes.md"][..],
+            &["check", "-f", "notes.md"],
+            &[
+                "push-db",
+                "-d",
+                "0b89a6e8f0064acc8ec6e6902b039e3a",
+                "-f",
+                "notes.md",
+            ],
+        ] {
+            let cli = Cli::try_parse_from(["notion-edit"].iter().chain(command).chain(&[
+                "--heading-offset",
+                "-1",
+                "--smart-punctuation",
+            ]))
+            .unwrap();
+            let parse = match cli.command {
+                Command::Push { parse, .. }
+                | Command::Check { parse, .. }
+                | Command::PushDb { parse, .. } => parse,
+                _ => panic!("expected a command that reads markdown"),
+            };
+
+            let options = ParseOptions::from(&parse);
+            assert_eq!(options.heading_offset, -1, "{command:?}");
+            assert!(options.smart_punctuation, "{command:?}");
         }
 
-        {
-            let notion_url_without_page = "https://www.notion.so/";
-            assert_eq!(
-                parse_page_id(notion_url_without_page),
-                Err(ParsePageIdError::Url(
-                    ParsePageIdFromUrlError::NotEnoughPathSegments
-                ))
-            );
-        }
+        let cli = Cli::try_parse_from([
+            "notion-edit",
+            "push-db",
+            "-d",
+            "0b89a6e8f0064acc8ec6e6902b039e3a",
+            "-f",
+            "notes.md",
+            "--safe-erase",
+            "--no-backup",
+        ])
+        .unwrap();
+        let Command::PushDb { push, .. } = cli.command else {
+            panic!("expected the push-db command");
+        };
+        assert!(push.safe_erase && push.no_backup && !push.force);
+        assert!(Cli::try_parse_from([
+            "notion-edit",
+            "push",
+            "-f",
+            "notes.md",
+            "--section",
+            "Notes",
+            "--title-heading",
+        ])
+        .is_err());
     }
 
     #[test]
-    fn uuid_from_string() {
-        assert_eq!(
-            parse_page_id("0b89a6e8f0064acc8ec6e6902b039e3a")
-                .unwrap()
-                .to_string(),
-            "0b89a6e8-f006-4acc-8ec6-e6902b039e3a"
-        );
+    fn arguments_are_consistent() {
+        use clap::CommandFactory;
 
-        assert_eq!(
-            parse_page_id("0b89a6e8-f006-4acc-8ec6-e6902b039e3a")
-                .unwrap()
-                .to_string(),
-            "0b89a6e8-f006-4acc-8ec6-e6902b039e3a"
-        );
+        Cli::command().debug_assert();
     }
 
     #[test]
-    fn invalid_uuids() {
-        assert!(matches!(
-            parse_page_id("some invalid uuid"),
-            Err(ParsePageIdError::Uuid(
-                ParsePageIdFromUuidError::CannotParse(_)
-            ))
-        ));
+    fn accepts_page_urls_on_notion_hosts_and_allowed_domains() {
+        for url in [
+            "https://www.notion.so/gelio/Notes-0b89a6e8f0064acc8ec6e6902b039e3a",
+            "https://notion.so/gelio/Notes-0b89a6e8f0064acc8ec6e6902b039e3a",
+            "https://gelio.notion.site/Notes-0b89a6e8f0064acc8ec6e6902b039e3a",
+            "https://wiki.example.com/Notes-0b89a6e8f0064acc8ec6e6902b039e3a",
+        ] {
+            let cli = Cli::try_parse_from([
+                "notion-edit",
+                "--allow-host",
+                "wiki.example.com",
+                "edit",
+                "-p",
+                url,
+            ])
+            .unwrap();
+            let Command::Edit { page_id, .. } = cli.command else {
+                panic!("expected the edit command");
+            };
+
+            assert_eq!(
+                page_id.check_host(&cli.allow_hosts).unwrap().to_string(),
+                "0b89a6e8-f006-4acc-8ec6-e6902b039e3a",
+                "{url}"
+            );
+        }
+
+        let cli = Cli::try_parse_from([
+            "notion-edit",
+            "edit",
+            "-p",
+            "https://wiki.example.com/Notes-0b89a6e8f0064acc8ec6e6902b039e3a",
+        ])
+        .unwrap();
+        let Command::Edit { page_id, .. } = cli.command else {
+            panic!("expected the edit command");
+        };
+        assert!(page_id
+            .check_host(&cli.allow_hosts)
+            .unwrap_err()
+            .to_string()
+            .contains("--allow-host wiki.example.com"));
     }
 }
@@ -0,0 +1,195 @@
+//! Checking that files can be pushed, without connecting to Notion.
+
+use std::fmt;
+
+use crate::markdown::from_cmark::{ParseOptions, PulldownCMarkEventParser};
+use crate::markdown::frontmatter::FrontMatter;
+use crate::markdown::limits::check_limits;
+use crate::markdown::span::Span;
+
+/// How serious a [`Problem`] found by [`check_markdown`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The file can be pushed, but not everything in it survives the push.
+    Warning,
+    /// The file cannot be pushed.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem with a file that would be pushed, at the place in the file where it starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub span: Span,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Parses the markdown file like `push` does and checks the text of the blocks against the
+/// limits of Notion, without connecting to Notion. The problems are in the order of the file.
+pub fn check_markdown(file: &str, parse_options: ParseOptions) -> Vec<Problem> {
+    let (_, content) = FrontMatter::split(file);
+    // NOTE: the content is the rest of the file after the front matter
+    let front_matter = &file[..file.len() - content.len()];
+
+    let mut parser = PulldownCMarkEventParser::with_offsets(
+        pulldown_cmark::Parser::new_ext(content, parse_options.pulldown_cmark_options())
+            .into_offset_iter(),
+        content,
+        parse_options,
+    );
+    let result = parser.parse();
+
+    let span = |offset: usize| Span::at(content, offset).after(front_matter);
+    let mut problems: Vec<_> = parser
+        .warnings()
+        .iter()
+        .zip(parser.warning_offsets())
+        .map(|(warning, offset)| Problem {
+            span: span(*offset),
+            severity: Severity::Warning,
+            message: warning.to_string(),
+        })
+        .collect();
+    match result {
+        Ok(tags) => {
+            for (tag, offset) in tags.iter().zip(parser.tag_offsets()) {
+                problems.extend(check_limits(tag).into_iter().map(|error| Problem {
+                    span: span(*offset),
+                    severity: Severity::Error,
+                    message: error.to_string(),
+                }));
+            }
+        }
+        Err(error) => problems.push(Problem {
+            span: error.span().after(front_matter),
+            severity: Severity::Error,
+            message: error.to_string(),
+        }),
+    }
+    problems.sort_by_key(|problem| problem.span);
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::markdown::mentions::UserMentionMap;
+    use crate::markdown::to_cmark::to_markdown;
+    use crate::markdown::to_cmark::SerializeOptions;
+    use crate::markdown::warning::Warning;
+    use crate::page::parse_markdown;
+
+    #[test]
+    fn collects_warnings_of_a_lossy_document() {
+        let mut warnings = Vec::new();
+
+        let tags = parse_markdown(
+            "#### Deep heading\n\nSome <u>underlined</u> text\n\n![diagram](https://example.com/diagram.png)\n",
+            ParseOptions::default(),
+            &UserMentionMap::default(),
+            &mut warnings,
+        )
+        .unwrap();
+
+        assert_eq!(
+            to_markdown(&tags, &SerializeOptions::default()),
+            "### Deep heading\n\nSome underlined text"
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                Warning::HeadingLevelLowered {
+                    original_level: pulldown_cmark::HeadingLevel::H4,
+                    text: "Deep heading".to_string(),
+                },
+                Warning::InlineFormattingDropped {
+                    text: "Some underlined text".to_string(),
+                },
+                Warning::ImageSkipped {
+                    url: "https://example.com/diagram.png".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_files_at_the_positions_of_the_problems() {
+        let file = format!(
+            "---\ntitle: Notes\n---\n# Zażółć\n\n  #### Deep\n\n{}\n",
+            "a".repeat(2001)
+        );
+
+        let problems = check_markdown(&file, ParseOptions::default());
+
+        assert_eq!(
+            problems
+                .iter()
+                .map(|problem| (problem.span.to_string(), problem.severity))
+                .collect::<Vec<_>>(),
+            [
+                ("6:8".to_string(), Severity::Warning),
+                ("8:1".to_string(), Severity::Error)
+            ]
+        );
+        assert_eq!(
+            problems[1].message,
+            format!(
+                "text starting with \"{}\" is 2001 characters long, Notion allows at most 2000",
+                "a".repeat(20)
+            )
+        );
+
+        let problems = check_markdown(
+            "Intro\n\n> #### Deep\n",
+            ParseOptions {
+                strict_headings: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            problems,
+            [Problem {
+                span: Span { line: 3, column: 3 },
+                severity: Severity::Error,
+                message: "unexpected heading level h4, Notion only supports heading levels up to 3"
+                    .to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn checks_files_with_thematic_breaks() {
+        for file in ["para\n\n***\n\nmore", "para\n\n---\n\nmore", "---"] {
+            assert_eq!(
+                check_markdown(file, ParseOptions::default()),
+                [],
+                "problems in {file:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn checks_files_with_unterminated_front_matter() {
+        assert_eq!(
+            check_markdown("---\ntitle: Notes\n", ParseOptions::default()),
+            [Problem {
+                span: Span { line: 1, column: 1 },
+                severity: Severity::Error,
+                message: "front matter is missing its closing `---`".to_string(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,393 @@
+//! Fetching the pages of a database into files, and pushing files to a database.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use notion::ids::DatabaseId;
+use thiserror::Error;
+
+use crate::commands::check_strict;
+use crate::commands::fetch::{fetch_pages_to_files, FetchOptions, PageFetch};
+use crate::commands::push::{
+    get_page_id_for_file, new_page_title, push_file, write_page_id_to_file, FilePushReport,
+    PushError, PushOptions,
+};
+use crate::files::FileArg;
+use crate::markdown::frontmatter::FrontMatter;
+use crate::markdown::mentions::UserMentionMap;
+use crate::notion_api::client::{
+    title_property_value, GetDatabaseError, GetPagesError, NotionClient,
+};
+use crate::notion_api::properties::{
+    new_page_properties, query_filter, title_property, PropertyError,
+};
+use crate::page_tree::{row_file_name, PagePaths};
+use crate::state::StateDir;
+
+/// Which pages of a database are fetched and how their files are named.
+pub struct DatabaseQuery {
+    /// The properties and their values, as in `--filter property=value`.
+    pub filters: Vec<(String, String)>,
+    /// The date the pages were edited on or after.
+    pub since: Option<String>,
+    pub name_template: String,
+}
+
+#[derive(Error, Debug)]
+pub enum FetchDatabaseError {
+    #[error("could not get the database: {0}")]
+    GetDatabase(#[from] GetDatabaseError),
+
+    #[error("invalid filter: {0}")]
+    Filter(#[from] PropertyError),
+
+    #[error("could not query the database: {0}")]
+    Query(#[from] GetPagesError),
+
+    #[error("could not create {directory}: {error}")]
+    CreateDirectory { directory: String, error: io::Error },
+}
+
+/// Fetches the pages of the database that match the query into the directory, with a file
+/// for each page. A page that cannot be fetched does not stop the others.
+pub async fn fetch_database_to_directory(
+    client: &NotionClient,
+    state_dir: &StateDir,
+    database_id: &DatabaseId,
+    query: &DatabaseQuery,
+    directory: &Path,
+    options: &FetchOptions,
+    user_mentions: &UserMentionMap,
+) -> Result<Vec<PageFetch>, FetchDatabaseError> {
+    // NOTE: the schema is only needed to know the types of the filtered properties
+    let schema = if query.filters.is_empty() {
+        serde_json::Map::new()
+    } else {
+        client
+            .get_database_properties(&database_id.to_string())
+            .await?
+    };
+    let filter = query_filter(&schema, &query.filters, query.since.as_deref())?;
+    let pages = client.query_database(database_id, filter.as_ref()).await?;
+    fs::create_dir_all(directory).map_err(|error| FetchDatabaseError::CreateDirectory {
+        directory: directory.display().to_string(),
+        error,
+    })?;
+
+    let mut page_paths = PagePaths::default();
+    let pages = pages
+        .into_iter()
+        .map(|page| {
+            let file_name = row_file_name(&query.name_template, &page.id, &page.title);
+            let file = FileArg::Path(page_paths.assign_file_name(directory, &file_name));
+            (page.id, file)
+        })
+        .collect();
+
+    Ok(fetch_pages_to_files(client, state_dir, pages, options, user_mentions).await)
+}
+
+/// Pushes the file to its page in the database. When the front matter has no page ID, a new
+/// page is created with the title and the properties from the front matter, and its ID is
+/// written to the front matter.
+pub async fn push_file_to_database(
+    client: &NotionClient,
+    state_dir: &StateDir,
+    configured_users: &HashMap<String, String>,
+    database_id: &DatabaseId,
+    schema: &serde_json::Map<String, serde_json::Value>,
+    path: &Path,
+    options: &PushOptions,
+) -> Result<FilePushReport, PushError> {
+    let content = fs::read_to_string(path).map_err(|error| PushError::ReadFile {
+        file: path.display().to_string(),
+        error,
+    })?;
+    let (front_matter, markdown_content) = FrontMatter::split(&content);
+    let mut warnings = Vec::new();
+    let page_id = match front_matter.as_ref().map(FrontMatter::page_id).transpose() {
+        Ok(Some(Some(_))) => get_page_id_for_file(None, front_matter.as_ref())?,
+        Ok(_) | Err(_) => {
+            let front_matter_properties = front_matter
+                .as_ref()
+                .map(FrontMatter::properties)
+                .transpose()
+                .map_err(PushError::InvalidFrontMatter)?
+                .flatten()
+                .unwrap_or_default();
+            let mut properties =
+                new_page_properties(schema, &front_matter_properties, &mut warnings)?;
+            let title_property = title_property(schema).unwrap_or("title");
+            properties.insert(
+                title_property.to_string(),
+                title_property_value(&new_page_title(front_matter.as_ref(), path)),
+            );
+            check_strict(&warnings, options.strict)?;
+
+            let page = client.create_database_page(database_id, properties).await?;
+            write_page_id_to_file(path, front_matter, markdown_content, &page.id)?;
+            page.id
+        }
+    };
+
+    let mut file_options = options.clone();
+    file_options.page_id = Some(page_id);
+    file_options.file = FileArg::Path(path.to_path_buf());
+    let mut push = push_file(client, state_dir, configured_users, &file_options).await?;
+    // NOTE: the properties of a new page come before the content of the file
+    warnings.append(&mut push.report.warnings);
+    push.report.warnings = warnings;
+
+    Ok(push)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_partial_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::commands::fetch::FetchOptions;
+    use crate::commands::push::{PushMode, PushOptions};
+    use crate::commands::test_util::{get_client, mock_bot_user, BLOCK_ID, PAGE_ID};
+    use crate::files::{InputFormat, OutputFormat};
+    use crate::markdown::from_cmark::ParseOptions;
+    use crate::markdown::notion_interop::NotionParseOptions;
+    use crate::markdown::to_cmark::SerializeOptions;
+    use crate::markdown::to_text::TextOptions;
+    use crate::notion_api::mock_server::{get_list_json, get_paragraph_block_json};
+    use crate::page::EraseOptions;
+    use crate::state::DEFAULT_MAX_BACKUPS;
+
+    #[tokio::test]
+    async fn pushes_a_new_file_as_a_database_page() {
+        const DATABASE_ID: &str = "7659d7a1-8538-4403-a1d6-03b828a21561";
+        const NEW_PAGE_ID: &str = "1b6f2c5f-9d1e-4d5b-8d6f-7e2a3c4b5d6e";
+
+        let server = MockServer::start().await;
+        mock_bot_user(&server).await;
+        let schema = json!({
+            "Name": { "type": "title", "title": {} },
+            "Status": { "type": "status", "status": { "options": [{ "name": "Done" }] } }
+        });
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/databases/{DATABASE_ID}")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "database",
+                "properties": schema
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/notion/v1/pages"))
+            .and(body_partial_json(json!({
+                "parent": { "database_id": DATABASE_ID },
+                "properties": {
+                    "Name": { "title": [{ "text": { "content": "Launch plan" } }] },
+                    "Status": { "status": { "name": "Done" } }
+                }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "page",
+                "id": NEW_PAGE_ID,
+                "url": format!("https://www.notion.so/launch-plan-{NEW_PAGE_ID}")
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/pages/{NEW_PAGE_ID}")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "page",
+                "id": NEW_PAGE_ID,
+                "last_edited_time": "2022-08-01T12:00:00.000Z",
+                "parent": { "type": "database_id", "database_id": DATABASE_ID },
+                "properties": {
+                    "Name": {
+                        "type": "title",
+                        "title": [{ "type": "text", "plain_text": "Launch plan" }]
+                    },
+                    "Status": { "type": "status", "status": { "name": "Done" } }
+                }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{NEW_PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(Vec::new())))
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/blocks/{NEW_PAGE_ID}/children")))
+            .and(body_partial_json(json!({
+                "children": [{ "paragraph": { "rich_text": [{ "text": { "content": "Steps" } }] } }]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_paragraph_block_json(BLOCK_ID, "Steps"),
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let directory = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(directory.path().join("state"));
+        let file = directory.path().join("launch.md");
+        let front_matter = "---\ntitle: Launch plan\nproperties:\n  Status: Done\n---\n";
+        fs::write(&file, format!("{front_matter}Steps\n")).unwrap();
+
+        push_file_to_database(
+            &get_client(&server),
+            &state_dir,
+            &HashMap::new(),
+            &DatabaseId::from_str(DATABASE_ID).unwrap(),
+            schema.as_object().unwrap(),
+            &file,
+            &PushOptions {
+                page_id: None,
+                file: FileArg::Stdio,
+                parse_options: ParseOptions::default(),
+                input_format: InputFormat::Markdown,
+                title_heading: false,
+                changelog: None,
+                erase_options: EraseOptions::default(),
+                max_backups: Some(DEFAULT_MAX_BACKUPS),
+                strict: false,
+                force: false,
+                links: None,
+                section: None,
+                block_id: None,
+                mode: PushMode::Replace,
+                resume: false,
+                unarchive: false,
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            format!(
+                "---\nnotion_page_id: {NEW_PAGE_ID}\ntitle: Launch plan\nproperties:\n  Status: Done\n---\nSteps\n"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn fetches_database_pages_into_a_directory() {
+        const DATABASE_ID: &str = "7659d7a1-8538-4403-a1d6-03b828a21561";
+        const UNTITLED_ID: &str = "1b6f2c5f-9d1e-4d5b-8d6f-7e2a3c4b5d6e";
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/databases/{DATABASE_ID}")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "database",
+                "properties": {
+                    "Status": { "type": "status", "status": { "options": [{ "name": "Done" }] } }
+                }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let page_json = |id: &str, title: &str| {
+            json!({
+                "object": "page",
+                "id": id,
+                "last_edited_time": "2022-08-01T12:00:00.000Z",
+                "parent": { "type": "database_id", "database_id": DATABASE_ID },
+                "properties": {
+                    "Name": {
+                        "type": "title",
+                        "title": [{ "type": "text", "plain_text": title }]
+                    },
+                    "Status": { "type": "status", "status": { "name": "Done" } }
+                }
+            })
+        };
+        Mock::given(method("POST"))
+            .and(path(format!("/notion/v1/databases/{DATABASE_ID}/query")))
+            .and(body_partial_json(json!({
+                "filter": {
+                    "and": [
+                        { "property": "Status", "status": { "equals": "Done" } },
+                        {
+                            "timestamp": "last_edited_time",
+                            "last_edited_time": { "on_or_after": "2022-08-01" }
+                        }
+                    ]
+                }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                page_json(PAGE_ID, "Launch"),
+                page_json(UNTITLED_ID, ""),
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+        for (id, title) in [(PAGE_ID, "Launch"), (UNTITLED_ID, "")] {
+            Mock::given(method("GET"))
+                .and(path(format!("/notion/v1/pages/{id}")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(page_json(id, title)))
+                .expect(1)
+                .mount(&server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path(format!("/notion/v1/blocks/{id}/children")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                    get_paragraph_block_json(BLOCK_ID, "Row content"),
+                ])))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let directory = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(directory.path().join("state"));
+        let output_dir = directory.path().join("rows");
+        let fetches = fetch_database_to_directory(
+            &get_client(&server),
+            &state_dir,
+            &DatabaseId::from_str(DATABASE_ID).unwrap(),
+            &DatabaseQuery {
+                filters: vec![("Status".to_string(), "Done".to_string())],
+                since: Some("2022-08-01".to_string()),
+                name_template: "{title}-{id8}.md".to_string(),
+            },
+            &output_dir,
+            &FetchOptions {
+                parse_options: NotionParseOptions::default(),
+                serialize_options: SerializeOptions::default(),
+                format: OutputFormat::Markdown,
+                title_heading: false,
+                strict: false,
+                section: None,
+                block_id: None,
+                html_document: None,
+                text_options: TextOptions::default(),
+                asset_dir: None,
+                links: None,
+            },
+            &UserMentionMap::default(),
+        )
+        .await
+        .expect("successful fetch");
+
+        assert!(fetches.iter().all(|fetch| fetch.result.is_ok()));
+        assert_eq!(
+            fs::read_to_string(output_dir.join("launch-ac32e025.md")).unwrap(),
+            format!("---\ntitle: Launch\nproperties:\n  Status: Done\nnotion_page_id: {PAGE_ID}\n---\n\nRow content\n")
+        );
+        assert_eq!(
+            fs::read_to_string(output_dir.join("untitled-1b6f2c5f.md")).unwrap(),
+            format!("---\nproperties:\n  Status: Done\nnotion_page_id: {UNTITLED_ID}\n---\n\nRow content\n")
+        );
+    }
+}
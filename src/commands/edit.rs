@@ -0,0 +1,311 @@
+//! Editing pages in a text editor.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+use thiserror::Error;
+
+use crate::commands::fetch::{fetch_page_to_file, FetchError, FetchOptions, FetchReport};
+use crate::commands::get_user_mention_map;
+use crate::commands::push::{is_same_markdown, push_file, FilePushReport, PushError, PushOptions};
+use crate::editor::{self, EditorError};
+use crate::files::{FileArg, OutputFormat};
+use crate::markdown::frontmatter::FrontMatter;
+use crate::markdown::notion_interop::NotionParseOptions;
+use crate::markdown::to_cmark::SerializeOptions;
+use crate::markdown::to_text::TextOptions;
+use crate::notion_api::client::{ListUsersError, NotionClient};
+use crate::state::StateDir;
+
+#[derive(Error, Debug)]
+pub enum EditError {
+    #[error("could not create the file to edit the page in: {0}")]
+    CreateFile(#[source] io::Error),
+
+    #[error("could not resolve the users from the config: {0}")]
+    ResolveUsers(#[source] ListUsersError),
+
+    #[error("{0}")]
+    Fetch(#[from] FetchError),
+
+    #[error("{error}, so nothing was pushed. The file is kept at {file}")]
+    Editor {
+        file: String,
+        #[source]
+        error: EditorError,
+    },
+
+    #[error("could not read {file}: {error}")]
+    ReadFile { file: String, error: io::Error },
+
+    #[error("could not ask for confirmation: {0}")]
+    Confirm(#[source] io::Error),
+
+    #[error("nothing was pushed, the edits are kept in {file}")]
+    Cancelled { file: String },
+
+    #[error("could not push the edits, they are kept in {file}: {error}")]
+    Push {
+        file: String,
+        #[source]
+        error: PushError,
+    },
+}
+
+/// What `edit` fetched and pushed.
+#[derive(Debug)]
+pub struct EditReport {
+    pub fetch: FetchReport,
+    /// The push of the edited file, or `None` when the file was not changed.
+    pub push: Option<FilePushReport>,
+}
+
+/// Fetches the page from the options into a temporary file, opens it in the editor, and
+/// pushes it when it changed. `confirm` asks whether to go on before erasing the page with
+/// an empty file or edits made in Notion in the meantime.
+///
+/// The file is removed once there is nothing left to push, and kept otherwise, so the edits
+/// are not lost.
+pub async fn edit_page(
+    client: &NotionClient,
+    state_dir: &StateDir,
+    configured_users: &HashMap<String, String>,
+    editor: &str,
+    options: &PushOptions,
+    mut confirm: impl FnMut(&str) -> io::Result<bool>,
+) -> Result<EditReport, EditError> {
+    let page_id = options
+        .page_id
+        .clone()
+        .expect("the page is required to edit it");
+    let path = tempfile::Builder::new()
+        .prefix("notion-edit-")
+        .suffix(".md")
+        .tempfile()
+        .and_then(|file| file.into_temp_path().keep().map_err(|error| error.error))
+        .map_err(EditError::CreateFile)?;
+    let file = FileArg::Path(path.clone());
+    let kept_file = || path.display().to_string();
+
+    let mut warnings = Vec::new();
+    let user_mentions = get_user_mention_map(client, configured_users, &mut warnings)
+        .await
+        .map_err(EditError::ResolveUsers)?;
+    let fetch = fetch_page_to_file(
+        client,
+        state_dir,
+        &page_id,
+        &file,
+        &FetchOptions {
+            parse_options: NotionParseOptions {
+                merge_adjacent_lists: options.parse_options.merge_adjacent_lists,
+                compact: !options.parse_options.keep_empty_paragraphs,
+                ..Default::default()
+            },
+            serialize_options: SerializeOptions {
+                newline_behavior: options.parse_options.newline_behavior,
+                flavor: options.parse_options.flavor,
+                ..Default::default()
+            },
+            format: OutputFormat::Markdown,
+            title_heading: false,
+            strict: options.strict,
+            section: None,
+            block_id: None,
+            html_document: None,
+            text_options: TextOptions::default(),
+            asset_dir: None,
+            links: None,
+        },
+        &user_mentions,
+        warnings,
+        false,
+    )
+    .await?;
+    let read_file = || {
+        fs::read_to_string(&path).map_err(|error| EditError::ReadFile {
+            file: kept_file(),
+            error,
+        })
+    };
+    let fetched_content = read_file()?;
+
+    editor::edit_file(editor, &path)
+        .await
+        .map_err(|error| EditError::Editor {
+            file: kept_file(),
+            error,
+        })?;
+    // NOTE: the page may have been edited in Notion while the editor was open
+    client.invalidate(&page_id);
+    let edited_content = read_file()?;
+    if is_same_markdown(&fetched_content, &edited_content) {
+        fs::remove_file(&path).ok();
+        return Ok(EditReport { fetch, push: None });
+    }
+    if FrontMatter::split(&edited_content).1.trim().is_empty()
+        && !confirm("The file is empty. Erase the content of the page?")
+            .map_err(EditError::Confirm)?
+    {
+        return Err(EditError::Cancelled { file: kept_file() });
+    }
+
+    let mut push_options = options.clone();
+    push_options.file = file;
+    let result = match push_file(client, state_dir, configured_users, &push_options).await {
+        Err(PushError::EditedSinceSync {
+            last_edited_time, ..
+        }) => {
+            let question = format!("The page was edited in Notion at {last_edited_time}, while it was being edited here. Overwrite these edits?");
+            if !confirm(&question).map_err(EditError::Confirm)? {
+                return Err(EditError::Cancelled { file: kept_file() });
+            }
+            push_options.force = true;
+            push_file(client, state_dir, configured_users, &push_options).await
+        }
+        result => result,
+    };
+    let push = result.map_err(|error| EditError::Push {
+        file: kept_file(),
+        error,
+    })?;
+    fs::remove_file(&path).ok();
+
+    Ok(EditReport {
+        fetch,
+        push: Some(push),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::str::FromStr;
+
+    use notion::ids::PageId;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_partial_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::commands::push::{PushMode, PushOptions};
+    use crate::commands::test_util::{
+        get_client, mock_bot_user, mock_page, mock_page_children, BLOCK_ID, PAGE_ID,
+    };
+    use crate::files::InputFormat;
+    use crate::markdown::from_cmark::ParseOptions;
+    use crate::notion_api::mock_server::{get_list_json, get_paragraph_block_json};
+    use crate::page::EraseOptions;
+
+    /// Edits the page with the editor command, failing when a confirmation is asked for.
+    async fn edit_with(server: &MockServer, editor: &str) -> Result<EditReport, EditError> {
+        let directory = tempfile::tempdir().unwrap();
+
+        edit_page(
+            &get_client(server),
+            &StateDir::new(directory.path().join("state")),
+            &HashMap::new(),
+            editor,
+            &PushOptions {
+                page_id: Some(PageId::from_str(PAGE_ID).unwrap()),
+                file: FileArg::Stdio,
+                parse_options: ParseOptions::default(),
+                input_format: InputFormat::Markdown,
+                title_heading: false,
+                changelog: None,
+                erase_options: EraseOptions::default(),
+                max_backups: None,
+                strict: false,
+                force: false,
+                links: None,
+                section: None,
+                block_id: None,
+                mode: PushMode::Replace,
+                resume: false,
+                unarchive: false,
+            },
+            |question| panic!("unexpected confirmation: {question}"),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn pushes_the_page_edited_in_the_editor() {
+        let server = MockServer::start().await;
+        mock_bot_user(&server).await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_paragraph_block_json(BLOCK_ID, "Hello"),
+            ])))
+            // NOTE: fetching, checking if the page is up to date, and erasing it
+            .up_to_n_times(3)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(Vec::new())))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path(format!("/notion/v1/blocks/{BLOCK_ID}")))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .and(body_partial_json(json!({
+                "children": [{
+                    "paragraph": { "rich_text": [{ "text": { "content": "Bye" } }] }
+                }]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_paragraph_block_json(BLOCK_ID, "Bye"),
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        edit_with(&server, "sed -i s/Hello/Bye/")
+            .await
+            .expect("successful edit");
+    }
+
+    #[tokio::test]
+    async fn does_not_push_unchanged_pages_or_after_the_editor_fails() {
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        mock_page_children(&server).await;
+        for request_method in ["PATCH", "DELETE"] {
+            Mock::given(method(request_method))
+                .respond_with(ResponseTemplate::new(200))
+                .expect(0)
+                .mount(&server)
+                .await;
+        }
+        edit_with(&server, "true").await.expect("nothing to push");
+
+        server.reset().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        mock_page_children(&server).await;
+        let error = edit_with(&server, "false")
+            .await
+            .expect_err("failed editor");
+        let file = match error {
+            EditError::Editor { file, .. } => PathBuf::from(file),
+            error => panic!("unexpected error: {error}"),
+        };
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            format!("---\nnotion_page_id: {PAGE_ID}\n---\n\nHello from the gateway\n"),
+            "the file is kept"
+        );
+        fs::remove_file(file).unwrap();
+    }
+}
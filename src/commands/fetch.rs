@@ -0,0 +1,1306 @@
+//! Fetching pages into markdown, HTML or text files.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use futures::{stream, StreamExt};
+use notion::ids::{BlockId, PageId};
+use thiserror::Error;
+
+use crate::commands::push::record_sync;
+use crate::commands::{check_strict, StrictModeError};
+use crate::files::{page_file_name, FileArg, OutputFormat};
+use crate::markdown::anchors::link_heading_blocks;
+use crate::markdown::frontmatter::{FrontMatter, FRONT_MATTER_STATE_FILE};
+use crate::markdown::heading_offset::offset_headings;
+use crate::markdown::mentions::UserMentionMap;
+use crate::markdown::notion_interop::{NotionParseOptions, NotionToMarkdownParser};
+use crate::markdown::obsidian::take_front_matter_properties;
+use crate::markdown::tag::Tag;
+use crate::markdown::to_cmark::{to_markdown, SerializeOptions};
+use crate::markdown::to_html::{to_html, HtmlDocument};
+use crate::markdown::to_text::{to_text, TextOptions};
+use crate::markdown::warning::Warning;
+use crate::markdown::Flavor;
+use crate::notion_api::assets::{page_assets, DownloadPool};
+use crate::notion_api::client::{
+    get_all_block_children, get_block_with_children, GetBlockChildrenError, GetBlockError,
+    GetPagesError, NotionClient, PageImage, PageSummary,
+};
+use crate::notion_api::properties::properties_to_yaml;
+use crate::notion_api::BlockWithChildren;
+use crate::page_tree::{
+    absolute_path, child_page_ids, relative_path, FetchedPage, LinkResolver, PagePaths,
+};
+use crate::section::{find_section, SectionError};
+use crate::state::{StateDir, StateKind, LAST_SYNC_STATE_FILE};
+
+pub(super) async fn fetch_page_tags(
+    client: &NotionClient,
+    page_id: PageId,
+    parse_options: &NotionParseOptions,
+    user_mentions: &UserMentionMap,
+    warnings: &mut Vec<Warning>,
+) -> Result<Vec<Tag>, GetBlockChildrenError> {
+    let block_id: BlockId = page_id.into();
+    let page_blocks = get_all_block_children(client, &block_id).await?;
+
+    Ok(blocks_to_tags(
+        &page_blocks,
+        parse_options,
+        user_mentions,
+        warnings,
+    ))
+}
+
+pub(super) fn blocks_to_tags(
+    blocks: &[BlockWithChildren],
+    parse_options: &NotionParseOptions,
+    user_mentions: &UserMentionMap,
+    warnings: &mut Vec<Warning>,
+) -> Vec<Tag> {
+    let mut tags = NotionToMarkdownParser::new(parse_options.clone()).feed(blocks.iter());
+    let mut parsed_tags: Vec<_> = tags.by_ref().collect();
+    warnings.extend_from_slice(tags.warnings());
+    link_heading_blocks(&mut parsed_tags, tags.heading_block_ids());
+    offset_headings(&mut parsed_tags, parse_options.heading_offset, warnings);
+    user_mentions.render_user_mentions(&mut parsed_tags);
+
+    parsed_tags
+}
+
+/// How `fetch` converts the page, from its arguments.
+pub struct FetchOptions {
+    pub parse_options: NotionParseOptions,
+    pub serialize_options: SerializeOptions,
+    pub format: OutputFormat,
+    /// Whether the title is written as a `# heading` instead of in the front matter.
+    pub title_heading: bool,
+    pub strict: bool,
+    /// The heading of the only section that is fetched.
+    pub section: Option<String>,
+    /// The only block that is fetched, with the blocks nested in it.
+    pub block_id: Option<BlockId>,
+    /// The document that HTML is wrapped in, or `None` to write a fragment.
+    pub html_document: Option<HtmlDocument>,
+    pub text_options: TextOptions,
+    /// The directory that images and files are downloaded into, or `None` to link to them
+    /// in Notion.
+    pub asset_dir: Option<PathBuf>,
+    /// The synced pages, so links to them become relative links to their files, or `None`
+    /// to keep the links as they are.
+    pub links: Option<LinkResolver>,
+}
+
+/// What happened while fetching a page into a file.
+#[derive(Debug, Default)]
+pub struct FetchReport {
+    /// The parts of the page that were converted lossily.
+    pub warnings: Vec<Warning>,
+    /// Whether the page is in the trash in Notion, so it can only be pushed to after it is
+    /// restored.
+    pub archived: bool,
+}
+
+#[derive(Error, Debug)]
+pub enum FetchError {
+    #[error("could not lock the page in the state directory: {0}")]
+    Lock(#[source] io::Error),
+
+    #[error("could not read {file}: {error}")]
+    ReadFile { file: String, error: io::Error },
+
+    #[error("{file} has changes that were not pushed, so it was not overwritten")]
+    UnsyncedChanges { file: String },
+
+    #[error("could not get the page: {0}")]
+    GetPage(#[from] GetPagesError),
+
+    #[error("could not fetch the page: {0}")]
+    Fetch(#[from] GetBlockChildrenError),
+
+    #[error("could not get the block: {0}")]
+    GetBlock(#[from] GetBlockError),
+
+    #[error("{0}")]
+    Section(#[from] SectionError),
+
+    #[error(transparent)]
+    Strict(#[from] StrictModeError),
+
+    #[error("could not write the page to {file}: {error}")]
+    WriteFile { file: String, error: io::Error },
+
+    #[error("could not store the sync state in the state directory: {0}")]
+    State(#[source] io::Error),
+}
+
+/// Fetches the page and writes it to the file. With `keep_unsynced_changes`, a file that
+/// changed since it was last fetched or pushed is not overwritten.
+#[allow(clippy::too_many_arguments)]
+pub async fn fetch_page_to_file(
+    client: &NotionClient,
+    state_dir: &StateDir,
+    page_id: &PageId,
+    file: &FileArg,
+    options: &FetchOptions,
+    user_mentions: &UserMentionMap,
+    mut warnings: Vec<Warning>,
+    keep_unsynced_changes: bool,
+) -> Result<FetchReport, FetchError> {
+    if let Some(heading) = &options.section {
+        return fetch_section_to_file(
+            client,
+            page_id,
+            heading,
+            file,
+            options,
+            user_mentions,
+            warnings,
+        )
+        .await;
+    }
+    if let Some(block_id) = &options.block_id {
+        return fetch_block_to_file(
+            client,
+            page_id,
+            block_id,
+            file,
+            options,
+            user_mentions,
+            warnings,
+        )
+        .await;
+    }
+
+    let lock = state_dir.lock_page(page_id).map_err(FetchError::Lock)?;
+    let read_file_error = |error| FetchError::ReadFile {
+        file: file.to_string(),
+        error,
+    };
+    if let (true, Some(path)) = (keep_unsynced_changes, file.path()) {
+        if has_unsynced_changes(path, state_dir, page_id).map_err(read_file_error)? {
+            return Err(FetchError::UnsyncedChanges {
+                file: file.to_string(),
+            });
+        }
+    }
+    let (content, page) = render_page_for_file(
+        client,
+        state_dir,
+        page_id,
+        file,
+        options,
+        user_mentions,
+        &mut warnings,
+    )
+    .await?;
+    check_strict(&warnings, options.strict)?;
+
+    file.write(&content, io::stdout().lock())
+        .map_err(|error| FetchError::WriteFile {
+            file: file.to_string(),
+            error,
+        })?;
+    if file.path().is_some() {
+        record_sync(
+            state_dir,
+            &lock,
+            page_id,
+            Some(&content),
+            &page.last_edited_time,
+        )
+        .map_err(FetchError::State)?;
+    }
+
+    Ok(FetchReport {
+        warnings,
+        archived: page.archived,
+    })
+}
+
+/// The whole page as the content of the file, with the front matter of the file, and the
+/// page it was fetched from.
+pub(super) async fn render_page_for_file(
+    client: &NotionClient,
+    state_dir: &StateDir,
+    page_id: &PageId,
+    file: &FileArg,
+    options: &FetchOptions,
+    user_mentions: &UserMentionMap,
+    warnings: &mut Vec<Warning>,
+) -> Result<(String, PageSummary), FetchError> {
+    let front_matter =
+        get_front_matter_for_fetch(file.path(), state_dir, page_id).map_err(|error| {
+            FetchError::ReadFile {
+                file: file.to_string(),
+                error,
+            }
+        })?;
+    // NOTE: the page is got before its content, so edits made during the fetch are not
+    // mistaken for synced ones
+    let page = client.get_page(page_id).await?;
+
+    let blocks = get_all_block_children(client, &page_id.clone().into()).await?;
+    let mut tags = blocks_to_tags(&blocks, &options.parse_options, user_mentions, warnings);
+    if let (Some(links), Some(path)) = (&options.links, file.path()) {
+        links.link_files(&mut tags, path, warnings);
+    }
+    if let Some(asset_dir) = &options.asset_dir {
+        download_assets(client, &blocks, &mut tags, asset_dir, file, warnings).await;
+    }
+
+    let content = match options.format {
+        OutputFormat::Markdown => {
+            let properties = properties_to_yaml(&page.properties, warnings);
+            with_page_front_matter(
+                front_matter,
+                page_id,
+                &page,
+                &properties,
+                options.serialize_options.flavor,
+                options.title_heading,
+                &render_tags(&tags, &page.title, options),
+            )
+        }
+        _ => render_tags(&tags, &page.title, options),
+    };
+
+    Ok((content, page))
+}
+
+/// Fetches the blocks under the heading of the page and writes them to the file, without
+/// front matter.
+// NOTE: the sync state is left as it is, as it is about the whole page
+async fn fetch_section_to_file(
+    client: &NotionClient,
+    page_id: &PageId,
+    heading: &str,
+    file: &FileArg,
+    options: &FetchOptions,
+    user_mentions: &UserMentionMap,
+    warnings: Vec<Warning>,
+) -> Result<FetchReport, FetchError> {
+    let blocks = get_all_block_children(client, &page_id.clone().into()).await?;
+    let section = find_section(blocks.iter().map(|block| &block.block), heading)?;
+
+    write_blocks_to_file(
+        client,
+        &blocks[section.blocks],
+        &section.heading.text,
+        file,
+        options,
+        user_mentions,
+        warnings,
+    )
+    .await
+}
+
+/// Fetches the block with the blocks nested in it and writes them to the file, without
+/// front matter.
+// NOTE: the sync state is left as it is, as it is about the whole page
+async fn fetch_block_to_file(
+    client: &NotionClient,
+    page_id: &PageId,
+    block_id: &BlockId,
+    file: &FileArg,
+    options: &FetchOptions,
+    user_mentions: &UserMentionMap,
+    warnings: Vec<Warning>,
+) -> Result<FetchReport, FetchError> {
+    let page = client.get_page(page_id).await?;
+    let block = client.get_block(block_id).await?;
+    let block = get_block_with_children(client, block).await?;
+
+    let report = write_blocks_to_file(
+        client,
+        std::slice::from_ref(&block),
+        &page.title,
+        file,
+        options,
+        user_mentions,
+        warnings,
+    )
+    .await?;
+
+    Ok(FetchReport {
+        archived: page.archived,
+        ..report
+    })
+}
+
+/// Converts a part of a page and writes it to the file, without front matter.
+async fn write_blocks_to_file(
+    client: &NotionClient,
+    blocks: &[BlockWithChildren],
+    title: &str,
+    file: &FileArg,
+    options: &FetchOptions,
+    user_mentions: &UserMentionMap,
+    mut warnings: Vec<Warning>,
+) -> Result<FetchReport, FetchError> {
+    let mut tags = blocks_to_tags(blocks, &options.parse_options, user_mentions, &mut warnings);
+    if let (Some(links), Some(path)) = (&options.links, file.path()) {
+        links.link_files(&mut tags, path, &mut warnings);
+    }
+    if let Some(asset_dir) = &options.asset_dir {
+        download_assets(client, blocks, &mut tags, asset_dir, file, &mut warnings).await;
+    }
+    let content = render_tags(&tags, title, options);
+    check_strict(&warnings, options.strict)?;
+
+    file.write(&content, io::stdout().lock())
+        .map_err(|error| FetchError::WriteFile {
+            file: file.to_string(),
+            error,
+        })?;
+
+    Ok(FetchReport {
+        warnings,
+        ..Default::default()
+    })
+}
+
+/// The tags in the output format of the options, without front matter.
+fn render_tags(tags: &[Tag], title: &str, options: &FetchOptions) -> String {
+    match options.format {
+        OutputFormat::Markdown => format!("{}\n", to_markdown(tags, &options.serialize_options)),
+        OutputFormat::Json => format!(
+            "{}\n",
+            serde_json::to_string_pretty(tags).expect("tags can be serialized")
+        ),
+        OutputFormat::Html => {
+            with_html_document(options, title, to_html(tags, &options.serialize_options))
+        }
+        OutputFormat::Text => to_text(tags, &options.text_options),
+    }
+}
+
+/// Downloads the images and files of the blocks into the asset directory and points the tags
+/// at the local copies, relative to the directory of the file. Assets that could not be
+/// downloaded keep their Notion URL, with a warning.
+async fn download_assets(
+    client: &NotionClient,
+    blocks: &[BlockWithChildren],
+    tags: &mut [Tag],
+    asset_dir: &Path,
+    file: &FileArg,
+    warnings: &mut Vec<Warning>,
+) {
+    let assets = page_assets(blocks);
+    if assets.is_empty() {
+        return;
+    }
+
+    let file_directory = absolute_path(
+        file.path()
+            .and_then(Path::parent)
+            .unwrap_or_else(|| Path::new("")),
+    );
+    let mut local_paths = HashMap::new();
+    for (asset, result) in DownloadPool::new(client.max_concurrency())
+        .download_all(&assets, asset_dir)
+        .await
+    {
+        match result {
+            Ok(path) => {
+                local_paths.insert(
+                    asset.url.clone(),
+                    relative_path(&file_directory, &absolute_path(&path)),
+                );
+            }
+            Err(error) => warnings.push(Warning::AssetNotDownloaded {
+                url: asset.url.clone(),
+                error: error.to_string(),
+            }),
+        }
+    }
+
+    for tag in tags {
+        tag.visit_image_urls_mut(&mut |url| {
+            if let Some(local_path) = local_paths.get(url.as_str()) {
+                *url = local_path.clone();
+            }
+        });
+        tag.visit_rich_text_mut(&mut |text| {
+            for link in text
+                .iter_mut()
+                .filter_map(|rich_text| rich_text.link.as_mut())
+            {
+                if let Some(local_path) = local_paths.get(link.as_str()) {
+                    *link = local_path.clone();
+                }
+            }
+        });
+    }
+}
+
+/// The HTML fragment wrapped in a document with the title when the options ask for one.
+fn with_html_document(options: &FetchOptions, title: &str, fragment: String) -> String {
+    match &options.html_document {
+        Some(document) => document.wrap(title, &fragment),
+        None => fragment,
+    }
+}
+
+/// A page fetched along with other pages.
+pub struct PageFetch {
+    pub page_id: PageId,
+    /// The file of the page, or `None` when the page could not be got to name its file.
+    pub file: Option<FileArg>,
+    /// How long the page took to fetch, including the wait for other pages' requests.
+    pub duration: Duration,
+    pub result: Result<FetchReport, FetchError>,
+}
+
+/// Fetches the pages into their files concurrently, in the order of `pages`. The pages share
+/// the concurrency limit of the client, and a page that cannot be fetched does not stop the
+/// others.
+pub async fn fetch_pages_to_files(
+    client: &NotionClient,
+    state_dir: &StateDir,
+    pages: Vec<(PageId, FileArg)>,
+    options: &FetchOptions,
+    user_mentions: &UserMentionMap,
+) -> Vec<PageFetch> {
+    stream::iter(pages)
+        .map(|(page_id, file)| async move {
+            let start = Instant::now();
+            let result = fetch_page_to_file(
+                client,
+                state_dir,
+                &page_id,
+                &file,
+                options,
+                user_mentions,
+                Vec::new(),
+                false,
+            )
+            .await;
+
+            PageFetch {
+                page_id,
+                file: Some(file),
+                duration: start.elapsed(),
+                result,
+            }
+        })
+        .buffered(client.max_concurrency())
+        .collect()
+        .await
+}
+
+/// Fetches the pages concurrently into the directory, with a file for each page named after
+/// its title.
+pub async fn fetch_pages_to_directory(
+    client: &NotionClient,
+    state_dir: &StateDir,
+    page_ids: Vec<PageId>,
+    directory: &Path,
+    options: &FetchOptions,
+    user_mentions: &UserMentionMap,
+) -> io::Result<Vec<PageFetch>> {
+    fs::create_dir_all(directory)?;
+
+    // NOTE: the titles are got first, so pages with the same title get numbered files
+    // in the order they were given
+    let titles: Vec<_> = stream::iter(&page_ids)
+        .map(|page_id| async move {
+            let start = Instant::now();
+            (client.get_page(page_id).await, start.elapsed())
+        })
+        .buffered(client.max_concurrency())
+        .collect()
+        .await;
+
+    let mut page_paths = PagePaths::default();
+    let mut pages = Vec::new();
+    let mut failed_pages = Vec::new();
+    for (index, (page_id, (page, duration))) in page_ids.into_iter().zip(titles).enumerate() {
+        match page {
+            Ok(page) => {
+                let file = page_paths.assign_file_name(directory, &page_file_name(&page.title));
+                pages.push((page_id, FileArg::Path(file)));
+            }
+            Err(error) => failed_pages.push((
+                index,
+                PageFetch {
+                    page_id,
+                    file: None,
+                    duration,
+                    result: Err(error.into()),
+                },
+            )),
+        }
+    }
+
+    let mut fetches = fetch_pages_to_files(client, state_dir, pages, options, user_mentions).await;
+    for (index, failed_page) in failed_pages {
+        fetches.insert(index, failed_page);
+    }
+
+    Ok(fetches)
+}
+
+/// Fetches the page and its child pages, recursively, into the directory, with a file for
+/// each page. Links between the fetched pages point to their files.
+pub async fn fetch_page_tree_to_directory(
+    client: &NotionClient,
+    state_dir: &StateDir,
+    root_page_id: &PageId,
+    directory: &Path,
+    options: &FetchOptions,
+    user_mentions: &UserMentionMap,
+    mut warnings: Vec<Warning>,
+) -> Result<FetchReport, FetchError> {
+    let parse_options = NotionParseOptions {
+        child_page_links: true,
+        ..options.parse_options.clone()
+    };
+    let mut pages = Vec::new();
+    let mut page_paths = PagePaths::default();
+    let mut fetched_page_ids = HashSet::new();
+    let mut pages_to_fetch = VecDeque::from([(root_page_id.clone(), None::<PathBuf>)]);
+
+    while let Some((page_id, parent_path)) = pages_to_fetch.pop_front() {
+        // NOTE: Notion pages have a single parent, but links between pages can form loops,
+        // so pages are never fetched twice
+        if !fetched_page_ids.insert(page_id.to_string()) {
+            continue;
+        }
+
+        let page = match client.get_page(&page_id).await {
+            Ok(page) => page,
+            Err(GetPagesError::Api(error))
+                if parent_path.is_some() && matches!(error.status, 403 | 404) =>
+            {
+                warnings.push(Warning::PageNotAccessible {
+                    id: page_id.to_string(),
+                });
+                continue;
+            }
+            Err(error) => return Err(error.into()),
+        };
+        let blocks = get_all_block_children(client, &page_id.clone().into()).await?;
+        let path = page_paths.assign(parent_path.as_deref(), &page.title);
+        pages_to_fetch.extend(
+            child_page_ids(&blocks)
+                .into_iter()
+                .map(|child_page_id| (child_page_id, Some(path.clone()))),
+        );
+
+        let mut tags = blocks_to_tags(&blocks, &parse_options, user_mentions, &mut warnings);
+        if let Some(asset_dir) = &options.asset_dir {
+            let file = FileArg::Path(directory.join(&path));
+            download_assets(client, &blocks, &mut tags, asset_dir, &file, &mut warnings).await;
+        }
+
+        pages.push(FetchedPage {
+            id: page_id,
+            path,
+            properties: properties_to_yaml(&page.properties, &mut warnings),
+            page,
+            tags,
+        });
+    }
+    let mut links = options.links.clone().unwrap_or_default();
+    for page in &pages {
+        links.insert(page.id.clone(), &directory.join(&page.path));
+    }
+    for page in &mut pages {
+        links.link_files(&mut page.tags, &directory.join(&page.path), &mut warnings);
+    }
+    check_strict(&warnings, options.strict)?;
+
+    for page in pages {
+        let file = FileArg::Path(directory.join(&page.path));
+        let lock = state_dir.lock_page(&page.id).map_err(FetchError::Lock)?;
+        let front_matter =
+            get_front_matter_for_fetch(file.path(), state_dir, &page.id).map_err(|error| {
+                FetchError::ReadFile {
+                    file: file.to_string(),
+                    error,
+                }
+            })?;
+        let mut markdown_content = to_markdown(&page.tags, &options.serialize_options);
+        markdown_content.push('\n');
+        let content = with_page_front_matter(
+            front_matter,
+            &page.id,
+            &page.page,
+            &page.properties,
+            options.serialize_options.flavor,
+            options.title_heading,
+            &markdown_content,
+        );
+
+        file.path()
+            .and_then(Path::parent)
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| file.write(&content, io::stdout().lock()))
+            .map_err(|error| FetchError::WriteFile {
+                file: file.to_string(),
+                error,
+            })?;
+        record_sync(
+            state_dir,
+            &lock,
+            &page.id,
+            Some(&content),
+            &page.page.last_edited_time,
+        )
+        .map_err(FetchError::State)?;
+    }
+
+    Ok(FetchReport {
+        warnings,
+        ..Default::default()
+    })
+}
+
+/// The markdown content with the front matter that has the ID of the page, its title, icon,
+/// cover and `properties`. With `title_heading`, the title is a `# heading` before the content
+/// instead. With the Obsidian flavor, the `Tags` and `Aliases` properties are written as the
+/// `tags` and `aliases` keys.
+fn with_page_front_matter(
+    front_matter: Option<FrontMatter>,
+    page_id: &PageId,
+    page: &PageSummary,
+    properties: &serde_yaml::Mapping,
+    flavor: Flavor,
+    title_heading: bool,
+    markdown_content: &str,
+) -> String {
+    let page_id = page_id.to_string();
+    let mut properties = properties.clone();
+    let obsidian_keys = match flavor {
+        Flavor::Commonmark => serde_yaml::Mapping::new(),
+        Flavor::Obsidian => take_front_matter_properties(&mut properties),
+    };
+    let mut front_matter = front_matter
+        .map_or_else(
+            || FrontMatter::for_page_id(&page_id),
+            |front_matter| front_matter.with_page_id(&page_id),
+        )
+        .with_icon(page.icon.as_ref().and_then(PageImage::front_matter_value))
+        .with_cover(page.cover.as_ref().and_then(PageImage::front_matter_value))
+        .with_properties(&properties);
+    for (key, value) in &obsidian_keys {
+        let key = key.as_str().expect("the Obsidian keys are strings");
+        front_matter = front_matter.with_yaml(key, Some(value));
+    }
+
+    // NOTE: untitled pages do not get an empty title that would be pushed back
+    if title_heading {
+        front_matter.prepend_to(&with_title_heading(&page.title, markdown_content))
+    } else if page.title.is_empty() {
+        front_matter.prepend_to(markdown_content)
+    } else {
+        front_matter
+            .with_title(&page.title)
+            .prepend_to(markdown_content)
+    }
+}
+
+/// The markdown content with the title of the page as a `# heading` at the start.
+pub fn with_title_heading(title: &str, markdown_content: &str) -> String {
+    if title.is_empty() {
+        return markdown_content.to_string();
+    }
+
+    format!("# {title}\n\n{markdown_content}")
+}
+
+/// Whether the file changed since it was last fetched or pushed. A missing file has no changes.
+pub(super) fn has_unsynced_changes(
+    file: &Path,
+    state_dir: &StateDir,
+    page_id: &PageId,
+) -> io::Result<bool> {
+    let content = match fs::read_to_string(file) {
+        Ok(content) => content,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(error) => return Err(error),
+    };
+    let last_synced_content =
+        state_dir.read_page_file(StateKind::State, page_id, LAST_SYNC_STATE_FILE)?;
+
+    Ok(last_synced_content.as_deref() != Some(content.as_str()))
+}
+
+/// The front matter to write at the top of the fetched file: the one already in the file,
+/// or the one kept from the last push. There is no file when writing to stdout.
+fn get_front_matter_for_fetch(
+    file: Option<&Path>,
+    state_dir: &StateDir,
+    page_id: &PageId,
+) -> io::Result<Option<FrontMatter>> {
+    match file.map(fs::read_to_string) {
+        Some(Ok(contents)) => {
+            if let (Some(front_matter), _) = FrontMatter::split(&contents) {
+                return Ok(Some(front_matter));
+            }
+        }
+        Some(Err(error)) if error.kind() == io::ErrorKind::NotFound => {}
+        Some(Err(error)) => return Err(error),
+        None => {}
+    }
+
+    Ok(state_dir
+        .read_page_file(StateKind::State, page_id, FRONT_MATTER_STATE_FILE)?
+        .and_then(|raw| FrontMatter::from_raw(&raw)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::commands::test_util::{
+        get_child_page_block_json, get_client, get_file_block_json, mock_page, mock_page_children,
+        mock_toggle, BLOCK_ID, LINK_TO_OTHER_PAGE, PAGE_ID,
+    };
+    use crate::commands::StrictModeError;
+    use crate::ids::parse_page_id;
+    use crate::markdown::text_diff::DocumentDiff;
+    use crate::notion_api::mock_server::{self, get_list_json, get_paragraph_block_json};
+    use crate::page::{convert_page_to_markdown, write_page_markdown};
+
+    #[tokio::test]
+    async fn fetches_page_through_custom_base_url() {
+        let server = MockServer::start().await;
+        mock_page_children(&server).await;
+
+        let markdown = convert_page_to_markdown(
+            &get_client(&server),
+            PageId::from_str(PAGE_ID).unwrap(),
+            &NotionParseOptions::default(),
+            &SerializeOptions::default(),
+            &UserMentionMap::default(),
+            &mut Vec::new(),
+        )
+        .await
+        .expect("successful fetch");
+
+        assert_eq!(markdown, "Hello from the gateway\n");
+    }
+
+    #[tokio::test]
+    async fn writes_long_pages_while_fetching_them() {
+        /// Records every write, to check that the page is written a part at a time.
+        #[derive(Default)]
+        struct ChunkWriter(Vec<String>);
+
+        impl io::Write for ChunkWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.push(String::from_utf8(buf.to_vec()).unwrap());
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // NOTE: the list crosses the boundary between the second and the third page of blocks
+        let is_list_item = |index: usize| index == 199 || index == 200;
+        let server = MockServer::start().await;
+        mock_server::mount_block_children(
+            &server,
+            PAGE_ID,
+            (0..1000)
+                .map(|index| {
+                    let id = format!("{index:032x}");
+                    if is_list_item(index) {
+                        mock_server::get_block_json(
+                            &id,
+                            "numbered_list_item",
+                            &format!("Item {index}"),
+                        )
+                    } else {
+                        get_paragraph_block_json(&id, &format!("Paragraph {index}"))
+                    }
+                })
+                .collect(),
+        )
+        .await;
+
+        let mut output = ChunkWriter::default();
+        write_page_markdown(
+            &mock_server::get_client(&server),
+            PageId::from_str(PAGE_ID).unwrap(),
+            &NotionParseOptions::default(),
+            &SerializeOptions::default(),
+            &UserMentionMap::default(),
+            &mut Vec::new(),
+            &mut output,
+        )
+        .await
+        .expect("successful fetch");
+
+        let paragraphs = |indices: std::ops::Range<usize>| -> Vec<String> {
+            indices.map(|index| format!("Paragraph {index}")).collect()
+        };
+        let expected = [
+            paragraphs(0..199).join("\n\n"),
+            "1. Item 199\n1. Item 200".to_string(),
+            paragraphs(201..1000).join("\n\n"),
+        ]
+        .join("\n\n")
+            + "\n";
+        assert_eq!(output.0.concat(), expected);
+        assert!(
+            output.0.len() >= 10,
+            "written a page of blocks at a time, in {} part(s)",
+            output.0.len()
+        );
+    }
+
+    #[tokio::test]
+    async fn fetched_file_does_not_differ_from_the_page() {
+        let server = MockServer::start().await;
+        mock_page_children(&server).await;
+        let fetched_file = FrontMatter::for_page_id(PAGE_ID).prepend_to("Hello from the gateway\n");
+
+        let page_content = convert_page_to_markdown(
+            &get_client(&server),
+            PageId::from_str(PAGE_ID).unwrap(),
+            &NotionParseOptions::default(),
+            &SerializeOptions::default(),
+            &UserMentionMap::default(),
+            &mut Vec::new(),
+        )
+        .await
+        .expect("successful fetch");
+
+        assert!(DocumentDiff::new(&page_content, &fetched_file).is_empty());
+    }
+
+    #[tokio::test]
+    async fn strict_fetch_lists_every_lossy_block_and_writes_nothing() {
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        let mut underlined = get_paragraph_block_json("1c2d3e4f5a6b4c7d8e9f0a1b2c3d4e5f", "Note");
+        underlined["paragraph"]["rich_text"][0]["annotations"] = json!({
+            "bold": false,
+            "italic": false,
+            "strikethrough": false,
+            "underline": true,
+            "code": false,
+            "color": "default"
+        });
+        let mut highlighted =
+            get_paragraph_block_json("8d4e2f603c1b4a7e9f2d5b6a7c8d9e0f", "Warning");
+        highlighted["paragraph"]["color"] = json!("red_background");
+        let mut unsupported = get_paragraph_block_json("0b89a6e8f0064acc8ec6e6902b039e3a", "");
+        unsupported["type"] = json!("unsupported");
+        unsupported["unsupported"] = unsupported["paragraph"].take();
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                underlined,
+                highlighted,
+                get_child_page_block_json("1b6f2c5f9d1e4d5b8d6f7e2a3c4b5d6e", "Meetings"),
+                unsupported,
+            ])))
+            .mount(&server)
+            .await;
+
+        let directory = tempfile::tempdir().unwrap();
+        let file = directory.path().join("page.md");
+        let result = fetch_page_to_file(
+            &get_client(&server),
+            &StateDir::new(directory.path().join("state")),
+            &PageId::from_str(PAGE_ID).unwrap(),
+            &FileArg::Path(file.clone()),
+            &FetchOptions {
+                parse_options: NotionParseOptions::default(),
+                serialize_options: SerializeOptions::default(),
+                format: OutputFormat::Markdown,
+                title_heading: false,
+                strict: true,
+                section: None,
+                block_id: None,
+                html_document: None,
+                text_options: TextOptions::default(),
+                asset_dir: None,
+                links: None,
+            },
+            &UserMentionMap::default(),
+            Vec::new(),
+            false,
+        )
+        .await;
+
+        let Err(FetchError::Strict(StrictModeError(warnings))) = result else {
+            panic!("expected a strict mode error, got {result:?}");
+        };
+        assert_eq!(
+            warnings.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "formatting (underline) of block 1c2d3e4f5a6b4c7d8e9f0a1b2c3d4e5f was dropped, markdown cannot express it",
+                "formatting (red_background) of block 8d4e2f603c1b4a7e9f2d5b6a7c8d9e0f was dropped, markdown cannot express it",
+                "child page \"Meetings\" (1b6f2c5f9d1e4d5b8d6f7e2a3c4b5d6e) was skipped, markdown cannot contain pages",
+                "unsupported block 0b89a6e8f0064acc8ec6e6902b039e3a was skipped, it has no markdown equivalent",
+            ]
+        );
+        assert!(!file.exists());
+    }
+
+    #[tokio::test]
+    async fn fetches_child_pages_into_a_directory() {
+        const ROOT_ID: &str = "ac32e025-6f9c-4fab-8b9d-dbb3c593ac46";
+        const CHILD_ID: &str = "1b6f2c5f-9d1e-4d5b-8d6f-7e2a3c4b5d6e";
+        const PRIVATE_ID: &str = "8d4e2f60-3c1b-4a7e-9f2d-5b6a7c8d9e0f";
+
+        let server = MockServer::start().await;
+        for (id, title) in [(ROOT_ID, "Team notes"), (CHILD_ID, "Meetings")] {
+            Mock::given(method("GET"))
+                .and(path(format!("/notion/v1/pages/{id}")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "object": "page",
+                    "id": id,
+                    "last_edited_time": "2022-08-01T12:00:00.000Z",
+                    "properties": {
+                        "title": {
+                            "type": "title",
+                            "title": [{ "type": "text", "plain_text": title }]
+                        }
+                    }
+                })))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/pages/{PRIVATE_ID}")))
+            .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+                "object": "error",
+                "status": 404,
+                "code": "object_not_found",
+                "message": "Could not find page"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{ROOT_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_child_page_block_json(CHILD_ID, "Meetings"),
+                get_child_page_block_json(PRIVATE_ID, "Private"),
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut link_back = get_paragraph_block_json(BLOCK_ID, "Back");
+        link_back["paragraph"]["rich_text"][0]["text"]["link"] =
+            json!({ "url": "/ac32e0256f9c4fab8b9ddbb3c593ac46" });
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{CHILD_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![link_back])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let directory = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(directory.path().join("state"));
+        let output_dir = directory.path().join("notes");
+        fetch_page_tree_to_directory(
+            &get_client(&server),
+            &state_dir,
+            &parse_page_id(ROOT_ID).unwrap(),
+            &output_dir,
+            &FetchOptions {
+                parse_options: NotionParseOptions::default(),
+                serialize_options: SerializeOptions::default(),
+                format: OutputFormat::Markdown,
+                title_heading: false,
+                strict: false,
+                section: None,
+                block_id: None,
+                html_document: None,
+                text_options: TextOptions::default(),
+                asset_dir: None,
+                links: None,
+            },
+            &UserMentionMap::default(),
+            Vec::new(),
+        )
+        .await
+        .expect("successful fetch");
+
+        assert_eq!(
+            fs::read_to_string(output_dir.join("team-notes.md")).unwrap(),
+            format!("---\ntitle: Team notes\nnotion_page_id: {ROOT_ID}\n---\n\n[Meetings](team-notes/meetings.md)\n\n[Private](https://www.notion.so/8d4e2f603c1b4a7e9f2d5b6a7c8d9e0f)\n")
+        );
+        assert_eq!(
+            fs::read_to_string(output_dir.join("team-notes/meetings.md")).unwrap(),
+            format!("---\ntitle: Meetings\nnotion_page_id: {CHILD_ID}\n---\n\n[Back](../team-notes.md)\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn fetch_downloads_assets_next_to_the_file() {
+        const IMAGE_ID: &str = "1b6f2c5f-9d1e-4d5b-8d6f-7e2a3c4b5d6e";
+        const PDF_ID: &str = "8d4e2f60-3c1b-4a7e-9f2d-5b6a7c8d9e0f";
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/pages/{PAGE_ID}")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "page",
+                "id": PAGE_ID,
+                "last_edited_time": "2022-08-01T12:00:00.000Z",
+                "properties": {
+                    "title": {
+                        "type": "title",
+                        "title": [{ "type": "text", "plain_text": "Diagrams" }]
+                    }
+                }
+            })))
+            .mount(&server)
+            .await;
+        let image_url = format!("{}/files/diagram.png?X-Amz-Signature=abc", server.uri());
+        let file_url = format!("{}/files/notes.pdf?X-Amz-Signature=abc", server.uri());
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_file_block_json(IMAGE_ID, "image", &image_url),
+                get_file_block_json(PDF_ID, "pdf", &file_url),
+            ])))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/files/diagram.png"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"png".to_vec()))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/files/notes.pdf"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let directory = tempfile::tempdir().unwrap();
+        fs::create_dir(directory.path().join("notes")).unwrap();
+        let file = directory.path().join("notes/diagrams.md");
+        fetch_page_to_file(
+            &get_client(&server),
+            &StateDir::new(directory.path().join("state")),
+            &PageId::from_str(PAGE_ID).unwrap(),
+            &FileArg::Path(file.clone()),
+            &FetchOptions {
+                parse_options: NotionParseOptions::default(),
+                serialize_options: SerializeOptions::default(),
+                format: OutputFormat::Markdown,
+                title_heading: true,
+                strict: false,
+                section: None,
+                block_id: None,
+                html_document: None,
+                text_options: TextOptions::default(),
+                asset_dir: Some(directory.path().join("assets")),
+                links: None,
+            },
+            &UserMentionMap::default(),
+            Vec::new(),
+            false,
+        )
+        .await
+        .expect("successful fetch");
+
+        assert_eq!(
+            fs::read(directory.path().join(format!("assets/{IMAGE_ID}.png"))).unwrap(),
+            b"png"
+        );
+        let content = fs::read_to_string(file).unwrap();
+        assert!(
+            content.ends_with(&format!(
+                "# Diagrams\n\n![](../assets/{IMAGE_ID}.png)\n\n[notes.pdf]({file_url})\n"
+            )),
+            "{content}"
+        );
+    }
+
+    #[tokio::test]
+    async fn fetches_only_the_block_and_its_nested_blocks() {
+        const TOGGLE_ID: &str = "1c2d3e4f5a6b4c7d8e9f0a1b2c3d4e5f";
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        mock_toggle(&server, TOGGLE_ID, false).await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(Vec::new())))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let directory = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(directory.path().join("state"));
+        let file = directory.path().join("details.md");
+        fetch_page_to_file(
+            &get_client(&server),
+            &state_dir,
+            &PageId::from_str(PAGE_ID).unwrap(),
+            &FileArg::Path(file.clone()),
+            &FetchOptions {
+                parse_options: NotionParseOptions::default(),
+                serialize_options: SerializeOptions::default(),
+                format: OutputFormat::Markdown,
+                title_heading: false,
+                strict: false,
+                section: None,
+                block_id: Some(BlockId::from_str(TOGGLE_ID).unwrap()),
+                html_document: None,
+                text_options: TextOptions::default(),
+                asset_dir: None,
+                links: None,
+            },
+            &UserMentionMap::default(),
+            Vec::new(),
+            false,
+        )
+        .await
+        .expect("successful fetch");
+
+        let content = fs::read_to_string(&file).unwrap();
+        assert!(content.contains("Details"), "{content}");
+        assert!(content.contains("Old details"), "{content}");
+        assert!(
+            !content.starts_with("---"),
+            "a part of the page has no front matter"
+        );
+    }
+
+    #[tokio::test]
+    async fn fetches_links_to_headings_as_anchors() {
+        const HEADING_ID: &str = "1c2d3e4f5a6b4c7d8e9f0a1b2c3d4e5f";
+        let server = MockServer::start().await;
+        let mut paragraph = get_paragraph_block_json(BLOCK_ID, "the details");
+        paragraph["paragraph"]["rich_text"][0]["text"]["link"] =
+            json!({ "url": format!("https://www.notion.so/Notes-{PAGE_ID}#{HEADING_ID}") });
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                mock_server::get_block_json(HEADING_ID, "heading_2", "Details"),
+                paragraph,
+            ])))
+            .mount(&server)
+            .await;
+
+        let markdown = convert_page_to_markdown(
+            &get_client(&server),
+            PageId::from_str(PAGE_ID).unwrap(),
+            &NotionParseOptions::default(),
+            &SerializeOptions::default(),
+            &UserMentionMap::default(),
+            &mut Vec::new(),
+        )
+        .await
+        .expect("successful fetch");
+
+        assert_eq!(markdown, "## Details\n\n[the details](#details)\n");
+    }
+
+    #[tokio::test]
+    async fn fetches_links_to_blocks_of_other_pages_as_they_are() {
+        let server = MockServer::start().await;
+        let mut paragraph = get_paragraph_block_json(BLOCK_ID, "the notes");
+        paragraph["paragraph"]["rich_text"][0]["text"]["link"] =
+            json!({ "url": LINK_TO_OTHER_PAGE });
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![paragraph])))
+            .mount(&server)
+            .await;
+
+        let markdown = convert_page_to_markdown(
+            &get_client(&server),
+            PageId::from_str(PAGE_ID).unwrap(),
+            &NotionParseOptions::default(),
+            &SerializeOptions::default(),
+            &UserMentionMap::default(),
+            &mut Vec::new(),
+        )
+        .await
+        .expect("successful fetch");
+
+        assert_eq!(markdown, format!("[the notes]({LINK_TO_OTHER_PAGE})\n"));
+    }
+
+    #[test]
+    fn files_changed_since_the_last_sync_have_unsynced_changes() {
+        let directory = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(directory.path().join("state"));
+        let page_id = PageId::from_str(PAGE_ID).unwrap();
+        let file = directory.path().join("page.md");
+
+        assert!(!has_unsynced_changes(&file, &state_dir, &page_id).unwrap());
+
+        fs::write(&file, "Synced\n").unwrap();
+        assert!(has_unsynced_changes(&file, &state_dir, &page_id).unwrap());
+
+        state_dir
+            .write_page_file(
+                StateKind::State,
+                &page_id,
+                LAST_SYNC_STATE_FILE,
+                b"Synced\n",
+            )
+            .unwrap();
+        assert!(!has_unsynced_changes(&file, &state_dir, &page_id).unwrap());
+
+        fs::write(&file, "Edited locally\n").unwrap();
+        assert!(has_unsynced_changes(&file, &state_dir, &page_id).unwrap());
+    }
+
+    #[test]
+    fn fetch_keeps_front_matter_of_the_file_or_of_the_last_push() {
+        let directory = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(directory.path().join("state"));
+        let page_id = PageId::from_str(PAGE_ID).unwrap();
+        let file = directory.path().join("page.md");
+
+        assert_eq!(
+            get_front_matter_for_fetch(Some(&file), &state_dir, &page_id).unwrap(),
+            None
+        );
+
+        let lock = state_dir.lock_page(&page_id).unwrap();
+        state_dir
+            .write_page_file_locked(
+                &lock,
+                StateKind::State,
+                &page_id,
+                FRONT_MATTER_STATE_FILE,
+                b"---\ntitle: Pushed\n---\n",
+            )
+            .unwrap();
+        assert_eq!(
+            get_front_matter_for_fetch(Some(&file), &state_dir, &page_id)
+                .unwrap()
+                .map(|front_matter| front_matter.raw().to_string()),
+            Some("---\ntitle: Pushed\n---\n".to_string())
+        );
+
+        fs::write(&file, "---\ntitle: Local\n---\n\nContent\n").unwrap();
+        assert_eq!(
+            get_front_matter_for_fetch(Some(&file), &state_dir, &page_id)
+                .unwrap()
+                .map(|front_matter| front_matter.raw().to_string()),
+            Some("---\ntitle: Local\n---\n".to_string())
+        );
+    }
+}
@@ -0,0 +1,75 @@
+//! Fetching pages into files and pushing files to pages, as done by the commands of the CLI.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::markdown::mentions::UserMentionMap;
+use crate::markdown::warning::Warning;
+use crate::notion_api::client::{ListUsersError, NotionClient};
+
+pub mod check;
+pub mod database;
+pub mod edit;
+pub mod fetch;
+pub mod new;
+pub mod push;
+pub mod restore;
+pub mod sync;
+#[cfg(test)]
+mod test_util;
+
+/// Builds the mapping of user mentions from the `[users]` config table.
+/// Keys that look like emails are resolved to user IDs using the users in the workspace.
+pub async fn get_user_mention_map(
+    client: &NotionClient,
+    configured_users: &HashMap<String, String>,
+    warnings: &mut Vec<Warning>,
+) -> Result<UserMentionMap, ListUsersError> {
+    let mut id_to_replacement = HashMap::new();
+    let mut email_to_replacement = HashMap::new();
+    for (key, replacement) in configured_users {
+        if key.contains('@') {
+            email_to_replacement.insert(key.as_str(), replacement.clone());
+        } else {
+            id_to_replacement.insert(key.clone(), replacement.clone());
+        }
+    }
+
+    if !email_to_replacement.is_empty() {
+        for user in client.list_users().await? {
+            if let notion::models::users::User::Person { common, person } = user {
+                if let Some(replacement) = email_to_replacement.remove(person.email.as_str()) {
+                    id_to_replacement.insert(common.id.to_string(), replacement);
+                }
+            }
+        }
+
+        warnings.extend(
+            email_to_replacement
+                .into_keys()
+                .map(|email| Warning::UserNotFound {
+                    email: email.to_string(),
+                }),
+        );
+    }
+
+    Ok(UserMentionMap::new(id_to_replacement))
+}
+
+/// The lossy conversions reported with `--strict`, each with the block or the text it is about.
+#[derive(Error, Debug)]
+#[error(
+    "{} lossy conversion(s) were reported with --strict, nothing was changed",
+    .0.len()
+)]
+pub struct StrictModeError(pub Vec<Warning>);
+
+/// With `--strict`, stops before anything is changed when the conversion reported warnings.
+pub(super) fn check_strict(warnings: &[Warning], strict: bool) -> Result<(), StrictModeError> {
+    if strict && !warnings.is_empty() {
+        return Err(StrictModeError(warnings.to_vec()));
+    }
+
+    Ok(())
+}
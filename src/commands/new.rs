@@ -0,0 +1,259 @@
+//! Creating pages from markdown templates.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use notion::ids::PageId;
+use thiserror::Error;
+
+use crate::commands::push::{new_page_title, push_file, FilePushReport, PushError, PushOptions};
+use crate::files::FileArg;
+use crate::ids::page_url;
+use crate::markdown::frontmatter::FrontMatter;
+use crate::markdown::mentions::UserMentionMap;
+use crate::markdown::tag::take_title_heading;
+use crate::notion_api::client::{CreatePageError, NotionClient};
+use crate::page::parse_markdown;
+use crate::state::StateDir;
+use crate::template::{render_template, TemplateError};
+
+#[derive(Error, Debug)]
+pub enum NewPageError {
+    #[error("could not read the template {file}: {error}")]
+    ReadTemplate { file: String, error: io::Error },
+
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+
+    #[error("{0}")]
+    Parse(String),
+
+    #[error("could not create the page: {0}")]
+    CreatePage(#[from] CreatePageError),
+
+    #[error("could not write the page to a temporary file: {0}")]
+    CreateFile(#[source] io::Error),
+
+    #[error("created the page {page_url}, but could not push the template to it: {error}")]
+    Push {
+        page_url: String,
+        #[source]
+        error: PushError,
+    },
+}
+
+/// A page created from a template.
+#[derive(Debug)]
+pub struct NewPage {
+    pub page_id: PageId,
+    /// The variables that were given, but are not in the template.
+    pub unused_variables: Vec<String>,
+    pub push: FilePushReport,
+}
+
+/// Creates a child page of the parent page with the content of the markdown template, after
+/// replacing its `{{variable}}` placeholders. The page is titled `title`, or after the `# heading`
+/// at the start of the template, which is then not pushed as content. Returns the new page.
+#[allow(clippy::too_many_arguments)]
+pub async fn create_page_from_template(
+    client: &NotionClient,
+    state_dir: &StateDir,
+    configured_users: &HashMap<String, String>,
+    parent_page_id: &PageId,
+    template: &Path,
+    variables: &HashMap<String, String>,
+    title: Option<String>,
+    options: &PushOptions,
+) -> Result<NewPage, NewPageError> {
+    let template_content =
+        fs::read_to_string(template).map_err(|error| NewPageError::ReadTemplate {
+            file: template.display().to_string(),
+            error,
+        })?;
+    let rendered = render_template(&template_content, variables)?;
+
+    // NOTE: the template is parsed before the page is created, so a broken template does
+    // not leave an empty page behind
+    let (front_matter, content) = FrontMatter::split(&rendered.content);
+    let mut tags = parse_markdown(
+        content,
+        options.parse_options.clone(),
+        &UserMentionMap::default(),
+        &mut Vec::new(),
+    )
+    .map_err(|error| {
+        let span = error
+            .span()
+            .after(&rendered.content[..rendered.content.len() - content.len()]);
+        NewPageError::Parse(format!("{}:{span}: {error}", template.display()))
+    })?;
+    let heading_title = take_title_heading(&mut tags);
+    let title_heading = title.is_none() && heading_title.is_some();
+    let title = title
+        .or(heading_title)
+        .unwrap_or_else(|| new_page_title(front_matter.as_ref(), template));
+
+    let file = tempfile::Builder::new()
+        .prefix("notion-edit-")
+        .suffix(".md")
+        .tempfile()
+        .and_then(|file| fs::write(file.path(), &rendered.content).map(|_| file))
+        .map_err(NewPageError::CreateFile)?;
+    let page = client.create_page(parent_page_id, &title).await?;
+    let push_options = PushOptions {
+        page_id: Some(page.id.clone()),
+        file: FileArg::Path(file.path().to_path_buf()),
+        title_heading,
+        ..options.clone()
+    };
+    let push = push_file(client, state_dir, configured_users, &push_options)
+        .await
+        .map_err(|error| NewPageError::Push {
+            page_url: page_url(&page.id),
+            error,
+        })?;
+
+    Ok(NewPage {
+        page_id: page.id,
+        unused_variables: rendered.unused_variables,
+        push,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_partial_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::commands::push::{PushMode, PushOptions};
+    use crate::commands::test_util::{get_client, mock_bot_user, mock_page, PAGE_ID};
+    use crate::files::InputFormat;
+    use crate::markdown::from_cmark::ParseOptions;
+    use crate::notion_api::mock_server::get_list_json;
+    use crate::page::EraseOptions;
+
+    #[tokio::test]
+    async fn creates_pages_from_templates() {
+        const PARENT_ID: &str = "0b89a6e8f0064acc8ec6e6902b039e3a";
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        mock_bot_user(&server).await;
+        Mock::given(method("POST"))
+            .and(path("/notion/v1/pages"))
+            .and(body_partial_json(json!({
+                "parent": { "page_id": PARENT_ID },
+                "properties": {
+                    "title": { "title": [{ "text": { "content": "Meeting 2024-05-02" } }] }
+                }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "page",
+                "id": PAGE_ID,
+                "url": format!("https://www.notion.so/meeting-{PAGE_ID}")
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(Vec::new())))
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/pages/{PAGE_ID}")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "page",
+                "id": PAGE_ID,
+                "last_edited_time": "2022-08-01T12:00:00.000Z",
+                "properties": {}
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(Vec::new())))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let directory = tempfile::tempdir().unwrap();
+        let template = directory.path().join("meeting.md");
+        fs::write(
+            &template,
+            "# Meeting {{date}}\n\nAttendees: {{attendees}}\n\n```\n\\{{date}}\n```\n",
+        )
+        .unwrap();
+        let client = get_client(&server);
+        let state_dir = StateDir::new(directory.path().join("state"));
+        let users = HashMap::new();
+        let parent_page_id = PageId::from_str(PARENT_ID).unwrap();
+        let options = PushOptions {
+            page_id: None,
+            file: FileArg::Stdio,
+            parse_options: ParseOptions::default(),
+            input_format: InputFormat::Markdown,
+            title_heading: false,
+            changelog: None,
+            erase_options: EraseOptions::default(),
+            max_backups: None,
+            strict: false,
+            force: false,
+            links: None,
+            section: None,
+            block_id: None,
+            mode: PushMode::Replace,
+            resume: false,
+            unarchive: false,
+        };
+        let date = ("date".to_string(), "2024-05-02".to_string());
+        let attendees = ("attendees".to_string(), "A, B".to_string());
+        let some_variables = HashMap::from([date.clone()]);
+        let all_variables = HashMap::from([date, attendees]);
+        let create = |variables| {
+            create_page_from_template(
+                &client,
+                &state_dir,
+                &users,
+                &parent_page_id,
+                &template,
+                variables,
+                None,
+                &options,
+            )
+        };
+
+        assert!(matches!(
+            create(&some_variables).await.unwrap_err(),
+            NewPageError::Template(TemplateError::MissingVariables(names)) if names == ["attendees"]
+        ));
+        let page_id = create(&all_variables).await.unwrap().page_id;
+
+        assert_eq!(
+            page_id.to_string(),
+            PageId::from_str(PAGE_ID).unwrap().to_string()
+        );
+        let requests = server.received_requests().await.unwrap();
+        let pushed = requests
+            .iter()
+            .find(|request| {
+                request.method == wiremock::http::Method::Patch
+                    && request.url.path().ends_with("/children")
+            })
+            .map(|request| String::from_utf8_lossy(&request.body).to_string())
+            .unwrap();
+        assert!(pushed.contains("Attendees: A, B"), "{pushed}");
+        assert!(pushed.contains("{{date}}"), "{pushed}");
+        assert!(
+            !pushed.contains("Meeting"),
+            "the title is not pushed as content"
+        );
+    }
+}
@@ -0,0 +1,2940 @@
+//! Pushing files to pages, in place of their content, of a section or of the blocks nested
+//! in a block, or before or after their content.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use notion::ids::{AsIdentifier, BlockId, PageId};
+use notion::models::Block;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::info;
+
+use crate::atomic_file;
+use crate::commands::fetch::{blocks_to_tags, fetch_page_tags};
+use crate::commands::{check_strict, get_user_mention_map, StrictModeError};
+use crate::files::{FileArg, InputFormat};
+use crate::ids::{page_url, parse_page_id, ParsePageIdError};
+use crate::markdown::anchors::qualify_heading_links;
+use crate::markdown::changelog::{diff, render_changelog, Outline};
+use crate::markdown::from_cmark::ParseOptions;
+use crate::markdown::frontmatter::{FrontMatter, FRONT_MATTER_STATE_FILE, PAGE_ID_KEY};
+use crate::markdown::mentions::UserMentionMap;
+use crate::markdown::notion_interop::NotionParseOptions;
+use crate::markdown::obsidian::put_front_matter_properties;
+use crate::markdown::plain_text::parse_plain_text;
+use crate::markdown::tag::{take_title_heading, Tag};
+use crate::markdown::to_cmark::{to_markdown, SerializeOptions};
+use crate::markdown::warning::Warning;
+use crate::markdown::Flavor;
+use crate::notion_api::client::{
+    back_up_page, delete_blocks, erase_page, get_block_with_children, validate_blocks,
+    BlockWithChildrenToCreate, CreatePageError, CreatedBlock, ErasePageError,
+    GetBlockChildrenError, GetBlockError, GetDatabaseError, GetPagesError, ListUsersError,
+    NotionClient, PageImage, PageSummary, PageUpdate, RequestCounts, UpdatePageError,
+    MAX_BLOCKS_PER_REQUEST,
+};
+use crate::notion_api::properties::{
+    changed_properties, check_options, sets_options, PropertyError,
+};
+use crate::page::{
+    blocks_to_create, link_heading_anchors, parse_markdown, EraseOptions, MarkdownToPageError,
+};
+use crate::page_tree::{markdown_files, LinkResolver};
+use crate::section::{find_section, SectionError};
+use crate::state::{
+    PageLock, StateDir, StateKind, LAST_EDITED_TIME_STATE_FILE, LAST_SYNC_STATE_FILE,
+    PUSH_JOURNAL_STATE_FILE,
+};
+use crate::PushReport;
+
+/// The changes to the title, the icon, the cover and the properties of the page.
+/// The icon, the cover and the properties are only changed when they are in the front matter,
+/// so pages are never left without them by accident.
+async fn get_page_update(
+    client: &NotionClient,
+    page: &PageSummary,
+    title: Option<String>,
+    front_matter: Option<&FrontMatter>,
+    flavor: Flavor,
+    warnings: &mut Vec<Warning>,
+) -> Result<PageUpdate, PushError> {
+    let (icon, cover, mut front_matter_properties) = match front_matter {
+        Some(front_matter) => (
+            front_matter.icon().map_err(PushError::InvalidFrontMatter)?,
+            front_matter
+                .cover()
+                .map_err(PushError::InvalidFrontMatter)?,
+            front_matter
+                .properties()
+                .map_err(PushError::InvalidFrontMatter)?,
+        ),
+        None => (None, None, None),
+    };
+    if let (Flavor::Obsidian, Some(front_matter)) = (flavor, front_matter) {
+        let yaml = front_matter.yaml().map_err(PushError::InvalidFrontMatter)?;
+        let properties = front_matter_properties.get_or_insert_with(Default::default);
+        let property_names: Vec<_> = page.properties.keys().map(String::as_str).collect();
+        put_front_matter_properties(&yaml, &property_names, properties);
+    }
+
+    let mut properties = match front_matter_properties {
+        Some(front_matter_properties) => {
+            changed_properties(&page.properties, &front_matter_properties, warnings)?
+        }
+        None => serde_json::Map::new(),
+    };
+    if let (true, Some(database_id)) = (sets_options(&properties), &page.database_id) {
+        let schema = client
+            .get_database_properties(database_id)
+            .await
+            .map_err(PushError::GetDatabase)?;
+        check_options(&properties, &schema)?;
+    }
+    if let Some(title) = title.filter(|title| *title != page.title) {
+        if let serde_json::Value::Object(title_properties) = page.title_properties(&title) {
+            properties.extend(title_properties);
+        }
+    }
+
+    Ok(PageUpdate {
+        properties: (!properties.is_empty()).then_some(serde_json::Value::Object(properties)),
+        icon: changed_page_image(page.icon.as_ref(), icon.as_deref()),
+        cover: changed_page_image(page.cover.as_ref(), cover.as_deref()),
+        archived: None,
+    })
+}
+
+/// The image to set on the page when the one from the front matter is different from
+/// the `current` one.
+fn changed_page_image(
+    current: Option<&PageImage>,
+    front_matter: Option<&str>,
+) -> Option<PageImage> {
+    let front_matter = front_matter?;
+    if current.and_then(PageImage::front_matter_value) == Some(front_matter) {
+        return None;
+    }
+
+    Some(PageImage::from_front_matter(front_matter))
+}
+
+/// A file pushed along with other files.
+pub struct FilePush {
+    /// The file, relative to the pushed directory.
+    pub file: PathBuf,
+    pub result: Result<FilePushReport, PushError>,
+}
+
+/// Pushes the markdown files in the directory as child pages of the page from the options,
+/// with the subdirectories as pages in between. The child pages of `notes.md` are the files
+/// in `notes/`, as written by `fetch --recursive`.
+///
+/// Files are pushed one by one, and a file that cannot be pushed does not stop the others.
+pub async fn push_directory(
+    client: &NotionClient,
+    state_dir: &StateDir,
+    configured_users: &HashMap<String, String>,
+    directory: &Path,
+    options: &PushOptions,
+) -> io::Result<Vec<FilePush>> {
+    let root_page_id = options
+        .page_id
+        .clone()
+        .expect("the parent page is required with --recursive");
+    let files = markdown_files(directory)?;
+    let mut file_pages: HashMap<PathBuf, Result<PageId, ()>> = HashMap::new();
+    let mut directory_pages = HashMap::from([(PathBuf::new(), root_page_id)]);
+    let mut child_pages: HashMap<String, Vec<BlockId>> = HashMap::new();
+    let mut pushes = Vec::new();
+
+    // NOTE: the pages are created before any file is pushed, so links between the files can
+    // point to the pages
+    for file in &files {
+        let page = find_or_create_file_page(
+            client,
+            directory,
+            file,
+            &files,
+            &file_pages,
+            &mut directory_pages,
+            &mut child_pages,
+        )
+        .await;
+        let page = match page {
+            Ok(page_id) => Ok(page_id),
+            Err(error) => {
+                pushes.push(FilePush {
+                    file: file.clone(),
+                    result: Err(error),
+                });
+                Err(())
+            }
+        };
+        file_pages.insert(file.clone(), page);
+    }
+
+    let mut links = options.links.clone().unwrap_or_default();
+    for (file, page_id) in &file_pages {
+        if let Ok(page_id) = page_id {
+            links.insert(page_id.clone(), &directory.join(file));
+        }
+    }
+    for file in &files {
+        let page_id = match &file_pages[file] {
+            Ok(page_id) => page_id,
+            Err(()) => continue,
+        };
+        let mut file_options = options.clone();
+        file_options.page_id = Some(page_id.clone());
+        file_options.file = FileArg::Path(directory.join(file));
+        file_options.erase_options.kept_block_ids = child_pages
+            .get(&page_id.to_string())
+            .cloned()
+            .unwrap_or_default();
+        file_options.links = Some(links.clone());
+
+        pushes.push(FilePush {
+            file: file.clone(),
+            result: push_file(client, state_dir, configured_users, &file_options).await,
+        });
+    }
+
+    Ok(pushes)
+}
+
+/// The title of a page created for the file: the title from the front matter, or the name
+/// of the file.
+pub(super) fn new_page_title(front_matter: Option<&FrontMatter>, path: &Path) -> String {
+    front_matter
+        .and_then(|front_matter| front_matter.title().ok().flatten())
+        .unwrap_or_else(|| {
+            path.file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        })
+}
+
+/// Adds the ID of the page created for the file to its front matter, so the next push
+/// updates the page.
+pub(super) fn write_page_id_to_file(
+    path: &Path,
+    front_matter: Option<FrontMatter>,
+    markdown_content: &str,
+    page_id: &PageId,
+) -> Result<(), PushError> {
+    let page_id = page_id.to_string();
+    let front_matter = front_matter.map_or_else(
+        || FrontMatter::for_page_id(&page_id),
+        |front_matter| front_matter.with_page_id(&page_id),
+    );
+    // NOTE: the content is kept as it is, without the empty line `prepend_to` adds
+    let mut content = front_matter.raw().to_string();
+    content.push_str(markdown_content);
+
+    atomic_file::write_atomically(path, |writer| writer.write_all(content.as_bytes())).map_err(
+        |error| PushError::WriteFile {
+            file: path.display().to_string(),
+            error,
+        },
+    )
+}
+
+/// The page of the file: the one from its front matter, or a new one. The ID of a new page
+/// is written to the front matter, so the next push updates the page.
+async fn find_or_create_file_page(
+    client: &NotionClient,
+    directory: &Path,
+    file: &Path,
+    files: &[PathBuf],
+    file_pages: &HashMap<PathBuf, Result<PageId, ()>>,
+    directory_pages: &mut HashMap<PathBuf, PageId>,
+    child_pages: &mut HashMap<String, Vec<BlockId>>,
+) -> Result<PageId, PushError> {
+    let parent_page_id = find_or_create_directory_page(
+        client,
+        file.parent().unwrap_or_else(|| Path::new("")),
+        files,
+        file_pages,
+        directory_pages,
+        child_pages,
+    )
+    .await?;
+
+    let path = directory.join(file);
+    let content = fs::read_to_string(&path).map_err(|error| PushError::ReadFile {
+        file: path.display().to_string(),
+        error,
+    })?;
+    let (front_matter, markdown_content) = FrontMatter::split(&content);
+    let page_id = match front_matter.as_ref().map(FrontMatter::page_id).transpose() {
+        Ok(Some(Some(_))) => get_page_id_for_file(None, front_matter.as_ref())?,
+        Ok(_) | Err(_) => {
+            let title = new_page_title(front_matter.as_ref(), file);
+            let page = client.create_page(&parent_page_id, &title).await?;
+            write_page_id_to_file(&path, front_matter, markdown_content, &page.id)?;
+            page.id
+        }
+    };
+
+    child_pages
+        .entry(parent_page_id.to_string())
+        .or_default()
+        .push(page_id.clone().into());
+
+    Ok(page_id)
+}
+
+/// The page of the directory: the page of the file next to it with the same name, or
+/// a page named after the directory, which is created when the parent page has no such
+/// child page yet.
+async fn find_or_create_directory_page(
+    client: &NotionClient,
+    relative_directory: &Path,
+    files: &[PathBuf],
+    file_pages: &HashMap<PathBuf, Result<PageId, ()>>,
+    directory_pages: &mut HashMap<PathBuf, PageId>,
+    child_pages: &mut HashMap<String, Vec<BlockId>>,
+) -> Result<PageId, PushError> {
+    let mut ancestors: Vec<_> = relative_directory.ancestors().collect();
+    ancestors.reverse();
+
+    let mut parent_page_id = directory_pages[Path::new("")].clone();
+    for ancestor in ancestors.into_iter().skip(1) {
+        if let Some(page_id) = directory_pages.get(ancestor) {
+            parent_page_id = page_id.clone();
+            continue;
+        }
+
+        let page_file = ancestor.with_extension("md");
+        let page_id = if files.contains(&page_file) {
+            file_pages
+                .get(&page_file)
+                .and_then(|page_id| page_id.clone().ok())
+                .ok_or_else(|| PushError::ParentNotPushed {
+                    parent: page_file.display().to_string(),
+                })?
+        } else {
+            let title = ancestor
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            let existing_page_id = client
+                .get_all_direct_block_children(&parent_page_id.clone().into())
+                .await
+                .map_err(PushError::ListChildPages)?
+                .into_iter()
+                .find_map(|block| match block {
+                    Block::ChildPage { common, child_page } if child_page.title == title => Some(
+                        PageId::from_str(&common.id.to_string())
+                            .expect("notion crate PageId does not do any validation when parsing"),
+                    ),
+                    _ => None,
+                });
+            let page_id = match existing_page_id {
+                Some(page_id) => page_id,
+                None => client.create_page(&parent_page_id, &title).await?.id,
+            };
+            child_pages
+                .entry(parent_page_id.to_string())
+                .or_default()
+                .push(page_id.clone().into());
+            page_id
+        };
+
+        directory_pages.insert(ancestor.to_path_buf(), page_id.clone());
+        parent_page_id = page_id;
+    }
+
+    Ok(parent_page_id)
+}
+
+/// Stores the content of the file and the `last_edited_time` of the page after a successful
+/// sync. There is no content when the page was synced with stdin or stdout.
+pub(super) fn record_sync(
+    state_dir: &StateDir,
+    lock: &PageLock,
+    page_id: &PageId,
+    content: Option<&str>,
+    last_edited_time: &str,
+) -> io::Result<()> {
+    match content {
+        Some(content) => state_dir
+            .write_page_file_locked(
+                lock,
+                StateKind::State,
+                page_id,
+                LAST_SYNC_STATE_FILE,
+                content.as_bytes(),
+            )
+            .map(|_| ()),
+        None => {
+            state_dir.remove_page_file_locked(lock, StateKind::State, page_id, LAST_SYNC_STATE_FILE)
+        }
+    }?;
+    state_dir.write_page_file_locked(
+        lock,
+        StateKind::State,
+        page_id,
+        LAST_EDITED_TIME_STATE_FILE,
+        last_edited_time.as_bytes(),
+    )?;
+
+    Ok(())
+}
+
+/// Fails when the page was edited in Notion since it was last fetched or pushed, as pushing
+/// would erase these edits. Pages that were never synced cannot be checked.
+// NOTE: Notion rounds `last_edited_time` to the minute, so edits made in the same minute
+// as the last sync go unnoticed
+fn check_not_edited_since_sync(state_dir: &StateDir, page: &PageSummary) -> Result<(), PushError> {
+    let last_synced_time = match state_dir
+        .read_page_file(StateKind::State, &page.id, LAST_EDITED_TIME_STATE_FILE)
+        .map_err(PushError::State)?
+    {
+        Some(last_synced_time) => last_synced_time,
+        None => return Ok(()),
+    };
+
+    if page.last_edited_time != last_synced_time {
+        return Err(PushError::EditedSinceSync {
+            last_synced_time,
+            last_edited_time: page.last_edited_time.clone(),
+        });
+    }
+
+    Ok(())
+}
+
+/// The progress of a push that replaces the content of a page, kept in the state directory
+/// until the push finishes, so `push --resume` can continue it after a failure.
+#[derive(Debug, Serialize, Deserialize)]
+struct PushJournal {
+    /// The pushed file, which must be the same when the push is resumed.
+    content: String,
+    /// The blocks that were left on the page when it was erased.
+    kept_block_ids: Vec<BlockId>,
+    /// The top-level blocks that were created along with all their children, in order.
+    created_blocks: Vec<JournaledBlock>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JournaledBlock {
+    /// The index of the tag the block was created for.
+    tag_index: usize,
+    block_id: BlockId,
+}
+
+impl PushJournal {
+    /// Reads the journal of the interrupted push of `content` to the page.
+    fn read(state_dir: &StateDir, page_id: &PageId, content: &str) -> Result<Self, PushError> {
+        let journal = state_dir
+            .read_page_file(StateKind::State, page_id, PUSH_JOURNAL_STATE_FILE)
+            .map_err(PushError::State)?
+            .ok_or(PushError::NothingToResume)?;
+        let journal: Self = serde_json::from_str(&journal).map_err(PushError::InvalidJournal)?;
+        if journal.content != content {
+            return Err(PushError::ResumedFileChanged);
+        }
+
+        Ok(journal)
+    }
+
+    fn write(
+        &self,
+        state_dir: &StateDir,
+        lock: &PageLock,
+        page_id: &PageId,
+    ) -> Result<(), PushError> {
+        let journal = serde_json::to_vec(self).map_err(PushError::InvalidJournal)?;
+        state_dir
+            .write_page_file_locked(
+                lock,
+                StateKind::State,
+                page_id,
+                PUSH_JOURNAL_STATE_FILE,
+                &journal,
+            )
+            .map_err(PushError::State)?;
+
+        Ok(())
+    }
+}
+
+/// What `push` sends and how, from its arguments.
+#[derive(Clone)]
+pub struct PushOptions {
+    pub page_id: Option<PageId>,
+    pub file: FileArg,
+    pub parse_options: ParseOptions,
+    pub input_format: InputFormat,
+    /// Whether the title is taken from a `# heading` instead of the front matter.
+    pub title_heading: bool,
+    pub changelog: Option<PathBuf>,
+    pub erase_options: EraseOptions,
+    /// How many local backups of the page are kept, or `None` to push without a backup.
+    pub max_backups: Option<usize>,
+    pub strict: bool,
+    pub force: bool,
+    /// The synced pages, so relative links to their files become links to the pages, or
+    /// `None` to keep the links as they are.
+    pub links: Option<LinkResolver>,
+    /// The heading of the only section that is replaced.
+    pub section: Option<String>,
+    /// The block whose children are replaced or added to, instead of the content of the page.
+    pub block_id: Option<BlockId>,
+    pub mode: PushMode,
+    /// Whether to continue the interrupted push of the same file instead of starting over.
+    pub resume: bool,
+    /// Whether a page in the trash is restored before it is pushed to, instead of refusing
+    /// to push.
+    pub unarchive: bool,
+}
+
+#[derive(Error, Debug)]
+pub enum PushError {
+    #[error("could not read {file}: {error}")]
+    ReadFile { file: String, error: io::Error },
+
+    #[error(transparent)]
+    PageId(#[from] FilePageIdError),
+
+    #[error("could not lock the page in the state directory: {0}")]
+    Lock(#[source] io::Error),
+
+    #[error("could not resolve the users from the config: {0}")]
+    ResolveUsers(#[from] ListUsersError),
+
+    /// The error of the markdown parser, after the file, line and column it is at, like
+    /// `notes.md:42:3: empty heading`.
+    #[error("{0}")]
+    Parse(String),
+
+    #[error("could not parse the JSON document: {0}")]
+    ParseJson(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Strict(#[from] StrictModeError),
+
+    #[error("could not get the page: {0}")]
+    GetPage(#[from] GetPagesError),
+
+    #[error(
+        "page {page_id} is in the trash in Notion, restore it or pass --unarchive to push to it"
+    )]
+    Archived { page_id: PageId },
+
+    #[error("could not get the block: {0}")]
+    GetBlock(#[from] GetBlockError),
+
+    #[error("the page was edited in Notion at {last_edited_time}, after it was last fetched or pushed at {last_synced_time}. Fetch it to get the edits, or pass --force to overwrite them")]
+    EditedSinceSync {
+        last_synced_time: String,
+        last_edited_time: String,
+    },
+
+    #[error("could not fetch the page before pushing: {0}")]
+    FetchBeforePush(#[source] GetBlockChildrenError),
+
+    #[error("could not back up the page before erasing it, pass --no-backup to push anyway")]
+    Backup(#[source] LocalBackupError),
+
+    #[error("could not push the document to Notion: {0}")]
+    Push(#[from] MarkdownToPageError),
+
+    #[error("{0}")]
+    Section(#[from] SectionError),
+
+    #[error("cannot move the first block of the page after the prepended content without losing parts of it: {}", .0.first().map(ToString::to_string).unwrap_or_default())]
+    FirstBlockNotCopied(Vec<Warning>),
+
+    #[error("could not store the sync state in the state directory: {0}")]
+    State(#[source] io::Error),
+
+    #[error("could not write the changelog to {file}: {error}")]
+    WriteChangelog { file: String, error: io::Error },
+
+    #[error("could not create the page: {0}")]
+    CreatePage(#[from] CreatePageError),
+
+    #[error("invalid front matter: {0}")]
+    InvalidFrontMatter(#[source] serde_yaml::Error),
+
+    #[error("could not update the page: {0}")]
+    UpdatePage(#[from] UpdatePageError),
+
+    #[error("could not get the database of the page: {0}")]
+    GetDatabase(#[source] GetDatabaseError),
+
+    #[error("invalid properties in the front matter: {0}")]
+    Property(#[from] PropertyError),
+
+    #[error("could not list the child pages: {0}")]
+    ListChildPages(#[source] GetBlockChildrenError),
+
+    #[error("the page of {parent} could not be pushed")]
+    ParentNotPushed { parent: String },
+
+    #[error("could not write {file}: {error}")]
+    WriteFile { file: String, error: io::Error },
+
+    #[error("there is no interrupted push of the page to resume")]
+    NothingToResume,
+
+    #[error("the file changed since the push was interrupted, push it again without --resume")]
+    ResumedFileChanged,
+
+    #[error("could not read the progress of the interrupted push: {0}")]
+    InvalidJournal(#[source] serde_json::Error),
+}
+
+/// Gets the page, and restores it from the trash with `unarchive`. The page is returned as
+/// it was before it was restored, so restoring it is not mistaken for an edit made in Notion.
+async fn get_page_to_push_to(
+    client: &NotionClient,
+    page_id: &PageId,
+    unarchive: bool,
+) -> Result<PageSummary, PushError> {
+    let page = client.get_page(page_id).await?;
+    if !page.archived {
+        return Ok(page);
+    }
+    if !unarchive {
+        return Err(PushError::Archived {
+            page_id: page_id.clone(),
+        });
+    }
+
+    info!("restoring the page from the trash");
+    client
+        .update_page(
+            page_id,
+            &PageUpdate {
+                archived: Some(false),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    Ok(page)
+}
+
+/// What happened while pushing a file.
+#[derive(Debug)]
+pub struct FilePushReport {
+    /// The page the file was pushed to.
+    pub page_id: PageId,
+    pub report: PushReport,
+    /// The page from the front matter of the file, when the file was pushed to another page
+    /// given with `--page-id`.
+    pub ignored_page_id: Option<PageId>,
+    /// Whether the page already had the content of the file, so nothing was pushed.
+    pub up_to_date: bool,
+}
+
+/// Replaces the content of the page with the file. Returns what the push changed.
+pub async fn push_file(
+    client: &NotionClient,
+    state_dir: &StateDir,
+    configured_users: &HashMap<String, String>,
+    options: &PushOptions,
+) -> Result<FilePushReport, PushError> {
+    let request_counts = client.request_counts();
+    let file = &options.file;
+    let buf = file
+        .read_to_string(io::stdin().lock())
+        .map_err(|error| PushError::ReadFile {
+            file: file.to_string(),
+            error,
+        })?;
+    let (front_matter, content) = match options.input_format {
+        InputFormat::Markdown => FrontMatter::split(&buf),
+        InputFormat::Text | InputFormat::Json => (None, buf.as_str()),
+    };
+    let page_id = get_page_id_for_file(options.page_id.clone(), front_matter.as_ref())?;
+    let ignored_page_id = ignored_front_matter_page_id(&page_id, front_matter.as_ref());
+    let lock = state_dir.lock_page(&page_id).map_err(PushError::Lock)?;
+
+    let mut warnings = Vec::new();
+    let user_mentions = match options.input_format {
+        InputFormat::Markdown => {
+            get_user_mention_map(client, configured_users, &mut warnings).await?
+        }
+        // NOTE: JSON keeps the mentions, so they do not have to be recreated
+        InputFormat::Text | InputFormat::Json => UserMentionMap::default(),
+    };
+    let mut tags = match options.input_format {
+        InputFormat::Markdown => parse_markdown(
+            content,
+            options.parse_options.clone(),
+            &user_mentions,
+            &mut warnings,
+        )
+        .map_err(|error| {
+            // NOTE: the content is the rest of the file after the front matter
+            let span = error.span().after(&buf[..buf.len() - content.len()]);
+            PushError::Parse(format!("{file}:{span}: {error}"))
+        })?,
+        InputFormat::Text => parse_plain_text(content),
+        InputFormat::Json => serde_json::from_str(content)?,
+    };
+    if let (Some(links), Some(path)) = (&options.links, file.path()) {
+        links.link_pages(&mut tags, path, &mut warnings);
+    }
+    let title = if options.title_heading {
+        take_title_heading(&mut tags)
+    } else {
+        front_matter
+            .as_ref()
+            .map(FrontMatter::title)
+            .transpose()
+            .map_err(PushError::InvalidFrontMatter)?
+            .flatten()
+    };
+    check_strict(&warnings, options.strict)?;
+    let link_headings = qualify_heading_links(&mut tags, &page_url(&page_id));
+    // NOTE: Notion rejects changes to pages in the trash, so this is checked before anything
+    // is erased
+    let page = get_page_to_push_to(client, &page_id, options.unarchive).await?;
+    // NOTE: the sync state is about the whole page, so it is neither checked nor recorded
+    // when only a part of the page changes, as others may be editing the rest of the page
+    // NOTE: a block that does not exist or was deleted is reported before anything changes
+    let parent_block_id = match &options.block_id {
+        Some(block_id) => client.get_block(block_id).await?.as_id().clone(),
+        None => page_id.clone().into(),
+    };
+    if options.mode != PushMode::Replace {
+        let report = add_page_contents(client, parent_block_id.clone(), tags, options.mode).await?;
+        if link_headings {
+            link_heading_anchors(client, &page_id, &parent_block_id).await?;
+        }
+        return Ok(FilePushReport {
+            page_id,
+            report: complete_push_report(client, request_counts, warnings, report),
+            ignored_page_id,
+            up_to_date: false,
+        });
+    }
+    if options.block_id.is_some() {
+        if let Some(max_backups) = options.max_backups {
+            back_up_page_locally(
+                client,
+                state_dir,
+                &lock,
+                &page_id,
+                &user_mentions,
+                max_backups,
+            )
+            .await
+            .map_err(PushError::Backup)?;
+        }
+        let report = replace_block_contents(
+            client,
+            parent_block_id.clone(),
+            tags,
+            &options.erase_options,
+        )
+        .await?;
+        if link_headings {
+            link_heading_anchors(client, &page_id, &parent_block_id).await?;
+        }
+        return Ok(FilePushReport {
+            page_id,
+            report: complete_push_report(client, request_counts, warnings, report),
+            ignored_page_id,
+            up_to_date: false,
+        });
+    }
+    if let Some(heading) = &options.section {
+        if let Some(max_backups) = options.max_backups {
+            back_up_page_locally(
+                client,
+                state_dir,
+                &lock,
+                &page_id,
+                &user_mentions,
+                max_backups,
+            )
+            .await
+            .map_err(PushError::Backup)?;
+        }
+        let report = replace_section_contents(
+            client,
+            page_id.clone(),
+            heading,
+            tags,
+            &options.erase_options,
+        )
+        .await?;
+        if link_headings {
+            link_heading_anchors(client, &page_id, &parent_block_id).await?;
+        }
+        return Ok(FilePushReport {
+            page_id,
+            report: complete_push_report(client, request_counts, warnings, report),
+            ignored_page_id,
+            up_to_date: false,
+        });
+    }
+    // NOTE: a resumed push was checked and backed up before it was interrupted, and the page
+    // was changed by the push since
+    let journal = if options.resume {
+        Some(PushJournal::read(state_dir, &page_id, &buf)?)
+    } else {
+        None
+    };
+    if !options.force && journal.is_none() {
+        check_not_edited_since_sync(state_dir, &page)?;
+    }
+    // NOTE: the update is checked before the content is replaced, so invalid properties
+    // do not leave the page half pushed
+    let update = get_page_update(
+        client,
+        &page,
+        title.clone(),
+        front_matter.as_ref(),
+        options.parse_options.flavor,
+        &mut warnings,
+    )
+    .await?;
+
+    let check_up_to_date =
+        !options.force && journal.is_none() && options.input_format == InputFormat::Markdown;
+    let remote_tags = if journal.is_none()
+        && (check_up_to_date || options.changelog.is_some() || options.max_backups.is_some())
+    {
+        // NOTE: the page is converted with the default options, which `restore` parses
+        // the backups with
+        let remote_tags = fetch_page_tags(
+            client,
+            page_id.clone(),
+            &NotionParseOptions::default(),
+            &user_mentions,
+            &mut Vec::new(),
+        )
+        .await
+        .map_err(|error| match options.max_backups {
+            Some(_) => PushError::Backup(LocalBackupError::Fetch(error)),
+            None => PushError::FetchBeforePush(error),
+        })?;
+        Some(remote_tags)
+    } else {
+        None
+    };
+    let up_to_date = match &remote_tags {
+        Some(remote_tags) if check_up_to_date && update.is_empty() => {
+            let serialize_options = SerializeOptions {
+                newline_behavior: options.parse_options.newline_behavior,
+                ..Default::default()
+            };
+            let mut remote_markdown = to_markdown(remote_tags, &serialize_options);
+            if let (true, Some(title)) = (options.title_heading, &title) {
+                remote_markdown = format!("# {title}\n\n{remote_markdown}");
+            }
+            is_same_markdown(&remote_markdown, content)
+        }
+        _ => false,
+    };
+    // NOTE: the remote page is only outlined, so losses in its conversion do not matter
+    let outlines = options.changelog.as_ref().zip(remote_tags.as_ref()).map(
+        |(changelog_path, remote_tags)| {
+            (
+                changelog_path,
+                Outline::from_tags(remote_tags),
+                Outline::from_tags(&tags),
+            )
+        },
+    );
+
+    let report = if let Some(journal) = journal {
+        resume_page_contents(client, state_dir, &lock, &page_id, tags, journal).await?
+    } else if up_to_date {
+        PushReport::default()
+    } else {
+        if let (Some(max_backups), Some(remote_tags)) = (options.max_backups, &remote_tags) {
+            write_local_backup(state_dir, &lock, &page_id, remote_tags, max_backups)
+                .map_err(|error| PushError::Backup(LocalBackupError::Write(error)))?;
+        }
+        let journal = PushJournal {
+            content: buf.clone(),
+            kept_block_ids: Vec::new(),
+            created_blocks: Vec::new(),
+        };
+        replace_page_contents_with_journal(
+            client,
+            state_dir,
+            &lock,
+            &page_id,
+            tags,
+            &options.erase_options,
+            journal,
+        )
+        .await?
+    };
+    if link_headings && !up_to_date {
+        link_heading_anchors(client, &page_id, &parent_block_id).await?;
+    }
+
+    // NOTE: Notion has no place for the front matter, so it is kept locally
+    // for the next fetch
+    match front_matter {
+        Some(front_matter) => state_dir
+            .write_page_file_locked(
+                &lock,
+                StateKind::State,
+                &page_id,
+                FRONT_MATTER_STATE_FILE,
+                front_matter.raw().as_bytes(),
+            )
+            .map(|_| ()),
+        None => state_dir.remove_page_file_locked(
+            &lock,
+            StateKind::State,
+            &page_id,
+            FRONT_MATTER_STATE_FILE,
+        ),
+    }
+    .map_err(PushError::State)?;
+    let page = if update.is_empty() {
+        client.get_page(&page_id).await?
+    } else {
+        client.update_page(&page_id, &update).await?
+    };
+    record_sync(
+        state_dir,
+        &lock,
+        &page_id,
+        file.path().map(|_| buf.as_str()),
+        &page.last_edited_time,
+    )
+    .map_err(PushError::State)?;
+    state_dir
+        .remove_page_file_locked(&lock, StateKind::State, &page_id, PUSH_JOURNAL_STATE_FILE)
+        .map_err(PushError::State)?;
+
+    if let Some((changelog_path, outline_before, outline_after)) = outlines {
+        let changelog = render_changelog(&diff(&outline_before, &outline_after));
+        atomic_file::write_atomically(changelog_path, |writer| {
+            writer.write_all(changelog.as_bytes())
+        })
+        .map_err(|error| PushError::WriteChangelog {
+            file: changelog_path.display().to_string(),
+            error,
+        })?;
+    }
+
+    Ok(FilePushReport {
+        page_id,
+        report: complete_push_report(client, request_counts, warnings, report),
+        ignored_page_id,
+        up_to_date,
+    })
+}
+
+/// Adds the warnings of the push and the requests it sent to the report of the blocks it
+/// changed.
+fn complete_push_report(
+    client: &NotionClient,
+    request_counts: RequestCounts,
+    warnings: Vec<Warning>,
+    report: PushReport,
+) -> PushReport {
+    let request_counts = client.request_counts().since(request_counts);
+
+    PushReport {
+        warnings,
+        requests: request_counts.requests,
+        retries: request_counts.retries,
+        ..report
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum LocalBackupError {
+    #[error("could not fetch the page")]
+    Fetch(#[source] GetBlockChildrenError),
+
+    #[error("could not write the backup to the state directory")]
+    Write(#[source] io::Error),
+}
+
+/// Saves the current content of the page as markdown in the state directory, so it can be
+/// restored with `restore`. Only the `max_backups` newest backups are kept.
+pub(super) async fn back_up_page_locally(
+    client: &NotionClient,
+    state_dir: &StateDir,
+    lock: &PageLock,
+    page_id: &PageId,
+    user_mentions: &UserMentionMap,
+    max_backups: usize,
+) -> Result<PathBuf, LocalBackupError> {
+    let tags = fetch_page_tags(
+        client,
+        page_id.clone(),
+        &NotionParseOptions::default(),
+        user_mentions,
+        &mut Vec::new(),
+    )
+    .await
+    .map_err(LocalBackupError::Fetch)?;
+
+    write_local_backup(state_dir, lock, page_id, &tags, max_backups)
+        .map_err(LocalBackupError::Write)
+}
+
+/// Writes the content of the page, already fetched as tags, as a local backup.
+// NOTE: the backup is only restored by notion-edit, so the conversion warnings are the
+// same as for the push that restores it
+fn write_local_backup(
+    state_dir: &StateDir,
+    lock: &PageLock,
+    page_id: &PageId,
+    tags: &[Tag],
+    max_backups: usize,
+) -> io::Result<PathBuf> {
+    let mut markdown = to_markdown(tags, &SerializeOptions::default());
+    markdown.push('\n');
+    let path =
+        state_dir.write_backup_locked(lock, page_id, SystemTime::now(), &markdown, max_backups)?;
+    info!(path = %path.display(), "backed up the page");
+
+    Ok(path)
+}
+
+/// Whether the markdown documents are the same, ignoring whitespace at the ends of lines and
+/// empty lines at the end.
+pub(super) fn is_same_markdown(left: &str, right: &str) -> bool {
+    let normalize = |markdown: &str| {
+        markdown
+            .lines()
+            .map(str::trim_end)
+            .collect::<Vec<_>>()
+            .join("\n")
+            .trim_end()
+            .to_string()
+    };
+
+    normalize(left) == normalize(right)
+}
+
+#[derive(Error, Debug)]
+pub enum FilePageIdError {
+    #[error(
+        "no page given. Pass --page-id or add `{PAGE_ID_KEY}` to the front matter of the file"
+    )]
+    Missing,
+
+    #[error("cannot parse the front matter: {0}")]
+    InvalidFrontMatter(#[from] serde_yaml::Error),
+
+    #[error("invalid `{PAGE_ID_KEY}` in the front matter: {0}")]
+    InvalidPageId(#[from] ParsePageIdError),
+}
+
+/// The page the file is synced with: the one passed with `--page-id`, or the one from
+/// the front matter.
+pub fn get_page_id_for_file(
+    page_id: Option<PageId>,
+    front_matter: Option<&FrontMatter>,
+) -> Result<PageId, FilePageIdError> {
+    // NOTE: the front matter does not matter when the page is given explicitly, even if it
+    // cannot be parsed
+    if let Some(page_id) = page_id {
+        return Ok(page_id);
+    }
+
+    match front_matter
+        .map(FrontMatter::page_id)
+        .transpose()?
+        .flatten()
+    {
+        Some(page_id) => Ok(parse_page_id(&page_id)?),
+        None => Err(FilePageIdError::Missing),
+    }
+}
+
+/// The page from the front matter, when the file is synced with another page given with
+/// `--page-id` instead.
+pub fn ignored_front_matter_page_id(
+    page_id: &PageId,
+    front_matter: Option<&FrontMatter>,
+) -> Option<PageId> {
+    let front_matter_page_id = parse_page_id(&front_matter?.page_id().ok()??).ok()?;
+
+    (front_matter_page_id != *page_id).then_some(front_matter_page_id)
+}
+
+/// Where `push` puts the content of the file in the page.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PushMode {
+    /// Erase the content of the page and push the file in its place.
+    #[default]
+    Replace,
+    /// Push the file after the content of the page.
+    Append,
+    /// Push the file before the content of the page.
+    Prepend,
+}
+
+/// Replaces the contents of the page with the tags like [`replace_page_contents`], and
+/// records the progress in the journal, so the push can be resumed with
+/// [`resume_page_contents`] when it fails halfway.
+async fn replace_page_contents_with_journal(
+    client: &NotionClient,
+    state_dir: &StateDir,
+    lock: &PageLock,
+    page_id: &PageId,
+    tags: Vec<Tag>,
+    erase_options: &EraseOptions,
+    mut journal: PushJournal,
+) -> Result<PushReport, PushError> {
+    let blocks_to_create = journaled_blocks_to_create(tags)?;
+    client
+        .ensure_access()
+        .await
+        .map_err(MarkdownToPageError::from)?;
+
+    let backup_page = if erase_options.safe_erase {
+        let backup_page = back_up_page(client, page_id)
+            .await
+            .map_err(MarkdownToPageError::from)?;
+        Some(backup_page)
+    } else {
+        None
+    };
+    journal.kept_block_ids = erase_options.kept_block_ids.clone();
+    journal
+        .kept_block_ids
+        .extend(backup_page.as_ref().map(|page| page.id.clone().into()));
+    journal.write(state_dir, lock, page_id)?;
+    let deleted_blocks = erase_page(
+        client,
+        page_id.clone(),
+        erase_options.keep_going,
+        &journal.kept_block_ids,
+    )
+    .await
+    .map_err(MarkdownToPageError::from)?;
+
+    let created_blocks =
+        create_journaled_blocks(client, state_dir, lock, page_id, blocks_to_create, journal)
+            .await?;
+
+    Ok(PushReport {
+        created_blocks,
+        deleted_blocks,
+        backup_page_url: backup_page.map(|page| page.url),
+        ..Default::default()
+    })
+}
+
+/// Continues the interrupted push in the journal. The blocks that were created after the
+/// last recorded ones, whose children may be missing, are deleted and created again.
+async fn resume_page_contents(
+    client: &NotionClient,
+    state_dir: &StateDir,
+    lock: &PageLock,
+    page_id: &PageId,
+    tags: Vec<Tag>,
+    journal: PushJournal,
+) -> Result<PushReport, PushError> {
+    let blocks_to_create = journaled_blocks_to_create(tags)?;
+    client
+        .ensure_access()
+        .await
+        .map_err(MarkdownToPageError::from)?;
+
+    let mut kept_block_ids = journal.kept_block_ids.clone();
+    kept_block_ids.extend(
+        journal
+            .created_blocks
+            .iter()
+            .map(|created_block| created_block.block_id.clone()),
+    );
+    info!(
+        created_blocks = journal.created_blocks.len(),
+        "resuming the push"
+    );
+    let deleted_blocks = erase_page(client, page_id.clone(), false, &kept_block_ids)
+        .await
+        .map_err(MarkdownToPageError::from)?;
+
+    let created_blocks =
+        create_journaled_blocks(client, state_dir, lock, page_id, blocks_to_create, journal)
+            .await?;
+
+    Ok(PushReport {
+        created_blocks,
+        deleted_blocks,
+        ..Default::default()
+    })
+}
+
+/// Like [`blocks_to_create`], along with the index of the tag that each block comes from.
+fn journaled_blocks_to_create(
+    tags: Vec<Tag>,
+) -> Result<Vec<(usize, BlockWithChildrenToCreate)>, MarkdownToPageError> {
+    let blocks: Vec<_> = tags
+        .into_iter()
+        .enumerate()
+        .flat_map(|(tag_index, tag)| {
+            BlockWithChildrenToCreate::from_markdown_tag(tag)
+                .into_iter()
+                .map(move |block| (tag_index, block))
+        })
+        .collect();
+    let errors = validate_blocks(blocks.iter().map(|(_, block)| block));
+    if !errors.is_empty() {
+        return Err(MarkdownToPageError::InvalidBlocks(errors));
+    }
+
+    Ok(blocks)
+}
+
+/// Creates the blocks that are not in the journal yet, a request at a time, and records them
+/// in the journal once they are created along with their children. Returns the created blocks.
+async fn create_journaled_blocks(
+    client: &NotionClient,
+    state_dir: &StateDir,
+    lock: &PageLock,
+    page_id: &PageId,
+    blocks_to_create: Vec<(usize, BlockWithChildrenToCreate)>,
+    mut journal: PushJournal,
+) -> Result<Vec<CreatedBlock>, PushError> {
+    let mut created_blocks = Vec::new();
+    let mut blocks_to_create: Vec<_> = blocks_to_create
+        .into_iter()
+        .skip(journal.created_blocks.len())
+        .collect();
+
+    while !blocks_to_create.is_empty() {
+        let rest = blocks_to_create.split_off(MAX_BLOCKS_PER_REQUEST.min(blocks_to_create.len()));
+        let (tag_indexes, blocks): (Vec<_>, Vec<_>) =
+            std::mem::replace(&mut blocks_to_create, rest)
+                .into_iter()
+                .unzip();
+        let offset = journal.created_blocks.len();
+        let new_blocks = client
+            .create_blocks(page_id.as_id().clone().into(), blocks)
+            .await
+            .map_err(|errors| {
+                MarkdownToPageError::CreateBlocks(
+                    errors
+                        .into_iter()
+                        .map(|error| error.shifted(offset))
+                        .collect(),
+                )
+            })?;
+        journal
+            .created_blocks
+            .extend(
+                tag_indexes
+                    .into_iter()
+                    .zip(&new_blocks)
+                    .map(|(tag_index, created_block)| JournaledBlock {
+                        tag_index,
+                        block_id: created_block.block_id.clone(),
+                    }),
+            );
+        journal.write(state_dir, lock, page_id)?;
+        created_blocks.extend(new_blocks);
+    }
+
+    Ok(created_blocks)
+}
+
+/// Adds the tags after or before the content of the page or the block, without erasing it.
+async fn add_page_contents(
+    client: &NotionClient,
+    block_id: BlockId,
+    tags: Vec<Tag>,
+    mode: PushMode,
+) -> Result<PushReport, PushError> {
+    let blocks_to_create = blocks_to_create(tags)?;
+    client
+        .ensure_access()
+        .await
+        .map_err(MarkdownToPageError::from)?;
+
+    let first_block = match mode {
+        PushMode::Prepend => client
+            .get_block_children(&block_id, None)
+            .await
+            .map_err(MarkdownToPageError::from)?
+            .results
+            .into_iter()
+            .next(),
+        PushMode::Append | PushMode::Replace => None,
+    };
+    let first_block = match first_block {
+        Some(first_block) => get_block_with_children(client, first_block)
+            .await
+            .map_err(MarkdownToPageError::from)?,
+        None => {
+            let created_blocks = client
+                .create_blocks(block_id, blocks_to_create)
+                .await
+                .map_err(MarkdownToPageError::CreateBlocks)?;
+            return Ok(PushReport {
+                created_blocks,
+                ..Default::default()
+            });
+        }
+    };
+
+    // NOTE: Notion only inserts blocks after another block, so the first block is copied
+    // after itself, the tags are inserted between the two, and the original is deleted
+    let mut warnings = Vec::new();
+    let first_block_copy: Vec<_> = blocks_to_tags(
+        std::slice::from_ref(&first_block),
+        &NotionParseOptions::default(),
+        &UserMentionMap::default(),
+        &mut warnings,
+    )
+    .into_iter()
+    .flat_map(BlockWithChildrenToCreate::from_markdown_tag)
+    .collect();
+    if !warnings.is_empty() {
+        return Err(PushError::FirstBlockNotCopied(warnings));
+    }
+    let first_block_id = first_block.block.as_id().clone();
+    let mut created_blocks = Vec::new();
+    for blocks in [first_block_copy, blocks_to_create] {
+        let new_blocks = client
+            .create_blocks_after(block_id.clone(), first_block_id.clone(), blocks)
+            .await
+            .map_err(MarkdownToPageError::CreateBlocks)?;
+        // NOTE: the blocks of the file end up before the copy of the first block
+        created_blocks.splice(0..0, new_blocks);
+    }
+    client
+        .delete_block(first_block_id.clone())
+        .await
+        .map_err(|error| {
+            MarkdownToPageError::from(ErasePageError::DeleteBlockFailed {
+                error,
+                block_id: first_block_id,
+            })
+        })?;
+
+    Ok(PushReport {
+        created_blocks,
+        deleted_blocks: 1,
+        ..Default::default()
+    })
+}
+
+/// Replaces the blocks under the heading of the page with the tags, keeping the heading and
+/// the rest of the page.
+async fn replace_section_contents(
+    client: &NotionClient,
+    page_id: PageId,
+    heading: &str,
+    tags: Vec<Tag>,
+    erase_options: &EraseOptions,
+) -> Result<PushReport, PushError> {
+    let blocks_to_create = blocks_to_create(tags)?;
+    client
+        .ensure_access()
+        .await
+        .map_err(MarkdownToPageError::from)?;
+
+    let block_id: BlockId = page_id.into();
+    let blocks = client
+        .get_all_direct_block_children(&block_id)
+        .await
+        .map_err(MarkdownToPageError::from)?;
+    let section = find_section(&blocks, heading)?;
+    let section_block_ids: Vec<_> = blocks[section.blocks]
+        .iter()
+        .map(|block| block.as_id().clone())
+        .collect();
+    let deleted_blocks = section_block_ids.len();
+    delete_blocks(client, section_block_ids, erase_options.keep_going)
+        .await
+        .map_err(MarkdownToPageError::ErasePage)?;
+
+    let created_blocks = client
+        .create_blocks_after(block_id, section.heading.id, blocks_to_create)
+        .await
+        .map_err(MarkdownToPageError::CreateBlocks)?;
+
+    Ok(PushReport {
+        created_blocks,
+        deleted_blocks,
+        ..Default::default()
+    })
+}
+
+/// Replaces the blocks nested in the block with the tags.
+async fn replace_block_contents(
+    client: &NotionClient,
+    block_id: BlockId,
+    tags: Vec<Tag>,
+    erase_options: &EraseOptions,
+) -> Result<PushReport, MarkdownToPageError> {
+    let blocks_to_create = blocks_to_create(tags)?;
+    client.ensure_access().await?;
+
+    let children = client.get_all_direct_block_children(&block_id).await?;
+    delete_blocks(
+        client,
+        children.iter().map(|block| block.as_id().clone()).collect(),
+        erase_options.keep_going,
+    )
+    .await
+    .map_err(MarkdownToPageError::ErasePage)?;
+
+    let created_blocks = client
+        .create_blocks(block_id, blocks_to_create)
+        .await
+        .map_err(MarkdownToPageError::CreateBlocks)?;
+
+    Ok(PushReport {
+        created_blocks,
+        deleted_blocks: children.len(),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+    use wiremock::{
+        matchers::{body_partial_json, method, path, path_regex},
+        Mock, MockServer, Request, Respond, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::commands::test_util::{
+        get_child_page_block_json, get_client, get_heading_block_json, mock_bot_user, mock_page,
+        mock_page_children, mock_push, mock_toggle, BLOCK_ID, LINK_TO_OTHER_PAGE, PAGE_ID,
+    };
+    use crate::commands::StrictModeError;
+    use crate::notion_api::client::{CheckAccessError, ImageUrl};
+    use crate::notion_api::mock_server::{self, get_list_json, get_paragraph_block_json};
+    use crate::page::replace_page_contents;
+    use crate::state::DEFAULT_MAX_BACKUPS;
+
+    #[tokio::test]
+    async fn pushes_page_through_custom_base_url() {
+        let server = MockServer::start().await;
+        mock_bot_user(&server).await;
+        mock_page_children(&server).await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(Vec::new())))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path(format!("/notion/v1/blocks/{BLOCK_ID}")))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .and(body_partial_json(json!({
+                "children": [{
+                    "paragraph": { "rich_text": [{ "text": { "content": "Pushed content" } }] }
+                }]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_paragraph_block_json(BLOCK_ID, "Pushed content"),
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let tags = parse_markdown(
+            "Pushed content",
+            ParseOptions::default(),
+            &UserMentionMap::default(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        replace_page_contents(
+            &get_client(&server),
+            PageId::from_str(PAGE_ID).unwrap(),
+            tags,
+            &EraseOptions::default(),
+        )
+        .await
+        .expect("successful push");
+    }
+
+    #[tokio::test]
+    async fn does_not_erase_the_page_with_an_invalid_api_key() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/notion/v1/users/me"))
+            .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+                "object": "error",
+                "status": 401,
+                "code": "unauthorized",
+                "message": "API token is invalid."
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(Vec::new())))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let error = replace_page_contents(
+            &get_client(&server),
+            PageId::from_str(PAGE_ID).unwrap(),
+            Vec::new(),
+            &EraseOptions::default(),
+        )
+        .await
+        .expect_err("invalid API key");
+
+        assert!(matches!(
+            error,
+            MarkdownToPageError::CheckAccess(CheckAccessError::InvalidApiKey(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn does_not_push_pages_that_are_up_to_date() {
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_paragraph_block_json(BLOCK_ID, "Pushed content"),
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+        for request_method in ["PATCH", "DELETE"] {
+            Mock::given(method(request_method))
+                .respond_with(ResponseTemplate::new(200))
+                .expect(0)
+                .mount(&server)
+                .await;
+        }
+
+        let directory = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(directory.path().join("state"));
+        let page_id = PageId::from_str(PAGE_ID).unwrap();
+        let file = directory.path().join("page.md");
+        fs::write(&file, "Pushed content  \n\n").unwrap();
+        let file_push = push_file(
+            &get_client(&server),
+            &state_dir,
+            &HashMap::new(),
+            &PushOptions {
+                page_id: Some(page_id.clone()),
+                file: FileArg::Path(file),
+                parse_options: ParseOptions::default(),
+                input_format: InputFormat::Markdown,
+                title_heading: false,
+                changelog: None,
+                erase_options: EraseOptions::default(),
+                max_backups: Some(DEFAULT_MAX_BACKUPS),
+                strict: false,
+                force: false,
+                links: None,
+                section: None,
+                block_id: None,
+                mode: PushMode::Replace,
+                resume: false,
+                unarchive: false,
+            },
+        )
+        .await
+        .expect("successful push");
+
+        assert!(file_push.up_to_date);
+        assert_eq!(
+            state_dir.backups(&page_id).unwrap(),
+            Vec::<String>::new(),
+            "nothing is erased, so nothing is backed up"
+        );
+    }
+
+    #[tokio::test]
+    async fn does_not_push_when_the_page_cannot_be_backed_up() {
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+                "object": "error",
+                "status": 404,
+                "code": "object_not_found",
+                "message": "Could not find block."
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(Vec::new())))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let directory = tempfile::tempdir().unwrap();
+        let file = directory.path().join("page.md");
+        fs::write(&file, "Pushed content\n").unwrap();
+        let result = push_file(
+            &get_client(&server),
+            &StateDir::new(directory.path().join("state")),
+            &HashMap::new(),
+            &PushOptions {
+                page_id: Some(PageId::from_str(PAGE_ID).unwrap()),
+                file: FileArg::Path(file),
+                parse_options: ParseOptions::default(),
+                input_format: InputFormat::Markdown,
+                title_heading: false,
+                changelog: None,
+                erase_options: EraseOptions::default(),
+                max_backups: Some(DEFAULT_MAX_BACKUPS),
+                strict: false,
+                force: true,
+                links: None,
+                section: None,
+                block_id: None,
+                mode: PushMode::Replace,
+                resume: false,
+                unarchive: false,
+            },
+        )
+        .await;
+
+        assert!(matches!(result, Err(PushError::Backup(_))));
+    }
+
+    #[tokio::test]
+    async fn does_not_erase_the_page_when_the_blocks_would_be_rejected() {
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        mock_bot_user(&server).await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_paragraph_block_json(BLOCK_ID, "Content that must survive"),
+            ])))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+        let directory = tempfile::tempdir().unwrap();
+        let file = directory.path().join("page.md");
+        fs::write(&file, format!("# Notes\n\n{}\n", "Too long. ".repeat(201))).unwrap();
+
+        let result = push_file(
+            &get_client(&server),
+            &StateDir::new(directory.path().join("state")),
+            &HashMap::new(),
+            &PushOptions {
+                page_id: Some(PageId::from_str(PAGE_ID).unwrap()),
+                file: FileArg::Path(file),
+                parse_options: ParseOptions::default(),
+                input_format: InputFormat::Markdown,
+                title_heading: false,
+                changelog: None,
+                erase_options: EraseOptions::default(),
+                max_backups: None,
+                strict: false,
+                force: true,
+                links: None,
+                section: None,
+                block_id: None,
+                mode: PushMode::Replace,
+                resume: false,
+                unarchive: false,
+            },
+        )
+        .await;
+
+        let Err(PushError::Push(MarkdownToPageError::InvalidBlocks(errors))) = result else {
+            panic!("expected invalid blocks, got {result:?}");
+        };
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["paragraph \"Too long. Too long. Too long. Too long. …\" has a text of 2010 characters, Notion allows at most 2000"]
+        );
+        assert!(server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .all(|request| request.method != wiremock::http::Method::Delete));
+    }
+
+    #[tokio::test]
+    async fn reports_parse_errors_at_their_position_in_the_file() {
+        let server = MockServer::start().await;
+        let directory = tempfile::tempdir().unwrap();
+        let file = directory.path().join("page.md");
+        fs::write(&file, "---\ntitle: Zażółć\n---\n\nŻółw 🐢 idzie.\n\n> #\n").unwrap();
+
+        let result = push_file(
+            &get_client(&server),
+            &StateDir::new(directory.path().join("state")),
+            &HashMap::new(),
+            &PushOptions {
+                page_id: Some(PageId::from_str(PAGE_ID).unwrap()),
+                file: FileArg::Path(file.clone()),
+                parse_options: ParseOptions::default(),
+                input_format: InputFormat::Markdown,
+                title_heading: false,
+                changelog: None,
+                erase_options: EraseOptions::default(),
+                max_backups: Some(DEFAULT_MAX_BACKUPS),
+                strict: false,
+                force: true,
+                links: None,
+                section: None,
+                block_id: None,
+                mode: PushMode::Replace,
+                resume: false,
+                unarchive: false,
+            },
+        )
+        .await;
+
+        let Err(error @ PushError::Parse(_)) = result else {
+            panic!("expected a parse error, got {result:?}");
+        };
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{}:7:3: empty heading, headings must have some text",
+                file.display()
+            )
+        );
+    }
+
+    async fn push_after_sync(
+        server: &MockServer,
+        last_synced_time: &str,
+        force: bool,
+    ) -> (Result<FilePushReport, PushError>, Option<String>) {
+        let directory = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(directory.path().join("state"));
+        let page_id = PageId::from_str(PAGE_ID).unwrap();
+        let file = directory.path().join("page.md");
+        fs::write(&file, "Pushed content\n").unwrap();
+        state_dir
+            .write_page_file(
+                StateKind::State,
+                &page_id,
+                LAST_EDITED_TIME_STATE_FILE,
+                last_synced_time.as_bytes(),
+            )
+            .unwrap();
+
+        let result = push_file(
+            &get_client(server),
+            &state_dir,
+            &HashMap::new(),
+            &PushOptions {
+                page_id: Some(page_id.clone()),
+                file: FileArg::Path(file),
+                parse_options: ParseOptions::default(),
+                input_format: InputFormat::Markdown,
+                title_heading: false,
+                changelog: None,
+                erase_options: EraseOptions::default(),
+                max_backups: Some(DEFAULT_MAX_BACKUPS),
+                strict: false,
+                force,
+                links: None,
+                section: None,
+                block_id: None,
+                mode: PushMode::Replace,
+                resume: false,
+                unarchive: false,
+            },
+        )
+        .await;
+        let recorded_time = state_dir
+            .read_page_file(StateKind::State, &page_id, LAST_EDITED_TIME_STATE_FILE)
+            .unwrap();
+
+        (result, recorded_time)
+    }
+
+    #[tokio::test]
+    async fn pushes_pages_not_edited_since_the_last_sync() {
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        mock_push(&server, 1).await;
+
+        let (result, recorded_time) =
+            push_after_sync(&server, "2022-08-01T12:00:00.000Z", false).await;
+
+        result.expect("successful push");
+        assert_eq!(recorded_time.as_deref(), Some("2022-08-01T12:00:00.000Z"));
+    }
+
+    #[tokio::test]
+    async fn refuses_to_push_pages_edited_since_the_last_sync() {
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-02T09:30:00.000Z").await;
+        mock_push(&server, 0).await;
+
+        let (result, recorded_time) =
+            push_after_sync(&server, "2022-08-01T12:00:00.000Z", false).await;
+
+        assert!(matches!(
+            result,
+            Err(PushError::EditedSinceSync {
+                last_synced_time,
+                last_edited_time,
+            }) if last_synced_time == "2022-08-01T12:00:00.000Z"
+                && last_edited_time == "2022-08-02T09:30:00.000Z"
+        ));
+        assert_eq!(recorded_time.as_deref(), Some("2022-08-01T12:00:00.000Z"));
+    }
+
+    #[tokio::test]
+    async fn force_pushes_pages_edited_since_the_last_sync() {
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-02T09:30:00.000Z").await;
+        mock_push(&server, 1).await;
+
+        let (result, recorded_time) =
+            push_after_sync(&server, "2022-08-01T12:00:00.000Z", true).await;
+
+        result.expect("successful push");
+        assert_eq!(recorded_time.as_deref(), Some("2022-08-02T09:30:00.000Z"));
+    }
+
+    async fn push_to_trashed_page(
+        server: &MockServer,
+        trash: Value,
+        unarchive: bool,
+    ) -> Result<FilePushReport, PushError> {
+        let mut page_json = json!({
+            "object": "page",
+            "id": PAGE_ID,
+            "last_edited_time": "2022-08-01T12:00:00.000Z",
+            "properties": {}
+        });
+        page_json
+            .as_object_mut()
+            .unwrap()
+            .extend(trash.as_object().unwrap().clone());
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/pages/{PAGE_ID}")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&page_json))
+            .mount(server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/pages/{PAGE_ID}")))
+            .and(body_partial_json(json!({ "archived": false })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&page_json))
+            .expect(u64::from(unarchive))
+            .mount(server)
+            .await;
+        let directory = tempfile::tempdir().unwrap();
+        let file = directory.path().join("page.md");
+        fs::write(&file, "Pushed content\n").unwrap();
+
+        push_file(
+            &get_client(server),
+            &StateDir::new(directory.path().join("state")),
+            &HashMap::new(),
+            &PushOptions {
+                page_id: Some(PageId::from_str(PAGE_ID).unwrap()),
+                file: FileArg::Path(file),
+                parse_options: ParseOptions::default(),
+                input_format: InputFormat::Markdown,
+                title_heading: false,
+                changelog: None,
+                erase_options: EraseOptions::default(),
+                max_backups: Some(DEFAULT_MAX_BACKUPS),
+                strict: false,
+                force: false,
+                links: None,
+                section: None,
+                block_id: None,
+                mode: PushMode::Replace,
+                resume: false,
+                unarchive,
+            },
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn refuses_to_push_archived_pages() {
+        let server = MockServer::start().await;
+        mock_push(&server, 0).await;
+
+        let result = push_to_trashed_page(&server, json!({ "archived": true }), false).await;
+
+        assert!(matches!(result, Err(PushError::Archived { .. })));
+    }
+
+    #[tokio::test]
+    async fn refuses_to_push_pages_under_a_trashed_page() {
+        let server = MockServer::start().await;
+        mock_push(&server, 0).await;
+
+        // NOTE: pages under a page in the trash are in the trash too, without being archived
+        // themselves
+        let result = push_to_trashed_page(
+            &server,
+            json!({ "archived": false, "in_trash": true }),
+            false,
+        )
+        .await;
+
+        assert!(matches!(result, Err(PushError::Archived { .. })));
+    }
+
+    #[tokio::test]
+    async fn restores_archived_pages_before_pushing_with_unarchive() {
+        let server = MockServer::start().await;
+        mock_push(&server, 1).await;
+
+        push_to_trashed_page(&server, json!({ "archived": true }), true)
+            .await
+            .expect("successful push");
+    }
+
+    #[tokio::test]
+    async fn strict_push_lists_every_lossy_construct_and_changes_nothing() {
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        mock_push(&server, 0).await;
+        let directory = tempfile::tempdir().unwrap();
+        let file = directory.path().join("page.md");
+        fs::write(
+            &file,
+            "#### Details {.wide}\n\n\
+             Some **bold** text\n\n\
+             <div>layout</div>\n\n\
+             See [^missing]\n\n\
+             3. Third\n\n\
+             ![Diagram](diagram.png)\n",
+        )
+        .unwrap();
+
+        let result = push_file(
+            &get_client(&server),
+            &StateDir::new(directory.path().join("state")),
+            &HashMap::new(),
+            &PushOptions {
+                page_id: Some(PageId::from_str(PAGE_ID).unwrap()),
+                file: FileArg::Path(file),
+                parse_options: ParseOptions::default(),
+                input_format: InputFormat::Markdown,
+                title_heading: false,
+                changelog: None,
+                erase_options: EraseOptions::default(),
+                max_backups: Some(DEFAULT_MAX_BACKUPS),
+                strict: true,
+                force: false,
+                links: None,
+                section: None,
+                block_id: None,
+                mode: PushMode::Replace,
+                resume: false,
+                unarchive: false,
+            },
+        )
+        .await;
+
+        let Err(PushError::Strict(StrictModeError(warnings))) = result else {
+            panic!("expected a strict mode error, got {result:?}");
+        };
+        assert_eq!(
+            warnings.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "heading \"Details\" has level h4, which was mapped to h3",
+                "classes [\"wide\"] of heading \"Details\" were dropped, Notion does not support them",
+                "inline formatting (bold, italics, strikethrough, code) in \"Some bold text\" was dropped",
+                "HTML \"<div>layout</div>\" has no Notion equivalent and was kept as an html code block",
+                "list starting with \"Third\" starts at 3, but Notion lists always start at 1",
+                "image diagram.png was skipped, images are not supported yet",
+                "footnote [^missing] is referenced, but never defined",
+                "1 footnote(s) were moved to a \"Footnotes\" section at the end",
+            ]
+        );
+    }
+
+    /// A page whose blocks are kept between the requests. The append request numbered
+    /// `failing_append` creates the blocks and still fails, like when the response is lost.
+    #[derive(Clone)]
+    struct FakePage {
+        blocks: Arc<Mutex<Vec<Value>>>,
+        appends: Arc<AtomicUsize>,
+        failing_append: usize,
+    }
+
+    impl Respond for FakePage {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            let mut blocks = self.blocks.lock().unwrap();
+            match request.method {
+                wiremock::http::Method::Patch => {
+                    let append = self.appends.fetch_add(1, Ordering::SeqCst);
+                    let body: Value = request.body_json().unwrap();
+                    let created: Vec<_> = body["children"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .enumerate()
+                        .map(|(index, child)| {
+                            get_paragraph_block_json(
+                                &format!("{:032x}", (append + 1) * 1000 + index),
+                                child["paragraph"]["rich_text"][0]["text"]["content"]
+                                    .as_str()
+                                    .unwrap(),
+                            )
+                        })
+                        .collect();
+                    blocks.extend(created.clone());
+                    if append == self.failing_append {
+                        ResponseTemplate::new(400).set_body_json(json!({
+                            "object": "error",
+                            "status": 400,
+                            "code": "validation_error",
+                            "message": "The response was lost."
+                        }))
+                    } else {
+                        ResponseTemplate::new(200).set_body_json(get_list_json(created))
+                    }
+                }
+                wiremock::http::Method::Delete => {
+                    let block_id = request
+                        .url
+                        .path()
+                        .rsplit('/')
+                        .next()
+                        .unwrap()
+                        .replace('-', "");
+                    blocks.retain(|block| block["id"] != block_id.as_str());
+                    ResponseTemplate::new(200)
+                }
+                _ => ResponseTemplate::new(200).set_body_json(get_list_json(blocks.clone())),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn resumes_an_interrupted_push_without_duplicates() {
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        mock_bot_user(&server).await;
+        let fake_page = FakePage {
+            blocks: Arc::new(Mutex::new(vec![get_paragraph_block_json(
+                BLOCK_ID,
+                "Old content",
+            )])),
+            appends: Arc::new(AtomicUsize::new(0)),
+            failing_append: 1,
+        };
+        Mock::given(path_regex("^/notion/v1/blocks/"))
+            .respond_with(fake_page.clone())
+            .mount(&server)
+            .await;
+
+        let directory = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(directory.path().join("state"));
+        let page_id = PageId::from_str(PAGE_ID).unwrap();
+        let file = directory.path().join("page.md");
+        let paragraphs: Vec<_> = (0..250).map(|index| format!("Paragraph {index}")).collect();
+        fs::write(&file, paragraphs.join("\n\n")).unwrap();
+        let client = get_client(&server);
+        let options = |resume| PushOptions {
+            page_id: Some(page_id.clone()),
+            file: FileArg::Path(file.clone()),
+            parse_options: ParseOptions::default(),
+            input_format: InputFormat::Markdown,
+            title_heading: false,
+            changelog: None,
+            erase_options: EraseOptions::default(),
+            max_backups: None,
+            strict: false,
+            force: false,
+            links: None,
+            section: None,
+            block_id: None,
+            mode: PushMode::Replace,
+            resume,
+            unarchive: false,
+        };
+
+        push_file(&client, &state_dir, &HashMap::new(), &options(false))
+            .await
+            .expect_err("the second append fails");
+        assert_eq!(fake_page.blocks.lock().unwrap().len(), 200);
+        push_file(&client, &state_dir, &HashMap::new(), &options(true))
+            .await
+            .expect("successful resume");
+
+        let pushed_paragraphs: Vec<_> = fake_page
+            .blocks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|block| {
+                block["paragraph"]["rich_text"][0]["plain_text"]
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(pushed_paragraphs, paragraphs);
+        assert_eq!(
+            state_dir
+                .read_page_file(StateKind::State, &page_id, PUSH_JOURNAL_STATE_FILE)
+                .unwrap(),
+            None
+        );
+        assert!(matches!(
+            push_file(&client, &state_dir, &HashMap::new(), &options(true)).await,
+            Err(PushError::NothingToResume)
+        ));
+    }
+
+    #[tokio::test]
+    async fn pushes_the_title_from_the_heading_when_it_changed() {
+        let server = MockServer::start().await;
+        mock_bot_user(&server).await;
+        mock_push(&server, 2).await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/pages/{PAGE_ID}")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "page",
+                "id": PAGE_ID,
+                "last_edited_time": "2022-08-01T12:00:00.000Z",
+                "properties": {
+                    "title": {
+                        "type": "title",
+                        "title": [{ "type": "text", "plain_text": "🚀 Launch" }]
+                    }
+                }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/pages/{PAGE_ID}")))
+            .and(body_partial_json(json!({
+                "properties": {
+                    "title": { "title": [{ "text": { "content": "🚀 Launch plan" } }] }
+                }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "page",
+                "id": PAGE_ID,
+                "last_edited_time": "2022-08-01T12:01:00.000Z",
+                "properties": {}
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let directory = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(directory.path().join("state"));
+        let file = directory.path().join("page.md");
+        let push_options = PushOptions {
+            page_id: Some(PageId::from_str(PAGE_ID).unwrap()),
+            file: FileArg::Path(file.clone()),
+            parse_options: ParseOptions::default(),
+            input_format: InputFormat::Markdown,
+            title_heading: true,
+            changelog: None,
+            erase_options: EraseOptions::default(),
+            max_backups: Some(DEFAULT_MAX_BACKUPS),
+            strict: false,
+            force: true,
+            links: None,
+            section: None,
+            block_id: None,
+            mode: PushMode::Replace,
+            resume: false,
+            unarchive: false,
+        };
+
+        for title in ["🚀 Launch plan", "🚀 Launch"] {
+            fs::write(&file, format!("# {title}\n\nPushed content\n")).unwrap();
+            push_file(
+                &get_client(&server),
+                &state_dir,
+                &HashMap::new(),
+                &push_options,
+            )
+            .await
+            .expect("successful push");
+        }
+    }
+
+    #[test]
+    fn changes_the_icon_only_when_the_front_matter_has_another_one() {
+        let emoji = PageImage::from_front_matter("🚀");
+        let uploaded = PageImage::File {
+            file: ImageUrl {
+                url: "https://s3.us-west-2.amazonaws.com/icon.png".to_string(),
+            },
+        };
+
+        assert_eq!(changed_page_image(Some(&emoji), None), None);
+        assert_eq!(changed_page_image(Some(&emoji), Some("🚀")), None);
+        assert_eq!(
+            changed_page_image(Some(&emoji), Some("📝")),
+            Some(PageImage::from_front_matter("📝"))
+        );
+        assert_eq!(
+            changed_page_image(Some(&uploaded), Some("https://example.com/icon.png")),
+            Some(PageImage::from_front_matter("https://example.com/icon.png"))
+        );
+        assert_eq!(
+            changed_page_image(None, Some("🚀")),
+            Some(PageImage::from_front_matter("🚀"))
+        );
+    }
+
+    #[tokio::test]
+    async fn pushes_a_directory_as_child_pages() {
+        const ROOT_ID: &str = "ac32e025-6f9c-4fab-8b9d-dbb3c593ac46";
+        const INTRO_ID: &str = "1b6f2c5f-9d1e-4d5b-8d6f-7e2a3c4b5d6e";
+        const GUIDE_ID: &str = "8d4e2f60-3c1b-4a7e-9f2d-5b6a7c8d9e0f";
+        const SETUP_ID: &str = "5c3b1a2d-7e6f-4a8b-9c0d-1e2f3a4b5c6d";
+
+        let server = MockServer::start().await;
+        mock_bot_user(&server).await;
+        Mock::given(method("POST"))
+            .and(path("/notion/v1/pages"))
+            .and(body_partial_json(json!({
+                "parent": { "page_id": ROOT_ID },
+                "properties": { "title": { "title": [{ "text": { "content": "intro" } }] } }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "page",
+                "id": INTRO_ID,
+                "url": format!("https://www.notion.so/intro-{INTRO_ID}")
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{ROOT_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_child_page_block_json(GUIDE_ID, "guide"),
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+        for (id, content) in [
+            (
+                INTRO_ID,
+                "https://www.notion.so/5c3b1a2d7e6f4a8b9c0d1e2f3a4b5c6d",
+            ),
+            (SETUP_ID, "Setup steps"),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(format!("/notion/v1/pages/{id}")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "object": "page",
+                    "id": id,
+                    "last_edited_time": "2022-08-01T12:00:00.000Z",
+                    "properties": {}
+                })))
+                .mount(&server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path(format!("/notion/v1/blocks/{id}/children")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(Vec::new())))
+                .mount(&server)
+                .await;
+            let text = if content.starts_with("https://") {
+                json!({ "link": { "url": content } })
+            } else {
+                json!({ "content": content })
+            };
+            Mock::given(method("PATCH"))
+                .and(path(format!("/notion/v1/blocks/{id}/children")))
+                .and(body_partial_json(json!({
+                    "children": [{ "paragraph": { "rich_text": [{ "text": text }] } }]
+                })))
+                .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                    get_paragraph_block_json(BLOCK_ID, "Pushed content"),
+                ])))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let directory = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(directory.path().join("state"));
+        let notes_dir = directory.path().join("notes");
+        fs::create_dir_all(notes_dir.join("guide")).unwrap();
+        fs::write(notes_dir.join("intro.md"), "[Setup](guide/setup.md)\n").unwrap();
+        fs::write(
+            notes_dir.join("guide/setup.md"),
+            format!("---\nnotion_page_id: {SETUP_ID}\n---\n\nSetup steps\n"),
+        )
+        .unwrap();
+
+        let pushes = push_directory(
+            &get_client(&server),
+            &state_dir,
+            &HashMap::new(),
+            &notes_dir,
+            &PushOptions {
+                page_id: Some(parse_page_id(ROOT_ID).unwrap()),
+                file: FileArg::Stdio,
+                parse_options: ParseOptions::default(),
+                input_format: InputFormat::Markdown,
+                title_heading: false,
+                changelog: None,
+                erase_options: EraseOptions::default(),
+                max_backups: Some(DEFAULT_MAX_BACKUPS),
+                strict: false,
+                force: false,
+                links: None,
+                section: None,
+                block_id: None,
+                mode: PushMode::Replace,
+                resume: false,
+                unarchive: false,
+            },
+        )
+        .await
+        .unwrap();
+
+        assert!(pushes.iter().all(|push| push.result.is_ok()));
+        assert_eq!(
+            fs::read_to_string(notes_dir.join("intro.md")).unwrap(),
+            format!("---\nnotion_page_id: {INTRO_ID}\n---\n[Setup](guide/setup.md)\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn backs_up_the_page_content_before_erasing_it() {
+        const BACKUP_PAGE_ID: &str = "1b6f2c5f9d1e4d5b8d6f7e2a3c4b5d6e";
+
+        let server = MockServer::start().await;
+        mock_bot_user(&server).await;
+        mock_page_children(&server).await;
+        Mock::given(method("POST"))
+            .and(path("/notion/v1/pages"))
+            .and(body_partial_json(
+                json!({ "parent": { "page_id": PAGE_ID } }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "page",
+                "id": BACKUP_PAGE_ID,
+                "url": format!("https://www.notion.so/Backup-{BACKUP_PAGE_ID}")
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/blocks/{BACKUP_PAGE_ID}/children")))
+            .and(body_partial_json(json!({
+                "children": [{
+                    "paragraph": {
+                        "rich_text": [{ "text": { "content": "Hello from the gateway" } }]
+                    }
+                }]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_paragraph_block_json(BLOCK_ID, "Hello from the gateway"),
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut backup_page_block = get_paragraph_block_json(BACKUP_PAGE_ID, "");
+        let fields = backup_page_block.as_object_mut().unwrap();
+        fields.remove("paragraph");
+        fields.insert("type".to_string(), json!("child_page"));
+        fields.insert("child_page".to_string(), json!({ "title": "Backup" }));
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                backup_page_block.clone(),
+                get_paragraph_block_json(BLOCK_ID, "Hello from the gateway"),
+            ])))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(get_list_json(vec![backup_page_block])),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path(format!("/notion/v1/blocks/{BLOCK_ID}")))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path(format!("/notion/v1/blocks/{BACKUP_PAGE_ID}")))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_paragraph_block_json(BLOCK_ID, "Pushed content"),
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let tags = parse_markdown(
+            "Pushed content",
+            ParseOptions::default(),
+            &UserMentionMap::default(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        replace_page_contents(
+            &get_client(&server),
+            PageId::from_str(PAGE_ID).unwrap(),
+            tags,
+            &EraseOptions {
+                safe_erase: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .expect("successful push");
+    }
+
+    #[tokio::test]
+    async fn pushes_a_section_in_place_of_its_old_blocks() {
+        const HEADING_ID: &str = "1c2d3e4f5a6b4c7d8e9f0a1b2c3d4e5f";
+        const NEXT_HEADING_ID: &str = "2d3e4f5a6b7c4d8e9f0a1b2c3d4e5f6a";
+        const KEPT_BLOCK_ID: &str = "3e4f5a6b7c8d4e9f0a1b2c3d4e5f6a7b";
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        mock_bot_user(&server).await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_heading_block_json(HEADING_ID, "Launch"),
+                get_paragraph_block_json(BLOCK_ID, "Old launch details"),
+                get_heading_block_json(NEXT_HEADING_ID, "Hiring"),
+                get_paragraph_block_json(KEPT_BLOCK_ID, "Hiring details"),
+            ])))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path(format!("/notion/v1/blocks/{BLOCK_ID}")))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .and(body_partial_json(json!({
+                "after": HEADING_ID,
+                "children": [{
+                    "paragraph": { "rich_text": [{ "text": { "content": "New launch details" } }] }
+                }]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_paragraph_block_json(BLOCK_ID, "New launch details"),
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let directory = tempfile::tempdir().unwrap();
+        let file = directory.path().join("launch.md");
+        fs::write(&file, "New launch details\n").unwrap();
+        let push_options = PushOptions {
+            page_id: Some(PageId::from_str(PAGE_ID).unwrap()),
+            file: FileArg::Path(file),
+            parse_options: ParseOptions::default(),
+            input_format: InputFormat::Markdown,
+            title_heading: false,
+            changelog: None,
+            erase_options: EraseOptions::default(),
+            max_backups: None,
+            strict: false,
+            force: false,
+            links: None,
+            section: Some("launch".to_string()),
+            block_id: None,
+            mode: PushMode::Replace,
+            resume: false,
+            unarchive: false,
+        };
+        let state_dir = StateDir::new(directory.path().join("state"));
+        push_file(
+            &get_client(&server),
+            &state_dir,
+            &HashMap::new(),
+            &push_options,
+        )
+        .await
+        .expect("successful push");
+
+        let error = push_file(
+            &get_client(&server),
+            &state_dir,
+            &HashMap::new(),
+            &PushOptions {
+                section: Some("Budget".to_string()),
+                ..push_options
+            },
+        )
+        .await
+        .expect_err("missing section");
+        assert!(matches!(
+            error,
+            PushError::Section(SectionError::NotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn pushes_under_the_block_and_not_under_deleted_blocks() {
+        const TOGGLE_ID: &str = "1c2d3e4f5a6b4c7d8e9f0a1b2c3d4e5f";
+        const DELETED_TOGGLE_ID: &str = "2d3e4f5a6b7c4d8e9f0a1b2c3d4e5f6a";
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        mock_bot_user(&server).await;
+        mock_toggle(&server, TOGGLE_ID, false).await;
+        mock_toggle(&server, DELETED_TOGGLE_ID, true).await;
+        Mock::given(method("DELETE"))
+            .and(path(format!("/notion/v1/blocks/{BLOCK_ID}")))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/blocks/{TOGGLE_ID}/children")))
+            .and(body_partial_json(json!({
+                "children": [{
+                    "paragraph": { "rich_text": [{ "text": { "content": "New details" } }] }
+                }]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_paragraph_block_json(BLOCK_ID, "New details"),
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let directory = tempfile::tempdir().unwrap();
+        let file = directory.path().join("details.md");
+        fs::write(&file, "New details\n").unwrap();
+        let push_options = PushOptions {
+            page_id: Some(PageId::from_str(PAGE_ID).unwrap()),
+            file: FileArg::Path(file),
+            parse_options: ParseOptions::default(),
+            input_format: InputFormat::Markdown,
+            title_heading: false,
+            changelog: None,
+            erase_options: EraseOptions::default(),
+            max_backups: None,
+            strict: false,
+            force: false,
+            links: None,
+            section: None,
+            block_id: Some(BlockId::from_str(TOGGLE_ID).unwrap()),
+            mode: PushMode::Replace,
+            resume: false,
+            unarchive: false,
+        };
+        let state_dir = StateDir::new(directory.path().join("state"));
+        push_file(
+            &get_client(&server),
+            &state_dir,
+            &HashMap::new(),
+            &push_options,
+        )
+        .await
+        .expect("successful push");
+
+        let error = push_file(
+            &get_client(&server),
+            &state_dir,
+            &HashMap::new(),
+            &PushOptions {
+                block_id: Some(BlockId::from_str(DELETED_TOGGLE_ID).unwrap()),
+                ..push_options
+            },
+        )
+        .await
+        .expect_err("deleted block");
+        assert!(matches!(
+            error,
+            PushError::GetBlock(GetBlockError::Archived(_))
+        ));
+    }
+
+    async fn push_with_mode(
+        server: &MockServer,
+        mode: PushMode,
+    ) -> Result<FilePushReport, PushError> {
+        let directory = tempfile::tempdir().unwrap();
+        let file = directory.path().join("log.md");
+        fs::write(&file, "New entry\n").unwrap();
+
+        push_file(
+            &get_client(server),
+            &StateDir::new(directory.path().join("state")),
+            &HashMap::new(),
+            &PushOptions {
+                page_id: Some(PageId::from_str(PAGE_ID).unwrap()),
+                file: FileArg::Path(file),
+                parse_options: ParseOptions::default(),
+                input_format: InputFormat::Markdown,
+                title_heading: false,
+                changelog: None,
+                erase_options: EraseOptions::default(),
+                max_backups: Some(DEFAULT_MAX_BACKUPS),
+                strict: false,
+                force: false,
+                links: None,
+                section: None,
+                block_id: None,
+                mode,
+                resume: false,
+                unarchive: false,
+            },
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn appends_without_erasing_the_page() {
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        mock_bot_user(&server).await;
+        Mock::given(method("DELETE"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .and(body_partial_json(json!({
+                "children": [{
+                    "paragraph": { "rich_text": [{ "text": { "content": "New entry" } }] }
+                }]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_paragraph_block_json(BLOCK_ID, "New entry"),
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let report = push_with_mode(&server, PushMode::Append)
+            .await
+            .expect("successful push")
+            .report;
+
+        let requests = server.received_requests().await.unwrap();
+        let patch = requests
+            .iter()
+            .find(|request| request.method == wiremock::http::Method::Patch)
+            .unwrap();
+        assert_eq!(
+            patch.body_json::<Value>().unwrap().get("after"),
+            None,
+            "appended at the end"
+        );
+        assert_eq!(
+            report,
+            PushReport {
+                warnings: Vec::new(),
+                created_blocks: vec![CreatedBlock {
+                    block_id: BlockId::from_str(BLOCK_ID).unwrap(),
+                    block_type: "paragraph".to_string(),
+                    preview: "New entry".to_string(),
+                    children: 0,
+                }],
+                deleted_blocks: 0,
+                requests: requests.len(),
+                retries: 0,
+                backup_page_url: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn points_links_to_headings_at_the_created_heading_blocks() {
+        const HEADING_ID: &str = "1c2d3e4f5a6b4c7d8e9f0a1b2c3d4e5f";
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        mock_bot_user(&server).await;
+        let qualified_link = format!("https://www.notion.so/{PAGE_ID}#details");
+        let mut paragraph = get_paragraph_block_json(BLOCK_ID, "the details");
+        paragraph["paragraph"]["rich_text"][0]["text"]["link"] = json!({ "url": qualified_link });
+        let created_blocks = vec![
+            mock_server::get_block_json(HEADING_ID, "heading_2", "Details"),
+            paragraph,
+        ];
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .and(body_partial_json(json!({
+                "children": [
+                    { "heading_2": { "rich_text": [{ "text": { "content": "Details" } }] } },
+                    {
+                        "paragraph": {
+                            "rich_text": [{ "text": { "link": { "url": qualified_link } } }]
+                        }
+                    }
+                ]
+            })))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(get_list_json(created_blocks.clone())),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(created_blocks)))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/blocks/{BLOCK_ID}")))
+            .and(body_partial_json(json!({
+                "paragraph": {
+                    "rich_text": [{
+                        "type": "text",
+                        "text": {
+                            "content": "the details",
+                            "link": { "url": format!("https://www.notion.so/{PAGE_ID}#{HEADING_ID}") }
+                        }
+                    }]
+                }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let directory = tempfile::tempdir().unwrap();
+        let file = directory.path().join("notes.md");
+        fs::write(&file, "## Details\n\n[the details](#details)\n").unwrap();
+
+        push_file(
+            &get_client(&server),
+            &StateDir::new(directory.path().join("state")),
+            &HashMap::new(),
+            &PushOptions {
+                page_id: Some(PageId::from_str(PAGE_ID).unwrap()),
+                file: FileArg::Path(file),
+                parse_options: ParseOptions::default(),
+                input_format: InputFormat::Markdown,
+                title_heading: false,
+                changelog: None,
+                erase_options: EraseOptions::default(),
+                max_backups: None,
+                strict: false,
+                force: false,
+                links: None,
+                section: None,
+                block_id: None,
+                mode: PushMode::Append,
+                resume: false,
+                unarchive: false,
+            },
+        )
+        .await
+        .expect("successful push");
+
+        let requests = server.received_requests().await.unwrap();
+        let update = requests
+            .iter()
+            .find(|request| request.url.path() == format!("/notion/v1/blocks/{BLOCK_ID}"))
+            .unwrap();
+        let rich_text = &update.body_json::<Value>().unwrap()["paragraph"]["rich_text"][0];
+        assert_eq!(rich_text.get("plain_text"), None);
+    }
+
+    #[tokio::test]
+    async fn pushes_links_to_blocks_of_other_pages_as_they_are() {
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        mock_bot_user(&server).await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .and(body_partial_json(json!({
+                "children": [{
+                    "paragraph": {
+                        "rich_text": [{
+                            "type": "text",
+                            "text": {
+                                "content": "the notes",
+                                "link": { "url": LINK_TO_OTHER_PAGE }
+                            }
+                        }]
+                    }
+                }]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_paragraph_block_json(BLOCK_ID, "the notes"),
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let directory = tempfile::tempdir().unwrap();
+        let file = directory.path().join("notes.md");
+        fs::write(&file, format!("[the notes]({LINK_TO_OTHER_PAGE})\n")).unwrap();
+
+        push_file(
+            &get_client(&server),
+            &StateDir::new(directory.path().join("state")),
+            &HashMap::new(),
+            &PushOptions {
+                page_id: Some(PageId::from_str(PAGE_ID).unwrap()),
+                file: FileArg::Path(file),
+                parse_options: ParseOptions::default(),
+                input_format: InputFormat::Markdown,
+                title_heading: false,
+                changelog: None,
+                erase_options: EraseOptions::default(),
+                max_backups: None,
+                strict: false,
+                force: false,
+                links: None,
+                section: None,
+                block_id: None,
+                mode: PushMode::Append,
+                resume: false,
+                unarchive: false,
+            },
+        )
+        .await
+        .expect("successful push");
+    }
+
+    #[tokio::test]
+    async fn prepends_before_the_first_block() {
+        const FIRST_BLOCK_ID: &str = "1c2d3e4f5a6b4c7d8e9f0a1b2c3d4e5f";
+        const COPY_ID: &str = "2d3e4f5a6b7c4d8e9f0a1b2c3d4e5f6a";
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        mock_bot_user(&server).await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_paragraph_block_json(FIRST_BLOCK_ID, "Old entry"),
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .and(body_partial_json(json!({
+                "after": FIRST_BLOCK_ID,
+                "children": [{
+                    "paragraph": { "rich_text": [{ "text": { "content": "Old entry" } }] }
+                }]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_paragraph_block_json(COPY_ID, "Old entry"),
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .and(body_partial_json(json!({
+                "after": FIRST_BLOCK_ID,
+                "children": [{
+                    "paragraph": { "rich_text": [{ "text": { "content": "New entry" } }] }
+                }]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_paragraph_block_json(BLOCK_ID, "New entry"),
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path(format!("/notion/v1/blocks/{FIRST_BLOCK_ID}")))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        push_with_mode(&server, PushMode::Prepend)
+            .await
+            .expect("successful push");
+
+        let requests = server.received_requests().await.unwrap();
+        let patched_contents: Vec<_> = requests
+            .iter()
+            .filter(|request| request.method == wiremock::http::Method::Patch)
+            .map(|request| {
+                request.body_json::<Value>().unwrap()["children"][0]["paragraph"]["rich_text"][0]
+                    ["text"]["content"]
+                    .clone()
+            })
+            .collect();
+        assert_eq!(
+            patched_contents,
+            vec![json!("Old entry"), json!("New entry")],
+            "the copy goes right after the first block, then the new content before the copy"
+        );
+    }
+
+    #[test]
+    fn takes_the_page_id_from_the_front_matter() {
+        const OTHER_PAGE_ID: &str = "0b89a6e8-f006-4acc-8ec6-e6902b039e3a";
+        let page_id = parse_page_id(PAGE_ID).unwrap();
+        let front_matter = FrontMatter::for_page_id(&page_id.to_string());
+
+        assert_eq!(
+            get_page_id_for_file(None, Some(&front_matter)).unwrap(),
+            page_id
+        );
+        assert_eq!(
+            get_page_id_for_file(
+                Some(parse_page_id(OTHER_PAGE_ID).unwrap()),
+                Some(&front_matter)
+            )
+            .unwrap()
+            .to_string(),
+            OTHER_PAGE_ID
+        );
+        assert!(matches!(
+            get_page_id_for_file(None, None),
+            Err(FilePageIdError::Missing)
+        ));
+        assert!(matches!(
+            get_page_id_for_file(
+                None,
+                FrontMatter::from_raw("---\ntitle: Notes\n---\n").as_ref()
+            ),
+            Err(FilePageIdError::Missing)
+        ));
+        assert!(matches!(
+            get_page_id_for_file(None, Some(&FrontMatter::for_page_id("not-a-page"))),
+            Err(FilePageIdError::InvalidPageId(_))
+        ));
+    }
+}
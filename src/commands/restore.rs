@@ -0,0 +1,202 @@
+//! Restoring pages from the local backups taken before each push.
+
+use std::collections::HashMap;
+use std::io;
+
+use notion::ids::PageId;
+use thiserror::Error;
+
+use crate::commands::get_user_mention_map;
+use crate::commands::push::{back_up_page_locally, LocalBackupError};
+use crate::markdown::from_cmark::{ParseError, ParseOptions};
+use crate::notion_api::client::{ListUsersError, NotionClient};
+use crate::page::{parse_markdown, replace_page_contents, EraseOptions, MarkdownToPageError};
+use crate::state::StateDir;
+use crate::PushReport;
+
+#[derive(Error, Debug)]
+pub enum RestoreError {
+    #[error("could not lock the page in the state directory")]
+    Lock(#[source] io::Error),
+
+    #[error("could not read the backups in the state directory")]
+    ReadBackups(#[source] io::Error),
+
+    #[error("there are no backups of the page {page_id}")]
+    NoBackups { page_id: PageId },
+
+    #[error("there is no backup {timestamp} of the page {page_id}")]
+    MissingBackup { page_id: PageId, timestamp: String },
+
+    #[error("could not resolve the users from the config")]
+    ResolveUsers(#[source] ListUsersError),
+
+    #[error("could not parse the backup at {}", .0.span())]
+    Parse(#[source] ParseError),
+
+    #[error("could not back up the page before restoring, pass --no-backup to restore anyway")]
+    Backup(#[source] LocalBackupError),
+
+    #[error("could not push the backup to Notion")]
+    Push(#[source] MarkdownToPageError),
+}
+
+/// A backup restored by [`restore_backup`].
+#[derive(Debug)]
+pub struct RestoredBackup {
+    pub timestamp: String,
+    pub report: PushReport,
+}
+
+/// Replaces the content of the page with its local backup, the newest one when `timestamp` is
+/// not given.
+pub async fn restore_backup(
+    client: &NotionClient,
+    state_dir: &StateDir,
+    configured_users: &HashMap<String, String>,
+    page_id: &PageId,
+    timestamp: Option<String>,
+    max_backups: Option<usize>,
+) -> Result<RestoredBackup, RestoreError> {
+    let lock = state_dir.lock_page(page_id).map_err(RestoreError::Lock)?;
+    let timestamp = match timestamp {
+        Some(timestamp) => timestamp,
+        None => state_dir
+            .backups(page_id)
+            .map_err(RestoreError::ReadBackups)?
+            .pop()
+            .ok_or_else(|| RestoreError::NoBackups {
+                page_id: page_id.clone(),
+            })?,
+    };
+    let content = state_dir
+        .read_backup(page_id, &timestamp)
+        .map_err(RestoreError::ReadBackups)?
+        .ok_or_else(|| RestoreError::MissingBackup {
+            page_id: page_id.clone(),
+            timestamp: timestamp.clone(),
+        })?;
+
+    let mut warnings = Vec::new();
+    let user_mentions = get_user_mention_map(client, configured_users, &mut warnings)
+        .await
+        .map_err(RestoreError::ResolveUsers)?;
+    let tags = parse_markdown(
+        &content,
+        ParseOptions::default(),
+        &user_mentions,
+        &mut warnings,
+    )
+    .map_err(RestoreError::Parse)?;
+    // NOTE: restoring erases the page too, so the content it replaces can be restored back
+    if let Some(max_backups) = max_backups {
+        back_up_page_locally(
+            client,
+            state_dir,
+            &lock,
+            page_id,
+            &user_mentions,
+            max_backups,
+        )
+        .await
+        .map_err(RestoreError::Backup)?;
+    }
+    let report = replace_page_contents(client, page_id.clone(), tags, &EraseOptions::default())
+        .await
+        .map_err(RestoreError::Push)?;
+
+    Ok(RestoredBackup {
+        timestamp,
+        report: PushReport { warnings, ..report },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_partial_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::commands::test_util::{get_client, mock_bot_user, BLOCK_ID, PAGE_ID};
+    use crate::notion_api::mock_server::{get_list_json, get_paragraph_block_json};
+    use crate::state::DEFAULT_MAX_BACKUPS;
+
+    #[tokio::test]
+    async fn restores_the_newest_backup() {
+        let server = MockServer::start().await;
+        mock_bot_user(&server).await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(Vec::new())))
+            .expect(3)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .and(body_partial_json(json!({
+                "children": [{
+                    "paragraph": { "rich_text": [{ "text": { "content": "Newer content" } }] }
+                }]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_paragraph_block_json(BLOCK_ID, "Newer content"),
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let directory = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(directory.path().to_path_buf());
+        let page_id = PageId::from_str(PAGE_ID).unwrap();
+        let lock = state_dir.lock_page(&page_id).unwrap();
+        let start = humantime::parse_rfc3339("2022-08-01T12:00:00Z").unwrap();
+        for (minute, content) in [(0, "Older content\n"), (1, "Newer content\n")] {
+            state_dir
+                .write_backup_locked(
+                    &lock,
+                    &page_id,
+                    start + std::time::Duration::from_secs(minute * 60),
+                    content,
+                    DEFAULT_MAX_BACKUPS,
+                )
+                .unwrap();
+        }
+        drop(lock);
+
+        let error = restore_backup(
+            &get_client(&server),
+            &state_dir,
+            &HashMap::new(),
+            &page_id,
+            Some("2022-08-01T12-05-00.000Z".to_string()),
+            None,
+        )
+        .await
+        .expect_err("missing backup");
+        assert!(matches!(error, RestoreError::MissingBackup { .. }));
+
+        let restored = restore_backup(
+            &get_client(&server),
+            &state_dir,
+            &HashMap::new(),
+            &page_id,
+            None,
+            Some(DEFAULT_MAX_BACKUPS),
+        )
+        .await
+        .expect("successful restore");
+
+        assert_eq!(restored.timestamp, "2022-08-01T12-01-00.000Z");
+        assert_eq!(
+            state_dir.backups(&page_id).unwrap().len(),
+            3,
+            "the replaced content is backed up too"
+        );
+    }
+}
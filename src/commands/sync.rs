@@ -0,0 +1,478 @@
+//! Syncing the pages listed in a manifest with their files.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::commands::check_strict;
+use crate::commands::fetch::{
+    fetch_page_to_file, has_unsynced_changes, render_page_for_file, FetchError, FetchOptions,
+    FetchReport,
+};
+use crate::commands::push::{push_file, record_sync, FilePushReport, PushError, PushOptions};
+use crate::files::FileArg;
+use crate::manifest::{ManifestEntry, SyncDirection, TwoWayAction};
+use crate::markdown::mentions::UserMentionMap;
+use crate::notion_api::client::{GetPagesError, NotionClient};
+use crate::state::{StateDir, StateKind, LAST_EDITED_TIME_STATE_FILE, LAST_SYNC_STATE_FILE};
+
+/// What `sync` did with an entry of the manifest, with the fetch and the push it took.
+#[derive(Debug)]
+pub struct SyncReport {
+    pub status: SyncStatus,
+    pub fetch: Option<FetchReport>,
+    pub push: Option<FilePushReport>,
+}
+
+impl From<SyncStatus> for SyncReport {
+    fn from(status: SyncStatus) -> Self {
+        Self {
+            status,
+            fetch: None,
+            push: None,
+        }
+    }
+}
+
+/// What `sync` did with an entry of the manifest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncStatus {
+    Fetched,
+    Pushed,
+    /// Both the file and the page changed, and their edits were merged into both.
+    Merged,
+    UpToDate,
+}
+
+#[derive(Error, Debug)]
+pub enum SyncError {
+    #[error("{0}")]
+    Fetch(#[from] FetchError),
+
+    #[error("{0}")]
+    Push(#[from] PushError),
+
+    #[error("could not read {file}: {error}")]
+    ReadFile { file: String, error: io::Error },
+
+    #[error("could not write the merged page to {file}: {error}")]
+    WriteFile { file: String, error: io::Error },
+
+    #[error("could not lock the page in the state directory: {0}")]
+    Lock(#[source] io::Error),
+
+    #[error("could not use the sync state in the state directory: {0}")]
+    State(#[source] io::Error),
+
+    #[error("could not get the page: {0}")]
+    GetPage(#[from] GetPagesError),
+
+    #[error("both {file} and the page changed, but they were never synced. Fetch or push the page on its own to pick the side to keep")]
+    Conflict { file: String },
+
+    #[error("the edits of {file} and of the page conflict. Resolve the conflicts marked in the file and sync again")]
+    MergeConflict {
+        file: String,
+        /// The fetch of the page that was merged into the file.
+        fetch: FetchReport,
+    },
+
+    #[error("{file} has unresolved conflict markers. Resolve the conflicts and sync again")]
+    UnresolvedConflict { file: String },
+}
+
+/// Fetches or pushes the page of the entry. Two-way entries are fetched when the page was
+/// edited in Notion since the last sync, pushed when the file changed, and merged when both
+/// changed.
+pub async fn sync_manifest_entry(
+    client: &NotionClient,
+    state_dir: &StateDir,
+    configured_users: &HashMap<String, String>,
+    entry: &ManifestEntry,
+    fetch_options: &FetchOptions,
+    push_options: &PushOptions,
+    user_mentions: &UserMentionMap,
+) -> Result<SyncReport, SyncError> {
+    let page_id = &entry.page_id;
+    let file = FileArg::Path(entry.file.clone());
+
+    let direction = match entry.direction {
+        SyncDirection::Fetch => SyncDirection::Fetch,
+        SyncDirection::Push => SyncDirection::Push,
+        SyncDirection::TwoWay => {
+            let file_changed =
+                has_unsynced_changes(&entry.file, state_dir, page_id).map_err(|error| {
+                    SyncError::ReadFile {
+                        file: file.to_string(),
+                        error,
+                    }
+                })?;
+            let last_synced_time = state_dir
+                .read_page_file(StateKind::State, page_id, LAST_EDITED_TIME_STATE_FILE)
+                .map_err(SyncError::State)?;
+            let page = client.get_page(page_id).await?;
+            // NOTE: a missing file is fetched again, even when the page did not change
+            let page_changed = last_synced_time.as_deref() != Some(page.last_edited_time.as_str())
+                || !entry.file.exists();
+
+            match TwoWayAction::new(page_changed, file_changed) {
+                TwoWayAction::Fetch => SyncDirection::Fetch,
+                TwoWayAction::Push => SyncDirection::Push,
+                TwoWayAction::Nothing => return Ok(SyncStatus::UpToDate.into()),
+                TwoWayAction::Conflict => {
+                    return merge_two_way_entry(
+                        client,
+                        state_dir,
+                        configured_users,
+                        entry,
+                        fetch_options,
+                        push_options,
+                        user_mentions,
+                    )
+                    .await
+                }
+            }
+        }
+    };
+    if entry.direction == SyncDirection::TwoWay && direction == SyncDirection::Push {
+        check_conflicts_resolved(&entry.file)?;
+    }
+
+    match direction {
+        SyncDirection::Fetch => {
+            let fetch = fetch_page_to_file(
+                client,
+                state_dir,
+                page_id,
+                &file,
+                fetch_options,
+                user_mentions,
+                Vec::new(),
+                true,
+            )
+            .await?;
+            Ok(SyncReport {
+                fetch: Some(fetch),
+                ..SyncStatus::Fetched.into()
+            })
+        }
+        SyncDirection::Push | SyncDirection::TwoWay => {
+            let push_options = PushOptions {
+                page_id: Some(page_id.clone()),
+                file,
+                ..push_options.clone()
+            };
+            let push = push_file(client, state_dir, configured_users, &push_options).await?;
+            Ok(SyncReport {
+                push: Some(push),
+                ..SyncStatus::Pushed.into()
+            })
+        }
+    }
+}
+
+/// Merges the edits of the file and of the page, with the content of the last sync as their
+/// common ancestor. A clean merge is written to the file and pushed. Conflicts are written
+/// to the file with conflict markers, and the page is recorded as synced, so the next sync
+/// pushes the file once the conflicts are resolved.
+async fn merge_two_way_entry(
+    client: &NotionClient,
+    state_dir: &StateDir,
+    configured_users: &HashMap<String, String>,
+    entry: &ManifestEntry,
+    fetch_options: &FetchOptions,
+    push_options: &PushOptions,
+    user_mentions: &UserMentionMap,
+) -> Result<SyncReport, SyncError> {
+    let page_id = &entry.page_id;
+    let file = FileArg::Path(entry.file.clone());
+    check_conflicts_resolved(&entry.file)?;
+
+    let lock = state_dir.lock_page(page_id).map_err(SyncError::Lock)?;
+    let Some(base) = state_dir
+        .read_page_file(StateKind::State, page_id, LAST_SYNC_STATE_FILE)
+        .map_err(SyncError::State)?
+    else {
+        return Err(SyncError::Conflict {
+            file: file.to_string(),
+        });
+    };
+    let local = fs::read_to_string(&entry.file).map_err(|error| SyncError::ReadFile {
+        file: file.to_string(),
+        error,
+    })?;
+    let mut warnings = Vec::new();
+    let (remote, page) = render_page_for_file(
+        client,
+        state_dir,
+        page_id,
+        &file,
+        fetch_options,
+        user_mentions,
+        &mut warnings,
+    )
+    .await?;
+    check_strict(&warnings, fetch_options.strict).map_err(FetchError::from)?;
+    let fetch = FetchReport {
+        warnings,
+        archived: page.archived,
+    };
+
+    let (merged, merge_conflict) = match diffy::merge(&base, &local, &remote) {
+        Ok(merged) => (merged, false),
+        Err(conflicts) => (label_conflicts(&conflicts), true),
+    };
+    file.write(&merged, io::stdout().lock())
+        .map_err(|error| SyncError::WriteFile {
+            file: file.to_string(),
+            error,
+        })?;
+    // NOTE: the page now matches the fetched content, so pushing checks that it was not
+    // edited again since the fetch
+    record_sync(
+        state_dir,
+        &lock,
+        page_id,
+        Some(&remote),
+        &page.last_edited_time,
+    )
+    .map_err(SyncError::State)?;
+    drop(lock);
+
+    if merge_conflict {
+        return Err(SyncError::MergeConflict {
+            file: file.to_string(),
+            fetch,
+        });
+    }
+    let push = if merged != remote {
+        let push_options = PushOptions {
+            page_id: Some(page_id.clone()),
+            file,
+            ..push_options.clone()
+        };
+        Some(push_file(client, state_dir, configured_users, &push_options).await?)
+    } else {
+        None
+    };
+
+    Ok(SyncReport {
+        status: SyncStatus::Merged,
+        fetch: Some(fetch),
+        push,
+    })
+}
+
+/// Names the sides of the conflicts after the file and the page, in place of the `ours` and
+/// `theirs` of the merge.
+fn label_conflicts(conflicts: &str) -> String {
+    conflicts
+        .split_inclusive('\n')
+        .map(|line| match line.trim_end_matches('\n') {
+            "<<<<<<< ours" => "<<<<<<< file\n",
+            "||||||| original" => "||||||| last sync\n",
+            ">>>>>>> theirs" => ">>>>>>> notion\n",
+            _ => line,
+        })
+        .collect()
+}
+
+/// Fails when the file still has the conflict markers written by a merge, so they are never
+/// pushed to the page.
+fn check_conflicts_resolved(file: &Path) -> Result<(), SyncError> {
+    let content = match fs::read_to_string(file) {
+        Ok(content) => content,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => {
+            return Err(SyncError::ReadFile {
+                file: file.display().to_string(),
+                error,
+            })
+        }
+    };
+    let has_marker = |marker: &str| content.lines().any(|line| line == marker);
+    if has_marker("<<<<<<< file") && has_marker(">>>>>>> notion") {
+        return Err(SyncError::UnresolvedConflict {
+            file: file.display().to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use notion::ids::PageId;
+    use pretty_assertions::assert_eq;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::commands::fetch::FetchOptions;
+    use crate::commands::push::{PushMode, PushOptions};
+    use crate::commands::test_util::{
+        get_client, mock_bot_user, mock_page, mock_page_children, PAGE_ID,
+    };
+    use crate::files::{InputFormat, OutputFormat};
+    use crate::markdown::from_cmark::ParseOptions;
+    use crate::markdown::notion_interop::NotionParseOptions;
+    use crate::markdown::to_cmark::SerializeOptions;
+    use crate::markdown::to_text::TextOptions;
+    use crate::notion_api::mock_server::{get_list_json, get_paragraph_block_json};
+    use crate::page::EraseOptions;
+
+    #[tokio::test]
+    async fn syncs_two_way_entries_in_the_direction_that_changed() {
+        async fn mock_children(server: &MockServer, paragraphs: &[&str]) {
+            Mock::given(method("GET"))
+                .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+                .respond_with(
+                    ResponseTemplate::new(200).set_body_json(get_list_json(
+                        paragraphs
+                            .iter()
+                            .enumerate()
+                            .map(|(index, text)| {
+                                get_paragraph_block_json(&format!("{index:032x}"), text)
+                            })
+                            .collect(),
+                    )),
+                )
+                // NOTE: erasing the page lists the blocks to delete after the fetch
+                .up_to_n_times(2)
+                .mount(server)
+                .await;
+        }
+
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z").await;
+        mock_page_children(&server).await;
+        let client = get_client(&server);
+        let directory = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(directory.path().join("state"));
+        let file = directory.path().join("page.md");
+        let entry = ManifestEntry {
+            page_id: PageId::from_str(PAGE_ID).unwrap(),
+            file: file.clone(),
+            direction: SyncDirection::TwoWay,
+        };
+        let push_options = PushOptions {
+            page_id: None,
+            file: FileArg::Stdio,
+            parse_options: ParseOptions::default(),
+            input_format: InputFormat::Markdown,
+            title_heading: false,
+            changelog: None,
+            erase_options: EraseOptions::default(),
+            max_backups: None,
+            strict: false,
+            force: false,
+            links: None,
+            section: None,
+            block_id: None,
+            mode: PushMode::Replace,
+            resume: false,
+            unarchive: false,
+        };
+        let fetch_options = FetchOptions {
+            parse_options: NotionParseOptions::default(),
+            serialize_options: SerializeOptions::default(),
+            format: OutputFormat::Markdown,
+            title_heading: false,
+            strict: false,
+            section: None,
+            block_id: None,
+            html_document: None,
+            text_options: TextOptions::default(),
+            asset_dir: None,
+            links: None,
+        };
+        let users = HashMap::new();
+        let user_mentions = UserMentionMap::default();
+        let sync = || {
+            sync_manifest_entry(
+                &client,
+                &state_dir,
+                &users,
+                &entry,
+                &fetch_options,
+                &push_options,
+                &user_mentions,
+            )
+        };
+
+        assert_eq!(
+            sync().await.unwrap().status,
+            SyncStatus::Fetched,
+            "missing file"
+        );
+        let content = fs::read_to_string(&file).unwrap();
+        assert!(content.ends_with("Hello from the gateway\n"), "{content}");
+        assert_eq!(sync().await.unwrap().status, SyncStatus::UpToDate);
+
+        server.reset().await;
+        mock_page(&server, "2022-08-02T12:00:00.000Z").await;
+        mock_bot_user(&server).await;
+        mock_children(&server, &["Edited in Notion", "Hello from the gateway"]).await;
+        mock_children(&server, &[]).await;
+        Mock::given(method("DELETE"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(Vec::new())))
+            .expect(1)
+            .mount(&server)
+            .await;
+        fs::write(&file, format!("{content}\nLocal edit\n")).unwrap();
+        assert_eq!(sync().await.unwrap().status, SyncStatus::Merged);
+        let merged = fs::read_to_string(&file).unwrap();
+        assert!(
+            merged.ends_with("\nEdited in Notion\n\nHello from the gateway\n\nLocal edit\n"),
+            "{merged}"
+        );
+        let requests = server.received_requests().await.unwrap();
+        let pushed = requests
+            .iter()
+            .find(|request| request.method == wiremock::http::Method::Patch)
+            .map(|request| String::from_utf8_lossy(&request.body).to_string())
+            .unwrap();
+        assert!(pushed.contains("Edited in Notion") && pushed.contains("Local edit"));
+
+        server.reset().await;
+        mock_page(&server, "2022-08-03T12:00:00.000Z").await;
+        mock_children(
+            &server,
+            &["Edited in Notion", "Hello from Notion", "Local edit"],
+        )
+        .await;
+        fs::write(
+            &file,
+            merged.replace("Hello from the gateway", "Hello from the file"),
+        )
+        .unwrap();
+        assert!(matches!(
+            sync().await.unwrap_err(),
+            SyncError::MergeConflict { .. }
+        ));
+        let conflicts = fs::read_to_string(&file).unwrap();
+        assert!(
+            conflicts.contains("\n<<<<<<< file\nHello from the file\n||||||| last sync\nHello from the gateway\n=======\nHello from Notion\n>>>>>>> notion\n"),
+            "{conflicts}"
+        );
+        assert!(
+            matches!(
+                sync().await.unwrap_err(),
+                SyncError::UnresolvedConflict { .. }
+            ),
+            "the conflicts are not pushed"
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! The mocks of the Notion API that the tests of the commands share.
+
+use serde_json::{json, Value};
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use super::*;
+use crate::notion_api::client::{get_notion_reqwest_client, parse_api_base_url};
+use crate::notion_api::mock_server::{get_list_json, get_paragraph_block_json};
+
+pub(super) const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+
+pub(super) const BLOCK_ID: &str = "0a5f1b4e8c0d4c4a9c5e6d1f2b3a4c5d";
+
+pub(super) fn get_client(server: &MockServer) -> NotionClient {
+    let base_url = parse_api_base_url(&format!("{}/notion", server.uri())).unwrap();
+
+    NotionClient::new(get_notion_reqwest_client("secret", &Default::default()))
+        .with_base_url(base_url)
+}
+
+pub(super) async fn mock_bot_user(server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path("/notion/v1/users/me"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "object": "user",
+            "id": "16d84278-ab0e-484c-9bdd-b35da3bd8905",
+            "type": "bot",
+            "bot": { "owner": { "type": "workspace", "workspace": true } }
+        })))
+        .mount(server)
+        .await;
+}
+
+pub(super) async fn mock_page_children(server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+            get_paragraph_block_json(BLOCK_ID, "Hello from the gateway"),
+        ])))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(server)
+        .await;
+}
+
+pub(super) async fn mock_page(server: &MockServer, last_edited_time: &str) {
+    Mock::given(method("GET"))
+        .and(path(format!("/notion/v1/pages/{PAGE_ID}")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "object": "page",
+            "id": PAGE_ID,
+            "last_edited_time": last_edited_time,
+            "properties": {}
+        })))
+        .mount(server)
+        .await;
+}
+
+/// Mocks erasing the page and creating its content `expected_pushes` times.
+pub(super) async fn mock_push(server: &MockServer, expected_pushes: u64) {
+    mock_bot_user(server).await;
+    Mock::given(method("GET"))
+        .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(Vec::new())))
+        // NOTE: the local backup reads the page too
+        .expect(expected_pushes * 3)
+        .mount(server)
+        .await;
+    Mock::given(method("PATCH"))
+        .and(path(format!("/notion/v1/blocks/{PAGE_ID}/children")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+            get_paragraph_block_json(BLOCK_ID, "Pushed content"),
+        ])))
+        .expect(expected_pushes)
+        .mount(server)
+        .await;
+}
+
+pub(super) fn get_child_page_block_json(id: &str, title: &str) -> Value {
+    let mut block = get_paragraph_block_json(id, "");
+    let fields = block.as_object_mut().unwrap();
+    fields.remove("paragraph");
+    fields.insert("type".to_string(), json!("child_page"));
+    fields.insert("child_page".to_string(), json!({ "title": title }));
+
+    block
+}
+
+pub(super) fn get_file_block_json(id: &str, block_type: &str, url: &str) -> Value {
+    let mut block = get_paragraph_block_json(id, "");
+    let fields = block.as_object_mut().unwrap();
+    fields.remove("paragraph");
+    fields.insert("type".to_string(), json!(block_type));
+    fields.insert(
+        block_type.to_string(),
+        json!({
+            "type": "file",
+            "file": { "url": url, "expiry_time": "2022-08-01T13:00:00.000Z" }
+        }),
+    );
+
+    block
+}
+
+pub(super) fn get_heading_block_json(id: &str, content: &str) -> Value {
+    let mut block = get_paragraph_block_json(id, content);
+    block["type"] = json!("heading_2");
+    block["heading_2"] = block["paragraph"].take();
+    block.as_object_mut().unwrap().remove("paragraph");
+    block
+}
+
+pub(super) fn get_toggle_block_json(id: &str, content: &str, archived: bool) -> Value {
+    let mut block = get_paragraph_block_json(id, content);
+    block["type"] = json!("toggle");
+    block["toggle"] = block["paragraph"].take();
+    block["has_children"] = json!(true);
+    block["archived"] = json!(archived);
+
+    block
+}
+
+pub(super) async fn mock_toggle(server: &MockServer, toggle_id: &str, archived: bool) {
+    Mock::given(method("GET"))
+        .and(path(format!("/notion/v1/blocks/{toggle_id}")))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(get_toggle_block_json(toggle_id, "Details", archived)),
+        )
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("/notion/v1/blocks/{toggle_id}/children")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+            get_paragraph_block_json(BLOCK_ID, "Old details"),
+        ])))
+        .mount(server)
+        .await;
+}
+
+pub(super) const LINK_TO_OTHER_PAGE: &str = "https://www.notion.so/Caf%C3%A9-notes-0b89a6e8f0064acc8ec6e6902b039e3a?pvs=4#1c2d3e4f5a6b4c7d8e9f0a1b2c3d4e5f";
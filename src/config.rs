@@ -0,0 +1,177 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::auth::ApiKey;
+
+/// User configuration, read from `~/.config/notion-edit/config.toml` by default.
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+pub struct Config {
+    /// Maps Notion user IDs (or emails of the users) to the text that mentions of these users
+    /// are rendered as in markdown.
+    #[serde(default)]
+    pub users: HashMap<String, String>,
+
+    /// Base URL of the Notion API, for example a local gateway (`http://localhost:8080/notion`).
+    /// `--api-base-url` and the `NOTION_API_BASE_URL` environment variable take precedence.
+    pub api_base_url: Option<String>,
+
+    /// The Notion API key, used when neither `--token` nor `NOTION_API_KEY` is given.
+    pub api_key: Option<ApiKey>,
+
+    /// How many local backups of each page are kept in the state directory, 5 by default.
+    pub max_backups: Option<usize>,
+
+    /// Custom domains of workspaces, whose page and database URLs are accepted besides
+    /// the `notion.so` and `*.notion.site` ones.
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+
+    /// Settings for other workspaces, picked with `--profile`.
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+}
+
+/// Settings of a `[profiles.<name>]` table.
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+pub struct Profile {
+    pub api_key: Option<ApiKey>,
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("cannot read config file {path}")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("invalid config file {path}")]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|config_dir| config_dir.join("notion-edit").join("config.toml"))
+    }
+
+    /// Loads the config from the explicitly provided path, or from the default path.
+    /// A missing config file at the default path is not an error.
+    pub fn load(explicit_path: Option<&Path>) -> Result<Self, ConfigError> {
+        let path = match explicit_path
+            .map(Path::to_path_buf)
+            .or_else(Self::default_path)
+        {
+            Some(path) => path,
+            None => return Ok(Self::default()),
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => {
+                Self::parse(&contents).map_err(|source| ConfigError::Parse { path, source })
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound && explicit_path.is_none() => {
+                Ok(Self::default())
+            }
+            Err(source) => Err(ConfigError::Read { path, source }),
+        }
+    }
+
+    fn parse(contents: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn parses_users_table() {
+        let config = Config::parse(
+            r#"
+[users]
+"ac32e025-6f9c-4fab-8b9d-dbb3c593ac46" = "@gelio"
+"someone@example.com" = "@someone"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.users,
+            HashMap::from([
+                (
+                    "ac32e025-6f9c-4fab-8b9d-dbb3c593ac46".to_string(),
+                    "@gelio".to_string()
+                ),
+                ("someone@example.com".to_string(), "@someone".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn parses_api_base_url() {
+        let config = Config::parse(r#"api_base_url = "http://localhost:8080/notion""#).unwrap();
+
+        assert_eq!(
+            config.api_base_url.as_deref(),
+            Some("http://localhost:8080/notion")
+        );
+    }
+
+    #[test]
+    fn parses_allowed_hosts() {
+        let config = Config::parse(r#"allowed_hosts = ["wiki.example.com"]"#).unwrap();
+
+        assert_eq!(config.allowed_hosts, ["wiki.example.com"]);
+    }
+
+    #[test]
+    fn parses_profiles() {
+        let config = Config::parse(
+            r#"
+api_key = "secret_personal"
+
+[profiles.work]
+api_key = "secret_work"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.api_key, Some(ApiKey::new("secret_personal")));
+        assert_eq!(
+            config.profiles["work"].api_key,
+            Some(ApiKey::new("secret_work"))
+        );
+    }
+
+    #[test]
+    fn empty_config_is_valid() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn missing_explicit_config_is_an_error() {
+        let directory = tempfile::tempdir().unwrap();
+
+        assert!(matches!(
+            Config::load(Some(&directory.path().join("config.toml"))),
+            Err(ConfigError::Read { .. })
+        ));
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    env, io,
+    path::Path,
+    process::{ExitStatus, Stdio},
+};
+
+use thiserror::Error;
+use tokio::process::Command;
+
+/// The editor used when neither $VISUAL nor $EDITOR is set.
+const DEFAULT_EDITOR: &str = "vi";
+
+/// The editor command from $VISUAL or $EDITOR, like `code --wait`.
+pub fn editor_command() -> String {
+    env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string())
+}
+
+#[derive(Error, Debug)]
+pub enum EditorError {
+    #[error("could not start the editor `{editor}`: {error}")]
+    Spawn { editor: String, error: io::Error },
+
+    #[error("the editor `{editor}` exited with {status}")]
+    Failed { editor: String, status: ExitStatus },
+}
+
+/// Opens the file in the editor and waits until the editor exits. The editor command is split
+/// on whitespace, and the path of the file is passed after its arguments.
+pub async fn edit_file(editor: &str, path: &Path) -> Result<(), EditorError> {
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or(DEFAULT_EDITOR);
+
+    let status = Command::new(program)
+        .args(words)
+        .arg(path)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .await
+        .map_err(|error| EditorError::Spawn {
+            editor: editor.to_string(),
+            error,
+        })?;
+    if !status.success() {
+        return Err(EditorError::Failed {
+            editor: editor.to_string(),
+            status,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn passes_the_file_after_the_arguments_of_the_editor() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("page.md");
+        fs::write(&path, "Hello\n").unwrap();
+
+        edit_file("sed -i s/Hello/Bye/", &path).await.unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Bye\n");
+    }
+
+    #[tokio::test]
+    async fn reports_editors_that_fail_or_do_not_exist() {
+        let path = Path::new("page.md");
+
+        assert!(matches!(
+            edit_file("false", path).await,
+            Err(EditorError::Failed { .. })
+        ));
+        assert!(matches!(
+            edit_file("notion-edit-missing-editor", path).await,
+            Err(EditorError::Spawn { .. })
+        ));
+    }
+}
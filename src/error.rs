@@ -0,0 +1,388 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    process::ExitCode,
+};
+
+use notion_edit::{
+    ids::{ParseDatabaseIdError, ParsePageIdError, ParsePageIdFromUrlError},
+    markdown::warning::Warning,
+    notion_api::{
+        client::{
+            BackUpPageError, CheckAccessError, CreateBlocksError, ErasePageError, GetBlockError,
+            InvalidBlockError, NotionApiError, RequestError,
+        },
+        properties::PropertyError,
+    },
+    MarkdownToPageError,
+};
+
+use crate::auth::ApiKeyError;
+use crate::commands::database::FetchDatabaseError;
+use crate::commands::fetch::FetchError;
+use crate::commands::new::NewPageError;
+use crate::commands::push::{FilePageIdError, PushError};
+use crate::commands::restore::RestoreError;
+use crate::commands::StrictModeError;
+use crate::section::SectionError;
+
+/// What went wrong, which decides the exit code of the command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Any other failure. `diff` also exits with it when the file differs from the page.
+    Failure = 1,
+    /// Invalid arguments, like a missing page or an invalid filter.
+    Usage = 2,
+    /// Notion rejected the API key, or there is no API key.
+    Auth = 3,
+    /// The page or the database does not exist, or is not shared with the integration.
+    NotFound = 4,
+    /// Notion could not be reached.
+    Network = 5,
+    /// The document could not be converted, for example invalid front matter.
+    Conversion = 6,
+    /// The conversion would have lost something, and `--strict` was passed.
+    Lossy = 7,
+    /// `sync` wrote conflicts between the edits of the file and of the page to the file.
+    Conflict = 8,
+}
+
+impl ErrorKind {
+    /// The kind of the first error in the chain with a known kind.
+    pub fn of(error: &(dyn Error + 'static)) -> Self {
+        let mut error = Some(error);
+        while let Some(current) = error {
+            if let Some(kind) = known_kind(current) {
+                return kind;
+            }
+            error = current.source();
+        }
+
+        Self::Failure
+    }
+
+    pub fn exit_code(self) -> ExitCode {
+        ExitCode::from(self as u8)
+    }
+}
+
+fn known_kind(error: &(dyn Error + 'static)) -> Option<ErrorKind> {
+    if let Some(error) = error.downcast_ref::<NotionApiError>() {
+        return match error.status {
+            401 => Some(ErrorKind::Auth),
+            403 | 404 => Some(ErrorKind::NotFound),
+            _ => None,
+        };
+    }
+    if error.is::<RequestError>() {
+        return Some(ErrorKind::Network);
+    }
+    if error.is::<ApiKeyError>()
+        || matches!(
+            error.downcast_ref(),
+            Some(CheckAccessError::InvalidApiKey(_))
+        )
+    {
+        return Some(ErrorKind::Auth);
+    }
+    if error.is::<ParsePageIdError>()
+        || error.is::<ParseDatabaseIdError>()
+        || error.is::<ParsePageIdFromUrlError>()
+        || error.is::<SectionError>()
+        || matches!(error.downcast_ref(), Some(FilePageIdError::Missing))
+        || matches!(error.downcast_ref(), Some(FetchDatabaseError::Filter(_)))
+        || matches!(error.downcast_ref(), Some(NewPageError::Template(_)))
+    {
+        return Some(ErrorKind::Usage);
+    }
+    if matches!(
+        error.downcast_ref(),
+        Some(RestoreError::NoBackups { .. } | RestoreError::MissingBackup { .. })
+    ) || matches!(error.downcast_ref(), Some(GetBlockError::Archived(_)))
+        || matches!(error.downcast_ref(), Some(PushError::Archived { .. }))
+    {
+        return Some(ErrorKind::NotFound);
+    }
+    if strict_warnings(error).is_some() {
+        return Some(ErrorKind::Lossy);
+    }
+    if error.is::<PropertyError>()
+        || error.is::<serde_yaml::Error>()
+        || matches!(
+            error.downcast_ref(),
+            Some(PushError::Parse(_) | PushError::ParseJson(_) | PushError::InvalidFrontMatter(_))
+        )
+        || matches!(error.downcast_ref(), Some(RestoreError::Parse(_)))
+        || matches!(error.downcast_ref(), Some(NewPageError::Parse(_)))
+        || matches!(
+            error.downcast_ref(),
+            Some(MarkdownToPageError::InvalidBlocks(_))
+        )
+    {
+        return Some(ErrorKind::Conversion);
+    }
+    // NOTE: the failures of the requests are not sources, as there may be many
+    if let Some(MarkdownToPageError::CreateBlocks(errors)) = error.downcast_ref() {
+        return errors.first().map(|error| ErrorKind::of(error));
+    }
+    if let Some(ErasePageError::DeleteBlocksFailed(errors)) = error.downcast_ref() {
+        return errors.first().map(|error| ErrorKind::of(error));
+    }
+
+    None
+}
+
+/// An error that stops the command.
+#[derive(Debug)]
+pub struct CommandError {
+    kind: ErrorKind,
+    error: Box<dyn Error + Send + Sync>,
+}
+
+impl CommandError {
+    pub fn new(kind: ErrorKind, error: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        Self {
+            kind,
+            error: error.into(),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error followed by its sources, each indented under the previous one. Sources
+    /// already included in the message above them are skipped. The blocks that could not
+    /// be created, or the lossy conversions under `--strict`, follow as a numbered list in
+    /// the order of the document.
+    pub fn render(&self) -> String {
+        let mut rendered = format!("Error: {}\n", self.error);
+        let mut previous_message = self.error.to_string();
+        let mut depth = 1;
+        let mut source = self.error.source();
+        while let Some(error) = source {
+            let message = error.to_string();
+            if !previous_message.contains(&message) {
+                rendered.push_str(&format!("{}# {message}\n", "  ".repeat(depth)));
+                depth += 1;
+            }
+            previous_message = message;
+            source = error.source();
+        }
+        for (number, item) in listed_items(self.error.as_ref()).iter().enumerate() {
+            rendered.push_str(&format!("  {}. {item}\n", number + 1));
+        }
+
+        rendered
+    }
+}
+
+/// The blocks that could not be created or the lossy conversions, from the first error in
+/// the chain that has some.
+fn listed_items(error: &(dyn Error + 'static)) -> Vec<String> {
+    let mut error = Some(error);
+    while let Some(current) = error {
+        if let Some(warnings) = strict_warnings(current) {
+            return warnings.iter().map(ToString::to_string).collect();
+        }
+        match (current.downcast_ref(), current.downcast_ref()) {
+            (Some(MarkdownToPageError::CreateBlocks(errors)), _)
+            | (_, Some(BackUpPageError::CopyContent(errors))) => {
+                return errors
+                    .iter()
+                    .map(|error: &CreateBlocksError| one_line(error))
+                    .collect()
+            }
+            (Some(MarkdownToPageError::InvalidBlocks(errors)), _) => {
+                return errors
+                    .iter()
+                    .map(|error: &InvalidBlockError| one_line(error))
+                    .collect()
+            }
+            _ => error = current.source(),
+        }
+    }
+
+    Vec::new()
+}
+
+/// The lossy conversions of the error reported with `--strict`. The errors that wrap them
+/// are transparent, so they are not found among the sources.
+fn strict_warnings<'a>(error: &'a (dyn Error + 'static)) -> Option<&'a [Warning]> {
+    match (
+        error.downcast_ref(),
+        error.downcast_ref(),
+        error.downcast_ref(),
+    ) {
+        (Some(StrictModeError(warnings)), _, _)
+        | (_, Some(FetchError::Strict(StrictModeError(warnings))), _)
+        | (_, _, Some(PushError::Strict(StrictModeError(warnings)))) => Some(warnings),
+        _ => None,
+    }
+}
+
+/// The error and the sources that are not already in its message, on one line.
+fn one_line(error: &(dyn Error + 'static)) -> String {
+    let mut line = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        let message = error.to_string();
+        if !line.contains(&message) {
+            line.push_str(": ");
+            line.push_str(&message);
+        }
+        source = error.source();
+    }
+
+    line
+}
+
+impl<E: Error + Send + Sync + 'static> From<E> for CommandError {
+    fn from(error: E) -> Self {
+        Self {
+            kind: ErrorKind::of(&error),
+            error: Box::new(error),
+        }
+    }
+}
+
+/// The error with a message that says what was being done.
+#[derive(Debug)]
+struct ContextError<E> {
+    message: String,
+    source: E,
+}
+
+impl<E> Display for ContextError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl<E: Error + 'static> Error for ContextError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+pub trait Context<T> {
+    /// Describes what failed, keeping the error as the source.
+    fn context(self, message: impl Display) -> Result<T, CommandError>;
+}
+
+impl<T, E: Error + Send + Sync + 'static> Context<T> for Result<T, E> {
+    fn context(self, message: impl Display) -> Result<T, CommandError> {
+        self.map_err(|source| {
+            ContextError {
+                message: message.to_string(),
+                source,
+            }
+            .into()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, str::FromStr};
+
+    use notion::ids::BlockId;
+    use notion_edit::notion_api::client::AppendBlockChildrenError;
+    use pretty_assertions::assert_eq;
+    use thiserror::Error;
+
+    use super::*;
+
+    #[derive(Error, Debug)]
+    enum TestError {
+        #[error("could not get the page: {0}")]
+        GetPage(#[source] RequestError),
+    }
+
+    #[test]
+    fn renders_the_sources_not_in_the_message() {
+        let error = Err::<(), _>(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "permission denied",
+        ))
+        .context("could not read notes.md")
+        .unwrap_err();
+
+        assert_eq!(
+            error.render(),
+            "Error: could not read notes.md\n  # permission denied\n"
+        );
+        assert_eq!(error.kind(), ErrorKind::Failure);
+    }
+
+    #[test]
+    fn takes_the_kind_from_the_sources() {
+        let error = CommandError::from(TestError::GetPage(RequestError::TimedOut {
+            timeout: std::time::Duration::from_secs(30),
+        }));
+
+        assert_eq!(error.kind(), ErrorKind::Network);
+        assert_eq!(
+            error.render(),
+            "Error: could not get the page: request to Notion timed out after 30s\n"
+        );
+    }
+    #[test]
+    fn missing_sections_are_usage_errors() {
+        let error = CommandError::from(PushError::Section(SectionError::NotFound {
+            heading: "Budget".to_string(),
+            headings: Vec::new(),
+        }));
+
+        assert_eq!(error.kind(), ErrorKind::Usage);
+    }
+
+    #[test]
+    fn lists_the_blocks_that_could_not_be_created() {
+        let failed_blocks = |position: Vec<usize>, code: &str| CreateBlocksError {
+            parent_block_id: BlockId::from_str("0a5f1b4e8c0d4c4a9c5e6d1f2b3a4c5d").unwrap(),
+            parent_position: position,
+            blocks: 0..2,
+            preview: "paragraph \"Budget\"".to_string(),
+            error: AppendBlockChildrenError::Api(NotionApiError {
+                status: 400,
+                code: code.to_string(),
+                message: "body.children[1] is invalid".to_string(),
+            }),
+        };
+        let error = CommandError::from(PushError::Push(MarkdownToPageError::CreateBlocks(vec![
+            failed_blocks(vec![2], "validation_error"),
+            failed_blocks(vec![4, 1], "conflict_error"),
+        ])));
+
+        assert_eq!(error.kind(), ErrorKind::Failure);
+        assert_eq!(
+            error.render(),
+            "Error: could not push the document to Notion: cannot create 2 group(s) of blocks\n  \
+             1. blocks 1-2 nested in block 3 (0a5f1b4e8c0d4c4a9c5e6d1f2b3a4c5d), starting with paragraph \"Budget\": Notion rejected the request: validation_error: body.children[1] is invalid\n  \
+             2. blocks 1-2 nested in block 5.2 (0a5f1b4e8c0d4c4a9c5e6d1f2b3a4c5d), starting with paragraph \"Budget\": Notion rejected the request: conflict_error: body.children[1] is invalid\n"
+        );
+    }
+
+    #[test]
+    fn lists_the_lossy_conversions_under_strict() {
+        let error = CommandError::from(FetchError::Strict(StrictModeError(vec![
+            Warning::BlockSkipped {
+                block_id: "0a5f1b4e8c0d4c4a9c5e6d1f2b3a4c5d".to_string(),
+                block_type: "column_list".to_string(),
+            },
+            Warning::FootnoteNotDefined {
+                label: "missing".to_string(),
+            },
+        ])));
+
+        assert_eq!(error.kind(), ErrorKind::Lossy);
+        assert_eq!(error.kind().exit_code(), ExitCode::from(7));
+        assert_eq!(
+            error.render(),
+            "Error: 2 lossy conversion(s) were reported with --strict, nothing was changed\n  \
+             1. column_list block 0a5f1b4e8c0d4c4a9c5e6d1f2b3a4c5d was skipped, it has no markdown equivalent\n  \
+             2. footnote [^missing] is referenced, but never defined\n"
+        );
+    }
+}
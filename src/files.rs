@@ -0,0 +1,174 @@
+//! The files that pages are fetched into and pushed from.
+
+use std::{
+    fmt::{self, Display},
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::atomic_file::write_atomically;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum InputFormat {
+    Markdown,
+    /// Plain text, where paragraphs are separated by blank lines. Markdown syntax is not
+    /// interpreted.
+    Text,
+    /// The tags written by `fetch --format json`.
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum OutputFormat {
+    Markdown,
+    /// The tags of the document as JSON, for processing with other tools.
+    Json,
+    /// An HTML fragment without the front matter, to preview the page or send it by email.
+    Html,
+    /// Plain text without any markup, for grepping and other text tools.
+    Text,
+}
+
+/// A file argument, where `-` stands for stdin when reading and for stdout when writing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileArg {
+    Stdio,
+    Path(PathBuf),
+}
+
+impl From<PathBuf> for FileArg {
+    fn from(path: PathBuf) -> Self {
+        if path.as_os_str() == "-" {
+            Self::Stdio
+        } else {
+            Self::Path(path)
+        }
+    }
+}
+
+impl Display for FileArg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stdio => write!(f, "standard input/output"),
+            Self::Path(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl FileArg {
+    /// The markdown file named after the title of a page, for example `my-page-title.md`.
+    pub fn for_page_title(title: &str) -> Self {
+        Self::Path(PathBuf::from(page_file_name(title)))
+    }
+
+    /// The path of the file, unless it is stdin or stdout.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Stdio => None,
+            Self::Path(path) => Some(path),
+        }
+    }
+
+    pub fn read_to_string(&self, mut stdin: impl Read) -> io::Result<String> {
+        match self {
+            Self::Stdio => {
+                let mut content = String::new();
+                stdin.read_to_string(&mut content)?;
+                Ok(content)
+            }
+            Self::Path(path) => fs::read_to_string(path),
+        }
+    }
+
+    /// Writes the content, atomically when writing to a file.
+    pub fn write(&self, content: &str, mut stdout: impl Write) -> io::Result<()> {
+        match self {
+            Self::Stdio => {
+                stdout.write_all(content.as_bytes())?;
+                stdout.flush()
+            }
+            Self::Path(path) => {
+                write_atomically(path, |writer| writer.write_all(content.as_bytes()))
+            }
+        }
+    }
+}
+
+/// The markdown file name for a page, from its slugified title, for example `my-page-title.md`.
+pub fn page_file_name(title: &str) -> String {
+    format!("{}.md", page_slug(title))
+}
+
+/// The title in lowercase, with the words separated by dashes, for example `my-page-title`.
+/// Untitled pages are `untitled`.
+pub fn page_slug(title: &str) -> String {
+    let mut slug = String::new();
+    for word in title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.extend(word.chars().flat_map(char::to_lowercase));
+    }
+    if slug.is_empty() {
+        slug.push_str("untitled");
+    }
+
+    slug
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn dash_is_stdio() {
+        assert_eq!(FileArg::from(PathBuf::from("-")), FileArg::Stdio);
+        assert_eq!(
+            FileArg::from(PathBuf::from("./-")),
+            FileArg::Path(PathBuf::from("./-"))
+        );
+    }
+
+    #[test]
+    fn stdio_reads_and_writes_the_same_as_files() {
+        let directory = tempfile::tempdir().unwrap();
+        let file = FileArg::Path(directory.path().join("page.md"));
+        let content = "# Page\n\nContent\n";
+
+        let mut stdout = Vec::new();
+        FileArg::Stdio.write(content, &mut stdout).unwrap();
+        file.write(content, io::sink()).unwrap();
+        assert_eq!(
+            String::from_utf8(stdout).unwrap(),
+            fs::read_to_string(file.path().unwrap()).unwrap()
+        );
+
+        assert_eq!(
+            FileArg::Stdio.read_to_string(content.as_bytes()).unwrap(),
+            file.read_to_string(io::empty()).unwrap()
+        );
+    }
+
+    #[test]
+    fn file_for_page_title_is_a_slug() {
+        for (title, file) in [
+            ("My Page Title", "my-page-title.md"),
+            ("  Q3 — plans & goals!  ", "q3-plans-goals.md"),
+            ("Zażółć gęślą jaźń", "zażółć-gęślą-jaźń.md"),
+            ("", "untitled.md"),
+        ] {
+            assert_eq!(
+                FileArg::for_page_title(title),
+                FileArg::Path(PathBuf::from(file))
+            );
+        }
+    }
+}
@@ -0,0 +1,535 @@
+//! Parsing the IDs of pages and databases from their Notion URLs.
+
+use std::str::FromStr;
+
+use notion::ids::{BlockId, DatabaseId, PageId};
+use reqwest::Url;
+use thiserror::Error;
+use url::Host;
+use uuid::Uuid;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ParseDatabaseIdError {
+    #[error("Invalid URL: {0}")]
+    Url(#[from] ParsePageIdFromUrlError),
+
+    #[error("Invalid UUID: {0}")]
+    Uuid(#[from] ParsePageIdFromUuidError),
+}
+
+/// Hosts of Notion URLs: the app, with or without `www`, and public pages on subdomains of
+/// `notion.site`.
+const NOTION_HOSTS: [&str; 2] = ["notion.so", "www.notion.so"];
+const PUBLIC_PAGES_DOMAIN: &str = ".notion.site";
+
+/// Whether URLs on the host point to Notion pages. `allowed_hosts` are the custom domains of
+/// workspaces.
+pub fn is_notion_host(host: &str, allowed_hosts: &[String]) -> bool {
+    NOTION_HOSTS.contains(&host)
+        || host.ends_with(PUBLIC_PAGES_DOMAIN)
+        || allowed_hosts
+            .iter()
+            .any(|allowed_host| allowed_host.eq_ignore_ascii_case(host))
+}
+
+fn check_host(url: &Url, allowed_hosts: &[String]) -> Result<(), ParsePageIdFromUrlError> {
+    match url.host() {
+        None => Err(ParsePageIdFromUrlError::MissingHostname),
+        Some(Host::Domain(domain)) if is_notion_host(domain, allowed_hosts) => Ok(()),
+        Some(host) => Err(ParsePageIdFromUrlError::NotNotionHostname(host.to_owned())),
+    }
+}
+
+/// The host of the URL when it is not one of Notion's.
+fn unknown_host(s: &str) -> Option<String> {
+    let url = Url::parse(s).ok()?;
+    let host = url.host_str()?;
+
+    (!is_notion_host(host, &[])).then(|| host.to_string())
+}
+
+/// An ID from the command line. URLs on other hosts than Notion's are accepted when they are
+/// parsed, as the custom domains from `--allow-host` and the config are not known yet, and
+/// are checked with [`IdArg::check_host`] afterwards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdArg<Id> {
+    id: Id,
+    unknown_host: Option<String>,
+}
+
+impl<Id> IdArg<Id> {
+    /// The ID, when the URL it was parsed from is on a Notion host or on one of
+    /// `allowed_hosts`.
+    pub fn check_host(self, allowed_hosts: &[String]) -> Result<Id, ParsePageIdFromUrlError> {
+        match self.unknown_host {
+            Some(host) if !is_notion_host(&host, allowed_hosts) => {
+                Err(ParsePageIdFromUrlError::NotNotionHostname(
+                    Host::parse(&host).expect("the host was parsed from the URL"),
+                ))
+            }
+            _ => Ok(self.id),
+        }
+    }
+}
+
+/// Parses the ID of a page from the command line, to be checked with [`IdArg::check_host`].
+pub fn parse_page_id_arg(s: &str) -> Result<IdArg<PageId>, ParsePageIdError> {
+    let unknown_host = unknown_host(s);
+    let id = parse_page_id_with_hosts(s, unknown_host.as_slice())?;
+
+    Ok(IdArg { id, unknown_host })
+}
+
+/// Parses the page and the block of a URL from the command line, to be checked with
+/// [`IdArg::check_host`].
+pub fn parse_target_arg(s: &str) -> Result<IdArg<Target>, ParsePageIdError> {
+    let unknown_host = unknown_host(s);
+    let id = parse_target_with_hosts(s, unknown_host.as_slice())?;
+
+    Ok(IdArg { id, unknown_host })
+}
+
+/// Parses the ID of a block from the command line, to be checked with
+/// [`IdArg::check_host`]. It is the ID itself, or a URL with the ID after `#`.
+pub fn parse_block_id_arg(s: &str) -> Result<IdArg<BlockId>, ParsePageIdError> {
+    if Url::parse(s).is_err() {
+        return Ok(IdArg {
+            id: parse_block_id_from_uuid(s)?,
+            unknown_host: None,
+        });
+    }
+
+    let IdArg { id, unknown_host } = parse_target_arg(s)?;
+    let block_id = id
+        .block_id
+        .ok_or(ParsePageIdFromUrlError::MissingBlockInFragment)?;
+
+    Ok(IdArg {
+        id: block_id,
+        unknown_host,
+    })
+}
+
+/// Parses the ID of a database from the command line, to be checked with
+/// [`IdArg::check_host`].
+pub fn parse_database_id_arg(s: &str) -> Result<IdArg<DatabaseId>, ParseDatabaseIdError> {
+    let unknown_host = unknown_host(s);
+    let id = parse_database_id_with_hosts(s, unknown_host.as_slice())?;
+
+    Ok(IdArg { id, unknown_host })
+}
+
+/// Parses the ID of the database from its URL or the ID itself. Unlike in page URLs,
+/// the `v` and `p` query parameters are the view and the page opened in it.
+pub fn parse_database_id(s: &str) -> Result<DatabaseId, ParseDatabaseIdError> {
+    parse_database_id_with_hosts(s, &[])
+}
+
+/// Parses the ID of the database like [`parse_database_id`], also from URLs on the
+/// custom domains in `allowed_hosts`.
+pub fn parse_database_id_with_hosts(
+    s: &str,
+    allowed_hosts: &[String],
+) -> Result<DatabaseId, ParseDatabaseIdError> {
+    let uuid = match Url::parse(s) {
+        Ok(url) => {
+            check_host(&url, allowed_hosts)?;
+
+            let last_segment = url
+                .path_segments()
+                .and_then(|segments| segments.rev().find(|segment| !segment.is_empty()))
+                .ok_or(ParsePageIdFromUrlError::NoPathSegments)?;
+            let uuid_candidate = last_segment.split('-').next_back().unwrap_or_default();
+
+            parse_page_id_from_uuid(uuid_candidate)
+                .map_err(ParsePageIdFromUrlError::InvalidUuidInPath)?
+        }
+        Err(_) => parse_page_id_from_uuid(s)?,
+    };
+
+    Ok(DatabaseId::from_str(&uuid.to_string())
+        .expect("notion crate DatabaseId does not do any validation when parsing"))
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ParsePageIdError {
+    #[error("Invalid URL: {0}")]
+    Url(#[from] ParsePageIdFromUrlError),
+
+    #[error("Invalid UUID: {0}")]
+    Uuid(#[from] ParsePageIdFromUuidError),
+}
+
+/// Parses the ID of the page from its URL or the ID itself. The ID is at the end of the last
+/// segment of the path, unless the `p` query parameter has the ID of a page opened in
+/// a database.
+pub fn parse_page_id(s: &str) -> Result<PageId, ParsePageIdError> {
+    parse_page_id_with_hosts(s, &[])
+}
+
+/// Parses the ID of the page like [`parse_page_id`], also from URLs on the custom domains
+/// in `allowed_hosts`.
+pub fn parse_page_id_with_hosts(
+    s: &str,
+    allowed_hosts: &[String],
+) -> Result<PageId, ParsePageIdError> {
+    if let Ok(url) = Url::parse(s) {
+        parse_page_id_from_url(url, allowed_hosts).map_err(Into::into)
+    } else {
+        parse_page_id_from_uuid(s).map_err(Into::into)
+    }
+}
+
+/// A page, or a block in it when its URL has the ID of the block after `#`, as in the links
+/// to blocks copied from Notion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub page_id: PageId,
+    pub block_id: Option<BlockId>,
+}
+
+/// Parses the page like [`parse_page_id`], and the block from the `#` fragment of the URL.
+pub fn parse_target(s: &str) -> Result<Target, ParsePageIdError> {
+    parse_target_with_hosts(s, &[])
+}
+
+/// Parses the page and the block like [`parse_target`], also from URLs on the custom domains
+/// in `allowed_hosts`.
+pub fn parse_target_with_hosts(
+    s: &str,
+    allowed_hosts: &[String],
+) -> Result<Target, ParsePageIdError> {
+    let page_id = parse_page_id_with_hosts(s, allowed_hosts)?;
+    let fragment = Url::parse(s)
+        .ok()
+        .and_then(|url| url.fragment().map(str::to_string))
+        .filter(|fragment| !fragment.is_empty());
+    let block_id = match fragment {
+        Some(fragment) => Some(parse_block_id_from_uuid(&fragment).map_err(|error| {
+            ParsePageIdFromUrlError::InvalidUuidInFragment {
+                source: error,
+                uuid_candidate: fragment,
+            }
+        })?),
+        None => None,
+    };
+
+    Ok(Target { page_id, block_id })
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ParsePageIdFromUrlError {
+    #[error("Missing hostname")]
+    MissingHostname,
+
+    #[error("Not a Notion URL: {0}. If it is the custom domain of a workspace, pass --allow-host {0} or add it to allowed_hosts in the config")]
+    NotNotionHostname(Host<String>),
+
+    #[error("Empty path")]
+    NoPathSegments,
+
+    #[error("Page ID missing in the URL. Expected page ID at the end of the path")]
+    NotEnoughPathSegments,
+
+    #[error("Invalid UUID in path. {0}")]
+    InvalidUuidInPath(#[source] ParsePageIdFromUuidError),
+
+    #[error("Invalid UUID {uuid_candidate} in query parameters. {source}")]
+    InvalidUuidInQuery {
+        #[source]
+        source: ParsePageIdFromUuidError,
+
+        uuid_candidate: String,
+    },
+
+    #[error("Invalid block ID {uuid_candidate} after # in the URL. {source}")]
+    InvalidUuidInFragment {
+        #[source]
+        source: ParsePageIdFromUuidError,
+
+        uuid_candidate: String,
+    },
+
+    #[error("Block ID missing in the URL. Expected it after # at the end of the URL")]
+    MissingBlockInFragment,
+}
+
+fn parse_page_id_from_url(
+    url: Url,
+    allowed_hosts: &[String],
+) -> Result<PageId, ParsePageIdFromUrlError> {
+    check_host(&url, allowed_hosts)?;
+
+    // NOTE: app URLs have the workspace before the page, while public pages and custom
+    // domains may start with the page
+    let last_segment = match url.path_segments() {
+        None => Err(ParsePageIdFromUrlError::NoPathSegments),
+        Some(mut path_segments) => path_segments
+            .rfind(|segment| !segment.is_empty())
+            .ok_or(ParsePageIdFromUrlError::NotEnoughPathSegments),
+    }?;
+
+    let path_uuid_candidate = last_segment
+        .split('-')
+        .next_back()
+        .ok_or(ParsePageIdFromUrlError::NotEnoughPathSegments)?;
+
+    let path_uuid = parse_page_id_from_uuid(path_uuid_candidate)
+        .map_err(ParsePageIdFromUrlError::InvalidUuidInPath)?;
+
+    match url.query_pairs().find(|(key, _value)| key == "p") {
+        None => Ok(path_uuid),
+        Some((_key, query_uuid_candidate)) => parse_page_id_from_uuid(&query_uuid_candidate)
+            .map_err(|error| ParsePageIdFromUrlError::InvalidUuidInQuery {
+                source: error,
+                uuid_candidate: query_uuid_candidate.to_string(),
+            }),
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ParsePageIdFromUuidError {
+    #[error("Cannot parse UUID: {0}")]
+    CannotParse(#[from] uuid::Error),
+}
+
+fn parse_page_id_from_uuid(s: &str) -> Result<PageId, ParsePageIdFromUuidError> {
+    let parsed_uuid = Uuid::try_parse(s).map_err(ParsePageIdFromUuidError::CannotParse)?;
+
+    Ok(PageId::from_str(&parsed_uuid.hyphenated().to_string())
+        .expect("notion crate PageId does not do any validation when parsing"))
+}
+
+fn parse_block_id_from_uuid(s: &str) -> Result<BlockId, ParsePageIdFromUuidError> {
+    let parsed_uuid = Uuid::try_parse(s).map_err(ParsePageIdFromUuidError::CannotParse)?;
+
+    Ok(BlockId::from_str(&parsed_uuid.hyphenated().to_string())
+        .expect("notion crate BlockId does not do any validation when parsing"))
+}
+
+/// The URL of the Notion page.
+pub fn page_url(page_id: &PageId) -> String {
+    format!(
+        "https://www.notion.so/{}",
+        page_id.to_string().replace('-', "")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn uuid_from_valid_notion_urls() {
+        {
+            let url_with_hash = "https://www.notion.so/gregorr/Test-page-0b89a6e8f0064acc8ec6e6902b039e3a#951424222c544b4896fc599a043f0c3d";
+            assert_eq!(
+                parse_page_id(url_with_hash).unwrap().to_string(),
+                "0b89a6e8-f006-4acc-8ec6-e6902b039e3a"
+            );
+        }
+
+        {
+            let url_without_hash =
+                "https://www.notion.so/gregorr/Test-page-0b89a6e8f0064acc8ec6e6902b039e3a";
+            assert_eq!(
+                parse_page_id(url_without_hash).unwrap().to_string(),
+                "0b89a6e8-f006-4acc-8ec6-e6902b039e3a"
+            );
+        }
+
+        {
+            let url_without_page_name =
+                "https://www.notion.so/gregorr/0b89a6e8f0064acc8ec6e6902b039e3a";
+            assert_eq!(
+                parse_page_id(url_without_page_name).unwrap().to_string(),
+                "0b89a6e8-f006-4acc-8ec6-e6902b039e3a"
+            );
+        }
+
+        {
+            let database_url_with_center_peek = "https://www.notion.so/gregorr/7659d7a185384403a1d603b828a21561?v=1156113b60bd45a48187e2fb5448f5ec&p=0b89a6e8f0064acc8ec6e6902b039e3a&pm=c";
+            assert_eq!(
+                parse_page_id(database_url_with_center_peek)
+                    .unwrap()
+                    .to_string(),
+                "0b89a6e8-f006-4acc-8ec6-e6902b039e3a"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_notion_urls() {
+        {
+            let github_url = "https://github.com/";
+            assert_eq!(
+                parse_page_id(github_url),
+                Err(ParsePageIdError::Url(
+                    ParsePageIdFromUrlError::NotNotionHostname(Host::Domain(
+                        "github.com".to_string()
+                    ))
+                ))
+            );
+        }
+
+        {
+            let notion_url_without_page = "https://www.notion.so/";
+            assert_eq!(
+                parse_page_id(notion_url_without_page),
+                Err(ParsePageIdError::Url(
+                    ParsePageIdFromUrlError::NotEnoughPathSegments
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn uuid_from_urls_on_other_notion_hosts() {
+        for url in [
+            "https://notion.so/gregorr/Test-page-0b89a6e8f0064acc8ec6e6902b039e3a",
+            "https://www.notion.so/Test-page-0b89a6e8f0064acc8ec6e6902b039e3a",
+            "https://gregorr.notion.site/Test-page-0b89a6e8f0064acc8ec6e6902b039e3a",
+            "https://gregorr.notion.site/0b89a6e8f0064acc8ec6e6902b039e3a?pvs=4",
+        ] {
+            assert_eq!(
+                parse_page_id(url).unwrap().to_string(),
+                "0b89a6e8-f006-4acc-8ec6-e6902b039e3a",
+                "{url}"
+            );
+        }
+    }
+
+    #[test]
+    fn uuid_from_urls_on_allowed_custom_domains() {
+        let url = "https://wiki.example.com/Test-page-0b89a6e8f0064acc8ec6e6902b039e3a";
+        let not_notion_host = || {
+            ParsePageIdFromUrlError::NotNotionHostname(Host::Domain("wiki.example.com".to_string()))
+        };
+
+        let error = parse_page_id(url).unwrap_err();
+        assert_eq!(error, ParsePageIdError::Url(not_notion_host()));
+        assert!(error.to_string().contains("--allow-host wiki.example.com"));
+        assert_eq!(
+            parse_page_id_with_hosts(url, &["Wiki.Example.com".to_string()])
+                .unwrap()
+                .to_string(),
+            "0b89a6e8-f006-4acc-8ec6-e6902b039e3a"
+        );
+
+        let id_arg = parse_page_id_arg(url).unwrap();
+        assert_eq!(id_arg.clone().check_host(&[]), Err(not_notion_host()));
+        assert_eq!(
+            id_arg
+                .check_host(&["wiki.example.com".to_string()])
+                .unwrap()
+                .to_string(),
+            "0b89a6e8-f006-4acc-8ec6-e6902b039e3a"
+        );
+    }
+
+    #[test]
+    fn target_with_the_block_after_the_hash() {
+        let target = parse_target("https://www.notion.so/gregorr/Test-page-0b89a6e8f0064acc8ec6e6902b039e3a#951424222c544b4896fc599a043f0c3d").unwrap();
+        assert_eq!(
+            target.page_id.to_string(),
+            "0b89a6e8-f006-4acc-8ec6-e6902b039e3a"
+        );
+        assert_eq!(
+            target.block_id.map(|block_id| block_id.to_string()),
+            Some("95142422-2c54-4b48-96fc-599a043f0c3d".to_string())
+        );
+
+        for page in [
+            "https://www.notion.so/gregorr/Test-page-0b89a6e8f0064acc8ec6e6902b039e3a",
+            "0b89a6e8f0064acc8ec6e6902b039e3a",
+        ] {
+            assert_eq!(parse_target(page).unwrap().block_id, None, "{page}");
+        }
+
+        assert!(matches!(
+            parse_target("https://www.notion.so/gregorr/Test-page-0b89a6e8f0064acc8ec6e6902b039e3a#heading"),
+            Err(ParsePageIdError::Url(
+                ParsePageIdFromUrlError::InvalidUuidInFragment { uuid_candidate, .. }
+            )) if uuid_candidate == "heading"
+        ));
+    }
+
+    #[test]
+    fn block_id_from_urls_and_strings() {
+        for input in [
+            "https://www.notion.so/gregorr/Test-page-0b89a6e8f0064acc8ec6e6902b039e3a#951424222c544b4896fc599a043f0c3d",
+            "951424222c544b4896fc599a043f0c3d",
+        ] {
+            assert_eq!(
+                parse_block_id_arg(input)
+                    .unwrap()
+                    .check_host(&[])
+                    .unwrap()
+                    .to_string(),
+                "95142422-2c54-4b48-96fc-599a043f0c3d",
+                "{input}"
+            );
+        }
+
+        assert_eq!(
+            parse_block_id_arg(
+                "https://www.notion.so/gregorr/Test-page-0b89a6e8f0064acc8ec6e6902b039e3a"
+            ),
+            Err(ParsePageIdError::Url(
+                ParsePageIdFromUrlError::MissingBlockInFragment
+            ))
+        );
+    }
+
+    #[test]
+    fn uuid_from_string() {
+        assert_eq!(
+            parse_page_id("0b89a6e8f0064acc8ec6e6902b039e3a")
+                .unwrap()
+                .to_string(),
+            "0b89a6e8-f006-4acc-8ec6-e6902b039e3a"
+        );
+
+        assert_eq!(
+            parse_page_id("0b89a6e8-f006-4acc-8ec6-e6902b039e3a")
+                .unwrap()
+                .to_string(),
+            "0b89a6e8-f006-4acc-8ec6-e6902b039e3a"
+        );
+    }
+
+    #[test]
+    fn invalid_uuids() {
+        assert!(matches!(
+            parse_page_id("some invalid uuid"),
+            Err(ParsePageIdError::Uuid(
+                ParsePageIdFromUuidError::CannotParse(_)
+            ))
+        ));
+    }
+
+    #[test]
+    fn database_id_from_urls_and_strings() {
+        for input in [
+            "https://www.notion.so/gregorr/7659d7a185384403a1d603b828a21561?v=1156113b60bd45a48187e2fb5448f5ec",
+            "https://www.notion.so/7659d7a185384403a1d603b828a21561?v=1156113b60bd45a48187e2fb5448f5ec&p=0b89a6e8f0064acc8ec6e6902b039e3a",
+            "https://www.notion.so/gregorr/Tasks-7659d7a185384403a1d603b828a21561",
+            "7659d7a185384403a1d603b828a21561",
+        ] {
+            assert_eq!(
+                parse_database_id(input).unwrap().to_string(),
+                "7659d7a1-8538-4403-a1d6-03b828a21561",
+                "{input}"
+            );
+        }
+
+        assert_eq!(
+            parse_database_id("https://www.notion.so/"),
+            Err(ParseDatabaseIdError::Url(
+                ParsePageIdFromUrlError::NoPathSegments
+            ))
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Fetch Notion pages as markdown and push markdown documents to Notion pages.
+//!
+//! ```no_run
+//! use notion_edit::{
+//!     fetch_page_to_markdown, ids::parse_page_id, push_markdown_to_page, NotionClient,
+//!     PushOptions,
+//! };
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = NotionClient::from_api_key("secret_...");
+//! let page_id =
+//!     parse_page_id("https://www.notion.so/gelio/Notes-0b89a6e8f0064acc8ec6e6902b039e3a")?;
+//!
+//! let markdown = fetch_page_to_markdown(&client, &page_id).await?;
+//! let report = push_markdown_to_page(
+//!     &client,
+//!     &page_id,
+//!     &format!("{markdown}\nOne more paragraph\n"),
+//!     PushOptions::default(),
+//! )
+//! .await?;
+//! for warning in report.warnings {
+//!     eprintln!("Warning: {warning}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The documents are converted through the tags of [`markdown::tag`], which can also be
+//! built or inspected directly.
+
+use notion::ids::PageId;
+use serde::Serialize;
+use thiserror::Error;
+
+use markdown::from_cmark::{ParseError, ParseOptions};
+use markdown::mentions::UserMentionMap;
+use markdown::notion_interop::NotionParseOptions;
+use markdown::to_cmark::SerializeOptions;
+use markdown::warning::Warning;
+pub use notion_api::client::NotionClient;
+use notion_api::client::{CreatedBlock, GetBlockChildrenError};
+
+pub mod atomic_file;
+pub mod ids;
+pub mod markdown;
+pub mod notion_api;
+pub mod page;
+
+pub use page::MarkdownToPageError;
+
+#[derive(Error, Debug)]
+pub enum FetchError {
+    #[error("could not fetch the page: {0}")]
+    Fetch(#[from] GetBlockChildrenError),
+}
+
+/// Fetches the content of the page as markdown, the same way as `notion-edit fetch` without
+/// the front matter.
+pub async fn fetch_page_to_markdown(
+    client: &NotionClient,
+    page_id: &PageId,
+) -> Result<String, FetchError> {
+    let markdown = page::convert_page_to_markdown(
+        client,
+        page_id.clone(),
+        &NotionParseOptions::default(),
+        &SerializeOptions::default(),
+        &UserMentionMap::default(),
+        &mut Vec::new(),
+    )
+    .await?;
+
+    Ok(markdown)
+}
+
+/// How [`push_markdown_to_page`] converts and pushes the document.
+#[derive(Debug, Default, Clone)]
+pub struct PushOptions {
+    pub parse_options: ParseOptions,
+    /// Keep erasing the page when some of its blocks cannot be deleted.
+    pub keep_going: bool,
+    /// Fail before changing the page when parts of the document cannot be pushed exactly.
+    pub strict: bool,
+}
+
+/// What happened while pushing a document.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct PushReport {
+    /// The parts of the document that were converted lossily.
+    #[serde(skip)]
+    pub warnings: Vec<Warning>,
+    /// The blocks created at the top level of the page, or of the section or the block that
+    /// was pushed to, in order.
+    pub created_blocks: Vec<CreatedBlock>,
+    /// The number of blocks deleted to make room for the document.
+    pub deleted_blocks: usize,
+    /// The requests sent to Notion, counting each retry as a request.
+    pub requests: usize,
+    pub retries: usize,
+    /// The URL of the page that the previous content was copied to before it was erased,
+    /// when it was backed up.
+    #[serde(skip)]
+    pub backup_page_url: Option<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum PushError {
+    #[error("could not parse the document at {}", .0.span())]
+    Parse(#[source] ParseError),
+
+    #[error("{} part(s) of the document cannot be pushed exactly, nothing was changed", .0.len())]
+    Strict(Vec<Warning>),
+
+    #[error("could not push the document to Notion: {0}")]
+    Push(#[from] MarkdownToPageError),
+}
+
+/// Replaces the content of the page with the markdown document, the same way as
+/// `notion-edit push` without the front matter and the sync state.
+pub async fn push_markdown_to_page(
+    client: &NotionClient,
+    page_id: &PageId,
+    markdown: &str,
+    options: PushOptions,
+) -> Result<PushReport, PushError> {
+    let request_counts = client.request_counts();
+    let mut warnings = Vec::new();
+    let tags = page::parse_markdown(
+        markdown,
+        options.parse_options,
+        &UserMentionMap::default(),
+        &mut warnings,
+    )
+    .map_err(PushError::Parse)?;
+    if options.strict && !warnings.is_empty() {
+        return Err(PushError::Strict(warnings));
+    }
+
+    let report = page::replace_page_contents(
+        client,
+        page_id.clone(),
+        tags,
+        &page::EraseOptions {
+            keep_going: options.keep_going,
+            ..Default::default()
+        },
+    )
+    .await?;
+    let request_counts = client.request_counts().since(request_counts);
+
+    Ok(PushReport {
+        warnings,
+        requests: request_counts.requests,
+        retries: request_counts.retries,
+        ..report
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn strict_push_fails_before_reaching_notion() {
+        // NOTE: nothing listens on port 1
+        let client = NotionClient::from_api_key("secret")
+            .with_base_url(notion_api::client::parse_api_base_url("http://127.0.0.1:1").unwrap());
+        let page_id = PageId::from_str("ac32e025-6f9c-4fab-8b9d-dbb3c593ac46").unwrap();
+
+        let error = push_markdown_to_page(
+            &client,
+            &page_id,
+            "#### Deep heading\n",
+            PushOptions {
+                strict: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(error, PushError::Strict(warnings) if warnings.len() == 1));
+    }
+
+    #[tokio::test]
+    async fn parse_errors_keep_their_position() {
+        let client = NotionClient::from_api_key("secret")
+            .with_base_url(notion_api::client::parse_api_base_url("http://127.0.0.1:1").unwrap());
+        let page_id = PageId::from_str("ac32e025-6f9c-4fab-8b9d-dbb3c593ac46").unwrap();
+
+        let error = push_markdown_to_page(
+            &client,
+            &page_id,
+            "Intro\n\n<details>\n",
+            PushOptions::default(),
+        )
+        .await
+        .unwrap_err();
+
+        let PushError::Parse(source) = &error else {
+            panic!("expected a parse error, got {error:?}");
+        };
+        assert!(matches!(source, ParseError::UnclosedDetails { .. }));
+        assert_eq!(source.span(), markdown::span::Span { line: 3, column: 1 });
+        assert!(std::error::Error::source(&error).is_some());
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    env,
+    io::{self, IsTerminal},
+};
+
+use tracing::Subscriber;
+use tracing_subscriber::{fmt::MakeWriter, EnvFilter};
+
+/// The logs for the number of `-v` flags: warnings only by default, the requests sent to
+/// Notion with `-v`, and details like block counts and pagination cursors with `-vv`.
+fn default_filter(verbose: u8) -> &'static str {
+    match verbose {
+        0 => "warn",
+        1 => "warn,notion_edit=info",
+        _ => "warn,notion_edit=debug",
+    }
+}
+
+/// The subscriber that writes the logs to the `writer`. `RUST_LOG`, given as `rust_log`,
+/// takes precedence over `verbose`.
+pub fn subscriber<W>(
+    verbose: u8,
+    rust_log: Option<&str>,
+    writer: W,
+    ansi: bool,
+) -> impl Subscriber + Send + Sync
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let filter = rust_log
+        .filter(|rust_log| !rust_log.is_empty())
+        .and_then(|rust_log| match EnvFilter::try_new(rust_log) {
+            Ok(filter) => Some(filter),
+            Err(error) => {
+                eprintln!("Warning: ignoring invalid RUST_LOG: {error}");
+                None
+            }
+        })
+        .unwrap_or_else(|| EnvFilter::new(default_filter(verbose)));
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(ansi)
+        .with_target(false)
+        .finish()
+}
+
+/// Writes the logs to stderr, as stdout may be the fetched page.
+pub fn init(verbose: u8) {
+    let subscriber = subscriber(
+        verbose,
+        env::var("RUST_LOG").ok().as_deref(),
+        io::stderr,
+        io::stderr().is_terminal(),
+    );
+    tracing::subscriber::set_global_default(subscriber).expect("logging is set up only once");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        str::FromStr,
+        sync::{Arc, Mutex},
+    };
+
+    use notion::ids::PageId;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use notion_edit::notion_api::client::{
+        get_notion_reqwest_client, parse_api_base_url, NotionClient,
+    };
+
+    use super::*;
+
+    const API_KEY: &str = "secret_Zm9vYmFyYmF6cXV4";
+    const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+
+    #[derive(Clone, Default)]
+    struct Logs(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Logs {
+        type Writer = Self;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    impl Logs {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    async fn fetch_page_with_logs(verbose: u8) -> String {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/pages/{PAGE_ID}")))
+            .and(header(
+                "Authorization",
+                format!("Bearer {API_KEY}").as_str(),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "page",
+                "id": PAGE_ID,
+                "last_edited_time": "2022-08-01T12:00:00.000Z",
+                "properties": {}
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let client = NotionClient::new(get_notion_reqwest_client(API_KEY, &Default::default()))
+            .with_base_url(parse_api_base_url(&server.uri()).unwrap());
+
+        let logs = Logs::default();
+        let _guard =
+            tracing::subscriber::set_default(subscriber(verbose, None, logs.clone(), false));
+        client
+            .get_page(&PageId::from_str(PAGE_ID).unwrap())
+            .await
+            .expect("successful request");
+
+        logs.contents()
+    }
+
+    #[tokio::test]
+    async fn logs_requests_without_the_api_key() {
+        let logs = fetch_page_with_logs(2).await;
+
+        assert!(
+            logs.contains(&format!("get_page{{page_id={PAGE_ID}}}")),
+            "{logs}"
+        );
+        assert!(
+            logs.contains(&format!(
+                "method=GET path=\"/v1/pages/{PAGE_ID}\" status=200"
+            )),
+            "{logs}"
+        );
+        assert!(!logs.contains(API_KEY), "{logs}");
+    }
+
+    #[tokio::test]
+    async fn logs_nothing_without_verbose() {
+        assert_eq!(fetch_page_with_logs(0).await, "");
+    }
+
+    #[test]
+    fn prefers_rust_log_over_verbose() {
+        let logs = Logs::default();
+        tracing::subscriber::with_default(
+            subscriber(0, Some("notion_edit=debug"), logs.clone(), false),
+            || tracing::debug!("details"),
+        );
+
+        assert!(logs.contents().contains("details"));
+    }
+}
@@ -1,111 +1,1611 @@
 use std::env;
-use std::io::Read;
-use std::{fs::File, io::Write};
+use std::fs;
+use std::io::{self, BufRead, IsTerminal};
+use std::mem;
+use std::path::Path;
+use std::process::ExitCode;
 
-use ::notion::{
-    ids::{BlockId, PageId},
-    NotionApi,
+use auth::{
+    keyring_api_key, resolve_api_key, store_api_key, ApiKey, ApiKeySources, DEFAULT_PROFILE,
 };
 use clap::Parser;
-use cli::{Cli, Command};
+use cli::{
+    AuthCommand, CacheCommand, Cli, Command, ListFormat, ObjectType, ReportFormat, StateCommand,
+    UsersCommand,
+};
+use commands::check::{check_markdown, Severity};
+use commands::database::{fetch_database_to_directory, push_file_to_database, DatabaseQuery};
+use commands::edit::edit_page;
+use commands::fetch::{
+    fetch_page_to_file, fetch_page_tree_to_directory, fetch_pages_to_directory, with_title_heading,
+    FetchOptions, FetchReport, PageFetch,
+};
+use commands::get_user_mention_map;
+use commands::new::create_page_from_template;
+use commands::push::{
+    get_page_id_for_file, ignored_front_matter_page_id, push_directory, push_file, FilePushReport,
+    PushMode, PushOptions,
+};
+use commands::restore::restore_backup;
+use commands::sync::{sync_manifest_entry, SyncError, SyncReport, SyncStatus};
+use config::Config;
 use dotenv::dotenv;
-use markdown::from_cmark::{ParseError, PulldownCMarkEventParser};
-use markdown::notion_interop::NotionToMarkdownParser;
-use markdown::to_cmark::get_pulldown_cmark_events;
-use notion::ids::AsIdentifier;
-use notion_api::client::{
-    erase_page, get_all_block_children, get_notion_reqwest_client, AppendBlockChildrenError,
-    BlockWithChildrenToCreate, ErasePageError, NotionClient,
+use error::{CommandError, Context, ErrorKind};
+use files::{FileArg, InputFormat, OutputFormat};
+use futures::{stream, StreamExt};
+use manifest::{Manifest, ManifestEntry, SyncDirection};
+use notion::ids::PageId;
+use notion_edit::ids::{page_url, parse_page_id_with_hosts};
+use notion_edit::markdown::from_cmark::ParseOptions;
+use notion_edit::markdown::frontmatter::FrontMatter;
+use notion_edit::markdown::notion_interop::NotionParseOptions;
+use notion_edit::markdown::obsidian::WikiLinkMap;
+use notion_edit::markdown::text_diff::DocumentDiff;
+use notion_edit::markdown::to_cmark::SerializeOptions;
+use notion_edit::markdown::to_html::HtmlDocument;
+use notion_edit::markdown::to_text::TextOptions;
+use notion_edit::markdown::warning::Warning;
+use notion_edit::notion_api::cache::BlockCache;
+use notion_edit::notion_api::client::{
+    get_notion_reqwest_client, parse_api_base_url, HttpOptions, NotionClient, PageSummary,
+    SearchObject, SearchResult, DEFAULT_NOTION_VERSION, DEFAULT_REQUEST_TIMEOUT,
 };
-use thiserror::Error;
+use notion_edit::notion_api::retry::RetryPolicy;
+use notion_edit::page::{convert_page_to_markdown, EraseOptions};
+use page_tree::{markdown_files, LinkResolver};
+use state::{GcPolicy, StateDir, DEFAULT_MAX_BACKUPS};
+use table::format_table;
 
+// NOTE: the modules of the CLI refer to the modules of the library from the root of the
+// crate, as `crate::markdown` and the like
+use notion_edit::{atomic_file, ids, markdown, notion_api, page, PushReport};
+
+mod auth;
 mod cli;
-mod markdown;
-mod notion_api;
+mod commands;
+mod config;
+mod editor;
+mod error;
+mod files;
+mod logging;
+mod manifest;
+mod page_tree;
+mod picker;
+mod section;
+mod state;
+mod table;
+mod template;
+mod watch;
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     // NOTE: a missing `.env` file is not a problem
     dotenv().ok();
     let cli = Cli::parse();
+    logging::init(cli.verbose);
 
-    let notion_api_key =
-        env::var("NOTION_API_KEY").expect("NOTION_API_KEY environment variable to be defined");
-    let client = NotionClient::new(get_notion_reqwest_client(&notion_api_key));
-    let notion_api = NotionApi::new(notion_api_key).expect("could not create NotionApi");
+    match run(cli).await {
+        Ok(exit_code) => exit_code,
+        Err(error) => {
+            eprint!("{}", error.render());
+            error.kind().exit_code()
+        }
+    }
+}
+
+/// Runs the command. Returns the exit code when the command did not fail, but still
+/// does not exit with success, like `diff` when the file differs from the page.
+async fn run(cli: Cli) -> Result<ExitCode, CommandError> {
+    let state_dir = StateDir::resolve(cli.state_dir);
+
+    if let Command::State { command } = cli.command {
+        match command {
+            StateCommand::Gc {
+                max_backup_age,
+                max_lock_age,
+            } => {
+                let report = state_dir
+                    .gc(&GcPolicy {
+                        max_backup_age,
+                        max_lock_age,
+                    })
+                    .context("could not clean up the state directory")?;
+                println!(
+                    "Removed {} backup(s) and {} stale lock(s) from {}",
+                    report.removed_backups.len(),
+                    report.removed_locks.len(),
+                    state_dir.root().display()
+                );
+            }
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Command::Cache {
+        command: CacheCommand::Clear,
+    } = cli.command
+    {
+        let cache = BlockCache::new(state_dir.block_cache_dir());
+        cache
+            .clear()
+            .context("could not remove the cached blocks")?;
+        println!(
+            "Removed the cached blocks from {}",
+            cache.directory().display()
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Command::Check { file, parse } = cli.command {
+        warn_if_keep_empty_paragraphs(parse.keep_empty_paragraphs);
+        let content = file
+            .read_to_string(io::stdin().lock())
+            .context(format!("could not read {file}"))?;
+        let problems = check_markdown(
+            &content,
+            ParseOptions {
+                keep_empty_paragraphs: !cli.compact,
+                flavor: cli.flavor,
+                merge_adjacent_lists: cli.merge_adjacent_lists,
+                ..ParseOptions::from(&parse)
+            },
+        );
+        let file_name = file
+            .path()
+            .map_or_else(|| "<stdin>".to_string(), |path| path.display().to_string());
+        for problem in &problems {
+            println!(
+                "{file_name}:{}: {}: {}",
+                problem.span, problem.severity, problem.message
+            );
+        }
+        let failed = problems
+            .iter()
+            .any(|problem| problem.severity == Severity::Error || cli.strict);
+        return Ok(if failed {
+            ErrorKind::Failure.exit_code()
+        } else {
+            ExitCode::SUCCESS
+        });
+    }
+
+    if let Command::Auth {
+        command: AuthCommand::Set,
+    } = cli.command
+    {
+        let profile = cli.profile.as_deref().unwrap_or(DEFAULT_PROFILE);
+        eprint!("Paste the API key for profile {profile}: ");
+        let mut key = String::new();
+        io::stdin()
+            .read_line(&mut key)
+            .context("could not read the key")?;
+        store_api_key(profile, &ApiKey::new(key.trim())).context("could not store the key")?;
+        eprintln!("Stored the API key for profile {profile}");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let config = Config::load(cli.config.as_deref())
+        .map_err(|error| CommandError::new(ErrorKind::Usage, error))?;
+    let max_backups = config.max_backups.unwrap_or(DEFAULT_MAX_BACKUPS);
+    let allowed_hosts: Vec<_> = cli
+        .allow_hosts
+        .into_iter()
+        .chain(config.allowed_hosts.iter().cloned())
+        .collect();
+    let notion_api_key = resolve_api_key(
+        ApiKeySources {
+            flag: cli.token.as_deref(),
+            env: env::var("NOTION_API_KEY").ok(),
+            config: &config,
+            profile: cli.profile.as_deref(),
+        },
+        keyring_api_key,
+    )?;
+    let request_timeout = match cli.timeout {
+        Some(timeout) => timeout,
+        None => match env::var("NOTION_EDIT_TIMEOUT") {
+            Ok(timeout) => humantime::parse_duration(&timeout).map_err(|error| {
+                CommandError::new(
+                    ErrorKind::Usage,
+                    format!("invalid NOTION_EDIT_TIMEOUT duration: {error}"),
+                )
+            })?,
+            Err(_) => DEFAULT_REQUEST_TIMEOUT,
+        },
+    };
+    let notion_version = cli
+        .notion_version
+        .or_else(|| env::var("NOTION_VERSION").ok())
+        .unwrap_or_else(|| DEFAULT_NOTION_VERSION.to_string());
+    let http_options = HttpOptions {
+        notion_version: notion_version.clone(),
+        proxy: cli.proxy,
+        ..Default::default()
+    };
+    let mut client = NotionClient::new(get_notion_reqwest_client(
+        notion_api_key.expose(),
+        &http_options,
+    ))
+    .with_request_timeout(request_timeout)
+    .with_max_concurrency(cli.max_concurrency)
+    .with_retry_policy(RetryPolicy {
+        max_retries: cli.max_retries,
+        ..Default::default()
+    })
+    .with_response_cache();
+    if let Some(api_base_url) = cli
+        .api_base_url
+        .clone()
+        .or_else(|| env::var("NOTION_API_BASE_URL").ok())
+        .or_else(|| config.api_base_url.clone())
+    {
+        let base_url = parse_api_base_url(&api_base_url).map_err(|error| {
+            CommandError::new(
+                ErrorKind::Usage,
+                format!("invalid Notion API base URL {api_base_url}: {error}"),
+            )
+        })?;
+        client = client.with_base_url(base_url);
+    }
+
+    // NOTE: the models may not match the responses of other versions, so fail early when
+    // the version is not even accepted
+    if notion_version != DEFAULT_NOTION_VERSION {
+        client.check_access().await.context(format!(
+            "Notion API version {notion_version} cannot be used"
+        ))?;
+    }
+
+    let wiki_links: WikiLinkMap = match &cli.wikilinks {
+        Some(path) => {
+            let mapping =
+                fs::read_to_string(path).context(format!("could not read {}", path.display()))?;
+            serde_yaml::from_str(&mapping).map_err(|error| {
+                CommandError::new(
+                    ErrorKind::Usage,
+                    format!("invalid wikilink mapping {}: {error}", path.display()),
+                )
+            })?
+        }
+        None => WikiLinkMap::new(),
+    };
 
     match cli.command {
-        Command::Fetch { page_id, file } => {
-            let mut file = File::create(file).expect("MD file to create successfully");
-            let markdown_content = convert_page_to_markdown(&notion_api, page_id)
+        Command::Fetch {
+            page,
+            output,
+            page_ids,
+            file,
+            serialize,
+            format,
+            standalone,
+            css,
+            show_urls,
+            download_assets,
+            no_cache,
+            title_heading,
+            watch,
+            interval,
+            recursive,
+            output_dir,
+            section,
+            heading_offset,
+            links_from,
+        } => {
+            if standalone && format != OutputFormat::Html {
+                return Err(CommandError::new(
+                    ErrorKind::Usage,
+                    "--standalone can only be used with --format html",
+                ));
+            }
+            if show_urls && format != OutputFormat::Text {
+                return Err(CommandError::new(
+                    ErrorKind::Usage,
+                    "--show-urls can only be used with --format text",
+                ));
+            }
+            let html_document = match (standalone, css) {
+                (false, _) => None,
+                (true, None) => Some(HtmlDocument::default()),
+                (true, Some(css)) => Some(HtmlDocument {
+                    css: Some(
+                        fs::read_to_string(&css)
+                            .context(format!("could not read {}", css.display()))?,
+                    ),
+                }),
+            };
+            if !no_cache {
+                client = client.with_block_cache(BlockCache::new(state_dir.block_cache_dir()));
+            }
+            let targets = page
+                .into_iter()
+                .chain(page_ids)
+                .map(|target| target.check_host(&allowed_hosts))
+                .collect::<Result<Vec<_>, _>>()?;
+            let block_id = targets.iter().find_map(|target| target.block_id.clone());
+            if block_id.is_some() && (targets.len() > 1 || recursive || output_dir.is_some()) {
+                return Err(CommandError::new(
+                    ErrorKind::Usage,
+                    "a block from a URL can only be fetched on its own, into a single file",
+                ));
+            }
+            if block_id.is_some() && section.is_some() {
+                return Err(CommandError::new(
+                    ErrorKind::Usage,
+                    "--section cannot be used with a block from a URL",
+                ));
+            }
+            let mut page_ids: Vec<_> = targets.into_iter().map(|target| target.page_id).collect();
+            let mut warnings = Vec::new();
+            let user_mentions = get_user_mention_map(&client, &config.users, &mut warnings)
+                .await
+                .context("could not resolve the users from the config")?;
+            let fetch_options = FetchOptions {
+                parse_options: NotionParseOptions {
+                    list_separator_policy: serialize.list_separator_policy,
+                    merge_adjacent_lists: cli.merge_adjacent_lists,
+                    heading_offset,
+                    compact: cli.compact,
+                    ..Default::default()
+                },
+                serialize_options: SerializeOptions {
+                    flavor: cli.flavor,
+                    ..SerializeOptions::from(&serialize)
+                },
+                format,
+                title_heading,
+                strict: cli.strict,
+                section,
+                block_id,
+                html_document,
+                text_options: TextOptions { show_urls },
+                asset_dir: download_assets,
+                links: load_links(links_from.as_deref())?,
+            };
+
+            if page_ids.len() > 1 && output_dir.is_none() {
+                return Err(CommandError::new(
+                    ErrorKind::Usage,
+                    "several pages can only be fetched into an --output-dir",
+                ));
+            }
+            if recursive {
+                if page_ids.len() > 1 {
+                    return Err(CommandError::new(
+                        ErrorKind::Usage,
+                        "--recursive fetches a single page with its child pages",
+                    ));
+                }
+                let page_id = match page_ids.pop() {
+                    Some(page_id) => page_id,
+                    None => pick_page_to_fetch(&client).await?.id,
+                };
+                let directory = output_dir.expect("--output-dir is required with --recursive");
+                let fetch_report = fetch_page_tree_to_directory(
+                    &client,
+                    &state_dir,
+                    &page_id,
+                    &directory,
+                    &fetch_options,
+                    &user_mentions,
+                    warnings,
+                )
+                .await?;
+                print_fetch_report(&page_id, &fetch_report);
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            if let Some(directory) = output_dir {
+                if page_ids.is_empty() {
+                    page_ids = read_page_ids(io::stdin().lock(), &allowed_hosts)?;
+                }
+                print_warnings(&warnings);
+                let fetches = fetch_pages_to_directory(
+                    &client,
+                    &state_dir,
+                    page_ids,
+                    &directory,
+                    &fetch_options,
+                    &user_mentions,
+                )
                 .await
-                .expect("Could not fetch the page");
+                .context(format!("could not create {}", directory.display()))?;
+                for fetch in &fetches {
+                    match &fetch.result {
+                        Ok(fetch_report) => print_fetch_report(&fetch.page_id, fetch_report),
+                        Err(error) => {
+                            eprintln!("Error: could not fetch page {}: {error}", fetch.page_id)
+                        }
+                    }
+                }
+                print!("{}", format_fetch_results(&fetches));
 
-            file.write_all(markdown_content.as_bytes())
-                .expect("Could not write the page markdown to a file");
+                let failed_pages = fetches.iter().filter(|fetch| fetch.result.is_err()).count();
+                if failed_pages > 0 {
+                    return Err(CommandError::new(
+                        ErrorKind::Failure,
+                        format!("{failed_pages} page(s) could not be fetched"),
+                    ));
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            let (page_id, file) =
+                get_page_and_file_to_fetch(&client, page_ids.pop(), output.or(file)).await?;
+            if !watch {
+                let fetch_report = fetch_page_to_file(
+                    &client,
+                    &state_dir,
+                    &page_id,
+                    &file,
+                    &fetch_options,
+                    &user_mentions,
+                    warnings,
+                    false,
+                )
+                .await?;
+                print_fetch_report(&page_id, &fetch_report);
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            if file.path().is_none() {
+                return Err(CommandError::new(
+                    ErrorKind::Usage,
+                    "--watch needs a file to write the page to, not stdout",
+                ));
+            }
+            let mut stop = watch::on_ctrl_c();
+            let mut last_edited_time = None;
+            loop {
+                // NOTE: Notion rounds `last_edited_time` to the minute, so edits made in the
+                // same minute as the last fetch are only noticed after the next edit
+                client.invalidate(&page_id);
+                match client.get_page(&page_id).await {
+                    Ok(page) if last_edited_time.as_ref() != Some(&page.last_edited_time) => {
+                        match fetch_page_to_file(
+                            &client,
+                            &state_dir,
+                            &page_id,
+                            &file,
+                            &fetch_options,
+                            &user_mentions,
+                            mem::take(&mut warnings),
+                            true,
+                        )
+                        .await
+                        {
+                            Ok(fetch_report) => {
+                                print_fetch_report(&page_id, &fetch_report);
+                                eprintln!("Fetched the page to {file}");
+                                last_edited_time = Some(page.last_edited_time);
+                            }
+                            Err(error) => eprintln!("Error: {error}"),
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(error) => eprintln!("Error: could not check the page for changes: {error}"),
+                }
+
+                tokio::select! {
+                    _ = &mut stop => break,
+                    _ = tokio::time::sleep(interval) => {}
+                }
+            }
         }
-        Command::Push { page_id, file } => {
-            let mut file = File::open(file).expect("File does not exist");
-            let mut buf = String::new();
-            file.read_to_string(&mut buf)
-                .expect("Could not read the file");
-            convert_markdown_to_page(&notion_api, &client, page_id, &buf)
+        Command::Push {
+            page_id,
+            file,
+            parse,
+            push,
+            input_format,
+            changelog,
+            watch,
+            recursive,
+            directory,
+            section,
+            links_from,
+            append,
+            prepend,
+            resume,
+            to_block,
+            unarchive,
+            report,
+        } => {
+            warn_if_keep_empty_paragraphs(parse.keep_empty_paragraphs);
+            let push_options = PushOptions {
+                page_id: page_id
+                    .map(|page_id| page_id.check_host(&allowed_hosts))
+                    .transpose()?,
+                // NOTE: with --recursive, the file and its page are set for every pushed file
+                file: file.unwrap_or(FileArg::Stdio),
+                parse_options: ParseOptions {
+                    keep_empty_paragraphs: !cli.compact,
+                    flavor: cli.flavor,
+                    wiki_links: wiki_links.clone(),
+                    merge_adjacent_lists: cli.merge_adjacent_lists,
+                    ..ParseOptions::from(&parse)
+                },
+                input_format,
+                title_heading: push.title_heading,
+                changelog,
+                erase_options: EraseOptions {
+                    keep_going: push.keep_going,
+                    safe_erase: push.safe_erase,
+                    ..Default::default()
+                },
+                max_backups: (!push.no_backup).then_some(max_backups),
+                strict: cli.strict,
+                force: push.force,
+                links: load_links(links_from.as_deref())?,
+                section,
+                block_id: to_block
+                    .map(|block_id| block_id.check_host(&allowed_hosts))
+                    .transpose()?,
+                mode: match (append, prepend) {
+                    (true, _) => PushMode::Append,
+                    (_, true) => PushMode::Prepend,
+                    _ => PushMode::Replace,
+                },
+                resume,
+                unarchive,
+            };
+
+            if recursive {
+                let directory = directory.expect("--directory is required with --recursive");
+                let pushes = push_directory(
+                    &client,
+                    &state_dir,
+                    &config.users,
+                    &directory,
+                    &push_options,
+                )
                 .await
-                .expect("Error when pushing the document to Notion");
+                .context(format!(
+                    "could not read the directory {}",
+                    directory.display()
+                ))?;
+                for push in &pushes {
+                    match &push.result {
+                        Ok(file_push) => print_push_report(file_push),
+                        Err(error) => {
+                            eprintln!("Error: could not push {}: {error}", push.file.display())
+                        }
+                    }
+                }
+
+                let failed_files = pushes.iter().filter(|push| push.result.is_err()).count();
+                if failed_files > 0 {
+                    return Err(CommandError::new(
+                        ErrorKind::Failure,
+                        format!("{failed_files} file(s) could not be pushed"),
+                    ));
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            if !watch {
+                let file_push =
+                    push_file(&client, &state_dir, &config.users, &push_options).await?;
+                print_push_report(&file_push);
+                if let Some(format) = report {
+                    print!("{}", format_push_report(&file_push.report, format));
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            let file = &push_options.file;
+            let path = file.path().ok_or_else(|| {
+                CommandError::new(ErrorKind::Usage, "--watch needs a file to push, not stdin")
+            })?;
+            let (_watcher, mut changes) =
+                watch::watch_file(path).context(format!("could not watch {file}"))?;
+            let mut stop = watch::on_ctrl_c();
+            loop {
+                // NOTE: the page may have been edited in Notion since the last push
+                client.invalidate_all();
+                match push_file(&client, &state_dir, &config.users, &push_options).await {
+                    Ok(file_push) => {
+                        print_push_report(&file_push);
+                        if let Some(format) = report {
+                            print!("{}", format_push_report(&file_push.report, format));
+                        }
+                        eprintln!("Pushed {file}");
+                    }
+                    Err(error) => eprintln!("Error: {error}"),
+                }
+                eprintln!("Watching {file} for changes, press Ctrl-C to stop");
+
+                tokio::select! {
+                    _ = &mut stop => break,
+                    change = watch::next_change(&mut changes, watch::DEBOUNCE_DELAY) => {
+                        if change.is_none() {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        Command::FetchDb {
+            database_id,
+            output_dir,
+            name_template,
+            filters,
+            since,
+            serialize,
+            title_heading,
+        } => {
+            let database_id = database_id.check_host(&allowed_hosts)?;
+            let mut warnings = Vec::new();
+            let user_mentions = get_user_mention_map(&client, &config.users, &mut warnings)
+                .await
+                .context("could not resolve the users from the config")?;
+            print_warnings(&warnings);
+            let fetch_options = FetchOptions {
+                parse_options: NotionParseOptions {
+                    list_separator_policy: serialize.list_separator_policy,
+                    merge_adjacent_lists: cli.merge_adjacent_lists,
+                    compact: cli.compact,
+                    ..Default::default()
+                },
+                serialize_options: SerializeOptions {
+                    flavor: cli.flavor,
+                    ..SerializeOptions::from(&serialize)
+                },
+                format: OutputFormat::Markdown,
+                title_heading,
+                strict: cli.strict,
+                section: None,
+                block_id: None,
+                html_document: None,
+                text_options: TextOptions::default(),
+                asset_dir: None,
+                links: None,
+            };
+
+            let fetches = fetch_database_to_directory(
+                &client,
+                &state_dir,
+                &database_id,
+                &DatabaseQuery {
+                    filters,
+                    since,
+                    name_template,
+                },
+                &output_dir,
+                &fetch_options,
+                &user_mentions,
+            )
+            .await?;
+            for fetch in &fetches {
+                let file = fetch.file.as_ref().expect("the pages are fetched to files");
+                match &fetch.result {
+                    Ok(fetch_report) => {
+                        print_fetch_report(&fetch.page_id, fetch_report);
+                        eprintln!("Fetched {file}");
+                    }
+                    Err(error) => eprintln!("Error: could not fetch {file}: {error}"),
+                }
+            }
+
+            let failed_pages = fetches.iter().filter(|fetch| fetch.result.is_err()).count();
+            if failed_pages > 0 {
+                return Err(CommandError::new(
+                    ErrorKind::Failure,
+                    format!("{failed_pages} page(s) could not be fetched"),
+                ));
+            }
+        }
+        Command::PushDb {
+            database_id,
+            file,
+            directory,
+            parse,
+            push,
+        } => {
+            warn_if_keep_empty_paragraphs(parse.keep_empty_paragraphs);
+            let database_id = database_id.check_host(&allowed_hosts)?;
+            let push_options = PushOptions {
+                page_id: None,
+                // NOTE: the file and its page are set for every pushed file
+                file: FileArg::Stdio,
+                parse_options: ParseOptions {
+                    keep_empty_paragraphs: !cli.compact,
+                    flavor: cli.flavor,
+                    wiki_links: wiki_links.clone(),
+                    merge_adjacent_lists: cli.merge_adjacent_lists,
+                    ..ParseOptions::from(&parse)
+                },
+                input_format: InputFormat::Markdown,
+                title_heading: push.title_heading,
+                changelog: None,
+                erase_options: EraseOptions {
+                    keep_going: push.keep_going,
+                    safe_erase: push.safe_erase,
+                    ..Default::default()
+                },
+                max_backups: (!push.no_backup).then_some(max_backups),
+                strict: cli.strict,
+                force: push.force,
+                links: None,
+                section: None,
+                block_id: None,
+                mode: PushMode::Replace,
+                resume: false,
+                unarchive: false,
+            };
+            let files = match (file, directory) {
+                (Some(file), _) => vec![file],
+                (None, Some(directory)) => markdown_files(&directory)
+                    .context(format!(
+                        "could not read the directory {}",
+                        directory.display()
+                    ))?
+                    .into_iter()
+                    .map(|file| directory.join(file))
+                    .collect(),
+                (None, None) => unreachable!("clap requires --file or --directory"),
+            };
+            let schema = client
+                .get_database_properties(&database_id.to_string())
+                .await
+                .context("could not get the database")?;
+
+            let mut failed_files = 0;
+            for file in &files {
+                match push_file_to_database(
+                    &client,
+                    &state_dir,
+                    &config.users,
+                    &database_id,
+                    &schema,
+                    file,
+                    &push_options,
+                )
+                .await
+                {
+                    Ok(file_push) => {
+                        print_push_report(&file_push);
+                        eprintln!("Pushed {}", file.display());
+                    }
+                    Err(error) => {
+                        eprintln!("Error: could not push {}: {error}", file.display());
+                        failed_files += 1;
+                    }
+                }
+            }
+            if files.len() > 1 {
+                eprintln!(
+                    "{} file(s) pushed, {failed_files} failed",
+                    files.len() - failed_files
+                );
+            }
+            if failed_files > 0 {
+                return Ok(ErrorKind::Failure.exit_code());
+            }
+        }
+        Command::Diff {
+            page_id,
+            file,
+            serialize,
+            title_heading,
+            stat,
+        } => {
+            let buf = file
+                .read_to_string(io::stdin().lock())
+                .context(format!("could not read {file}"))?;
+            let (front_matter, _) = FrontMatter::split(&buf);
+            let page_id = page_id
+                .map(|page_id| page_id.check_host(&allowed_hosts))
+                .transpose()?;
+            if let Some(page_id) = &page_id {
+                if let Some(ignored_page_id) =
+                    ignored_front_matter_page_id(page_id, front_matter.as_ref())
+                {
+                    warn_about_ignored_page_id(page_id, &ignored_page_id);
+                }
+            }
+            let page_id = get_page_id_for_file(page_id, front_matter.as_ref())?;
+            let mut warnings = Vec::new();
+            let user_mentions = get_user_mention_map(&client, &config.users, &mut warnings)
+                .await
+                .context("could not resolve the users from the config")?;
+            // NOTE: the page is converted the same way as when fetching, so a freshly fetched
+            // file has no differences
+            let mut page_content = convert_page_to_markdown(
+                &client,
+                page_id.clone(),
+                &NotionParseOptions {
+                    list_separator_policy: serialize.list_separator_policy,
+                    merge_adjacent_lists: cli.merge_adjacent_lists,
+                    compact: cli.compact,
+                    ..Default::default()
+                },
+                &SerializeOptions {
+                    flavor: cli.flavor,
+                    ..SerializeOptions::from(&serialize)
+                },
+                &user_mentions,
+                &mut warnings,
+            )
+            .await
+            .context("could not fetch the page")?;
+            if title_heading {
+                let page = client
+                    .get_page(&page_id)
+                    .await
+                    .context("could not fetch the page")?;
+                page_content = with_title_heading(&page.title, &page_content);
+            }
+            print_warnings(&warnings);
+
+            let diff = DocumentDiff::new(&page_content, &buf);
+            if diff.is_empty() {
+                return Ok(ExitCode::SUCCESS);
+            }
+            if stat {
+                println!("{file}: {}", diff.stat());
+            } else {
+                print!(
+                    "{}",
+                    diff.unified(
+                        &format!("Notion page {page_id}"),
+                        &file.to_string(),
+                        io::stdout().is_terminal()
+                    )
+                );
+            }
+            return Ok(ErrorKind::Failure.exit_code());
+        }
+        Command::Edit { page_id, no_backup } => {
+            let page_id = page_id.check_host(&allowed_hosts)?;
+            let push_options = PushOptions {
+                page_id: Some(page_id.clone()),
+                // NOTE: the file is the temporary file the page is fetched into
+                file: FileArg::Stdio,
+                parse_options: ParseOptions {
+                    keep_empty_paragraphs: !cli.compact,
+                    flavor: cli.flavor,
+                    wiki_links: wiki_links.clone(),
+                    merge_adjacent_lists: cli.merge_adjacent_lists,
+                    ..Default::default()
+                },
+                input_format: InputFormat::Markdown,
+                title_heading: false,
+                changelog: None,
+                erase_options: EraseOptions::default(),
+                max_backups: (!no_backup).then_some(max_backups),
+                strict: cli.strict,
+                force: false,
+                links: None,
+                section: None,
+                block_id: None,
+                mode: PushMode::Replace,
+                resume: false,
+                unarchive: false,
+            };
+            let edit_report = edit_page(
+                &client,
+                &state_dir,
+                &config.users,
+                &editor::editor_command(),
+                &push_options,
+                |question| picker::confirm(question, io::stdin().lock(), io::stderr()),
+            )
+            .await?;
+            print_fetch_report(&page_id, &edit_report.fetch);
+            match &edit_report.push {
+                Some(file_push) => print_push_report(file_push),
+                None => eprintln!("The page was not changed, nothing was pushed"),
+            }
         }
+        Command::New {
+            parent_page_id,
+            template,
+            variables,
+            title,
+        } => {
+            let push_options = PushOptions {
+                page_id: None,
+                // NOTE: the file is the temporary file the template is rendered into
+                file: FileArg::Stdio,
+                parse_options: ParseOptions {
+                    keep_empty_paragraphs: !cli.compact,
+                    flavor: cli.flavor,
+                    wiki_links: wiki_links.clone(),
+                    merge_adjacent_lists: cli.merge_adjacent_lists,
+                    ..Default::default()
+                },
+                input_format: InputFormat::Markdown,
+                title_heading: false,
+                changelog: None,
+                erase_options: EraseOptions::default(),
+                max_backups: None,
+                strict: cli.strict,
+                force: false,
+                links: None,
+                section: None,
+                block_id: None,
+                mode: PushMode::Replace,
+                resume: false,
+                unarchive: false,
+            };
+            let new_page = create_page_from_template(
+                &client,
+                &state_dir,
+                &config.users,
+                &parent_page_id.check_host(&allowed_hosts)?,
+                &template,
+                &variables.into_iter().collect(),
+                title,
+                &push_options,
+            )
+            .await?;
+            if !new_page.unused_variables.is_empty() {
+                eprintln!(
+                    "Warning: the template does not use the variables: {}",
+                    new_page.unused_variables.join(", ")
+                );
+            }
+            print_push_report(&new_page.push);
+            println!("{}", page_url(&new_page.page_id));
+        }
+        Command::Sync { manifest } => {
+            let manifest = Manifest::load(&manifest)
+                .map_err(|error| CommandError::new(ErrorKind::Usage, error))?;
+            let mut warnings = Vec::new();
+            let user_mentions = get_user_mention_map(&client, &config.users, &mut warnings)
+                .await
+                .context("could not resolve the users from the config")?;
+            print_warnings(&warnings);
+            let mut links = LinkResolver::default();
+            for entry in &manifest.entries {
+                links.insert(entry.page_id.clone(), &entry.file);
+            }
+            let push_options = PushOptions {
+                page_id: None,
+                // NOTE: the file and its page are set for every entry
+                file: FileArg::Stdio,
+                parse_options: ParseOptions {
+                    keep_empty_paragraphs: !cli.compact,
+                    flavor: cli.flavor,
+                    wiki_links: wiki_links.clone(),
+                    merge_adjacent_lists: cli.merge_adjacent_lists,
+                    ..Default::default()
+                },
+                input_format: InputFormat::Markdown,
+                title_heading: false,
+                changelog: None,
+                erase_options: EraseOptions::default(),
+                max_backups: Some(max_backups),
+                strict: cli.strict,
+                force: false,
+                links: Some(links.clone()),
+                section: None,
+                block_id: None,
+                mode: PushMode::Replace,
+                resume: false,
+                unarchive: false,
+            };
+            let fetch_options = FetchOptions {
+                parse_options: NotionParseOptions {
+                    merge_adjacent_lists: cli.merge_adjacent_lists,
+                    compact: cli.compact,
+                    ..Default::default()
+                },
+                serialize_options: SerializeOptions {
+                    flavor: cli.flavor,
+                    ..Default::default()
+                },
+                format: OutputFormat::Markdown,
+                title_heading: false,
+                strict: cli.strict,
+                section: None,
+                block_id: None,
+                html_document: None,
+                text_options: TextOptions::default(),
+                asset_dir: None,
+                links: Some(links),
+            };
+
+            let results: Vec<_> = stream::iter(&manifest.entries)
+                .map(|entry| {
+                    let sync = sync_manifest_entry(
+                        &client,
+                        &state_dir,
+                        &config.users,
+                        entry,
+                        &fetch_options,
+                        &push_options,
+                        &user_mentions,
+                    );
+                    async move {
+                        let result = sync.await;
+                        match &result {
+                            Ok(sync_report) => {
+                                if let Some(fetch_report) = &sync_report.fetch {
+                                    print_fetch_report(&entry.page_id, fetch_report);
+                                }
+                                if let Some(file_push) = &sync_report.push {
+                                    print_push_report(file_push);
+                                }
+                            }
+                            Err(error) => {
+                                if let SyncError::MergeConflict { fetch, .. } = error {
+                                    print_fetch_report(&entry.page_id, fetch);
+                                }
+                                eprintln!(
+                                    "Error: could not sync {}: {error}",
+                                    entry.file.display()
+                                );
+                            }
+                        }
+                        (entry, result)
+                    }
+                })
+                .buffered(cli.max_concurrency)
+                .collect()
+                .await;
+            print!("{}", format_sync_results(&results));
+
+            let failed_entries = results.iter().filter(|(_, result)| result.is_err()).count();
+            let conflicted_entries = results
+                .iter()
+                .filter(|(_, result)| matches!(result, Err(SyncError::MergeConflict { .. })))
+                .count();
+            if failed_entries > 0 && failed_entries == conflicted_entries {
+                return Err(CommandError::new(
+                    ErrorKind::Conflict,
+                    format!("{conflicted_entries} page(s) have conflicts to resolve"),
+                ));
+            }
+            if failed_entries > 0 {
+                return Err(CommandError::new(
+                    ErrorKind::Failure,
+                    format!("{failed_entries} page(s) could not be synced"),
+                ));
+            }
+        }
+        Command::Users {
+            command: UsersCommand::List,
+        } => {
+            let users = client.list_users().await.context("could not list users")?;
+            for user in users {
+                let (common, email) = match &user {
+                    notion::models::users::User::Person { common, person } => {
+                        (common, person.email.as_str())
+                    }
+                    notion::models::users::User::Bot { common, .. } => (common, "(bot)"),
+                };
+                println!(
+                    "{}\t{}\t{}",
+                    common.id,
+                    common.name.as_deref().unwrap_or(""),
+                    email
+                );
+            }
+        }
+        Command::Restore {
+            page_id,
+            backup,
+            no_backup,
+        } => {
+            let restored = restore_backup(
+                &client,
+                &state_dir,
+                &config.users,
+                &page_id.check_host(&allowed_hosts)?,
+                backup,
+                (!no_backup).then_some(max_backups),
+            )
+            .await?;
+            print_warnings(&restored.report.warnings);
+            eprintln!("Restored the backup {} of the page", restored.timestamp);
+        }
+        Command::Whoami => {
+            let user = client.get_bot_user().await?;
+
+            println!("Bot: {}", user.name);
+            println!(
+                "Workspace: {}",
+                user.workspace_name.as_deref().unwrap_or("(unknown)")
+            );
+            println!("Owner: {}", user.owner);
+        }
+        Command::List {
+            query,
+            object_type,
+            format,
+        } => {
+            let object = object_type.map(|object_type| match object_type {
+                ObjectType::Page => SearchObject::Page,
+                ObjectType::Database => SearchObject::Database,
+            });
+            let results = client
+                .search(query.as_deref(), object)
+                .await
+                .context("could not search")?;
+
+            match format {
+                ListFormat::Table => print!("{}", format_search_results(&results)),
+                ListFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&results)
+                        .expect("search results can be serialized")
+                ),
+            }
+        }
+        Command::State { .. }
+        | Command::Cache { .. }
+        | Command::Auth { .. }
+        | Command::Check { .. } => {
+            unreachable!("handled before creating the Notion clients")
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The search results as a table, one page or database per line.
+/// `--keep-empty-paragraphs` is deprecated, as empty paragraphs are kept unless `--compact`
+/// is passed.
+fn warn_if_keep_empty_paragraphs(keep_empty_paragraphs: bool) {
+    if keep_empty_paragraphs {
+        eprintln!(
+            "Warning: --keep-empty-paragraphs is deprecated and has no effect, empty paragraphs are kept unless --compact is passed"
+        );
     }
 }
 
-async fn convert_page_to_markdown(
-    notion_api: &NotionApi,
-    page_id: PageId,
-) -> Result<String, notion::Error> {
-    let block_id: BlockId = page_id.into();
-    let page_blocks = get_all_block_children(notion_api, &block_id).await?;
+fn format_search_results(results: &[SearchResult]) -> String {
+    let rows: Vec<_> = results
+        .iter()
+        .map(|result| {
+            vec![
+                result.id.clone(),
+                match result.object {
+                    SearchObject::Page => "page",
+                    SearchObject::Database => "database",
+                }
+                .to_string(),
+                if result.title.is_empty() {
+                    "(untitled)".to_string()
+                } else {
+                    result.title.clone()
+                },
+                result.parent.to_string(),
+                result.last_edited_time.clone(),
+            ]
+        })
+        .collect();
 
-    let parsed_tags: Vec<_> = NotionToMarkdownParser::default()
-        .feed(page_blocks.iter())
+    format_table(&["ID", "TYPE", "TITLE", "PARENT", "LAST EDITED"], &rows)
+}
+
+/// The outcome of `sync` as a table, one entry of the manifest per line.
+fn format_sync_results(results: &[(&ManifestEntry, Result<SyncReport, SyncError>)]) -> String {
+    let rows: Vec<_> = results
+        .iter()
+        .map(|(entry, result)| {
+            vec![
+                entry.file.display().to_string(),
+                entry.page_id.to_string(),
+                match entry.direction {
+                    SyncDirection::Fetch => "fetch",
+                    SyncDirection::Push => "push",
+                    SyncDirection::TwoWay => "two-way",
+                }
+                .to_string(),
+                match result.as_ref().map(|sync_report| sync_report.status) {
+                    Ok(SyncStatus::Fetched) => "fetched",
+                    Ok(SyncStatus::Pushed) => "pushed",
+                    Ok(SyncStatus::Merged) => "merged",
+                    Ok(SyncStatus::UpToDate) => "up to date",
+                    Err(SyncError::MergeConflict { .. }) => "conflict",
+                    Err(_) => "failed",
+                }
+                .to_string(),
+            ]
+        })
         .collect();
 
-    let events = parsed_tags.iter().flat_map(get_pulldown_cmark_events);
-    let mut buf = String::new();
-    pulldown_cmark_to_cmark::cmark(events, &mut buf).expect("serialization failed");
-    buf.push('\n');
+    format_table(&["FILE", "PAGE", "DIRECTION", "STATUS"], &rows)
+}
 
-    Ok(buf)
+/// The report of a push: a table of the created blocks followed by the totals, or JSON.
+fn format_push_report(report: &PushReport, format: ReportFormat) -> String {
+    match format {
+        ReportFormat::Text => {
+            let rows: Vec<_> = report
+                .created_blocks
+                .iter()
+                .map(|block| {
+                    vec![
+                        block.block_id.to_string(),
+                        block.block_type.clone(),
+                        block.children.to_string(),
+                        block.preview.clone(),
+                    ]
+                })
+                .collect();
+
+            format!(
+                "{}Created {} block(s) and deleted {} in {} request(s), with {} retries\n",
+                format_table(&["BLOCK", "TYPE", "CHILDREN", "PREVIEW"], &rows),
+                report.created_blocks.len(),
+                report.deleted_blocks,
+                report.requests,
+                report.retries
+            )
+        }
+        ReportFormat::Json => format!(
+            "{}\n",
+            serde_json::to_string_pretty(report).expect("push reports can be serialized")
+        ),
+    }
 }
 
-#[derive(Error, Debug)]
-enum MarkdownToPageError<'a> {
-    #[error("cannot erase page {0}")]
-    ErasePage(#[from] ErasePageError),
+/// The pages synced to the markdown files in the directory, to resolve links between them.
+fn load_links(directory: Option<&Path>) -> Result<Option<LinkResolver>, CommandError> {
+    directory
+        .map(|directory| {
+            LinkResolver::from_directory(directory).context(format!(
+                "could not read the pages of the files in {}",
+                directory.display()
+            ))
+        })
+        .transpose()
+}
 
-    #[error("cannot create blocks")]
-    CreateBlocks(Vec<AppendBlockChildrenError>),
+/// Prints the warnings about the content that could not be converted as is.
+fn print_warnings(warnings: &[Warning]) {
+    for warning in warnings {
+        eprintln!("Warning: {warning}");
+    }
+}
 
-    #[error("cannot parse document {0}")]
-    Parse(ParseError<'a>),
+/// Prints what the user should know about a fetched page.
+fn print_fetch_report(page_id: &PageId, fetch_report: &FetchReport) {
+    if fetch_report.archived {
+        eprintln!(
+            "Warning: page {page_id} is in the trash in Notion, it can only be pushed to with --unarchive"
+        );
+    }
+    print_warnings(&fetch_report.warnings);
 }
 
-async fn convert_markdown_to_page<'a>(
-    notion_api: &NotionApi,
-    client: &NotionClient,
-    page_id: PageId,
-    input: &'a str,
-) -> Result<(), MarkdownToPageError<'a>> {
-    erase_page(notion_api, client, page_id.clone()).await?;
-
-    let markdown_tags = PulldownCMarkEventParser::new(pulldown_cmark::Parser::new(input))
-        .parse()
-        .map_err(MarkdownToPageError::Parse)?;
-    let blocks_to_create: Vec<_> = markdown_tags
-        .into_iter()
-        .flat_map(BlockWithChildrenToCreate::from_markdown_tag)
+/// Prints what the user should know about a pushed file, besides the `--report`.
+fn print_push_report(file_push: &FilePushReport) {
+    if let Some(ignored_page_id) = &file_push.ignored_page_id {
+        warn_about_ignored_page_id(&file_push.page_id, ignored_page_id);
+    }
+    if let Some(backup_page_url) = &file_push.report.backup_page_url {
+        println!("Backed up the page content to {backup_page_url}");
+    }
+    if file_push.up_to_date {
+        eprintln!("The page is already up to date");
+    }
+    print_warnings(&file_push.report.warnings);
+}
+
+fn warn_about_ignored_page_id(page_id: &PageId, ignored_page_id: &PageId) {
+    eprintln!(
+        "Warning: using page {page_id} given with --page-id, not page {ignored_page_id} from the front matter"
+    );
+}
+
+/// The results of fetching several pages as a table, one page per line.
+fn format_fetch_results(fetches: &[PageFetch]) -> String {
+    let rows: Vec<_> = fetches
+        .iter()
+        .map(|fetch| {
+            vec![
+                fetch
+                    .file
+                    .as_ref()
+                    .map_or_else(|| "-".to_string(), ToString::to_string),
+                fetch.page_id.to_string(),
+                match fetch.result {
+                    Ok(_) => "fetched",
+                    Err(_) => "failed",
+                }
+                .to_string(),
+                format!("{:.1}s", fetch.duration.as_secs_f64()),
+            ]
+        })
         .collect();
-    client
-        .create_blocks(page_id.as_id().clone().into(), blocks_to_create)
+
+    format_table(&["FILE", "PAGE", "STATUS", "TIME"], &rows)
+}
+
+/// The pages to fetch, one URL or ID per line. Blank lines are skipped.
+fn read_page_ids(
+    reader: impl BufRead,
+    allowed_hosts: &[String],
+) -> Result<Vec<PageId>, CommandError> {
+    let mut page_ids = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.context("could not read the pages from stdin")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let page_id = parse_page_id_with_hosts(line.trim(), allowed_hosts).map_err(|error| {
+            CommandError::new(
+                ErrorKind::Usage,
+                format!("invalid page on line {} of stdin: {error}", index + 1),
+            )
+        })?;
+        page_ids.push(page_id);
+    }
+
+    Ok(page_ids)
+}
+
+/// The page to fetch and the file to write it to. The page is picked interactively when it is
+/// not given, and the file is named after the page when it is not given.
+async fn get_page_and_file_to_fetch(
+    client: &NotionClient,
+    page_id: Option<PageId>,
+    file: Option<FileArg>,
+) -> Result<(PageId, FileArg), CommandError> {
+    let (page_id, title) = match page_id {
+        Some(page_id) => (page_id, None),
+        None => {
+            let page = pick_page_to_fetch(client).await?;
+            (page.id, Some(page.title))
+        }
+    };
+
+    let file = match file {
+        Some(file) => file,
+        None => {
+            let title = match title {
+                Some(title) => title,
+                None => {
+                    client
+                        .get_page(&page_id)
+                        .await
+                        .context("could not get the title of the page")?
+                        .title
+                }
+            };
+            FileArg::for_page_title(&title)
+        }
+    };
+
+    Ok((page_id, file))
+}
+
+/// Lets the user pick the page to fetch among the pages shared with the integration.
+/// Fails when there is no terminal to pick the page in.
+async fn pick_page_to_fetch(client: &NotionClient) -> Result<PageSummary, CommandError> {
+    if !io::stdin().is_terminal() {
+        return Err(CommandError::new(ErrorKind::Usage, "no page given. Pass the URL or ID of the page, for example `notion-edit fetch https://www.notion.so/My-page-0b89a6e8f0064acc8ec6e6902b039e3a my-page.md`"));
+    }
+
+    let pages = client
+        .search_pages()
+        .await
+        .context("could not list the pages")?;
+    // NOTE: stdout may be the fetched page, so the picker uses stderr
+    picker::pick(
+        &pages,
+        |page| page.title.as_str(),
+        io::stdin().lock(),
+        io::stderr(),
+    )
+    .context("could not pick the page")?
+    .cloned()
+    .ok_or_else(|| CommandError::new(ErrorKind::Usage, "no page was picked"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+
+    /// Runs the command with Notion at `api_base_url`.
+    async fn run_command(api_base_url: &str, args: &[&str]) -> Result<ExitCode, CommandError> {
+        let directory = tempfile::tempdir().unwrap();
+        let config = directory.path().join("config.toml");
+        fs::write(&config, format!("api_base_url = \"{api_base_url}\"\n")).unwrap();
+        let state_dir = directory.path().join("state");
+
+        let mut cli_args = vec![
+            "notion-edit".to_string(),
+            "--token=secret_test".to_string(),
+            format!("--config={}", config.display()),
+            format!("--state-dir={}", state_dir.display()),
+        ];
+        cli_args.extend(args.iter().map(|arg| arg.to_string()));
+        let cli = Cli::try_parse_from(cli_args).unwrap();
+
+        let result = run(cli).await;
+        directory.close().unwrap();
+        result
+    }
+
+    #[tokio::test]
+    async fn exits_with_the_code_of_the_failure() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/notion/v1/users/me"))
+            .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+                "object": "error",
+                "status": 401,
+                "code": "unauthorized",
+                "message": "API token is invalid."
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/pages/{PAGE_ID}")))
+            .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+                "object": "error",
+                "status": 404,
+                "code": "object_not_found",
+                "message": "Could not find page."
+            })))
+            .mount(&server)
+            .await;
+        let api_base_url = format!("{}/notion", server.uri());
+        let directory = tempfile::tempdir().unwrap();
+        let json_file = directory.path().join("page.json");
+        fs::write(&json_file, "not JSON").unwrap();
+
+        for (api_base_url, args, kind) in [
+            (
+                api_base_url.as_str(),
+                vec!["fetch", PAGE_ID, "-", "--watch"],
+                ErrorKind::Usage,
+            ),
+            (api_base_url.as_str(), vec!["whoami"], ErrorKind::Auth),
+            (
+                api_base_url.as_str(),
+                vec!["fetch", PAGE_ID, "-"],
+                ErrorKind::NotFound,
+            ),
+            // NOTE: nothing listens on port 1
+            ("http://127.0.0.1:1", vec!["whoami"], ErrorKind::Network),
+            (
+                api_base_url.as_str(),
+                vec![
+                    "push",
+                    "--page-id",
+                    PAGE_ID,
+                    "--input-format=json",
+                    "--file",
+                    json_file.to_str().unwrap(),
+                ],
+                ErrorKind::Conversion,
+            ),
+        ] {
+            let error = run_command(api_base_url, &args)
+                .await
+                .expect_err("failed command");
+
+            assert_eq!(error.kind(), kind, "{args:?}: {}", error.render());
+        }
+    }
+
+    #[tokio::test]
+    async fn api_base_url_flag_takes_precedence_over_the_config() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/gateway/v1/users/me"))
+            .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+                "object": "error",
+                "status": 401,
+                "code": "unauthorized",
+                "message": "API token is invalid."
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let flag = format!("--api-base-url={}/gateway", server.uri());
+
+        // NOTE: nothing listens on port 1, so only the flag reaches the mock server
+        let error = run_command("http://127.0.0.1:1", &[&flag, "whoami"])
+            .await
+            .expect_err("failed command");
+
+        assert_eq!(error.kind(), ErrorKind::Auth, "{}", error.render());
+    }
+
+    #[tokio::test]
+    async fn fetches_several_pages_and_fails_when_one_fails() {
+        const MISSING_PAGE_ID: &str = "8d4e2f60-3c1b-4a7e-9f2d-5b6a7c8d9e0f";
+        const FOUND_PAGE_ID: &str = "ac32e025-6f9c-4fab-8b9d-dbb3c593ac46";
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/pages/{FOUND_PAGE_ID}")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "page",
+                "id": FOUND_PAGE_ID,
+                "last_edited_time": "2022-08-01T12:00:00.000Z",
+                "properties": {
+                    "title": {
+                        "type": "title",
+                        "title": [{ "type": "text", "plain_text": "Team notes" }]
+                    }
+                }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/blocks/{FOUND_PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": [],
+                "next_cursor": null,
+                "has_more": false
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/notion/v1/pages/{MISSING_PAGE_ID}")))
+            .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+                "object": "error",
+                "status": 404,
+                "code": "object_not_found",
+                "message": "Could not find page."
+            })))
+            .mount(&server)
+            .await;
+        let directory = tempfile::tempdir().unwrap();
+
+        let error = run_command(
+            &format!("{}/notion", server.uri()),
+            &[
+                "fetch",
+                "-p",
+                MISSING_PAGE_ID,
+                "-p",
+                FOUND_PAGE_ID,
+                "-o",
+                directory.path().to_str().unwrap(),
+            ],
+        )
         .await
-        .map_err(MarkdownToPageError::CreateBlocks)?;
+        .expect_err("a page could not be fetched");
 
-    Ok(())
+        assert_eq!(error.kind(), ErrorKind::Failure);
+        assert!(directory.path().join("team-notes.md").exists());
+    }
+
+    #[test]
+    fn reads_page_ids_from_lines() {
+        let page_ids = read_page_ids(
+            format!(
+                "{PAGE_ID}\n\nhttps://www.notion.so/gelio/Notes-8d4e2f603c1b4a7e9f2d5b6a7c8d9e0f\n"
+            )
+            .as_bytes(),
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(
+            page_ids.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "ac32e025-6f9c-4fab-8b9d-dbb3c593ac46",
+                "8d4e2f60-3c1b-4a7e-9f2d-5b6a7c8d9e0f"
+            ]
+        );
+        assert_eq!(
+            read_page_ids("not a page\n".as_bytes(), &[])
+                .expect_err("invalid page")
+                .kind(),
+            ErrorKind::Usage
+        );
+    }
 }
@@ -0,0 +1,235 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use notion::ids::PageId;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::ids::{parse_page_id, ParsePageIdError};
+
+/// The pages synced by `sync`, read from a TOML file like:
+///
+/// ```toml
+/// [[pages]]
+/// page = "https://www.notion.so/gelio/Notes-0b89a6e8f0064acc8ec6e6902b039e3a"
+/// file = "notes.md"
+/// direction = "two-way"
+/// ```
+#[derive(Debug, PartialEq, Eq)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub page_id: PageId,
+    /// The file of the page, relative to the current directory.
+    pub file: PathBuf,
+    pub direction: SyncDirection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncDirection {
+    Fetch,
+    Push,
+    /// Fetch when the page changed since the last sync, and push when the file changed.
+    TwoWay,
+}
+
+#[derive(Deserialize)]
+struct RawManifest {
+    #[serde(default)]
+    pages: Vec<RawManifestEntry>,
+}
+
+#[derive(Deserialize)]
+struct RawManifestEntry {
+    page: String,
+    file: PathBuf,
+    direction: SyncDirection,
+}
+
+#[derive(Error, Debug)]
+pub enum ManifestError {
+    #[error("cannot read manifest {path}")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("invalid manifest {path}")]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    #[error("invalid page {page} in manifest {path}")]
+    InvalidPage {
+        path: PathBuf,
+        page: String,
+        #[source]
+        source: ParsePageIdError,
+    },
+}
+
+impl Manifest {
+    /// Loads the manifest. The files in it are relative to the directory of the manifest.
+    pub fn load(path: &Path) -> Result<Self, ManifestError> {
+        let contents = fs::read_to_string(path).map_err(|source| ManifestError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+        Self::parse(&contents, directory).map_err(|error| match error {
+            ParseManifestError::Toml(source) => ManifestError::Parse {
+                path: path.to_path_buf(),
+                source,
+            },
+            ParseManifestError::Page { page, source } => ManifestError::InvalidPage {
+                path: path.to_path_buf(),
+                page,
+                source,
+            },
+        })
+    }
+
+    fn parse(contents: &str, directory: &Path) -> Result<Self, ParseManifestError> {
+        let raw: RawManifest = toml::from_str(contents).map_err(ParseManifestError::Toml)?;
+
+        let entries = raw
+            .pages
+            .into_iter()
+            .map(|entry| {
+                let page_id =
+                    parse_page_id(&entry.page).map_err(|source| ParseManifestError::Page {
+                        page: entry.page.clone(),
+                        source,
+                    })?;
+
+                Ok(ManifestEntry {
+                    page_id,
+                    file: directory.join(entry.file),
+                    direction: entry.direction,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { entries })
+    }
+}
+
+#[derive(Debug)]
+enum ParseManifestError {
+    Toml(toml::de::Error),
+    Page {
+        page: String,
+        source: ParsePageIdError,
+    },
+}
+
+/// What a two-way sync does, from whether the page and the file changed since the last sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TwoWayAction {
+    Fetch,
+    Push,
+    Nothing,
+    /// Both changed, so the edits of both are merged.
+    Conflict,
+}
+
+impl TwoWayAction {
+    pub fn new(page_changed: bool, file_changed: bool) -> Self {
+        match (page_changed, file_changed) {
+            (false, false) => Self::Nothing,
+            (true, false) => Self::Fetch,
+            (false, true) => Self::Push,
+            (true, true) => Self::Conflict,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn parses_entries_relative_to_the_manifest() {
+        let manifest = Manifest::parse(
+            r#"
+[[pages]]
+page = "https://www.notion.so/gelio/Notes-0b89a6e8f0064acc8ec6e6902b039e3a"
+file = "notes.md"
+direction = "two-way"
+
+[[pages]]
+page = "ac32e0256f9c4fab8b9ddbb3c593ac46"
+file = "plans/launch.md"
+direction = "fetch"
+"#,
+            Path::new("docs"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            manifest,
+            Manifest {
+                entries: vec![
+                    ManifestEntry {
+                        page_id: PageId::from_str("0b89a6e8-f006-4acc-8ec6-e6902b039e3a").unwrap(),
+                        file: PathBuf::from("docs/notes.md"),
+                        direction: SyncDirection::TwoWay,
+                    },
+                    ManifestEntry {
+                        page_id: PageId::from_str("ac32e025-6f9c-4fab-8b9d-dbb3c593ac46").unwrap(),
+                        file: PathBuf::from("docs/plans/launch.md"),
+                        direction: SyncDirection::Fetch,
+                    },
+                ]
+            }
+        );
+    }
+
+    #[test]
+    fn reports_invalid_entries() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("pages.toml");
+
+        fs::write(
+            &path,
+            "[[pages]]\npage = \"not a page\"\nfile = \"notes.md\"\ndirection = \"push\"\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            Manifest::load(&path),
+            Err(ManifestError::InvalidPage { .. })
+        ));
+
+        fs::write(
+            &path,
+            "[[pages]]\npage = \"ac32e0256f9c4fab8b9ddbb3c593ac46\"\nfile = \"notes.md\"\ndirection = \"sideways\"\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            Manifest::load(&path),
+            Err(ManifestError::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn two_way_sync_follows_the_side_that_changed() {
+        assert_eq!(TwoWayAction::new(false, false), TwoWayAction::Nothing);
+        assert_eq!(TwoWayAction::new(true, false), TwoWayAction::Fetch);
+        assert_eq!(TwoWayAction::new(false, true), TwoWayAction::Push);
+        assert_eq!(TwoWayAction::new(true, true), TwoWayAction::Conflict);
+    }
+}
@@ -0,0 +1,421 @@
+//! Anchors of headings, so links between the sections of a page work in markdown.
+//!
+//! Notion links to a block with the ID of the block after `#` in the URL of the page.
+//! Such links to the headings of the same page become `#anchor` links in markdown.
+
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use super::tag::{RichText, Tag};
+
+/// The anchor of a heading with the text, the way GitHub makes them: lowercase, without
+/// punctuation, and with hyphens in place of spaces.
+pub fn slugify(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            c if c.is_whitespace() => Some('-'),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The anchors of the headings in the document, in order, including the headings nested in
+/// other blocks. Explicit `{#anchor}`s are kept as they are. Headings whose slug is already
+/// taken get a `-1`, `-2` suffix.
+pub fn heading_anchors(tags: &[Tag]) -> Vec<String> {
+    HeadingAnchors::default().anchors(tags)
+}
+
+/// The anchors that are taken by the headings seen so far, so the anchors of a document
+/// can be made a few tags at a time.
+#[derive(Debug, Default)]
+struct HeadingAnchors {
+    used_anchors: HashSet<String>,
+    suffixes: HashMap<String, usize>,
+}
+
+impl HeadingAnchors {
+    /// The anchors of the next headings of the document, see [`heading_anchors`].
+    fn anchors(&mut self, tags: &[Tag]) -> Vec<String> {
+        let mut headings = Vec::new();
+        collect_headings(tags, &mut headings);
+
+        headings
+            .into_iter()
+            .map(|(text, id)| {
+                let anchor = match id {
+                    Some(id) => id.to_string(),
+                    None => {
+                        let slug = slugify(&text);
+                        let mut anchor = slug.clone();
+                        while self.used_anchors.contains(&anchor) {
+                            let suffix = self.suffixes.entry(slug.clone()).or_default();
+                            *suffix += 1;
+                            anchor = format!("{slug}-{suffix}");
+                        }
+                        anchor
+                    }
+                };
+                self.used_anchors.insert(anchor.clone());
+
+                anchor
+            })
+            .collect()
+    }
+}
+
+fn collect_headings<'a>(tags: &'a [Tag], headings: &mut Vec<(String, Option<&'a str>)>) {
+    for tag in tags {
+        match tag {
+            Tag::Heading { text, id, .. } => headings.push((plain_text(text), id.as_deref())),
+            Tag::OrderedList { items, .. } => {
+                for item in items {
+                    collect_headings(&item.children, headings);
+                }
+            }
+            Tag::Quote { children, .. }
+            | Tag::Callout { children, .. }
+            | Tag::Toggle { children, .. } => collect_headings(children, headings),
+            Tag::Paragraph(_) | Tag::Code { .. } | Tag::Image { .. } | Tag::Divider => {}
+        }
+    }
+}
+
+/// Points the links to the heading blocks at the anchors of the headings. The IDs of the
+/// heading blocks are in the order of the headings in the document.
+pub fn link_heading_blocks(tags: &mut [Tag], heading_block_ids: &[String]) {
+    let anchors = block_anchors(heading_block_ids, heading_anchors(tags));
+    if anchors.is_empty() {
+        return;
+    }
+
+    for tag in tags {
+        link_blocks(tag, &anchors);
+    }
+}
+
+/// [`link_heading_blocks`] for a document that is converted a few tags at a time.
+///
+/// Links to headings further down the page cannot be pointed at their anchors until the
+/// headings are seen, so the tags from the first such link on are held back. Links to the
+/// blocks of other pages do not hold anything back.
+#[derive(Debug)]
+pub struct HeadingLinker {
+    /// The ID of the page, without hyphens, as it is in the links to its blocks.
+    page_id: String,
+    anchors: HeadingAnchors,
+    block_anchors: HashMap<Uuid, String>,
+    pending_tags: Vec<Tag>,
+}
+
+impl HeadingLinker {
+    pub fn new(page_id: &str) -> Self {
+        Self {
+            page_id: page_id.replace('-', ""),
+            anchors: HeadingAnchors::default(),
+            block_anchors: HashMap::new(),
+            pending_tags: Vec::new(),
+        }
+    }
+
+    /// Adds the next tags of the document, with the IDs of their heading blocks, and returns
+    /// the tags whose links are all resolved, in order.
+    pub fn push(&mut self, tags: Vec<Tag>, heading_block_ids: &[String]) -> Vec<Tag> {
+        let anchors = self.anchors.anchors(&tags);
+        self.block_anchors
+            .extend(block_anchors(heading_block_ids, anchors));
+        self.pending_tags.extend(tags);
+
+        let mut first_unresolved = None;
+        for (index, tag) in self.pending_tags.iter_mut().enumerate() {
+            link_blocks(tag, &self.block_anchors);
+            if first_unresolved.is_none() && links_to_page_blocks(tag, &self.page_id) {
+                first_unresolved = Some(index);
+            }
+        }
+
+        let ready_tags = first_unresolved.unwrap_or(self.pending_tags.len());
+        let pending_tags = self.pending_tags.split_off(ready_tags);
+        std::mem::replace(&mut self.pending_tags, pending_tags)
+    }
+
+    /// The tags that were held back. Links to blocks that turned out not to be headings are
+    /// kept as they are.
+    pub fn finish(&mut self) -> Vec<Tag> {
+        std::mem::take(&mut self.pending_tags)
+    }
+}
+
+fn block_anchors(heading_block_ids: &[String], anchors: Vec<String>) -> HashMap<Uuid, String> {
+    heading_block_ids
+        .iter()
+        .zip(anchors)
+        .filter_map(|(block_id, anchor)| Some((Uuid::try_parse(block_id).ok()?, anchor)))
+        .collect()
+}
+
+fn link_blocks(tag: &mut Tag, anchors: &HashMap<Uuid, String>) {
+    tag.visit_rich_text_mut(&mut |text| {
+        for link in text
+            .iter_mut()
+            .filter_map(|rich_text| rich_text.link.as_mut())
+        {
+            let anchor = link_fragment(link).and_then(|block_id| anchors.get(&block_id));
+            if let Some(anchor) = anchor {
+                *link = format!("#{anchor}");
+            }
+        }
+    });
+}
+
+/// Whether the tag links to a block of the page, other than to the headings that were
+/// already linked.
+fn links_to_page_blocks(tag: &mut Tag, page_id: &str) -> bool {
+    let mut links_to_blocks = false;
+    tag.visit_rich_text_mut(&mut |text| {
+        links_to_blocks |= text
+            .iter()
+            .filter_map(|rich_text| rich_text.link.as_deref())
+            .any(|link| {
+                link_fragment(link).is_some()
+                    && link
+                        .split_once('#')
+                        .is_some_and(|(page_url, _)| page_url.contains(page_id))
+            });
+    });
+
+    links_to_blocks
+}
+
+/// The ID of the block that the link points to.
+fn link_fragment(link: &str) -> Option<Uuid> {
+    link.rsplit_once('#')
+        .and_then(|(_, fragment)| Uuid::try_parse(fragment).ok())
+}
+
+/// Prefixes the `#anchor` links to the headings of the document with the URL of its page,
+/// as Notion only accepts absolute links. Returns whether the document had such links.
+pub fn qualify_heading_links(tags: &mut [Tag], page_url: &str) -> bool {
+    let anchors: HashSet<_> = heading_anchors(tags).into_iter().collect();
+
+    let mut qualified = false;
+    for tag in tags {
+        tag.visit_rich_text_mut(&mut |text| {
+            for link in text
+                .iter_mut()
+                .filter_map(|rich_text| rich_text.link.as_mut())
+            {
+                if link
+                    .strip_prefix('#')
+                    .is_some_and(|anchor| anchors.contains(anchor))
+                {
+                    *link = format!("{page_url}{link}");
+                    qualified = true;
+                }
+            }
+        });
+    }
+
+    qualified
+}
+
+fn plain_text(text: &[RichText]) -> String {
+    text.iter()
+        .map(|rich_text| rich_text.text.as_str())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::markdown::tag::{HeadingLevel, Paragraph};
+
+    const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+    const OVERVIEW_ID: &str = "0b89a6e8-f006-4acc-8ec6-e6902b039e3a";
+    const DETAILS_ID: &str = "8d4e2f60-3c1b-4a7e-9f2d-5b6a7c8d9e0f";
+    const OTHER_BLOCK_ID: &str = "0a5f1b4e8c0d4c4a9c5e6d1f2b3a4c5d";
+
+    fn text(text: &str, link: Option<&str>) -> Vec<RichText> {
+        vec![RichText {
+            text: text.to_string(),
+            link: link.map(ToString::to_string),
+            mention: None,
+        }]
+    }
+
+    fn heading(text_content: &str, id: Option<&str>) -> Tag {
+        Tag::Heading {
+            level: HeadingLevel::H2,
+            text: text(text_content, None),
+            id: id.map(ToString::to_string),
+        }
+    }
+
+    #[test]
+    fn slugifies_heading_text() {
+        assert_eq!(slugify("Getting Started"), "getting-started");
+        assert_eq!(slugify(" What's new in v2.0? "), "whats-new-in-v20");
+        assert_eq!(slugify("snake_case and-hyphens"), "snake_case-and-hyphens");
+        assert_eq!(slugify("Zażółć gęślą"), "zażółć-gęślą");
+    }
+
+    #[test]
+    fn disambiguates_headings_with_the_same_slug() {
+        let tags = vec![
+            heading("Notes", None),
+            heading("Notes", None),
+            Tag::Toggle {
+                text: Vec::new(),
+                children: vec![heading("Notes!", None)],
+            },
+            heading("Notes-1", None),
+            heading("Summary", Some("notes-3")),
+            heading("Notes", None),
+        ];
+
+        assert_eq!(
+            heading_anchors(&tags),
+            [
+                "notes",
+                "notes-1",
+                "notes-2",
+                "notes-1-1",
+                "notes-3",
+                "notes-4"
+            ]
+        );
+    }
+
+    #[test]
+    fn links_to_heading_blocks_become_anchors() {
+        let links = [
+            format!(
+                "https://www.notion.so/Notes-{PAGE_ID}#{}",
+                DETAILS_ID.replace('-', "")
+            ),
+            format!("/{PAGE_ID}#{}", OVERVIEW_ID.replace('-', "")),
+            format!("https://www.notion.so/{PAGE_ID}#{OTHER_BLOCK_ID}"),
+        ];
+        let mut tags = vec![
+            heading("Overview", None),
+            Tag::Paragraph(Paragraph {
+                text: links
+                    .iter()
+                    .map(|link| RichText {
+                        text: "see".to_string(),
+                        link: Some(link.clone()),
+                        mention: None,
+                    })
+                    .collect(),
+            }),
+            heading("Details", Some("more")),
+        ];
+
+        link_heading_blocks(
+            &mut tags,
+            &[OVERVIEW_ID.to_string(), DETAILS_ID.to_string()],
+        );
+
+        let Tag::Paragraph(Paragraph { text }) = &tags[1] else {
+            unreachable!("the links are in a paragraph");
+        };
+        assert_eq!(
+            text.iter()
+                .map(|rich_text| rich_text.link.as_deref().unwrap())
+                .collect::<Vec<_>>(),
+            ["#more", "#overview", links[2].as_str()]
+        );
+    }
+
+    #[test]
+    fn holds_back_links_to_headings_further_down() {
+        let link = |text_content: &str, link: String| {
+            Tag::Paragraph(Paragraph {
+                text: text(text_content, Some(&link)),
+            })
+        };
+        let link_target = |tag: &Tag| match tag {
+            Tag::Paragraph(Paragraph { text }) => text[0].link.clone(),
+            _ => None,
+        };
+        let mut linker = HeadingLinker::new(PAGE_ID);
+
+        let ready = linker.push(
+            vec![
+                link(
+                    "elsewhere",
+                    format!("https://www.notion.so/{OTHER_BLOCK_ID}#{OTHER_BLOCK_ID}"),
+                ),
+                heading("Notes", None),
+                link(
+                    "down",
+                    format!("/{PAGE_ID}#{}", DETAILS_ID.replace('-', "")),
+                ),
+                heading("After", None),
+            ],
+            &[
+                OVERVIEW_ID.to_string(),
+                "3f4a5b6c-7d8e-4f9a-0b1c-2d3e4f5a6b7c".to_string(),
+            ],
+        );
+        assert_eq!(ready.len(), 2, "held back from the unresolved link on");
+        assert_eq!(
+            link_target(&ready[0]),
+            Some(format!(
+                "https://www.notion.so/{OTHER_BLOCK_ID}#{OTHER_BLOCK_ID}"
+            )),
+            "links to other pages are not held back"
+        );
+
+        let ready = linker.push(vec![heading("Notes", None)], &[DETAILS_ID.to_string()]);
+        assert_eq!(
+            ready.iter().map(link_target).collect::<Vec<_>>(),
+            [Some("#notes-1".to_string()), None, None]
+        );
+        assert_eq!(linker.finish(), Vec::new());
+    }
+
+    #[test]
+    fn qualifies_links_to_headings_with_the_page_url() {
+        let mut tags = vec![
+            heading("Overview", None),
+            Tag::Paragraph(Paragraph {
+                text: [("overview", "#overview"), ("elsewhere", "#missing")]
+                    .into_iter()
+                    .map(|(text, link)| RichText {
+                        text: text.to_string(),
+                        link: Some(link.to_string()),
+                        mention: None,
+                    })
+                    .collect(),
+            }),
+        ];
+
+        assert!(qualify_heading_links(
+            &mut tags,
+            "https://www.notion.so/ac32e0256f9c4fab8b9ddbb3c593ac46"
+        ));
+        let Tag::Paragraph(Paragraph { text }) = &tags[1] else {
+            unreachable!("the links are in a paragraph");
+        };
+        assert_eq!(
+            text.iter()
+                .map(|rich_text| rich_text.link.as_deref().unwrap())
+                .collect::<Vec<_>>(),
+            [
+                "https://www.notion.so/ac32e0256f9c4fab8b9ddbb3c593ac46#overview",
+                "#missing"
+            ]
+        );
+        assert!(!qualify_heading_links(
+            &mut tags[1..],
+            "https://www.notion.so/"
+        ));
+    }
+}
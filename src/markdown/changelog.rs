@@ -0,0 +1,512 @@
+use std::fmt::Write;
+
+use super::tag::{OrderedListItem, Paragraph, RichText, Tag};
+
+/// The text that dividers are compared by.
+const DIVIDER_TEXT: &str = "---";
+
+/// A normalized form of a document used to compare its structure: sections delimited by
+/// headings, each with the plain text of its blocks.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Outline {
+    sections: Vec<Section>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Section {
+    /// `None` for the content before the first heading.
+    heading: Option<String>,
+    blocks: Vec<OutlineBlock>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum OutlineBlock {
+    Paragraph(String),
+    List {
+        /// The text of each item, including the text of its children.
+        items: Vec<String>,
+        /// The number of Notion blocks in the list, including nested items.
+        block_count: usize,
+    },
+}
+
+impl OutlineBlock {
+    fn block_count(&self) -> usize {
+        match self {
+            OutlineBlock::Paragraph(_) => 1,
+            OutlineBlock::List { block_count, .. } => *block_count,
+        }
+    }
+}
+
+impl Outline {
+    pub fn from_tags(tags: &[Tag]) -> Self {
+        let mut sections = vec![Section {
+            heading: None,
+            blocks: Vec::new(),
+        }];
+
+        for tag in tags {
+            if let Tag::Heading { text, .. } = tag {
+                sections.push(Section {
+                    heading: Some(normalize_text(text)),
+                    blocks: Vec::new(),
+                });
+            } else {
+                push_blocks(
+                    &mut sections
+                        .last_mut()
+                        .expect("there is always at least one section")
+                        .blocks,
+                    tag,
+                );
+            }
+        }
+
+        if sections[0].blocks.is_empty() {
+            sections.remove(0);
+        }
+
+        Self { sections }
+    }
+}
+
+fn push_blocks(blocks: &mut Vec<OutlineBlock>, tag: &Tag) {
+    match tag {
+        Tag::Paragraph(Paragraph { text }) | Tag::Heading { text, .. } => {
+            blocks.push(OutlineBlock::Paragraph(normalize_text(text)))
+        }
+        Tag::OrderedList { items, .. } => blocks.push(OutlineBlock::List {
+            items: items.iter().map(item_text).collect(),
+            block_count: count_blocks(tag),
+        }),
+        // NOTE: the content of quotes, callouts and toggles is compared like the content outside of them
+        Tag::Quote { text, children }
+        | Tag::Callout { text, children, .. }
+        | Tag::Toggle { text, children } => {
+            if !text.is_empty() {
+                blocks.push(OutlineBlock::Paragraph(normalize_text(text)));
+            }
+            for child in children {
+                push_blocks(blocks, child);
+            }
+        }
+        Tag::Code { text, .. } => blocks.push(OutlineBlock::Paragraph(text.clone())),
+        Tag::Image { url } => blocks.push(OutlineBlock::Paragraph(url.clone())),
+        Tag::Divider => blocks.push(OutlineBlock::Paragraph(DIVIDER_TEXT.to_string())),
+    }
+}
+
+/// A change between two outlines, as shown in the changelog.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SectionChange {
+    Added {
+        heading: Option<String>,
+        block_count: usize,
+    },
+    Removed {
+        heading: Option<String>,
+        block_count: usize,
+    },
+    Edited {
+        heading: Option<String>,
+        edits: Vec<BlockEdit>,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BlockEdit {
+    ParagraphsAdded(usize),
+    ParagraphsRemoved(usize),
+    ParagraphsEdited(usize),
+    ListAdded { items: usize },
+    ListRemoved { items: usize },
+    ListResized { before: usize, after: usize },
+    ListEdited,
+}
+
+/// Computes the structural difference between two outlines. Sections are matched by their
+/// headings and blocks within matching sections are matched by their text.
+pub fn diff(before: &Outline, after: &Outline) -> Vec<SectionChange> {
+    let mut changes = Vec::new();
+
+    for step in align(&before.sections, &after.sections, |before, after| {
+        before.heading == after.heading
+    }) {
+        match step {
+            Alignment::Same(before_index, after_index) => {
+                let before = &before.sections[before_index];
+                let after = &after.sections[after_index];
+                let edits = diff_blocks(&before.blocks, &after.blocks);
+                if !edits.is_empty() {
+                    changes.push(SectionChange::Edited {
+                        heading: after.heading.clone(),
+                        edits,
+                    });
+                }
+            }
+            Alignment::Gap { removed, added } => {
+                changes.extend(removed.into_iter().map(|index| {
+                    let section = &before.sections[index];
+                    SectionChange::Removed {
+                        heading: section.heading.clone(),
+                        block_count: section.blocks.iter().map(OutlineBlock::block_count).sum(),
+                    }
+                }));
+                changes.extend(added.into_iter().map(|index| {
+                    let section = &after.sections[index];
+                    SectionChange::Added {
+                        heading: section.heading.clone(),
+                        block_count: section.blocks.iter().map(OutlineBlock::block_count).sum(),
+                    }
+                }));
+            }
+        }
+    }
+
+    changes
+}
+
+fn diff_blocks(before: &[OutlineBlock], after: &[OutlineBlock]) -> Vec<BlockEdit> {
+    let mut paragraphs_added = 0;
+    let mut paragraphs_removed = 0;
+    let mut paragraphs_edited = 0;
+    let mut list_edits = Vec::new();
+
+    for step in align(before, after, PartialEq::eq) {
+        let Alignment::Gap { removed, added } = step else {
+            continue;
+        };
+
+        let (removed_paragraphs, removed_lists) =
+            split_by_kind(removed.iter().map(|&i| &before[i]));
+        let (added_paragraphs, added_lists) = split_by_kind(added.iter().map(|&i| &after[i]));
+
+        // NOTE: paragraphs replaced in the same place are treated as edited
+        let edited = removed_paragraphs.min(added_paragraphs);
+        paragraphs_edited += edited;
+        paragraphs_removed += removed_paragraphs - edited;
+        paragraphs_added += added_paragraphs - edited;
+
+        for index in 0..removed_lists.len().max(added_lists.len()) {
+            list_edits.push(match (removed_lists.get(index), added_lists.get(index)) {
+                (Some(before), Some(after)) if before.len() == after.len() => BlockEdit::ListEdited,
+                (Some(before), Some(after)) => BlockEdit::ListResized {
+                    before: before.len(),
+                    after: after.len(),
+                },
+                (Some(before), None) => BlockEdit::ListRemoved {
+                    items: before.len(),
+                },
+                (None, Some(after)) => BlockEdit::ListAdded { items: after.len() },
+                (None, None) => unreachable!("the index is within one of the lists"),
+            });
+        }
+    }
+
+    let mut edits = Vec::new();
+    if paragraphs_added > 0 {
+        edits.push(BlockEdit::ParagraphsAdded(paragraphs_added));
+    }
+    if paragraphs_edited > 0 {
+        edits.push(BlockEdit::ParagraphsEdited(paragraphs_edited));
+    }
+    if paragraphs_removed > 0 {
+        edits.push(BlockEdit::ParagraphsRemoved(paragraphs_removed));
+    }
+    edits.extend(list_edits);
+
+    edits
+}
+
+/// Returns the number of paragraphs and the items of the lists.
+fn split_by_kind<'a>(
+    blocks: impl Iterator<Item = &'a OutlineBlock>,
+) -> (usize, Vec<&'a Vec<String>>) {
+    let mut paragraphs = 0;
+    let mut lists = Vec::new();
+    for block in blocks {
+        match block {
+            OutlineBlock::Paragraph(_) => paragraphs += 1,
+            OutlineBlock::List { items, .. } => lists.push(items),
+        }
+    }
+
+    (paragraphs, lists)
+}
+
+/// Renders the changes as a short markdown summary.
+pub fn render_changelog(changes: &[SectionChange]) -> String {
+    if changes.is_empty() {
+        return "No changes\n".to_string();
+    }
+
+    let mut buf = String::new();
+    for change in changes {
+        let line = match change {
+            SectionChange::Added {
+                heading,
+                block_count,
+            } => format!(
+                "Added {} ({})",
+                describe_section(heading),
+                pluralize(*block_count, "block")
+            ),
+            SectionChange::Removed {
+                heading,
+                block_count,
+            } => format!(
+                "Removed {} ({})",
+                describe_section(heading),
+                pluralize(*block_count, "block")
+            ),
+            SectionChange::Edited { heading, edits } => {
+                let edits: Vec<_> = edits.iter().map(describe_edit).collect();
+                let location = match heading {
+                    Some(heading) => format!("under '{heading}'"),
+                    None => "at the top of the page".to_string(),
+                };
+                capitalize(&format!("{} {location}", edits.join(", ")))
+            }
+        };
+        writeln!(buf, "- {line}").expect("writing to a string cannot fail");
+    }
+
+    buf
+}
+
+fn describe_section(heading: &Option<String>) -> String {
+    match heading {
+        Some(heading) => format!("section '{heading}'"),
+        None => "content at the top of the page".to_string(),
+    }
+}
+
+fn describe_edit(edit: &BlockEdit) -> String {
+    match edit {
+        BlockEdit::ParagraphsAdded(count) => format!("added {}", pluralize(*count, "paragraph")),
+        BlockEdit::ParagraphsRemoved(count) => {
+            format!("removed {}", pluralize(*count, "paragraph"))
+        }
+        BlockEdit::ParagraphsEdited(count) => format!("edited {}", pluralize(*count, "paragraph")),
+        BlockEdit::ListAdded { items } => format!("added a list ({})", pluralize(*items, "item")),
+        BlockEdit::ListRemoved { items } => {
+            format!("removed a list ({})", pluralize(*items, "item"))
+        }
+        BlockEdit::ListResized { before, after } if after > before => {
+            format!("grew a list from {before} to {}", pluralize(*after, "item"))
+        }
+        BlockEdit::ListResized { before, after } => {
+            format!(
+                "shrank a list from {before} to {}",
+                pluralize(*after, "item")
+            )
+        }
+        BlockEdit::ListEdited => "edited a list".to_string(),
+    }
+}
+
+fn pluralize(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{count} {noun}")
+    } else {
+        format!("{count} {noun}s")
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Joins the text and collapses whitespace, so that differences in wrapping do not count
+/// as changes.
+fn normalize_text(text: &[RichText]) -> String {
+    let text: String = text
+        .iter()
+        .map(|rich_text| rich_text.text.as_str())
+        .collect();
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn item_text(item: &OrderedListItem) -> String {
+    let mut text = normalize_text(&item.text);
+    for child in &item.children {
+        push_tag_text(&mut text, child);
+    }
+
+    text
+}
+
+fn push_tag_text(buf: &mut String, tag: &Tag) {
+    match tag {
+        Tag::Paragraph(Paragraph { text }) | Tag::Heading { text, .. } => {
+            buf.push('\n');
+            buf.push_str(&normalize_text(text));
+        }
+        Tag::OrderedList { items, .. } => {
+            for item in items {
+                buf.push('\n');
+                buf.push_str(&item_text(item));
+            }
+        }
+        Tag::Quote { text, children }
+        | Tag::Callout { text, children, .. }
+        | Tag::Toggle { text, children } => {
+            buf.push('\n');
+            buf.push_str(&normalize_text(text));
+            for child in children {
+                push_tag_text(buf, child);
+            }
+        }
+        Tag::Code { text, .. } => {
+            buf.push('\n');
+            buf.push_str(text);
+        }
+        Tag::Image { url } => {
+            buf.push('\n');
+            buf.push_str(url);
+        }
+        Tag::Divider => {
+            buf.push('\n');
+            buf.push_str(DIVIDER_TEXT);
+        }
+    }
+}
+
+fn count_blocks(tag: &Tag) -> usize {
+    match tag {
+        Tag::Paragraph(_)
+        | Tag::Heading { .. }
+        | Tag::Code { .. }
+        | Tag::Image { .. }
+        | Tag::Divider => 1,
+        Tag::OrderedList { items, .. } => items
+            .iter()
+            .map(|item| 1 + item.children.iter().map(count_blocks).sum::<usize>())
+            .sum(),
+        Tag::Quote { children, .. }
+        | Tag::Callout { children, .. }
+        | Tag::Toggle { children, .. } => 1 + children.iter().map(count_blocks).sum::<usize>(),
+    }
+}
+
+enum Alignment {
+    /// Elements at these indices are the same in both sequences.
+    Same(usize, usize),
+    /// Elements that only exist in one of the sequences, between two matching elements.
+    Gap {
+        removed: Vec<usize>,
+        added: Vec<usize>,
+    },
+}
+
+/// Aligns two sequences using their longest common subsequence.
+fn align<T>(before: &[T], after: &[T], eq: impl Fn(&T, &T) -> bool) -> Vec<Alignment> {
+    // NOTE: lcs[i][j] is the length of the longest common subsequence of before[i..] and after[j..]
+    let mut lcs = vec![vec![0usize; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            lcs[i][j] = if eq(&before[i], &after[j]) {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut alignment = Vec::new();
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < before.len() || j < after.len() {
+        if i < before.len() && j < after.len() && eq(&before[i], &after[j]) {
+            if !removed.is_empty() || !added.is_empty() {
+                alignment.push(Alignment::Gap {
+                    removed: std::mem::take(&mut removed),
+                    added: std::mem::take(&mut added),
+                });
+            }
+            alignment.push(Alignment::Same(i, j));
+            i += 1;
+            j += 1;
+        } else if j == after.len() || (i < before.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            removed.push(i);
+            i += 1;
+        } else {
+            added.push(j);
+            j += 1;
+        }
+    }
+    if !removed.is_empty() || !added.is_empty() {
+        alignment.push(Alignment::Gap { removed, added });
+    }
+
+    alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::markdown::from_cmark::PulldownCMarkEventParser;
+
+    use super::*;
+
+    fn get_outline(document: &str) -> Outline {
+        let tags = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new(document),
+            Default::default(),
+        )
+        .parse()
+        .expect("successful parsing of the fixture");
+
+        Outline::from_tags(&tags)
+    }
+
+    fn assert_changelog(before: &str, after: &str, expected_changelog: &str) {
+        let changes = diff(&get_outline(before), &get_outline(after));
+
+        assert_eq!(render_changelog(&changes), expected_changelog);
+    }
+
+    #[test]
+    fn added_section() {
+        assert_changelog(
+            include_str!("../../tests/fixtures/changelog/added_section/before.md"),
+            include_str!("../../tests/fixtures/changelog/added_section/after.md"),
+            include_str!("../../tests/fixtures/changelog/added_section/changelog.md"),
+        );
+    }
+
+    #[test]
+    fn edited_paragraphs_and_lists() {
+        assert_changelog(
+            include_str!("../../tests/fixtures/changelog/edited_paragraphs_and_lists/before.md"),
+            include_str!("../../tests/fixtures/changelog/edited_paragraphs_and_lists/after.md"),
+            include_str!("../../tests/fixtures/changelog/edited_paragraphs_and_lists/changelog.md"),
+        );
+    }
+
+    #[test]
+    fn removed_section_and_preamble() {
+        assert_changelog(
+            include_str!("../../tests/fixtures/changelog/removed_section/before.md"),
+            include_str!("../../tests/fixtures/changelog/removed_section/after.md"),
+            include_str!("../../tests/fixtures/changelog/removed_section/changelog.md"),
+        );
+    }
+
+    #[test]
+    fn rewrapped_text_is_not_a_change() {
+        assert_changelog(
+            "# Risks\n\nA long sentence\nwrapped over lines.",
+            "# Risks\n\nA long sentence wrapped over lines.",
+            "No changes\n",
+        );
+    }
+}
@@ -1,80 +1,390 @@
-use std::iter::Peekable;
+use std::{collections::HashMap, iter::Peekable, ops::Range};
 
-use pulldown_cmark::Event;
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, LinkType};
 use thiserror::Error;
 
-pub struct PulldownCMarkEventParser<I> {
-    event_iterator: I,
+use super::{
+    frontmatter::FrontMatter,
+    obsidian::{self, WikiLinkMap},
+    span::Span,
+    warning::Warning,
+    Flavor, NewlineBehavior,
+};
+
+#[derive(Debug, Default, Clone)]
+pub struct ParseOptions {
+    /// The representation of newlines that should be converted back into newlines embedded
+    /// in the text. It should match the one used when serializing the document.
+    pub newline_behavior: NewlineBehavior,
+
+    /// Reject headings deeper than Notion supports instead of mapping them to H3.
+    pub strict_headings: bool,
+
+    /// Skip HTML that has no Notion equivalent instead of keeping it as an html code block.
+    pub skip_html: bool,
+
+    /// Convert straight quotes, `--`, `---` and `...` into curly quotes, dashes and ellipses.
+    pub smart_punctuation: bool,
+
+    /// Keep paragraphs without text and standalone `<br>` lines as empty paragraphs, which
+    /// Notion uses for vertical space, instead of skipping them.
+    pub keep_empty_paragraphs: bool,
+
+    pub flavor: Flavor,
+
+    /// The pages that `[[wikilinks]]` point to, with the Obsidian flavor.
+    pub wiki_links: WikiLinkMap,
+
+    /// Accept lists numbered as the continuation of the previous list, like fetched with
+    /// `merge_adjacent_lists`, instead of warning that Notion restarts them at 1.
+    pub merge_adjacent_lists: bool,
+
+    /// The number of levels every heading is shifted by, to undo the offset of a page that
+    /// was fetched with [`super::heading_offset::offset_headings`]. The shift happens before
+    /// deeper headings are mapped to H3, and levels are clamped to H1 and H3 with a warning.
+    pub heading_offset: i8,
+}
+
+impl ParseOptions {
+    /// The pulldown-cmark extensions that should be enabled for these options.
+    pub fn pulldown_cmark_options(&self) -> pulldown_cmark::Options {
+        let mut options = pulldown_cmark_options();
+        if self.smart_punctuation {
+            options |= pulldown_cmark::Options::ENABLE_SMART_PUNCTUATION;
+        }
+
+        options
+    }
+}
+
+/// The pulldown-cmark extensions for the markdown syntax supported by the parser.
+pub fn pulldown_cmark_options() -> pulldown_cmark::Options {
+    pulldown_cmark::Options::ENABLE_HEADING_ATTRIBUTES | pulldown_cmark::Options::ENABLE_FOOTNOTES
 }
 
+/// The heading of the section that footnote definitions are moved to.
+pub const FOOTNOTES_HEADING: &str = "Footnotes";
+
+/// The events of a markdown document with the byte ranges they span in the source.
+type OffsetEvent<'a> = (Event<'a>, Range<usize>);
+
+pub struct PulldownCMarkEventParser<'a, I: Iterator> {
+    event_iterator: Peekable<I>,
+    /// The markdown that the offsets of the events point into.
+    source: &'a str,
+    options: ParseOptions,
+    warnings: Vec<Warning>,
+    /// The byte offset in the source of the last event that was parsed.
+    offset: usize,
+    /// The offsets at which each of the warnings was reported.
+    warning_offsets: Vec<usize>,
+    /// The offsets of the blocks at the top of the document that each tag was parsed from.
+    tag_offsets: Vec<usize>,
+    /// Whether inline formatting was dropped from the rich text that is currently parsed.
+    dropped_formatting: bool,
+    /// Footnote labels in the order of their first reference. The footnote number is the index
+    /// in this list plus one.
+    footnote_labels: Vec<String>,
+    footnote_definitions: HashMap<String, super::tag::OrderedListItem>,
+    /// The number after the last item of the previous list on the current level, which
+    /// a list that continues it starts at. Headings start over.
+    next_list_number: Option<u64>,
+}
+
+/// A part of the document that cannot be pushed, with the position in the source where it
+/// starts.
 #[derive(Debug, Error)]
-pub enum ParseError<'a> {
-    #[error("unexpected heading level {0}, Notion only supports heading levels up to 3")]
-    UnexpectedHeadingLevel(pulldown_cmark::HeadingLevel),
+pub enum ParseError {
+    #[error("unexpected heading level {level}, Notion only supports heading levels up to 3")]
+    UnexpectedHeadingLevel {
+        level: pulldown_cmark::HeadingLevel,
+        span: Span,
+    },
+
+    #[error("empty heading, headings must have some text")]
+    EmptyHeading { span: Span },
+
+    #[error("<details> is missing a matching </details>")]
+    UnclosedDetails { span: Span },
+
+    #[error("front matter is missing its closing `---`")]
+    UnterminatedFrontMatter { span: Span },
+
+    #[error("unimplemented tag: {tag}")]
+    UnimplementedTag { tag: &'static str, span: Span },
+
+    #[error("unimplemented markdown: {event}")]
+    UnimplementedEvent { event: &'static str, span: Span },
+
+    #[error("the document ends inside of a {tag}")]
+    UnexpectedEnd { tag: &'static str, span: Span },
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::UnexpectedHeadingLevel { span, .. }
+            | Self::EmptyHeading { span }
+            | Self::UnclosedDetails { span }
+            | Self::UnterminatedFrontMatter { span }
+            | Self::UnimplementedTag { span, .. }
+            | Self::UnimplementedEvent { span, .. }
+            | Self::UnexpectedEnd { span, .. } => *span,
+        }
+    }
+}
+
+/// The name of the kind of the tag, without its contents.
+fn tag_name(tag: &pulldown_cmark::Tag) -> &'static str {
+    match tag {
+        pulldown_cmark::Tag::Paragraph => "Paragraph",
+        pulldown_cmark::Tag::Heading(..) => "Heading",
+        pulldown_cmark::Tag::BlockQuote => "BlockQuote",
+        pulldown_cmark::Tag::CodeBlock(_) => "CodeBlock",
+        pulldown_cmark::Tag::List(_) => "List",
+        pulldown_cmark::Tag::Item => "Item",
+        pulldown_cmark::Tag::FootnoteDefinition(_) => "FootnoteDefinition",
+        pulldown_cmark::Tag::Table(_) => "Table",
+        pulldown_cmark::Tag::TableHead => "TableHead",
+        pulldown_cmark::Tag::TableRow => "TableRow",
+        pulldown_cmark::Tag::TableCell => "TableCell",
+        pulldown_cmark::Tag::Emphasis => "Emphasis",
+        pulldown_cmark::Tag::Strong => "Strong",
+        pulldown_cmark::Tag::Strikethrough => "Strikethrough",
+        pulldown_cmark::Tag::Link(..) => "Link",
+        pulldown_cmark::Tag::Image(..) => "Image",
+    }
+}
 
-    #[error("unimplemented tag")]
-    UnimplementedTag(pulldown_cmark::Tag<'a>),
+/// The name of the kind of a block-level event that has no Notion equivalent.
+fn event_name(event: &Event) -> &'static str {
+    match event {
+        Event::Start(tag) | Event::End(tag) => tag_name(tag),
+        Event::Text(_) => "Text",
+        Event::Code(_) => "Code",
+        Event::Html(_) => "Html",
+        Event::FootnoteReference(_) => "FootnoteReference",
+        Event::SoftBreak => "SoftBreak",
+        Event::HardBreak => "HardBreak",
+        Event::Rule => "Rule",
+        Event::TaskListMarker(_) => "TaskListMarker",
+    }
 }
 
-impl<'a, I> PulldownCMarkEventParser<Peekable<I>>
+impl<'a, I> PulldownCMarkEventParser<'a, std::iter::Map<I, fn(Event<'a>) -> OffsetEvent<'a>>>
 where
     I: Iterator<Item = Event<'a>>,
 {
-    pub fn new(event_iterator: I) -> Self {
+    /// A parser of events without their positions in the source. The offsets of the warnings
+    /// and tags are all 0, and the errors are all at 1:1.
+    pub fn new(event_iterator: I, options: ParseOptions) -> Self {
+        PulldownCMarkEventParser::with_offsets(
+            event_iterator.map((|event| (event, 0..0)) as fn(_) -> _),
+            "",
+            options,
+        )
+    }
+}
+
+impl<'a, I> PulldownCMarkEventParser<'a, I>
+where
+    I: Iterator<Item = OffsetEvent<'a>>,
+{
+    /// A parser of the events of [`pulldown_cmark::Parser::into_offset_iter`] for `source`,
+    /// which knows where in the source the warnings, the tags and the errors come from.
+    pub fn with_offsets(event_iterator: I, source: &'a str, options: ParseOptions) -> Self {
         Self {
             event_iterator: event_iterator.peekable(),
+            source,
+            options,
+            warnings: Vec::new(),
+            offset: 0,
+            warning_offsets: Vec::new(),
+            tag_offsets: Vec::new(),
+            dropped_formatting: false,
+            footnote_labels: Vec::new(),
+            footnote_definitions: HashMap::new(),
+            next_list_number: None,
         }
     }
 
-    pub fn parse(mut self) -> Result<Vec<super::tag::Tag>, ParseError<'a>> {
+    pub fn parse(&mut self) -> Result<Vec<super::tag::Tag>, ParseError> {
         let mut tags: Vec<super::tag::Tag> = Vec::new();
 
-        while let Some(event) = self.event_iterator.next() {
-            tags.push(self.parse_single_event(event)?);
+        while let Some(event) = self.next_event() {
+            let offset = self.offset;
+            if let Some(tag) = self.parse_single_event(event)? {
+                tags.push(tag);
+                self.tag_offsets.push(offset);
+            }
+        }
+        for tag in self.take_footnotes() {
+            tags.push(tag);
+            self.tag_offsets.push(self.offset);
         }
 
         Ok(tags)
     }
 
-    fn parse_single_event(&mut self, event: Event<'a>) -> Result<super::tag::Tag, ParseError<'a>> {
+    /// Parts of the parsed document that were converted lossily.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// The byte offsets in the source at which each of the [`Self::warnings`] was reported.
+    pub fn warning_offsets(&self) -> &[usize] {
+        &self.warning_offsets
+    }
+
+    /// The byte offsets in the source of the blocks that each of the parsed tags comes from.
+    /// The footnotes moved to the end of the document are at the end of the source.
+    pub fn tag_offsets(&self) -> &[usize] {
+        &self.tag_offsets
+    }
+
+    /// The next event, remembering where it starts in the source.
+    fn next_event(&mut self) -> Option<Event<'a>> {
+        let (event, range) = self.event_iterator.next()?;
+        self.offset = range.start;
+
+        Some(event)
+    }
+
+    /// The next event, when it matches the predicate.
+    fn next_event_if(&mut self, predicate: impl FnOnce(&Event<'a>) -> bool) -> Option<Event<'a>> {
+        let (event, range) = self.event_iterator.next_if(|(event, _)| predicate(event))?;
+        self.offset = range.start;
+
+        Some(event)
+    }
+
+    /// The position in the source of the last parsed event.
+    fn span(&self) -> Span {
+        Span::at(self.source, self.offset)
+    }
+
+    /// The next event inside of the tag, which the document must not end before.
+    fn next_event_inside(&mut self, tag: &'static str) -> Result<Event<'a>, ParseError> {
+        match self.next_event() {
+            Some(event) => Ok(event),
+            None => Err(ParseError::UnexpectedEnd {
+                tag,
+                span: self.span(),
+            }),
+        }
+    }
+
+    /// Consumes the end of the tag, which must be the next event.
+    fn parse_end(&mut self, tag: &'static str) -> Result<(), ParseError> {
+        match self.next_event_inside(tag)? {
+            Event::End(end) if tag_name(&end) == tag => Ok(()),
+            event => Err(ParseError::UnimplementedEvent {
+                event: event_name(&event),
+                span: self.span(),
+            }),
+        }
+    }
+
+    fn peek_event(&mut self) -> Option<&Event<'a>> {
+        self.event_iterator.peek().map(|(event, _)| event)
+    }
+
+    fn warn(&mut self, warning: Warning) {
+        self.warnings.push(warning);
+        self.warning_offsets.push(self.offset);
+    }
+
+    /// Parses the block that starts with the event.
+    /// Returns `None` for blocks that are skipped, like HTML comments.
+    fn parse_single_event(
+        &mut self,
+        event: Event<'a>,
+    ) -> Result<Option<super::tag::Tag>, ParseError> {
         match event {
             Event::Start(tag) => match tag {
-                pulldown_cmark::Tag::Heading(original_heading_level, None, _) => {
-                    self.parse_heading(original_heading_level)
+                pulldown_cmark::Tag::Heading(original_heading_level, id, classes) => {
+                    self.next_list_number = None;
+                    self.parse_heading(original_heading_level, id, classes)
+                        .map(Some)
                 }
-                pulldown_cmark::Tag::List(Some(_start_number)) => {
+                pulldown_cmark::Tag::List(Some(start)) => {
                     let mut items: Vec<super::tag::OrderedListItem> = Vec::new();
+                    let mut loose = false;
+                    let continues = self.options.merge_adjacent_lists
+                        && start != 1
+                        && self.next_list_number == Some(start);
 
                     while self
-                        .event_iterator
-                        .next_if_eq(&pulldown_cmark::Event::Start(pulldown_cmark::Tag::Item))
+                        .next_event_if(|event| {
+                            *event == pulldown_cmark::Event::Start(pulldown_cmark::Tag::Item)
+                        })
                         .is_some()
                     {
+                        // NOTE: the lists nested in an item are numbered on their own
+                        self.next_list_number = None;
+                        // NOTE: only the items of loose lists are wrapped in paragraphs
+                        loose |= self.peek_event()
+                            == Some(&Event::Start(pulldown_cmark::Tag::Paragraph));
                         items.push(self.parse_ordered_list_item()?);
                     }
 
-                    assert_eq!(
-                        self.event_iterator.next().expect("end of list"),
-                        Event::End(tag),
-                        "end of list tag"
-                    );
+                    self.parse_end("List")?;
+
+                    self.next_list_number = Some(start + items.len() as u64);
+                    if start != 1 && !continues {
+                        self.warn(Warning::ListStartNumberDropped {
+                            start,
+                            text: items
+                                .first()
+                                .map(|item| {
+                                    item.text
+                                        .iter()
+                                        .map(|rich_text| rich_text.text.as_str())
+                                        .collect()
+                                })
+                                .unwrap_or_default(),
+                        });
+                    }
 
-                    Ok(super::tag::Tag::OrderedList { items })
+                    Ok(Some(super::tag::Tag::OrderedList {
+                        items,
+                        loose,
+                        start,
+                        continues,
+                    }))
                 }
                 pulldown_cmark::Tag::Paragraph => {
-                    Ok(super::tag::Tag::Paragraph(self.parse_paragraph()))
+                    let paragraph = self.parse_paragraph()?;
+                    Ok(self.paragraph_tag(paragraph.text))
+                }
+                pulldown_cmark::Tag::BlockQuote => {
+                    // NOTE: the lists in the quote are numbered on their own
+                    let next_list_number = self.next_list_number.take();
+                    let quote = self.parse_quote();
+                    self.next_list_number = next_list_number;
+                    quote.map(Some)
                 }
-                tag => Err(ParseError::UnimplementedTag(tag)),
+                pulldown_cmark::Tag::CodeBlock(kind) => Ok(Some(self.parse_code_block(kind))),
+                pulldown_cmark::Tag::FootnoteDefinition(label) => {
+                    self.parse_footnote_definition(label.to_string())?;
+                    Ok(None)
+                }
+                tag => Err(ParseError::UnimplementedTag {
+                    tag: tag_name(&tag),
+                    span: self.span(),
+                }),
             },
-            Event::End(_) => {
-                unreachable!(
-                    "end events should be handled in start event handlers, found {event:#?}"
-                )
+            Event::Html(html) => self.parse_html_block(html),
+            // NOTE: the front matter is split from the source before parsing, so a delimiter
+            // at its start was never closed
+            Event::Rule if self.offset == 0 && FrontMatter::is_unterminated(self.source) => {
+                Err(ParseError::UnterminatedFrontMatter { span: self.span() })
             }
-            Event::Text(_) => {
-                unreachable!("text should be handled in start event handlers, found {event:#?}")
-            }
-            event => unimplemented!("unhandled event: {event:?}"),
+            Event::Rule => Ok(Some(super::tag::Tag::Divider)),
+            event => Err(ParseError::UnimplementedEvent {
+                event: event_name(&event),
+                span: self.span(),
+            }),
         }
     }
 
@@ -83,20 +393,44 @@ where
     fn parse_heading(
         &mut self,
         original_heading_level: pulldown_cmark::HeadingLevel,
-    ) -> Result<super::tag::Tag, ParseError<'a>> {
-        let heading_level = match original_heading_level {
-            pulldown_cmark::HeadingLevel::H1 => Ok(super::tag::HeadingLevel::H1),
-            pulldown_cmark::HeadingLevel::H2 => Ok(super::tag::HeadingLevel::H2),
-            pulldown_cmark::HeadingLevel::H3 => Ok(super::tag::HeadingLevel::H3),
-            _ => Err(ParseError::UnexpectedHeadingLevel(original_heading_level)),
+        id: Option<&str>,
+        classes: Vec<&str>,
+    ) -> Result<super::tag::Tag, ParseError> {
+        let span = self.span();
+        let shifted_level = original_heading_level as i16 + i16::from(self.options.heading_offset);
+        let heading_level = match shifted_level {
+            ..=3 => Ok(super::tag::HeadingLevel::clamped(shifted_level)),
+            _ if self.options.strict_headings => Err(ParseError::UnexpectedHeadingLevel {
+                level: (&super::tag::HeadingLevel::clamped(shifted_level)).into(),
+                span,
+            }),
+            _ => Ok(super::tag::HeadingLevel::H3),
         }?;
 
-        let text = self.parse_text();
-        assert!(!text.is_empty(), "empty heading");
+        let text = self.parse_text()?;
+        if text.is_empty() {
+            return Err(ParseError::EmptyHeading { span });
+        }
+
+        let plain_text = text
+            .iter()
+            .map(|rich_text| rich_text.text.as_str())
+            .collect();
+        if self.options.heading_offset != 0 && i16::from(heading_level.number()) != shifted_level {
+            self.warn(Warning::HeadingLevelClamped {
+                text: plain_text,
+                level: shifted_level,
+                clamped_level: heading_level.number(),
+            });
+        } else if shifted_level > 3 {
+            self.warn(Warning::HeadingLevelLowered {
+                original_level: original_heading_level,
+                text: plain_text,
+            });
+        }
 
         match self
-            .event_iterator
-            .next()
+            .next_event()
             .expect("unexpected end of events, expected end of heading")
         {
             Event::End(pulldown_cmark::Tag::Heading(..)) => {}
@@ -105,50 +439,85 @@ where
             ),
         }
 
+        if !classes.is_empty() {
+            self.warn(Warning::HeadingClassesDropped {
+                classes: classes.iter().map(ToString::to_string).collect(),
+                text: text
+                    .iter()
+                    .map(|rich_text| rich_text.text.as_str())
+                    .collect(),
+            });
+        }
+
         Ok(super::tag::Tag::Heading {
             level: heading_level,
             text,
+            id: id.map(ToString::to_string),
         })
     }
 
     /// Parses an ordered list item with its content.
     /// Assumes the start event for the list item was already consumed.
-    fn parse_ordered_list_item(&mut self) -> Result<super::tag::OrderedListItem, ParseError<'a>> {
-        let paragraph = match self
-            .event_iterator
-            .peek()
-            .expect("unexpected end of events, expected list item to have some content")
-        {
-            Event::Start(pulldown_cmark::Tag::Paragraph) => {
-                self.event_iterator
-                    .next()
+    fn parse_ordered_list_item(&mut self) -> Result<super::tag::OrderedListItem, ParseError> {
+        let paragraph = match self.peek_event() {
+            Some(Event::Start(pulldown_cmark::Tag::Paragraph)) => {
+                self.next_event()
                     .expect("the start of the paragraph was just peeked, so it must exist");
-                self.parse_paragraph()
+                self.parse_paragraph()?
             }
-            Event::Text(_) => super::tag::Paragraph {
-                text: self.parse_text(),
-            },
-
-            event => {
-                unreachable!(
-                    "list items must have paragraph or text immediately inside, found {event:#?}"
-                )
+            Some(event)
+                if Self::is_plain_text_event(event)
+                    || matches!(
+                        event,
+                        Event::Start(
+                            pulldown_cmark::Tag::Link(..) | pulldown_cmark::Tag::Image(..)
+                        )
+                    ) =>
+            {
+                super::tag::Paragraph {
+                    text: self.parse_text()?,
+                }
             }
+
+            // NOTE: empty items, or items that start with another block
+            _ => super::tag::Paragraph { text: Vec::new() },
         };
 
-        let end_item_event = Event::End(pulldown_cmark::Tag::Item);
         let mut children: Vec<super::tag::Tag> = Vec::new();
 
         loop {
-            let event = self
-                .event_iterator
-                .next()
-                .expect("abrupt end of events - the end item event should still appear");
-
-            if event == end_item_event {
-                break;
+            match self.peek_event() {
+                Some(Event::End(pulldown_cmark::Tag::Item)) => {
+                    self.next_event();
+                    break;
+                }
+                // NOTE: the text after a nested block in the item of a tight list is not
+                // wrapped in a paragraph. HTML on its own line is still a block.
+                Some(event)
+                    if (Self::is_plain_text_event(event) && !matches!(event, Event::Html(_)))
+                        || matches!(
+                            event,
+                            Event::Start(
+                                pulldown_cmark::Tag::Link(..) | pulldown_cmark::Tag::Image(..)
+                            )
+                        ) =>
+                {
+                    let text = self.parse_text()?;
+                    children.extend(self.paragraph_tag(text));
+                }
+                Some(_) => {
+                    let event = self
+                        .next_event()
+                        .expect("the event was just peeked, so it must exist");
+                    children.extend(self.parse_single_event(event)?);
+                }
+                None => {
+                    return Err(ParseError::UnexpectedEnd {
+                        tag: "Item",
+                        span: self.span(),
+                    })
+                }
             }
-            children.push(self.parse_single_event(event)?);
         }
 
         Ok(super::tag::OrderedListItem {
@@ -157,149 +526,1624 @@ where
         })
     }
 
-    /// Parses a markdown paragraph.
-    /// Assumes the Event::Start(Paragraph) was already consumed.
-    fn parse_paragraph(&mut self) -> super::tag::Paragraph {
-        let text = self.parse_text();
-        assert!(!text.is_empty(), "empty paragraph");
+    /// Parses a block quote with its content.
+    /// Assumes the Event::Start(BlockQuote) event was already consumed.
+    fn parse_quote(&mut self) -> Result<super::tag::Tag, ParseError> {
+        let mut text = if self
+            .next_event_if(|event| *event == Event::Start(pulldown_cmark::Tag::Paragraph))
+            .is_some()
+        {
+            self.parse_paragraph()?.text
+        } else {
+            Vec::new()
+        };
 
-        assert_eq!(
-            self.event_iterator.next(),
-            Some(Event::End(pulldown_cmark::Tag::Paragraph)),
-            "end of paragraph"
-        );
+        let mut children: Vec<super::tag::Tag> = Vec::new();
+
+        loop {
+            match self.next_event_inside("BlockQuote")? {
+                Event::End(pulldown_cmark::Tag::BlockQuote) => break,
+                event => children.extend(self.parse_single_event(event)?),
+            }
+        }
+
+        if self.options.flavor == Flavor::Obsidian {
+            if let Some(icon) = Self::take_callout_marker(&mut text) {
+                return Ok(super::tag::Tag::Callout {
+                    icon,
+                    text,
+                    children,
+                });
+            }
+        }
 
-        super::tag::Paragraph { text }
+        Ok(super::tag::Tag::Quote { text, children })
     }
 
-    /// Parses Event::Text until another type of event is encountered.{
-    fn parse_text(&mut self) -> Vec<super::tag::RichText> {
-        // TODO: handle text modifiers
-        // https://github.com/Gelio/notion-edit/issues/1
+    /// Removes the `[!type]` marker from the text of a block quote that is an Obsidian callout,
+    /// and returns the icon of the callout.
+    fn take_callout_marker(text: &mut Vec<super::tag::RichText>) -> Option<Option<String>> {
+        let first = text.first_mut()?;
+        if first.link.is_some() || first.mention.is_some() {
+            return None;
+        }
+        let (callout_type, rest) = obsidian::split_callout_marker(&first.text)?;
+        let icon = obsidian::callout_icon(callout_type).map(ToString::to_string);
 
-        let mut parsed_text = Vec::new();
-        while let Some(Event::Text(text)) = self.event_iterator.peek() {
-            parsed_text.push(super::tag::RichText {
-                text: text.to_string(),
-            });
+        first.text = rest.to_string();
+        if first.text.is_empty() {
+            text.remove(0);
+        }
 
-            // NOTE: consume the peeked event
-            self.event_iterator.next();
+        Some(icon)
+    }
+
+    /// Parses the content of a footnote definition and stores it until the end of the document.
+    /// Assumes the Event::Start(FootnoteDefinition) event was already consumed.
+    fn parse_footnote_definition(&mut self, label: String) -> Result<(), ParseError> {
+        let mut children: Vec<super::tag::Tag> = Vec::new();
+        loop {
+            match self.next_event_inside("FootnoteDefinition")? {
+                Event::End(pulldown_cmark::Tag::FootnoteDefinition(_)) => break,
+                event => children.extend(self.parse_single_event(event)?),
+            }
         }
 
-        parsed_text
+        let text = match children.first() {
+            Some(super::tag::Tag::Paragraph(_)) => match children.remove(0) {
+                super::tag::Tag::Paragraph(paragraph) => paragraph.text,
+                _ => unreachable!("the first child was just checked to be a paragraph"),
+            },
+            _ => Vec::new(),
+        };
+
+        self.footnote_number(&label);
+        self.footnote_definitions
+            .insert(label, super::tag::OrderedListItem { text, children });
+
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use pretty_assertions::assert_eq;
+    /// Returns the number of the footnote, assigning the next one on its first use.
+    fn footnote_number(&mut self, label: &str) -> usize {
+        let index = match self.footnote_labels.iter().position(|other| other == label) {
+            Some(index) => index,
+            None => {
+                self.footnote_labels.push(label.to_string());
+                self.footnote_labels.len() - 1
+            }
+        };
 
-    use crate::markdown::tag::{OrderedListItem, Paragraph, Tag};
+        index + 1
+    }
 
-    use super::*;
+    /// Builds the trailing section with the footnote definitions, numbered in the order
+    /// of their first use.
+    fn take_footnotes(&mut self) -> Vec<super::tag::Tag> {
+        if self.footnote_labels.is_empty() {
+            return Vec::new();
+        }
 
-    fn get_document_tags() -> Vec<Tag> {
-        vec![
-            Tag::Heading {
-                level: crate::markdown::tag::HeadingLevel::H1,
-                text: vec![crate::markdown::tag::RichText {
-                    text: "Summary".to_string(),
-                }],
-            },
-            Tag::OrderedList {
-                items: vec![
-                    OrderedListItem {
-                        text: vec![crate::markdown::tag::RichText {
-                            text: "Watch some videos".to_string(),
-                        }],
+        let items: Vec<_> = std::mem::take(&mut self.footnote_labels)
+            .into_iter()
+            .map(|label| match self.footnote_definitions.remove(&label) {
+                Some(item) => item,
+                None => {
+                    let text = vec![super::tag::RichText {
+                        text: format!("[^{label}]"),
+                        link: None,
+                        mention: None,
+                    }];
+                    self.warn(Warning::FootnoteNotDefined { label });
+                    super::tag::OrderedListItem {
+                        text,
                         children: Vec::new(),
-                    },
-                    OrderedListItem {
-                        text: vec![crate::markdown::tag::RichText {
-                            text: "Another list item".to_string(),
-                        }],
-                        children: vec![Tag::OrderedList {
-                            items: vec![OrderedListItem {
-                                text: vec![crate::markdown::tag::RichText {
-                                    text: "Second level list item".to_string(),
-                                }],
-                                children: vec![Tag::Paragraph(Paragraph {
-                                    text: vec![crate::markdown::tag::RichText {
-                                        text: "Second level item's extra description".to_string(),
-                                    }],
-                                })],
-                            }],
-                        }],
-                    },
-                ],
-            },
-            Tag::Heading {
-                level: crate::markdown::tag::HeadingLevel::H1,
-                text: vec![crate::markdown::tag::RichText {
-                    text: "Details".to_string(),
+                    }
+                }
+            })
+            .collect();
+        self.warnings
+            .push(Warning::FootnotesMovedToEnd { count: items.len() });
+
+        vec![
+            super::tag::Tag::Heading {
+                level: super::tag::HeadingLevel::H2,
+                text: vec![super::tag::RichText {
+                    text: FOOTNOTES_HEADING.to_string(),
+                    link: None,
+                    mention: None,
                 }],
+                id: None,
             },
-            Tag::Paragraph(Paragraph {
-                text: vec![crate::markdown::tag::RichText {
-                    text: "More description".to_string(),
-                }],
-            }),
+            super::tag::Tag::ordered_list(items),
         ]
     }
 
-    const SERIALIZED_DOCUMENT: &str = r"# Summary
+    /// Parses a fenced or indented code block.
+    /// Assumes the Event::Start(CodeBlock) event was already consumed.
+    fn parse_code_block(&mut self, kind: CodeBlockKind<'a>) -> super::tag::Tag {
+        let language = match kind {
+            CodeBlockKind::Fenced(info) => info.split_whitespace().next().unwrap_or("").to_string(),
+            CodeBlockKind::Indented => String::new(),
+        };
 
-1. Watch some videos
+        let mut text = String::new();
+        while let Some(Event::Text(text_part)) =
+            self.next_event_if(|event| matches!(event, Event::Text(_)))
+        {
+            text.push_str(&text_part);
+        }
+        // NOTE: the content of code blocks always ends with a newline
+        if text.ends_with('\n') {
+            text.pop();
+        }
 
-1. Another list item
-   
-   1. Second level list item
-      
-      Second level item's extra description
+        match self.next_event() {
+            Some(Event::End(pulldown_cmark::Tag::CodeBlock(_))) => {}
+            event => unreachable!(
+                "start code block should have a matching end code block event, found {event:#?}"
+            ),
+        }
 
-# Details
+        super::tag::Tag::Code { language, text }
+    }
 
-More description";
+    /// Parses an HTML block. Every line of the block is a separate event.
+    fn parse_html_block(
+        &mut self,
+        first_line: CowStr<'a>,
+    ) -> Result<Option<super::tag::Tag>, ParseError> {
+        let span = self.span();
+        let mut html = first_line.to_string();
+        // NOTE: consecutive HTML blocks are not separated by any event, so a comment followed
+        // by a table would be treated as one block. The `<summary>` ends the start of a toggle,
+        // so the `</details>` and the toggles after it are parsed on their own.
+        let is_complete = |html: &str| match classify_html(html) {
+            HtmlFragment::Comment | HtmlFragment::LineBreak => true,
+            HtmlFragment::DetailsStart { closed, .. } => {
+                closed || html.to_ascii_lowercase().contains("</summary>")
+            }
+            _ => false,
+        };
+        while !is_complete(&html) {
+            match self.next_event_if(|event| matches!(event, Event::Html(_))) {
+                Some(Event::Html(line)) => html.push_str(&line),
+                _ => break,
+            }
+        }
 
-    #[test]
-    fn parses_as_expected() {
-        let mut event_parser = pulldown_cmark::Parser::new(SERIALIZED_DOCUMENT);
-        let parsed_document = PulldownCMarkEventParser::new(&mut event_parser).parse();
+        match classify_html(&html) {
+            HtmlFragment::Comment => Ok(None),
+            HtmlFragment::LineBreak => Ok(self.empty_paragraph()),
+            HtmlFragment::DetailsStart { summary, closed } => {
+                let children = if closed {
+                    Vec::new()
+                } else {
+                    self.parse_details_content(span)?
+                };
 
-        assert_eq!(
-            parsed_document.unwrap(),
-            get_document_tags(),
-            "different parse result"
-        );
+                Ok(Some(super::tag::Tag::Toggle {
+                    text: if summary.is_empty() {
+                        Vec::new()
+                    } else {
+                        vec![super::tag::RichText {
+                            text: summary,
+                            link: None,
+                            mention: None,
+                        }]
+                    },
+                    children,
+                }))
+            }
+            HtmlFragment::Formatting | HtmlFragment::DetailsEnd | HtmlFragment::Other => {
+                let html = html.trim_end_matches('\n').to_string();
+                if self.options.skip_html {
+                    self.warn(Warning::HtmlSkipped { html });
+                    Ok(None)
+                } else {
+                    self.warnings
+                        .push(Warning::HtmlKeptAsCode { html: html.clone() });
+                    Ok(Some(super::tag::Tag::Code {
+                        language: "html".to_string(),
+                        text: html,
+                    }))
+                }
+            }
+        }
     }
 
-    #[test]
-    fn parses_larger_document() {
-        let mut event_parser = pulldown_cmark::Parser::new(
-            "# Summary
+    /// Parses the blocks inside `<details>` until the matching `</details>`.
+    /// Assumes the `<details>` and `<summary>` HTML at `span` was already consumed.
+    fn parse_details_content(&mut self, span: Span) -> Result<Vec<super::tag::Tag>, ParseError> {
+        let mut children = Vec::new();
+        loop {
+            match self.next_event() {
+                None => return Err(ParseError::UnclosedDetails { span }),
+                Some(Event::Html(html)) if classify_html(&html) == HtmlFragment::DetailsEnd => {
+                    return Ok(children)
+                }
+                Some(event) => children.extend(self.parse_single_event(event)?),
+            }
+        }
+    }
 
-1. Something to do
+    /// Parses a markdown paragraph.
+    /// Assumes the Event::Start(Paragraph) was already consumed.
+    fn parse_paragraph(&mut self) -> Result<super::tag::Paragraph, ParseError> {
+        let text = self.parse_text()?;
+        self.parse_end("Paragraph")?;
 
-1. Here
-   
-   1. Second level list item
-      
-      Second level list item’s paragraph
-      
-      1. Third level list
+        Ok(super::tag::Paragraph { text })
+    }
 
-## Subheading
+    /// Parses rich text (text, line breaks, inline formatting, and links) until another type
+    /// of event is encountered.
+    fn parse_text(&mut self) -> Result<Vec<super::tag::RichText>, ParseError> {
+        let mut parsed_text = Vec::new();
+        loop {
+            match self.peek_event() {
+                Some(event) if Self::is_plain_text_event(event) => {
+                    let text = self.parse_plain_text();
+                    match self.options.flavor {
+                        Flavor::Commonmark => Self::push_rich_text(
+                            &mut parsed_text,
+                            super::tag::RichText {
+                                text,
+                                link: None,
+                                mention: None,
+                            },
+                        ),
+                        Flavor::Obsidian => {
+                            for rich_text in obsidian::split_wiki_links(
+                                &text,
+                                &self.options.wiki_links,
+                                &mut self.warnings,
+                            ) {
+                                Self::push_rich_text(&mut parsed_text, rich_text);
+                            }
+                        }
+                    }
+                }
+                // NOTE: pulldown-cmark resolves the destinations of reference and shortcut
+                // links, and does not emit events for link definitions
+                Some(Event::Start(pulldown_cmark::Tag::Link(..))) => {
+                    let rich_text = self.parse_link()?;
+                    Self::push_rich_text(&mut parsed_text, rich_text);
+                }
+                Some(Event::Start(pulldown_cmark::Tag::Image(..))) => self.skip_image()?,
+                _ => break,
+            }
+        }
 
-1. Done something
+        if self.dropped_formatting {
+            self.dropped_formatting = false;
+            self.warn(Warning::InlineFormattingDropped {
+                text: parsed_text
+                    .iter()
+                    .map(|rich_text| rich_text.text.as_str())
+                    .collect(),
+            });
+        }
 
-1. Done something else
+        Ok(parsed_text)
+    }
 
-1. New point",
-        );
+    /// Parses a link with its text.
+    /// Assumes the Event::Start(Link) event was peeked, but not consumed.
+    /// The images in the text of the link, like badges, are skipped.
+    fn parse_link(&mut self) -> Result<super::tag::RichText, ParseError> {
+        let link = match self.next_event() {
+            Some(Event::Start(pulldown_cmark::Tag::Link(LinkType::Email, destination, _))) => {
+                format!("mailto:{destination}")
+            }
+            Some(Event::Start(pulldown_cmark::Tag::Link(_, destination, _))) => {
+                destination.to_string()
+            }
+            event => unreachable!("expected the start of a link, found {event:#?}"),
+        };
 
-        PulldownCMarkEventParser::new(&mut event_parser)
-            .parse()
-            .expect("successful parsing");
+        let mut text = String::new();
+        loop {
+            text.push_str(&self.parse_plain_text());
+            match self.peek_event() {
+                Some(Event::End(pulldown_cmark::Tag::Link(..))) => {
+                    self.next_event();
+                    break;
+                }
+                Some(Event::Start(pulldown_cmark::Tag::Image(..))) => self.skip_image()?,
+                Some(event) => {
+                    return Err(ParseError::UnimplementedEvent {
+                        event: event_name(event),
+                        span: self.span(),
+                    })
+                }
+                None => {
+                    return Err(ParseError::UnexpectedEnd {
+                        tag: "Link",
+                        span: self.span(),
+                    })
+                }
+            }
+        }
+
+        Ok(super::tag::RichText {
+            text,
+            link: Some(link),
+            mention: None,
+        })
+    }
+
+    /// Skips an image with its alt text.
+    /// Assumes the Event::Start(Image) event was peeked, but not consumed.
+    fn skip_image(&mut self) -> Result<(), ParseError> {
+        let url = match self.next_event() {
+            Some(Event::Start(pulldown_cmark::Tag::Image(_, url, _))) => url.to_string(),
+            event => unreachable!("expected the start of an image, found {event:#?}"),
+        };
+
+        loop {
+            match self.next_event() {
+                Some(Event::End(pulldown_cmark::Tag::Image(..))) => break,
+                Some(_) => {}
+                None => {
+                    return Err(ParseError::UnexpectedEnd {
+                        tag: "Image",
+                        span: self.span(),
+                    })
+                }
+            }
+        }
+
+        self.warn(Warning::ImageSkipped { url });
+        Ok(())
+    }
+
+    /// The paragraph with the text. Paragraphs that only contained skipped content (like
+    /// comments or images) are dropped, or kept empty.
+    fn paragraph_tag(&self, text: Vec<super::tag::RichText>) -> Option<super::tag::Tag> {
+        if text.is_empty() {
+            self.empty_paragraph()
+        } else {
+            Some(super::tag::Tag::Paragraph(super::tag::Paragraph { text }))
+        }
+    }
+
+    /// The paragraph that content without text is converted to, if any.
+    fn empty_paragraph(&self) -> Option<super::tag::Tag> {
+        self.options
+            .keep_empty_paragraphs
+            .then(|| super::tag::Tag::Paragraph(super::tag::Paragraph { text: Vec::new() }))
+    }
+
+    fn is_plain_text_event(event: &Event<'a>) -> bool {
+        matches!(
+            event,
+            Event::Text(_)
+                | Event::Code(_)
+                | Event::Html(_)
+                | Event::FootnoteReference(_)
+                | Event::SoftBreak
+                | Event::HardBreak
+                | Event::Start(
+                    pulldown_cmark::Tag::Strong
+                        | pulldown_cmark::Tag::Emphasis
+                        | pulldown_cmark::Tag::Strikethrough
+                )
+                | Event::End(
+                    pulldown_cmark::Tag::Strong
+                        | pulldown_cmark::Tag::Emphasis
+                        | pulldown_cmark::Tag::Strikethrough
+                )
+        )
+    }
+
+    /// Parses Event::Text and line breaks until another type of event is encountered.
+    /// Inline formatting is not supported yet, so only the formatted text is kept.
+    fn parse_plain_text(&mut self) -> String {
+        let mut text = String::new();
+        loop {
+            match self.peek_event() {
+                Some(Event::Text(text_part)) => text.push_str(text_part),
+                Some(Event::Code(code)) => {
+                    text.push_str(code);
+                    self.dropped_formatting = true;
+                }
+                Some(Event::SoftBreak) => {
+                    text.push(match self.options.newline_behavior {
+                        NewlineBehavior::Space => '\n',
+                        // NOTE: soft breaks are only a way to wrap long lines
+                        NewlineBehavior::HardBreak | NewlineBehavior::Backslash => ' ',
+                    });
+                }
+                Some(Event::HardBreak) => text.push('\n'),
+                Some(Event::FootnoteReference(label)) => {
+                    let label = label.to_string();
+                    text.push_str(&format!("[{}]", self.footnote_number(&label)));
+                }
+                Some(Event::Html(html)) => {
+                    let html = html.to_string();
+                    match classify_html(&html) {
+                        HtmlFragment::Comment => {}
+                        HtmlFragment::LineBreak => text.push('\n'),
+                        HtmlFragment::Formatting => self.dropped_formatting = true,
+                        _ if self.options.skip_html => self.warn(Warning::HtmlSkipped { html }),
+                        _ => {
+                            text.push_str(&html);
+                            self.warn(Warning::InlineHtmlKeptAsText { html });
+                        }
+                    }
+                }
+                Some(event) if Self::is_plain_text_event(event) => {
+                    // NOTE: the start or end of inline formatting
+                    self.dropped_formatting = true;
+                }
+                _ => break,
+            }
+
+            // NOTE: consume the peeked event
+            self.next_event();
+        }
+
+        text
+    }
+
+    /// Appends the rich text, merging it with the previous one if they share the same
+    /// formatting.
+    fn push_rich_text(
+        parsed_text: &mut Vec<super::tag::RichText>,
+        rich_text: super::tag::RichText,
+    ) {
+        if rich_text.text.is_empty() {
+            return;
+        }
+
+        match parsed_text.last_mut() {
+            Some(previous)
+                if previous.link == rich_text.link
+                    && previous.mention.is_none()
+                    && rich_text.mention.is_none() =>
+            {
+                previous.text.push_str(&rich_text.text)
+            }
+            _ => parsed_text.push(rich_text),
+        }
+    }
+}
+
+/// The kinds of HTML that are handled differently when converting to Notion.
+#[derive(Debug, PartialEq, Eq)]
+enum HtmlFragment {
+    /// `<!-- comment -->`, skipped silently.
+    Comment,
+    /// `<br>`
+    LineBreak,
+    /// `<u>` and `<span>`, which only change the formatting of the text inside.
+    Formatting,
+    /// `<details>` with an optional `<summary>`, which become a toggle block.
+    /// `closed` is set when the `</details>` is a part of the same fragment.
+    DetailsStart {
+        summary: String,
+        closed: bool,
+    },
+    DetailsEnd,
+    /// HTML without a Notion equivalent.
+    Other,
+}
+
+fn classify_html(html: &str) -> HtmlFragment {
+    let html = html.trim();
+    let lowercase_html = html.to_ascii_lowercase();
+
+    if html.starts_with("<!--") && html.ends_with("-->") {
+        return HtmlFragment::Comment;
+    }
+
+    let is_tag = |name: &str| {
+        lowercase_html
+            .strip_prefix('<')
+            .and_then(|rest| rest.strip_prefix(name))
+            .is_some_and(|rest| {
+                rest.ends_with('>')
+                    && !rest[..rest.len() - 1].contains('>')
+                    && (rest.starts_with(['>', '/']) || rest.starts_with(char::is_whitespace))
+            })
+    };
+
+    if is_tag("br") {
+        return HtmlFragment::LineBreak;
+    }
+    if is_tag("u") || is_tag("/u") || is_tag("span") || is_tag("/span") {
+        return HtmlFragment::Formatting;
+    }
+    if lowercase_html == "</details>" {
+        return HtmlFragment::DetailsEnd;
+    }
+
+    parse_details_start(html, &lowercase_html).unwrap_or(HtmlFragment::Other)
+}
+
+/// Parses `<details>` followed by an optional `<summary>` and an optional `</details>`.
+fn parse_details_start(html: &str, lowercase_html: &str) -> Option<HtmlFragment> {
+    let rest = lowercase_html.strip_prefix("<details")?;
+    if !(rest.starts_with('>') || rest.starts_with(char::is_whitespace)) {
+        return None;
+    }
+    // NOTE: the lowercase HTML has the same byte offsets, as only ASCII letters are changed
+    let mut offset = lowercase_html.find('>')? + 1;
+    let skip_whitespace = |offset: usize| {
+        offset + lowercase_html[offset..].len() - lowercase_html[offset..].trim_start().len()
+    };
+    offset = skip_whitespace(offset);
+
+    let mut summary = String::new();
+    if lowercase_html[offset..].starts_with("<summary>") {
+        let summary_start = offset + "<summary>".len();
+        let summary_end = summary_start + lowercase_html[summary_start..].find("</summary>")?;
+        summary = unescape_html(html[summary_start..summary_end].trim());
+        offset = skip_whitespace(summary_end + "</summary>".len());
+    }
+
+    match &lowercase_html[offset..] {
+        "" => Some(HtmlFragment::DetailsStart {
+            summary,
+            closed: false,
+        }),
+        "</details>" => Some(HtmlFragment::DetailsStart {
+            summary,
+            closed: true,
+        }),
+        _ => None,
+    }
+}
+
+fn unescape_html(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::markdown::tag::{OrderedListItem, Paragraph, RichText, Tag};
+
+    use super::*;
+
+    fn get_document_tags() -> Vec<Tag> {
+        vec![
+            Tag::Heading {
+                level: crate::markdown::tag::HeadingLevel::H1,
+                text: vec![crate::markdown::tag::RichText {
+                    text: "Summary".to_string(),
+                    link: None,
+                    mention: None,
+                }],
+                id: None,
+            },
+            Tag::OrderedList {
+                items: vec![
+                    OrderedListItem {
+                        text: vec![crate::markdown::tag::RichText {
+                            text: "Watch some videos".to_string(),
+                            link: None,
+                            mention: None,
+                        }],
+                        children: Vec::new(),
+                    },
+                    OrderedListItem {
+                        text: vec![crate::markdown::tag::RichText {
+                            text: "Another list item".to_string(),
+                            link: None,
+                            mention: None,
+                        }],
+                        children: vec![Tag::OrderedList {
+                            items: vec![OrderedListItem {
+                                text: vec![crate::markdown::tag::RichText {
+                                    text: "Second level list item".to_string(),
+                                    link: None,
+                                    mention: None,
+                                }],
+                                children: vec![Tag::Paragraph(Paragraph {
+                                    text: vec![crate::markdown::tag::RichText {
+                                        text: "Second level item's extra description".to_string(),
+                                        link: None,
+                                        mention: None,
+                                    }],
+                                })],
+                            }],
+                            loose: true,
+                            start: 1,
+                            continues: false,
+                        }],
+                    },
+                ],
+                loose: true,
+                start: 1,
+                continues: false,
+            },
+            Tag::Heading {
+                level: crate::markdown::tag::HeadingLevel::H1,
+                text: vec![crate::markdown::tag::RichText {
+                    text: "Details".to_string(),
+                    link: None,
+                    mention: None,
+                }],
+                id: None,
+            },
+            Tag::Paragraph(Paragraph {
+                text: vec![crate::markdown::tag::RichText {
+                    text: "More description".to_string(),
+                    link: None,
+                    mention: None,
+                }],
+            }),
+        ]
+    }
+
+    const SERIALIZED_DOCUMENT: &str = r"# Summary
+
+1. Watch some videos
+
+1. Another list item
+   
+   1. Second level list item
+      
+      Second level item's extra description
+
+# Details
+
+More description";
+
+    #[test]
+    fn parses_as_expected() {
+        let mut event_parser = pulldown_cmark::Parser::new(SERIALIZED_DOCUMENT);
+        let parsed_document =
+            PulldownCMarkEventParser::new(&mut event_parser, Default::default()).parse();
+
+        assert_eq!(
+            parsed_document.unwrap(),
+            get_document_tags(),
+            "different parse result"
+        );
+    }
+
+    #[test]
+    fn parses_larger_document() {
+        let mut event_parser = pulldown_cmark::Parser::new(
+            "# Summary
+
+1. Something to do
+
+1. Here
+   
+   1. Second level list item
+      
+      Second level list item’s paragraph
+      
+      1. Third level list
+
+## Subheading
+
+1. Done something
+
+1. Done something else
+
+1. New point",
+        );
+
+        PulldownCMarkEventParser::new(&mut event_parser, Default::default())
+            .parse()
+            .expect("successful parsing");
+    }
+
+    const DEEP_HEADINGS_DOCUMENT: &str = "#### Deep\n\n###### Deeper";
+
+    #[test]
+    fn maps_deep_headings_to_h3_with_warnings() {
+        let mut parser = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new(DEEP_HEADINGS_DOCUMENT),
+            Default::default(),
+        );
+        let tags = parser.parse().expect("successful parsing");
+
+        assert_eq!(
+            tags,
+            vec![
+                Tag::Heading {
+                    level: crate::markdown::tag::HeadingLevel::H3,
+                    text: vec![crate::markdown::tag::RichText {
+                        text: "Deep".to_string(),
+                        link: None,
+                        mention: None,
+                    }],
+                    id: None,
+                },
+                Tag::Heading {
+                    level: crate::markdown::tag::HeadingLevel::H3,
+                    text: vec![crate::markdown::tag::RichText {
+                        text: "Deeper".to_string(),
+                        link: None,
+                        mention: None,
+                    }],
+                    id: None,
+                },
+            ]
+        );
+        assert_eq!(
+            parser.warnings(),
+            [
+                Warning::HeadingLevelLowered {
+                    original_level: pulldown_cmark::HeadingLevel::H4,
+                    text: "Deep".to_string(),
+                },
+                Warning::HeadingLevelLowered {
+                    original_level: pulldown_cmark::HeadingLevel::H6,
+                    text: "Deeper".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_deep_headings_in_strict_mode() {
+        let result = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new(DEEP_HEADINGS_DOCUMENT),
+            ParseOptions {
+                strict_headings: true,
+                ..Default::default()
+            },
+        )
+        .parse();
+
+        assert!(matches!(
+            result,
+            Err(ParseError::UnexpectedHeadingLevel {
+                level: pulldown_cmark::HeadingLevel::H4,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn shifts_headings_by_the_heading_offset() {
+        let heading = |level, text: &str| Tag::Heading {
+            level,
+            text: vec![crate::markdown::tag::RichText {
+                text: text.to_string(),
+                link: None,
+                mention: None,
+            }],
+            id: None,
+        };
+        let mut parser = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new("# Title\n\n## Summary\n\n#### Details\n"),
+            ParseOptions {
+                heading_offset: -1,
+                ..Default::default()
+            },
+        );
+        let tags = parser.parse().expect("successful parsing");
+
+        assert_eq!(
+            tags,
+            vec![
+                heading(crate::markdown::tag::HeadingLevel::H1, "Title"),
+                heading(crate::markdown::tag::HeadingLevel::H1, "Summary"),
+                heading(crate::markdown::tag::HeadingLevel::H3, "Details"),
+            ]
+        );
+        assert_eq!(
+            parser.warnings(),
+            [Warning::HeadingLevelClamped {
+                text: "Title".to_string(),
+                level: 0,
+                clamped_level: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn clamps_shifted_headings_to_the_notion_headings() {
+        let mut parser = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new("## Summary\n"),
+            ParseOptions {
+                heading_offset: 2,
+                ..Default::default()
+            },
+        );
+        parser.parse().expect("successful parsing");
+
+        assert_eq!(
+            parser.warnings(),
+            [Warning::HeadingLevelClamped {
+                text: "Summary".to_string(),
+                level: 4,
+                clamped_level: 3,
+            }]
+        );
+    }
+
+    fn parse_with_warnings(document: &str) -> (Vec<Tag>, Vec<Warning>) {
+        let mut parser = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new(document),
+            Default::default(),
+        );
+        let tags = parser.parse().expect("successful parsing");
+
+        (tags, parser.warnings().to_vec())
+    }
+
+    fn get_heading(text: Vec<crate::markdown::tag::RichText>) -> Tag {
+        Tag::Heading {
+            level: crate::markdown::tag::HeadingLevel::H1,
+            text,
+            id: None,
+        }
+    }
+
+    #[test]
+    fn parses_headings_with_formatting() {
+        let (tags, warnings) = parse_with_warnings("# **Bold** title");
+
+        assert_eq!(
+            tags,
+            vec![get_heading(vec![crate::markdown::tag::RichText {
+                text: "Bold title".to_string(),
+                link: None,
+                mention: None,
+            }])]
+        );
+        assert_eq!(
+            warnings,
+            vec![Warning::InlineFormattingDropped {
+                text: "Bold title".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_headings_with_links() {
+        let (tags, warnings) = parse_with_warnings("# [Linked](https://example.com) title");
+
+        assert_eq!(
+            tags,
+            vec![get_heading(vec![
+                crate::markdown::tag::RichText {
+                    text: "Linked".to_string(),
+                    link: Some("https://example.com".to_string()),
+                    mention: None,
+                },
+                crate::markdown::tag::RichText {
+                    text: " title".to_string(),
+                    link: None,
+                    mention: None,
+                },
+            ])]
+        );
+        assert_eq!(warnings, Vec::new());
+    }
+
+    #[test]
+    fn skips_images_in_links() {
+        let (tags, warnings) = parse_with_warnings(
+            "[![build](badge.svg)](https://example.com/ci) [**Docs** ![logo](logo.svg)](https://example.com/docs)",
+        );
+
+        assert_eq!(
+            tags,
+            vec![Tag::Paragraph(crate::markdown::tag::Paragraph {
+                text: vec![
+                    crate::markdown::tag::RichText {
+                        text: " ".to_string(),
+                        link: None,
+                        mention: None,
+                    },
+                    crate::markdown::tag::RichText {
+                        text: "Docs ".to_string(),
+                        link: Some("https://example.com/docs".to_string()),
+                        mention: None,
+                    },
+                ]
+            })]
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                Warning::ImageSkipped {
+                    url: "badge.svg".to_string()
+                },
+                Warning::ImageSkipped {
+                    url: "logo.svg".to_string()
+                },
+                Warning::InlineFormattingDropped {
+                    text: " Docs ".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_headings_with_only_code() {
+        let (tags, _warnings) = parse_with_warnings("# `main.rs`");
+
+        assert_eq!(
+            tags,
+            vec![get_heading(vec![crate::markdown::tag::RichText {
+                text: "main.rs".to_string(),
+                link: None,
+                mention: None,
+            }])]
+        );
+    }
+
+    #[test]
+    fn empty_heading_is_an_error() {
+        let result =
+            PulldownCMarkEventParser::new(pulldown_cmark::Parser::new("#\n"), Default::default())
+                .parse();
+
+        assert!(matches!(result, Err(ParseError::EmptyHeading { .. })));
+    }
+
+    #[test]
+    fn parses_setext_headings() {
+        let (tags, _warnings) = parse_with_warnings("Title\n=====\n\nSubtitle\n--------");
+
+        assert_eq!(
+            tags,
+            vec![
+                get_heading(vec![crate::markdown::tag::RichText {
+                    text: "Title".to_string(),
+                    link: None,
+                    mention: None,
+                }]),
+                Tag::Heading {
+                    level: crate::markdown::tag::HeadingLevel::H2,
+                    text: vec![crate::markdown::tag::RichText {
+                        text: "Subtitle".to_string(),
+                        link: None,
+                        mention: None,
+                    }],
+                    id: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_reference_and_shortcut_links() {
+        let document = "See [the docs][docs], [the API][api] and [Notion].
+
+[docs]: https://example.com/docs
+[api]: https://example.com/api \"API reference\"
+[Notion]: https://www.notion.so
+";
+        let mut parser = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext(document, pulldown_cmark_options()),
+            Default::default(),
+        );
+        let tags = parser.parse().expect("successful parsing");
+
+        let rich_text = |text: &str, link: Option<&str>| crate::markdown::tag::RichText {
+            text: text.to_string(),
+            link: link.map(ToString::to_string),
+            mention: None,
+        };
+        assert_eq!(
+            tags,
+            vec![Tag::Paragraph(Paragraph {
+                text: vec![
+                    rich_text("See ", None),
+                    rich_text("the docs", Some("https://example.com/docs")),
+                    rich_text(", ", None),
+                    rich_text("the API", Some("https://example.com/api")),
+                    rich_text(" and ", None),
+                    rich_text("Notion", Some("https://www.notion.so")),
+                    rich_text(".", None),
+                ]
+            })]
+        );
+        assert!(parser.warnings().is_empty());
+    }
+
+    const DOCUMENT_WITH_EMPTY_CONTENT: &str = "Text
+
+
+
+![diagram](https://example.com/diagram.png)
+
+<br>
+
+1.
+1. Item
+
+End";
+
+    fn parse_document_with_empty_content(keep_empty_paragraphs: bool) -> (Vec<Tag>, Vec<Warning>) {
+        let mut parser = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext(DOCUMENT_WITH_EMPTY_CONTENT, pulldown_cmark_options()),
+            ParseOptions {
+                keep_empty_paragraphs,
+                ..Default::default()
+            },
+        );
+        let tags = parser.parse().expect("successful parsing");
+
+        (tags, parser.warnings().to_vec())
+    }
+
+    fn paragraph(text: &str) -> Tag {
+        Tag::Paragraph(Paragraph {
+            text: if text.is_empty() {
+                Vec::new()
+            } else {
+                vec![crate::markdown::tag::RichText {
+                    text: text.to_string(),
+                    link: None,
+                    mention: None,
+                }]
+            },
+        })
+    }
+
+    fn list_with_an_empty_item() -> Tag {
+        Tag::ordered_list(vec![
+            OrderedListItem {
+                text: Vec::new(),
+                children: Vec::new(),
+            },
+            OrderedListItem {
+                text: vec![crate::markdown::tag::RichText {
+                    text: "Item".to_string(),
+                    link: None,
+                    mention: None,
+                }],
+                children: Vec::new(),
+            },
+        ])
+    }
+
+    #[test]
+    fn skips_empty_content() {
+        let (tags, warnings) = parse_document_with_empty_content(false);
+
+        assert_eq!(
+            tags,
+            vec![
+                paragraph("Text"),
+                list_with_an_empty_item(),
+                paragraph("End")
+            ]
+        );
+        assert_eq!(
+            warnings,
+            vec![Warning::ImageSkipped {
+                url: "https://example.com/diagram.png".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn keeps_empty_paragraphs() {
+        let (tags, _) = parse_document_with_empty_content(true);
+
+        assert_eq!(
+            tags,
+            vec![
+                paragraph("Text"),
+                paragraph(""),
+                paragraph(""),
+                list_with_an_empty_item(),
+                paragraph("End")
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_text_after_nested_blocks_in_tight_lists() {
+        let (tags, warnings) = parse_with_warnings("1. Item\n   ```\n   code\n   ```\n   Tail\n");
+
+        assert_eq!(
+            tags,
+            vec![Tag::OrderedList {
+                items: vec![OrderedListItem {
+                    text: vec![RichText {
+                        text: "Item".to_string(),
+                        link: None,
+                        mention: None,
+                    }],
+                    children: vec![
+                        Tag::Code {
+                            language: String::new(),
+                            text: "code".to_string(),
+                        },
+                        paragraph("Tail"),
+                    ],
+                }],
+                loose: false,
+                start: 1,
+                continues: false,
+            }]
+        );
+        assert_eq!(warnings, Vec::new());
+    }
+
+    #[test]
+    fn unclosed_items_are_an_error() {
+        let events = vec![
+            Event::Start(pulldown_cmark::Tag::List(Some(1))),
+            Event::Start(pulldown_cmark::Tag::Item),
+            Event::Text("Item".into()),
+        ];
+        let mut parser = PulldownCMarkEventParser::new(events.into_iter(), Default::default());
+
+        assert!(matches!(
+            parser.parse(),
+            Err(ParseError::UnexpectedEnd { tag: "Item", .. })
+        ));
+    }
+
+    #[test]
+    fn converts_smart_punctuation() {
+        let options = ParseOptions {
+            smart_punctuation: true,
+            ..Default::default()
+        };
+        let mut parser = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext(
+                r#"It's "quoted" -- with dashes --- and ellipses..."#,
+                options.pulldown_cmark_options(),
+            ),
+            options,
+        );
+        let tags = parser.parse().expect("successful parsing");
+
+        assert_eq!(
+            tags,
+            vec![Tag::Paragraph(Paragraph {
+                text: vec![crate::markdown::tag::RichText {
+                    text: "It’s “quoted” – with dashes — and ellipses…".to_string(),
+                    link: None,
+                    mention: None,
+                }]
+            })]
+        );
+    }
+
+    #[test]
+    fn keeps_heading_ids_and_drops_classes() {
+        let mut parser = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext(
+                "# Rollout {#rollout .wide .centered}",
+                pulldown_cmark_options(),
+            ),
+            Default::default(),
+        );
+        let tags = parser.parse().expect("successful parsing");
+
+        assert_eq!(
+            tags,
+            vec![Tag::Heading {
+                level: crate::markdown::tag::HeadingLevel::H1,
+                text: vec![crate::markdown::tag::RichText {
+                    text: "Rollout".to_string(),
+                    link: None,
+                    mention: None,
+                }],
+                id: Some("rollout".to_string()),
+            }]
+        );
+        assert_eq!(
+            parser.warnings(),
+            [Warning::HeadingClassesDropped {
+                classes: vec!["wide".to_string(), "centered".to_string()],
+                text: "Rollout".to_string(),
+            }]
+        );
+    }
+
+    fn get_plain_paragraph(text: &str) -> Tag {
+        Tag::Paragraph(Paragraph {
+            text: vec![crate::markdown::tag::RichText {
+                text: text.to_string(),
+                link: None,
+                mention: None,
+            }],
+        })
+    }
+
+    #[test]
+    fn classifies_html_fragments() {
+        let cases = [
+            ("<!-- a comment -->", HtmlFragment::Comment),
+            ("<!--\nmultiline\n-->\n", HtmlFragment::Comment),
+            ("<br>", HtmlFragment::LineBreak),
+            ("<BR />", HtmlFragment::LineBreak),
+            ("<u>", HtmlFragment::Formatting),
+            ("</u>", HtmlFragment::Formatting),
+            ("<span style=\"color: red\">", HtmlFragment::Formatting),
+            ("</span>", HtmlFragment::Formatting),
+            ("</details>\n", HtmlFragment::DetailsEnd),
+            (
+                "<details>\n<summary>Q &amp; A</summary>\n",
+                HtmlFragment::DetailsStart {
+                    summary: "Q & A".to_string(),
+                    closed: false,
+                },
+            ),
+            (
+                "<details open><summary>Title</summary></details>",
+                HtmlFragment::DetailsStart {
+                    summary: "Title".to_string(),
+                    closed: true,
+                },
+            ),
+            ("<details>\nRaw content\n</details>", HtmlFragment::Other),
+            ("<bread>", HtmlFragment::Other),
+            ("<u>underlined</u>", HtmlFragment::Other),
+            ("<table><tr><td>1</td></tr></table>", HtmlFragment::Other),
+        ];
+
+        for (html, expected) in cases {
+            assert_eq!(classify_html(html), expected, "classification of {html:?}");
+        }
+    }
+
+    #[test]
+    fn handles_inline_html() {
+        let (tags, warnings) = parse_with_warnings(
+            "Line<br>break <!-- hidden --><u>underlined</u> and <kbd>Ctrl</kbd>",
+        );
+
+        assert_eq!(
+            tags,
+            vec![get_plain_paragraph(
+                "Line\nbreak underlined and <kbd>Ctrl</kbd>"
+            )]
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                Warning::InlineHtmlKeptAsText {
+                    html: "<kbd>".to_string()
+                },
+                Warning::InlineHtmlKeptAsText {
+                    html: "</kbd>".to_string()
+                },
+                Warning::InlineFormattingDropped {
+                    text: "Line\nbreak underlined and <kbd>Ctrl</kbd>".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn html_blocks_become_code_blocks() {
+        let (tags, warnings) = parse_with_warnings(
+            "<!-- TODO -->\n\n<table>\n  <tr><td>1</td></tr>\n</table>\n\nAfter",
+        );
+
+        let html = "<table>\n  <tr><td>1</td></tr>\n</table>";
+        assert_eq!(
+            tags,
+            vec![
+                Tag::Code {
+                    language: "html".to_string(),
+                    text: html.to_string(),
+                },
+                get_plain_paragraph("After"),
+            ]
+        );
+        assert_eq!(
+            warnings,
+            vec![Warning::HtmlKeptAsCode {
+                html: html.to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn skips_html_when_requested() {
+        let mut parser = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new("<table></table>\n\nPress <kbd>Enter</kbd>"),
+            ParseOptions {
+                skip_html: true,
+                ..Default::default()
+            },
+        );
+        let tags = parser.parse().expect("successful parsing");
+
+        assert_eq!(tags, vec![get_plain_paragraph("Press Enter")]);
+        assert_eq!(
+            parser.warnings(),
+            [
+                Warning::HtmlSkipped {
+                    html: "<table></table>".to_string()
+                },
+                Warning::HtmlSkipped {
+                    html: "<kbd>".to_string()
+                },
+                Warning::HtmlSkipped {
+                    html: "</kbd>".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_details_into_toggles() {
+        let (tags, warnings) =
+            parse_with_warnings("<details>\n<summary>More</summary>\n\nHidden text\n\n</details>");
+
+        assert_eq!(
+            tags,
+            vec![Tag::Toggle {
+                text: vec![crate::markdown::tag::RichText {
+                    text: "More".to_string(),
+                    link: None,
+                    mention: None,
+                }],
+                children: vec![get_plain_paragraph("Hidden text")],
+            }]
+        );
+        assert_eq!(warnings, Vec::new());
+    }
+
+    #[test]
+    fn parses_adjacent_empty_toggles() {
+        let toggle = |summary: &str| Tag::Toggle {
+            text: vec![RichText {
+                text: summary.to_string(),
+                link: None,
+                mention: None,
+            }],
+            children: Vec::new(),
+        };
+
+        let (tags, warnings) = parse_with_warnings(
+            "<details>\n<summary>First</summary>\n\n</details>\n\n<details>\n<summary>Second</summary>\n\n</details>",
+        );
+
+        assert_eq!(tags, vec![toggle("First"), toggle("Second")]);
+        assert_eq!(warnings, Vec::new());
+    }
+
+    #[test]
+    fn unclosed_details_is_an_error() {
+        let result = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new("<details>\n\nNever closed"),
+            Default::default(),
+        )
+        .parse();
+
+        assert!(matches!(result, Err(ParseError::UnclosedDetails { .. })));
+    }
+
+    fn parse_error(source: &str, options: pulldown_cmark::Options) -> ParseError {
+        PulldownCMarkEventParser::with_offsets(
+            pulldown_cmark::Parser::new_ext(source, options).into_offset_iter(),
+            source,
+            ParseOptions {
+                strict_headings: true,
+                ..Default::default()
+            },
+        )
+        .parse()
+        .unwrap_err()
+    }
+
+    #[test]
+    fn unterminated_front_matter_is_an_error() {
+        let error = parse_error("---\ntitle: Notes\n\n# Notes\n", pulldown_cmark_options());
+
+        assert!(matches!(error, ParseError::UnterminatedFrontMatter { .. }));
+        assert_eq!(error.span(), Span { line: 1, column: 1 });
+    }
+
+    #[test]
+    fn errors_are_at_their_line_and_column() {
+        const DOCUMENT: &str = "# Zażółć gęślą jaźń\n\nŻółw 🐢 idzie — powoli…\n\n> | a | b |\n> |---|---|\n> | 1 | 2 |\n";
+
+        let error = parse_error(
+            DOCUMENT,
+            pulldown_cmark_options() | pulldown_cmark::Options::ENABLE_TABLES,
+        );
+
+        assert_eq!(error.span(), Span { line: 5, column: 3 });
+        assert_eq!(
+            format!("notes.md:{}: {error}", error.span()),
+            "notes.md:5:3: unimplemented tag: Table"
+        );
+
+        let error = parse_error("Żółw 🐢\n\n> - jaźń\n", pulldown_cmark_options());
+        assert_eq!(
+            format!("notes.md:{}: {error}", error.span()),
+            "notes.md:3:3: unimplemented tag: List"
+        );
+    }
+
+    #[test]
+    fn errors_after_multi_byte_text_on_the_same_line() {
+        let options = pulldown_cmark_options();
+
+        assert_eq!(
+            parse_error("Zażółć\n\n> 🐢 quote\n>\n> #### Deep\n", options).span(),
+            Span { line: 5, column: 3 }
+        );
+        assert_eq!(
+            parse_error("1. ż\n2. ł\n\n   > #\n", options).span(),
+            Span { line: 4, column: 6 }
+        );
+        assert_eq!(
+            parse_error(
+                "Żółw\n\n<details><summary>Jaźń</summary>\n\nNever closed",
+                options
+            )
+            .span(),
+            Span { line: 3, column: 1 }
+        );
+    }
+
+    #[test]
+    fn html_never_panics() {
+        let documents = [
+            "<!-- only a comment -->",
+            "<span></span>",
+            "# <!-- empty heading -->",
+            "1. <div>\n   list item\n   </div>",
+            "> <details>\n> <summary>Quoted</summary>\n>\n> </details>",
+            "</details>",
+            "<details>\n<summary>Unclosed summary\n\n</details>",
+            "<br>\n\n<u>\n\ntext\n\n</u>",
+            "Text with <a href=\"https://example.com\">a link</a>",
+            "<script>alert(1)</script>",
+        ];
+
+        for document in documents {
+            let _ = PulldownCMarkEventParser::new(
+                pulldown_cmark::Parser::new_ext(document, pulldown_cmark_options()),
+                Default::default(),
+            )
+            .parse();
+        }
+    }
+
+    #[test]
+    fn moves_footnotes_to_the_end() {
+        let mut parser = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext(
+                "Claim[^source], another[^note], again[^source].\n\n\
+                 [^note]: A side note.\n\n\
+                 [^source]: The source.\n\n\
+                 After",
+                pulldown_cmark_options(),
+            ),
+            Default::default(),
+        );
+        let tags = parser.parse().expect("successful parsing");
+
+        assert_eq!(
+            tags,
+            vec![
+                get_plain_paragraph("Claim[1], another[2], again[1]."),
+                get_plain_paragraph("After"),
+                Tag::Heading {
+                    level: crate::markdown::tag::HeadingLevel::H2,
+                    text: vec![crate::markdown::tag::RichText {
+                        text: FOOTNOTES_HEADING.to_string(),
+                        link: None,
+                        mention: None,
+                    }],
+                    id: None,
+                },
+                Tag::OrderedList {
+                    items: ["The source.", "A side note."]
+                        .into_iter()
+                        .map(|text| OrderedListItem {
+                            text: vec![crate::markdown::tag::RichText {
+                                text: text.to_string(),
+                                link: None,
+                                mention: None,
+                            }],
+                            children: Vec::new(),
+                        })
+                        .collect(),
+                    loose: false,
+                    start: 1,
+                    continues: false,
+                },
+            ]
+        );
+        assert_eq!(
+            parser.warnings(),
+            [Warning::FootnotesMovedToEnd { count: 2 }]
+        );
+    }
+
+    #[test]
+    fn warns_about_undefined_footnotes() {
+        let mut parser = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext(
+                "Text[^a].\n\n[^b]: Defined.",
+                pulldown_cmark_options(),
+            ),
+            Default::default(),
+        );
+        let tags = parser.parse().expect("successful parsing");
+
+        assert_eq!(tags[0], get_plain_paragraph("Text[1]."));
+        assert_eq!(
+            tags[2],
+            Tag::OrderedList {
+                items: ["[^a]", "Defined."]
+                    .into_iter()
+                    .map(|text| OrderedListItem {
+                        text: vec![crate::markdown::tag::RichText {
+                            text: text.to_string(),
+                            link: None,
+                            mention: None,
+                        }],
+                        children: Vec::new(),
+                    })
+                    .collect(),
+                loose: false,
+                start: 1,
+                continues: false,
+            }
+        );
+        assert_eq!(
+            parser.warnings(),
+            [
+                Warning::FootnoteNotDefined {
+                    label: "a".to_string()
+                },
+                Warning::FootnotesMovedToEnd { count: 2 }
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_about_list_start_numbers() {
+        let (_, warnings) = parse_with_warnings("3. Third\n4. Fourth\n\nBetween\n\n1. First");
+
+        assert_eq!(
+            warnings,
+            vec![Warning::ListStartNumberDropped {
+                start: 3,
+                text: "Third".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn accepts_continued_list_numbers_when_merging_adjacent_lists() {
+        let document = "1. First\n2. Second\n\nBetween\n\n3. Third\n\n## Next\n\n4. Fourth";
+        let mut parser = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new(document),
+            ParseOptions {
+                merge_adjacent_lists: true,
+                ..Default::default()
+            },
+        );
+
+        let tags = parser.parse().expect("successful parsing");
+
+        let continues: Vec<_> = tags
+            .iter()
+            .filter_map(|tag| match tag {
+                Tag::OrderedList { continues, .. } => Some(*continues),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(continues, vec![false, true, false]);
+        assert_eq!(
+            parser.warnings(),
+            [Warning::ListStartNumberDropped {
+                start: 4,
+                text: "Fourth".to_string()
+            }],
+            "lists after a heading start over"
+        );
     }
 }
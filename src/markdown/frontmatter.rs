@@ -0,0 +1,410 @@
+/// The file in the page's state directory that keeps the front matter of the last push,
+/// so that fetching the page re-emits it.
+pub const FRONT_MATTER_STATE_FILE: &str = "front-matter.md";
+/// The front matter key with the ID of the Notion page the file is synced with.
+pub const PAGE_ID_KEY: &str = "notion_page_id";
+/// The front matter key with the title of the Notion page.
+pub const TITLE_KEY: &str = "title";
+/// The front matter key with the icon of the Notion page, an emoji or the URL of an image.
+pub const ICON_KEY: &str = "icon";
+/// The front matter key with the URL of the cover image of the Notion page.
+pub const COVER_KEY: &str = "cover";
+/// The front matter key with the properties of the Notion page in a database.
+pub const PROPERTIES_KEY: &str = "properties";
+
+const DELIMITER: &str = "---";
+/// YAML allows ending the document with `...` instead of `---`.
+const ALTERNATIVE_END_DELIMITER: &str = "...";
+
+/// The YAML front matter block at the start of a markdown file.
+/// It is kept verbatim, so writing it back does not change its formatting or comments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrontMatter {
+    /// The whole block, including the delimiters and the newline after the closing one.
+    raw: String,
+}
+
+impl FrontMatter {
+    /// Splits the front matter from the start of the document.
+    /// Returns `None` and the whole document when it does not start with front matter.
+    pub fn split(document: &str) -> (Option<Self>, &str) {
+        let mut lines = document.split_inclusive('\n');
+        let mut end = match lines.next() {
+            Some(first_line) if first_line.trim_end() == DELIMITER => first_line.len(),
+            _ => return (None, document),
+        };
+
+        for line in lines {
+            end += line.len();
+            let line = line.trim_end();
+            if line == DELIMITER || line == ALTERNATIVE_END_DELIMITER {
+                let (raw, rest) = document.split_at(end);
+                return (
+                    Some(Self {
+                        raw: raw.to_string(),
+                    }),
+                    rest,
+                );
+            }
+        }
+
+        // NOTE: without the closing delimiter, the `---` is a thematic break
+        (None, document)
+    }
+
+    /// Whether the document starts with the opening delimiter of front matter that is never
+    /// closed. Markdown would read the delimiter as a thematic break and the front matter as
+    /// text. A delimiter followed by a blank line is only a thematic break.
+    pub fn is_unterminated(document: &str) -> bool {
+        let mut lines = document.lines().map(str::trim_end);
+        lines.next() == Some(DELIMITER)
+            && lines.next().is_some_and(|line| !line.is_empty())
+            && Self::split(document).0.is_none()
+    }
+
+    /// Restores the front matter from the text produced by [`FrontMatter::raw`].
+    pub fn from_raw(raw: &str) -> Option<Self> {
+        match Self::split(raw) {
+            (Some(front_matter), "") => Some(front_matter),
+            _ => None,
+        }
+    }
+
+    /// The front matter block exactly as it appeared in the document.
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    /// Front matter with only the ID of the Notion page.
+    pub fn for_page_id(page_id: &str) -> Self {
+        Self {
+            raw: format!("{DELIMITER}\n{PAGE_ID_KEY}: {page_id}\n{DELIMITER}\n"),
+        }
+    }
+
+    /// The ID of the Notion page from the front matter, as written in the file.
+    pub fn page_id(&self) -> Result<Option<String>, serde_yaml::Error> {
+        Ok(self.yaml()?[PAGE_ID_KEY].as_str().map(str::to_string))
+    }
+
+    /// Sets the ID of the Notion page, keeping the rest of the front matter verbatim.
+    pub fn with_page_id(&self, page_id: &str) -> Self {
+        self.with_value(PAGE_ID_KEY, page_id)
+    }
+
+    /// The title of the Notion page from the front matter.
+    pub fn title(&self) -> Result<Option<String>, serde_yaml::Error> {
+        self.string(TITLE_KEY)
+    }
+
+    /// Sets the title of the Notion page, keeping the rest of the front matter verbatim.
+    pub fn with_title(&self, title: &str) -> Self {
+        self.with_string(TITLE_KEY, Some(title))
+    }
+
+    /// The icon of the Notion page from the front matter.
+    pub fn icon(&self) -> Result<Option<String>, serde_yaml::Error> {
+        self.string(ICON_KEY)
+    }
+
+    /// Sets or removes the icon of the Notion page, keeping the rest of the front matter
+    /// verbatim.
+    pub fn with_icon(&self, icon: Option<&str>) -> Self {
+        self.with_string(ICON_KEY, icon)
+    }
+
+    /// The cover image of the Notion page from the front matter.
+    pub fn cover(&self) -> Result<Option<String>, serde_yaml::Error> {
+        self.string(COVER_KEY)
+    }
+
+    /// Sets or removes the cover image of the Notion page, keeping the rest of the front matter
+    /// verbatim.
+    pub fn with_cover(&self, cover: Option<&str>) -> Self {
+        self.with_string(COVER_KEY, cover)
+    }
+
+    /// The properties of the Notion page from the front matter.
+    pub fn properties(&self) -> Result<Option<serde_yaml::Mapping>, serde_yaml::Error> {
+        Ok(self.yaml()?[PROPERTIES_KEY].as_mapping().cloned())
+    }
+
+    /// Sets the properties of the Notion page, or removes them when there are none, keeping
+    /// the rest of the front matter verbatim.
+    pub fn with_properties(&self, properties: &serde_yaml::Mapping) -> Self {
+        let properties = (!properties.is_empty()).then(|| properties.clone().into());
+
+        self.with_yaml(PROPERTIES_KEY, properties.as_ref())
+    }
+
+    /// Sets or removes the key with a value of any type, like the `tags` list that Obsidian
+    /// reads, keeping the rest of the front matter verbatim.
+    pub fn with_yaml(&self, key: &str, value: Option<&serde_yaml::Value>) -> Self {
+        let key_prefix = format!("{key}:");
+        let (opening_line, rest) = self.raw.split_at(self.raw.find('\n').map_or(0, |i| i + 1));
+
+        let mut raw = opening_line.to_string();
+        let mut value_start = opening_line.len();
+        let mut in_value = false;
+        for line in rest.split_inclusive('\n') {
+            // NOTE: nested values are the lines indented under the key, or the items of a list
+            // right under it
+            if in_value && (line.starts_with([' ', '\t']) || line.starts_with("- ")) {
+                continue;
+            }
+            in_value = line.starts_with(&key_prefix);
+            if in_value {
+                value_start = raw.len();
+            } else {
+                raw.push_str(line);
+            }
+        }
+
+        if let Some(value) = value {
+            let mut mapping = serde_yaml::Mapping::new();
+            mapping.insert(key.into(), value.clone());
+            let yaml = serde_yaml::to_string(&mapping).expect("mappings can always be serialized");
+            raw.insert_str(value_start, &yaml);
+        }
+
+        Self { raw }
+    }
+
+    fn string(&self, key: &str) -> Result<Option<String>, serde_yaml::Error> {
+        Ok(self.yaml()?[key].as_str().map(str::to_string))
+    }
+
+    fn with_string(&self, key: &str, value: Option<&str>) -> Self {
+        match value {
+            Some(value) => {
+                // NOTE: values can have characters with a meaning in YAML, like `: `
+                // or a leading `[`
+                let value = serde_yaml::to_string(value).expect("strings can always be serialized");
+                self.with_value(key, value.trim_end())
+            }
+            None => self.without_value(key),
+        }
+    }
+
+    /// Replaces the line with the `key`, or adds it after the opening delimiter.
+    /// The `value` is written as it is, so it has to be valid YAML.
+    fn with_value(&self, key: &str, value: &str) -> Self {
+        let key_line = format!("{key}: {value}\n");
+        let (opening_line, rest) = self.raw.split_at(self.raw.find('\n').map_or(0, |i| i + 1));
+
+        let mut raw = opening_line.to_string();
+        let mut replaced = false;
+        for line in rest.split_inclusive('\n') {
+            if !replaced && line.starts_with(&format!("{key}:")) {
+                raw.push_str(&key_line);
+                replaced = true;
+            } else {
+                raw.push_str(line);
+            }
+        }
+        if !replaced {
+            raw.insert_str(opening_line.len(), &key_line);
+        }
+
+        Self { raw }
+    }
+
+    /// Removes the line with the `key`.
+    fn without_value(&self, key: &str) -> Self {
+        let key_prefix = format!("{key}:");
+
+        Self {
+            raw: self
+                .raw
+                .split_inclusive('\n')
+                .filter(|line| !line.starts_with(&key_prefix))
+                .collect(),
+        }
+    }
+
+    /// Parses the YAML between the delimiters.
+    pub fn yaml(&self) -> Result<serde_yaml::Value, serde_yaml::Error> {
+        let content = self.raw.split_once('\n').map_or("", |(_, rest)| rest);
+        let content = content
+            .trim_end()
+            .rsplit_once('\n')
+            .map_or("", |(content, _)| content);
+
+        serde_yaml::from_str(content)
+    }
+
+    /// Writes the front matter at the start of the document.
+    /// The front matter is separated from the content with an empty line.
+    pub fn prepend_to(&self, document: &str) -> String {
+        let mut buf = self.raw.clone();
+        if !buf.ends_with('\n') {
+            buf.push('\n');
+        }
+        buf.push('\n');
+        buf.push_str(document);
+
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const DOCUMENT: &str = "---\ntitle: Release notes\ntags: [notion]\n---\n\n# Summary\n";
+
+    #[test]
+    fn splits_front_matter() {
+        let (front_matter, rest) = FrontMatter::split(DOCUMENT);
+
+        let front_matter = front_matter.expect("front matter");
+        assert_eq!(
+            front_matter.raw(),
+            "---\ntitle: Release notes\ntags: [notion]\n---\n"
+        );
+        assert_eq!(rest, "\n# Summary\n");
+        assert_eq!(front_matter.prepend_to("# Summary\n"), DOCUMENT);
+    }
+
+    #[test]
+    fn parses_yaml() {
+        let (front_matter, _) = FrontMatter::split(DOCUMENT);
+
+        let yaml = front_matter.unwrap().yaml().unwrap();
+
+        assert_eq!(yaml["title"].as_str(), Some("Release notes"));
+        assert_eq!(yaml["tags"][0].as_str(), Some("notion"));
+    }
+
+    #[test]
+    fn thematic_breaks_are_not_front_matter() {
+        for document in [
+            "Text\n\n---\n\nMore text\n---\n",
+            "---\n\nNo closing delimiter",
+        ] {
+            assert_eq!(FrontMatter::split(document), (None, document));
+            assert!(!FrontMatter::is_unterminated(document));
+        }
+    }
+
+    #[test]
+    fn detects_unterminated_front_matter() {
+        assert!(FrontMatter::is_unterminated("---\ntitle: Notes\n"));
+        assert!(!FrontMatter::is_unterminated(DOCUMENT));
+    }
+
+    #[test]
+    fn sets_the_page_id() {
+        const PAGE_ID: &str = "0b89a6e8-f006-4acc-8ec6-e6902b039e3a";
+        let (front_matter, _) = FrontMatter::split(DOCUMENT);
+
+        let with_page_id = front_matter.unwrap().with_page_id(PAGE_ID);
+        assert_eq!(
+            with_page_id.raw(),
+            format!("---\nnotion_page_id: {PAGE_ID}\ntitle: Release notes\ntags: [notion]\n---\n")
+        );
+        assert_eq!(with_page_id.page_id().unwrap().as_deref(), Some(PAGE_ID));
+
+        let updated = FrontMatter::split("---\n# Synced\nnotion_page_id: old\n---\n")
+            .0
+            .unwrap()
+            .with_page_id(PAGE_ID);
+        assert_eq!(
+            updated.raw(),
+            format!("---\n# Synced\nnotion_page_id: {PAGE_ID}\n---\n")
+        );
+        assert_eq!(
+            FrontMatter::for_page_id(PAGE_ID),
+            FrontMatter::from_raw(&format!("---\nnotion_page_id: {PAGE_ID}\n---\n")).unwrap()
+        );
+    }
+
+    #[test]
+    fn sets_the_title() {
+        let (front_matter, _) = FrontMatter::split(DOCUMENT);
+        let front_matter = front_matter.unwrap();
+
+        for title in ["🚀 Launch plan", "Notes: week 1", "[draft] 2022", "42"] {
+            let with_title = front_matter.with_title(title);
+
+            assert_eq!(with_title.title().unwrap().as_deref(), Some(title));
+            assert_eq!(
+                with_title.yaml().unwrap()["tags"][0].as_str(),
+                Some("notion")
+            );
+        }
+        assert_eq!(
+            front_matter.with_title("🚀 Launch plan").raw(),
+            "---\ntitle: 🚀 Launch plan\ntags: [notion]\n---\n"
+        );
+    }
+
+    #[test]
+    fn sets_and_removes_the_icon_and_cover() {
+        let front_matter = FrontMatter::for_page_id("ac32e0256f9c4fab8b9ddbb3c593ac46")
+            .with_icon(Some("🚀"))
+            .with_cover(Some("https://example.com/cover.png"));
+
+        assert_eq!(front_matter.icon().unwrap().as_deref(), Some("🚀"));
+        assert_eq!(
+            front_matter.cover().unwrap().as_deref(),
+            Some("https://example.com/cover.png")
+        );
+
+        let front_matter = front_matter.with_icon(None).with_cover(None);
+        assert_eq!(front_matter.icon().unwrap(), None);
+        assert_eq!(
+            front_matter,
+            FrontMatter::for_page_id("ac32e0256f9c4fab8b9ddbb3c593ac46")
+        );
+    }
+
+    #[test]
+    fn replaces_the_properties() {
+        let (front_matter, _) = FrontMatter::split(
+            "---\ntitle: Launch\nproperties:\n  Status: Draft\n  Tags:\n  - old\nicon: 🚀\n---\n",
+        );
+        let properties: serde_yaml::Mapping =
+            serde_yaml::from_str("Status: Done\nDue: 2022-08-01").unwrap();
+
+        let with_properties = front_matter.unwrap().with_properties(&properties);
+
+        assert_eq!(
+            with_properties.raw(),
+            "---\ntitle: Launch\nproperties:\n  Status: Done\n  Due: 2022-08-01\nicon: 🚀\n---\n"
+        );
+        assert_eq!(with_properties.properties().unwrap(), Some(properties));
+        assert_eq!(
+            with_properties
+                .with_properties(&serde_yaml::Mapping::new())
+                .raw(),
+            "---\ntitle: Launch\nicon: 🚀\n---\n"
+        );
+    }
+
+    #[test]
+    fn replaces_top_level_lists() {
+        let (front_matter, _) =
+            FrontMatter::split("---\ntags:\n- old\n- older\ntitle: Launch\n---\n");
+        let tags: serde_yaml::Value = serde_yaml::from_str("[release, launch]").unwrap();
+
+        assert_eq!(
+            front_matter.unwrap().with_yaml("tags", Some(&tags)).raw(),
+            "---\ntags:\n- release\n- launch\ntitle: Launch\n---\n"
+        );
+    }
+
+    #[test]
+    fn restores_from_raw() {
+        let (front_matter, _) = FrontMatter::split(DOCUMENT);
+        let front_matter = front_matter.unwrap();
+
+        assert_eq!(
+            FrontMatter::from_raw(front_matter.raw()),
+            Some(front_matter)
+        );
+        assert_eq!(FrontMatter::from_raw("not front matter"), None);
+    }
+}
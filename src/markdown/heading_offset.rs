@@ -0,0 +1,172 @@
+use super::{
+    tag::{HeadingLevel, Tag},
+    warning::Warning,
+};
+
+/// Shifts the level of every heading in the document by `offset`, including the headings
+/// nested in lists, quotes, callouts and toggles. `fetch` uses this to embed a page in a
+/// larger document, where the H1 of the page becomes an H2 or deeper.
+///
+/// Levels are clamped to H1 and H6, the headings that markdown has, and a [`Warning`] is
+/// reported for every clamped heading.
+pub fn offset_headings(tags: &mut [Tag], offset: i8, warnings: &mut Vec<Warning>) {
+    if offset == 0 {
+        return;
+    }
+
+    for tag in tags {
+        match tag {
+            Tag::Heading { level, text, .. } => {
+                let shifted_level = i16::from(level.number()) + i16::from(offset);
+                *level = HeadingLevel::clamped(shifted_level);
+                if i16::from(level.number()) != shifted_level {
+                    warnings.push(Warning::HeadingLevelClamped {
+                        text: text
+                            .iter()
+                            .map(|rich_text| rich_text.text.as_str())
+                            .collect(),
+                        level: shifted_level,
+                        clamped_level: level.number(),
+                    });
+                }
+            }
+            Tag::OrderedList { items, .. } => {
+                for item in items {
+                    offset_headings(&mut item.children, offset, warnings);
+                }
+            }
+            Tag::Quote { children, .. }
+            | Tag::Callout { children, .. }
+            | Tag::Toggle { children, .. } => offset_headings(children, offset, warnings),
+            Tag::Paragraph(_) | Tag::Code { .. } | Tag::Image { .. } | Tag::Divider => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::markdown::tag::{OrderedListItem, Paragraph, RichText};
+
+    fn heading(level: HeadingLevel, text: &str) -> Tag {
+        Tag::Heading {
+            level,
+            text: vec![RichText {
+                text: text.to_string(),
+                link: None,
+                mention: None,
+            }],
+            id: None,
+        }
+    }
+
+    #[test]
+    fn shifts_headings_deeper() {
+        let mut tags = vec![
+            heading(HeadingLevel::H1, "Title"),
+            Tag::Paragraph(Paragraph { text: Vec::new() }),
+            heading(HeadingLevel::H3, "Details"),
+        ];
+        let mut warnings = Vec::new();
+
+        offset_headings(&mut tags, 2, &mut warnings);
+
+        assert_eq!(
+            tags,
+            vec![
+                heading(HeadingLevel::H3, "Title"),
+                Tag::Paragraph(Paragraph { text: Vec::new() }),
+                heading(HeadingLevel::H5, "Details"),
+            ]
+        );
+        assert_eq!(warnings, Vec::new());
+    }
+
+    #[test]
+    fn shifts_nested_headings() {
+        let mut tags = vec![
+            Tag::Toggle {
+                text: Vec::new(),
+                children: vec![heading(HeadingLevel::H2, "In a toggle")],
+            },
+            Tag::OrderedList {
+                items: vec![OrderedListItem {
+                    text: Vec::new(),
+                    children: vec![Tag::Quote {
+                        text: Vec::new(),
+                        children: vec![heading(HeadingLevel::H2, "In a quote in a list")],
+                    }],
+                }],
+                loose: false,
+                start: 1,
+                continues: false,
+            },
+        ];
+
+        offset_headings(&mut tags, -1, &mut Vec::new());
+
+        assert_eq!(
+            tags,
+            vec![
+                Tag::Toggle {
+                    text: Vec::new(),
+                    children: vec![heading(HeadingLevel::H1, "In a toggle")],
+                },
+                Tag::OrderedList {
+                    items: vec![OrderedListItem {
+                        text: Vec::new(),
+                        children: vec![Tag::Quote {
+                            text: Vec::new(),
+                            children: vec![heading(HeadingLevel::H1, "In a quote in a list")],
+                        }],
+                    }],
+                    loose: false,
+                    start: 1,
+                    continues: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn clamps_levels_to_the_markdown_headings() {
+        let mut tags = vec![
+            heading(HeadingLevel::H1, "Title"),
+            heading(HeadingLevel::H3, "Details"),
+        ];
+        let mut warnings = Vec::new();
+
+        offset_headings(&mut tags, 4, &mut warnings);
+        offset_headings(&mut tags, -7, &mut warnings);
+
+        assert_eq!(
+            tags,
+            vec![
+                heading(HeadingLevel::H1, "Title"),
+                heading(HeadingLevel::H1, "Details"),
+            ]
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                Warning::HeadingLevelClamped {
+                    text: "Details".to_string(),
+                    level: 7,
+                    clamped_level: 6,
+                },
+                Warning::HeadingLevelClamped {
+                    text: "Title".to_string(),
+                    level: -2,
+                    clamped_level: 1,
+                },
+                Warning::HeadingLevelClamped {
+                    text: "Details".to_string(),
+                    level: -1,
+                    clamped_level: 1,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! The limits of the Notion API on the text of the blocks, which a document has to fit in
+//! before it can be pushed.
+
+use thiserror::Error;
+
+use super::{
+    plain_text::{MAX_RICH_TEXTS_PER_BLOCK, MAX_RICH_TEXT_LENGTH},
+    tag::{RichText, Tag},
+};
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum LimitError {
+    #[error("text starting with \"{preview}\" is {length} characters long, Notion allows at most {MAX_RICH_TEXT_LENGTH}")]
+    TextTooLong { preview: String, length: usize },
+
+    #[error("block starting with \"{preview}\" has {count} parts with different links or mentions, Notion allows at most {MAX_RICH_TEXTS_PER_BLOCK}")]
+    TooManyTextParts { preview: String, count: usize },
+}
+
+/// The text in the tag and in the tags nested in it that Notion would reject.
+/// Code blocks are split into parts that fit when they are pushed, so they are not checked.
+pub fn check_limits(tag: &Tag) -> Vec<LimitError> {
+    let mut errors = Vec::new();
+    check_tag(tag, &mut errors);
+
+    errors
+}
+
+fn check_tag(tag: &Tag, errors: &mut Vec<LimitError>) {
+    match tag {
+        Tag::Paragraph(paragraph) => check_text(&paragraph.text, errors),
+        Tag::Heading { text, .. } => check_text(text, errors),
+        Tag::OrderedList { items, .. } => {
+            for item in items {
+                check_text(&item.text, errors);
+                for child in &item.children {
+                    check_tag(child, errors);
+                }
+            }
+        }
+        Tag::Quote { text, children }
+        | Tag::Callout { text, children, .. }
+        | Tag::Toggle { text, children } => {
+            check_text(text, errors);
+            for child in children {
+                check_tag(child, errors);
+            }
+        }
+        Tag::Code { .. } | Tag::Image { .. } | Tag::Divider => {}
+    }
+}
+
+fn check_text(text: &[RichText], errors: &mut Vec<LimitError>) {
+    for rich_text in text {
+        let length = rich_text.text.chars().count();
+        if length > MAX_RICH_TEXT_LENGTH {
+            errors.push(LimitError::TextTooLong {
+                preview: preview(&rich_text.text),
+                length,
+            });
+        }
+    }
+    if text.len() > MAX_RICH_TEXTS_PER_BLOCK {
+        errors.push(LimitError::TooManyTextParts {
+            preview: preview(&text[0].text),
+            count: text.len(),
+        });
+    }
+}
+
+fn preview(text: &str) -> String {
+    text.chars().take(20).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::markdown::tag::{OrderedListItem, Paragraph};
+
+    fn text(content: String, link: Option<&str>) -> RichText {
+        RichText {
+            text: content,
+            link: link.map(ToString::to_string),
+            mention: None,
+        }
+    }
+
+    #[test]
+    fn finds_text_over_the_limits_in_nested_blocks() {
+        let long_text = "ł".repeat(MAX_RICH_TEXT_LENGTH + 1);
+        let many_links = (0..=MAX_RICH_TEXTS_PER_BLOCK)
+            .map(|index| text(format!("link {index} "), Some("https://example.com")))
+            .collect();
+        let tag = Tag::ordered_list(vec![OrderedListItem {
+            text: vec![text("Item".to_string(), None)],
+            children: vec![
+                Tag::Paragraph(Paragraph {
+                    text: vec![text(long_text, None)],
+                }),
+                Tag::Toggle {
+                    text: many_links,
+                    children: Vec::new(),
+                },
+            ],
+        }]);
+
+        assert_eq!(
+            check_limits(&tag),
+            [
+                LimitError::TextTooLong {
+                    preview: "ł".repeat(20),
+                    length: MAX_RICH_TEXT_LENGTH + 1,
+                },
+                LimitError::TooManyTextParts {
+                    preview: "link 0 ".to_string(),
+                    count: MAX_RICH_TEXTS_PER_BLOCK + 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_text_at_the_limit() {
+        let tag = Tag::Paragraph(Paragraph {
+            text: vec![text("ł".repeat(MAX_RICH_TEXT_LENGTH), None)],
+        });
+
+        assert_eq!(check_limits(&tag), []);
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use super::tag::{Mention, RichText, Tag};
+
+/// Maps Notion user IDs to the text that user mentions are rendered as in markdown
+/// (for example, GitHub handles).
+#[derive(Debug, Default)]
+pub struct UserMentionMap {
+    id_to_replacement: HashMap<String, String>,
+}
+
+impl UserMentionMap {
+    pub fn new(id_to_replacement: HashMap<String, String>) -> Self {
+        Self { id_to_replacement }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.id_to_replacement.is_empty()
+    }
+
+    /// Replaces the text of user mentions with the mapped replacement.
+    /// Mentions of unmapped users keep their display name.
+    pub fn render_user_mentions(&self, tags: &mut [Tag]) {
+        for tag in tags {
+            tag.visit_rich_text_mut(&mut |text| {
+                for rich_text in text.iter_mut() {
+                    if let Some(Mention::User { id }) = &rich_text.mention {
+                        if let Some(replacement) = self.id_to_replacement.get(id) {
+                            rich_text.text = replacement.clone();
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+    /// Finds the mapped replacements in plain text and turns them back into user mentions.
+    pub fn recreate_user_mentions(&self, tags: &mut [Tag]) {
+        if self.is_empty() {
+            return;
+        }
+
+        // NOTE: try longer replacements first, so that `@gelio-work` is not matched as `@gelio`
+        let mut replacements: Vec<_> = self
+            .id_to_replacement
+            .iter()
+            .map(|(id, replacement)| (replacement.as_str(), id.as_str()))
+            .collect();
+        replacements.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then(a.cmp(b)));
+
+        for tag in tags {
+            tag.visit_rich_text_mut(&mut |text| {
+                *text = std::mem::take(text)
+                    .into_iter()
+                    .flat_map(|rich_text| split_mentions(rich_text, &replacements))
+                    .collect();
+            });
+        }
+    }
+}
+
+fn split_mentions(rich_text: RichText, replacements: &[(&str, &str)]) -> Vec<RichText> {
+    if rich_text.mention.is_some() || rich_text.link.is_some() {
+        return vec![rich_text];
+    }
+
+    let mut result = Vec::new();
+    let mut plain_text_start = 0;
+    let mut position = 0;
+    let text = &rich_text.text;
+
+    while position < text.len() {
+        let rest = &text[position..];
+        let matched = replacements.iter().find(|(replacement, _)| {
+            rest.starts_with(replacement)
+                && is_word_boundary(text[..position].chars().next_back())
+                && is_word_boundary(rest[replacement.len()..].chars().next())
+        });
+
+        match matched {
+            Some((replacement, id)) => {
+                if plain_text_start < position {
+                    result.push(plain_text(&text[plain_text_start..position]));
+                }
+                result.push(RichText {
+                    text: replacement.to_string(),
+                    link: None,
+                    mention: Some(Mention::User { id: id.to_string() }),
+                });
+                position += replacement.len();
+                plain_text_start = position;
+            }
+            None => {
+                position += rest.chars().next().map_or(1, char::len_utf8);
+            }
+        }
+    }
+
+    if plain_text_start == 0 {
+        return vec![rich_text];
+    }
+    if plain_text_start < text.len() {
+        result.push(plain_text(&text[plain_text_start..]));
+    }
+
+    result
+}
+
+fn plain_text(text: &str) -> RichText {
+    RichText {
+        text: text.to_string(),
+        link: None,
+        mention: None,
+    }
+}
+
+fn is_word_boundary(c: Option<char>) -> bool {
+    c.is_none_or(|c| !(c.is_alphanumeric() || c == '_'))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::markdown::tag::Paragraph;
+
+    use super::*;
+
+    const USER_ID: &str = "ac32e025-6f9c-4fab-8b9d-dbb3c593ac46";
+
+    fn get_map() -> UserMentionMap {
+        UserMentionMap::new(HashMap::from([(USER_ID.to_string(), "@gelio".to_string())]))
+    }
+
+    fn get_paragraph_with_mention(mention_text: &str) -> Vec<Tag> {
+        vec![Tag::Paragraph(Paragraph {
+            text: vec![
+                plain_text("Reviewed by "),
+                RichText {
+                    text: mention_text.to_string(),
+                    link: None,
+                    mention: Some(Mention::User {
+                        id: USER_ID.to_string(),
+                    }),
+                },
+                plain_text(", thanks!"),
+            ],
+        })]
+    }
+
+    #[test]
+    fn renders_mapped_user_mentions() {
+        let mut tags = get_paragraph_with_mention("@Grzegorz Rozdzialik");
+
+        get_map().render_user_mentions(&mut tags);
+
+        assert_eq!(tags, get_paragraph_with_mention("@gelio"));
+    }
+
+    #[test]
+    fn keeps_display_names_of_unmapped_users() {
+        let mut tags = get_paragraph_with_mention("@Grzegorz Rozdzialik");
+
+        UserMentionMap::default().render_user_mentions(&mut tags);
+
+        assert_eq!(tags, get_paragraph_with_mention("@Grzegorz Rozdzialik"));
+    }
+
+    #[test]
+    fn recreates_mentions_from_replacements() {
+        let mut tags = vec![Tag::Paragraph(Paragraph {
+            text: vec![plain_text("Reviewed by @gelio, thanks!")],
+        })];
+
+        get_map().recreate_user_mentions(&mut tags);
+
+        assert_eq!(tags, get_paragraph_with_mention("@gelio"));
+    }
+
+    #[test]
+    fn does_not_recreate_mentions_inside_words() {
+        let original = vec![Tag::Paragraph(Paragraph {
+            text: vec![plain_text("Ask @gelio2 or someone@gelio.com")],
+        })];
+        let mut tags = vec![Tag::Paragraph(Paragraph {
+            text: vec![plain_text("Ask @gelio2 or someone@gelio.com")],
+        })];
+
+        get_map().recreate_user_mentions(&mut tags);
+
+        assert_eq!(tags, original);
+    }
+}
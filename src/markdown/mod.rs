@@ -1,28 +1,188 @@
+pub mod anchors;
+pub mod changelog;
 pub mod from_cmark;
+pub mod frontmatter;
+pub mod heading_offset;
+pub mod limits;
+pub mod mentions;
 pub mod notion_interop;
+pub mod obsidian;
+pub mod plain_text;
+pub mod span;
 pub mod tag;
+pub mod text_diff;
 pub mod to_cmark;
+pub mod to_html;
+pub mod to_text;
+pub mod warning;
+
+/// How newlines embedded in Notion rich text are represented in markdown.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum NewlineBehavior {
+    /// A soft break (a plain newline), which markdown renderers display as a space.
+    Space,
+    /// A hard break written as two trailing spaces.
+    /// These are the only trailing whitespace in the serialized markdown.
+    HardBreak,
+    /// A hard break written as a trailing backslash. This is the default, since it renders
+    /// like [`NewlineBehavior::HardBreak`] without any trailing whitespace.
+    #[default]
+    Backslash,
+}
+
+/// The dialect of markdown that documents are read and written in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Flavor {
+    /// Plain CommonMark, which any markdown editor can render.
+    #[default]
+    Commonmark,
+    /// The markdown of Obsidian vaults, with `[[wikilinks]]` to pages, `> [!note]` callouts,
+    /// and the `tags` and `aliases` front matter keys.
+    Obsidian,
+}
 
 #[cfg(test)]
 mod tests {
     use pretty_assertions::assert_eq;
+    use proptest::prelude::*;
 
-    use super::{from_cmark::PulldownCMarkEventParser, to_cmark::get_pulldown_cmark_events};
+    use super::{
+        from_cmark::{pulldown_cmark_options, ParseOptions, PulldownCMarkEventParser},
+        tag::{
+            strategies::{document, Target},
+            OrderedListItem, Paragraph, RichText, Tag,
+        },
+        to_cmark::{to_markdown, ListNumbering, MarkdownWriter, SerializeOptions},
+        NewlineBehavior,
+    };
 
     fn assert_parse_and_serialize(document: &str) {
-        let mut event_parser = pulldown_cmark::Parser::new(document);
-        let parsed_document = PulldownCMarkEventParser::new(&mut event_parser)
+        let mut event_parser = pulldown_cmark::Parser::new_ext(document, pulldown_cmark_options());
+        let parsed_document = PulldownCMarkEventParser::new(&mut event_parser, Default::default())
             .parse()
             .expect("successful parsing of the document");
 
-        let events = parsed_document.iter().flat_map(get_pulldown_cmark_events);
-        let mut buf = String::new();
-        pulldown_cmark_to_cmark::cmark(events, &mut buf).expect("serialization to pass");
+        let buf = to_markdown(&parsed_document, &Default::default());
 
         assert_eq!(
             buf, document,
             "parse and serialize yields the same document"
         );
+        assert_no_trailing_whitespace(&buf);
+    }
+
+    /// Fails if any line of the serialized document ends with whitespace, which editors that
+    /// trim whitespace on save would remove.
+    fn assert_no_trailing_whitespace(document: &str) {
+        for (index, line) in document.lines().enumerate() {
+            assert_eq!(
+                line.trim_end(),
+                line,
+                "line {} of the document ends with whitespace",
+                index + 1
+            );
+        }
+    }
+
+    fn assert_serialize_and_parse(tags: &[Tag], newline_behavior: NewlineBehavior, document: &str) {
+        let buf = to_markdown(
+            tags,
+            &SerializeOptions {
+                newline_behavior,
+                ..Default::default()
+            },
+        );
+        assert_eq!(buf, document, "serialized document");
+        // NOTE: hard breaks written as two spaces are trailing whitespace by definition
+        if newline_behavior != NewlineBehavior::HardBreak {
+            assert_no_trailing_whitespace(&buf);
+        }
+
+        let parsed_document = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext(&buf, pulldown_cmark_options()),
+            ParseOptions {
+                newline_behavior,
+                ..Default::default()
+            },
+        )
+        .parse()
+        .expect("successful parsing of the document");
+        assert_eq!(
+            parsed_document, tags,
+            "serialize and parse yields the same tags"
+        );
+    }
+
+    #[test]
+    fn empty_paragraphs_round_trip() {
+        let paragraph = |text: &str| {
+            Tag::Paragraph(Paragraph {
+                text: if text.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![RichText {
+                        text: text.to_string(),
+                        link: None,
+                        mention: None,
+                    }]
+                },
+            })
+        };
+        let tags = vec![
+            paragraph("Before"),
+            paragraph(""),
+            paragraph(""),
+            paragraph("After"),
+        ];
+
+        let buf = to_markdown(&tags, &Default::default());
+        assert_eq!(buf, "Before\n\n<br>\n\n<br>\n\nAfter");
+
+        let parse = |keep_empty_paragraphs| {
+            PulldownCMarkEventParser::new(
+                pulldown_cmark::Parser::new_ext(&buf, pulldown_cmark_options()),
+                ParseOptions {
+                    keep_empty_paragraphs,
+                    ..Default::default()
+                },
+            )
+            .parse()
+            .expect("successful parsing of the document")
+        };
+        assert_eq!(parse(true), tags);
+        assert_eq!(parse(false), vec![paragraph("Before"), paragraph("After")]);
+    }
+
+    #[test]
+    fn smart_punctuation_round_trip() {
+        let document = r#"It's "quoted" -- with dashes --- and ellipses..."#;
+        let parse_options = ParseOptions {
+            smart_punctuation: true,
+            ..Default::default()
+        };
+        let parsed_document = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext(document, parse_options.pulldown_cmark_options()),
+            parse_options,
+        )
+        .parse()
+        .expect("successful parsing of the document");
+
+        assert_eq!(
+            to_markdown(&parsed_document, &Default::default()),
+            "It’s “quoted” – with dashes — and ellipses…"
+        );
+        assert_eq!(
+            to_markdown(
+                &parsed_document,
+                &SerializeOptions {
+                    ascii_punctuation: true,
+                    ..Default::default()
+                },
+            ),
+            document
+        );
     }
 
     #[test]
@@ -33,9 +193,9 @@ mod tests {
 1. Watch some videos
 
 1. Another list item
-   
+
    1. Second level list item
-      
+
       Second level item's extra description
 
 # Details
@@ -54,4 +214,343 @@ A **decorated** _paragraph_ with ~strikethrough~ and **_mixed_** decorations.
 1. *List items can have decorations **too***",
         );
     }
+
+    fn get_address_paragraph() -> Vec<Tag> {
+        vec![Tag::Paragraph(Paragraph {
+            text: vec![RichText {
+                text: "221B Baker Street\nLondon\nNW1 6XE".to_string(),
+                link: None,
+                mention: None,
+            }],
+        })]
+    }
+
+    #[test]
+    fn address_with_newlines_as_spaces() {
+        assert_serialize_and_parse(
+            &get_address_paragraph(),
+            NewlineBehavior::Space,
+            "221B Baker Street\nLondon\nNW1 6XE",
+        );
+    }
+
+    #[test]
+    fn address_with_newlines_as_hard_breaks() {
+        assert_serialize_and_parse(
+            &get_address_paragraph(),
+            NewlineBehavior::HardBreak,
+            "221B Baker Street  \nLondon  \nNW1 6XE",
+        );
+    }
+
+    #[test]
+    fn address_with_newlines_as_backslashes() {
+        assert_serialize_and_parse(
+            &get_address_paragraph(),
+            NewlineBehavior::Backslash,
+            "221B Baker Street\\\nLondon\\\nNW1 6XE",
+        );
+    }
+
+    #[test]
+    fn autolink_in_the_middle_of_a_sentence() {
+        assert_parse_and_serialize("See <https://example.com/docs?page=2> for details.");
+    }
+
+    #[test]
+    fn autolink_as_the_whole_paragraph() {
+        assert_parse_and_serialize("<https://example.com>");
+    }
+
+    #[test]
+    fn links_with_custom_text() {
+        assert_parse_and_serialize(
+            "Read [the docs](https://example.com) or write to <someone@example.com>.",
+        );
+    }
+
+    #[test]
+    fn links_keep_fragments_queries_and_encoded_characters() {
+        let links = [
+            "https://www.notion.so/Page-0123456789abcdef0123456789abcdef#fedcba9876543210fedcba9876543210",
+            "https://www.notion.so/0123456789abcdef0123456789abcdef?pvs=4",
+            "/0123456789abcdef0123456789abcdef#fedcba9876543210fedcba9876543210",
+            "https://example.com/a%20b?q=caf%C3%A9&x=1#part%2F2",
+        ];
+        let document = format!(
+            "[Section]({}), [shared]({}), [relative]({}) and <{}>",
+            links[0], links[1], links[2], links[3]
+        );
+
+        assert_parse_and_serialize(&document);
+        let tags = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext(&document, pulldown_cmark_options()),
+            Default::default(),
+        )
+        .parse()
+        .unwrap();
+        let Tag::Paragraph(Paragraph { text }) = &tags[0] else {
+            unreachable!("the links are in a paragraph");
+        };
+        assert_eq!(
+            text.iter()
+                .filter_map(|rich_text| rich_text.link.as_deref())
+                .collect::<Vec<_>>(),
+            links
+        );
+    }
+
+    fn get_paragraph(text: &str) -> Tag {
+        Tag::Paragraph(Paragraph {
+            text: vec![RichText {
+                text: text.to_string(),
+                link: None,
+                mention: None,
+            }],
+        })
+    }
+
+    #[test]
+    fn paragraph_starting_with_a_year() {
+        assert_serialize_and_parse(
+            &[get_paragraph("1986. The year it began")],
+            NewlineBehavior::HardBreak,
+            "1986\\. The year it began",
+        );
+    }
+
+    #[test]
+    fn list_item_starting_with_a_year() {
+        assert_serialize_and_parse(
+            &[Tag::OrderedList {
+                items: vec![OrderedListItem {
+                    text: vec![RichText {
+                        text: "1986. The year it began".to_string(),
+                        link: None,
+                        mention: None,
+                    }],
+                    children: Vec::new(),
+                }],
+                loose: false,
+                start: 1,
+                continues: false,
+            }],
+            NewlineBehavior::HardBreak,
+            "1. 1986\\. The year it began",
+        );
+    }
+
+    #[test]
+    fn line_starting_with_a_year_after_a_newline() {
+        assert_serialize_and_parse(
+            &[get_paragraph("It all started in\n1986. The year it began")],
+            NewlineBehavior::Space,
+            "It all started in\n1986\\. The year it began",
+        );
+    }
+
+    #[test]
+    fn paragraph_starting_with_a_letter_and_a_period() {
+        assert_serialize_and_parse(
+            &[get_paragraph("A. Lincoln was here")],
+            NewlineBehavior::HardBreak,
+            "A. Lincoln was here",
+        );
+    }
+
+    #[test]
+    fn paragraph_starting_with_a_dash() {
+        assert_serialize_and_parse(
+            &[
+                get_paragraph("- not a list"),
+                get_paragraph("+ not a list either"),
+            ],
+            NewlineBehavior::HardBreak,
+            "\\- not a list\n\n\\+ not a list either",
+        );
+    }
+
+    #[test]
+    fn numbers_that_are_not_list_markers_are_not_escaped() {
+        assert_serialize_and_parse(
+            &[
+                get_paragraph("3.14 is close to pi"),
+                get_paragraph("-5 degrees"),
+            ],
+            NewlineBehavior::HardBreak,
+            "3.14 is close to pi\n\n-5 degrees",
+        );
+    }
+
+    #[test]
+    fn heading_with_an_explicit_anchor() {
+        assert_parse_and_serialize("# Introduction {#intro}\n\nSee [the introduction](#intro).");
+    }
+
+    #[test]
+    fn quote_with_multiple_paragraphs() {
+        assert_parse_and_serialize(
+            "> The first paragraph of the quote.\n>\n> The second one.\n>\n> And the third.",
+        );
+    }
+
+    #[test]
+    fn quote_with_nested_quote_and_list() {
+        assert_parse_and_serialize(
+            "> Quoted text\n>\n> > Nested quote\n>\n> 1. A list in a quote\n>\n> 1. Another item",
+        );
+    }
+
+    #[test]
+    fn toggle_with_nested_content() {
+        assert_parse_and_serialize(
+            "<details>\n<summary>Open me</summary>\n\nHidden text\n\n1. Hidden list\n\n</details>\n\nAfter",
+        );
+    }
+
+    #[test]
+    fn fenced_code_block() {
+        assert_parse_and_serialize("```html\n<table>\n  <tr></tr>\n</table>\n```\n\nAfter");
+    }
+
+    #[test]
+    fn tight_list() {
+        assert_parse_and_serialize("1. First\n1. Second\n   1. Nested\n   1. Nested too\n1. Third");
+    }
+
+    #[test]
+    fn loose_list() {
+        assert_parse_and_serialize("1. First\n\n1. Second\n\n   With a paragraph\n\n1. Third");
+    }
+
+    #[test]
+    fn list_with_a_start_number() {
+        assert_parse_and_serialize("Some text\n\n5. Fifth\n5. Sixth");
+    }
+
+    #[test]
+    fn incrementing_list_numbers() {
+        let document = "8. Eighth\n9. Ninth\n   1. Nested\n   2. Nested too\n10. Tenth";
+        let tags = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext(document, pulldown_cmark_options()),
+            Default::default(),
+        )
+        .parse()
+        .expect("successful parsing of the document");
+
+        let buf = to_markdown(
+            &tags,
+            &SerializeOptions {
+                list_numbering: ListNumbering::Incrementing,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(buf, document);
+    }
+
+    #[test]
+    fn list_item_with_multiple_paragraphs_and_a_nested_list() {
+        assert_parse_and_serialize(
+            "1. First paragraph\n\n   Second paragraph\n\n   Third paragraph\n\n   1. Nested item\n   1. Another nested item\n\n1. Next item",
+        );
+    }
+
+    #[test]
+    fn tight_list_with_a_loose_nested_list() {
+        assert_parse_and_serialize("1. Outer\n   1. Nested\n\n      Paragraph\n1. Outer again");
+    }
+
+    #[test]
+    fn paragraph_after_a_nested_list() {
+        assert_parse_and_serialize("1. Item\n\n   1. Nested\n\n   Paragraph");
+    }
+
+    #[test]
+    fn list_item_with_empty_text_and_children() {
+        let item = |text: &str| OrderedListItem {
+            text: vec![RichText {
+                text: text.to_string(),
+                link: None,
+                mention: None,
+            }],
+            children: Vec::new(),
+        };
+
+        assert_serialize_and_parse(
+            &[Tag::ordered_list(vec![
+                item("First"),
+                OrderedListItem {
+                    text: Vec::new(),
+                    children: vec![get_paragraph("Nested"), get_paragraph("Another nested")],
+                },
+                item("Third"),
+            ])],
+            NewlineBehavior::HardBreak,
+            "1. First\n\n1. <!-- empty -->\n\n   Nested\n\n   Another nested\n\n1. Third",
+        );
+    }
+
+    #[test]
+    fn thematic_breaks_are_dividers() {
+        assert_parse_and_serialize("Intro\n\n***\n\nMore");
+
+        let parsed_document = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext("Intro\n\n---\n\nMore", pulldown_cmark_options()),
+            Default::default(),
+        )
+        .parse()
+        .expect("successful parsing of the document");
+        assert_eq!(
+            parsed_document,
+            [get_paragraph("Intro"), Tag::Divider, get_paragraph("More")]
+        );
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let document = "# Summary {#summary}\n\nRead [the docs](https://example.com) first.\n\n5. Fifth\n\n   With a paragraph\n\n   1. Nested\n\n5. Sixth\n\n> Quoted text\n>\n> > Nested quote\n\n<details>\n<summary>Open me</summary>\n\nHidden text\n\n</details>\n\n```html\n<table></table>\n```";
+        let tags = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext(document, pulldown_cmark_options()),
+            Default::default(),
+        )
+        .parse()
+        .expect("successful parsing of the document");
+
+        let json = serde_json::to_string(&tags).expect("tags can be serialized");
+        let tags_from_json: Vec<Tag> = serde_json::from_str(&json).expect("valid JSON");
+
+        assert_eq!(tags_from_json, tags);
+        assert_eq!(to_markdown(&tags_from_json, &Default::default()), document);
+    }
+
+    proptest! {
+        #[test]
+        fn generated_documents_round_trip(tags in document(Target::Markdown)) {
+            let markdown = to_markdown(&tags, &Default::default());
+            let parsed_tags = PulldownCMarkEventParser::new(
+                pulldown_cmark::Parser::new_ext(&markdown, pulldown_cmark_options()),
+                Default::default(),
+            )
+            .parse()
+            .map_err(|error| error.to_string());
+
+            prop_assert_eq!(parsed_tags, Ok(tags), "markdown:\n{}", markdown);
+        }
+
+        #[test]
+        fn documents_written_a_tag_at_a_time_are_the_same(
+            tags in document(Target::Markdown)
+        ) {
+            let mut writer = MarkdownWriter::new(Default::default());
+            let mut markdown = String::new();
+            for tag in &tags {
+                writer.write_tags(std::slice::from_ref(tag));
+                markdown.push_str(&writer.take_complete_lines());
+            }
+            markdown.push_str(&writer.finish());
+
+            prop_assert_eq!(markdown, to_markdown(&tags, &Default::default()));
+        }
+    }
 }
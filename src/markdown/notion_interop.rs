@@ -1,115 +1,329 @@
-use std::iter::FromIterator;
-
-use crate::{markdown::tag::Paragraph, notion_api::BlockWithChildren};
-
-#[derive(Debug)]
-enum ParserState {
-    Idle,
-    ProcessingList(Vec<super::tag::OrderedListItem>),
-    /// A tag can be buffered in the following scenario:
-    /// 1. A list is being processed. No tags are emitted. The list is buffered.
-    /// 2. Another tag is encountered. The list is finished and is emitted. The tag
-    ///    that was parsed in this call cannot be emitted right away, because the parser
-    ///    only emits a single tag at a time. Thus, it needs to be buffered.
-    /// 3. The tag from the previous call will be emitted for the next tag. The next tag
-    ///    will be buffered.
-    /// 4. The final buffered tag will be emitted at the end, in the final `flush` call.
-    WithBufferedTag(super::tag::Tag),
+use std::{collections::VecDeque, iter::FromIterator, str::FromStr};
+
+use crate::{
+    markdown::{tag::Paragraph, warning::Warning},
+    notion_api::{assets::file_url, BlockWithChildren},
+};
+use notion::ids::AsIdentifier;
+
+/// What to do with an empty paragraph between two numbered lists. Notion sometimes renders
+/// such lists as a single list, while the API returns them as separate runs of list items.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ListSeparatorPolicy {
+    /// Keep the lists separate.
+    #[default]
+    Split,
+    /// Merge the lists and drop the empty paragraph.
+    Merge,
+    /// Merge the lists and keep the empty paragraph as a child of the preceding item.
+    MergeKeepSeparator,
 }
 
-impl Default for ParserState {
-    fn default() -> Self {
-        Self::Idle
-    }
+#[derive(Debug, Default, Clone)]
+pub struct NotionParseOptions {
+    pub list_separator_policy: ListSeparatorPolicy,
+    /// Write child pages as links to them instead of skipping them.
+    pub child_page_links: bool,
+    /// Number a list that follows another list, with other blocks between them, as the
+    /// continuation of that list instead of from 1. Notion has no such lists, so this only
+    /// changes how the lists are numbered in markdown.
+    pub merge_adjacent_lists: bool,
+    /// The number of levels every heading is shifted by, see
+    /// [`super::heading_offset::offset_headings`].
+    pub heading_offset: i8,
+    /// Skip the empty paragraphs that Notion uses for vertical space. The ones that separate
+    /// two lists are kept, so the lists stay separate.
+    pub compact: bool,
 }
 
+/// Converts Notion blocks into markdown tags.
+///
+/// Markdown cannot nest blocks under paragraphs and headings, so the children of such blocks
+/// are emitted right after them, on the same level, and a [`Warning`] is reported.
 #[derive(Debug, Default)]
 pub struct NotionToMarkdownParser {
-    state: ParserState,
+    options: NotionParseOptions,
+    /// The items of the list that is currently being processed. A list is finished only
+    /// when a block that is not a list item is encountered.
+    current_list: Option<Vec<super::tag::OrderedListItem>>,
+    /// An empty paragraph right after a list, which may separate two parts of the same list.
+    pending_separator: Option<Paragraph>,
+    /// The number after the last item of the previous list, with `merge_adjacent_lists`.
+    /// Headings start over.
+    next_list_number: Option<u64>,
+    /// Tags that are parsed and ready to be emitted, in order.
+    ready_tags: VecDeque<super::tag::Tag>,
+    warnings: Vec<Warning>,
+    /// The IDs of the heading blocks, in the order of the headings in the parsed tags.
+    heading_block_ids: Vec<String>,
 }
 
 impl NotionToMarkdownParser {
-    fn parse_block(&mut self, value: &BlockWithChildren) -> Option<super::tag::Tag> {
+    pub fn new(options: NotionParseOptions) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
+
+    /// Converts the block into tags and queues them in `ready_tags`.
+    /// A single block can become several tags, while numbered list items are queued only
+    /// when the whole list is finished.
+    fn parse_block(&mut self, value: &BlockWithChildren) {
         use super::tag::{HeadingLevel, Tag};
         use notion::models::Block;
 
+        if let Block::Heading1 { .. } | Block::Heading2 { .. } | Block::Heading3 { .. } =
+            &value.block
+        {
+            // NOTE: lists in another section are numbered on their own
+            self.finish_list();
+            self.next_list_number = None;
+            self.heading_block_ids.push(block_id(&value.block));
+        }
+        let formatting = dropped_formatting(&value.block);
+        if !formatting.is_empty() {
+            self.warnings.push(Warning::FormattingDropped {
+                block_id: block_id(&value.block),
+                formatting,
+            });
+        }
+
         // TODO: ensure that children are empty in most cases
-        match &value.block {
-            Block::Heading1 { heading_1, .. } => self.next_tag(Tag::Heading {
-                level: HeadingLevel::H1,
-                text: Self::parse_rich_text(&heading_1.rich_text),
-            }),
-            Block::Heading2 { heading_2, .. } => self.next_tag(Tag::Heading {
-                level: HeadingLevel::H2,
-                text: Self::parse_rich_text(&heading_2.rich_text),
-            }),
-            Block::Heading3 { heading_3, .. } => self.next_tag(Tag::Heading {
-                level: HeadingLevel::H3,
-                text: Self::parse_rich_text(&heading_3.rich_text),
-            }),
-            Block::Paragraph { paragraph, .. } => self.next_tag(Tag::Paragraph(Paragraph {
-                text: Self::parse_rich_text(&paragraph.rich_text),
-            })),
+        let tags = match &value.block {
+            Block::Heading1 { heading_1, .. } => {
+                let heading = self.parse_heading(value, HeadingLevel::H1, &heading_1.rich_text);
+                self.with_flattened_children(heading, value)
+            }
+            Block::Heading2 { heading_2, .. } => {
+                let heading = self.parse_heading(value, HeadingLevel::H2, &heading_2.rich_text);
+                self.with_flattened_children(heading, value)
+            }
+            Block::Heading3 { heading_3, .. } => {
+                let heading = self.parse_heading(value, HeadingLevel::H3, &heading_3.rich_text);
+                self.with_flattened_children(heading, value)
+            }
+            Block::Paragraph { paragraph, .. } => {
+                let paragraph = Paragraph {
+                    text: self.parse_rich_text(value, &paragraph.rich_text),
+                };
+                if self.current_list.is_some()
+                    && self.pending_separator.is_none()
+                    && value.children.is_empty()
+                    && self.options.list_separator_policy != ListSeparatorPolicy::Split
+                    && paragraph.is_blank()
+                {
+                    self.pending_separator = Some(paragraph);
+                    return;
+                }
+                if self.options.compact
+                    && self.current_list.is_none()
+                    && value.children.is_empty()
+                    && paragraph.is_blank()
+                {
+                    return;
+                }
+
+                self.with_flattened_children(Tag::Paragraph(paragraph), value)
+            }
+            Block::Quote { quote, .. } => vec![Tag::Quote {
+                text: self.parse_rich_text(value, &quote.rich_text),
+                children: self.parse_children(value),
+            }],
+            Block::Callout { callout, .. } => vec![Tag::Callout {
+                icon: match &callout.icon {
+                    notion::models::FileOrEmojiObject::Emoji { emoji } => Some(emoji.clone()),
+                    _ => None,
+                },
+                text: self.parse_rich_text(value, &callout.rich_text),
+                children: self.parse_children(value),
+            }],
+            Block::Toggle { toggle, .. } => vec![Tag::Toggle {
+                text: self.parse_rich_text(value, &toggle.rich_text),
+                children: self.parse_children(value),
+            }],
+            Block::Code { code, .. } => vec![Tag::Code {
+                language: code_language_name(&code.language),
+                text: self
+                    .parse_rich_text(value, &code.rich_text)
+                    .into_iter()
+                    .map(|rich_text| rich_text.text)
+                    .collect(),
+            }],
             Block::NumberedListItem {
                 numbered_list_item, ..
             } => {
-                let children_parser = Self::default();
                 let next_list_item = super::tag::OrderedListItem {
-                    text: Self::parse_rich_text(&numbered_list_item.rich_text),
-                    children: children_parser.feed(value.children.iter()).collect(),
+                    text: self.parse_rich_text(value, &numbered_list_item.rich_text),
+                    children: self.parse_children(value),
                 };
 
-                match self.state {
-                    ParserState::Idle => {
-                        let current_list_items = vec![next_list_item];
-                        self.state = ParserState::ProcessingList(current_list_items);
-                        None
-                    }
-                    ParserState::ProcessingList(ref mut current_list_items) => {
-                        current_list_items.push(next_list_item);
-                        None
-                    }
-                    ParserState::WithBufferedTag(_) => {
-                        let current_list_items = vec![next_list_item];
-
-                        // NOTE: we need ownership over the buffered_tag
-                        match std::mem::replace(
-                            &mut self.state,
-                            ParserState::ProcessingList(current_list_items),
-                        ) {
-                            ParserState::WithBufferedTag(buffered_tag) => Some(buffered_tag),
-                            _ => unreachable!("this code is in a WithBufferedTag branch"),
+                match self.current_list {
+                    Some(ref mut current_list_items) => {
+                        if let Some(separator) = self.pending_separator.take() {
+                            if self.options.list_separator_policy
+                                == ListSeparatorPolicy::MergeKeepSeparator
+                            {
+                                current_list_items
+                                    .last_mut()
+                                    .expect("the current list has at least one item")
+                                    .children
+                                    .push(Tag::Paragraph(separator));
+                            }
                         }
+                        current_list_items.push(next_list_item);
                     }
+                    None => self.current_list = Some(vec![next_list_item]),
+                }
+                return;
+            }
+            Block::Divider { .. } => vec![Tag::Divider],
+            // NOTE: markdown has no columns or synced blocks, so their content takes their place
+            Block::ColumnList { .. } | Block::SyncedBlock { .. } | Block::Column { .. } => {
+                if !matches!(value.block, Block::Column { .. }) {
+                    self.warnings.push(Warning::BlockUnwrapped {
+                        block_id: block_id(&value.block),
+                        block_type: block_type(&value.block),
+                    });
                 }
+                // NOTE: a list does not continue across the edges of the block
+                self.finish_list();
+                for child in &value.children {
+                    self.parse_block(child);
+                }
+                Vec::new()
+            }
+            Block::Image { image, .. } => match file_url(image) {
+                Some(url) => vec![Tag::Image { url }],
+                None => Vec::new(),
+            },
+            // NOTE: markdown has no files, so they become links to the file
+            Block::File { file, .. } | Block::Pdf { pdf: file, .. } => match file_url(file) {
+                Some(url) => vec![Tag::Paragraph(Paragraph {
+                    text: vec![super::tag::RichText {
+                        text: file_name(&url),
+                        link: Some(url),
+                        mention: None,
+                    }],
+                })],
+                None => Vec::new(),
+            },
+            Block::ChildPage { common, child_page } if self.options.child_page_links => {
+                vec![Tag::Paragraph(Paragraph {
+                    text: vec![super::tag::RichText {
+                        text: child_page.title.clone(),
+                        link: Some(format!(
+                            "https://www.notion.so/{}",
+                            common.id.to_string().replace('-', "")
+                        )),
+                        mention: None,
+                    }],
+                })]
+            }
+            Block::ChildPage { common, child_page } => {
+                self.warnings.push(Warning::ChildPageSkipped {
+                    block_id: common.id.to_string(),
+                    title: child_page.title.clone(),
+                });
+                Vec::new()
             }
-            _ => todo!("block not implemented"),
+            block => {
+                self.warnings.push(Warning::BlockSkipped {
+                    block_id: block_id(block),
+                    block_type: block_type(block),
+                });
+                Vec::new()
+            }
+        };
+
+        self.finish_list();
+        self.ready_tags.extend(tags);
+    }
+
+    /// The tag followed by the children of the block, which markdown cannot nest under it.
+    fn with_flattened_children(
+        &mut self,
+        tag: super::tag::Tag,
+        value: &BlockWithChildren,
+    ) -> Vec<super::tag::Tag> {
+        let mut tags = vec![tag];
+        if !value.children.is_empty() {
+            self.warnings.push(Warning::ChildrenFlattened {
+                block_id: value.block.as_id().to_string(),
+            });
+            tags.extend(self.parse_children(value));
         }
+
+        tags
     }
 
-    fn next_tag(&mut self, tag: super::tag::Tag) -> Option<super::tag::Tag> {
-        if let Some(previous_tag) = self.maybe_flush_processed_tag() {
-            self.state = ParserState::WithBufferedTag(tag);
-            Some(previous_tag)
-        } else {
-            Some(tag)
+    /// Emits the list that is currently being processed, followed by the empty paragraph
+    /// that turned out not to separate two parts of the list.
+    fn finish_list(&mut self) {
+        if let Some(items) = self.current_list.take() {
+            let mut list = super::tag::Tag::ordered_list(items);
+            if let super::tag::Tag::OrderedList {
+                items,
+                start,
+                continues,
+                ..
+            } = &mut list
+            {
+                if self.options.merge_adjacent_lists {
+                    if let Some(next_list_number) = self.next_list_number {
+                        *start = next_list_number;
+                        *continues = true;
+                    }
+                    self.next_list_number = Some(*start + items.len() as u64);
+                }
+            }
+            self.ready_tags.push_back(list);
+        }
+        if let Some(separator) = self.pending_separator.take() {
+            self.ready_tags
+                .push_back(super::tag::Tag::Paragraph(separator));
         }
     }
 
-    fn maybe_flush_processed_tag(&mut self) -> Option<super::tag::Tag> {
-        let state = std::mem::replace(&mut self.state, ParserState::Idle);
+    fn parse_children(&mut self, value: &BlockWithChildren) -> Vec<super::tag::Tag> {
+        let mut children = Self::new(self.options.clone()).feed(value.children.iter());
+        let tags = children.by_ref().collect();
+        self.warnings.extend(children.parser.warnings);
 
-        match state {
-            ParserState::Idle => None,
-            ParserState::ProcessingList(current_list_items) => Some(super::tag::Tag::OrderedList {
-                items: current_list_items,
-            }),
-            ParserState::WithBufferedTag(tag) => Some(tag),
+        tags
+    }
+
+    /// Converts the next blocks of a document that is fetched a few blocks at a time, and
+    /// returns the tags that are ready. A numbered list that the next blocks may continue is
+    /// only returned by [`Self::finish`].
+    pub fn parse_blocks<'a>(
+        &mut self,
+        blocks: impl IntoIterator<Item = &'a BlockWithChildren>,
+    ) -> Vec<super::tag::Tag> {
+        for block in blocks {
+            self.parse_block(block);
         }
+
+        self.ready_tags.drain(..).collect()
+    }
+
+    /// The tags that were held back by [`Self::parse_blocks`] at the end of the document.
+    pub fn finish(&mut self) -> Vec<super::tag::Tag> {
+        self.finish_list();
+
+        self.ready_tags.drain(..).collect()
     }
 
-    fn flush(mut self) -> Option<super::tag::Tag> {
-        self.maybe_flush_processed_tag()
+    /// The warnings about the blocks that were converted so far.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// The IDs of the heading blocks that were converted so far, in the order of the headings
+    /// in the tags.
+    pub fn heading_block_ids(&self) -> &[String] {
+        &self.heading_block_ids
     }
 
     pub fn feed<'a, I>(self, blocks: I) -> MarkdownTagIterator<'a, I>
@@ -118,13 +332,163 @@ impl NotionToMarkdownParser {
     {
         MarkdownTagIterator {
             blocks,
-            parser: Some(self),
+            parser: self,
         }
     }
 
-    fn parse_rich_text(rich_text: &[notion::models::text::RichText]) -> Vec<super::tag::RichText> {
+    fn parse_rich_text(
+        &mut self,
+        value: &BlockWithChildren,
+        rich_text: &[notion::models::text::RichText],
+    ) -> Vec<super::tag::RichText> {
+        for part in rich_text {
+            if let Some(kind) = text_only_rich_text_kind(part) {
+                self.warnings.push(Warning::RichTextKeptAsText {
+                    block_id: value.block.as_id().to_string(),
+                    kind: kind.to_string(),
+                    text: part.plain_text().to_string(),
+                });
+            }
+        }
+
         rich_text.iter().map(Into::into).collect()
     }
+
+    fn parse_heading(
+        &mut self,
+        value: &BlockWithChildren,
+        level: super::tag::HeadingLevel,
+        rich_text: &[notion::models::text::RichText],
+    ) -> super::tag::Tag {
+        let mut text = self.parse_rich_text(value, rich_text);
+        let id = split_heading_id(&mut text);
+
+        super::tag::Tag::Heading { level, text, id }
+    }
+}
+
+fn block_id(block: &notion::models::Block) -> String {
+    match block {
+        // NOTE: blocks of types the `notion` crate does not know have no ID
+        notion::models::Block::Unknown => String::new(),
+        block => block.as_id().to_string(),
+    }
+}
+
+/// The type of the block in the Notion API, like `column_list`.
+fn block_type(block: &notion::models::Block) -> String {
+    serde_json::to_value(block).unwrap_or_default()["type"]
+        .as_str()
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+/// The formatting of the text and the color of the block, which markdown cannot express:
+/// the names of the annotations and colors, each once.
+fn dropped_formatting(block: &notion::models::Block) -> Vec<String> {
+    let value = serde_json::to_value(block).unwrap_or_default();
+    let content = match value["type"].as_str() {
+        Some(block_type) => &value[block_type],
+        None => return Vec::new(),
+    };
+
+    let mut formatting: Vec<String> = Vec::new();
+    let mut add = |name: &str| {
+        if !formatting.iter().any(|other| other == name) {
+            formatting.push(name.to_string());
+        }
+    };
+    for rich_text in content["rich_text"].as_array().into_iter().flatten() {
+        let annotations = &rich_text["annotations"];
+        for annotation in ["bold", "italic", "strikethrough", "underline", "code"] {
+            if annotations[annotation].as_bool() == Some(true) {
+                add(annotation);
+            }
+        }
+        if let Some(color) = annotations["color"]
+            .as_str()
+            .filter(|color| *color != "default")
+        {
+            add(color);
+        }
+    }
+    if let Some(color) = content["color"]
+        .as_str()
+        .filter(|color| *color != "default")
+    {
+        add(color);
+    }
+
+    formatting
+}
+
+/// The name of the file from its URL, which is the text of the link to the file.
+fn file_name(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| {
+            url.path_segments()?
+                .next_back()
+                .filter(|segment| !segment.is_empty())
+                .map(ToString::to_string)
+        })
+        .unwrap_or_else(|| url.to_string())
+}
+
+/// Removes the trailing ` {#anchor}` from the heading text and returns the anchor.
+fn split_heading_id(text: &mut Vec<super::tag::RichText>) -> Option<String> {
+    let last = text.last_mut()?;
+    if last.link.is_some() || last.mention.is_some() {
+        return None;
+    }
+
+    let (heading_text, id) = last.text.strip_suffix('}')?.rsplit_once("{#")?;
+    if id.is_empty() || id.contains(|c: char| c.is_whitespace() || c == '{' || c == '}') {
+        return None;
+    }
+
+    let id = id.to_string();
+    last.text = heading_text.trim_end().to_string();
+    if last.text.is_empty() {
+        text.pop();
+    }
+
+    Some(id)
+}
+
+/// The heading text as stored in Notion. Notion has no heading anchors, so an explicit
+/// anchor is kept as ` {#anchor}` at the end of the text.
+pub fn heading_rich_text(
+    mut text: Vec<super::tag::RichText>,
+    id: Option<String>,
+) -> Vec<super::tag::RichText> {
+    if let Some(id) = id {
+        text.push(super::tag::RichText {
+            text: format!(" {{#{id}}}"),
+            link: None,
+            mention: None,
+        });
+    }
+
+    text
+}
+
+/// The info string of a fenced code block in the Notion code language.
+pub fn code_language_name(language: &notion::models::CodeLanguage) -> String {
+    match language {
+        notion::models::CodeLanguage::PlainText => String::new(),
+        language => serde_json::to_value(language)
+            .ok()
+            .and_then(|name| name.as_str().map(ToString::to_string))
+            .unwrap_or_default(),
+    }
+}
+
+/// The Notion code language for the info string of a fenced code block.
+/// Languages that Notion does not know become plain text.
+pub fn code_language(name: &str) -> notion::models::CodeLanguage {
+    serde_json::from_value(serde_json::Value::String(name.to_lowercase()))
+        .unwrap_or(notion::models::CodeLanguage::PlainText)
 }
 
 pub struct MarkdownTagIterator<'a, I>
@@ -132,7 +496,23 @@ where
     I: Iterator<Item = &'a BlockWithChildren>,
 {
     blocks: I,
-    parser: Option<NotionToMarkdownParser>,
+    parser: NotionToMarkdownParser,
+}
+
+impl<'a, I> MarkdownTagIterator<'a, I>
+where
+    I: Iterator<Item = &'a BlockWithChildren>,
+{
+    /// The warnings about the blocks that were converted so far.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.parser.warnings
+    }
+
+    /// The IDs of the heading blocks that were converted so far, in the order of the headings
+    /// in the tags, for [`super::anchors::link_heading_blocks`].
+    pub fn heading_block_ids(&self) -> &[String] {
+        &self.parser.heading_block_ids
+    }
 }
 
 impl<'a, I> Iterator for MarkdownTagIterator<'a, I>
@@ -142,55 +522,132 @@ where
     type Item = super::tag::Tag;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let parser = self.parser.as_mut()?;
+        loop {
+            if let Some(tag) = self.parser.ready_tags.pop_front() {
+                return Some(tag);
+            }
 
-        for block in self.blocks.by_ref() {
-            if let Some(parsed_tag) = parser.parse_block(block) {
-                return Some(parsed_tag);
+            match self.blocks.next() {
+                Some(block) => self.parser.parse_block(block),
+                None => {
+                    self.parser.finish_list();
+                    return self.parser.ready_tags.pop_front();
+                }
             }
         }
-
-        let parser = self.parser.take().expect("was Some before");
-        parser.flush()
     }
 }
 
 impl From<&notion::models::text::RichText> for super::tag::RichText {
     fn from(value: &notion::models::text::RichText) -> Self {
-        use notion::models::text::RichText;
+        use notion::models::text::{MentionObject, RichText};
+        use notion::models::users::User;
 
-        Self {
-            text: match value {
-                RichText::Text { text, .. } => text.content.clone(),
-                RichText::Equation { .. } => {
-                    unimplemented!("Equations are not planned to be implemented")
-                }
-                RichText::Mention { .. } => {
-                    todo!("Mentions are not implemented yet. Encountered mention: {value:#?}")
+        match value {
+            RichText::Text { text, .. } => Self {
+                text: text.content.clone(),
+                link: text.link.as_ref().map(|link| link.url.clone()),
+                mention: None,
+            },
+            RichText::Mention {
+                rich_text,
+                mention: MentionObject::User { user },
+            } => {
+                let (User::Person { common, .. } | User::Bot { common, .. }) = user;
+
+                Self {
+                    text: rich_text.plain_text.clone(),
+                    link: None,
+                    mention: Some(super::tag::Mention::User {
+                        id: common.id.to_string(),
+                    }),
                 }
+            }
+            // NOTE: markdown cannot express these, so only their text is kept, see
+            // `text_only_rich_text_kind`
+            RichText::Mention { rich_text, .. } | RichText::Equation { rich_text } => Self {
+                text: rich_text.plain_text.clone(),
+                link: rich_text.href.clone(),
+                mention: None,
             },
         }
     }
 }
 
+/// The kind of the rich text that is converted to plain text, because markdown cannot express
+/// it, or `None` for rich text that is converted without losing anything.
+fn text_only_rich_text_kind(rich_text: &notion::models::text::RichText) -> Option<&'static str> {
+    use notion::models::text::{MentionObject, RichText};
+
+    match rich_text {
+        RichText::Text { .. } => None,
+        RichText::Mention {
+            mention: MentionObject::User { .. },
+            ..
+        } => None,
+        RichText::Mention {
+            mention: MentionObject::Page { .. },
+            ..
+        } => Some("page mention"),
+        RichText::Mention {
+            mention: MentionObject::Database { .. },
+            ..
+        } => Some("database mention"),
+        RichText::Mention {
+            mention: MentionObject::Date { .. },
+            ..
+        } => Some("date mention"),
+        RichText::Mention {
+            mention: MentionObject::Unknown,
+            ..
+        } => Some("mention"),
+        RichText::Equation { .. } => Some("equation"),
+    }
+}
+
 impl From<&super::tag::RichText> for notion::models::text::RichText {
     fn from(rich_text: &super::tag::RichText) -> Self {
-        Self::Text {
-            text: ::notion::models::text::Text {
-                link: None,
-                content: rich_text.text.to_string(),
+        let common = ::notion::models::text::RichTextCommon {
+            annotations: Some(::notion::models::text::Annotations {
+                bold: Some(false),
+                code: Some(false),
+                color: Some(::notion::models::text::TextColor::Default),
+                italic: Some(false),
+                underline: Some(false),
+                strikethrough: Some(false),
+            }),
+            href: rich_text.link.clone(),
+            plain_text: rich_text.text.to_string(),
+        };
+
+        match &rich_text.mention {
+            Some(super::tag::Mention::User { id }) => Self::Mention {
+                rich_text: common,
+                mention: ::notion::models::text::MentionObject::User {
+                    // NOTE: the API only needs the user ID to create a mention. The other
+                    // fields are required by the `notion` crate's model and are ignored.
+                    user: ::notion::models::users::User::Person {
+                        common: ::notion::models::users::UserCommon {
+                            id: ::notion::ids::UserId::from_str(id)
+                                .expect("notion crate UserId does not do any validation"),
+                            name: None,
+                            avatar_url: None,
+                        },
+                        person: ::notion::models::users::Person {
+                            email: String::new(),
+                        },
+                    },
+                },
             },
-            rich_text: ::notion::models::text::RichTextCommon {
-                annotations: Some(::notion::models::text::Annotations {
-                    bold: Some(false),
-                    code: Some(false),
-                    color: Some(::notion::models::text::TextColor::Default),
-                    italic: Some(false),
-                    underline: Some(false),
-                    strikethrough: Some(false),
-                }),
-                href: None,
-                plain_text: rich_text.text.to_string(),
+            None => Self::Text {
+                text: ::notion::models::text::Text {
+                    link: rich_text
+                        .link
+                        .as_ref()
+                        .map(|url| ::notion::models::text::Link { url: url.clone() }),
+                    content: rich_text.text.to_string(),
+                },
+                rich_text: common,
             },
         }
     }
@@ -283,7 +740,7 @@ mod tests {
 
     #[test]
     fn parses_simple_doc() {
-        let blocks = vec![
+        let blocks = [
             BlockWithChildren {
                 block: Block::Heading1 {
                     common: get_block_common_stub(),
@@ -373,49 +830,739 @@ mod tests {
                 Tag::Heading {
                     level: crate::markdown::tag::HeadingLevel::H1,
                     text: vec![crate::markdown::tag::RichText {
-                        text: "Summary".to_string()
-                    }]
+                        text: "Summary".to_string(),
+                        link: None,
+                        mention: None,
+                    }],
+                    id: None,
                 },
                 Tag::OrderedList {
                     items: vec![
                         OrderedListItem {
                             text: vec![crate::markdown::tag::RichText {
-                                text: "Watch some videos".to_string()
+                                text: "Watch some videos".to_string(),
+                                link: None,
+                                mention: None,
                             }],
                             children: Vec::new(),
                         },
                         OrderedListItem {
                             text: vec![crate::markdown::tag::RichText {
-                                text: "Another list item".to_string()
+                                text: "Another list item".to_string(),
+                                link: None,
+                                mention: None,
                             }],
                             children: vec![Tag::OrderedList {
                                 items: vec![OrderedListItem {
                                     text: vec![crate::markdown::tag::RichText {
-                                        text: "Second level list item".to_string()
+                                        text: "Second level list item".to_string(),
+                                        link: None,
+                                        mention: None,
                                     }],
                                     children: vec![Tag::Paragraph(Paragraph {
                                         text: vec![crate::markdown::tag::RichText {
                                             text: "Second level item's extra description"
-                                                .to_string()
+                                                .to_string(),
+                                            link: None,
+                                            mention: None,
                                         }]
                                     })]
-                                }]
+                                }],
+                                loose: true,
+                                start: 1,
+                                continues: false,
                             }],
                         }
-                    ]
+                    ],
+                    loose: false,
+                    start: 1,
+                    continues: false,
                 },
                 Tag::Heading {
                     level: crate::markdown::tag::HeadingLevel::H1,
                     text: vec![crate::markdown::tag::RichText {
-                        text: "Details".to_string()
-                    }]
+                        text: "Details".to_string(),
+                        link: None,
+                        mention: None,
+                    }],
+                    id: None,
                 },
                 Tag::Paragraph(Paragraph {
                     text: vec![crate::markdown::tag::RichText {
-                        text: "More description".to_string()
+                        text: "More description".to_string(),
+                        link: None,
+                        mention: None,
                     }]
                 })
             ]
         )
     }
+
+    #[test]
+    fn parses_links() {
+        let url = "https://example.com";
+        let blocks = [BlockWithChildren {
+            block: Block::Paragraph {
+                common: get_block_common_stub(),
+                paragraph: TextAndChildren {
+                    rich_text: vec![
+                        get_rich_text("Go to ", None, Some(get_default_annotations())),
+                        get_rich_text(
+                            url,
+                            Some(Link {
+                                url: url.to_string(),
+                            }),
+                            Some(get_default_annotations()),
+                        ),
+                    ],
+                    children: None,
+                    color: TextColor::Default,
+                },
+            },
+            children: Vec::new(),
+        }];
+
+        let result: Vec<_> = NotionToMarkdownParser::default()
+            .feed(blocks.iter())
+            .collect();
+
+        assert_eq!(
+            result,
+            vec![Tag::Paragraph(Paragraph {
+                text: vec![
+                    crate::markdown::tag::RichText {
+                        text: "Go to ".to_string(),
+                        link: None,
+                        mention: None,
+                    },
+                    crate::markdown::tag::RichText {
+                        text: url.to_string(),
+                        link: Some(url.to_string()),
+                        mention: None,
+                    },
+                ]
+            })]
+        );
+        assert_eq!(
+            crate::markdown::to_cmark::to_markdown(&result, &Default::default()),
+            "Go to <https://example.com>"
+        );
+    }
+
+    #[test]
+    fn parses_heading_anchors() {
+        let blocks = [BlockWithChildren {
+            block: Block::Heading2 {
+                common: get_block_common_stub(),
+                heading_2: notion::models::Text {
+                    rich_text: vec![
+                        get_rich_text("Rollout plan", None, Some(get_default_annotations())),
+                        get_rich_text(" {#rollout}", None, Some(get_default_annotations())),
+                    ],
+                },
+            },
+            children: Vec::new(),
+        }];
+
+        let result: Vec<_> = NotionToMarkdownParser::default()
+            .feed(blocks.iter())
+            .collect();
+
+        assert_eq!(
+            result,
+            vec![Tag::Heading {
+                level: crate::markdown::tag::HeadingLevel::H2,
+                text: vec![crate::markdown::tag::RichText {
+                    text: "Rollout plan".to_string(),
+                    link: None,
+                    mention: None,
+                }],
+                id: Some("rollout".to_string()),
+            }]
+        );
+        assert_eq!(
+            crate::markdown::to_cmark::to_markdown(&result, &Default::default()),
+            "## Rollout plan {#rollout}"
+        );
+    }
+
+    #[test]
+    fn keeps_braces_that_are_not_anchors() {
+        let mut text = vec![crate::markdown::tag::RichText {
+            text: "Using {#id with spaces}".to_string(),
+            link: None,
+            mention: None,
+        }];
+
+        assert_eq!(split_heading_id(&mut text), None);
+        assert_eq!(text[0].text, "Using {#id with spaces}");
+    }
+
+    #[test]
+    fn parses_quotes_with_children() {
+        let get_paragraph_block = |text: &str| BlockWithChildren {
+            block: Block::Paragraph {
+                common: get_block_common_stub(),
+                paragraph: TextAndChildren {
+                    rich_text: vec![get_rich_text(text, None, Some(get_default_annotations()))],
+                    children: None,
+                    color: TextColor::Default,
+                },
+            },
+            children: Vec::new(),
+        };
+        let blocks = [BlockWithChildren {
+            block: Block::Quote {
+                common: get_block_common_stub(),
+                quote: TextAndChildren {
+                    rich_text: vec![get_rich_text(
+                        "The first paragraph.",
+                        None,
+                        Some(get_default_annotations()),
+                    )],
+                    children: None,
+                    color: TextColor::Default,
+                },
+            },
+            children: vec![
+                get_paragraph_block("The second one."),
+                get_paragraph_block("And the third."),
+            ],
+        }];
+
+        let result: Vec<_> = NotionToMarkdownParser::default()
+            .feed(blocks.iter())
+            .collect();
+
+        assert_eq!(
+            crate::markdown::to_cmark::to_markdown(&result, &Default::default()),
+            "> The first paragraph.\n>\n> The second one.\n>\n> And the third."
+        );
+    }
+
+    #[test]
+    fn skips_child_pages() {
+        let blocks = [
+            BlockWithChildren {
+                block: Block::ChildPage {
+                    common: get_block_common_stub(),
+                    child_page: notion::models::ChildPageFields {
+                        title: "Backup".to_string(),
+                    },
+                },
+                children: Vec::new(),
+            },
+            BlockWithChildren {
+                block: get_numbered_list_item(vec![get_rich_text(
+                    "Item",
+                    None,
+                    Some(get_default_annotations()),
+                )]),
+                children: Vec::new(),
+            },
+        ];
+
+        let mut tags = NotionToMarkdownParser::default().feed(blocks.iter());
+        let result: Vec<_> = tags.by_ref().collect();
+
+        assert_eq!(
+            crate::markdown::to_cmark::to_markdown(&result, &Default::default()),
+            "1. Item"
+        );
+        assert_eq!(
+            tags.warnings(),
+            [Warning::ChildPageSkipped {
+                block_id: "ac32e0256f9c4fab8b9ddbb3c593ac46".to_string(),
+                title: "Backup".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn keeps_the_text_of_equations_and_mentions_that_markdown_cannot_express() {
+        let paragraph: Block = serde_json::from_value(serde_json::json!({
+            "object": "block",
+            "id": "ac32e0256f9c4fab8b9ddbb3c593ac46",
+            "type": "paragraph",
+            "created_time": "2022-08-01T12:00:00.000Z",
+            "last_edited_time": "2022-08-01T12:00:00.000Z",
+            "has_children": false,
+            "created_by": { "object": "user", "id": "ac32e0256f9c4fab8b9ddbb3c593ac46" },
+            "last_edited_by": { "object": "user", "id": "ac32e0256f9c4fab8b9ddbb3c593ac46" },
+            "paragraph": {
+                "color": "default",
+                "rich_text": [
+                    {
+                        "type": "equation",
+                        "equation": { "expression": "e^{i\\pi}" },
+                        "plain_text": "e^{i\\pi}",
+                        "href": null
+                    },
+                    {
+                        "type": "text",
+                        "text": { "content": " due ", "link": null },
+                        "plain_text": " due ",
+                        "href": null
+                    },
+                    {
+                        "type": "mention",
+                        "mention": { "type": "date", "date": { "start": "2022-08-01" } },
+                        "plain_text": "2022-08-01",
+                        "href": null
+                    }
+                ]
+            }
+        }))
+        .expect("valid paragraph block");
+        let blocks = [BlockWithChildren::leaf(paragraph)];
+
+        let mut tags = NotionToMarkdownParser::default().feed(blocks.iter());
+        let result: Vec<_> = tags.by_ref().collect();
+
+        assert_eq!(
+            crate::markdown::to_cmark::to_markdown(&result, &Default::default()),
+            "e^{i\\pi} due 2022-08-01"
+        );
+        assert_eq!(
+            tags.warnings(),
+            [
+                Warning::RichTextKeptAsText {
+                    block_id: "ac32e0256f9c4fab8b9ddbb3c593ac46".to_string(),
+                    kind: "equation".to_string(),
+                    text: "e^{i\\pi}".to_string(),
+                },
+                Warning::RichTextKeptAsText {
+                    block_id: "ac32e0256f9c4fab8b9ddbb3c593ac46".to_string(),
+                    kind: "date mention".to_string(),
+                    text: "2022-08-01".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn links_to_child_pages() {
+        let mut common = get_block_common_stub();
+        common.id = BlockId::from_str("0b89a6e8-f006-4acc-8ec6-e6902b039e3a").unwrap();
+        let blocks = [BlockWithChildren {
+            block: Block::ChildPage {
+                common,
+                child_page: notion::models::ChildPageFields {
+                    title: "Meeting notes".to_string(),
+                },
+            },
+            children: Vec::new(),
+        }];
+
+        let mut tags = NotionToMarkdownParser::new(NotionParseOptions {
+            child_page_links: true,
+            ..Default::default()
+        })
+        .feed(blocks.iter());
+        let result: Vec<_> = tags.by_ref().collect();
+
+        assert_eq!(
+            crate::markdown::to_cmark::to_markdown(&result, &Default::default()),
+            "[Meeting notes](https://www.notion.so/0b89a6e8f0064acc8ec6e6902b039e3a)"
+        );
+        assert_eq!(tags.warnings(), []);
+    }
+
+    /// Serializes the tags with the Obsidian flavor and parses them back.
+    fn round_trip_obsidian(
+        tags: &[Tag],
+        wiki_links: crate::markdown::obsidian::WikiLinkMap,
+    ) -> (String, Vec<Tag>) {
+        use crate::markdown::from_cmark::{ParseOptions, PulldownCMarkEventParser};
+
+        let markdown = crate::markdown::to_cmark::to_markdown(
+            tags,
+            &crate::markdown::to_cmark::SerializeOptions {
+                flavor: crate::markdown::Flavor::Obsidian,
+                ..Default::default()
+            },
+        );
+        let options = ParseOptions {
+            flavor: crate::markdown::Flavor::Obsidian,
+            wiki_links,
+            ..Default::default()
+        };
+        let parsed = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext(&markdown, options.pulldown_cmark_options()),
+            options,
+        )
+        .parse()
+        .unwrap();
+
+        (markdown, parsed)
+    }
+
+    #[test]
+    fn round_trips_child_page_links_as_wiki_links() {
+        let mut common = get_block_common_stub();
+        common.id = BlockId::from_str("0b89a6e8-f006-4acc-8ec6-e6902b039e3a").unwrap();
+        let blocks = [BlockWithChildren {
+            block: Block::ChildPage {
+                common,
+                child_page: notion::models::ChildPageFields {
+                    title: "Meeting notes".to_string(),
+                },
+            },
+            children: Vec::new(),
+        }];
+        let tags: Vec<_> = NotionToMarkdownParser::new(NotionParseOptions {
+            child_page_links: true,
+            ..Default::default()
+        })
+        .feed(blocks.iter())
+        .collect();
+
+        let (markdown, parsed) = round_trip_obsidian(
+            &tags,
+            [(
+                "Meeting notes".to_string(),
+                "https://www.notion.so/0b89a6e8f0064acc8ec6e6902b039e3a".to_string(),
+            )]
+            .into(),
+        );
+
+        assert_eq!(markdown, "[[Meeting notes]]");
+        assert_eq!(parsed, tags);
+    }
+
+    #[test]
+    fn round_trips_callouts() {
+        let blocks = [BlockWithChildren {
+            block: Block::Callout {
+                common: get_block_common_stub(),
+                callout: notion::models::Callout {
+                    rich_text: vec![get_rich_text(
+                        "Back up the database first",
+                        None,
+                        Some(get_default_annotations()),
+                    )],
+                    icon: notion::models::FileOrEmojiObject::Emoji {
+                        emoji: "⚠️".to_string(),
+                    },
+                    color: TextColor::Default,
+                },
+            },
+            children: Vec::new(),
+        }];
+        let tags: Vec<_> = NotionToMarkdownParser::default()
+            .feed(blocks.iter())
+            .collect();
+
+        let (markdown, parsed) = round_trip_obsidian(&tags, Default::default());
+
+        assert_eq!(markdown, "> [!warning]\n> Back up the database first");
+        assert_eq!(parsed, tags);
+        assert_eq!(
+            crate::markdown::to_cmark::to_markdown(&tags, &Default::default()),
+            "> ⚠️ Back up the database first"
+        );
+    }
+
+    #[test]
+    fn moves_children_of_paragraphs_and_headings_after_them() {
+        let get_paragraph_block = |text: &str, children| BlockWithChildren {
+            block: Block::Paragraph {
+                common: get_block_common_stub(),
+                paragraph: TextAndChildren {
+                    rich_text: vec![get_rich_text(text, None, Some(get_default_annotations()))],
+                    children: None,
+                    color: TextColor::Default,
+                },
+            },
+            children,
+        };
+        let blocks = [
+            BlockWithChildren {
+                block: Block::Heading2 {
+                    common: get_block_common_stub(),
+                    heading_2: notion::models::Text {
+                        rich_text: vec![get_rich_text(
+                            "Toggle heading",
+                            None,
+                            Some(get_default_annotations()),
+                        )],
+                    },
+                },
+                children: vec![get_paragraph_block("Under the heading", Vec::new())],
+            },
+            get_paragraph_block(
+                "Paragraph",
+                vec![
+                    get_paragraph_block("Indented paragraph", Vec::new()),
+                    BlockWithChildren {
+                        block: get_numbered_list_item(vec![get_rich_text(
+                            "Indented item",
+                            None,
+                            Some(get_default_annotations()),
+                        )]),
+                        children: Vec::new(),
+                    },
+                ],
+            ),
+        ];
+
+        let mut tags = NotionToMarkdownParser::default().feed(blocks.iter());
+        let result: Vec<_> = tags.by_ref().collect();
+
+        assert_eq!(
+            crate::markdown::to_cmark::to_markdown(&result, &Default::default()),
+            "## Toggle heading\n\nUnder the heading\n\nParagraph\n\nIndented paragraph\n\n1. Indented item"
+        );
+        assert_eq!(
+            tags.warnings(),
+            [
+                Warning::ChildrenFlattened {
+                    block_id: "ac32e0256f9c4fab8b9ddbb3c593ac46".to_string()
+                },
+                Warning::ChildrenFlattened {
+                    block_id: "ac32e0256f9c4fab8b9ddbb3c593ac46".to_string()
+                },
+            ]
+        );
+    }
+
+    /// Builds blocks from a pattern, where `L` is a numbered list item, `P` is a paragraph,
+    /// `E` is an empty paragraph, and `H` is a heading.
+    fn get_blocks_from_pattern(pattern: &str) -> Vec<BlockWithChildren> {
+        pattern
+            .chars()
+            .map(|kind| {
+                let block = match kind {
+                    'L' => get_numbered_list_item(vec![get_rich_text("item", None, None)]),
+                    'P' | 'E' => Block::Paragraph {
+                        common: get_block_common_stub(),
+                        paragraph: TextAndChildren {
+                            rich_text: if kind == 'P' {
+                                vec![get_rich_text("text", None, None)]
+                            } else {
+                                vec![get_rich_text(" ", None, None)]
+                            },
+                            children: None,
+                            color: TextColor::Default,
+                        },
+                    },
+                    'H' => Block::Heading1 {
+                        common: get_block_common_stub(),
+                        heading_1: notion::models::Text {
+                            rich_text: vec![get_rich_text("heading", None, None)],
+                        },
+                    },
+                    kind => panic!("unknown block kind {kind}"),
+                };
+
+                BlockWithChildren {
+                    block,
+                    children: Vec::new(),
+                }
+            })
+            .collect()
+    }
+
+    /// Describes the tags in the same notation as the patterns. Lists are written as
+    /// `L[...]` with an `i` for each item, followed by the item's children in parentheses.
+    fn describe_tags(tags: &[Tag]) -> String {
+        tags.iter()
+            .map(|tag| match tag {
+                Tag::OrderedList { items, .. } => format!(
+                    "L[{}]",
+                    items
+                        .iter()
+                        .map(|item| if item.children.is_empty() {
+                            "i".to_string()
+                        } else {
+                            format!("i({})", describe_tags(&item.children))
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                ),
+                Tag::Paragraph(paragraph) if paragraph.is_blank() => "E".to_string(),
+                Tag::Paragraph(_) => "P".to_string(),
+                Tag::Heading { .. } => "H".to_string(),
+                Tag::Quote { .. } => "Q".to_string(),
+                Tag::Callout { .. } => "A".to_string(),
+                Tag::Toggle { .. } => "T".to_string(),
+                Tag::Code { .. } => "C".to_string(),
+                Tag::Image { .. } => "I".to_string(),
+                Tag::Divider => "D".to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn parse_pattern(pattern: &str, list_separator_policy: ListSeparatorPolicy) -> Vec<Tag> {
+        NotionToMarkdownParser::new(NotionParseOptions {
+            list_separator_policy,
+            ..Default::default()
+        })
+        .feed(get_blocks_from_pattern(pattern).iter())
+        .collect()
+    }
+
+    #[test]
+    fn interleaved_blocks() {
+        use ListSeparatorPolicy::*;
+
+        let cases = [
+            (Split, "", ""),
+            (Split, "L", "L[i]"),
+            (Split, "LL", "L[i i]"),
+            (Split, "HL", "H L[i]"),
+            (Split, "PP", "P P"),
+            (Split, "LLP", "L[i i] P"),
+            (Split, "PLLH", "P L[i i] H"),
+            (Split, "LPL", "L[i] P L[i]"),
+            (Split, "LHL", "L[i] H L[i]"),
+            (Split, "LEL", "L[i] E L[i]"),
+            (Split, "LPHPL", "L[i] P H P L[i]"),
+            (Merge, "LEL", "L[i i]"),
+            (Merge, "LELEL", "L[i i i]"),
+            (Merge, "LEEL", "L[i] E E L[i]"),
+            (Merge, "LEP", "L[i] E P"),
+            (Merge, "LEH", "L[i] E H"),
+            (Merge, "LE", "L[i] E"),
+            (Merge, "EL", "E L[i]"),
+            (Merge, "LPL", "L[i] P L[i]"),
+            (MergeKeepSeparator, "LEL", "L[i(E) i]"),
+            (MergeKeepSeparator, "LLELL", "L[i i(E) i i]"),
+            (MergeKeepSeparator, "LEP", "L[i] E P"),
+            (MergeKeepSeparator, "LE", "L[i] E"),
+        ];
+
+        for (policy, pattern, expected) in cases {
+            assert_eq!(
+                describe_tags(&parse_pattern(pattern, policy)),
+                expected,
+                "pattern {pattern} with {policy:?}"
+            );
+        }
+    }
+
+    fn get_all_patterns(max_length: usize) -> Vec<String> {
+        let mut patterns = vec![String::new()];
+        let mut previous_length = vec![String::new()];
+        for _ in 0..max_length {
+            previous_length = previous_length
+                .iter()
+                .flat_map(|pattern| ['L', 'P', 'E', 'H'].map(|kind| format!("{pattern}{kind}")))
+                .collect();
+            patterns.extend(previous_length.iter().cloned());
+        }
+
+        patterns
+    }
+
+    /// Writes the tags back as a pattern, with one `L` per list item.
+    fn flatten_tags(tags: &[Tag]) -> String {
+        tags.iter()
+            .map(|tag| match tag {
+                Tag::OrderedList { items, .. } => items
+                    .iter()
+                    .map(|item| format!("L{}", flatten_tags(&item.children)))
+                    .collect(),
+                Tag::Paragraph(paragraph) if paragraph.is_blank() => "E".to_string(),
+                Tag::Paragraph(_) => "P".to_string(),
+                Tag::Heading { .. } => "H".to_string(),
+                Tag::Quote { .. } => "Q".to_string(),
+                Tag::Callout { .. } => "A".to_string(),
+                Tag::Toggle { .. } => "T".to_string(),
+                Tag::Code { .. } => "C".to_string(),
+                Tag::Image { .. } => "I".to_string(),
+                Tag::Divider => "D".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_interleaving_keeps_blocks_in_order() {
+        for pattern in get_all_patterns(6) {
+            for policy in [
+                ListSeparatorPolicy::Split,
+                ListSeparatorPolicy::Merge,
+                ListSeparatorPolicy::MergeKeepSeparator,
+            ] {
+                let tags = parse_pattern(&pattern, policy);
+                let flattened = flatten_tags(&tags);
+
+                if policy == ListSeparatorPolicy::Merge {
+                    assert_eq!(
+                        flattened.replace('E', ""),
+                        pattern.replace('E', ""),
+                        "only separators are dropped for pattern {pattern}"
+                    );
+                } else {
+                    assert_eq!(
+                        flattened, pattern,
+                        "no blocks are lost or reordered for pattern {pattern} with {policy:?}"
+                    );
+                }
+                assert!(
+                    tags.windows(2).all(|pair| !matches!(
+                        pair,
+                        [Tag::OrderedList { .. }, Tag::OrderedList { .. }]
+                    )),
+                    "adjacent lists are merged for pattern {pattern} with {policy:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn empty_paragraphs_keep_the_vertical_space() {
+        use crate::markdown::from_cmark::{ParseOptions, PulldownCMarkEventParser};
+
+        let fetch = |compact| {
+            let tags: Vec<_> = NotionToMarkdownParser::new(NotionParseOptions {
+                compact,
+                ..Default::default()
+            })
+            .feed(get_blocks_from_pattern("PEEEP").iter())
+            .collect();
+            crate::markdown::to_cmark::to_markdown(&tags, &Default::default())
+        };
+        let push = |markdown: &str, keep_empty_paragraphs| {
+            describe_tags(
+                &PulldownCMarkEventParser::new(
+                    pulldown_cmark::Parser::new(markdown),
+                    ParseOptions {
+                        keep_empty_paragraphs,
+                        ..Default::default()
+                    },
+                )
+                .parse()
+                .unwrap(),
+            )
+        };
+
+        let markdown = fetch(false);
+        assert_eq!(markdown, "text\n\n<br>\n\n<br>\n\n<br>\n\ntext");
+        assert_eq!(push(&markdown, true), "P E E E P");
+        assert_eq!(push(&markdown, false), "P P");
+        assert_eq!(fetch(true), "text\n\ntext");
+    }
+
+    #[test]
+    fn compact_keeps_the_empty_paragraphs_between_lists() {
+        let cases = [
+            ("EPEEEPE", "P P"),
+            ("LEL", "L[i] E L[i]"),
+            ("LEEEL", "L[i] E L[i]"),
+            ("LEEP", "L[i] E P"),
+        ];
+
+        for (pattern, expected) in cases {
+            let tags: Vec<_> = NotionToMarkdownParser::new(NotionParseOptions {
+                compact: true,
+                ..Default::default()
+            })
+            .feed(get_blocks_from_pattern(pattern).iter())
+            .collect();
+
+            assert_eq!(describe_tags(&tags), expected, "pattern {pattern}");
+        }
+    }
 }
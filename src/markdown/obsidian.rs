@@ -0,0 +1,287 @@
+//! The markdown of Obsidian vaults: `[[wikilinks]]`, `> [!note]` callouts and the `tags` and
+//! `aliases` front matter keys.
+
+use std::collections::HashMap;
+
+use serde_yaml::{Mapping, Value as YamlValue};
+
+use super::{tag::RichText, warning::Warning};
+
+/// The front matter keys that Obsidian reads, which are written for the page properties
+/// with the same names.
+pub const FRONT_MATTER_KEYS: [&str; 2] = ["tags", "aliases"];
+
+/// The callout type for Notion callouts with an icon that has no type of its own.
+const DEFAULT_CALLOUT_TYPE: &str = "note";
+
+/// Obsidian callout types and the icons of the Notion callouts they stand for.
+const CALLOUT_ICONS: [(&str, &str); 10] = [
+    ("note", "📝"),
+    ("info", "ℹ️"),
+    ("tip", "💡"),
+    ("success", "✅"),
+    ("question", "❓"),
+    ("warning", "⚠️"),
+    ("danger", "🚨"),
+    ("failure", "❌"),
+    ("bug", "🐛"),
+    ("quote", "💬"),
+];
+
+/// The names of the pages that wikilinks point to, and the URLs of these pages in Notion.
+pub type WikiLinkMap = HashMap<String, String>;
+
+/// The Obsidian callout type for the icon of a Notion callout.
+pub fn callout_type(icon: Option<&str>) -> &'static str {
+    CALLOUT_ICONS
+        .iter()
+        .find(|(_, callout_icon)| Some(*callout_icon) == icon)
+        .map_or(DEFAULT_CALLOUT_TYPE, |(callout_type, _)| callout_type)
+}
+
+/// The icon of the Notion callout for the Obsidian callout type, which is case-insensitive.
+pub fn callout_icon(callout_type: &str) -> Option<&'static str> {
+    CALLOUT_ICONS
+        .iter()
+        .find(|(other, _)| other.eq_ignore_ascii_case(callout_type))
+        .map(|(_, icon)| *icon)
+}
+
+/// Splits the `[!type]` marker of a callout from the start of the text of a block quote.
+pub fn split_callout_marker(text: &str) -> Option<(&str, &str)> {
+    let (callout_type, rest) = text.strip_prefix("[!")?.split_once(']')?;
+    if callout_type.is_empty()
+        || !callout_type
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-')
+    {
+        return None;
+    }
+
+    Some((callout_type, rest.trim_start()))
+}
+
+/// Whether the URL points to a Notion page by its ID, like the links to child pages.
+/// Such links are written as wikilinks.
+pub fn is_page_link(url: &str) -> bool {
+    url.strip_prefix("https://www.notion.so/")
+        .is_some_and(|id| id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Splits the `[[wikilinks]]` out of the text. Wikilinks to pages in `wiki_links` become links
+/// to the pages, with the text after `|` as the text of the link. The other wikilinks are kept
+/// as they are, with a warning.
+pub fn split_wiki_links(
+    text: &str,
+    wiki_links: &WikiLinkMap,
+    warnings: &mut Vec<Warning>,
+) -> Vec<RichText> {
+    let mut rich_texts = Vec::new();
+    let mut plain_text = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        let Some(length) = rest[start + 2..].find("]]") else {
+            break;
+        };
+        let (before, wiki_link) = rest.split_at(start);
+        let (wiki_link, after) = wiki_link.split_at(length + 4);
+        plain_text.push_str(before);
+        rest = after;
+
+        let inner = &wiki_link[2..wiki_link.len() - 2];
+        let (target, link_text) = inner.split_once('|').unwrap_or((inner, inner));
+        match wiki_links.get(target.trim()) {
+            Some(url) => {
+                if !plain_text.is_empty() {
+                    rich_texts.push(plain_rich_text(std::mem::take(&mut plain_text)));
+                }
+                rich_texts.push(RichText {
+                    text: link_text.trim().to_string(),
+                    link: Some(url.clone()),
+                    mention: None,
+                });
+            }
+            None => {
+                warnings.push(Warning::WikiLinkNotResolved {
+                    target: target.trim().to_string(),
+                });
+                plain_text.push_str(wiki_link);
+            }
+        }
+    }
+    plain_text.push_str(rest);
+    if !plain_text.is_empty() {
+        rich_texts.push(plain_rich_text(plain_text));
+    }
+
+    rich_texts
+}
+
+fn plain_rich_text(text: String) -> RichText {
+    RichText {
+        text,
+        link: None,
+        mention: None,
+    }
+}
+
+/// Moves the page properties named like the Obsidian front matter keys (in any case) out of
+/// `properties`, and returns them under these keys.
+pub fn take_front_matter_properties(properties: &mut Mapping) -> Mapping {
+    let mut front_matter = Mapping::new();
+    for key in FRONT_MATTER_KEYS {
+        let name = properties
+            .keys()
+            .find(|name| {
+                name.as_str()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(key))
+            })
+            .cloned();
+        if let Some(value) = name.and_then(|name| properties.remove(&name)) {
+            front_matter.insert(key.into(), value);
+        }
+    }
+
+    front_matter
+}
+
+/// Adds the Obsidian front matter keys from `front_matter` to `properties`, under the names
+/// of the page properties they stand for.
+pub fn put_front_matter_properties(
+    front_matter: &YamlValue,
+    property_names: &[&str],
+    properties: &mut Mapping,
+) {
+    for key in FRONT_MATTER_KEYS {
+        let (Some(value), Some(name)) = (
+            front_matter.get(key),
+            property_names
+                .iter()
+                .find(|name| name.eq_ignore_ascii_case(key)),
+        ) else {
+            continue;
+        };
+        properties.insert((*name).into(), value.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::markdown::{
+        from_cmark::{ParseOptions, PulldownCMarkEventParser},
+        tag::{Paragraph, Tag},
+        to_cmark::{to_markdown, SerializeOptions},
+        Flavor,
+    };
+
+    #[test]
+    fn splits_wiki_links() {
+        let wiki_links = WikiLinkMap::from([(
+            "Meetings".to_string(),
+            "https://www.notion.so/8d4e2f603c1b4a7e9f2d5b6a7c8d9e0f".to_string(),
+        )]);
+        let mut warnings = Vec::new();
+
+        let rich_texts = split_wiki_links(
+            "See [[Meetings|the meetings]] and [[Drafts]]",
+            &wiki_links,
+            &mut warnings,
+        );
+
+        assert_eq!(
+            rich_texts,
+            vec![
+                plain_rich_text("See ".to_string()),
+                RichText {
+                    text: "the meetings".to_string(),
+                    link: Some(
+                        "https://www.notion.so/8d4e2f603c1b4a7e9f2d5b6a7c8d9e0f".to_string()
+                    ),
+                    mention: None,
+                },
+                plain_rich_text(" and [[Drafts]]".to_string()),
+            ]
+        );
+        assert_eq!(
+            warnings,
+            vec![Warning::WikiLinkNotResolved {
+                target: "Drafts".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn round_trips_wiki_links() {
+        let options = ParseOptions {
+            flavor: Flavor::Obsidian,
+            wiki_links: WikiLinkMap::from([(
+                "Meetings".to_string(),
+                "https://www.notion.so/8d4e2f603c1b4a7e9f2d5b6a7c8d9e0f".to_string(),
+            )]),
+            ..Default::default()
+        };
+        let markdown = "[[Meetings]] are on Mondays";
+
+        let tags = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext(markdown, options.pulldown_cmark_options()),
+            options,
+        )
+        .parse()
+        .unwrap();
+
+        assert_eq!(
+            tags,
+            vec![Tag::Paragraph(Paragraph {
+                text: vec![
+                    RichText {
+                        text: "Meetings".to_string(),
+                        link: Some(
+                            "https://www.notion.so/8d4e2f603c1b4a7e9f2d5b6a7c8d9e0f".to_string()
+                        ),
+                        mention: None,
+                    },
+                    plain_rich_text(" are on Mondays".to_string()),
+                ]
+            })]
+        );
+        assert_eq!(
+            to_markdown(
+                &tags,
+                &SerializeOptions {
+                    flavor: Flavor::Obsidian,
+                    ..Default::default()
+                }
+            ),
+            markdown
+        );
+    }
+
+    #[test]
+    fn round_trips_front_matter_keys() {
+        let mut properties: Mapping =
+            serde_yaml::from_str("Tags: [release]\nAliases: [Changelog]\nStatus: Done\n").unwrap();
+
+        let front_matter = take_front_matter_properties(&mut properties);
+        assert_eq!(
+            serde_yaml::to_string(&front_matter).unwrap(),
+            "tags:\n- release\naliases:\n- Changelog\n"
+        );
+        assert_eq!(
+            serde_yaml::to_string(&properties).unwrap(),
+            "Status: Done\n"
+        );
+
+        put_front_matter_properties(
+            &front_matter.into(),
+            &["Tags", "Aliases", "Status"],
+            &mut properties,
+        );
+        assert_eq!(
+            serde_yaml::to_string(&properties).unwrap(),
+            "Status: Done\nTags:\n- release\nAliases:\n- Changelog\n"
+        );
+    }
+}
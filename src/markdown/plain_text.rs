@@ -0,0 +1,113 @@
+use super::tag::{Paragraph, RichText, Tag};
+
+/// The maximum length of the content of a single rich text object in the Notion API.
+pub const MAX_RICH_TEXT_LENGTH: usize = 2000;
+
+/// The maximum number of rich text objects in a single block in the Notion API.
+pub const MAX_RICH_TEXTS_PER_BLOCK: usize = 100;
+
+/// Converts plain text into paragraphs separated by blank lines.
+/// No markdown syntax is interpreted, so the text arrives in Notion literally.
+///
+/// Paragraphs that do not fit within the rich text limits of a single block are split
+/// into multiple paragraphs.
+pub fn parse_plain_text(input: &str) -> Vec<Tag> {
+    let input = input.replace("\r\n", "\n");
+
+    let mut tags = Vec::new();
+    let mut paragraph_lines: Vec<&str> = Vec::new();
+    for line in input.lines().chain(std::iter::once("")) {
+        if line.trim().is_empty() {
+            if !paragraph_lines.is_empty() {
+                push_paragraphs(&mut tags, &paragraph_lines.join("\n"));
+                paragraph_lines.clear();
+            }
+        } else {
+            paragraph_lines.push(line);
+        }
+    }
+
+    tags
+}
+
+fn push_paragraphs(tags: &mut Vec<Tag>, text: &str) {
+    let chunks = split_into_chunks(text, MAX_RICH_TEXT_LENGTH);
+
+    for paragraph_chunks in chunks.chunks(MAX_RICH_TEXTS_PER_BLOCK) {
+        tags.push(Tag::Paragraph(Paragraph {
+            text: paragraph_chunks
+                .iter()
+                .map(|chunk| RichText {
+                    text: chunk.to_string(),
+                    link: None,
+                    mention: None,
+                })
+                .collect(),
+        }));
+    }
+}
+
+/// Splits the text into chunks of at most `max_length` characters.
+// NOTE: Notion counts characters, not bytes
+pub fn split_into_chunks(text: &str, max_length: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let chunk_end = rest
+            .char_indices()
+            .nth(max_length)
+            .map_or(rest.len(), |(index, _)| index);
+        let (chunk, remaining) = rest.split_at(chunk_end);
+        chunks.push(chunk);
+        rest = remaining;
+    }
+
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn get_paragraph(text: &str) -> Tag {
+        Tag::Paragraph(Paragraph {
+            text: vec![RichText {
+                text: text.to_string(),
+                link: None,
+                mention: None,
+            }],
+        })
+    }
+
+    #[test]
+    fn splits_paragraphs_on_blank_lines() {
+        let tags = parse_plain_text("first line\nsecond line\n\n  \n\r\nlast paragraph\n");
+
+        assert_eq!(
+            tags,
+            vec![
+                get_paragraph("first line\nsecond line"),
+                get_paragraph("last paragraph")
+            ]
+        );
+    }
+
+    #[test]
+    fn long_paragraphs_fit_within_the_limits() {
+        let long_line = "ą".repeat(MAX_RICH_TEXT_LENGTH * MAX_RICH_TEXTS_PER_BLOCK + 1);
+
+        let tags = parse_plain_text(&long_line);
+
+        assert_eq!(tags.len(), 2);
+        let Tag::Paragraph(Paragraph { text }) = &tags[0] else {
+            panic!("expected a paragraph, found {:?}", tags[0]);
+        };
+        assert_eq!(text.len(), MAX_RICH_TEXTS_PER_BLOCK);
+        assert!(text
+            .iter()
+            .all(|rich_text| rich_text.text.chars().count() == MAX_RICH_TEXT_LENGTH));
+        assert_eq!(tags[1], get_paragraph("ą"));
+    }
+}
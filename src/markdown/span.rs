@@ -0,0 +1,84 @@
+use std::fmt;
+
+/// A position in the source of a document, as the line and the column of a character,
+/// both counted from 1. Columns count characters, not bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// The position of the character at the byte offset in the source.
+    pub fn at(source: &str, offset: usize) -> Self {
+        let before = source.get(..offset).unwrap_or(source);
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+
+        Self {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+
+    /// The position in a document where `prefix` comes before the source of this position,
+    /// like the front matter before the markdown of a file.
+    pub fn after(self, prefix: &str) -> Self {
+        let end = Self::at(prefix, prefix.len());
+        if self.line == 1 {
+            Self {
+                line: end.line,
+                column: end.column + self.column - 1,
+            }
+        } else {
+            Self {
+                line: end.line + self.line - 1,
+                column: self.column,
+            }
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn counts_lines_and_characters() {
+        let source = "# Zażółć\n\ngęślą jaźń\n";
+
+        assert_eq!(Span::at(source, 0), Span { line: 1, column: 1 });
+        assert_eq!(Span::at(source, 2), Span { line: 1, column: 3 });
+        assert_eq!(Span::at(source, 13), Span { line: 2, column: 1 });
+        assert_eq!(
+            Span::at(source, source.find("jaźń").unwrap()),
+            Span { line: 3, column: 7 }
+        );
+        assert_eq!(Span::at(source, source.len()).to_string(), "4:1");
+    }
+
+    #[test]
+    fn moves_positions_after_a_prefix() {
+        let prefix = "---\ntitle: Zażółć\n---\n";
+
+        assert_eq!(
+            Span { line: 1, column: 3 }.after(prefix),
+            Span { line: 4, column: 3 }
+        );
+        assert_eq!(
+            Span { line: 2, column: 5 }.after(prefix),
+            Span { line: 5, column: 5 }
+        );
+        assert_eq!(
+            Span { line: 1, column: 3 }.after("> "),
+            Span { line: 1, column: 5 }
+        );
+    }
+}
@@ -1,43 +1,506 @@
 // TODO: Support:
 // * unordered lists
-// * links
 // * inline code
-// * code blocks
 // * mentions of other pages
 
-#[derive(Debug, PartialEq, Eq)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum HeadingLevel {
     H1,
     H2,
     H3,
+    /// Deeper headings only come from shifting the headings with a heading offset. Notion
+    /// has no such headings, so they are pushed as H3.
+    H4,
+    H5,
+    H6,
+}
+
+impl HeadingLevel {
+    /// The level as a number, from 1 to 6.
+    pub fn number(self) -> u8 {
+        self as u8 + 1
+    }
+
+    /// The level with the number, clamped to H1 and H6.
+    pub fn clamped(number: i16) -> Self {
+        match number {
+            i16::MIN..=1 => Self::H1,
+            2 => Self::H2,
+            3 => Self::H3,
+            4 => Self::H4,
+            5 => Self::H5,
+            _ => Self::H6,
+        }
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RichText {
     pub text: String,
+    /// The URL the text links to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    /// The Notion object mentioned by this text. The text is what is displayed in markdown.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mention: Option<Mention>,
     // TODO: support **strong**, _emphasis_, ~strikethrough``
     // https://github.com/Gelio/notion-edit/issues/1
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Mention {
+    User { id: String },
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Paragraph {
     pub text: Vec<RichText>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl Paragraph {
+    /// Whether the paragraph has no visible text. Notion uses such paragraphs for vertical space.
+    pub fn is_blank(&self) -> bool {
+        self.text
+            .iter()
+            .all(|rich_text| rich_text.text.trim().is_empty())
+    }
+}
+
+/// A block of the document.
+///
+/// In JSON, tags are objects with a snake_case `type` and the fields of the variant, for example
+/// `{"type": "heading", "level": "h1", "text": [{"text": "Summary"}]}`. Optional fields
+/// (`link`, `mention`, `id`, `icon`) are omitted when empty, and `loose` and `start` of ordered lists
+/// default to `false` and `1`.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum Tag {
     Paragraph(Paragraph),
     Heading {
         level: HeadingLevel,
         text: Vec<RichText>,
+        /// An explicit anchor set with `{#anchor}`, so links within the document keep working.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
     },
     OrderedList {
         items: Vec<OrderedListItem>,
+        /// Loose lists have blank lines between the items in markdown.
+        #[serde(default)]
+        loose: bool,
+        /// The number of the first item. Notion lists always start at 1.
+        #[serde(default = "default_list_start")]
+        start: u64,
+        /// Whether the numbering continues the previous list on the same level, with other
+        /// blocks between them. Notion restarts the numbering after other blocks, so such
+        /// lists are only numbered this way with `merge_adjacent_lists`.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        continues: bool,
+    },
+    /// A block quote. In Notion, the first paragraph of the quote is the text of the quote
+    /// block and the rest of the content is nested in it.
+    Quote {
+        text: Vec<RichText>,
+        children: Vec<Tag>,
+    },
+    /// A callout block with its icon, an emoji. Like in quotes, the content after the first
+    /// paragraph is nested in the callout block.
+    Callout {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        icon: Option<String>,
+        text: Vec<RichText>,
+        children: Vec<Tag>,
+    },
+    /// A toggle block, written as `<details>` with a `<summary>` in markdown.
+    Toggle {
+        text: Vec<RichText>,
+        children: Vec<Tag>,
+    },
+    /// A code block. The language is the info string of a fenced code block.
+    Code {
+        language: String,
+        text: String,
     },
+    /// An image block, written as a paragraph with only the image in markdown.
+    Image {
+        url: String,
+    },
+    /// A divider block, a thematic break in markdown.
+    Divider,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct OrderedListItem {
     pub text: Vec<RichText>,
+    #[serde(default)]
     pub children: Vec<Tag>,
 }
+
+fn default_list_start() -> u64 {
+    1
+}
+
+/// Removes the `# heading` from the start of the document and returns its text.
+/// It is the title of the page when the title is written as a heading.
+pub fn take_title_heading(tags: &mut Vec<Tag>) -> Option<String> {
+    match tags.first() {
+        Some(Tag::Heading {
+            level: HeadingLevel::H1,
+            ..
+        }) => match tags.remove(0) {
+            Tag::Heading { text, .. } => Some(text.into_iter().map(|text| text.text).collect()),
+            _ => unreachable!("the first tag is a heading"),
+        },
+        _ => None,
+    }
+}
+
+impl Tag {
+    /// An ordered list that is only loose when an item has content that needs blank lines
+    /// around it in markdown (anything other than a nested list).
+    pub fn ordered_list(items: Vec<OrderedListItem>) -> Self {
+        let loose = items.iter().any(|item| {
+            item.children
+                .iter()
+                .any(|child| !matches!(child, Tag::OrderedList { .. }))
+        });
+
+        Tag::OrderedList {
+            items,
+            loose,
+            start: 1,
+            continues: false,
+        }
+    }
+
+    /// Calls `f` with the rich text of this tag and of all the tags nested in it.
+    pub fn visit_rich_text_mut<F>(&mut self, f: &mut F)
+    where
+        F: FnMut(&mut Vec<RichText>),
+    {
+        match self {
+            Tag::Paragraph(Paragraph { text }) | Tag::Heading { text, .. } => f(text),
+            Tag::OrderedList { items, .. } => {
+                for item in items {
+                    f(&mut item.text);
+                    for child in &mut item.children {
+                        child.visit_rich_text_mut(f);
+                    }
+                }
+            }
+            Tag::Quote { text, children }
+            | Tag::Callout { text, children, .. }
+            | Tag::Toggle { text, children } => {
+                f(text);
+                for child in children {
+                    child.visit_rich_text_mut(f);
+                }
+            }
+            Tag::Code { .. } | Tag::Image { .. } | Tag::Divider => {}
+        }
+    }
+
+    /// Calls `f` with the URL of the images in this tag and in all the tags nested in it.
+    pub fn visit_image_urls_mut<F>(&mut self, f: &mut F)
+    where
+        F: FnMut(&mut String),
+    {
+        match self {
+            Tag::Image { url } => f(url),
+            Tag::OrderedList { items, .. } => {
+                for child in items.iter_mut().flat_map(|item| &mut item.children) {
+                    child.visit_image_urls_mut(f);
+                }
+            }
+            Tag::Quote { children, .. }
+            | Tag::Callout { children, .. }
+            | Tag::Toggle { children, .. } => {
+                for child in children {
+                    child.visit_image_urls_mut(f);
+                }
+            }
+            Tag::Paragraph(_) | Tag::Heading { .. } | Tag::Code { .. } | Tag::Divider => {}
+        }
+    }
+}
+
+/// Strategies that generate documents for property tests. The documents stay within what both
+/// Notion and markdown can express, so converting them back and forth must not change them.
+#[cfg(test)]
+pub(crate) mod strategies {
+    use proptest::{collection::vec, option, prelude::*};
+
+    use super::*;
+
+    /// The conversion that the generated documents must survive.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Target {
+        /// Serializing to markdown and parsing it back, which has no images, callouts
+        /// or mentions (they need the Obsidian flavor or a user mapping).
+        Markdown,
+        /// Creating the blocks in Notion, which accepts every tag.
+        Notion,
+    }
+
+    /// How deeply blocks are nested in one another.
+    const MAX_DEPTH: u32 = 3;
+
+    /// Words of letters and digits. Markup characters are left out, as the text is not
+    /// escaped everywhere they would be parsed as markup.
+    fn words() -> impl Strategy<Value = String> {
+        vec("[a-z0-9]{1,8}", 1..4).prop_map(|words| words.join(" "))
+    }
+
+    fn rich_text(target: Target) -> impl Strategy<Value = RichText> {
+        let link = option::of("[a-z]{1,8}".prop_map(|path| format!("https://example.com/{path}")));
+        let plain = (words(), link).prop_map(|(text, link)| RichText {
+            text,
+            link,
+            mention: None,
+        });
+        let mention = "[a-z]{1,8}".prop_map(|name| RichText {
+            text: format!("@{name}"),
+            link: None,
+            mention: Some(Mention::User {
+                id: "ac32e025-6f9c-4fab-8b9d-dbb3c593ac46".to_string(),
+            }),
+        });
+
+        match target {
+            Target::Markdown => plain.boxed(),
+            Target::Notion => prop_oneof![4 => plain, 1 => mention].boxed(),
+        }
+    }
+
+    /// Non-empty text, with the spans merged the way the markdown parser merges them: spans
+    /// next to each other only differ in their link or are mentions.
+    fn text(target: Target) -> impl Strategy<Value = Vec<RichText>> {
+        vec(rich_text(target), 1..4).prop_map(|spans| {
+            let mut text: Vec<RichText> = Vec::new();
+            for span in spans {
+                match text.last_mut() {
+                    Some(previous)
+                        if previous.link == span.link
+                            && previous.mention.is_none()
+                            && span.mention.is_none() =>
+                    {
+                        previous.text.push_str(&span.text)
+                    }
+                    _ => text.push(span),
+                }
+            }
+
+            text
+        })
+    }
+
+    fn plain_text() -> impl Strategy<Value = Vec<RichText>> {
+        words().prop_map(|text| {
+            vec![RichText {
+                text,
+                link: None,
+                mention: None,
+            }]
+        })
+    }
+
+    fn heading(target: Target) -> impl Strategy<Value = Tag> {
+        (1..=3u8, text(target), option::of("[a-z]{1,8}")).prop_map(|(level, text, id)| {
+            Tag::Heading {
+                level: match level {
+                    1 => HeadingLevel::H1,
+                    2 => HeadingLevel::H2,
+                    _ => HeadingLevel::H3,
+                },
+                text,
+                id,
+            }
+        })
+    }
+
+    fn leaf(target: Target) -> BoxedStrategy<Tag> {
+        let paragraph = text(target).prop_map(|text| Tag::Paragraph(Paragraph { text }));
+        let code = (
+            prop_oneof!["", "rust", "python"],
+            vec(words(), 1..4).prop_map(|lines| lines.join("\n")),
+        )
+            .prop_map(|(language, text)| Tag::Code { language, text });
+        let image = "[a-z]{1,8}".prop_map(|name| Tag::Image {
+            url: format!("https://example.com/{name}.png"),
+        });
+
+        let divider = proptest::strategy::LazyJust::new(|| Tag::Divider);
+
+        match target {
+            Target::Markdown => {
+                prop_oneof![3 => paragraph, 1 => heading(target), 1 => code, 1 => divider].boxed()
+            }
+            Target::Notion => prop_oneof![
+                3 => paragraph,
+                1 => heading(target),
+                1 => code,
+                1 => image,
+                1 => divider
+            ]
+            .boxed(),
+        }
+    }
+
+    /// Sibling blocks. A list right after another list would continue it in markdown, so only
+    /// the first one is kept.
+    fn blocks(
+        block: BoxedStrategy<Tag>,
+        size: std::ops::Range<usize>,
+    ) -> impl Strategy<Value = Vec<Tag>> {
+        vec(block, size).prop_map(|tags| {
+            let mut blocks: Vec<Tag> = Vec::new();
+            for tag in tags {
+                if matches!(
+                    (blocks.last(), &tag),
+                    (Some(Tag::OrderedList { .. }), Tag::OrderedList { .. })
+                ) {
+                    continue;
+                }
+                blocks.push(tag);
+            }
+
+            blocks
+        })
+    }
+
+    fn block(target: Target) -> BoxedStrategy<Tag> {
+        leaf(target)
+            .prop_recursive(MAX_DEPTH, 32, 4, move |inner| {
+                let list = vec(
+                    (text(target), blocks(inner.clone(), 0..3))
+                        .prop_map(|(text, children)| OrderedListItem { text, children }),
+                    1..4,
+                )
+                .prop_map(Tag::ordered_list);
+                let quote = (text(target), blocks(inner.clone(), 0..3))
+                    .prop_map(|(text, children)| Tag::Quote { text, children });
+                let toggle = (plain_text(), blocks(inner.clone(), 0..3))
+                    .prop_map(|(text, children)| Tag::Toggle { text, children });
+                let callout = (
+                    option::of(prop_oneof!["💡", "⚠️", "📌"]),
+                    text(target),
+                    blocks(inner.clone(), 0..3),
+                )
+                    .prop_map(|(icon, text, children)| Tag::Callout {
+                        icon,
+                        text,
+                        children,
+                    });
+
+                match target {
+                    Target::Markdown => {
+                        prop_oneof![2 => inner, 1 => list, 1 => quote, 1 => toggle].boxed()
+                    }
+                    Target::Notion => {
+                        prop_oneof![2 => inner, 1 => list, 1 => quote, 1 => toggle, 1 => callout]
+                            .boxed()
+                    }
+                }
+            })
+            .boxed()
+    }
+
+    /// A document of a few blocks, with lists, quotes and toggles nested up to a few levels.
+    pub fn document(target: Target) -> impl Strategy<Value = Vec<Tag>> {
+        blocks(block(target), 0..6)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn json_shape() {
+        let tags = vec![
+            Tag::Heading {
+                level: HeadingLevel::H1,
+                text: vec![RichText {
+                    text: "Summary".to_string(),
+                    link: None,
+                    mention: None,
+                }],
+                id: None,
+            },
+            Tag::ordered_list(vec![OrderedListItem {
+                text: vec![RichText {
+                    text: "@gelio".to_string(),
+                    link: None,
+                    mention: Some(Mention::User {
+                        id: "ac32e025-6f9c-4fab-8b9d-dbb3c593ac46".to_string(),
+                    }),
+                }],
+                children: Vec::new(),
+            }]),
+            Tag::Paragraph(Paragraph {
+                text: vec![RichText {
+                    text: "docs".to_string(),
+                    link: Some("https://example.com".to_string()),
+                    mention: None,
+                }],
+            }),
+        ];
+        let expected_json = json!([
+            {
+                "type": "heading",
+                "level": "h1",
+                "text": [{ "text": "Summary" }]
+            },
+            {
+                "type": "ordered_list",
+                "items": [{
+                    "text": [{
+                        "text": "@gelio",
+                        "mention": { "type": "user", "id": "ac32e025-6f9c-4fab-8b9d-dbb3c593ac46" }
+                    }],
+                    "children": []
+                }],
+                "loose": false,
+                "start": 1
+            },
+            {
+                "type": "paragraph",
+                "text": [{ "text": "docs", "link": "https://example.com" }]
+            }
+        ]);
+
+        assert_eq!(serde_json::to_value(&tags).unwrap(), expected_json);
+        assert_eq!(
+            serde_json::from_value::<Vec<Tag>>(expected_json).unwrap(),
+            tags
+        );
+    }
+
+    #[test]
+    fn json_defaults() {
+        let tags: Vec<Tag> = serde_json::from_value(json!([{
+            "type": "ordered_list",
+            "items": [{ "text": [{ "text": "Item" }] }]
+        }]))
+        .unwrap();
+
+        assert_eq!(
+            tags,
+            vec![Tag::ordered_list(vec![OrderedListItem {
+                text: vec![RichText {
+                    text: "Item".to_string(),
+                    link: None,
+                    mention: None,
+                }],
+                children: Vec::new(),
+            }])]
+        );
+    }
+}
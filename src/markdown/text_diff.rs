@@ -0,0 +1,194 @@
+use std::fmt::{self, Display, Write};
+
+use diffy::{DiffOptions, Line, Patch};
+
+use super::frontmatter::FrontMatter;
+
+const CONTEXT_LINES: usize = 3;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// A line-based comparison of two markdown documents, ignoring the differences that do not
+/// reach Notion: the front matter, line endings and trailing newlines.
+pub struct DocumentDiff {
+    old: String,
+    new: String,
+}
+
+/// The number of lines added and removed by a [`DocumentDiff`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffStat {
+    pub added: usize,
+    pub removed: usize,
+}
+
+impl DocumentDiff {
+    pub fn new(old: &str, new: &str) -> Self {
+        Self {
+            old: normalize(old),
+            new: normalize(new),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.old == self.new
+    }
+
+    pub fn stat(&self) -> DiffStat {
+        let mut stat = DiffStat {
+            added: 0,
+            removed: 0,
+        };
+        for hunk in self.patch().hunks() {
+            for line in hunk.lines() {
+                match line {
+                    Line::Insert(_) => stat.added += 1,
+                    Line::Delete(_) => stat.removed += 1,
+                    Line::Context(_) => {}
+                }
+            }
+        }
+
+        stat
+    }
+
+    /// The diff in the unified format, with ANSI colors when `color` is set.
+    pub fn unified(&self, old_name: &str, new_name: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("{style}{text}{RESET}")
+            } else {
+                text.to_string()
+            }
+        };
+
+        let mut buf = String::new();
+        if self.is_empty() {
+            return buf;
+        }
+        writeln!(buf, "{}", paint(BOLD, &format!("--- {old_name}"))).unwrap();
+        writeln!(buf, "{}", paint(BOLD, &format!("+++ {new_name}"))).unwrap();
+
+        for hunk in self.patch().hunks() {
+            let header = format!("@@ -{} +{} @@", hunk.old_range(), hunk.new_range());
+            writeln!(buf, "{}", paint(CYAN, &header)).unwrap();
+            for line in hunk.lines() {
+                // NOTE: normalized documents end with a newline, so every line has one
+                let (style, prefix, text) = match line {
+                    Line::Insert(text) => (Some(GREEN), "+", text),
+                    Line::Delete(text) => (Some(RED), "-", text),
+                    Line::Context(text) => (None, " ", text),
+                };
+                let line = format!("{prefix}{}", text.trim_end_matches('\n'));
+                let line = match style {
+                    Some(style) => paint(style, &line),
+                    None => line,
+                };
+                writeln!(buf, "{line}").unwrap();
+            }
+        }
+
+        buf
+    }
+
+    fn patch(&self) -> Patch<'_, str> {
+        DiffOptions::new()
+            .set_context_len(CONTEXT_LINES)
+            .create_patch(&self.old, &self.new)
+    }
+}
+
+impl Display for DiffStat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} removed",
+            pluralize_lines(self.added),
+            pluralize_lines(self.removed)
+        )
+    }
+}
+
+fn pluralize_lines(count: usize) -> String {
+    if count == 1 {
+        format!("{count} line")
+    } else {
+        format!("{count} lines")
+    }
+}
+
+/// Drops the front matter, uses `\n` line endings and ends the document with a single newline.
+fn normalize(document: &str) -> String {
+    let (_, content) = FrontMatter::split(document);
+    let mut content = content.replace("\r\n", "\n").trim().to_string();
+    if !content.is_empty() {
+        content.push('\n');
+    }
+
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn assert_diff(old: &str, new: &str, expected_diff: &str) {
+        let diff = DocumentDiff::new(old, new);
+
+        assert_eq!(diff.unified("Notion", "page.md", false), expected_diff);
+    }
+
+    #[test]
+    fn edited_paragraph() {
+        assert_diff(
+            include_str!("../../tests/fixtures/diff/edited_paragraph/old.md"),
+            include_str!("../../tests/fixtures/diff/edited_paragraph/new.md"),
+            include_str!("../../tests/fixtures/diff/edited_paragraph/diff.patch"),
+        );
+    }
+
+    #[test]
+    fn same_content() {
+        assert_diff(
+            include_str!("../../tests/fixtures/diff/same_content/old.md"),
+            include_str!("../../tests/fixtures/diff/same_content/new.md"),
+            "",
+        );
+    }
+
+    #[test]
+    fn ignores_line_endings_and_trailing_newlines() {
+        assert!(DocumentDiff::new("# Notes\n\nText\n", "# Notes\r\n\r\nText\n\n\n").is_empty());
+        assert!(DocumentDiff::new("", "\n").is_empty());
+    }
+
+    #[test]
+    fn counts_added_and_removed_lines() {
+        let diff = DocumentDiff::new("One\nTwo\nThree\n", "One\n2\nThree\nFour\n");
+
+        assert_eq!(
+            diff.stat(),
+            DiffStat {
+                added: 2,
+                removed: 1
+            }
+        );
+        assert_eq!(diff.stat().to_string(), "2 lines added, 1 line removed");
+    }
+
+    #[test]
+    fn colors_the_changes() {
+        let diff = DocumentDiff::new("Old\n", "New\n");
+
+        assert_eq!(
+            diff.unified("a", "b", true),
+            "\x1b[1m--- a\x1b[0m\n\x1b[1m+++ b\x1b[0m\n\x1b[36m@@ -1 +1 @@\x1b[0m\n\x1b[31m-Old\x1b[0m\n\x1b[32m+New\x1b[0m\n"
+        );
+    }
+}
@@ -1,27 +1,379 @@
-use pulldown_cmark::{CowStr, Event};
+use std::{borrow::Cow, ops::Range};
 
-pub fn get_pulldown_cmark_events(tag: &super::tag::Tag) -> Vec<Event> {
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, LinkType};
+
+use super::{obsidian, Flavor, NewlineBehavior};
+
+const QUOTE_PADDING: &str = "> ";
+const CODE_FENCE: &str = "```";
+/// The markdown for an empty Notion paragraph.
+const EMPTY_PARAGRAPH: &str = "<br>\n";
+const THEMATIC_BREAK: &str = "***";
+/// Stands in for the missing text of a list item that only holds children.
+const EMPTY_LIST_ITEM: &str = "<!-- empty -->\n";
+
+#[derive(Debug, Default, Clone)]
+pub struct SerializeOptions {
+    pub newline_behavior: NewlineBehavior,
+    pub list_numbering: ListNumbering,
+    /// Write curly quotes, dashes and ellipses as ASCII punctuation.
+    pub ascii_punctuation: bool,
+    pub flavor: Flavor,
+}
+
+/// How the items of ordered lists are numbered in markdown.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ListNumbering {
+    /// Every item repeats the number of the first item (`1.`, `1.`, `1.`),
+    /// which keeps diffs small when items are added or removed.
+    #[default]
+    Repeated,
+    /// Every item has its own number (`1.`, `2.`, `3.`).
+    Incrementing,
+}
+
+/// Serializes the tags into a markdown document.
+pub fn to_markdown(tags: &[super::tag::Tag], options: &SerializeOptions) -> String {
+    let mut writer = MarkdownWriter::new(options.clone());
+    writer.write_tags(tags);
+
+    writer.finish()
+}
+
+/// Serializes a markdown document a few tags at a time, so the start of the document can be
+/// written out before the rest is converted.
+#[derive(Debug, Default)]
+pub struct MarkdownWriter {
+    options: SerializeOptions,
+    /// The markdown that was serialized, but not taken yet.
+    buf: String,
+    state: Option<pulldown_cmark_to_cmark::State<'static>>,
+    at_line_start: bool,
+    /// Whether each list that is currently serialized is loose. Only the items of loose
+    /// lists start with a paragraph.
+    loose_lists: Vec<bool>,
+    at_item_start: bool,
+    /// Trailing whitespace in `buf` that is a part of the content (code) or of the syntax
+    /// (hard breaks written as two spaces), which must not be trimmed.
+    significant_whitespace: Vec<Range<usize>>,
+    code_block_start: Option<usize>,
+}
+
+impl MarkdownWriter {
+    pub fn new(options: SerializeOptions) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
+
+    /// Serializes the next tags of the document.
+    pub fn write_tags(&mut self, tags: &[super::tag::Tag]) {
+        for event in tags
+            .iter()
+            .flat_map(|tag| get_pulldown_cmark_events(tag, &self.options))
+        {
+            match &event {
+                Event::Start(pulldown_cmark::Tag::List(_)) => self.loose_lists.push(false),
+                Event::End(pulldown_cmark::Tag::List(_)) => {
+                    self.loose_lists.pop();
+                }
+                Event::Start(pulldown_cmark::Tag::Paragraph) if self.at_item_start => {
+                    if let Some(loose) = self.loose_lists.last_mut() {
+                        *loose = true;
+                    }
+                }
+                _ => {}
+            }
+            self.at_item_start = matches!(event, Event::Start(pulldown_cmark::Tag::Item));
+
+            let next_at_line_start = matches!(
+                event,
+                Event::Start(pulldown_cmark::Tag::Paragraph | pulldown_cmark::Tag::Item)
+                    | Event::SoftBreak
+                    | Event::HardBreak
+            );
+
+            match event {
+                // NOTE: pulldown_cmark_to_cmark always prints hard breaks as two trailing spaces.
+                Event::HardBreak if self.options.newline_behavior == NewlineBehavior::Backslash => {
+                    self.buf.push_str("\\\n");
+                    if let Some(state) = &self.state {
+                        state
+                            .padding
+                            .iter()
+                            .for_each(|padding| self.buf.push_str(padding));
+                    }
+                }
+                Event::Text(text) if self.at_line_start && escape_line_start(&text).is_some() => {
+                    // NOTE: pulldown_cmark_to_cmark would escape the backslash, so the escaped text
+                    // is written directly. The empty text event flushes pending newlines and padding.
+                    self.state = Some(resume(
+                        Event::Text(CowStr::Borrowed("")),
+                        &mut self.buf,
+                        self.state.take(),
+                    ));
+                    self.buf
+                        .push_str(&escape_line_start(&text).expect("checked in the guard"));
+                }
+                // NOTE: pulldown_cmark_to_cmark starts block quotes with an empty line and
+                // indents them with an extra space.
+                Event::Start(pulldown_cmark::Tag::BlockQuote) => {
+                    let mut current_state = self.state.take().unwrap_or_default();
+                    consume_newlines(&mut self.buf, &mut current_state);
+                    self.buf.push_str(QUOTE_PADDING);
+                    current_state.padding.push(QUOTE_PADDING.into());
+                    self.state = Some(current_state);
+                }
+                // NOTE: pulldown_cmark_to_cmark starts code blocks at the start of the document
+                // with an empty line.
+                Event::Start(pulldown_cmark::Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                    let mut current_state = self.state.take().unwrap_or_default();
+                    consume_newlines(&mut self.buf, &mut current_state);
+                    self.buf.push_str(CODE_FENCE);
+                    self.buf.push_str(&info);
+                    push_newline(&mut self.buf, &current_state);
+                    current_state.is_in_code_block = true;
+                    self.state = Some(current_state);
+                    self.code_block_start = Some(self.buf.len());
+                }
+                Event::End(pulldown_cmark::Tag::CodeBlock(_)) => {
+                    if let Some(start) = self.code_block_start.take() {
+                        self.significant_whitespace.push(start..self.buf.len());
+                    }
+                    self.state = Some(resume(event, &mut self.buf, self.state.take()));
+                }
+                Event::HardBreak => {
+                    let start = self.buf.len();
+                    self.state = Some(resume(event, &mut self.buf, self.state.take()));
+                    self.significant_whitespace.push(start..self.buf.len());
+                }
+                // NOTE: Obsidian syntax is written as inline HTML, as pulldown_cmark_to_cmark would
+                // escape the leading `[` of text. The empty text event flushes pending newlines
+                // and padding.
+                Event::Html(syntax) if syntax.starts_with('[') => {
+                    self.state = Some(resume(
+                        Event::Text(CowStr::Borrowed("")),
+                        &mut self.buf,
+                        self.state.take(),
+                    ));
+                    self.buf.push_str(&syntax);
+                }
+                // NOTE: pulldown_cmark_to_cmark does not pad the block that follows HTML.
+                // HTML blocks are followed by an empty line, so markdown inside `<details>` is parsed.
+                Event::Html(html) => {
+                    let mut current_state = self.state.take().unwrap_or_default();
+                    consume_newlines(&mut self.buf, &mut current_state);
+                    for (index, line) in html.trim_end_matches('\n').split('\n').enumerate() {
+                        if index > 0 {
+                            push_newline(&mut self.buf, &current_state);
+                        }
+                        self.buf.push_str(line);
+                    }
+                    current_state.newlines_before_start = 2;
+                    self.state = Some(current_state);
+                }
+                // NOTE: pulldown_cmark_to_cmark writes thematic breaks as `---`, which at the start
+                // of the document would open front matter
+                Event::Rule => {
+                    let mut current_state = self.state.take().unwrap_or_default();
+                    consume_newlines(&mut self.buf, &mut current_state);
+                    self.buf.push_str(THEMATIC_BREAK);
+                    current_state.newlines_before_start = 2;
+                    self.state = Some(current_state);
+                }
+                // NOTE: pulldown_cmark_to_cmark separates items with an empty line when the previous
+                // item ends with a paragraph, no matter whether the list is loose
+                Event::Start(pulldown_cmark::Tag::Item) => {
+                    let mut current_state = self.state.take().unwrap_or_default();
+                    if current_state.newlines_before_start > 0 {
+                        current_state.newlines_before_start = match self.loose_lists.last() {
+                            Some(true) => 2,
+                            _ => 1,
+                        };
+                    }
+                    self.state = Some(resume(event, &mut self.buf, Some(current_state)));
+                }
+                // NOTE: pulldown_cmark_to_cmark only follows top-level lists with an empty line, so
+                // a paragraph after a nested list would continue its last item. The start of the
+                // next item of a tight list takes the empty line back.
+                Event::End(pulldown_cmark::Tag::List(_)) => {
+                    let mut current_state = resume(event, &mut self.buf, self.state.take());
+                    current_state.newlines_before_start =
+                        current_state.newlines_before_start.max(2);
+                    self.state = Some(current_state);
+                }
+                Event::End(pulldown_cmark::Tag::Item)
+                    if self.options.list_numbering == ListNumbering::Incrementing =>
+                {
+                    let mut current_state = resume(event, &mut self.buf, self.state.take());
+                    // NOTE: pulldown_cmark_to_cmark prints the start number for every item
+                    if let Some(Some(number)) = current_state.list_stack.last_mut() {
+                        *number += 1;
+                    }
+                    self.state = Some(current_state);
+                }
+                event => {
+                    self.state = Some(resume(event, &mut self.buf, self.state.take()));
+                }
+            }
+
+            self.at_line_start = next_at_line_start;
+        }
+    }
+
+    /// The lines of the document that are complete, which the next tags cannot change.
+    /// Code blocks are only taken once they end, as their whitespace is kept.
+    pub fn take_complete_lines(&mut self) -> String {
+        let limit = self.code_block_start.unwrap_or(self.buf.len());
+        let Some(end) = self.buf[..limit].rfind('\n').map(|newline| newline + 1) else {
+            return String::new();
+        };
+
+        let lines = trim_trailing_whitespace(&self.buf[..end], &self.significant_whitespace);
+        self.buf.drain(..end);
+        self.significant_whitespace = self
+            .significant_whitespace
+            .drain(..)
+            .filter(|range| range.end > end)
+            .map(|range| range.start.saturating_sub(end)..range.end - end)
+            .collect();
+        if let Some(start) = &mut self.code_block_start {
+            *start -= end;
+        }
+
+        lines
+    }
+
+    /// The rest of the document.
+    pub fn finish(mut self) -> String {
+        if let Some(state) = self.state.take() {
+            state.finalize(&mut self.buf).expect("serialization failed");
+        }
+
+        trim_trailing_whitespace(&self.buf, &self.significant_whitespace)
+    }
+}
+
+/// Removes the whitespace at the end of lines, like the padding of empty lines inside
+/// list items, unless it overlaps one of the `significant_whitespace` ranges.
+fn trim_trailing_whitespace(buf: &str, significant_whitespace: &[Range<usize>]) -> String {
+    let mut trimmed = String::with_capacity(buf.len());
+    let mut line_start = 0;
+    for line in buf.split_inclusive('\n') {
+        let content = line.strip_suffix('\n').unwrap_or(line);
+        let content_end = line_start + content.trim_end().len();
+        let line_end = line_start + content.len();
+        let is_significant = significant_whitespace
+            .iter()
+            .any(|range| range.start < line_end && content_end < range.end);
+
+        if is_significant {
+            trimmed.push_str(line);
+        } else {
+            trimmed.push_str(content.trim_end());
+            trimmed.push_str(&line[content.len()..]);
+        }
+        line_start += line.len();
+    }
+
+    trimmed
+}
+
+fn resume(
+    event: Event<'_>,
+    buf: &mut String,
+    state: Option<pulldown_cmark_to_cmark::State<'static>>,
+) -> pulldown_cmark_to_cmark::State<'static> {
+    let options = pulldown_cmark_to_cmark::Options {
+        code_block_token_count: CODE_FENCE.len(),
+        ..Default::default()
+    };
+
+    pulldown_cmark_to_cmark::cmark_resume_with_options(std::iter::once(event), buf, state, options)
+        .expect("serialization failed")
+}
+
+/// Writes the newlines that should precede the next block.
+fn consume_newlines(buf: &mut String, state: &mut pulldown_cmark_to_cmark::State<'static>) {
+    while state.newlines_before_start > 0 {
+        state.newlines_before_start -= 1;
+        push_newline(buf, state);
+    }
+}
+
+fn push_newline(buf: &mut String, state: &pulldown_cmark_to_cmark::State<'static>) {
+    buf.push('\n');
+    state
+        .padding
+        .iter()
+        .for_each(|padding| buf.push_str(padding));
+}
+
+/// Escapes text at the start of a line that would otherwise be parsed as a list marker
+/// (`1986. `, `1) `, `- `, `+ `) or as a thematic break/setext heading underline (`---`).
+/// Returns `None` when the text can be written as-is.
+fn escape_line_start(text: &str) -> Option<String> {
+    let is_marker_end = |rest: &str| rest.is_empty() || rest.starts_with(char::is_whitespace);
+
+    let digits = text.chars().take_while(char::is_ascii_digit).count();
+    // NOTE: CommonMark list markers have at most 9 digits
+    if (1..=9).contains(&digits) {
+        let (number, rest) = text.split_at(digits);
+        let mut rest_chars = rest.chars();
+        return match rest_chars.next() {
+            Some(delimiter @ ('.' | ')')) if is_marker_end(rest_chars.as_str()) => {
+                Some(format!("{number}\\{delimiter}{}", rest_chars.as_str()))
+            }
+            _ => None,
+        };
+    }
+
+    let trimmed = text.trim_end();
+    let is_dash_line = !trimmed.is_empty() && trimmed.chars().all(|c| c == '-' || c == ' ');
+    let is_bullet = (text.starts_with('-') || text.starts_with('+')) && is_marker_end(&text[1..]);
+    if is_dash_line || is_bullet {
+        return Some(format!("\\{text}"));
+    }
+
+    None
+}
+
+pub fn get_pulldown_cmark_events<'a>(
+    tag: &'a super::tag::Tag,
+    options: &SerializeOptions,
+) -> Vec<Event<'a>> {
     match tag {
-        super::tag::Tag::Heading { level, text } => {
-            let tag = pulldown_cmark::Tag::Heading(level.into(), None, Vec::new());
+        super::tag::Tag::Heading { level, text, id } => {
+            let tag = pulldown_cmark::Tag::Heading(level.into(), id.as_deref(), Vec::new());
 
             let mut events = Vec::with_capacity(text.len() + 2);
             events.push(Event::Start(tag.clone()));
-            events.extend(rich_text_to_events(text));
+            events.extend(rich_text_to_events(text, options));
             events.push(Event::End(tag));
             events
         }
+        // NOTE: markdown has no empty paragraphs, so the vertical space is kept with a line break
+        super::tag::Tag::Paragraph(paragraph) if paragraph.is_blank() => {
+            vec![Event::Html(CowStr::Borrowed(EMPTY_PARAGRAPH))]
+        }
         super::tag::Tag::Paragraph(super::tag::Paragraph { text }) => {
             let tag = pulldown_cmark::Tag::Paragraph;
 
             let mut events = Vec::with_capacity(text.len() + 2);
             events.push(Event::Start(tag.clone()));
-            events.extend(rich_text_to_events(text));
+            events.extend(rich_text_to_events(text, options));
             events.push(Event::End(tag));
             events
         }
-        super::tag::Tag::OrderedList { items } => {
-            let list_tag = pulldown_cmark::Tag::List(Some(1));
+        super::tag::Tag::OrderedList {
+            items,
+            loose,
+            start,
+            ..
+        } => {
+            let list_tag = pulldown_cmark::Tag::List(Some(*start));
 
             let mut events: Vec<Event> = Vec::with_capacity(items.len() + 2);
             events.push(Event::Start(list_tag.clone()));
@@ -30,26 +382,163 @@ pub fn get_pulldown_cmark_events(tag: &super::tag::Tag) -> Vec<Event> {
                 let list_item_tag = pulldown_cmark::Tag::Item;
                 events.push(Event::Start(list_item_tag.clone()));
 
-                {
+                // NOTE: without a marker the first child would be read back as the item's text
+                if item.text.is_empty() && !item.children.is_empty() {
+                    events.push(Event::Html(CowStr::Borrowed(EMPTY_LIST_ITEM)));
+                } else if *loose {
                     let paragraph_tag = pulldown_cmark::Tag::Paragraph;
                     events.push(Event::Start(paragraph_tag.clone()));
-                    events.extend(rich_text_to_events(&item.text));
+                    events.extend(rich_text_to_events(&item.text, options));
                     events.push(Event::End(paragraph_tag));
+                } else {
+                    events.extend(rich_text_to_events(&item.text, options));
                 }
 
                 for child in &item.children {
-                    events.extend(get_pulldown_cmark_events(child));
+                    events.extend(get_pulldown_cmark_events(child, options));
                 }
                 events.push(Event::End(list_item_tag));
             }
             events.push(Event::End(list_tag));
             events
         }
+        super::tag::Tag::Quote { text, children } => {
+            let quote_tag = pulldown_cmark::Tag::BlockQuote;
+
+            let mut events = vec![Event::Start(quote_tag.clone())];
+            if !text.is_empty() {
+                let paragraph_tag = pulldown_cmark::Tag::Paragraph;
+                events.push(Event::Start(paragraph_tag.clone()));
+                events.extend(rich_text_to_events(text, options));
+                events.push(Event::End(paragraph_tag));
+            }
+            for child in children {
+                events.extend(get_pulldown_cmark_events(child, options));
+            }
+            events.push(Event::End(quote_tag));
+            events
+        }
+        super::tag::Tag::Callout {
+            icon,
+            text,
+            children,
+        } => {
+            let quote_tag = pulldown_cmark::Tag::BlockQuote;
+            let paragraph_tag = pulldown_cmark::Tag::Paragraph;
+
+            let mut events = vec![
+                Event::Start(quote_tag.clone()),
+                Event::Start(paragraph_tag.clone()),
+            ];
+            match options.flavor {
+                Flavor::Commonmark => {
+                    if let Some(icon) = icon {
+                        events.push(Event::Text(format!("{icon} ").into()));
+                    }
+                }
+                Flavor::Obsidian => {
+                    let callout_type = obsidian::callout_type(icon.as_deref());
+                    events.push(Event::Html(format!("[!{callout_type}]").into()));
+                    if !text.is_empty() {
+                        events.push(Event::SoftBreak);
+                    }
+                }
+            }
+            events.extend(rich_text_to_events(text, options));
+            events.push(Event::End(paragraph_tag));
+            for child in children {
+                events.extend(get_pulldown_cmark_events(child, options));
+            }
+            events.push(Event::End(quote_tag));
+            events
+        }
+        super::tag::Tag::Toggle { text, children } => {
+            let summary: String = text
+                .iter()
+                .map(|rich_text| escape_html(&punctuation(&rich_text.text, options)))
+                .collect();
+
+            let mut events = vec![Event::Html(
+                format!("<details>\n<summary>{summary}</summary>\n").into(),
+            )];
+            for child in children {
+                events.extend(get_pulldown_cmark_events(child, options));
+            }
+            events.push(Event::Html(CowStr::Borrowed("</details>\n")));
+            events
+        }
+        super::tag::Tag::Code { language, text } => {
+            let code_block_tag =
+                pulldown_cmark::Tag::CodeBlock(CodeBlockKind::Fenced(language.as_str().into()));
+
+            vec![
+                Event::Start(code_block_tag.clone()),
+                Event::Text(format!("{text}\n").into()),
+                Event::End(code_block_tag),
+            ]
+        }
+        super::tag::Tag::Image { url } => {
+            let image_tag =
+                pulldown_cmark::Tag::Image(LinkType::Inline, url.as_str().into(), "".into());
+
+            vec![
+                Event::Start(pulldown_cmark::Tag::Paragraph),
+                Event::Start(image_tag.clone()),
+                Event::End(image_tag),
+                Event::End(pulldown_cmark::Tag::Paragraph),
+            ]
+        }
+        super::tag::Tag::Divider => vec![Event::Rule],
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Replaces the punctuation that smart punctuation produces with its ASCII source
+/// when `ascii_punctuation` is enabled.
+fn punctuation<'a>(text: &'a str, options: &SerializeOptions) -> Cow<'a, str> {
+    const REPLACEMENTS: [(char, &str); 7] = [
+        ('‘', "'"),
+        ('’', "'"),
+        ('“', "\""),
+        ('”', "\""),
+        ('–', "--"),
+        ('—', "---"),
+        ('…', "..."),
+    ];
+
+    if !options.ascii_punctuation
+        || !text
+            .chars()
+            .any(|c| REPLACEMENTS.iter().any(|(from, _)| *from == c))
+    {
+        return Cow::Borrowed(text);
     }
+
+    let mut buf = String::with_capacity(text.len());
+    for c in text.chars() {
+        match REPLACEMENTS.iter().find(|(from, _)| *from == c) {
+            Some((_, to)) => buf.push_str(to),
+            None => buf.push(c),
+        }
+    }
+
+    Cow::Owned(buf)
 }
 
-fn rich_text_to_events(text_parts: &[super::tag::RichText]) -> impl Iterator<Item = Event> {
-    text_parts.iter().flat_map(Into::<Vec<_>>::into)
+fn rich_text_to_events<'a>(
+    text_parts: &'a [super::tag::RichText],
+    options: &SerializeOptions,
+) -> impl Iterator<Item = Event<'a>> {
+    let options = options.clone();
+
+    text_parts
+        .iter()
+        .flat_map(move |rich_text| rich_text_span_to_events(rich_text, &options))
 }
 
 impl From<&super::tag::HeadingLevel> for pulldown_cmark::HeadingLevel {
@@ -58,13 +547,75 @@ impl From<&super::tag::HeadingLevel> for pulldown_cmark::HeadingLevel {
             super::tag::HeadingLevel::H1 => Self::H1,
             super::tag::HeadingLevel::H2 => Self::H2,
             super::tag::HeadingLevel::H3 => Self::H3,
+            super::tag::HeadingLevel::H4 => Self::H4,
+            super::tag::HeadingLevel::H5 => Self::H5,
+            super::tag::HeadingLevel::H6 => Self::H6,
         }
     }
 }
 
-impl<'a> From<&'a super::tag::RichText> for Vec<pulldown_cmark::Event<'a>> {
-    fn from(value: &'a super::tag::RichText) -> Self {
-        vec![Event::Text(CowStr::Borrowed(&value.text))]
+/// Converts a single rich text span into events.
+/// Newlines embedded in the text are converted into line breaks according to `newline_behavior`.
+fn rich_text_span_to_events<'a>(
+    rich_text: &'a super::tag::RichText,
+    options: &SerializeOptions,
+) -> Vec<Event<'a>> {
+    let line_break = match options.newline_behavior {
+        NewlineBehavior::Space => Event::SoftBreak,
+        NewlineBehavior::HardBreak | NewlineBehavior::Backslash => Event::HardBreak,
+    };
+
+    let mut events = Vec::new();
+    for (index, line) in rich_text.text.split('\n').enumerate() {
+        if index > 0 {
+            events.push(line_break.clone());
+        }
+        if !line.is_empty() {
+            events.push(Event::Text(match punctuation(line, options) {
+                Cow::Borrowed(line) => CowStr::Borrowed(line),
+                Cow::Owned(line) => line.into(),
+            }));
+        }
+    }
+
+    match &rich_text.link {
+        None => events,
+        Some(link) if options.flavor == Flavor::Obsidian && obsidian::is_page_link(link) => {
+            vec![Event::Html(format!("[[{}]]", rich_text.text).into())]
+        }
+        Some(link) => {
+            let link_tag = get_link_tag(&rich_text.text, link);
+
+            let mut link_events = Vec::with_capacity(events.len() + 2);
+            link_events.push(Event::Start(link_tag.clone()));
+            link_events.extend(events);
+            link_events.push(Event::End(link_tag));
+            link_events
+        }
+    }
+}
+
+/// Links whose text is the same as the URL are emitted as autolinks (`<https://example.com>`)
+/// to keep the markdown readable.
+fn get_link_tag<'a>(text: &'a str, link: &'a str) -> pulldown_cmark::Tag<'a> {
+    if text == link {
+        pulldown_cmark::Tag::Link(
+            LinkType::Autolink,
+            CowStr::Borrowed(link),
+            CowStr::Borrowed(""),
+        )
+    } else if link.strip_prefix("mailto:") == Some(text) {
+        pulldown_cmark::Tag::Link(
+            LinkType::Email,
+            CowStr::Borrowed(text),
+            CowStr::Borrowed(""),
+        )
+    } else {
+        pulldown_cmark::Tag::Link(
+            LinkType::Inline,
+            CowStr::Borrowed(link),
+            CowStr::Borrowed(""),
+        )
     }
 }
 
@@ -80,44 +631,66 @@ mod tests {
                 level: crate::markdown::tag::HeadingLevel::H1,
                 text: vec![crate::markdown::tag::RichText {
                     text: "Summary".to_string(),
+                    link: None,
+                    mention: None,
                 }],
+                id: None,
             },
             Tag::OrderedList {
                 items: vec![
                     OrderedListItem {
                         text: vec![crate::markdown::tag::RichText {
                             text: "Watch some videos".to_string(),
+                            link: None,
+                            mention: None,
                         }],
                         children: Vec::new(),
                     },
                     OrderedListItem {
                         text: vec![crate::markdown::tag::RichText {
                             text: "Another list item".to_string(),
+                            link: None,
+                            mention: None,
                         }],
                         children: vec![Tag::OrderedList {
                             items: vec![OrderedListItem {
                                 text: vec![crate::markdown::tag::RichText {
                                     text: "Second level list item".to_string(),
+                                    link: None,
+                                    mention: None,
                                 }],
                                 children: vec![Tag::Paragraph(Paragraph {
                                     text: vec![crate::markdown::tag::RichText {
                                         text: "Second level item's extra description".to_string(),
+                                        link: None,
+                                        mention: None,
                                     }],
                                 })],
                             }],
+                            loose: true,
+                            start: 1,
+                            continues: false,
                         }],
                     },
                 ],
+                loose: true,
+                start: 1,
+                continues: false,
             },
             Tag::Heading {
                 level: crate::markdown::tag::HeadingLevel::H1,
                 text: vec![crate::markdown::tag::RichText {
                     text: "Details".to_string(),
+                    link: None,
+                    mention: None,
                 }],
+                id: None,
             },
             Tag::Paragraph(Paragraph {
                 text: vec![crate::markdown::tag::RichText {
                     text: "More description".to_string(),
+                    link: None,
+                    mention: None,
                 }],
             }),
         ]
@@ -128,9 +701,9 @@ mod tests {
 1. Watch some videos
 
 1. Another list item
-   
+
    1. Second level list item
-      
+
       Second level item's extra description
 
 # Details
@@ -141,10 +714,8 @@ More description";
     fn prints_simple_document() {
         let document = get_document_tags();
 
-        let events = document.iter().flat_map(get_pulldown_cmark_events);
+        let buf = to_markdown(&document, &Default::default());
 
-        let mut buf = String::new();
-        pulldown_cmark_to_cmark::cmark(events, &mut buf).expect("serialization failed");
         assert_eq!(buf, SERIALIZED_DOCUMENT);
     }
 
@@ -153,7 +724,7 @@ More description";
         let document = get_document_tags();
         let document_events: Vec<_> = document
             .iter()
-            .flat_map(get_pulldown_cmark_events)
+            .flat_map(|tag| get_pulldown_cmark_events(tag, &Default::default()))
             .collect();
 
         let parser = pulldown_cmark::Parser::new(SERIALIZED_DOCUMENT);
@@ -161,4 +732,26 @@ More description";
 
         assert_eq!(document_events, parsed_events, "different events");
     }
+
+    #[test]
+    fn trims_trailing_whitespace_outside_of_code() {
+        let document = vec![Tag::Quote {
+            text: vec![crate::markdown::tag::RichText {
+                text: "Quoted text ".to_string(),
+                link: None,
+                mention: None,
+            }],
+            children: vec![Tag::Code {
+                language: String::new(),
+                text: "let x = 1;  \n\nlet y = 2;".to_string(),
+            }],
+        }];
+
+        let buf = to_markdown(&document, &Default::default());
+
+        assert_eq!(
+            buf,
+            "> Quoted text\n>\n> ```\n> let x = 1;  \n> \n> let y = 2;\n> ```"
+        );
+    }
 }
@@ -0,0 +1,98 @@
+use pulldown_cmark::escape::escape_html;
+
+use super::{
+    tag::Tag,
+    to_cmark::{get_pulldown_cmark_events, SerializeOptions},
+};
+
+/// Renders the tags as an HTML fragment, through the same events as the markdown.
+pub fn to_html(tags: &[Tag], options: &SerializeOptions) -> String {
+    let events = tags
+        .iter()
+        .flat_map(|tag| get_pulldown_cmark_events(tag, options));
+
+    let mut buf = String::new();
+    pulldown_cmark::html::push_html(&mut buf, events);
+
+    buf
+}
+
+/// The complete HTML document that a fragment is wrapped in, like for `--standalone`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HtmlDocument {
+    /// The stylesheet inlined in the head of the document.
+    pub css: Option<String>,
+}
+
+impl HtmlDocument {
+    /// Wraps the HTML fragment in a document with the title.
+    pub fn wrap(&self, title: &str, fragment: &str) -> String {
+        let mut buf = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        buf.push_str("<title>");
+        escape_html(&mut buf, title).expect("writing to a string cannot fail");
+        buf.push_str("</title>\n");
+        if let Some(css) = &self.css {
+            buf.push_str("<style>\n");
+            buf.push_str(css.trim_end());
+            buf.push_str("\n</style>\n");
+        }
+        buf.push_str("</head>\n<body>\n");
+        buf.push_str(fragment);
+        buf.push_str("</body>\n</html>\n");
+
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::markdown::from_cmark::{ParseOptions, PulldownCMarkEventParser};
+
+    fn markdown_to_html(markdown: &str) -> String {
+        let options = ParseOptions::default();
+        let tags = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext(markdown, options.pulldown_cmark_options()),
+            options,
+        )
+        .parse()
+        .unwrap();
+
+        to_html(&tags, &SerializeOptions::default())
+    }
+
+    #[test]
+    fn renders_the_fixture_documents() {
+        for (markdown, html) in [
+            (
+                include_str!("../../tests/fixtures/changelog/edited_paragraphs_and_lists/after.md"),
+                include_str!("../../tests/fixtures/html/edited_paragraphs_and_lists.html"),
+            ),
+            (
+                include_str!("../../tests/fixtures/html/nested_lists_and_code/page.md"),
+                include_str!("../../tests/fixtures/html/nested_lists_and_code/page.html"),
+            ),
+        ] {
+            assert_eq!(markdown_to_html(markdown), html);
+        }
+    }
+
+    #[test]
+    fn wraps_the_fragment_in_a_document_with_the_title() {
+        let fragment = "<p>Hello</p>\n";
+
+        assert_eq!(
+            HtmlDocument::default().wrap("Q&A <draft>", fragment),
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Q&amp;A &lt;draft&gt;</title>\n</head>\n<body>\n<p>Hello</p>\n</body>\n</html>\n"
+        );
+        assert_eq!(
+            HtmlDocument {
+                css: Some("p { color: gray; }\n".to_string())
+            }
+            .wrap("Notes", fragment),
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Notes</title>\n<style>\np { color: gray; }\n</style>\n</head>\n<body>\n<p>Hello</p>\n</body>\n</html>\n"
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use super::tag::{OrderedListItem, Paragraph, RichText, Tag};
+
+/// How the tags are rendered as plain text.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TextOptions {
+    /// Write the URL of links after their text, as `text (url)`.
+    pub show_urls: bool,
+}
+
+/// Renders the tags as plain text without any markup, for grepping and other text tools.
+///
+/// Blocks are separated by blank lines, list items are prefixed with their number and the
+/// content nested in them is indented. Links and mentions are reduced to their visible text.
+pub fn to_text(tags: &[Tag], options: &TextOptions) -> String {
+    let mut text = blocks_to_text(tags, options, "\n\n");
+    text.push('\n');
+
+    text
+}
+
+fn blocks_to_text(tags: &[Tag], options: &TextOptions, separator: &str) -> String {
+    tags.iter()
+        .map(|tag| block_to_text(tag, options))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn block_to_text(tag: &Tag, options: &TextOptions) -> String {
+    match tag {
+        Tag::Paragraph(Paragraph { text }) | Tag::Heading { text, .. } => {
+            rich_text_to_text(text, options)
+        }
+        Tag::OrderedList {
+            items,
+            loose,
+            start,
+            ..
+        } => {
+            let separator = if *loose { "\n\n" } else { "\n" };
+
+            items
+                .iter()
+                .zip(*start..)
+                .map(|(item, number)| list_item_to_text(item, number, separator, options))
+                .collect::<Vec<_>>()
+                .join(separator)
+        }
+        Tag::Quote { text, children }
+        | Tag::Callout { text, children, .. }
+        | Tag::Toggle { text, children } => {
+            let text = rich_text_to_text(text, options);
+            if children.is_empty() {
+                text
+            } else {
+                format!("{text}\n\n{}", blocks_to_text(children, options, "\n\n"))
+            }
+        }
+        Tag::Code { text, .. } => text.trim_end_matches('\n').to_string(),
+        Tag::Image { url } => url.clone(),
+        Tag::Divider => "---".to_string(),
+    }
+}
+
+fn list_item_to_text(
+    item: &OrderedListItem,
+    number: u64,
+    separator: &str,
+    options: &TextOptions,
+) -> String {
+    let marker = format!("{number}. ");
+    let mut text = format!("{marker}{}", rich_text_to_text(&item.text, options));
+    if !item.children.is_empty() {
+        let children = blocks_to_text(&item.children, options, separator);
+        text.push_str(separator);
+        text.push_str(&indent(&children, marker.len()));
+    }
+
+    text
+}
+
+fn rich_text_to_text(text: &[RichText], options: &TextOptions) -> String {
+    text.iter()
+        .map(|rich_text| match &rich_text.link {
+            Some(link) if options.show_urls && *link != rich_text.text => {
+                format!("{} ({link})", rich_text.text)
+            }
+            _ => rich_text.text.clone(),
+        })
+        .collect()
+}
+
+/// Indents the lines that are not blank by `width` spaces.
+fn indent(text: &str, width: usize) -> String {
+    text.split('\n')
+        .map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("{:width$}{line}", "")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::markdown::from_cmark::{ParseOptions, PulldownCMarkEventParser};
+
+    fn markdown_to_text(markdown: &str, options: &TextOptions) -> String {
+        let parse_options = ParseOptions::default();
+        let tags = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext(markdown, parse_options.pulldown_cmark_options()),
+            parse_options,
+        )
+        .parse()
+        .unwrap();
+
+        to_text(&tags, options)
+    }
+
+    #[test]
+    fn renders_the_fixture_documents() {
+        for (markdown, text) in [
+            (
+                include_str!("../../tests/fixtures/changelog/edited_paragraphs_and_lists/after.md"),
+                include_str!("../../tests/fixtures/text/edited_paragraphs_and_lists.txt"),
+            ),
+            (
+                include_str!("../../tests/fixtures/html/nested_lists_and_code/page.md"),
+                include_str!("../../tests/fixtures/text/nested_lists_and_code.txt"),
+            ),
+        ] {
+            assert_eq!(markdown_to_text(markdown, &TextOptions::default()), text);
+        }
+    }
+
+    #[test]
+    fn shows_urls_after_the_text_of_links() {
+        let markdown = "See [the runbook](https://example.com/runbook) or <https://example.com>\n";
+
+        assert_eq!(
+            markdown_to_text(markdown, &TextOptions::default()),
+            "See the runbook or https://example.com\n"
+        );
+        assert_eq!(
+            markdown_to_text(markdown, &TextOptions { show_urls: true }),
+            "See the runbook (https://example.com/runbook) or https://example.com\n"
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use thiserror::Error;
+
+use super::from_cmark::FOOTNOTES_HEADING;
+
+/// Parts of the document that were converted lossily and will not round-trip exactly.
+/// Warnings are collected during the conversion and reported once it succeeds.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum Warning {
+    #[error("heading \"{text}\" has level {original_level}, which was mapped to h3")]
+    HeadingLevelLowered {
+        original_level: pulldown_cmark::HeadingLevel,
+        text: String,
+    },
+
+    #[error(
+        "heading \"{text}\" would have level {level} with the heading offset, it was clamped to h{clamped_level}"
+    )]
+    HeadingLevelClamped {
+        text: String,
+        level: i16,
+        clamped_level: u8,
+    },
+
+    #[error(
+        "classes {classes:?} of heading \"{text}\" were dropped, Notion does not support them"
+    )]
+    HeadingClassesDropped { classes: Vec<String>, text: String },
+
+    // TODO: keep the formatting once rich text supports annotations
+    // https://github.com/Gelio/notion-edit/issues/1
+    #[error("inline formatting (bold, italics, strikethrough, code) in \"{text}\" was dropped")]
+    InlineFormattingDropped { text: String },
+
+    #[error("HTML {html:?} has no Notion equivalent and was kept as an html code block")]
+    HtmlKeptAsCode { html: String },
+
+    #[error("inline HTML {html:?} has no Notion equivalent and was kept as text")]
+    InlineHtmlKeptAsText { html: String },
+
+    #[error("HTML {html:?} has no Notion equivalent and was skipped")]
+    HtmlSkipped { html: String },
+
+    // NOTE: Notion has no footnotes. References become `[1]` and definitions become a numbered
+    // list, so fetching the page back does not recreate the footnotes.
+    #[error("{count} footnote(s) were moved to a \"{FOOTNOTES_HEADING}\" section at the end")]
+    FootnotesMovedToEnd { count: usize },
+
+    #[error("footnote [^{label}] is referenced, but never defined")]
+    FootnoteNotDefined { label: String },
+
+    #[error("list starting with \"{text}\" starts at {start}, but Notion lists always start at 1")]
+    ListStartNumberDropped { start: u64, text: String },
+
+    #[error("image {url} was skipped, images are not supported yet")]
+    ImageSkipped { url: String },
+
+    #[error(
+        "blocks nested under block {block_id} were moved after it, markdown cannot nest blocks under paragraphs and headings"
+    )]
+    ChildrenFlattened { block_id: String },
+
+    #[error("wikilink [[{target}]] was kept as text, it is not in the wikilink mapping")]
+    WikiLinkNotResolved { target: String },
+
+    #[error(
+        "links to pages that are not synced to local files were kept: {}",
+        links.join(", ")
+    )]
+    LinksNotResolved { links: Vec<String> },
+
+    #[error("{url} was not downloaded, so it links to Notion: {error}")]
+    AssetNotDownloaded { url: String, error: String },
+
+    #[error(
+        "formatting ({}) of block {block_id} was dropped, markdown cannot express it",
+        formatting.join(", ")
+    )]
+    FormattingDropped {
+        block_id: String,
+        formatting: Vec<String>,
+    },
+
+    #[error("{block_type} block {block_id} was skipped, it has no markdown equivalent")]
+    BlockSkipped {
+        block_id: String,
+        block_type: String,
+    },
+
+    #[error(
+        "{kind} \"{text}\" in block {block_id} was kept as plain text, markdown cannot express it"
+    )]
+    RichTextKeptAsText {
+        block_id: String,
+        kind: String,
+        text: String,
+    },
+
+    #[error(
+        "{block_type} block {block_id} was replaced by its content, markdown cannot express it"
+    )]
+    BlockUnwrapped {
+        block_id: String,
+        block_type: String,
+    },
+
+    #[error("child page \"{title}\" ({block_id}) was skipped, markdown cannot contain pages")]
+    ChildPageSkipped { block_id: String, title: String },
+
+    #[error("no user with email {email} found in the workspace")]
+    UserNotFound { email: String },
+
+    #[error("page {id} was skipped, the integration cannot access it")]
+    PageNotAccessible { id: String },
+
+    #[error(
+        "property {name:?} of type {kind} was skipped, it cannot be written to the front matter"
+    )]
+    PropertySkipped { name: String, kind: String },
+
+    #[error("property {name:?} of type {kind} was not pushed, it cannot be changed from the front matter")]
+    PropertyNotPushed { name: String, kind: String },
+}
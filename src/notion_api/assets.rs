@@ -0,0 +1,253 @@
+//! Downloading the files of image and file blocks. Files uploaded to Notion have signed URLs
+//! that expire after an hour, so the fetched documents link to local copies instead.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use futures::{stream, StreamExt};
+use notion::{
+    ids::AsIdentifier,
+    models::{Block, FileObject},
+};
+use reqwest::{StatusCode, Url};
+use thiserror::Error;
+
+use super::{client::DEFAULT_CONNECT_TIMEOUT, flatten, BlockWithChildren};
+
+/// A file of an image or file block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Asset {
+    pub block_id: String,
+    pub url: String,
+}
+
+impl Asset {
+    /// The name of the local copy: the ID of the block with the extension of the original file.
+    pub fn file_name(&self) -> String {
+        let extension = Url::parse(&self.url).ok().and_then(|url| {
+            let file_name = url.path_segments()?.next_back()?.to_string();
+            Path::new(&file_name)
+                .extension()
+                .map(|extension| extension.to_string_lossy().into_owned())
+        });
+
+        match extension {
+            Some(extension) => format!("{}.{extension}", self.block_id),
+            None => self.block_id.clone(),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum DownloadError {
+    #[error("could not download {url}: {error}")]
+    Request { url: String, error: reqwest::Error },
+
+    #[error("could not download {url}: the server responded with {status}")]
+    Status { url: String, status: StatusCode },
+
+    #[error("could not write {file}: {error}")]
+    WriteFile { file: String, error: io::Error },
+}
+
+/// The URL of the file, which the `notion` crate does not expose.
+pub fn file_url(file: &FileObject) -> Option<String> {
+    let value = serde_json::to_value(file).ok()?;
+    let url = match file {
+        FileObject::File { .. } => &value["file"]["url"],
+        FileObject::External { .. } => &value["external"]["url"],
+    };
+
+    url.as_str().map(ToString::to_string)
+}
+
+/// The files of the image, file and PDF blocks, including the nested ones, in document order.
+pub fn page_assets(blocks: &[BlockWithChildren]) -> Vec<Asset> {
+    flatten(blocks)
+        .filter_map(|block| {
+            let file = match &block.block {
+                Block::Image { image: file, .. }
+                | Block::File { file, .. }
+                | Block::Pdf { pdf: file, .. } => file,
+                _ => return None,
+            };
+
+            file_url(file).map(|url| Asset {
+                block_id: block.block.as_id().to_string(),
+                url,
+            })
+        })
+        .collect()
+}
+
+/// Downloads files, a few at a time. The requests do not go through the Notion client,
+/// as the files are hosted elsewhere and must not receive the API key.
+pub struct DownloadPool {
+    client: reqwest::Client,
+    max_concurrency: usize,
+}
+
+impl DownloadPool {
+    pub fn new(max_concurrency: usize) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .connect_timeout(DEFAULT_CONNECT_TIMEOUT)
+                .build()
+                .expect("valid reqwest client"),
+            max_concurrency: max_concurrency.max(1),
+        }
+    }
+
+    /// Downloads the assets into the directory, and returns the path of every local copy
+    /// or the reason it could not be downloaded, in the order of `assets`.
+    pub async fn download_all<'a>(
+        &self,
+        assets: &'a [Asset],
+        directory: &Path,
+    ) -> Vec<(&'a Asset, Result<PathBuf, DownloadError>)> {
+        stream::iter(assets)
+            .map(|asset| async move { (asset, self.download(asset, directory).await) })
+            .buffered(self.max_concurrency)
+            .collect()
+            .await
+    }
+
+    /// Downloads the asset into the directory. A local copy with the same content is left
+    /// untouched.
+    async fn download(&self, asset: &Asset, directory: &Path) -> Result<PathBuf, DownloadError> {
+        let request_error = |error| DownloadError::Request {
+            url: asset.url.clone(),
+            error,
+        };
+        let response = self
+            .client
+            .get(&asset.url)
+            .send()
+            .await
+            .map_err(request_error)?;
+        if !response.status().is_success() {
+            return Err(DownloadError::Status {
+                url: asset.url.clone(),
+                status: response.status(),
+            });
+        }
+        let content = response.bytes().await.map_err(request_error)?;
+
+        let file = directory.join(asset.file_name());
+        let write_error = |error| DownloadError::WriteFile {
+            file: file.display().to_string(),
+            error,
+        };
+        match fs::read(&file) {
+            Ok(existing_content) if existing_content == content => {}
+            Ok(_) => fs::write(&file, &content).map_err(write_error)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                fs::create_dir_all(directory).map_err(write_error)?;
+                fs::write(&file, &content).map_err(write_error)?;
+            }
+            Err(error) => return Err(write_error(error)),
+        }
+
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use pretty_assertions::assert_eq;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    fn get_asset(server: &MockServer, block_id: &str, file_path: &str) -> Asset {
+        Asset {
+            block_id: block_id.to_string(),
+            url: format!("{}{file_path}?X-Amz-Signature=abc", server.uri()),
+        }
+    }
+
+    #[test]
+    fn names_files_by_block_id_and_extension() {
+        let asset = Asset {
+            block_id: "0b89a6e8-f006-4acc-8ec6-e6902b039e3a".to_string(),
+            url: "https://s3.us-west-2.amazonaws.com/secure/diagram.png?X-Amz-Expires=3600"
+                .to_string(),
+        };
+
+        assert_eq!(
+            asset.file_name(),
+            "0b89a6e8-f006-4acc-8ec6-e6902b039e3a.png"
+        );
+    }
+
+    #[tokio::test]
+    async fn downloads_assets_and_keeps_going_after_failures() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/files/diagram.png"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"png".to_vec()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/files/expired.pdf"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&server)
+            .await;
+        let directory = tempfile::tempdir().unwrap();
+        let assets = [
+            get_asset(&server, "diagram", "/files/diagram.png"),
+            get_asset(&server, "expired", "/files/expired.pdf"),
+        ];
+
+        let results = DownloadPool::new(2)
+            .download_all(&assets, &directory.path().join("assets"))
+            .await;
+
+        assert_eq!(results.len(), 2);
+        let diagram = results[0].1.as_ref().unwrap();
+        assert_eq!(diagram, &directory.path().join("assets/diagram.png"));
+        assert_eq!(fs::read(diagram).unwrap(), b"png");
+        assert!(matches!(
+            results[1].1,
+            Err(DownloadError::Status {
+                status: StatusCode::FORBIDDEN,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn leaves_copies_with_the_same_content_untouched() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/files/diagram.png"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"png".to_vec()))
+            .mount(&server)
+            .await;
+        let directory = tempfile::tempdir().unwrap();
+        let file = directory.path().join("diagram.png");
+        fs::write(&file, b"png").unwrap();
+        let old_time = SystemTime::UNIX_EPOCH;
+        fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(old_time)
+            .unwrap();
+        let assets = [get_asset(&server, "diagram", "/files/diagram.png")];
+
+        let results = DownloadPool::new(1)
+            .download_all(&assets, directory.path())
+            .await;
+
+        assert!(results[0].1.is_ok());
+        assert_eq!(fs::metadata(&file).unwrap().modified().unwrap(), old_time);
+    }
+}
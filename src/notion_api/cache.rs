@@ -0,0 +1,199 @@
+//! A cache of the children of blocks on disk, so fetching a page again only lists the blocks
+//! at the top of the page when nothing changed.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use notion::{ids::BlockId, models::Block};
+use tracing::debug;
+
+use crate::atomic_file::write_atomically;
+
+use super::{BlockWithChildren, GetCommon};
+
+/// Blocks with their children, stored as JSON files named by the ID of the block.
+#[derive(Debug, Clone)]
+pub struct BlockCache {
+    directory: PathBuf,
+}
+
+impl BlockCache {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// The cached children of the block, when its `last_edited_time` and `has_children` did
+    /// not change since they were cached.
+    // NOTE: the subtree is reused as a whole, so edits deeper down that change neither field
+    // of the block are only seen with `--no-cache` or after `cache clear`
+    pub fn children(&self, block: &Block) -> Option<Vec<BlockWithChildren>> {
+        let common = block.common()?;
+        let content = fs::read(self.path(&common.id)).ok()?;
+        // NOTE: files that cannot be read are fetched again and overwritten
+        let cached: BlockWithChildren = serde_json::from_slice(&content).ok()?;
+        let cached_common = cached.block.common()?;
+
+        (cached_common.last_edited_time == common.last_edited_time
+            && cached_common.has_children == common.has_children)
+            .then_some(cached.children)
+    }
+
+    /// Stores the block with its children. The cache only makes fetches faster, so failures
+    /// are logged and otherwise ignored.
+    pub fn insert(&self, block: &BlockWithChildren) {
+        let Some(common) = block.block.common() else {
+            return;
+        };
+        let path = self.path(&common.id);
+        let result = fs::create_dir_all(&self.directory).and_then(|_| {
+            write_atomically(&path, |writer| {
+                serde_json::to_writer(writer, block).map_err(io::Error::from)
+            })
+        });
+        if let Err(error) = result {
+            debug!(%error, path = %path.display(), "could not cache the block");
+        }
+    }
+
+    /// Removes all the cached blocks.
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.directory) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+
+    fn path(&self, block_id: &BlockId) -> PathBuf {
+        self.directory.join(format!("{block_id}.json"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use serde_json::{json, Value};
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::notion_api::{
+        client::{
+            get_all_block_children, get_notion_reqwest_client, parse_api_base_url, NotionClient,
+        },
+        mock_server::{self, get_list_json},
+    };
+
+    const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+    const TOGGLE_ID: &str = "0a5f1b4e8c0d4c4a9c5e6d1f2b3a4c5d";
+
+    fn get_block_json(id: &str, block_type: &str, has_children: bool, edited: &str) -> Value {
+        let mut block = mock_server::get_block_json(id, block_type, "Details");
+        block["has_children"] = json!(has_children);
+        block["last_edited_time"] = json!(edited);
+
+        block
+    }
+
+    async fn mock_page(server: &MockServer, toggle_edited: &str, page_listings: u64) {
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                get_block_json(TOGGLE_ID, "toggle", true, toggle_edited),
+            ])))
+            .expect(page_listings)
+            .mount(server)
+            .await;
+    }
+
+    async fn fetch_page(client: &NotionClient) -> Vec<BlockWithChildren> {
+        get_all_block_children(client, &BlockId::from_str(PAGE_ID).unwrap())
+            .await
+            .expect("successful fetch")
+    }
+
+    fn get_client(server: &MockServer, cache: &BlockCache) -> NotionClient {
+        NotionClient::new(get_notion_reqwest_client("secret", &Default::default()))
+            .with_base_url(parse_api_base_url(&server.uri()).unwrap())
+            .with_block_cache(cache.clone())
+    }
+
+    #[tokio::test]
+    async fn reuses_the_children_of_blocks_that_did_not_change() {
+        let server = MockServer::start().await;
+        mock_page(&server, "2022-08-01T12:00:00.000Z", 2).await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{TOGGLE_ID}/children")))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(get_list_json(
+                    (0..500)
+                        .map(|index| {
+                            get_block_json(
+                                &format!("{index:032x}"),
+                                "paragraph",
+                                false,
+                                "2022-08-01T12:00:00.000Z",
+                            )
+                        })
+                        .collect(),
+                )),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let directory = tempfile::tempdir().unwrap();
+        let client = get_client(&server, &BlockCache::new(directory.path().join("blocks")));
+
+        let cold = fetch_page(&client).await;
+        let warm = fetch_page(&client).await;
+
+        assert_eq!(cold[0].children.len(), 500);
+        assert_eq!(warm[0].children.len(), 500);
+        assert_eq!(warm[0].children[499].block, cold[0].children[499].block);
+    }
+
+    #[tokio::test]
+    async fn fetches_the_children_of_edited_blocks_again() {
+        let cache_directory = tempfile::tempdir().unwrap();
+        let cache = BlockCache::new(cache_directory.path().to_path_buf());
+        for (edited, paragraph_id) in [
+            (
+                "2022-08-01T12:00:00.000Z",
+                "0b89a6e8f0064acc8ec6e6902b039e3a",
+            ),
+            (
+                "2022-08-02T12:00:00.000Z",
+                "8d4e2f603c1b4a7e9f2d5b6a7c8d9e0f",
+            ),
+        ] {
+            let server = MockServer::start().await;
+            mock_page(&server, edited, 1).await;
+            Mock::given(method("GET"))
+                .and(path(format!("/v1/blocks/{TOGGLE_ID}/children")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(get_list_json(vec![
+                    get_block_json(paragraph_id, "paragraph", false, edited),
+                ])))
+                .expect(1)
+                .mount(&server)
+                .await;
+
+            let blocks = fetch_page(&get_client(&server, &cache)).await;
+
+            assert_eq!(
+                blocks[0].children[0].block.common().unwrap().id,
+                BlockId::from_str(paragraph_id).unwrap()
+            );
+        }
+
+        cache.clear().unwrap();
+        assert!(!cache_directory.path().exists());
+    }
+}
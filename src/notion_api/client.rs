@@ -1,309 +1,4609 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    ops::Range,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
 use async_recursion::async_recursion;
-use futures::future::join_all;
+use futures::{
+    future::{join_all, try_join_all},
+    stream, StreamExt, TryStreamExt,
+};
 use notion::{
-    ids::{AsIdentifier, BlockId, PageId},
-    models::ListResponse,
+    ids::{AsIdentifier, BlockId, DatabaseId, PageId},
+    models::{paging::PagingCursor, users::User, Block, ListResponse},
 };
-use reqwest::header;
+use reqwest::{header, Method, RequestBuilder, StatusCode};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use thiserror::Error;
+use tokio::sync::{OnceCell, Semaphore};
+use tracing::{debug, info, instrument};
+use url::Url;
+
+use crate::markdown::{
+    notion_interop::{code_language, heading_rich_text, NotionToMarkdownParser},
+    plain_text::{split_into_chunks, MAX_RICH_TEXTS_PER_BLOCK, MAX_RICH_TEXT_LENGTH},
+    tag::{HeadingLevel, Paragraph, RichText, Tag},
+};
+
+use super::{
+    cache::BlockCache,
+    response_cache::ResponseCache,
+    retry::{RetryPolicy, DEFAULT_MAX_CONCURRENCY},
+    BlockWithChildren, GetCommon,
+};
 
-use crate::markdown::tag::{HeadingLevel, Paragraph, Tag};
+pub const DEFAULT_API_BASE_URL: &str = "https://api.notion.com/";
 
-use super::{BlockWithChildren, GetCommon};
+/// The version of the Notion API that the models of the `notion` crate follow.
+// NOTE: same value as in the `notion` crate
+// https://github.com/jakeswenson/notion/blob/e75a5433a98ce51c1fe1633ee5344879c01e7fb7/src/lib.rs#L15
+pub const DEFAULT_NOTION_VERSION: &str = "2022-02-22";
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The most blocks the Notion API accepts in a single append block children request, counting
+/// the children nested in the blocks.
+pub const MAX_BLOCKS_PER_REQUEST: usize = 100;
+/// The longest URL, like the link of a text, that the Notion API accepts.
+pub const MAX_URL_LENGTH: usize = 2000;
+/// The icon of callouts without one, which is also the default icon in Notion.
+const DEFAULT_CALLOUT_ICON: &str = "💡";
 
 pub struct NotionClient {
     client: reqwest::Client,
+    base_url: Url,
+    retry_policy: RetryPolicy,
+    request_timeout: Duration,
+    max_concurrency: usize,
+    /// Limits the number of requests in flight, shared by all the requests of the client.
+    concurrency_limit: Arc<Semaphore>,
+    /// Set once the API key was accepted by [`NotionClient::ensure_access`].
+    access_checked: OnceCell<()>,
+    block_cache: Option<BlockCache>,
+    response_cache: Option<ResponseCache>,
+    /// The requests sent so far, including the retried ones.
+    requests: AtomicUsize,
+    retries: AtomicUsize,
+}
+
+/// The number of requests a client sent, from [`NotionClient::request_counts`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RequestCounts {
+    /// Every request sent, counting each retry as a request.
+    pub requests: usize,
+    pub retries: usize,
+}
+
+impl RequestCounts {
+    /// The requests sent since the `earlier` counts were taken.
+    pub fn since(self, earlier: RequestCounts) -> Self {
+        Self {
+            requests: self.requests - earlier.requests,
+            retries: self.retries - earlier.retries,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ApiBaseUrlError {
+    #[error("invalid API base URL")]
+    Invalid(#[from] url::ParseError),
+
+    // NOTE: reqwest cannot connect to unix sockets, so a gateway on a unix socket needs to be
+    // exposed on a TCP port
+    #[error("unsupported API base URL scheme {scheme}, only http and https are supported")]
+    UnsupportedScheme { scheme: String },
+}
+
+/// Parses the base URL of the Notion API (for example a local gateway).
+pub fn parse_api_base_url(input: &str) -> Result<Url, ApiBaseUrlError> {
+    let url = Url::parse(input)?;
+
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(ApiBaseUrlError::UnsupportedScheme {
+            scheme: url.scheme().to_string(),
+        });
+    }
+
+    Ok(url)
+}
+
+#[derive(Error, Debug)]
+pub enum RequestError {
+    #[error("request to Notion timed out after {}", humantime::format_duration(*.timeout))]
+    TimedOut { timeout: Duration },
+
+    #[error("connecting to Notion timed out")]
+    ConnectTimedOut(#[source] reqwest::Error),
+
+    #[error("request to Notion failed")]
+    Failed(#[source] reqwest::Error),
+}
+
+impl From<reqwest::Error> for RequestError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            Self::ConnectTimedOut(error)
+        } else {
+            Self::Failed(error)
+        }
+    }
+}
+
+/// The error body the Notion API responds with when it rejects a request.
+#[derive(Error, Debug, Deserialize)]
+#[error("{code}: {message}")]
+pub struct NotionApiError {
+    pub status: u16,
+    pub code: String,
+    pub message: String,
+}
+
+impl NotionApiError {
+    /// Reads the error from the body of a failed response.
+    /// Bodies that are not Notion errors (for example from a proxy) are kept as the message.
+    fn from_response(status_code: StatusCode, response_text: &str) -> Self {
+        serde_json::from_str(response_text).unwrap_or_else(|_| Self {
+            status: status_code.as_u16(),
+            code: status_code
+                .canonical_reason()
+                .unwrap_or("unknown_error")
+                .to_string(),
+            message: response_text.to_string(),
+        })
+    }
 }
 
 #[derive(Error, Debug)]
 pub enum AppendBlockChildrenError {
     #[error("cannot append block children")]
-    AppendFailed {
-        error: reqwest::Error,
-        parent_block_id: BlockId,
-        children: Vec<BlockToCreate>,
-    },
+    AppendFailed(#[from] RequestError),
 
     #[error("response cannot be deserialized")]
     UnexpectedBody(#[from] serde_json::Error),
 
-    #[error("unexpected API failure: {response}")]
-    UnexpectedApiFailure { response: String },
+    #[error("Notion rejected the request: {0}")]
+    Api(#[source] NotionApiError),
+
+    #[error("the request with {blocks} block(s) is too large")]
+    PayloadTooLarge { blocks: usize },
+
+    #[error("cannot get the created children to append their children to")]
+    FetchingCreatedChildren(#[from] GetBlockChildrenError),
 }
 
-impl NotionClient {
-    pub fn new(client: reqwest::Client) -> Self {
-        Self { client }
-    }
+/// Blocks that could not be created, with where they are in the document.
+#[derive(Error, Debug)]
+pub struct CreateBlocksError {
+    pub parent_block_id: BlockId,
+    /// The position of the parent among the created blocks: the index of a top-level block,
+    /// then the index among its children, and so on. Empty when the parent is the page itself.
+    pub parent_position: Vec<usize>,
+    /// The indexes of the blocks that were not created among the children of the parent.
+    pub blocks: Range<usize>,
+    /// The type and the start of the text of the first block that was not created.
+    pub preview: String,
+    #[source]
+    pub error: AppendBlockChildrenError,
+}
 
-    pub async fn delete_block(&self, block_id: BlockId) -> Result<(), reqwest::Error> {
-        self.client
-            .delete(format!("https://api.notion.com/v1/blocks/{}", block_id))
-            .send()
-            .await
-            .map(|_| ())
+impl CreateBlocksError {
+    /// The position of the first block that was not created, which orders the errors like
+    /// the document.
+    pub fn position(&self) -> Vec<usize> {
+        let mut position = self.parent_position.clone();
+        position.push(self.blocks.start);
+
+        position
     }
 
-    async fn append_block_children_shallow<Children>(
-        &self,
-        parent_block_id: BlockId,
-        children: Children,
-    ) -> Result<Vec<notion::models::Block>, AppendBlockChildrenError>
-    where
-        Children: IntoIterator<Item = BlockToCreate>,
-    {
-        let append_block_children_url = format!(
-            "https://api.notion.com/v1/blocks/{}/children",
-            parent_block_id
-        );
-        let children: Vec<_> = children.into_iter().collect();
-        let children_to_create = ChildrenToCreate { children };
+    /// Moves the blocks down by `offset` top-level blocks, for documents created in several
+    /// calls.
+    pub fn shifted(mut self, offset: usize) -> Self {
+        match self.parent_position.first_mut() {
+            Some(index) => *index += offset,
+            None => self.blocks = self.blocks.start + offset..self.blocks.end + offset,
+        }
 
-        let response = self
-            .client
-            .patch(append_block_children_url)
-            .json(&children_to_create)
-            .send()
-            .await
-            .map_err(|error| AppendBlockChildrenError::AppendFailed {
-                error,
-                children: children_to_create.children,
-                parent_block_id,
-            })?;
-        let status_code = response.status();
-        let response_text = response.text().await.expect("could not get response text");
+        self
+    }
+}
 
-        if !status_code.is_success() {
-            return Err(AppendBlockChildrenError::UnexpectedApiFailure {
-                response: response_text,
-            });
+impl fmt::Display for CreateBlocksError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Range { start, end } = self.blocks;
+        if end - start == 1 {
+            write!(f, "block {}", start + 1)?;
+        } else {
+            write!(f, "blocks {}-{end}", start + 1)?;
+        }
+        if self.parent_position.is_empty() {
+            write!(f, " of {}", self.parent_block_id)?;
+        } else {
+            let position: Vec<_> = self
+                .parent_position
+                .iter()
+                .map(|index| (index + 1).to_string())
+                .collect();
+            write!(
+                f,
+                " nested in block {} ({})",
+                position.join("."),
+                self.parent_block_id
+            )?;
         }
 
-        let created_blocks =
-            serde_json::from_str::<ListResponse<notion::models::Block>>(&response_text)
-                .map_err(AppendBlockChildrenError::UnexpectedBody)?
-                .results;
-
-        Ok(created_blocks)
+        write!(f, ", starting with {}", self.preview)
     }
+}
 
-    #[async_recursion]
-    pub async fn create_blocks<'a>(
-        &self,
-        parent_block_id: BlockId,
-        blocks_to_create: Vec<BlockWithChildrenToCreate>,
-    ) -> Result<(), Vec<AppendBlockChildrenError>> {
-        let mut blocks_to_create_iter = blocks_to_create.into_iter();
-        let top_level_blocks_to_create: Vec<_> = blocks_to_create_iter
-            .by_ref()
-            .map(|block_to_create| block_to_create.block)
-            .collect();
+#[derive(Error, Debug)]
+pub enum GetBlockChildrenError {
+    #[error("cannot get block children")]
+    RequestFailed(#[from] RequestError),
 
-        let created_blocks = self
-            .append_block_children_shallow(parent_block_id.clone(), top_level_blocks_to_create)
-            .await
-            .map_err(|error| vec![error])?;
+    #[error("response cannot be deserialized")]
+    UnexpectedBody(#[from] serde_json::Error),
 
-        join_all(std::iter::zip(blocks_to_create_iter, created_blocks).map(
-            |(block_to_create, created_block)| async move {
-                if block_to_create.children.is_empty() {
-                    Ok(())
-                } else {
-                    self.create_blocks(created_block.as_id().clone(), block_to_create.children)
-                        .await
-                }
-            },
-        ))
-        .await
-        .into_iter()
-        .collect::<Result<_, _>>()
-    }
+    #[error("Notion rejected the request: {0}")]
+    Api(#[source] NotionApiError),
 }
 
-pub fn get_notion_reqwest_client(notion_api_key: &str) -> reqwest::Client {
-    let mut headers = header::HeaderMap::new();
-    headers.append(
-        "Notion-Version",
-        header::HeaderValue::from_static(
-            // NOTE: same value as in the `notion` module
-            // https://github.com/jakeswenson/notion/blob/e75a5433a98ce51c1fe1633ee5344879c01e7fb7/src/lib.rs#L15
-            "2022-02-22",
-        ),
-    );
+#[derive(Error, Debug)]
+pub enum DeleteBlockError {
+    #[error("cannot delete block")]
+    RequestFailed(#[from] RequestError),
 
-    let mut auth_value =
-        header::HeaderValue::from_str(&format!("Bearer {}", notion_api_key)).expect("valid header");
-    auth_value.set_sensitive(true);
-    headers.append(header::AUTHORIZATION, auth_value);
+    #[error("Notion rejected the request: {0}")]
+    Api(#[source] NotionApiError),
+}
 
-    reqwest::ClientBuilder::new()
-        .default_headers(headers)
-        .build()
-        .expect("valid reqwest client")
+#[derive(Error, Debug)]
+pub enum UpdateBlockError {
+    #[error("cannot update block")]
+    RequestFailed(#[from] RequestError),
+
+    #[error("Notion rejected the request: {0}")]
+    Api(#[source] NotionApiError),
 }
 
-// NOTE: the Block enum from the notion crate requires too much boilerplate information
-// that is not required to create the block.
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(untagged)]
-pub enum BlockToCreate {
-    Heading1 {
-        heading_1: notion::models::Text,
-    },
-    Heading2 {
-        heading_2: notion::models::Text,
-    },
-    Heading3 {
-        heading_3: notion::models::Text,
-    },
-    Paragraph {
-        paragraph: notion::models::Text,
-    },
-    NumberedListItem {
-        numbered_list_item: notion::models::TextAndChildren,
-    },
+#[derive(Error, Debug)]
+pub enum CreatePageError {
+    #[error("cannot create page")]
+    RequestFailed(#[from] RequestError),
+
+    #[error("response cannot be deserialized")]
+    UnexpectedBody(#[from] serde_json::Error),
+
+    #[error("Notion rejected the request: {0}")]
+    Api(#[source] NotionApiError),
 }
 
-pub struct BlockWithChildrenToCreate {
-    block: BlockToCreate,
-    children: Vec<BlockWithChildrenToCreate>,
+#[derive(Error, Debug)]
+pub enum UpdatePageError {
+    #[error("cannot update page")]
+    RequestFailed(#[from] RequestError),
+
+    #[error("response cannot be deserialized")]
+    UnexpectedBody(#[from] serde_json::Error),
+
+    #[error("Notion rejected the request: {0}")]
+    Api(#[source] NotionApiError),
 }
 
-impl BlockWithChildrenToCreate {
-    fn childless_block(block: BlockToCreate) -> Self {
-        Self {
-            block,
-            children: Vec::new(),
-        }
-    }
-    /// Transforms the tag into a vector of blocks to create.
-    ///
-    /// It does not handle nested children (for example for lists).
-    /// The consumer should go through
-    pub fn from_markdown_tag(tag: Tag) -> Vec<Self> {
-        match tag {
-            Tag::Heading {
-                level: HeadingLevel::H1,
-                text,
-            } => vec![Self::childless_block(BlockToCreate::Heading1 {
-                heading_1: text.into_iter().collect(),
-            })],
-            Tag::Heading {
-                level: HeadingLevel::H2,
-                text,
-            } => vec![Self::childless_block(BlockToCreate::Heading2 {
-                heading_2: text.into_iter().collect(),
-            })],
-            Tag::Heading {
-                level: HeadingLevel::H3,
-                text,
-            } => vec![Self::childless_block(BlockToCreate::Heading3 {
-                heading_3: text.into_iter().collect(),
-            })],
-            Tag::Paragraph(Paragraph { text }) => {
-                vec![Self::childless_block(BlockToCreate::Paragraph {
-                    paragraph: text.into_iter().collect(),
-                })]
-            }
-            Tag::OrderedList { items } => items
-                .into_iter()
-                .map(|item| BlockWithChildrenToCreate {
-                    block: BlockToCreate::NumberedListItem {
-                        numbered_list_item: notion::models::TextAndChildren {
-                            rich_text: item.text.iter().map(Into::into).collect(),
-                            children: Some(Vec::new()),
-                            color: notion::models::text::TextColor::Default,
-                        },
-                    },
-                    children: item
-                        .children
-                        .into_iter()
-                        .flat_map(Self::from_markdown_tag)
-                        .collect(),
-                })
-                .collect(),
-        }
-    }
+#[derive(Error, Debug)]
+pub enum GetDatabaseError {
+    #[error("cannot get database")]
+    RequestFailed(#[from] RequestError),
+
+    #[error("response cannot be deserialized")]
+    UnexpectedBody(#[from] serde_json::Error),
+
+    #[error("Notion rejected the request: {0}")]
+    Api(#[source] NotionApiError),
 }
 
-#[derive(Serialize)]
-struct ChildrenToCreate {
-    children: Vec<BlockToCreate>,
+#[derive(Error, Debug)]
+pub enum CheckAccessError {
+    #[error("cannot reach Notion, check the network connection and the proxy")]
+    RequestFailed(#[from] RequestError),
+
+    #[error("Notion rejected the API key, check that NOTION_API_KEY is the secret of an integration in the workspace")]
+    InvalidApiKey(#[source] NotionApiError),
+
+    #[error("response cannot be deserialized")]
+    UnexpectedBody(#[from] serde_json::Error),
+
+    #[error("Notion rejected the request: {0}")]
+    Api(#[source] NotionApiError),
 }
 
-#[async_recursion]
-pub async fn get_all_block_children(
-    notion_api: &notion::NotionApi,
-    block_id: &BlockId,
-) -> Result<Vec<BlockWithChildren>, notion::Error> {
-    let children = notion_api.get_block_children(block_id).await?;
+#[derive(Error, Debug)]
+pub enum GetPagesError {
+    #[error("cannot get pages")]
+    RequestFailed(#[from] RequestError),
 
-    if children.has_more {
-        // TODO: handle pagination
-        todo!("pagination when retrieving blocks")
-    }
+    #[error("response cannot be deserialized")]
+    UnexpectedBody(#[from] serde_json::Error),
+
+    #[error("Notion rejected the request: {0}")]
+    Api(#[source] NotionApiError),
+}
 
-    join_all(children.results.into_iter().map(|child_block| async {
-        let has_children = child_block
-            .common()
-            .map_or(false, |common| common.has_children);
+#[derive(Error, Debug)]
+pub enum GetBlockError {
+    #[error("cannot get the block")]
+    RequestFailed(#[from] RequestError),
 
-        if has_children {
-            get_all_block_children(notion_api, child_block.as_id())
-                .await
-                .map(|children| BlockWithChildren {
-                    block: child_block,
-                    children,
-                })
-        } else {
-            Ok(BlockWithChildren {
-                block: child_block,
-                children: Vec::new(),
-            })
-        }
-    }))
-    .await
-    .into_iter()
-    .collect()
+    #[error("response cannot be deserialized")]
+    UnexpectedBody(#[from] serde_json::Error),
+
+    #[error("Notion rejected the request: {0}")]
+    Api(#[source] NotionApiError),
+
+    #[error("the block {0} was deleted")]
+    Archived(BlockId),
 }
 
 #[derive(Error, Debug)]
-pub enum ErasePageError {
-    #[error("could not fetch page content")]
-    FetchingPageContent(#[from] Box<notion::Error>),
+pub enum SearchError {
+    #[error("cannot search the workspace")]
+    RequestFailed(#[from] RequestError),
 
-    #[error("deleting block failed")]
-    DeleteBlockFailed {
-        error: reqwest::Error,
-        block_id: BlockId,
-    },
+    #[error("response cannot be deserialized")]
+    UnexpectedBody(#[from] serde_json::Error),
+
+    #[error("Notion rejected the request: {0}")]
+    Api(#[source] NotionApiError),
 }
 
-pub async fn erase_page(
-    notion_api: &notion::NotionApi,
-    client: &NotionClient,
-    page_id: PageId,
-) -> Result<(), ErasePageError> {
-    let block_id: BlockId = page_id.into();
-    let blocks = notion_api
-        .get_block_children(block_id)
-        .await
-        .map_err(|error| ErasePageError::FetchingPageContent(Box::new(error)))?
-        .results;
+#[derive(Error, Debug)]
+pub enum ListUsersError {
+    #[error("cannot list users")]
+    RequestFailed(#[from] RequestError),
 
-    join_all(blocks.into_iter().map(|block| async move {
-        client
-            .delete_block(block.as_id().clone())
-            .await
-            .map_err(|error| ErasePageError::DeleteBlockFailed {
-                error,
-                block_id: block.as_id().clone(),
-            })
-    }))
-    .await
-    .into_iter()
-    // NOTE: only expose the first error. Discards information about other errors
-    .collect::<Result<Vec<()>, _>>()
-    .map(|_| ())
+    #[error("response cannot be deserialized")]
+    UnexpectedBody(#[from] serde_json::Error),
+
+    #[error("Notion rejected the request: {0}")]
+    Api(#[source] NotionApiError),
+}
+
+impl NotionClient {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            base_url: Url::parse(DEFAULT_API_BASE_URL).expect("valid default API base URL"),
+            retry_policy: RetryPolicy::default(),
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            concurrency_limit: Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENCY)),
+            access_checked: OnceCell::new(),
+            block_cache: None,
+            response_cache: None,
+            requests: AtomicUsize::new(0),
+            retries: AtomicUsize::new(0),
+        }
+    }
+
+    /// A client with the API key of an integration and the default HTTP options.
+    pub fn from_api_key(notion_api_key: &str) -> Self {
+        Self::new(get_notion_reqwest_client(
+            notion_api_key,
+            &HttpOptions::default(),
+        ))
+    }
+
+    /// Sends the requests to `base_url` instead of the public Notion API.
+    /// The URL should come from [`parse_api_base_url`].
+    pub fn with_base_url(self, base_url: Url) -> Self {
+        Self { base_url, ..self }
+    }
+
+    pub fn with_retry_policy(self, retry_policy: RetryPolicy) -> Self {
+        Self {
+            retry_policy,
+            ..self
+        }
+    }
+
+    /// Gives up on a request (including reading its response) that takes longer than `timeout`.
+    pub fn with_request_timeout(self, request_timeout: Duration) -> Self {
+        Self {
+            request_timeout,
+            ..self
+        }
+    }
+
+    /// Reuses the children of blocks that did not change since they were cached, instead of
+    /// listing them again.
+    pub fn with_block_cache(self, block_cache: BlockCache) -> Self {
+        Self {
+            block_cache: Some(block_cache),
+            ..self
+        }
+    }
+
+    /// Reuses the responses to `GET` requests for the same URL, for as long as the client
+    /// lives. Changes made through the client forget the responses about pages and blocks,
+    /// while changes made elsewhere are only seen after [`NotionClient::invalidate`].
+    pub fn with_response_cache(self) -> Self {
+        Self {
+            response_cache: Some(ResponseCache::default()),
+            ..self
+        }
+    }
+
+    /// Forgets the cached responses about the page, so it is fetched again. Blocks do not
+    /// know their page, so the children of all blocks are forgotten too.
+    pub fn invalidate(&self, page_id: &PageId) {
+        if let Some(cache) = &self.response_cache {
+            let page_id = page_id.to_string().replace('-', "");
+            cache.forget(|key| {
+                key.contains("/v1/blocks/") || key.replace('-', "").contains(&page_id)
+            });
+        }
+    }
+
+    /// Forgets all the cached responses.
+    pub fn invalidate_all(&self) {
+        if let Some(cache) = &self.response_cache {
+            cache.forget(|_| true);
+        }
+    }
+
+    /// The most requests sent at the same time.
+    pub fn max_concurrency(&self) -> usize {
+        self.max_concurrency
+    }
+
+    /// Sends at most `max_concurrency` requests at the same time.
+    pub fn with_max_concurrency(self, max_concurrency: usize) -> Self {
+        let max_concurrency = max_concurrency.max(1);
+
+        Self {
+            max_concurrency,
+            concurrency_limit: Arc::new(Semaphore::new(max_concurrency)),
+            ..self
+        }
+    }
+
+    /// The requests sent by the client so far.
+    pub fn request_counts(&self) -> RequestCounts {
+        RequestCounts {
+            requests: self.requests.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Sends the request, or reuses the response to the same `GET` request with the response
+    /// cache.
+    async fn send(&self, request: RequestBuilder) -> Result<NotionResponse, RequestError> {
+        let cache = self.response_cache.as_ref();
+        let key = match request.try_clone().map(RequestBuilder::build) {
+            Some(Ok(built)) if built.method() == Method::GET => {
+                Some(format!("{} {}", built.method(), built.url()))
+            }
+            _ => None,
+        };
+
+        match cache.zip(key) {
+            Some((cache, key)) => cache.get_or_send(key, || self.send_uncached(request)).await,
+            None => self.send_uncached(request).await,
+        }
+    }
+
+    /// Sends a request that changes pages or blocks, and forgets the cached responses about
+    /// them.
+    async fn send_change(&self, request: RequestBuilder) -> Result<NotionResponse, RequestError> {
+        let response = self.send(request).await;
+        if let Some(cache) = &self.response_cache {
+            cache.forget(|key| key.contains("/v1/pages/") || key.contains("/v1/blocks/"));
+        }
+
+        response
+    }
+
+    /// Sends the request and reads the response, retrying it when the retry policy allows it.
+    async fn send_uncached(&self, request: RequestBuilder) -> Result<NotionResponse, RequestError> {
+        let mut retry = 0;
+
+        loop {
+            let attempt = request
+                .try_clone()
+                .expect("requests without streamed bodies can be cloned");
+            let attempt = attempt.build()?;
+            let method = attempt.method().clone();
+            let path = attempt.url().path().to_string();
+            let (response, text) = {
+                let _permit = self
+                    .concurrency_limit
+                    .acquire()
+                    .await
+                    .expect("the semaphore is never closed");
+                let started = Instant::now();
+                self.requests.fetch_add(1, Ordering::Relaxed);
+                tokio::time::timeout(self.request_timeout, async {
+                    let response = self.client.execute(attempt).await?;
+                    info!(
+                        %method,
+                        path,
+                        status = response.status().as_u16(),
+                        duration_ms = started.elapsed().as_millis() as u64,
+                        "request"
+                    );
+                    let status = response.status();
+                    let headers = response.headers().clone();
+                    let text = response.text().await?;
+
+                    Ok::<_, reqwest::Error>(((status, headers), text))
+                })
+                .await
+                .map_err(|_| RequestError::TimedOut {
+                    timeout: self.request_timeout,
+                })??
+            };
+            let (status, headers) = response;
+
+            match self
+                .retry_policy
+                .retry_delay(retry, &method, status, &headers)
+            {
+                Some(delay) => {
+                    info!(%method, path, delay = ?delay, "retrying");
+                    tokio::time::sleep(delay).await;
+                    retry += 1;
+                    self.retries.fetch_add(1, Ordering::Relaxed);
+                }
+                None => return Ok(NotionResponse { status, text }),
+            }
+        }
+    }
+
+    /// The URL of an endpoint of the API, with the segments appended to the base URL.
+    fn endpoint(&self, segments: &[&str]) -> Url {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .expect("the base URL is an http(s) URL")
+            .pop_if_empty()
+            .extend(segments);
+
+        url
+    }
+
+    /// Gets a single page of the direct children of the block.
+    #[instrument(level = "debug", skip_all, fields(%block_id))]
+    pub async fn get_block_children(
+        &self,
+        block_id: &BlockId,
+        start_cursor: Option<&PagingCursor>,
+    ) -> Result<ListResponse<Block>, GetBlockChildrenError> {
+        let mut request = self
+            .client
+            .get(self.endpoint(&["v1", "blocks", &block_id.to_string(), "children"]))
+            .query(&[("page_size", "100")]);
+        if let Some(start_cursor) = start_cursor {
+            request = request.query(&[("start_cursor", start_cursor)]);
+        }
+
+        let NotionResponse {
+            status: status_code,
+            text: response_text,
+        } = self.send(request).await?;
+        if !status_code.is_success() {
+            return Err(GetBlockChildrenError::Api(NotionApiError::from_response(
+                status_code,
+                &response_text,
+            )));
+        }
+
+        let page: ListResponse<serde_json::Value> = serde_json::from_str(&response_text)?;
+        let page = ListResponse {
+            results: page
+                .results
+                .into_iter()
+                .map(parse_block)
+                .collect::<Result<_, _>>()?,
+            next_cursor: page.next_cursor,
+            has_more: page.has_more,
+        };
+        debug!(
+            blocks = page.results.len(),
+            next_cursor = ?page.next_cursor.as_ref().filter(|_| page.has_more),
+            "got block children"
+        );
+
+        Ok(page)
+    }
+
+    /// Gets all the direct children of the block, page by page.
+    #[instrument(level = "debug", skip_all, fields(%block_id))]
+    pub async fn get_all_direct_block_children(
+        &self,
+        block_id: &BlockId,
+    ) -> Result<Vec<Block>, GetBlockChildrenError> {
+        let mut blocks = Vec::new();
+        let mut start_cursor: Option<PagingCursor> = None;
+
+        loop {
+            let page = self
+                .get_block_children(block_id, start_cursor.as_ref())
+                .await?;
+            blocks.extend(page.results);
+
+            match page.next_cursor {
+                Some(next_cursor) if page.has_more => start_cursor = Some(next_cursor),
+                _ => return Ok(blocks),
+            }
+        }
+    }
+
+    /// Checks that Notion accepts the requests of the client, for example its `Notion-Version`,
+    /// by getting the bot user of the API key.
+    pub async fn check_access(&self) -> Result<(), CheckAccessError> {
+        self.get_bot_user().await.map(|_| ())
+    }
+
+    /// Checks that Notion accepts the API key, once for the client. Used before changes that
+    /// cannot be undone, so an invalid key is reported before anything is erased.
+    pub async fn ensure_access(&self) -> Result<(), CheckAccessError> {
+        self.access_checked
+            .get_or_try_init(|| self.check_access())
+            .await
+            .map(|_| ())
+    }
+
+    /// Gets the bot user the API key belongs to.
+    pub async fn get_bot_user(&self) -> Result<BotUser, CheckAccessError> {
+        let NotionResponse {
+            status: status_code,
+            text: response_text,
+        } = self
+            .send(self.client.get(self.endpoint(&["v1", "users", "me"])))
+            .await?;
+        if status_code == StatusCode::UNAUTHORIZED {
+            return Err(CheckAccessError::InvalidApiKey(
+                NotionApiError::from_response(status_code, &response_text),
+            ));
+        }
+        if !status_code.is_success() {
+            return Err(CheckAccessError::Api(NotionApiError::from_response(
+                status_code,
+                &response_text,
+            )));
+        }
+
+        let user: serde_json::Value = serde_json::from_str(&response_text)?;
+        let owner = &user["bot"]["owner"];
+        let owner = match owner["type"].as_str() {
+            Some("user") => BotOwner::User {
+                name: owner["user"]["name"]
+                    .as_str()
+                    .or_else(|| owner["user"]["id"].as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+            },
+            _ => BotOwner::Workspace,
+        };
+
+        Ok(BotUser {
+            name: user["name"].as_str().unwrap_or_default().to_string(),
+            workspace_name: user["bot"]["workspace_name"].as_str().map(str::to_string),
+            owner,
+        })
+    }
+
+    /// Gets the title of the page.
+    #[instrument(level = "debug", skip_all, fields(%page_id))]
+    pub async fn get_page(&self, page_id: &PageId) -> Result<PageSummary, GetPagesError> {
+        let NotionResponse {
+            status: status_code,
+            text: response_text,
+        } = self
+            .send(
+                self.client
+                    .get(self.endpoint(&["v1", "pages", &page_id.to_string()])),
+            )
+            .await?;
+        if !status_code.is_success() {
+            return Err(GetPagesError::Api(NotionApiError::from_response(
+                status_code,
+                &response_text,
+            )));
+        }
+
+        Ok(serde_json::from_str(&response_text)?)
+    }
+
+    /// Gets the block, so a block from a URL is known to exist before its children are
+    /// fetched or changed. Blocks in the trash are an error too.
+    #[instrument(level = "debug", skip_all, fields(%block_id))]
+    pub async fn get_block(&self, block_id: &BlockId) -> Result<Block, GetBlockError> {
+        let NotionResponse {
+            status: status_code,
+            text: response_text,
+        } = self
+            .send(
+                self.client
+                    .get(self.endpoint(&["v1", "blocks", &block_id.to_string()])),
+            )
+            .await?;
+        if !status_code.is_success() {
+            return Err(GetBlockError::Api(NotionApiError::from_response(
+                status_code,
+                &response_text,
+            )));
+        }
+
+        let block: serde_json::Value = serde_json::from_str(&response_text)?;
+        if block["archived"].as_bool() == Some(true) {
+            return Err(GetBlockError::Archived(block_id.clone()));
+        }
+
+        Ok(serde_json::from_value(block)?)
+    }
+
+    /// Lists all the pages shared with the integration.
+    pub async fn search_pages(&self) -> Result<Vec<PageSummary>, GetPagesError> {
+        let mut pages = Vec::new();
+        let mut start_cursor: Option<PagingCursor> = None;
+
+        loop {
+            let mut body = json!({
+                "filter": { "property": "object", "value": "page" },
+                "page_size": 100
+            });
+            if let Some(start_cursor) = &start_cursor {
+                body["start_cursor"] = json!(start_cursor);
+            }
+
+            let NotionResponse {
+                status: status_code,
+                text: response_text,
+            } = self
+                .send(
+                    self.client
+                        .post(self.endpoint(&["v1", "search"]))
+                        .json(&body),
+                )
+                .await?;
+            if !status_code.is_success() {
+                return Err(GetPagesError::Api(NotionApiError::from_response(
+                    status_code,
+                    &response_text,
+                )));
+            }
+
+            let page = serde_json::from_str::<ListResponse<PageSummary>>(&response_text)?;
+            debug!(
+                results = page.results.len(),
+                next_cursor = ?page.next_cursor.as_ref().filter(|_| page.has_more),
+                "got a page of results"
+            );
+            pages.extend(page.results);
+
+            match page.next_cursor {
+                Some(next_cursor) if page.has_more => start_cursor = Some(next_cursor),
+                _ => return Ok(pages),
+            }
+        }
+    }
+
+    /// Lists all the pages and databases shared with the integration whose title matches
+    /// the `query`, optionally only the ones of the `object` type.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn search(
+        &self,
+        query: Option<&str>,
+        object: Option<SearchObject>,
+    ) -> Result<Vec<SearchResult>, SearchError> {
+        let mut results = Vec::new();
+        let mut start_cursor: Option<PagingCursor> = None;
+
+        loop {
+            let mut body = json!({ "page_size": 100 });
+            if let Some(query) = query {
+                body["query"] = json!(query);
+            }
+            if let Some(object) = object {
+                body["filter"] = json!({ "property": "object", "value": object });
+            }
+            if let Some(start_cursor) = &start_cursor {
+                body["start_cursor"] = json!(start_cursor);
+            }
+
+            let NotionResponse {
+                status: status_code,
+                text: response_text,
+            } = self
+                .send(
+                    self.client
+                        .post(self.endpoint(&["v1", "search"]))
+                        .json(&body),
+                )
+                .await?;
+            if !status_code.is_success() {
+                return Err(SearchError::Api(NotionApiError::from_response(
+                    status_code,
+                    &response_text,
+                )));
+            }
+
+            let page = serde_json::from_str::<ListResponse<serde_json::Value>>(&response_text)?;
+            debug!(
+                results = page.results.len(),
+                next_cursor = ?page.next_cursor.as_ref().filter(|_| page.has_more),
+                "got a page of results"
+            );
+            results.extend(page.results.iter().filter_map(SearchResult::from_json));
+
+            match page.next_cursor {
+                Some(next_cursor) if page.has_more => start_cursor = Some(next_cursor),
+                _ => return Ok(results),
+            }
+        }
+    }
+
+    /// Lists all the pages of the database that match the `filter`, in the format of
+    /// the Notion API.
+    #[instrument(level = "debug", skip_all, fields(%database_id))]
+    pub async fn query_database(
+        &self,
+        database_id: &DatabaseId,
+        filter: Option<&serde_json::Value>,
+    ) -> Result<Vec<PageSummary>, GetPagesError> {
+        let mut pages = Vec::new();
+        let mut start_cursor: Option<PagingCursor> = None;
+
+        loop {
+            let mut body = json!({ "page_size": 100 });
+            if let Some(filter) = filter {
+                body["filter"] = filter.clone();
+            }
+            if let Some(start_cursor) = &start_cursor {
+                body["start_cursor"] = json!(start_cursor);
+            }
+
+            let NotionResponse {
+                status: status_code,
+                text: response_text,
+            } = self
+                .send(
+                    self.client
+                        .post(self.endpoint(&[
+                            "v1",
+                            "databases",
+                            &database_id.to_string(),
+                            "query",
+                        ]))
+                        .json(&body),
+                )
+                .await?;
+            if !status_code.is_success() {
+                return Err(GetPagesError::Api(NotionApiError::from_response(
+                    status_code,
+                    &response_text,
+                )));
+            }
+
+            let page = serde_json::from_str::<ListResponse<PageSummary>>(&response_text)?;
+            debug!(
+                results = page.results.len(),
+                next_cursor = ?page.next_cursor.as_ref().filter(|_| page.has_more),
+                "got a page of results"
+            );
+            pages.extend(page.results);
+
+            match page.next_cursor {
+                Some(next_cursor) if page.has_more => start_cursor = Some(next_cursor),
+                _ => return Ok(pages),
+            }
+        }
+    }
+
+    /// Lists all the users in the workspace.
+    pub async fn list_users(&self) -> Result<Vec<User>, ListUsersError> {
+        let mut users = Vec::new();
+        let mut start_cursor: Option<PagingCursor> = None;
+
+        loop {
+            let mut request = self
+                .client
+                .get(self.endpoint(&["v1", "users"]))
+                .query(&[("page_size", "100")]);
+            if let Some(start_cursor) = &start_cursor {
+                request = request.query(&[("start_cursor", start_cursor)]);
+            }
+
+            let NotionResponse {
+                status: status_code,
+                text: response_text,
+            } = self.send(request).await?;
+            if !status_code.is_success() {
+                return Err(ListUsersError::Api(NotionApiError::from_response(
+                    status_code,
+                    &response_text,
+                )));
+            }
+
+            let page = serde_json::from_str::<ListResponse<User>>(&response_text)?;
+            debug!(
+                results = page.results.len(),
+                next_cursor = ?page.next_cursor.as_ref().filter(|_| page.has_more),
+                "got a page of results"
+            );
+            users.extend(page.results);
+
+            match page.next_cursor {
+                Some(next_cursor) if page.has_more => start_cursor = Some(next_cursor),
+                _ => return Ok(users),
+            }
+        }
+    }
+
+    /// The properties of the database, with their options.
+    pub async fn get_database_properties(
+        &self,
+        database_id: &str,
+    ) -> Result<serde_json::Map<String, serde_json::Value>, GetDatabaseError> {
+        #[derive(Deserialize)]
+        struct DatabaseObject {
+            properties: serde_json::Map<String, serde_json::Value>,
+        }
+
+        let NotionResponse {
+            status: status_code,
+            text: response_text,
+        } = self
+            .send(
+                self.client
+                    .get(self.endpoint(&["v1", "databases", database_id])),
+            )
+            .await?;
+        if !status_code.is_success() {
+            return Err(GetDatabaseError::Api(NotionApiError::from_response(
+                status_code,
+                &response_text,
+            )));
+        }
+
+        Ok(serde_json::from_str::<DatabaseObject>(&response_text)?.properties)
+    }
+
+    /// Updates the properties, the icon, the cover or the trash state of the page.
+    /// Returns the updated page.
+    #[instrument(level = "debug", skip_all, fields(%page_id))]
+    pub async fn update_page(
+        &self,
+        page_id: &PageId,
+        update: &PageUpdate,
+    ) -> Result<PageSummary, UpdatePageError> {
+        let NotionResponse {
+            status: status_code,
+            text: response_text,
+        } = self
+            .send_change(
+                self.client
+                    .patch(self.endpoint(&["v1", "pages", &page_id.to_string()]))
+                    .json(update),
+            )
+            .await?;
+        if !status_code.is_success() {
+            return Err(UpdatePageError::Api(NotionApiError::from_response(
+                status_code,
+                &response_text,
+            )));
+        }
+
+        Ok(serde_json::from_str(&response_text)?)
+    }
+
+    /// Creates an empty page with the `title` under the parent page.
+    #[instrument(level = "debug", skip_all, fields(%parent_page_id))]
+    pub async fn create_page(
+        &self,
+        parent_page_id: &PageId,
+        title: &str,
+    ) -> Result<CreatedPage, CreatePageError> {
+        let NotionResponse {
+            status: status_code,
+            text: response_text,
+        } = self
+            .send_change(
+                self.client
+                    .post(self.endpoint(&["v1", "pages"]))
+                    .json(&json!({
+                        "parent": { "page_id": parent_page_id },
+                        "properties": {
+                            "title": { "title": [{ "text": { "content": title } }] }
+                        }
+                    })),
+            )
+            .await?;
+        if !status_code.is_success() {
+            return Err(CreatePageError::Api(NotionApiError::from_response(
+                status_code,
+                &response_text,
+            )));
+        }
+
+        Ok(serde_json::from_str(&response_text)?)
+    }
+
+    /// Creates an empty page in the database, with the `properties` in the format of
+    /// the Notion API.
+    #[instrument(level = "debug", skip_all, fields(%database_id))]
+    pub async fn create_database_page(
+        &self,
+        database_id: &DatabaseId,
+        properties: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<CreatedPage, CreatePageError> {
+        let NotionResponse {
+            status: status_code,
+            text: response_text,
+        } = self
+            .send_change(
+                self.client
+                    .post(self.endpoint(&["v1", "pages"]))
+                    .json(&json!({
+                        "parent": { "database_id": database_id },
+                        "properties": properties
+                    })),
+            )
+            .await?;
+        if !status_code.is_success() {
+            return Err(CreatePageError::Api(NotionApiError::from_response(
+                status_code,
+                &response_text,
+            )));
+        }
+
+        Ok(serde_json::from_str(&response_text)?)
+    }
+
+    #[instrument(level = "debug", skip_all, fields(%block_id))]
+    pub async fn delete_block(&self, block_id: BlockId) -> Result<(), DeleteBlockError> {
+        let NotionResponse {
+            status: status_code,
+            text: response_text,
+        } = self
+            .send_change(self.client.delete(self.endpoint(&[
+                "v1",
+                "blocks",
+                &block_id.to_string(),
+            ])))
+            .await?;
+        if !status_code.is_success() {
+            return Err(DeleteBlockError::Api(NotionApiError::from_response(
+                status_code,
+                &response_text,
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the rich text of the block of the type, keeping the rest of its content.
+    #[instrument(level = "debug", skip_all, fields(%block_id))]
+    pub async fn update_block_text(
+        &self,
+        block_id: &BlockId,
+        block_type: &str,
+        rich_text: serde_json::Value,
+    ) -> Result<(), UpdateBlockError> {
+        let NotionResponse {
+            status: status_code,
+            text: response_text,
+        } = self
+            .send_change(
+                self.client
+                    .patch(self.endpoint(&["v1", "blocks", &block_id.to_string()]))
+                    .json(&json!({ block_type: { "rich_text": rich_text } })),
+            )
+            .await?;
+        if !status_code.is_success() {
+            return Err(UpdateBlockError::Api(NotionApiError::from_response(
+                status_code,
+                &response_text,
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Appends the blocks to the parent in as many requests as needed, in order, after
+    /// the `after` block or at the end. Returns the created blocks in the same order.
+    async fn append_block_children_shallow(
+        &self,
+        parent_block_id: BlockId,
+        parent_position: &[usize],
+        mut after: Option<BlockId>,
+        children: Vec<BlockToCreate>,
+    ) -> Result<Vec<notion::models::Block>, CreateBlocksError> {
+        let mut pending_chunks = VecDeque::new();
+        let mut chunk_start = 0;
+        let mut chunk_blocks = 0;
+        for (index, block) in children.iter().enumerate() {
+            // NOTE: the children sent along with their parents count towards the limit
+            let blocks = 1 + block.children().len();
+            if index > chunk_start && chunk_blocks + blocks > MAX_BLOCKS_PER_REQUEST {
+                pending_chunks.push_back(chunk_start..index);
+                chunk_start = index;
+                chunk_blocks = 0;
+            }
+            chunk_blocks += blocks;
+        }
+        if chunk_start < children.len() {
+            pending_chunks.push_back(chunk_start..children.len());
+        }
+
+        let mut created_blocks = Vec::new();
+        // NOTE: chunks are sent one by one, so each one is appended after the previous one
+        while let Some(chunk) = pending_chunks.pop_front() {
+            match self
+                .append_block_children_request(
+                    &parent_block_id,
+                    after.clone(),
+                    &children[chunk.clone()],
+                )
+                .await
+            {
+                Ok(blocks) => {
+                    if after.is_some() {
+                        after = blocks.last().map(|block| block.as_id().clone());
+                    }
+                    created_blocks.extend(blocks);
+                }
+                Err(AppendBlockChildrenError::PayloadTooLarge { blocks }) if blocks > 1 => {
+                    let middle = chunk.start + blocks / 2;
+                    pending_chunks.push_front(middle..chunk.end);
+                    pending_chunks.push_front(chunk.start..middle);
+                }
+                Err(error) => {
+                    return Err(CreateBlocksError {
+                        preview: children[chunk.start].preview(),
+                        parent_block_id,
+                        parent_position: parent_position.to_vec(),
+                        blocks: chunk,
+                        error,
+                    })
+                }
+            }
+        }
+
+        Ok(created_blocks)
+    }
+
+    async fn append_block_children_request(
+        &self,
+        parent_block_id: &BlockId,
+        after: Option<BlockId>,
+        children: &[BlockToCreate],
+    ) -> Result<Vec<notion::models::Block>, AppendBlockChildrenError> {
+        let append_block_children_url =
+            self.endpoint(&["v1", "blocks", &parent_block_id.to_string(), "children"]);
+        let children_to_create = ChildrenToCreate { children, after };
+
+        let NotionResponse {
+            status: status_code,
+            text: response_text,
+        } = self
+            .send_change(
+                self.client
+                    .patch(append_block_children_url)
+                    .json(&children_to_create),
+            )
+            .await?;
+
+        if status_code == StatusCode::PAYLOAD_TOO_LARGE {
+            return Err(AppendBlockChildrenError::PayloadTooLarge {
+                blocks: children.len(),
+            });
+        }
+        if !status_code.is_success() {
+            return Err(AppendBlockChildrenError::Api(
+                NotionApiError::from_response(status_code, &response_text),
+            ));
+        }
+
+        let created_blocks =
+            serde_json::from_str::<ListResponse<notion::models::Block>>(&response_text)
+                .map_err(AppendBlockChildrenError::UnexpectedBody)?
+                .results;
+
+        Ok(created_blocks)
+    }
+
+    /// Creates the blocks along with all their nested children.
+    /// Returns the created blocks, without their children.
+    ///
+    /// The blocks that could not be created are returned in the order of the document.
+    #[instrument(skip_all, fields(%parent_block_id, blocks = blocks_to_create.len()))]
+    pub async fn create_blocks(
+        &self,
+        parent_block_id: BlockId,
+        blocks_to_create: Vec<BlockWithChildrenToCreate>,
+    ) -> Result<Vec<CreatedBlock>, Vec<CreateBlocksError>> {
+        self.create_blocks_at(parent_block_id, Vec::new(), None, blocks_to_create)
+            .await
+    }
+
+    /// Creates the blocks along with all their nested children right after the `after` block,
+    /// which is a child of the parent.
+    #[instrument(skip_all, fields(%parent_block_id, %after, blocks = blocks_to_create.len()))]
+    pub async fn create_blocks_after(
+        &self,
+        parent_block_id: BlockId,
+        after: BlockId,
+        blocks_to_create: Vec<BlockWithChildrenToCreate>,
+    ) -> Result<Vec<CreatedBlock>, Vec<CreateBlocksError>> {
+        self.create_blocks_at(parent_block_id, Vec::new(), Some(after), blocks_to_create)
+            .await
+    }
+
+    #[async_recursion]
+    async fn create_blocks_at(
+        &self,
+        parent_block_id: BlockId,
+        parent_position: Vec<usize>,
+        after: Option<BlockId>,
+        blocks_to_create: Vec<BlockWithChildrenToCreate>,
+    ) -> Result<Vec<CreatedBlock>, Vec<CreateBlocksError>> {
+        // NOTE: Notion accepts children nested two levels deep in a single request, so the
+        // children are sent along with their parents. Deeper levels are appended afterwards.
+        let summaries: Vec<_> = blocks_to_create
+            .iter()
+            .map(|block| {
+                (
+                    block.block.block_type(),
+                    block.block.text_preview(),
+                    block.children.len(),
+                )
+            })
+            .collect();
+        let (top_level_blocks_to_create, pending_children): (Vec<_>, Vec<_>) = blocks_to_create
+            .into_iter()
+            .map(BlockWithChildrenToCreate::inline_children)
+            .unzip();
+
+        let created_blocks = self
+            .append_block_children_shallow(
+                parent_block_id.clone(),
+                &parent_position,
+                after,
+                top_level_blocks_to_create,
+            )
+            .await
+            .map_err(|error| vec![error])?;
+        let created_block_summaries = std::iter::zip(&created_blocks, summaries)
+            .map(|(block, (block_type, preview, children))| CreatedBlock {
+                block_id: block.as_id().clone(),
+                block_type,
+                preview,
+                children,
+            })
+            .collect();
+
+        // NOTE: Notion returns the created blocks in the order they were sent
+        let results = join_all(
+            std::iter::zip(pending_children, created_blocks)
+                .enumerate()
+                .map(|(index, (pending_children, created_block))| {
+                    let mut position = parent_position.clone();
+                    position.push(index);
+                    async move {
+                        match pending_children {
+                            PendingChildren::Children(children) if !children.is_empty() => self
+                                .create_blocks_at(
+                                    created_block.as_id().clone(),
+                                    position,
+                                    None,
+                                    children,
+                                )
+                                .await
+                                .map(drop),
+                            PendingChildren::Grandchildren(grandchildren)
+                                if grandchildren.iter().any(|children| !children.is_empty()) =>
+                            {
+                                self.create_grandchildren(
+                                    created_block.as_id(),
+                                    position,
+                                    grandchildren,
+                                )
+                                .await
+                            }
+                            _ => Ok(()),
+                        }
+                    }
+                }),
+        )
+        .await;
+
+        flatten_errors(results).map(|()| created_block_summaries)
+    }
+
+    /// Creates the children of the inlined children of the block.
+    /// The created children are not returned when creating the block, so they are fetched.
+    async fn create_grandchildren(
+        &self,
+        block_id: &BlockId,
+        position: Vec<usize>,
+        grandchildren: Vec<Vec<BlockWithChildrenToCreate>>,
+    ) -> Result<(), Vec<CreateBlocksError>> {
+        let created_children = match self.get_all_direct_block_children(block_id).await {
+            Ok(created_children) => created_children,
+            Err(error) => {
+                // NOTE: none of the grandchildren can be created, so the error covers the
+                // children that have some
+                let with_children: Vec<_> = grandchildren
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, children)| !children.is_empty())
+                    .collect();
+                let (first, first_children) = with_children[0];
+                let (last, _) = with_children[with_children.len() - 1];
+                return Err(vec![CreateBlocksError {
+                    parent_block_id: block_id.clone(),
+                    parent_position: position,
+                    blocks: first..last + 1,
+                    preview: first_children[0].block.preview(),
+                    error: error.into(),
+                }]);
+            }
+        };
+
+        let results = join_all(
+            std::iter::zip(grandchildren, created_children)
+                .enumerate()
+                .map(|(index, (children, created_child))| {
+                    let mut position = position.clone();
+                    position.push(index);
+                    async move {
+                        if children.is_empty() {
+                            Ok(())
+                        } else {
+                            self.create_blocks_at(
+                                created_child.as_id().clone(),
+                                position,
+                                None,
+                                children,
+                            )
+                            .await
+                            .map(drop)
+                        }
+                    }
+                }),
+        )
+        .await;
+
+        flatten_errors(results)
+    }
+}
+
+/// The errors of all the results, in the order of the document.
+fn flatten_errors(
+    results: Vec<Result<(), Vec<CreateBlocksError>>>,
+) -> Result<(), Vec<CreateBlocksError>> {
+    let mut errors: Vec<_> = results
+        .into_iter()
+        .filter_map(Result::err)
+        .flatten()
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        errors.sort_by_key(CreateBlocksError::position);
+        Err(errors)
+    }
+}
+
+/// A response of the Notion API, read in full.
+#[derive(Debug, Clone)]
+pub(super) struct NotionResponse {
+    pub(super) status: StatusCode,
+    pub(super) text: String,
+}
+
+/// How the HTTP client connects to Notion.
+#[derive(Debug, Clone)]
+pub struct HttpOptions {
+    /// The `Notion-Version` header sent with every request.
+    pub notion_version: String,
+    pub connect_timeout: Duration,
+    /// The proxy for all requests. Without it, the `HTTPS_PROXY`, `HTTP_PROXY` and `ALL_PROXY`
+    /// environment variables are used.
+    pub proxy: Option<Url>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            notion_version: DEFAULT_NOTION_VERSION.to_string(),
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            proxy: None,
+        }
+    }
+}
+
+pub fn get_notion_reqwest_client(notion_api_key: &str, options: &HttpOptions) -> reqwest::Client {
+    let mut headers = header::HeaderMap::new();
+    headers.append(
+        "Notion-Version",
+        header::HeaderValue::from_str(&options.notion_version).expect("valid Notion version"),
+    );
+
+    let mut auth_value =
+        header::HeaderValue::from_str(&format!("Bearer {}", notion_api_key)).expect("valid header");
+    auth_value.set_sensitive(true);
+    headers.append(header::AUTHORIZATION, auth_value);
+
+    let mut builder = reqwest::ClientBuilder::new()
+        .default_headers(headers)
+        .connect_timeout(options.connect_timeout);
+    if let Some(proxy) = &options.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy.clone()).expect("valid proxy URL"));
+    }
+
+    builder.build().expect("valid reqwest client")
+}
+
+// NOTE: the Block enum from the notion crate requires too much boilerplate information
+// that is not required to create the block.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BlockToCreate {
+    Heading1 {
+        heading_1: notion::models::Text,
+    },
+    Heading2 {
+        heading_2: notion::models::Text,
+    },
+    Heading3 {
+        heading_3: notion::models::Text,
+    },
+    Paragraph {
+        paragraph: notion::models::Text,
+    },
+    NumberedListItem {
+        numbered_list_item: TextAndChildrenToCreate,
+    },
+    Quote {
+        quote: TextAndChildrenToCreate,
+    },
+    Callout {
+        callout: CalloutToCreate,
+    },
+    Toggle {
+        toggle: TextAndChildrenToCreate,
+    },
+    Code {
+        code: notion::models::CodeFields,
+    },
+    Image {
+        image: FileToCreate,
+    },
+    Divider {
+        divider: EmptyObject,
+    },
+}
+
+/// The empty object that blocks without any content, like dividers, are created with.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct EmptyObject {}
+
+impl BlockToCreate {
+    /// The type of the block and the start of the first line of its text, to tell which
+    /// block an error is about without dumping the whole block.
+    pub fn preview(&self) -> String {
+        let block_type = self.block_type();
+        let text = self.text_preview();
+        if text.is_empty() {
+            format!("a {block_type}")
+        } else {
+            format!("{block_type} \"{text}\"")
+        }
+    }
+
+    /// The type of the block in the Notion API, like `paragraph`.
+    pub fn block_type(&self) -> String {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|value| value.as_object()?.keys().next().cloned())
+            .unwrap_or_else(|| "block".to_string())
+    }
+
+    /// The start of the first line of the text of the block, empty for blocks without text.
+    pub fn text_preview(&self) -> String {
+        const MAX_PREVIEW_LENGTH: usize = 40;
+
+        let value = serde_json::to_value(self).unwrap_or_default();
+        let Some(content) = value.as_object().and_then(|block| block.values().next()) else {
+            return String::new();
+        };
+        let text: String = content["rich_text"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|rich_text| rich_text["text"]["content"].as_str())
+            .collect();
+        let line = text.lines().next().unwrap_or_default().trim();
+
+        let mut preview: String = line.chars().take(MAX_PREVIEW_LENGTH).collect();
+        if preview.len() < line.len() {
+            preview.push('…');
+        }
+
+        preview
+    }
+
+    fn children(&self) -> &[BlockToCreate] {
+        match self {
+            BlockToCreate::NumberedListItem {
+                numbered_list_item: content,
+            }
+            | BlockToCreate::Quote { quote: content }
+            | BlockToCreate::Toggle { toggle: content } => &content.children,
+            BlockToCreate::Callout { callout } => &callout.children,
+            _ => &[],
+        }
+    }
+
+    fn children_mut(&mut self) -> Option<&mut Vec<BlockToCreate>> {
+        match self {
+            BlockToCreate::NumberedListItem {
+                numbered_list_item: content,
+            }
+            | BlockToCreate::Quote { quote: content }
+            | BlockToCreate::Toggle { toggle: content } => Some(&mut content.children),
+            BlockToCreate::Callout { callout } => Some(&mut callout.children),
+            _ => None,
+        }
+    }
+}
+
+/// Like [`notion::models::TextAndChildren`], but the children are blocks to create.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TextAndChildrenToCreate {
+    pub rich_text: Vec<notion::models::text::RichText>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<BlockToCreate>,
+    pub color: notion::models::text::TextColor,
+}
+
+impl TextAndChildrenToCreate {
+    fn childless(text: &[RichText]) -> Self {
+        Self {
+            rich_text: text.iter().map(Into::into).collect(),
+            children: Vec::new(),
+            color: notion::models::text::TextColor::Default,
+        }
+    }
+}
+
+/// Like [`notion::models::Callout`], but with the children to create.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CalloutToCreate {
+    pub rich_text: Vec<notion::models::text::RichText>,
+    pub icon: notion::models::FileOrEmojiObject,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<BlockToCreate>,
+    pub color: notion::models::text::TextColor,
+}
+
+/// Like [`notion::models::FileObject`], whose URL cannot be set. Only external files can be
+/// created through the API.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FileToCreate {
+    External { external: ExternalFileToCreate },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExternalFileToCreate {
+    pub url: String,
+}
+
+/// A block created by [`NotionClient::create_blocks`], with the ID that Notion gave it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CreatedBlock {
+    pub block_id: BlockId,
+    /// The type of the block in the Notion API, like `paragraph`.
+    #[serde(rename = "type")]
+    pub block_type: String,
+    /// The start of the first line of the text of the block.
+    pub preview: String,
+    /// The number of blocks nested directly in the block.
+    pub children: usize,
+}
+
+pub struct BlockWithChildrenToCreate {
+    block: BlockToCreate,
+    children: Vec<BlockWithChildrenToCreate>,
+}
+
+/// The children that still have to be created after their parent block is created.
+enum PendingChildren {
+    /// The children of the block itself.
+    Children(Vec<BlockWithChildrenToCreate>),
+    /// The children of each child, which were created along with the block.
+    Grandchildren(Vec<Vec<BlockWithChildrenToCreate>>),
+}
+
+impl BlockWithChildrenToCreate {
+    fn childless_block(block: BlockToCreate) -> Self {
+        Self {
+            block,
+            children: Vec::new(),
+        }
+    }
+
+    /// Moves the children into the block, so they are created in the same request, unless the
+    /// block and its children would not fit in a single request.
+    fn inline_children(self) -> (BlockToCreate, PendingChildren) {
+        let Self {
+            mut block,
+            children,
+        } = self;
+
+        match block.children_mut() {
+            Some(inline_children)
+                if !children.is_empty() && children.len() < MAX_BLOCKS_PER_REQUEST =>
+            {
+                let grandchildren = children
+                    .into_iter()
+                    .map(|child| {
+                        inline_children.push(child.block);
+                        child.children
+                    })
+                    .collect();
+
+                (block, PendingChildren::Grandchildren(grandchildren))
+            }
+            _ => (block, PendingChildren::Children(children)),
+        }
+    }
+
+    /// Transforms the tag into a vector of blocks to create, with the blocks nested in the tag
+    /// (for example in list items) as their children.
+    ///
+    /// The children are only sent along with their parent when it is created, as Notion accepts
+    /// blocks nested two levels deep in a single request. The deeper levels are appended to the
+    /// created blocks afterwards, see [`NotionClient::create_blocks`].
+    pub fn from_markdown_tag(tag: Tag) -> Vec<Self> {
+        match tag {
+            Tag::Heading {
+                level: HeadingLevel::H1,
+                text,
+                id,
+            } => vec![Self::childless_block(BlockToCreate::Heading1 {
+                heading_1: heading_rich_text(text, id).into_iter().collect(),
+            })],
+            Tag::Heading {
+                level: HeadingLevel::H2,
+                text,
+                id,
+            } => vec![Self::childless_block(BlockToCreate::Heading2 {
+                heading_2: heading_rich_text(text, id).into_iter().collect(),
+            })],
+            Tag::Heading {
+                level: HeadingLevel::H3 | HeadingLevel::H4 | HeadingLevel::H5 | HeadingLevel::H6,
+                text,
+                id,
+            } => vec![Self::childless_block(BlockToCreate::Heading3 {
+                heading_3: heading_rich_text(text, id).into_iter().collect(),
+            })],
+            Tag::Paragraph(Paragraph { text }) => {
+                vec![Self::childless_block(BlockToCreate::Paragraph {
+                    paragraph: text.into_iter().collect(),
+                })]
+            }
+            Tag::OrderedList { items, .. } => items
+                .into_iter()
+                .map(|item| BlockWithChildrenToCreate {
+                    block: BlockToCreate::NumberedListItem {
+                        numbered_list_item: TextAndChildrenToCreate::childless(&item.text),
+                    },
+                    children: item
+                        .children
+                        .into_iter()
+                        .flat_map(Self::from_markdown_tag)
+                        .collect(),
+                })
+                .collect(),
+            Tag::Quote { text, children } => vec![BlockWithChildrenToCreate {
+                block: BlockToCreate::Quote {
+                    quote: TextAndChildrenToCreate::childless(&text),
+                },
+                children: children
+                    .into_iter()
+                    .flat_map(Self::from_markdown_tag)
+                    .collect(),
+            }],
+            Tag::Callout {
+                icon,
+                text,
+                children,
+            } => vec![BlockWithChildrenToCreate {
+                block: BlockToCreate::Callout {
+                    callout: CalloutToCreate {
+                        rich_text: text.iter().map(Into::into).collect(),
+                        icon: notion::models::FileOrEmojiObject::Emoji {
+                            emoji: icon.unwrap_or_else(|| DEFAULT_CALLOUT_ICON.to_string()),
+                        },
+                        children: Vec::new(),
+                        color: notion::models::text::TextColor::Default,
+                    },
+                },
+                children: children
+                    .into_iter()
+                    .flat_map(Self::from_markdown_tag)
+                    .collect(),
+            }],
+            Tag::Toggle { text, children } => vec![BlockWithChildrenToCreate {
+                block: BlockToCreate::Toggle {
+                    toggle: TextAndChildrenToCreate::childless(&text),
+                },
+                children: children
+                    .into_iter()
+                    .flat_map(Self::from_markdown_tag)
+                    .collect(),
+            }],
+            Tag::Code { language, text } => {
+                vec![Self::childless_block(BlockToCreate::Code {
+                    code: notion::models::CodeFields {
+                        rich_text: split_into_chunks(&text, MAX_RICH_TEXT_LENGTH)
+                            .into_iter()
+                            .map(|chunk| {
+                                (&RichText {
+                                    text: chunk.to_string(),
+                                    link: None,
+                                    mention: None,
+                                })
+                                    .into()
+                            })
+                            .collect(),
+                        caption: Vec::new(),
+                        language: code_language(&language),
+                    },
+                })]
+            }
+            Tag::Image { url } => vec![Self::childless_block(BlockToCreate::Image {
+                image: FileToCreate::External {
+                    external: ExternalFileToCreate { url },
+                },
+            })],
+            Tag::Divider => vec![Self::childless_block(BlockToCreate::Divider {
+                divider: EmptyObject::default(),
+            })],
+        }
+    }
+}
+
+/// A block to create that Notion would reject, found before any request is sent.
+#[derive(Debug, Error)]
+pub enum InvalidBlockError {
+    #[error("cannot serialize {preview}")]
+    Serialize {
+        preview: String,
+        #[source]
+        error: serde_json::Error,
+    },
+
+    #[error(
+        "{preview} has {count} rich text objects, Notion allows at most {MAX_RICH_TEXTS_PER_BLOCK}"
+    )]
+    TooManyRichTexts { preview: String, count: usize },
+
+    #[error(
+        "{preview} has a text of {length} characters, Notion allows at most {MAX_RICH_TEXT_LENGTH}"
+    )]
+    TextTooLong { preview: String, length: usize },
+
+    #[error("{preview} has a URL of {length} characters, Notion allows at most {MAX_URL_LENGTH}")]
+    UrlTooLong { preview: String, length: usize },
+}
+
+/// Serializes the blocks and their children the way they are sent to Notion and checks them
+/// against the documented limits of the API, so a push can fail before it changes the page.
+/// The errors are in the order of the blocks.
+pub fn validate_blocks<'a>(
+    blocks: impl IntoIterator<Item = &'a BlockWithChildrenToCreate>,
+) -> Vec<InvalidBlockError> {
+    let mut errors = Vec::new();
+    let mut pending_blocks: Vec<_> = blocks.into_iter().collect();
+    pending_blocks.reverse();
+    while let Some(block) = pending_blocks.pop() {
+        pending_blocks.extend(block.children.iter().rev());
+        match serde_json::to_value(&block.block) {
+            Ok(value) => validate_value(&value, &|| block.block.preview(), &mut errors),
+            Err(error) => errors.push(InvalidBlockError::Serialize {
+                preview: block.block.preview(),
+                error,
+            }),
+        }
+    }
+
+    errors
+}
+
+fn validate_value(
+    value: &serde_json::Value,
+    preview: &dyn Fn() -> String,
+    errors: &mut Vec<InvalidBlockError>,
+) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (key, value) in fields {
+                match (key.as_str(), value) {
+                    ("rich_text" | "caption", serde_json::Value::Array(rich_text))
+                        if rich_text.len() > MAX_RICH_TEXTS_PER_BLOCK =>
+                    {
+                        errors.push(InvalidBlockError::TooManyRichTexts {
+                            preview: preview(),
+                            count: rich_text.len(),
+                        })
+                    }
+                    ("content", serde_json::Value::String(text))
+                        if text.chars().count() > MAX_RICH_TEXT_LENGTH =>
+                    {
+                        errors.push(InvalidBlockError::TextTooLong {
+                            preview: preview(),
+                            length: text.chars().count(),
+                        })
+                    }
+                    ("url", serde_json::Value::String(url))
+                        if url.chars().count() > MAX_URL_LENGTH =>
+                    {
+                        errors.push(InvalidBlockError::UrlTooLong {
+                            preview: preview(),
+                            length: url.chars().count(),
+                        })
+                    }
+                    _ => {}
+                }
+                validate_value(value, preview, errors);
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                validate_value(value, preview, errors);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreatedPage {
+    pub id: PageId,
+    pub url: String,
+}
+
+/// A page, without its content.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "PageObject")]
+pub struct PageSummary {
+    pub id: PageId,
+    pub title: String,
+    /// The name of the title property, which is `title` unless the page is in a database.
+    pub title_property: String,
+    pub icon: Option<PageImage>,
+    pub cover: Option<PageImage>,
+    /// All the properties of the page, as returned by the Notion API. Only database entries
+    /// have properties other than the title.
+    pub properties: serde_json::Map<String, serde_json::Value>,
+    /// The database the page is an entry of.
+    pub database_id: Option<String>,
+    /// When the page was last edited, as an ISO 8601 timestamp.
+    pub last_edited_time: String,
+    /// Whether the page is in the trash, by itself or along with a page it is in.
+    pub archived: bool,
+}
+
+#[derive(Deserialize)]
+struct PageObject {
+    id: PageId,
+    last_edited_time: String,
+    #[serde(default)]
+    archived: bool,
+    #[serde(default)]
+    in_trash: bool,
+    properties: serde_json::Map<String, serde_json::Value>,
+    #[serde(default)]
+    icon: Option<serde_json::Value>,
+    #[serde(default)]
+    cover: Option<serde_json::Value>,
+    #[serde(default)]
+    parent: serde_json::Value,
+}
+
+impl From<PageObject> for PageSummary {
+    fn from(page: PageObject) -> Self {
+        // NOTE: every page has exactly one title property, but its name depends on the parent
+        // (`title` for pages, the name of the column for database entries)
+        let title_property = page
+            .properties
+            .iter()
+            .find(|(_, property)| property["type"] == "title");
+        let title = title_property
+            .and_then(|(_, property)| property["title"].as_array())
+            .map(|rich_text| {
+                rich_text
+                    .iter()
+                    .filter_map(|text| text["plain_text"].as_str())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            id: page.id,
+            title,
+            title_property: title_property
+                .map_or_else(|| "title".to_string(), |(name, _)| name.clone()),
+            // NOTE: icons of other types, like custom emoji, are skipped instead of failing
+            // to read the page
+            icon: page.icon.and_then(|icon| serde_json::from_value(icon).ok()),
+            cover: page
+                .cover
+                .and_then(|cover| serde_json::from_value(cover).ok()),
+            database_id: page.parent["database_id"].as_str().map(str::to_string),
+            properties: page.properties,
+            last_edited_time: page.last_edited_time,
+            archived: page.archived || page.in_trash,
+        }
+    }
+}
+
+/// The bot user of an integration, which makes the requests of the API key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BotUser {
+    pub name: String,
+    /// The name of the workspace, missing for older integrations.
+    pub workspace_name: Option<String>,
+    pub owner: BotOwner,
+}
+
+/// Who installed the integration: the whole workspace for internal integrations, or a user
+/// for public ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BotOwner {
+    Workspace,
+    User { name: String },
+}
+
+impl std::fmt::Display for BotOwner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Workspace => write!(f, "workspace"),
+            Self::User { name } => write!(f, "user {name}"),
+        }
+    }
+}
+
+/// The kind of objects returned by a search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchObject {
+    Page,
+    Database,
+}
+
+/// Where a page or a database is in the workspace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Parent {
+    Workspace,
+    Page { id: String },
+    Database { id: String },
+    Block { id: String },
+}
+
+impl Parent {
+    fn from_json(parent: &serde_json::Value) -> Self {
+        let id = |key: &str| parent[key].as_str().unwrap_or_default().to_string();
+
+        match parent["type"].as_str() {
+            Some("page_id") => Self::Page { id: id("page_id") },
+            Some("database_id") => Self::Database {
+                id: id("database_id"),
+            },
+            Some("block_id") => Self::Block { id: id("block_id") },
+            _ => Self::Workspace,
+        }
+    }
+}
+
+impl std::fmt::Display for Parent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Workspace => write!(f, "workspace"),
+            Self::Page { id } => write!(f, "page {id}"),
+            Self::Database { id } => write!(f, "database {id}"),
+            Self::Block { id } => write!(f, "block {id}"),
+        }
+    }
+}
+
+/// A page or a database found by a search.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchResult {
+    pub object: SearchObject,
+    pub id: String,
+    /// The plain text of the title, empty for untitled pages and databases.
+    pub title: String,
+    pub parent: Parent,
+    /// When the page or the database was last edited, as an ISO 8601 timestamp.
+    pub last_edited_time: String,
+}
+
+impl SearchResult {
+    /// Reads a page or a database from the search results. Objects the integration lost
+    /// access to are returned with only their ID, and are skipped.
+    fn from_json(object: &serde_json::Value) -> Option<Self> {
+        let (kind, title) = match object["object"].as_str()? {
+            "page" => (
+                SearchObject::Page,
+                object["properties"]
+                    .as_object()?
+                    .values()
+                    .find(|property| property["type"] == "title")
+                    .map(|property| &property["title"]),
+            ),
+            "database" => (SearchObject::Database, Some(&object["title"])),
+            _ => return None,
+        };
+        let title = title
+            .and_then(serde_json::Value::as_array)
+            .map(|rich_text| {
+                rich_text
+                    .iter()
+                    .filter_map(|text| text["plain_text"].as_str())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(Self {
+            object: kind,
+            id: object["id"].as_str()?.to_string(),
+            title,
+            parent: Parent::from_json(&object["parent"]),
+            last_edited_time: object["last_edited_time"].as_str()?.to_string(),
+        })
+    }
+}
+
+/// The icon or the cover image of a page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PageImage {
+    Emoji {
+        emoji: String,
+    },
+    External {
+        external: ImageUrl,
+    },
+    /// An image uploaded to Notion. Its URL expires after an hour.
+    File {
+        file: ImageUrl,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageUrl {
+    pub url: String,
+}
+
+impl PageImage {
+    /// The image from the front matter, which is either an emoji or the URL of an image.
+    pub fn from_front_matter(value: &str) -> Self {
+        if value.starts_with("https://") || value.starts_with("http://") {
+            Self::External {
+                external: ImageUrl {
+                    url: value.to_string(),
+                },
+            }
+        } else {
+            Self::Emoji {
+                emoji: value.to_string(),
+            }
+        }
+    }
+
+    /// The image as written in the front matter. Uploaded images have none, as their URLs
+    /// expire.
+    pub fn front_matter_value(&self) -> Option<&str> {
+        match self {
+            Self::Emoji { emoji } => Some(emoji),
+            Self::External { external } => Some(&external.url),
+            Self::File { .. } => None,
+        }
+    }
+}
+
+/// The changes made by [`NotionClient::update_page`]. Whatever is `None` is kept.
+#[derive(Debug, Default, Serialize)]
+pub struct PageUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub properties: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<PageImage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cover: Option<PageImage>,
+    /// Whether the page is moved to the trash, or restored from it with `false`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archived: Option<bool>,
+}
+
+impl PageUpdate {
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_none()
+            && self.icon.is_none()
+            && self.cover.is_none()
+            && self.archived.is_none()
+    }
+}
+
+impl PageSummary {
+    /// The properties that set the title of the page to `title`.
+    pub fn title_properties(&self, title: &str) -> serde_json::Value {
+        json!({ &self.title_property: title_property_value(title) })
+    }
+}
+
+/// The value of a title property with the `title`, split into spans Notion accepts.
+pub fn title_property_value(title: &str) -> serde_json::Value {
+    let rich_text: Vec<_> = split_into_chunks(title, MAX_RICH_TEXT_LENGTH)
+        .into_iter()
+        .map(|chunk| json!({ "type": "text", "text": { "content": chunk } }))
+        .collect();
+
+    json!({ "title": rich_text })
+}
+
+#[derive(Serialize)]
+struct ChildrenToCreate<'a> {
+    children: &'a [BlockToCreate],
+    /// The block the children are inserted after, instead of at the end.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    after: Option<BlockId>,
+}
+
+#[async_recursion]
+#[instrument(skip_all, fields(%block_id))]
+pub async fn get_all_block_children(
+    client: &NotionClient,
+    block_id: &BlockId,
+) -> Result<Vec<BlockWithChildren>, GetBlockChildrenError> {
+    let children = client.get_all_direct_block_children(block_id).await?;
+
+    // NOTE: the children are fetched a few at a time to avoid creating futures for every block
+    // of large pages at once. The order of the results is kept.
+    stream::iter(children)
+        .map(|child_block| get_block_with_children(client, child_block))
+        .buffered(client.max_concurrency)
+        .try_collect()
+        .await
+}
+
+/// Gets a page of the direct children of the block, with all their children, and the cursor
+/// of the next page, if there is one. Long pages can be converted a page of blocks at a time.
+pub async fn get_block_children_page(
+    client: &NotionClient,
+    block_id: &BlockId,
+    start_cursor: Option<&PagingCursor>,
+) -> Result<(Vec<BlockWithChildren>, Option<PagingCursor>), GetBlockChildrenError> {
+    let page = client.get_block_children(block_id, start_cursor).await?;
+    let next_cursor = page.next_cursor.filter(|_| page.has_more);
+    let blocks = stream::iter(page.results)
+        .map(|child_block| get_block_with_children(client, child_block))
+        .buffered(client.max_concurrency)
+        .try_collect()
+        .await?;
+
+    Ok((blocks, next_cursor))
+}
+
+/// Deserializes a block from the list of the children of its parent.
+fn parse_block(mut block: serde_json::Value) -> Result<Block, serde_json::Error> {
+    // NOTE: the notion crate requires the children of these blocks inside the block, but the
+    // API only lists them with the children of the block
+    const BLOCKS_WITH_CHILDREN: [&str; 4] = ["column_list", "column", "synced_block", "table"];
+
+    if let Some(block_type) = block["type"]
+        .as_str()
+        .filter(|block_type| BLOCKS_WITH_CHILDREN.contains(block_type))
+        .map(str::to_string)
+    {
+        if let Some(fields) = block[&block_type].as_object_mut() {
+            fields.entry("children").or_insert_with(|| json!([]));
+        }
+    }
+
+    serde_json::from_value(block)
+}
+
+/// Gets all the children of the block, when it has any. The content of child pages and
+/// databases is not part of the page, so it is not fetched. Neither are the rows of tables,
+/// which markdown cannot express.
+pub async fn get_block_with_children(
+    client: &NotionClient,
+    block: Block,
+) -> Result<BlockWithChildren, GetBlockChildrenError> {
+    let has_children = !matches!(
+        block,
+        Block::ChildPage { .. } | Block::ChildDatabase { .. } | Block::Table { .. }
+    ) && block.common().is_some_and(|common| common.has_children);
+    if !has_children {
+        return Ok(BlockWithChildren::leaf(block));
+    }
+
+    if let Some(children) = client
+        .block_cache
+        .as_ref()
+        .and_then(|cache| cache.children(&block))
+    {
+        return Ok(BlockWithChildren::new(block, children));
+    }
+    let children = get_all_block_children(client, block.as_id()).await?;
+    let block = BlockWithChildren::new(block, children);
+    if let Some(cache) = &client.block_cache {
+        cache.insert(&block);
+    }
+
+    Ok(block)
+}
+
+#[derive(Error, Debug)]
+pub enum ErasePageError {
+    #[error("could not fetch page content")]
+    FetchingPageContent(#[from] GetBlockChildrenError),
+
+    #[error("deleting block {block_id} failed: {error}")]
+    DeleteBlockFailed {
+        #[source]
+        error: DeleteBlockError,
+        block_id: BlockId,
+    },
+
+    #[error("deleting {} block(s) failed", .0.len())]
+    DeleteBlocksFailed(Vec<ErasePageError>),
+
+    #[error("the page still has blocks after erasing it")]
+    PageNotEmpty,
+}
+
+/// Deletes all the blocks of the page, except for the `kept_block_id` block. Returns the
+/// number of deleted blocks.
+///
+/// Stops at the first block that cannot be deleted, unless `keep_going` is set.
+/// Then, it tries to delete all the blocks and reports every failure.
+#[instrument(skip_all, fields(%page_id))]
+pub async fn erase_page(
+    client: &NotionClient,
+    page_id: PageId,
+    keep_going: bool,
+    kept_block_ids: &[BlockId],
+) -> Result<usize, ErasePageError> {
+    let block_id: BlockId = page_id.into();
+    let is_kept = |block: &Block| kept_block_ids.contains(block.as_id());
+    let blocks = client.get_all_direct_block_children(&block_id).await?;
+    debug!(
+        blocks = blocks.len(),
+        kept_blocks = blocks.iter().filter(|block| is_kept(block)).count(),
+        "erasing the page"
+    );
+
+    let deleted_block_ids: Vec<_> = blocks
+        .iter()
+        .filter(|block| !is_kept(block))
+        .map(|block| block.as_id().clone())
+        .collect();
+    let deleted_blocks = deleted_block_ids.len();
+    delete_blocks(client, deleted_block_ids, keep_going).await?;
+
+    // NOTE: new content would be appended after any leftover blocks
+    let remaining_blocks = client.get_block_children(&block_id, None).await?;
+    if remaining_blocks.results.iter().any(|block| !is_kept(block)) {
+        return Err(ErasePageError::PageNotEmpty);
+    }
+
+    Ok(deleted_blocks)
+}
+
+/// Deletes the blocks.
+///
+/// Stops at the first block that cannot be deleted, unless `keep_going` is set.
+/// Then, it tries to delete all the blocks and reports every failure.
+pub async fn delete_blocks(
+    client: &NotionClient,
+    block_ids: Vec<BlockId>,
+    keep_going: bool,
+) -> Result<(), ErasePageError> {
+    let deletions = block_ids.into_iter().map(|block_id| async move {
+        client
+            .delete_block(block_id.clone())
+            .await
+            .map_err(|error| ErasePageError::DeleteBlockFailed { error, block_id })
+    });
+
+    if keep_going {
+        let errors: Vec<_> = join_all(deletions)
+            .await
+            .into_iter()
+            .filter_map(Result::err)
+            .collect();
+        if !errors.is_empty() {
+            return Err(ErasePageError::DeleteBlocksFailed(errors));
+        }
+    } else {
+        // NOTE: the deletions that have not finished yet are cancelled
+        try_join_all(deletions).await?;
+    }
+
+    Ok(())
+}
+
+#[derive(Error, Debug)]
+pub enum BackUpPageError {
+    #[error("could not fetch page content")]
+    FetchingPageContent(#[from] GetBlockChildrenError),
+
+    #[error("could not create the backup page")]
+    CreatePage(#[from] CreatePageError),
+
+    #[error("could not copy the page content to the backup page")]
+    CopyContent(Vec<CreateBlocksError>),
+}
+
+/// Copies the content of the page to a new, timestamped child page, so the page can be erased
+/// without losing its content.
+///
+/// The content is converted the same way as when fetching the page, so the parts that cannot
+/// be fetched are not backed up either.
+#[instrument(skip_all, fields(%page_id))]
+pub async fn back_up_page(
+    client: &NotionClient,
+    page_id: &PageId,
+) -> Result<CreatedPage, BackUpPageError> {
+    let blocks = get_all_block_children(client, &page_id.clone().into()).await?;
+    let blocks_to_create: Vec<_> = NotionToMarkdownParser::new(Default::default())
+        .feed(blocks.iter())
+        .flat_map(BlockWithChildrenToCreate::from_markdown_tag)
+        .collect();
+
+    let title = format!(
+        "Backup from {}",
+        humantime::format_rfc3339_seconds(SystemTime::now())
+    );
+    let backup_page = client.create_page(page_id, &title).await?;
+    client
+        .create_blocks(backup_page.id.clone().into(), blocks_to_create)
+        .await
+        .map_err(BackUpPageError::CopyContent)?;
+
+    Ok(backup_page)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        str::FromStr,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use pretty_assertions::assert_eq;
+    use proptest::prelude::*;
+    use serde_json::{json, Value};
+    use wiremock::{
+        matchers::{
+            body_json, body_partial_json, header, method, path, path_regex, query_param,
+            query_param_is_missing,
+        },
+        Mock, MockServer, Respond, ResponseTemplate,
+    };
+
+    use crate::markdown::{
+        from_cmark::{pulldown_cmark_options, ParseOptions, PulldownCMarkEventParser},
+        notion_interop::{NotionParseOptions, NotionToMarkdownParser},
+        plain_text::parse_plain_text,
+        tag::strategies::{document, Target},
+        to_cmark::{to_markdown, SerializeOptions},
+        warning::Warning,
+    };
+
+    use super::*;
+    use crate::notion_api::mock_server::{
+        self, get_client, get_error_response, mount_block_children,
+    };
+
+    #[test]
+    fn paragraph_with_hard_breaks_becomes_single_block() {
+        let document = "221B Baker Street\\\nLondon\\\nNW1 6XE";
+        let tags = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new(document),
+            Default::default(),
+        )
+        .parse()
+        .expect("successful parsing of the document");
+
+        assert_eq!(
+            to_markdown(&tags, &SerializeOptions::default()),
+            document,
+            "the hard breaks are restored when fetching"
+        );
+
+        let blocks: Vec<_> = tags
+            .into_iter()
+            .flat_map(BlockWithChildrenToCreate::from_markdown_tag)
+            .collect();
+        assert_eq!(blocks.len(), 1, "a single block is created");
+        assert!(blocks[0].children.is_empty());
+
+        let block_json = serde_json::to_value(&blocks[0].block).unwrap();
+        assert_eq!(
+            block_json["paragraph"]["rich_text"][0]["text"]["content"],
+            json!("221B Baker Street\nLondon\nNW1 6XE")
+        );
+        assert_eq!(
+            block_json["paragraph"]["rich_text"]
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn plain_text_markdown_syntax_arrives_literally() {
+        let tags = parse_plain_text("# not a heading\n1. not a list\n\n**not bold** [no](link)");
+
+        let blocks_json: Vec<_> = tags
+            .into_iter()
+            .flat_map(BlockWithChildrenToCreate::from_markdown_tag)
+            .map(|block| serde_json::to_value(&block.block).unwrap())
+            .collect();
+
+        assert_eq!(blocks_json.len(), 2);
+        assert_eq!(
+            blocks_json[0]["paragraph"]["rich_text"][0]["text"]["content"],
+            json!("# not a heading\n1. not a list")
+        );
+        assert_eq!(
+            blocks_json[1]["paragraph"]["rich_text"][0]["text"]["content"],
+            json!("**not bold** [no](link)")
+        );
+    }
+
+    /// Turns the JSON of a block to create into the JSON of the block as it would be fetched.
+    fn to_fetched_block_json(mut block_json: Value, id: &str, has_children: bool) -> Value {
+        let block_type = block_json
+            .as_object()
+            .and_then(|fields| fields.keys().next().cloned())
+            .unwrap();
+        let user = json!({ "object": "user", "id": "ac32e0256f9c4fab8b9ddbb3c593ac46" });
+        let fields = block_json.as_object_mut().unwrap();
+        if let Some(content) = fields[&block_type].as_object_mut() {
+            // NOTE: the color is optional when creating blocks, but always fetched
+            content.entry("color").or_insert_with(|| json!("default"));
+            // NOTE: the children are fetched separately
+            content.remove("children");
+        }
+        fields.insert("object".to_string(), json!("block"));
+        fields.insert("type".to_string(), json!(block_type));
+        fields.insert("id".to_string(), json!(id));
+        fields.insert("created_time".to_string(), json!("2022-08-01T12:00:00Z"));
+        fields.insert(
+            "last_edited_time".to_string(),
+            json!("2022-08-01T12:00:00Z"),
+        );
+        fields.insert("has_children".to_string(), json!(has_children));
+        fields.insert("created_by".to_string(), user.clone());
+        fields.insert("last_edited_by".to_string(), user);
+
+        block_json
+    }
+
+    /// Turns the blocks that would be created into blocks as they would be fetched back.
+    fn created_to_fetched(blocks: Vec<BlockWithChildrenToCreate>) -> Vec<BlockWithChildren> {
+        blocks
+            .into_iter()
+            .map(|block| {
+                let block_json = to_fetched_block_json(
+                    serde_json::to_value(&block.block).unwrap(),
+                    "0a5f1b4e8c0d4c4a9c5e6d1f2b3a4c5d",
+                    !block.children.is_empty(),
+                );
+
+                BlockWithChildren {
+                    block: serde_json::from_value(block_json).unwrap(),
+                    children: created_to_fetched(block.children),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn list_item_with_paragraphs_survives_push_and_fetch() {
+        let document = "1. First paragraph\n\n   Second paragraph\n\n   Third paragraph\n\n   1. Nested item\n\n1. Next item";
+        let tags = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext(document, pulldown_cmark_options()),
+            Default::default(),
+        )
+        .parse()
+        .expect("successful parsing of the document");
+
+        let blocks = created_to_fetched(
+            tags.into_iter()
+                .flat_map(BlockWithChildrenToCreate::from_markdown_tag)
+                .collect(),
+        );
+        let fetched_tags: Vec<_> = NotionToMarkdownParser::new(Default::default())
+            .feed(blocks.iter())
+            .collect();
+
+        assert_eq!(to_markdown(&fetched_tags, &Default::default()), document);
+    }
+
+    #[test]
+    fn interrupted_lists_survive_push_and_fetch() {
+        for (merge_adjacent_lists, document) in [
+            (
+                false,
+                "1. First\n1. Second\n\nBetween the lists\n\n1. Third\n1. Fourth",
+            ),
+            (
+                true,
+                "1. First\n1. Second\n\nBetween the lists\n\n3. Third\n3. Fourth",
+            ),
+        ] {
+            let parse_options = ParseOptions {
+                merge_adjacent_lists,
+                ..Default::default()
+            };
+            let mut parser = PulldownCMarkEventParser::new(
+                pulldown_cmark::Parser::new_ext(document, parse_options.pulldown_cmark_options()),
+                parse_options,
+            );
+            let tags = parser.parse().expect("successful parsing of the document");
+            assert_eq!(parser.warnings(), []);
+
+            let blocks = created_to_fetched(
+                tags.into_iter()
+                    .flat_map(BlockWithChildrenToCreate::from_markdown_tag)
+                    .collect(),
+            );
+            assert_eq!(blocks.len(), 5, "the lists stay apart in Notion");
+            let fetched_tags: Vec<_> = NotionToMarkdownParser::new(NotionParseOptions {
+                merge_adjacent_lists,
+                ..Default::default()
+            })
+            .feed(blocks.iter())
+            .collect();
+
+            assert_eq!(to_markdown(&fetched_tags, &Default::default()), document);
+        }
+    }
+
+    #[test]
+    fn quote_content_becomes_children() {
+        let tags = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new("> First\n>\n> Second\n>\n> Third"),
+            Default::default(),
+        )
+        .parse()
+        .expect("successful parsing of the document");
+
+        let blocks: Vec<_> = tags
+            .into_iter()
+            .flat_map(BlockWithChildrenToCreate::from_markdown_tag)
+            .collect();
+        assert_eq!(blocks.len(), 1, "a single quote block is created");
+
+        let block_json = serde_json::to_value(&blocks[0].block).unwrap();
+        assert_eq!(
+            block_json["quote"]["rich_text"][0]["text"]["content"],
+            json!("First")
+        );
+        let children_json: Vec<_> = blocks[0]
+            .children
+            .iter()
+            .map(|child| serde_json::to_value(&child.block).unwrap())
+            .collect();
+        assert_eq!(
+            children_json
+                .iter()
+                .map(|child| child["paragraph"]["rich_text"][0]["text"]["content"].clone())
+                .collect::<Vec<_>>(),
+            vec![json!("Second"), json!("Third")]
+        );
+    }
+
+    #[test]
+    fn footnotes_become_a_trailing_list() {
+        let document = "One[^1], two[^2], one again[^1].\n\n[^1]: First.\n\n[^2]: Second.";
+        let tags = PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext(document, pulldown_cmark_options()),
+            Default::default(),
+        )
+        .parse()
+        .expect("successful parsing of the document");
+
+        let blocks: Vec<_> = tags
+            .into_iter()
+            .flat_map(BlockWithChildrenToCreate::from_markdown_tag)
+            .map(|block| serde_json::to_value(&block.block).unwrap())
+            .collect();
+
+        assert_eq!(
+            blocks[0]["paragraph"]["rich_text"][0]["text"]["content"],
+            json!("One[1], two[2], one again[1].")
+        );
+        assert_eq!(
+            blocks[1]["heading_2"]["rich_text"][0]["text"]["content"],
+            json!("Footnotes")
+        );
+        assert_eq!(
+            blocks[2..]
+                .iter()
+                .map(|block| block["numbered_list_item"]["rich_text"][0]["text"]["content"].clone())
+                .collect::<Vec<_>>(),
+            vec![json!("First."), json!("Second.")]
+        );
+    }
+
+    #[test]
+    fn html_block_becomes_html_code_block() {
+        let blocks = BlockWithChildrenToCreate::from_markdown_tag(Tag::Code {
+            language: "html".to_string(),
+            text: "<table></table>".to_string(),
+        });
+
+        let block_json = serde_json::to_value(&blocks[0].block).unwrap();
+        assert_eq!(block_json["code"]["language"], json!("html"));
+        assert_eq!(
+            block_json["code"]["rich_text"][0]["text"]["content"],
+            json!("<table></table>")
+        );
+    }
+
+    #[test]
+    fn api_base_url_keeps_the_path_prefix() {
+        for base_url in [
+            "http://localhost:8080/gateway/notion",
+            "http://localhost:8080/gateway/notion/",
+        ] {
+            let client = NotionClient::new(reqwest::Client::new())
+                .with_base_url(parse_api_base_url(base_url).unwrap());
+
+            assert_eq!(
+                client.endpoint(&["v1", "users"]).as_str(),
+                "http://localhost:8080/gateway/notion/v1/users",
+                "endpoint for {base_url}"
+            );
+        }
+    }
+
+    #[test]
+    fn default_api_base_url_is_the_public_api() {
+        let client = NotionClient::new(reqwest::Client::new());
+
+        assert_eq!(
+            client.endpoint(&["v1", "users"]).as_str(),
+            "https://api.notion.com/v1/users"
+        );
+    }
+
+    #[test]
+    fn api_base_url_accepts_http_and_https() {
+        for url in [
+            "http://localhost:8080",
+            "http://127.0.0.1:8080",
+            "http://[::1]",
+            "http://gateway.internal/notion",
+            "https://api.notion.com",
+        ] {
+            assert!(parse_api_base_url(url).is_ok(), "{url} should be allowed");
+        }
+
+        assert!(matches!(
+            parse_api_base_url("unix:/run/gateway.sock"),
+            Err(ApiBaseUrlError::UnsupportedScheme { .. })
+        ));
+    }
+
+    fn get_created_list_item_json(id: &str, content: &str) -> Value {
+        let user = json!({ "object": "user", "id": "ac32e0256f9c4fab8b9ddbb3c593ac46" });
+
+        json!({
+            "object": "list",
+            "results": [{
+                "object": "block",
+                "id": id,
+                "type": "numbered_list_item",
+                "created_time": "2022-08-01T12:00:00.000Z",
+                "last_edited_time": "2022-08-01T12:00:00.000Z",
+                "has_children": false,
+                "created_by": user,
+                "last_edited_by": user,
+                "numbered_list_item": {
+                    "rich_text": [{
+                        "type": "text",
+                        "plain_text": content,
+                        "href": null,
+                        "text": { "content": content, "link": null }
+                    }],
+                    "color": "default"
+                }
+            }],
+            "next_cursor": null,
+            "has_more": false
+        })
+    }
+
+    fn parse_blocks_to_create(document: &str) -> Vec<BlockWithChildrenToCreate> {
+        PulldownCMarkEventParser::new(
+            pulldown_cmark::Parser::new_ext(document, pulldown_cmark_options()),
+            Default::default(),
+        )
+        .parse()
+        .expect("successful parsing of the document")
+        .into_iter()
+        .flat_map(BlockWithChildrenToCreate::from_markdown_tag)
+        .collect()
+    }
+
+    #[tokio::test]
+    async fn sends_children_along_with_their_parents() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+        const PARENT_ID: &str = "0a5f1b4e8c0d4c4a9c5e6d1f2b3a4c5d";
+
+        let server = MockServer::start().await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .and(body_partial_json(json!({
+                "children": [{
+                    "numbered_list_item": {
+                        "rich_text": [{ "text": { "content": "Parent" } }],
+                        "children": [{
+                            "numbered_list_item": {
+                                "rich_text": [{ "text": { "content": "Child" } }]
+                            }
+                        }]
+                    }
+                }]
+            })))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(get_created_list_item_json(PARENT_ID, "Parent")),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let blocks = parse_blocks_to_create("1. Parent\n   1. Child");
+
+        get_client(&server)
+            .create_blocks(BlockId::from_str(PAGE_ID).unwrap(), blocks)
+            .await
+            .expect("successful creation of the blocks");
+
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1, "no other requests are needed");
+        let body: Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(
+            body["children"][0]["numbered_list_item"]["children"][0]["numbered_list_item"]
+                .get("children"),
+            None,
+            "childless blocks are sent without children"
+        );
+    }
+
+    #[test]
+    fn list_items_with_empty_text_are_created_with_empty_rich_text() {
+        let blocks = parse_blocks_to_create(
+            "1. First\n\n1. <!-- empty -->\n\n   Nested\n\n   Another nested\n\n1. Third",
+        );
+
+        let body =
+            serde_json::to_value(blocks.iter().map(|block| &block.block).collect::<Vec<_>>())
+                .unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 3, "one block per list item");
+        assert_eq!(body[1]["numbered_list_item"]["rich_text"], json!([]));
+        assert_eq!(blocks[1].children.len(), 2, "the item keeps its paragraphs");
+    }
+
+    /// Keeps the blocks in memory and serves the block children endpoints like Notion does,
+    /// including rejecting children nested more than two levels deep in a single request.
+    #[derive(Clone, Default)]
+    struct FakeNotion {
+        state: Arc<Mutex<FakeNotionState>>,
+    }
+
+    #[derive(Default)]
+    struct FakeNotionState {
+        created_blocks_count: usize,
+        blocks: HashMap<String, Value>,
+        children: HashMap<String, Vec<String>>,
+    }
+
+    impl FakeNotionState {
+        fn create_block(&mut self, parent_id: &str, mut block_json: Value) -> String {
+            self.created_blocks_count += 1;
+            let id = format!("{:032x}", self.created_blocks_count);
+
+            let block_type = block_json
+                .as_object()
+                .unwrap()
+                .keys()
+                .next()
+                .unwrap()
+                .clone();
+            let children = block_json[&block_type]
+                .as_object_mut()
+                .and_then(|content| content.remove("children"));
+            self.blocks.insert(id.clone(), block_json);
+            self.children
+                .entry(parent_id.to_string())
+                .or_default()
+                .push(id.clone());
+
+            for child in children
+                .into_iter()
+                .flat_map(|children| children.as_array().cloned().unwrap_or_default())
+            {
+                self.create_block(&id, child);
+            }
+
+            id
+        }
+
+        fn get_block_json(&self, id: &str) -> Value {
+            to_fetched_block_json(self.blocks[id].clone(), id, self.children.contains_key(id))
+        }
+    }
+
+    impl Respond for FakeNotion {
+        fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+            let mut state = self.state.lock().unwrap();
+            let block_id = request
+                .url
+                .path()
+                .strip_prefix("/v1/blocks/")
+                .and_then(|path| path.strip_suffix("/children"))
+                .unwrap()
+                .replace('-', "");
+
+            let ids = match request.method {
+                wiremock::http::Method::Get => {
+                    state.children.get(&block_id).cloned().unwrap_or_default()
+                }
+                wiremock::http::Method::Patch => {
+                    let body: Value = serde_json::from_slice(&request.body).unwrap();
+                    let children = body["children"].as_array().unwrap();
+                    let nested_too_deeply = children.iter().any(|child| {
+                        child
+                            .as_object()
+                            .and_then(|fields| fields.values().next())
+                            .and_then(|content| content["children"].as_array())
+                            .is_some_and(|children| {
+                                children.iter().any(|child| {
+                                    child
+                                        .as_object()
+                                        .and_then(|fields| fields.values().next())
+                                        .is_some_and(|content| content.get("children").is_some())
+                                })
+                            })
+                    });
+                    if nested_too_deeply {
+                        return ResponseTemplate::new(400);
+                    }
+
+                    children
+                        .iter()
+                        .map(|child| state.create_block(&block_id, child.clone()))
+                        .collect()
+                }
+                _ => return ResponseTemplate::new(405),
+            };
+
+            ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": ids.iter().map(|id| state.get_block_json(id)).collect::<Vec<_>>(),
+                "next_cursor": null,
+                "has_more": false
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn creates_lists_nested_deeper_than_two_levels() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+        let document = "1. Level 1\n   1. Level 2\n      1. Level 3\n         1. Level 4\n            1. Level 5\n         1. Level 4 again\n   1. Level 2 again";
+
+        let server = MockServer::start().await;
+        Mock::given(path_regex("^/v1/blocks/[0-9a-f-]+/children$"))
+            .respond_with(FakeNotion::default())
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server);
+        client
+            .create_blocks(
+                BlockId::from_str(PAGE_ID).unwrap(),
+                parse_blocks_to_create(document),
+            )
+            .await
+            .expect("successful creation of the blocks");
+
+        let blocks = get_all_block_children(&client, &BlockId::from_str(PAGE_ID).unwrap())
+            .await
+            .expect("successful fetch");
+        let fetched_tags: Vec<_> = NotionToMarkdownParser::new(Default::default())
+            .feed(blocks.iter())
+            .collect();
+        assert_eq!(to_markdown(&fetched_tags, &Default::default()), document);
+    }
+
+    fn get_paragraph_block_json(index: usize) -> Value {
+        mock_server::get_paragraph_block_json(
+            &format!("{index:032x}"),
+            &format!("Paragraph {index}"),
+        )
+    }
+
+    #[tokio::test]
+    async fn gets_all_pages_of_block_children() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+        const NEXT_CURSOR: &str = "8d4e2f60-3c1b-4a7e-9f2d-5b6a7c8d9e0f";
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .and(query_param("page_size", "100"))
+            .and(query_param_is_missing("start_cursor"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": (0..100).map(get_paragraph_block_json).collect::<Vec<_>>(),
+                "next_cursor": NEXT_CURSOR,
+                "has_more": true
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .and(query_param("page_size", "100"))
+            .and(query_param("start_cursor", NEXT_CURSOR))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": (100..150).map(get_paragraph_block_json).collect::<Vec<_>>(),
+                "next_cursor": null,
+                "has_more": false
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server);
+        let blocks = get_all_block_children(&client, &BlockId::from_str(PAGE_ID).unwrap())
+            .await
+            .expect("successful fetch");
+
+        let ids: Vec<_> = blocks
+            .iter()
+            .map(|block| block.block.as_id().to_string())
+            .collect();
+        assert_eq!(
+            ids,
+            (0..150)
+                .map(|index| format!("{index:032x}"))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn fetches_the_children_of_nested_blocks() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+        const TOGGLE_ID: &str = "0a5f1b4e8c0d4c4a9c5e6d1f2b3a4c5d";
+        const NESTED_TOGGLE_ID: &str = "1c2d3e4f5a6b4c7d8e9f0a1b2c3d4e5f";
+        let toggle = |id: &str| {
+            let mut block = mock_server::get_block_json(id, "toggle", "Details");
+            block["has_children"] = json!(true);
+            block
+        };
+
+        let server = MockServer::start().await;
+        mount_block_children(
+            &server,
+            PAGE_ID,
+            vec![toggle(TOGGLE_ID), get_paragraph_block_json(0)],
+        )
+        .await;
+        mount_block_children(
+            &server,
+            TOGGLE_ID,
+            std::iter::once(toggle(NESTED_TOGGLE_ID))
+                .chain((1..150).map(get_paragraph_block_json))
+                .collect(),
+        )
+        .await;
+        mount_block_children(
+            &server,
+            NESTED_TOGGLE_ID,
+            vec![get_paragraph_block_json(150)],
+        )
+        .await;
+
+        let blocks =
+            get_all_block_children(&get_client(&server), &BlockId::from_str(PAGE_ID).unwrap())
+                .await
+                .expect("successful fetch");
+
+        let ids = |blocks: &[BlockWithChildren]| -> Vec<String> {
+            blocks
+                .iter()
+                .map(|block| block.block.as_id().to_string())
+                .collect()
+        };
+        assert_eq!(ids(&blocks), [TOGGLE_ID, &format!("{:032x}", 0)]);
+        let toggle_children = &blocks[0].children;
+        assert_eq!(toggle_children.len(), 150);
+        assert_eq!(ids(&toggle_children[..1]), [NESTED_TOGGLE_ID]);
+        assert_eq!(ids(&toggle_children[0].children), [format!("{:032x}", 150)]);
+        assert!(toggle_children[1..]
+            .iter()
+            .all(|child| child.children.is_empty()));
+        assert!(blocks[1].children.is_empty());
+    }
+
+    #[tokio::test]
+    async fn fetches_the_children_of_every_block_type() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+        const QUOTE_ID: &str = "0a5f1b4e8c0d4c4a9c5e6d1f2b3a4c5d";
+        const ITEM_ID: &str = "1c2d3e4f5a6b4c7d8e9f0a1b2c3d4e5f";
+        const CHILD_PAGE_ID: &str = "2e3f4a5b6c7d4e8f9a0b1c2d3e4f5a6b";
+        let with_children = |id: &str, block_type: &str| {
+            let mut block = mock_server::get_block_json(id, block_type, "Parent");
+            block["has_children"] = json!(true);
+            block
+        };
+        let mut child_page = with_children(CHILD_PAGE_ID, "child_page");
+        child_page["child_page"] = json!({ "title": "Subpage" });
+
+        let server = MockServer::start().await;
+        mount_block_children(
+            &server,
+            PAGE_ID,
+            vec![
+                with_children(QUOTE_ID, "quote"),
+                with_children(ITEM_ID, "bulleted_list_item"),
+                child_page,
+            ],
+        )
+        .await;
+        mount_block_children(&server, QUOTE_ID, vec![get_paragraph_block_json(0)]).await;
+        mount_block_children(&server, ITEM_ID, vec![get_paragraph_block_json(1)]).await;
+
+        let blocks =
+            get_all_block_children(&get_client(&server), &BlockId::from_str(PAGE_ID).unwrap())
+                .await
+                .expect("successful fetch");
+
+        let child_ids: Vec<Vec<String>> = blocks
+            .iter()
+            .map(|block| {
+                block
+                    .children
+                    .iter()
+                    .map(|child| child.block.as_id().to_string())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            child_ids,
+            [
+                vec![format!("{:032x}", 0)],
+                vec![format!("{:032x}", 1)],
+                Vec::new()
+            ]
+        );
+        let requested_paths: Vec<_> = server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|request| request.url.path().to_string())
+            .collect();
+        assert!(
+            requested_paths.contains(&format!("/v1/blocks/{QUOTE_ID}/children")),
+            "the children of the quote are fetched"
+        );
+        assert!(
+            !requested_paths.contains(&format!("/v1/blocks/{CHILD_PAGE_ID}/children")),
+            "the content of the child page is not fetched"
+        );
+    }
+
+    #[tokio::test]
+    async fn fetches_the_content_of_column_lists() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+        const COLUMN_LIST_ID: &str = "0a5f1b4e8c0d4c4a9c5e6d1f2b3a4c5d";
+        const LEFT_COLUMN_ID: &str = "1c2d3e4f5a6b4c7d8e9f0a1b2c3d4e5f";
+        const RIGHT_COLUMN_ID: &str = "2e3f4a5b6c7d4e8f9a0b1c2d3e4f5a6b";
+        const TABLE_ID: &str = "3a4b5c6d7e8f4a9b8c7d6e5f4a3b2c1d";
+        // NOTE: Notion sends these blocks without their children, which are listed separately
+        let container = |id: &str, block_type: &str, fields: Value| {
+            let mut block = mock_server::get_block_json(id, block_type, "");
+            block["has_children"] = json!(true);
+            block[block_type] = fields;
+            block
+        };
+
+        let server = MockServer::start().await;
+        mount_block_children(
+            &server,
+            PAGE_ID,
+            vec![
+                container(COLUMN_LIST_ID, "column_list", json!({})),
+                container(
+                    TABLE_ID,
+                    "table",
+                    json!({ "table_width": 2, "has_column_header": false, "has_row_header": false }),
+                ),
+            ],
+        )
+        .await;
+        mount_block_children(
+            &server,
+            COLUMN_LIST_ID,
+            vec![
+                container(LEFT_COLUMN_ID, "column", json!({})),
+                container(RIGHT_COLUMN_ID, "column", json!({})),
+            ],
+        )
+        .await;
+        mount_block_children(&server, LEFT_COLUMN_ID, vec![get_paragraph_block_json(0)]).await;
+        mount_block_children(&server, RIGHT_COLUMN_ID, vec![get_paragraph_block_json(1)]).await;
+
+        let blocks =
+            get_all_block_children(&get_client(&server), &BlockId::from_str(PAGE_ID).unwrap())
+                .await
+                .expect("successful fetch");
+        let mut parser = NotionToMarkdownParser::default().feed(blocks.iter());
+        let tags: Vec<_> = parser.by_ref().collect();
+
+        assert_eq!(
+            to_markdown(&tags, &Default::default()),
+            "Paragraph 0\n\nParagraph 1"
+        );
+        assert_eq!(
+            parser.warnings(),
+            [
+                Warning::BlockUnwrapped {
+                    block_id: COLUMN_LIST_ID.to_string(),
+                    block_type: "column_list".to_string(),
+                },
+                Warning::BlockSkipped {
+                    block_id: TABLE_ID.to_string(),
+                    block_type: "table".to_string(),
+                },
+            ]
+        );
+        let requested_paths: Vec<_> = server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|request| request.url.path().to_string())
+            .collect();
+        assert!(
+            !requested_paths.contains(&format!("/v1/blocks/{TABLE_ID}/children")),
+            "the rows of the table are not fetched"
+        );
+    }
+
+    #[tokio::test]
+    async fn fetches_with_the_notion_headers() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .and(header("Notion-Version", DEFAULT_NOTION_VERSION))
+            .and(header("Authorization", "Bearer secret"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": [get_paragraph_block_json(0)],
+                "next_cursor": null,
+                "has_more": false
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = NotionClient::new(get_notion_reqwest_client("secret", &Default::default()))
+            .with_base_url(parse_api_base_url(&server.uri()).unwrap());
+        let blocks = get_all_block_children(&client, &BlockId::from_str(PAGE_ID).unwrap())
+            .await
+            .expect("successful fetch");
+
+        assert!(matches!(
+            blocks[..],
+            [BlockWithChildren {
+                block: Block::Paragraph { .. },
+                ..
+            }]
+        ));
+    }
+
+    /// Responds to every block children request after a delay, noting when the requests came.
+    struct SlowBlockChildrenResponder {
+        page_id: &'static str,
+        delay: Duration,
+        request_times: Arc<Mutex<Vec<Instant>>>,
+    }
+
+    impl Respond for SlowBlockChildrenResponder {
+        fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+            self.request_times.lock().unwrap().push(Instant::now());
+
+            // NOTE: every block of the page has children, which are empty themselves
+            let results: Vec<_> = if request.url.path().contains(self.page_id) {
+                (0..6)
+                    .map(|index| {
+                        let mut block_json = get_paragraph_block_json(index);
+                        block_json["has_children"] = json!(true);
+                        block_json
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+
+            ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "object": "list",
+                    "results": results,
+                    "next_cursor": null,
+                    "has_more": false
+                }))
+                .set_delay(self.delay)
+        }
+    }
+
+    #[tokio::test]
+    async fn limits_the_requests_in_flight_when_fetching() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+        const MAX_CONCURRENCY: usize = 2;
+        let delay = Duration::from_millis(100);
+
+        let request_times = Arc::new(Mutex::new(Vec::new()));
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path_regex("^/v1/blocks/[0-9a-f]{32}/children$"))
+            .respond_with(SlowBlockChildrenResponder {
+                page_id: PAGE_ID,
+                delay,
+                request_times: request_times.clone(),
+            })
+            .expect(7)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).with_max_concurrency(MAX_CONCURRENCY);
+        let blocks = get_all_block_children(&client, &BlockId::from_str(PAGE_ID).unwrap())
+            .await
+            .expect("successful fetch");
+        assert_eq!(
+            blocks
+                .iter()
+                .map(|block| block.block.as_id().to_string())
+                .collect::<Vec<_>>(),
+            get_expected_ids(6),
+            "the blocks keep their order"
+        );
+
+        // NOTE: a request can only start once one of the previous `MAX_CONCURRENCY` requests
+        // got its (delayed) response
+        let mut request_times = request_times.lock().unwrap().clone();
+        request_times.sort();
+        for window in request_times.windows(MAX_CONCURRENCY + 1) {
+            let elapsed = window[MAX_CONCURRENCY] - window[0];
+            assert!(
+                elapsed >= delay.mul_f64(0.8),
+                "{} requests were sent within {elapsed:?}",
+                MAX_CONCURRENCY + 1
+            );
+        }
+    }
+
+    /// Creates the paragraphs from the request like Notion does, but rejects requests with more
+    /// than `max_blocks` blocks as too large.
+    struct AppendParagraphsResponder {
+        max_blocks: usize,
+    }
+
+    impl Respond for AppendParagraphsResponder {
+        fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+            let children = get_requested_paragraph_indices(request);
+            if children.len() > self.max_blocks {
+                return ResponseTemplate::new(413);
+            }
+
+            ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": children.into_iter().map(get_paragraph_block_json).collect::<Vec<_>>(),
+                "next_cursor": null,
+                "has_more": false
+            }))
+        }
+    }
+
+    /// The indices of the `Paragraph {index}` blocks in the append block children request.
+    fn get_requested_paragraph_indices(request: &wiremock::Request) -> Vec<usize> {
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+
+        body["children"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|child| {
+                child["paragraph"]["rich_text"][0]["text"]["content"]
+                    .as_str()
+                    .and_then(|content| content.strip_prefix("Paragraph "))
+                    .and_then(|index| index.parse().ok())
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    async fn append_paragraphs(count: usize, max_blocks: usize) -> (Vec<String>, MockServer) {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+
+        let server = MockServer::start().await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(AppendParagraphsResponder { max_blocks })
+            .mount(&server)
+            .await;
+
+        let blocks = (0..count)
+            .flat_map(|index| {
+                BlockWithChildrenToCreate::from_markdown_tag(Tag::Paragraph(Paragraph {
+                    text: vec![RichText {
+                        text: format!("Paragraph {index}"),
+                        link: None,
+                        mention: None,
+                    }],
+                }))
+                .into_iter()
+                .map(|block| block.block)
+            })
+            .collect();
+        let created_blocks = get_client(&server)
+            .append_block_children_shallow(BlockId::from_str(PAGE_ID).unwrap(), &[], None, blocks)
+            .await
+            .expect("successful append");
+
+        let created_ids = created_blocks
+            .iter()
+            .map(|block| block.as_id().to_string())
+            .collect();
+        (created_ids, server)
+    }
+
+    fn get_expected_ids(count: usize) -> Vec<String> {
+        (0..count).map(|index| format!("{index:032x}")).collect()
+    }
+
+    #[tokio::test]
+    async fn appends_blocks_in_chunks_of_100() {
+        let (created_ids, server) = append_paragraphs(250, 100).await;
+
+        assert_eq!(created_ids, get_expected_ids(250));
+        let requested_chunks: Vec<_> = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(get_requested_paragraph_indices)
+            .map(|indices| (indices[0], indices.len()))
+            .collect();
+        assert_eq!(requested_chunks, vec![(0, 100), (100, 100), (200, 50)]);
+    }
+
+    #[tokio::test]
+    async fn splits_chunks_that_are_too_large() {
+        let (created_ids, server) = append_paragraphs(250, 40).await;
+
+        assert_eq!(created_ids, get_expected_ids(250));
+        let appended_indices: Vec<_> = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(get_requested_paragraph_indices)
+            .filter(|indices| indices.len() <= 40)
+            .flatten()
+            .collect();
+        assert_eq!(appended_indices, (0..250).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn counts_the_nested_blocks_in_chunks() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+
+        let server = MockServer::start().await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(|request: &wiremock::Request| {
+                let body: Value = serde_json::from_slice(&request.body).unwrap();
+                let children = body["children"].as_array().unwrap().len();
+                ResponseTemplate::new(200).set_body_json(json!({
+                    "object": "list",
+                    "results": (0..children).map(get_paragraph_block_json).collect::<Vec<_>>(),
+                    "next_cursor": null,
+                    "has_more": false
+                }))
+            })
+            .mount(&server)
+            .await;
+
+        let toggles = (0..5)
+            .flat_map(|_| {
+                BlockWithChildrenToCreate::from_markdown_tag(Tag::Toggle {
+                    text: vec![RichText {
+                        text: "Toggle".to_string(),
+                        link: None,
+                        mention: None,
+                    }],
+                    children: (0..30)
+                        .map(|_| {
+                            Tag::Paragraph(Paragraph {
+                                text: vec![RichText {
+                                    text: "Paragraph".to_string(),
+                                    link: None,
+                                    mention: None,
+                                }],
+                            })
+                        })
+                        .collect(),
+                })
+            })
+            .map(|block| block.inline_children().0)
+            .collect();
+        get_client(&server)
+            .append_block_children_shallow(BlockId::from_str(PAGE_ID).unwrap(), &[], None, toggles)
+            .await
+            .expect("successful append");
+
+        let requested_blocks: Vec<_> = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|request| {
+                let body: Value = serde_json::from_slice(&request.body).unwrap();
+                let children = body["children"].as_array().unwrap();
+                let nested_children: usize = children
+                    .iter()
+                    .map(|child| child["toggle"]["children"].as_array().unwrap().len())
+                    .sum();
+                (children.len(), children.len() + nested_children)
+            })
+            .collect();
+        assert_eq!(requested_blocks, vec![(3, 93), (2, 62)]);
+    }
+
+    fn get_fast_retry_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limited_requests() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": [get_paragraph_block_json(0)],
+                "next_cursor": null,
+                "has_more": false
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server);
+        let blocks = client
+            .get_all_direct_block_children(&BlockId::from_str(PAGE_ID).unwrap())
+            .await
+            .expect("successful fetch after the retries");
+
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(
+            client.request_counts(),
+            RequestCounts {
+                requests: 3,
+                retries: 2
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_with_backoff() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(502))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": [get_paragraph_block_json(0)],
+                "next_cursor": null,
+                "has_more": false
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        get_client(&server)
+            .with_retry_policy(get_fast_retry_policy(3))
+            .get_all_direct_block_children(&BlockId::from_str(PAGE_ID).unwrap())
+            .await
+            .expect("successful fetch after the retry");
+    }
+
+    #[tokio::test]
+    async fn does_not_append_blocks_again_after_server_errors() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+
+        let server = MockServer::start().await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(502))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let errors = get_client(&server)
+            .with_retry_policy(get_fast_retry_policy(3))
+            .create_blocks(
+                BlockId::from_str(PAGE_ID).unwrap(),
+                parse_blocks_to_create("Paragraph 0"),
+            )
+            .await
+            .expect_err("the append is not retried");
+
+        assert!(matches!(
+            errors.as_slice(),
+            [CreateBlocksError {
+                error: AppendBlockChildrenError::Api(NotionApiError { status: 502, .. }),
+                ..
+            }]
+        ));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let result = get_client(&server)
+            .with_retry_policy(get_fast_retry_policy(2))
+            .get_all_direct_block_children(&BlockId::from_str(PAGE_ID).unwrap())
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(GetBlockChildrenError::Api(NotionApiError {
+                status: 503,
+                ..
+            }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn surfaces_validation_errors_when_appending() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+        const MESSAGE: &str =
+            "body.children[3].paragraph.rich_text[0].text.content.length should be ≤ `2000`, instead was `2001`.";
+
+        let server = MockServer::start().await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(get_error_response(400, "validation_error", MESSAGE))
+            .mount(&server)
+            .await;
+
+        let errors = get_client(&server)
+            .create_blocks(
+                BlockId::from_str(PAGE_ID).unwrap(),
+                parse_blocks_to_create("Paragraph 0"),
+            )
+            .await
+            .expect_err("the API rejects the blocks");
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].error.to_string(),
+            format!("Notion rejected the request: validation_error: {MESSAGE}")
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_failed_blocks_in_document_order() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+        const FIRST_ID: &str = "0a5f1b4e8c0d4c4a9c5e6d1f2b3a4c5d";
+        const SECOND_ID: &str = "8d4e2f603c1b4a7e9f2d5b6a7c8d9e0f";
+
+        let server = MockServer::start().await;
+        let mut created_items = get_created_list_item_json(FIRST_ID, "First");
+        created_items["results"].as_array_mut().unwrap().extend(
+            get_created_list_item_json(SECOND_ID, "Second")["results"]
+                .as_array()
+                .unwrap()
+                .clone(),
+        );
+        Mock::given(method("PATCH"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(created_items))
+            .mount(&server)
+            .await;
+        // NOTE: the first failure arrives last
+        Mock::given(method("PATCH"))
+            .and(path(format!("/v1/blocks/{FIRST_ID}/children")))
+            .respond_with(
+                get_error_response(400, "validation_error", "body.children[7] is invalid")
+                    .set_delay(Duration::from_millis(200)),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/v1/blocks/{SECOND_ID}/children")))
+            .respond_with(get_error_response(
+                409,
+                "conflict_error",
+                "Conflict occurred",
+            ))
+            .mount(&server)
+            .await;
+        let nested_items = |text: &str| {
+            (0..101)
+                .map(|index| format!("   1. {text} {index}\n"))
+                .collect::<String>()
+        };
+        let document = format!(
+            "1. First\n{}1. Second\n{}",
+            nested_items("Step with a rather long description that is cut off"),
+            nested_items("Note")
+        );
+
+        let errors = get_client(&server)
+            .create_blocks(
+                BlockId::from_str(PAGE_ID).unwrap(),
+                parse_blocks_to_create(&document),
+            )
+            .await
+            .expect_err("the API rejects the nested blocks");
+
+        let messages: Vec<_> = errors
+            .iter()
+            .map(|error| format!("{error}: {}", error.error))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                format!("blocks 1-100 nested in block 1 ({FIRST_ID}), starting with numbered_list_item \"Step with a rather long description that…\": Notion rejected the request: validation_error: body.children[7] is invalid"),
+                format!("blocks 1-100 nested in block 2 ({SECOND_ID}), starting with numbered_list_item \"Note 0\": Notion rejected the request: conflict_error: Conflict occurred"),
+            ]
+        );
+        assert!(
+            messages
+                .iter()
+                .all(|message| !message.contains("rich_text")),
+            "the blocks are not dumped"
+        );
+    }
+
+    #[tokio::test]
+    async fn surfaces_errors_when_fetching() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(get_error_response(
+                404,
+                "object_not_found",
+                "Could not find block with ID: ac32e025-6f9c-4fab-8b9d-dbb3c593ac46.",
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v1/users"))
+            .respond_with(get_error_response(
+                401,
+                "unauthorized",
+                "API token is invalid.",
+            ))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server);
+
+        let error = client
+            .get_all_direct_block_children(&BlockId::from_str(PAGE_ID).unwrap())
+            .await
+            .expect_err("the page does not exist");
+        assert!(matches!(
+            error,
+            GetBlockChildrenError::Api(NotionApiError { status: 404, ref code, .. })
+                if code == "object_not_found"
+        ));
+
+        let error = client.list_users().await.expect_err("the token is invalid");
+        assert_eq!(
+            error.to_string(),
+            "Notion rejected the request: unauthorized: API token is invalid."
+        );
+    }
+
+    #[tokio::test]
+    async fn surfaces_errors_when_deleting() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": [get_paragraph_block_json(1)],
+                "next_cursor": null,
+                "has_more": false
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path(format!("/v1/blocks/{:032x}", 1)))
+            .respond_with(get_error_response(
+                429,
+                "rate_limited",
+                "You have been rate limited. Please try again in a few minutes.",
+            ))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).with_retry_policy(get_fast_retry_policy(0));
+        let error = erase_page(&client, PageId::from_str(PAGE_ID).unwrap(), false, &[])
+            .await
+            .expect_err("the deletion is rate limited");
+
+        assert!(matches!(
+            error,
+            ErasePageError::DeleteBlockFailed {
+                error: DeleteBlockError::Api(NotionApiError { status: 429, .. }),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn keeps_error_bodies_that_are_not_notion_errors() {
+        let error = NotionApiError::from_response(StatusCode::BAD_GATEWAY, "upstream timed out");
+
+        assert_eq!(error.to_string(), "Bad Gateway: upstream timed out");
+    }
+
+    #[tokio::test]
+    async fn keeps_deleting_blocks_after_failures_when_keeping_going() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": (1..=4).map(get_paragraph_block_json).collect::<Vec<_>>(),
+                "next_cursor": null,
+                "has_more": false
+            })))
+            .mount(&server)
+            .await;
+        for (index, status) in [(1, 200), (2, 404), (3, 200), (4, 404)] {
+            let response = if status == 200 {
+                ResponseTemplate::new(200).set_body_json(get_paragraph_block_json(index))
+            } else {
+                get_error_response(
+                    status,
+                    "object_not_found",
+                    "Could not find block with the ID.",
+                )
+            };
+            Mock::given(method("DELETE"))
+                .and(path(format!("/v1/blocks/{index:032x}")))
+                .respond_with(response)
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let client = get_client(&server);
+        let error = erase_page(&client, PageId::from_str(PAGE_ID).unwrap(), true, &[])
+            .await
+            .expect_err("some blocks cannot be deleted");
+
+        let ErasePageError::DeleteBlocksFailed(errors) = error else {
+            panic!("expected all the failures, got {error:?}");
+        };
+        let failed_block_ids: Vec<_> = errors
+            .iter()
+            .map(|error| match error {
+                ErasePageError::DeleteBlockFailed {
+                    error: DeleteBlockError::Api(NotionApiError { status: 404, .. }),
+                    block_id,
+                } => block_id.to_string(),
+                error => panic!("unexpected error {error:?}"),
+            })
+            .collect();
+        assert_eq!(
+            failed_block_ids,
+            vec![format!("{:032x}", 2), format!("{:032x}", 4)]
+        );
+    }
+
+    /// Mounts the page listing 150 blocks across two pages on the first fetch,
+    /// and `remaining_blocks` on the fetches after that.
+    async fn mount_page_to_erase(server: &MockServer, page_id: &str, remaining_blocks: Vec<Value>) {
+        const NEXT_CURSOR: &str = "8d4e2f60-3c1b-4a7e-9f2d-5b6a7c8d9e0f";
+
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{page_id}/children")))
+            .and(query_param_is_missing("start_cursor"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": (0..100).map(get_paragraph_block_json).collect::<Vec<_>>(),
+                "next_cursor": NEXT_CURSOR,
+                "has_more": true
+            })))
+            .up_to_n_times(1)
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{page_id}/children")))
+            .and(query_param("start_cursor", NEXT_CURSOR))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": (100..150).map(get_paragraph_block_json).collect::<Vec<_>>(),
+                "next_cursor": null,
+                "has_more": false
+            })))
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{page_id}/children")))
+            .and(query_param_is_missing("start_cursor"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": remaining_blocks,
+                "next_cursor": null,
+                "has_more": false
+            })))
+            .expect(1)
+            .mount(server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path_regex("^/v1/blocks/[0-9a-f]{32}$"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(150)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn erases_all_pages_of_blocks() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+
+        let server = MockServer::start().await;
+        mount_page_to_erase(&server, PAGE_ID, Vec::new()).await;
+
+        let client = get_client(&server);
+        erase_page(&client, PageId::from_str(PAGE_ID).unwrap(), false, &[])
+            .await
+            .expect("successful erase");
+
+        let mut deleted_paths: Vec<_> = server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|request| request.method == wiremock::http::Method::Delete)
+            .map(|request| request.url.path().to_string())
+            .collect();
+        deleted_paths.sort();
+        assert_eq!(
+            deleted_paths,
+            get_expected_ids(150)
+                .into_iter()
+                .map(|id| format!("/v1/blocks/{id}"))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_when_blocks_remain_after_erasing() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+
+        let server = MockServer::start().await;
+        mount_page_to_erase(&server, PAGE_ID, vec![get_paragraph_block_json(150)]).await;
+
+        let client = get_client(&server);
+        let error = erase_page(&client, PageId::from_str(PAGE_ID).unwrap(), false, &[])
+            .await
+            .expect_err("a block was added in the meantime");
+
+        assert!(matches!(error, ErasePageError::PageNotEmpty));
+    }
+
+    #[tokio::test]
+    async fn reports_requests_that_time_out() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({
+                        "object": "list",
+                        "results": [],
+                        "next_cursor": null,
+                        "has_more": false
+                    }))
+                    .set_delay(Duration::from_millis(500)),
+            )
+            .mount(&server)
+            .await;
+
+        let error = get_client(&server)
+            .with_request_timeout(Duration::from_millis(50))
+            .get_all_direct_block_children(&BlockId::from_str(PAGE_ID).unwrap())
+            .await
+            .expect_err("the response comes too late");
+
+        let GetBlockChildrenError::RequestFailed(error) = error else {
+            panic!("expected a failed request, got {error:?}");
+        };
+        assert_eq!(error.to_string(), "request to Notion timed out after 50ms");
+    }
+
+    #[tokio::test]
+    async fn sends_requests_through_the_proxy() {
+        let proxy = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/users"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": [],
+                "next_cursor": null,
+                "has_more": false
+            })))
+            .expect(1)
+            .mount(&proxy)
+            .await;
+
+        let options = HttpOptions {
+            proxy: Some(Url::parse(&proxy.uri()).unwrap()),
+            ..Default::default()
+        };
+        // NOTE: nothing listens on the API URL, so only the proxy can respond
+        let users = NotionClient::new(get_notion_reqwest_client("secret", &options))
+            .with_base_url(Url::parse("http://notion.invalid/").unwrap())
+            .list_users()
+            .await
+            .expect("successful request through the proxy");
+
+        assert!(users.is_empty());
+    }
+
+    #[tokio::test]
+    async fn gets_the_bot_user_of_the_api_key() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/users/me"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "user",
+                "id": "16d84278-ab0e-484c-9bdd-b35da3bd8905",
+                "name": "notion-edit",
+                "type": "bot",
+                "bot": {
+                    "owner": {
+                        "type": "user",
+                        "user": { "object": "user", "id": "8d4e2f60", "name": "Gelio" }
+                    },
+                    "workspace_name": "Notes"
+                }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let user = get_client(&server)
+            .get_bot_user()
+            .await
+            .expect("successful request");
+
+        assert_eq!(
+            user,
+            BotUser {
+                name: "notion-edit".to_string(),
+                workspace_name: Some("Notes".to_string()),
+                owner: BotOwner::User {
+                    name: "Gelio".to_string()
+                },
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn checks_the_api_key_once() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/users/me"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "user",
+                "id": "16d84278-ab0e-484c-9bdd-b35da3bd8905",
+                "type": "bot",
+                "bot": { "owner": { "type": "workspace", "workspace": true } }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server);
+
+        client.ensure_access().await.expect("accepted API key");
+        client.ensure_access().await.expect("accepted API key");
+    }
+
+    #[tokio::test]
+    async fn reports_invalid_api_keys() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/users/me"))
+            .respond_with(get_error_response(
+                401,
+                "unauthorized",
+                "API token is invalid.",
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let error = get_client(&server)
+            .ensure_access()
+            .await
+            .expect_err("invalid API key");
+
+        assert!(matches!(error, CheckAccessError::InvalidApiKey(_)));
+    }
+
+    #[tokio::test]
+    async fn reports_rejected_notion_versions() {
+        const NOTION_VERSION: &str = "1999-01-01";
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/users/me"))
+            .and(header("Notion-Version", NOTION_VERSION))
+            .respond_with(get_error_response(
+                400,
+                "invalid_request",
+                "Invalid Notion-Version header value.",
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let options = HttpOptions {
+            notion_version: NOTION_VERSION.to_string(),
+            ..Default::default()
+        };
+        let error = NotionClient::new(get_notion_reqwest_client("secret", &options))
+            .with_base_url(parse_api_base_url(&server.uri()).unwrap())
+            .check_access()
+            .await
+            .expect_err("the version is rejected");
+
+        assert_eq!(
+            error.to_string(),
+            "Notion rejected the request: invalid_request: Invalid Notion-Version header value."
+        );
+    }
+
+    fn get_page_json(id: &str, title_property: &str, title: &str) -> Value {
+        json!({
+            "object": "page",
+            "id": id,
+            "url": format!("https://www.notion.so/{id}"),
+            "last_edited_time": "2022-08-01T12:00:00.000Z",
+            "properties": {
+                "Tags": { "id": "tags", "type": "multi_select", "multi_select": [] },
+                title_property: {
+                    "id": "title",
+                    "type": "title",
+                    "title": [
+                        { "type": "text", "plain_text": title, "text": { "content": title } }
+                    ]
+                }
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn searches_all_pages() {
+        const NEXT_CURSOR: &str = "8d4e2f60-3c1b-4a7e-9f2d-5b6a7c8d9e0f";
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/search"))
+            .and(body_partial_json(
+                json!({ "filter": { "property": "object", "value": "page" } }),
+            ))
+            .and(body_partial_json(json!({ "start_cursor": NEXT_CURSOR })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": [get_page_json("1b6f2c5f9d1e4d5b8d6f7e2a3c4b5d6e", "Name", "Task")],
+                "next_cursor": null,
+                "has_more": false
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/search"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": [get_page_json("ac32e0256f9c4fab8b9ddbb3c593ac46", "title", "Notes")],
+                "next_cursor": NEXT_CURSOR,
+                "has_more": true
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let pages = get_client(&server)
+            .search_pages()
+            .await
+            .expect("successful search");
+
+        assert_eq!(
+            pages
+                .iter()
+                .map(|page| (page.id.to_string(), page.title.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("ac32e0256f9c4fab8b9ddbb3c593ac46".to_string(), "Notes"),
+                ("1b6f2c5f9d1e4d5b8d6f7e2a3c4b5d6e".to_string(), "Task"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn searches_pages_and_databases() {
+        const NEXT_CURSOR: &str = "8d4e2f60-3c1b-4a7e-9f2d-5b6a7c8d9e0f";
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/search"))
+            .and(body_partial_json(json!({ "query": "plan" })))
+            .and(body_partial_json(json!({ "start_cursor": NEXT_CURSOR })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": [
+                    // NOTE: the integration lost access to the page after the first request
+                    { "object": "page", "id": "5c3b1a2d-7e6f-4a8b-9c0d-1e2f3a4b5c6d" },
+                    {
+                        "object": "database",
+                        "id": "7659d7a1-8538-4403-a1d6-03b828a21561",
+                        "last_edited_time": "2022-08-02T08:30:00.000Z",
+                        "parent": { "type": "workspace", "workspace": true },
+                        "title": []
+                    }
+                ],
+                "next_cursor": null,
+                "has_more": false
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/search"))
+            .and(body_partial_json(json!({ "query": "plan" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": [{
+                    "object": "page",
+                    "id": "1b6f2c5f-9d1e-4d5b-8d6f-7e2a3c4b5d6e",
+                    "last_edited_time": "2022-08-01T12:00:00.000Z",
+                    "parent": {
+                        "type": "database_id",
+                        "database_id": "7659d7a1-8538-4403-a1d6-03b828a21561"
+                    },
+                    "properties": {
+                        "Name": {
+                            "type": "title",
+                            "title": [
+                                { "type": "text", "plain_text": "Launch " },
+                                { "type": "text", "plain_text": "plan" }
+                            ]
+                        }
+                    }
+                }],
+                "next_cursor": NEXT_CURSOR,
+                "has_more": true
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let results = get_client(&server)
+            .search(Some("plan"), None)
+            .await
+            .expect("successful search");
+
+        assert_eq!(
+            results,
+            vec![
+                SearchResult {
+                    object: SearchObject::Page,
+                    id: "1b6f2c5f-9d1e-4d5b-8d6f-7e2a3c4b5d6e".to_string(),
+                    title: "Launch plan".to_string(),
+                    parent: Parent::Database {
+                        id: "7659d7a1-8538-4403-a1d6-03b828a21561".to_string()
+                    },
+                    last_edited_time: "2022-08-01T12:00:00.000Z".to_string(),
+                },
+                SearchResult {
+                    object: SearchObject::Database,
+                    id: "7659d7a1-8538-4403-a1d6-03b828a21561".to_string(),
+                    title: String::new(),
+                    parent: Parent::Workspace,
+                    last_edited_time: "2022-08-02T08:30:00.000Z".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn queries_all_pages_of_the_database() {
+        const DATABASE_ID: &str = "7659d7a1-8538-4403-a1d6-03b828a21561";
+        const NEXT_CURSOR: &str = "8d4e2f60-3c1b-4a7e-9f2d-5b6a7c8d9e0f";
+        let filter = json!({ "property": "Status", "status": { "equals": "Done" } });
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(format!("/v1/databases/{DATABASE_ID}/query")))
+            .and(body_partial_json(
+                json!({ "filter": filter, "start_cursor": NEXT_CURSOR }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": [get_page_json("1b6f2c5f9d1e4d5b8d6f7e2a3c4b5d6e", "Name", "")],
+                "next_cursor": null,
+                "has_more": false
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(format!("/v1/databases/{DATABASE_ID}/query")))
+            .and(body_partial_json(json!({ "filter": filter })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "results": [get_page_json("ac32e0256f9c4fab8b9ddbb3c593ac46", "Name", "Launch")],
+                "next_cursor": NEXT_CURSOR,
+                "has_more": true
+            })))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let pages = get_client(&server)
+            .query_database(&DatabaseId::from_str(DATABASE_ID).unwrap(), Some(&filter))
+            .await
+            .expect("successful query");
+
+        assert_eq!(
+            pages
+                .iter()
+                .map(|page| page.title.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Launch", ""]
+        );
+    }
+
+    #[test]
+    fn reads_titles_made_of_several_spans() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+        let mut page_json = get_page_json(PAGE_ID, "Name", "🚀 Launch");
+        page_json["properties"]["Name"]["title"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({ "type": "text", "plain_text": " plan", "text": { "content": " plan" } }));
+
+        let page: PageSummary = serde_json::from_value(page_json).unwrap();
+
+        assert_eq!(page.title, "🚀 Launch plan");
+        assert_eq!(page.title_property, "Name");
+    }
+
+    #[tokio::test]
+    async fn updates_long_titles_in_several_spans() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+        let long_title = format!("🚀 {}", "a".repeat(MAX_RICH_TEXT_LENGTH));
+        let server = MockServer::start().await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("/v1/pages/{PAGE_ID}")))
+            .and(body_json(json!({
+                "properties": {
+                    "title": {
+                        "title": [
+                            { "type": "text", "text": { "content": format!("🚀 {}", "a".repeat(MAX_RICH_TEXT_LENGTH - 2)) } },
+                            { "type": "text", "text": { "content": "aa" } }
+                        ]
+                    }
+                }
+            })))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(get_page_json(PAGE_ID, "title", &long_title)),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let client = get_client(&server);
+        let page: PageSummary =
+            serde_json::from_value(get_page_json(PAGE_ID, "title", "Draft")).unwrap();
+
+        let updated_page = client
+            .update_page(
+                &page.id,
+                &PageUpdate {
+                    properties: Some(page.title_properties(&long_title)),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("successful update");
+
+        assert_eq!(updated_page.title, long_title);
+    }
+
+    #[test]
+    fn reads_emoji_and_uploaded_icons() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+        let mut page_json = get_page_json(PAGE_ID, "title", "Notes");
+        page_json["icon"] = json!({ "type": "emoji", "emoji": "🚀" });
+        page_json["cover"] = json!({
+            "type": "file",
+            "file": {
+                "url": "https://s3.us-west-2.amazonaws.com/cover.png",
+                "expiry_time": "2022-08-01T13:00:00.000Z"
+            }
+        });
+
+        let page: PageSummary = serde_json::from_value(page_json.clone()).unwrap();
+
+        assert_eq!(
+            page.icon.as_ref().and_then(PageImage::front_matter_value),
+            Some("🚀")
+        );
+        assert_eq!(
+            page.cover,
+            Some(PageImage::File {
+                file: ImageUrl {
+                    url: "https://s3.us-west-2.amazonaws.com/cover.png".to_string()
+                }
+            })
+        );
+        assert_eq!(page.cover.unwrap().front_matter_value(), None);
+
+        page_json["icon"] = json!({ "type": "custom_emoji", "custom_emoji": { "id": "1" } });
+        let page: PageSummary = serde_json::from_value(page_json).unwrap();
+        assert_eq!(page.icon, None);
+    }
+
+    #[test]
+    fn serializes_page_updates() {
+        let update = PageUpdate {
+            icon: Some(PageImage::from_front_matter("🚀")),
+            cover: Some(PageImage::from_front_matter(
+                "https://example.com/cover.png",
+            )),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            serde_json::to_value(&update).unwrap(),
+            json!({
+                "icon": { "type": "emoji", "emoji": "🚀" },
+                "cover": { "type": "external", "external": { "url": "https://example.com/cover.png" } }
+            })
+        );
+    }
+
+    /// The block with all its descendants nested in it, like in a request that creates the
+    /// whole tree at once.
+    fn nest_children(block: BlockWithChildrenToCreate) -> BlockToCreate {
+        let BlockWithChildrenToCreate {
+            mut block,
+            children,
+        } = block;
+        if let Some(nested_children) = block.children_mut() {
+            nested_children.extend(children.into_iter().map(nest_children));
+        }
+
+        block
+    }
+
+    proptest! {
+        #[test]
+        fn blocks_to_create_round_trip_through_json(tags in document(Target::Notion)) {
+            let blocks: Vec<_> = tags
+                .into_iter()
+                .flat_map(BlockWithChildrenToCreate::from_markdown_tag)
+                .map(nest_children)
+                .collect();
+            let json = serde_json::to_value(&blocks).unwrap();
+
+            let parsed_blocks = serde_json::from_value::<Vec<BlockToCreate>>(json.clone());
+            prop_assert!(parsed_blocks.is_ok(), "{:?} in {:#}", parsed_blocks.err(), json);
+            prop_assert_eq!(serde_json::to_value(parsed_blocks.unwrap()).unwrap(), json);
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_each_request_once_with_the_response_cache() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+        const TOGGLE_ID: &str = "0a5f1b4e8c0d4c4a9c5e6d1f2b3a4c5d";
+        let mut toggle = mock_server::get_block_json(TOGGLE_ID, "toggle", "Details");
+        toggle["has_children"] = json!(true);
+
+        let server = MockServer::start().await;
+        mount_block_children(
+            &server,
+            PAGE_ID,
+            std::iter::once(toggle)
+                .chain((0..150).map(get_paragraph_block_json))
+                .collect(),
+        )
+        .await;
+        mount_block_children(&server, TOGGLE_ID, vec![get_paragraph_block_json(150)]).await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/pages/{PAGE_ID}")))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(get_page_json(PAGE_ID, "title", "Notes")),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let client = get_client(&server).with_response_cache();
+        let page_id = PageId::from_str(PAGE_ID).unwrap();
+        let block_id = BlockId::from_str(PAGE_ID).unwrap();
+
+        // NOTE: like the conflict check and the backup of a push, which both need the page
+        let (first_page, first_blocks, second_page, second_blocks) = tokio::join!(
+            client.get_page(&page_id),
+            get_all_block_children(&client, &block_id),
+            client.get_page(&page_id),
+            get_all_block_children(&client, &block_id),
+        );
+
+        assert_eq!(first_page.unwrap().title, second_page.unwrap().title);
+        assert_eq!(first_blocks.unwrap(), second_blocks.unwrap());
+        let requests = server.received_requests().await.unwrap();
+        let urls: std::collections::HashSet<_> =
+            requests.iter().map(|request| &request.url).collect();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(urls.len(), 4, "every URL is requested once");
+    }
+
+    #[tokio::test]
+    async fn fetches_again_after_changes() {
+        const PAGE_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+        const BLOCK_ID: &str = "0a5f1b4e8c0d4c4a9c5e6d1f2b3a4c5d";
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{PAGE_ID}/children")))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(mock_server::get_list_json(vec![
+                    get_paragraph_block_json(0),
+                ])),
+            )
+            .expect(3)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path(format!("/v1/blocks/{BLOCK_ID}")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{BLOCK_ID}")))
+            .respond_with(get_error_response(
+                404,
+                "object_not_found",
+                "Could not find block.",
+            ))
+            .expect(2)
+            .mount(&server)
+            .await;
+        let client = get_client(&server).with_response_cache();
+        let block_id = BlockId::from_str(PAGE_ID).unwrap();
+
+        for _ in 0..2 {
+            client
+                .get_all_direct_block_children(&block_id)
+                .await
+                .unwrap();
+        }
+        client
+            .delete_block(BlockId::from_str(BLOCK_ID).unwrap())
+            .await
+            .unwrap();
+        client
+            .get_all_direct_block_children(&block_id)
+            .await
+            .unwrap();
+        client.invalidate(&PageId::from_str(PAGE_ID).unwrap());
+        client
+            .get_all_direct_block_children(&block_id)
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            client
+                .get_block(&BlockId::from_str(BLOCK_ID).unwrap())
+                .await
+                .expect_err("failed responses are not kept");
+        }
+    }
 }
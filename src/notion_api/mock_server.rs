@@ -0,0 +1,107 @@
+//! A mock of the Notion API for tests: builders for the JSON that Notion responds with, and
+//! mocks of the endpoints that list the children of blocks.
+
+use serde_json::{json, Value};
+use wiremock::{
+    matchers::{method, path, query_param, query_param_is_missing},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::notion_api::client::{parse_api_base_url, NotionClient};
+
+/// The number of children the client lists in one request.
+const PAGE_SIZE: usize = 100;
+
+/// The user that created and edited every block.
+pub const USER_ID: &str = "ac32e0256f9c4fab8b9ddbb3c593ac46";
+
+/// A client that sends the requests to the mock server.
+pub fn get_client(server: &MockServer) -> NotionClient {
+    NotionClient::new(reqwest::Client::new())
+        .with_base_url(parse_api_base_url(&server.uri()).unwrap())
+}
+
+/// A block with text, like a paragraph or a toggle, without children.
+pub fn get_block_json(id: &str, block_type: &str, content: &str) -> Value {
+    let user = json!({ "object": "user", "id": USER_ID });
+
+    json!({
+        "object": "block",
+        "id": id,
+        "type": block_type,
+        "created_time": "2022-08-01T12:00:00.000Z",
+        "last_edited_time": "2022-08-01T12:00:00.000Z",
+        "has_children": false,
+        "created_by": user,
+        "last_edited_by": user,
+        block_type: {
+            "rich_text": [{
+                "type": "text",
+                "plain_text": content,
+                "href": null,
+                "text": { "content": content, "link": null }
+            }],
+            "color": "default"
+        }
+    })
+}
+
+pub fn get_paragraph_block_json(id: &str, content: &str) -> Value {
+    get_block_json(id, "paragraph", content)
+}
+
+/// The last page of a list of results.
+pub fn get_list_json(results: Vec<Value>) -> Value {
+    get_list_page_json(results, None)
+}
+
+/// A page of a list of results, followed by more results from `next_cursor`.
+pub fn get_list_page_json(results: Vec<Value>, next_cursor: Option<&str>) -> Value {
+    json!({
+        "object": "list",
+        "results": results,
+        "next_cursor": next_cursor,
+        "has_more": next_cursor.is_some()
+    })
+}
+
+pub fn get_error_response(status: u16, code: &str, message: &str) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_json(json!({
+        "object": "error",
+        "status": status,
+        "code": code,
+        "message": message
+    }))
+}
+
+/// Mocks listing the children of the block, in pages of the size that the client asks for.
+/// Every page is expected to be fetched once.
+pub async fn mount_block_children(server: &MockServer, block_id: &str, children: Vec<Value>) {
+    let pages: Vec<_> = children.chunks(PAGE_SIZE).collect();
+    let page_count = pages.len().max(1);
+    let cursor = |page: usize| format!("{block_id}-{page}");
+
+    for page in 0..page_count {
+        let results = pages
+            .get(page)
+            .map(|page| page.to_vec())
+            .unwrap_or_default();
+        let next_cursor = (page + 1 < page_count).then(|| cursor(page + 1));
+        let mock = Mock::given(method("GET"))
+            .and(path(format!("/v1/blocks/{block_id}/children")))
+            .and(query_param("page_size", PAGE_SIZE.to_string()));
+        let mock = if page == 0 {
+            mock.and(query_param_is_missing("start_cursor"))
+        } else {
+            mock.and(query_param("start_cursor", cursor(page)))
+        };
+
+        mock.respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(get_list_page_json(results, next_cursor.as_deref())),
+        )
+        .expect(1)
+        .mount(server)
+        .await;
+    }
+}
@@ -1,13 +1,63 @@
 use notion::models::{Block, BlockCommon};
+use serde::{Deserialize, Serialize};
 
+pub mod assets;
+pub mod cache;
 pub mod client;
+#[cfg(any(test, feature = "test-util"))]
+pub mod mock_server;
+pub mod properties;
+pub mod response_cache;
+pub mod retry;
 
-#[derive(Debug)]
+/// A fetched block with all its children, nested the way they are in the page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct BlockWithChildren {
     pub block: Block,
     pub children: Vec<BlockWithChildren>,
 }
 
+impl BlockWithChildren {
+    pub fn new(block: Block, children: Vec<BlockWithChildren>) -> Self {
+        Self { block, children }
+    }
+
+    /// The block without children.
+    pub fn leaf(block: Block) -> Self {
+        Self::new(block, Vec::new())
+    }
+
+    /// The block followed by all its descendants, in document order.
+    pub fn iter_depth_first(&self) -> DepthFirst<'_> {
+        DepthFirst {
+            pending: vec![self],
+        }
+    }
+}
+
+/// All the blocks and their descendants, in document order.
+pub fn flatten(blocks: &[BlockWithChildren]) -> DepthFirst<'_> {
+    DepthFirst {
+        pending: blocks.iter().rev().collect(),
+    }
+}
+
+/// The iterator of [`BlockWithChildren::iter_depth_first`] and [`flatten`].
+pub struct DepthFirst<'a> {
+    pending: Vec<&'a BlockWithChildren>,
+}
+
+impl<'a> Iterator for DepthFirst<'a> {
+    type Item = &'a BlockWithChildren;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let block = self.pending.pop()?;
+        self.pending.extend(block.children.iter().rev());
+
+        Some(block)
+    }
+}
+
 trait GetCommon {
     fn common(&self) -> Option<&BlockCommon>;
 }
@@ -17,9 +67,96 @@ impl GetCommon for Block {
         use Block::*;
 
         match self {
-            NumberedListItem { common, .. } => Some(common),
-            // TODO: add more block patterns
-            _ => None,
+            Paragraph { common, .. }
+            | Heading1 { common, .. }
+            | Heading2 { common, .. }
+            | Heading3 { common, .. }
+            | Callout { common, .. }
+            | Quote { common, .. }
+            | BulletedListItem { common, .. }
+            | NumberedListItem { common, .. }
+            | ToDo { common, .. }
+            | Toggle { common, .. }
+            | Code { common, .. }
+            | ChildPage { common, .. }
+            | ChildDatabase { common, .. }
+            | Embed { common, .. }
+            | Image { common, .. }
+            | Video { common, .. }
+            | File { common, .. }
+            | Pdf { common, .. }
+            | Bookmark { common, .. }
+            | Equation { common, .. }
+            | Divider { common, .. }
+            | TableOfContents { common, .. }
+            | Breadcrumb { common, .. }
+            | ColumnList { common, .. }
+            | Column { common, .. }
+            | LinkPreview { common, .. }
+            | Template { common, .. }
+            | LinkToPage { common, .. }
+            | Table { common, .. }
+            | SyncedBlock { common, .. }
+            | TableRow { common, .. }
+            | Unsupported { common, .. } => Some(common),
+            Unknown => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use notion::ids::AsIdentifier;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::notion_api::mock_server::get_paragraph_block_json;
+
+    fn block(id: &str, children: Vec<BlockWithChildren>) -> BlockWithChildren {
+        let block = serde_json::from_value(get_paragraph_block_json(id, id)).unwrap();
+        BlockWithChildren::new(block, children)
+    }
+
+    fn ids<'a>(blocks: impl Iterator<Item = &'a BlockWithChildren>) -> Vec<String> {
+        blocks
+            .map(|block| block.block.as_id().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn iterates_blocks_in_document_order() {
+        let id = |number: u8| format!("{number:032x}");
+        let blocks = vec![
+            block(
+                &id(1),
+                vec![
+                    block(&id(2), vec![block(&id(3), Vec::new())]),
+                    block(&id(4), Vec::new()),
+                ],
+            ),
+            block(&id(5), vec![block(&id(6), Vec::new())]),
+        ];
+
+        assert_eq!(ids(flatten(&blocks)), (1..=6).map(id).collect::<Vec<_>>());
+        assert_eq!(
+            ids(blocks[0].iter_depth_first()),
+            (1..=4).map(id).collect::<Vec<_>>()
+        );
+        assert_eq!(ids(flatten(&[])), Vec::<String>::new());
+    }
+
+    #[test]
+    fn serializes_the_whole_tree() {
+        let tree = block(
+            "0a5f1b4e8c0d4c4a9c5e6d1f2b3a4c5d",
+            vec![block("1c2d3e4f5a6b4c7d8e9f0a1b2c3d4e5f", Vec::new())],
+        );
+
+        let json = serde_json::to_string(&tree).unwrap();
+
+        assert_eq!(
+            serde_json::from_str::<BlockWithChildren>(&json).unwrap(),
+            tree
+        );
+    }
+}
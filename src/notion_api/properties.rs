@@ -0,0 +1,616 @@
+use serde_json::{json, Map, Value};
+use serde_yaml::{Mapping, Value as YamlValue};
+use thiserror::Error;
+
+use crate::markdown::{
+    plain_text::{split_into_chunks, MAX_RICH_TEXT_LENGTH},
+    warning::Warning,
+};
+
+/// The types of properties that are written to the front matter, but cannot be changed from it.
+// TODO: push people and relations once users and pages can be looked up by name
+const READ_ONLY_TYPES: [&str; 2] = ["people", "relation"];
+
+/// The types of properties with options that have to exist in the database.
+const OPTION_TYPES: [&str; 3] = ["select", "multi_select", "status"];
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PropertyError {
+    #[error("the page has no property {name:?}")]
+    Unknown { name: String },
+
+    #[error("property {name:?} of type {kind} cannot be set to {value}")]
+    InvalidValue {
+        name: String,
+        kind: String,
+        value: String,
+    },
+
+    #[error("{option:?} is not an option of property {name:?}")]
+    UnknownOption { name: String, option: String },
+
+    #[error("property {name:?} of type {kind} cannot be filtered on")]
+    CannotFilter { name: String, kind: String },
+}
+
+/// The properties of a database page as front matter values. The title is left out, as it has
+/// its own key. Properties that cannot be written to the front matter are skipped with
+/// a warning.
+pub fn properties_to_yaml(properties: &Map<String, Value>, warnings: &mut Vec<Warning>) -> Mapping {
+    let mut mapping = Mapping::new();
+    for (name, property) in properties {
+        let kind = property_type(property);
+        if kind == "title" {
+            continue;
+        }
+
+        match property_to_yaml(property) {
+            Some(value) => {
+                mapping.insert(name.as_str().into(), value);
+            }
+            None => warnings.push(Warning::PropertySkipped {
+                name: name.clone(),
+                kind: kind.to_string(),
+            }),
+        }
+    }
+
+    mapping
+}
+
+/// The properties from the front matter that differ from the `current` properties of the page,
+/// as the body of a page update. Read-only properties are not changed, with a warning
+/// when they were edited.
+pub fn changed_properties(
+    current: &Map<String, Value>,
+    front_matter: &Mapping,
+    warnings: &mut Vec<Warning>,
+) -> Result<Map<String, Value>, PropertyError> {
+    let mut changed = Map::new();
+    for (name, value) in front_matter {
+        let name = yaml_to_string(name);
+        let property = current
+            .get(&name)
+            .ok_or_else(|| PropertyError::Unknown { name: name.clone() })?;
+        if property_to_yaml(property).as_ref() == Some(value) {
+            continue;
+        }
+
+        let kind = property_type(property);
+        if READ_ONLY_TYPES.contains(&kind) || property_to_yaml(property).is_none() {
+            warnings.push(Warning::PropertyNotPushed {
+                name,
+                kind: kind.to_string(),
+            });
+            continue;
+        }
+
+        let property_value =
+            yaml_to_property(kind, value).ok_or_else(|| PropertyError::InvalidValue {
+                name: name.clone(),
+                kind: kind.to_string(),
+                value: yaml_to_string(value),
+            })?;
+        changed.insert(name, json!({ kind: property_value }));
+    }
+
+    Ok(changed)
+}
+
+/// The properties of a new page in the database with the `schema`, from the front matter,
+/// as the body of the request creating it. The title is set separately.
+pub fn new_page_properties(
+    schema: &Map<String, Value>,
+    front_matter: &Mapping,
+    warnings: &mut Vec<Warning>,
+) -> Result<Map<String, Value>, PropertyError> {
+    let mut properties = Map::new();
+    for (name, value) in front_matter {
+        let name = yaml_to_string(name);
+        let kind = schema
+            .get(&name)
+            .map(property_type)
+            .ok_or_else(|| PropertyError::Unknown { name: name.clone() })?;
+        if READ_ONLY_TYPES.contains(&kind) {
+            warnings.push(Warning::PropertyNotPushed {
+                name,
+                kind: kind.to_string(),
+            });
+            continue;
+        }
+
+        let property_value =
+            yaml_to_property(kind, value).ok_or_else(|| PropertyError::InvalidValue {
+                name: name.clone(),
+                kind: kind.to_string(),
+                value: yaml_to_string(value),
+            })?;
+        properties.insert(name, json!({ kind: property_value }));
+    }
+    check_options(&properties, schema)?;
+
+    Ok(properties)
+}
+
+/// The name of the title property in the `schema` of the database.
+pub fn title_property(schema: &Map<String, Value>) -> Option<&str> {
+    schema
+        .iter()
+        .find(|(_, property)| property_type(property) == "title")
+        .map(|(name, _)| name.as_str())
+}
+
+/// Whether the changed properties set options, which have to be checked with
+/// [`check_options`].
+pub fn sets_options(changed: &Map<String, Value>) -> bool {
+    changed
+        .values()
+        .any(|property| OPTION_TYPES.iter().any(|kind| property.get(kind).is_some()))
+}
+
+/// Checks that the options set by the changed properties exist in the `schema` of the database.
+// NOTE: Notion would create the missing select options, so a typo would add a new option
+pub fn check_options(
+    changed: &Map<String, Value>,
+    schema: &Map<String, Value>,
+) -> Result<(), PropertyError> {
+    for (name, property) in changed {
+        for kind in OPTION_TYPES {
+            let options: Vec<&str> = match &property[kind] {
+                Value::Object(option) => option
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .into_iter()
+                    .collect(),
+                Value::Array(options) => options
+                    .iter()
+                    .filter_map(|option| option["name"].as_str())
+                    .collect(),
+                _ => continue,
+            };
+            let existing_options = schema
+                .get(name)
+                .and_then(|property| property[kind]["options"].as_array())
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+
+            for option in options {
+                if !existing_options
+                    .iter()
+                    .any(|existing_option| existing_option["name"] == option)
+                {
+                    return Err(PropertyError::UnknownOption {
+                        name: name.clone(),
+                        option: option.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The filter of a database query for the pages where each property from `equals` has
+/// the value, edited on or after `since`. The values are converted to the types of
+/// the properties in the `schema` of the database.
+pub fn query_filter(
+    schema: &Map<String, Value>,
+    equals: &[(String, String)],
+    since: Option<&str>,
+) -> Result<Option<Value>, PropertyError> {
+    let mut filters = Vec::new();
+    for (name, value) in equals {
+        let property = schema
+            .get(name)
+            .ok_or_else(|| PropertyError::Unknown { name: name.clone() })?;
+        let kind = property_type(property);
+        let invalid_value = || PropertyError::InvalidValue {
+            name: name.clone(),
+            kind: kind.to_string(),
+            value: value.clone(),
+        };
+
+        let condition = match kind {
+            "title" | "rich_text" | "url" | "email" | "phone_number" | "select" | "status"
+            | "date" => json!({ "equals": value }),
+            "multi_select" | "people" | "relation" => json!({ "contains": value }),
+            "number" => json!({ "equals": value.parse::<f64>().map_err(|_| invalid_value())? }),
+            "checkbox" => json!({ "equals": value.parse::<bool>().map_err(|_| invalid_value())? }),
+            _ => {
+                return Err(PropertyError::CannotFilter {
+                    name: name.clone(),
+                    kind: kind.to_string(),
+                })
+            }
+        };
+        filters.push(json!({ "property": name, kind: condition }));
+    }
+    if let Some(since) = since {
+        filters.push(json!({
+            "timestamp": "last_edited_time",
+            "last_edited_time": { "on_or_after": since }
+        }));
+    }
+
+    Ok(match filters.len() {
+        0 => None,
+        1 => filters.pop(),
+        _ => Some(json!({ "and": filters })),
+    })
+}
+
+fn property_type(property: &Value) -> &str {
+    property["type"].as_str().unwrap_or_default()
+}
+
+/// The value of the property in the front matter, or `None` when the property cannot be
+/// written there.
+fn property_to_yaml(property: &Value) -> Option<YamlValue> {
+    let kind = property_type(property);
+    let value = &property[kind];
+
+    Some(match kind {
+        "rich_text" => plain_text(value).into(),
+        "select" | "status" => value["name"]
+            .as_str()
+            .map_or(YamlValue::Null, YamlValue::from),
+        "multi_select" => names(value, "name"),
+        "date" if value.is_null() => YamlValue::Null,
+        // NOTE: dates without an end or a time zone are written as a plain string
+        "date" if value["end"].is_null() && value["time_zone"].is_null() => {
+            json_to_yaml(&value["start"])?
+        }
+        "date" => {
+            let mut date = Mapping::new();
+            for key in ["start", "end", "time_zone"] {
+                if !value[key].is_null() {
+                    date.insert(key.into(), json_to_yaml(&value[key])?);
+                }
+            }
+            date.into()
+        }
+        "checkbox" | "number" | "url" | "email" | "phone_number" => json_to_yaml(value)?,
+        "people" => YamlValue::Sequence(
+            value
+                .as_array()?
+                .iter()
+                .filter_map(|person| person["name"].as_str().or(person["id"].as_str()))
+                .map(YamlValue::from)
+                .collect(),
+        ),
+        "relation" => names(value, "id"),
+        _ => return None,
+    })
+}
+
+/// The value of the property of the `kind` in the Notion API, or `None` when the front matter
+/// value does not fit the property.
+fn yaml_to_property(kind: &str, value: &YamlValue) -> Option<Value> {
+    Some(match (kind, value) {
+        ("rich_text", YamlValue::Null) => json!([]),
+        ("rich_text", _) => {
+            let text = yaml_scalar_to_string(value)?;
+            Value::Array(
+                split_into_chunks(&text, MAX_RICH_TEXT_LENGTH)
+                    .into_iter()
+                    .map(|chunk| json!({ "type": "text", "text": { "content": chunk } }))
+                    .collect(),
+            )
+        }
+        ("select" | "status", YamlValue::Null) => Value::Null,
+        ("select" | "status", _) => json!({ "name": yaml_scalar_to_string(value)? }),
+        ("multi_select", YamlValue::Null) => json!([]),
+        ("multi_select", YamlValue::Sequence(options)) => Value::Array(
+            options
+                .iter()
+                .map(|option| Some(json!({ "name": yaml_scalar_to_string(option)? })))
+                .collect::<Option<_>>()?,
+        ),
+        ("date", YamlValue::Null) => Value::Null,
+        ("date", YamlValue::String(start)) => json!({ "start": start }),
+        ("date", YamlValue::Mapping(date)) => {
+            let start = date.get("start")?.as_str()?;
+            let end = date.get("end").and_then(YamlValue::as_str);
+            let time_zone = date.get("time_zone").and_then(YamlValue::as_str);
+            json!({ "start": start, "end": end, "time_zone": time_zone })
+        }
+        ("checkbox", YamlValue::Bool(checked)) => json!(checked),
+        ("number", YamlValue::Null | YamlValue::Number(_))
+        | ("url" | "email" | "phone_number", YamlValue::Null | YamlValue::String(_)) => {
+            serde_json::to_value(value).ok()?
+        }
+        _ => return None,
+    })
+}
+
+fn plain_text(rich_text: &Value) -> String {
+    rich_text
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|text| text["plain_text"].as_str())
+        .collect()
+}
+
+fn names(values: &Value, key: &str) -> YamlValue {
+    YamlValue::Sequence(
+        values
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|value| value[key].as_str())
+            .map(YamlValue::from)
+            .collect(),
+    )
+}
+
+fn json_to_yaml(value: &Value) -> Option<YamlValue> {
+    serde_yaml::to_value(value).ok()
+}
+
+/// Strings, numbers and booleans, which YAML does not require to be quoted, as strings.
+fn yaml_scalar_to_string(value: &YamlValue) -> Option<String> {
+    match value {
+        YamlValue::String(text) => Some(text.clone()),
+        YamlValue::Number(number) => Some(number.to_string()),
+        YamlValue::Bool(boolean) => Some(boolean.to_string()),
+        _ => None,
+    }
+}
+
+fn yaml_to_string(value: &YamlValue) -> String {
+    yaml_scalar_to_string(value).unwrap_or_else(|| {
+        serde_yaml::to_string(value)
+            .map(|yaml| yaml.trim_end().to_string())
+            .unwrap_or_default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn get_properties() -> Map<String, Value> {
+        let properties = json!({
+            "Name": { "id": "title", "type": "title", "title": [] },
+            "Status": { "id": "a", "type": "status", "status": { "name": "In progress" } },
+            "Tags": {
+                "id": "b",
+                "type": "multi_select",
+                "multi_select": [{ "name": "notion" }, { "name": "markdown" }]
+            },
+            "Due": {
+                "id": "c",
+                "type": "date",
+                "date": { "start": "2022-08-01", "end": null, "time_zone": null }
+            },
+            "Trip": {
+                "id": "d",
+                "type": "date",
+                "date": { "start": "2022-08-01", "end": "2022-08-05", "time_zone": null }
+            },
+            "Done": { "id": "e", "type": "checkbox", "checkbox": false },
+            "Estimate": { "id": "f", "type": "number", "number": 3 },
+            "Link": { "id": "g", "type": "url", "url": null },
+            "Owner": {
+                "id": "h",
+                "type": "people",
+                "people": [{ "object": "user", "id": "1b6f2c5f", "name": "Ada" }]
+            },
+            "Summary": { "id": "i", "type": "rich_text", "rich_text": [{ "plain_text": "Short" }] },
+            "Total": { "id": "j", "type": "formula", "formula": { "type": "number", "number": 4 } }
+        });
+
+        match properties {
+            Value::Object(properties) => properties,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn writes_properties_as_yaml() {
+        let mut warnings = Vec::new();
+
+        let mapping = properties_to_yaml(&get_properties(), &mut warnings);
+
+        assert_eq!(
+            serde_yaml::to_string(&mapping).unwrap(),
+            "\
+Done: false
+Due: 2022-08-01
+Estimate: 3
+Link: null
+Owner:
+- Ada
+Status: In progress
+Summary: Short
+Tags:
+- notion
+- markdown
+Trip:
+  start: 2022-08-01
+  end: 2022-08-05
+"
+        );
+        assert_eq!(
+            warnings,
+            [Warning::PropertySkipped {
+                name: "Total".to_string(),
+                kind: "formula".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn pushes_only_changed_properties() {
+        let properties = get_properties();
+        let mut front_matter = properties_to_yaml(&properties, &mut Vec::new());
+        front_matter.insert("Done".into(), true.into());
+        front_matter.insert("Tags".into(), serde_yaml::from_str("[notion]").unwrap());
+        front_matter.insert("Owner".into(), serde_yaml::from_str("[Grace]").unwrap());
+        let mut warnings = Vec::new();
+
+        let changed = changed_properties(&properties, &front_matter, &mut warnings).unwrap();
+
+        assert_eq!(
+            Value::Object(changed),
+            json!({
+                "Done": { "checkbox": true },
+                "Tags": { "multi_select": [{ "name": "notion" }] }
+            })
+        );
+        assert_eq!(
+            warnings,
+            [Warning::PropertyNotPushed {
+                name: "Owner".to_string(),
+                kind: "people".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_values_that_do_not_fit_the_property() {
+        let properties = get_properties();
+
+        for (name, value, expected_error) in [
+            (
+                "Done",
+                "yes please",
+                PropertyError::InvalidValue {
+                    name: "Done".to_string(),
+                    kind: "checkbox".to_string(),
+                    value: "yes please".to_string(),
+                },
+            ),
+            (
+                "Priority",
+                "High",
+                PropertyError::Unknown {
+                    name: "Priority".to_string(),
+                },
+            ),
+        ] {
+            let mut front_matter = Mapping::new();
+            front_matter.insert(name.into(), value.into());
+
+            assert_eq!(
+                changed_properties(&properties, &front_matter, &mut Vec::new()),
+                Err(expected_error)
+            );
+        }
+    }
+
+    #[test]
+    fn filters_by_the_types_of_the_properties() {
+        let schema = json!({
+            "Status": { "type": "status", "status": { "options": [] } },
+            "Tags": { "type": "multi_select", "multi_select": { "options": [] } },
+            "Done": { "type": "checkbox", "checkbox": {} }
+        });
+        let schema = schema.as_object().unwrap();
+        let equals = |filters: &[(&str, &str)]| -> Vec<(String, String)> {
+            filters
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
+
+        assert_eq!(query_filter(schema, &[], None), Ok(None));
+        assert_eq!(
+            query_filter(schema, &equals(&[("Status", "Done")]), None),
+            Ok(Some(
+                json!({ "property": "Status", "status": { "equals": "Done" } })
+            ))
+        );
+        assert_eq!(
+            query_filter(
+                schema,
+                &equals(&[("Tags", "notion"), ("Done", "true")]),
+                Some("2022-08-01")
+            ),
+            Ok(Some(json!({
+                "and": [
+                    { "property": "Tags", "multi_select": { "contains": "notion" } },
+                    { "property": "Done", "checkbox": { "equals": true } },
+                    {
+                        "timestamp": "last_edited_time",
+                        "last_edited_time": { "on_or_after": "2022-08-01" }
+                    }
+                ]
+            })))
+        );
+        assert_eq!(
+            query_filter(schema, &equals(&[("Done", "maybe")]), None),
+            Err(PropertyError::InvalidValue {
+                name: "Done".to_string(),
+                kind: "checkbox".to_string(),
+                value: "maybe".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn converts_properties_of_new_pages_with_the_schema() {
+        let schema = json!({
+            "Name": { "type": "title", "title": {} },
+            "Status": { "type": "status", "status": { "options": [{ "name": "Done" }] } },
+            "Due": { "type": "date", "date": {} },
+            "Estimate": { "type": "number", "number": {} }
+        });
+        let schema = schema.as_object().unwrap();
+        let front_matter: Mapping =
+            serde_yaml::from_str("Status: Done\nDue: 2022-08-01\nEstimate: 3").unwrap();
+
+        let properties = new_page_properties(schema, &front_matter, &mut Vec::new()).unwrap();
+
+        assert_eq!(title_property(schema), Some("Name"));
+        assert_eq!(
+            Value::Object(properties),
+            json!({
+                "Status": { "status": { "name": "Done" } },
+                "Due": { "date": { "start": "2022-08-01" } },
+                "Estimate": { "number": 3 }
+            })
+        );
+
+        let front_matter: Mapping = serde_yaml::from_str("Estimate: a lot").unwrap();
+        assert_eq!(
+            new_page_properties(schema, &front_matter, &mut Vec::new()),
+            Err(PropertyError::InvalidValue {
+                name: "Estimate".to_string(),
+                kind: "number".to_string(),
+                value: "a lot".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn checks_that_options_exist() {
+        let schema = json!({
+            "Status": {
+                "type": "status",
+                "status": { "options": [{ "name": "Done" }, { "name": "In progress" }] }
+            }
+        });
+        let schema = schema.as_object().unwrap();
+        let changed = |status: &str| {
+            json!({ "Status": { "status": { "name": status } } })
+                .as_object()
+                .unwrap()
+                .clone()
+        };
+
+        assert!(sets_options(&changed("Done")));
+        assert_eq!(check_options(&changed("Done"), schema), Ok(()));
+        assert_eq!(
+            check_options(&changed("Doen"), schema),
+            Err(PropertyError::UnknownOption {
+                name: "Status".to_string(),
+                option: "Doen".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,66 @@
+//! The responses to the `GET` requests of a run, kept in memory, so the parts of a command
+//! that need the same page, like the conflict check and the backup, fetch it only once.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::OnceCell;
+
+use super::client::{NotionResponse, RequestError};
+
+/// Successful responses by the method and the URL of the request, including the query with
+/// the cursor of the page of results.
+#[derive(Debug, Default)]
+pub struct ResponseCache {
+    responses: Mutex<HashMap<String, Arc<OnceCell<NotionResponse>>>>,
+}
+
+impl ResponseCache {
+    /// The cached response to the request, or the response that `send` gets. Requests with
+    /// the same key that are sent at the same time wait for the first one instead of being
+    /// sent again. Responses with an error status are not kept.
+    pub(super) async fn get_or_send<F, Fut>(
+        &self,
+        key: String,
+        send: F,
+    ) -> Result<NotionResponse, RequestError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<NotionResponse, RequestError>>,
+    {
+        let response = self
+            .responses
+            .lock()
+            .expect("the lock is not poisoned")
+            .entry(key)
+            .or_default()
+            .clone();
+
+        // NOTE: failures are returned as errors so they are not stored, and the next request
+        // with the key is sent again
+        let result = response
+            .get_or_try_init(|| async {
+                match send().await {
+                    Ok(response) if response.status.is_success() => Ok(response),
+                    result => Err(result),
+                }
+            })
+            .await;
+
+        match result {
+            Ok(response) => Ok(response.clone()),
+            Err(result) => result,
+        }
+    }
+
+    /// Forgets the responses whose key matches.
+    pub fn forget(&self, mut matches: impl FnMut(&str) -> bool) {
+        self.responses
+            .lock()
+            .expect("the lock is not poisoned")
+            .retain(|key, _| !matches(key));
+    }
+}
@@ -0,0 +1,190 @@
+use std::time::Duration;
+
+use reqwest::{
+    header::{self, HeaderMap},
+    Method, StatusCode,
+};
+
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+/// Notion allows an average of 3 requests per second, with bursts above it. Rate-limited
+/// requests are retried after the delay Notion asks for.
+pub const DEFAULT_MAX_CONCURRENCY: usize = 4;
+
+/// Decides if and when a failed request is sent again.
+/// Rate limited requests (429) are retried after the `Retry-After` delay,
+/// server errors (5xx) with an exponential backoff.
+///
+/// Server errors are only retried for idempotent requests, as the changes of the others, like
+/// appending block children, may have been made before the error, and would be made twice.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// How long to wait before retrying the request with the `method` that got a response with
+    /// the `status` and `headers`. `retry` is the number of retries made so far.
+    ///
+    /// Returns `None` when the request should not be retried.
+    pub fn retry_delay(
+        &self,
+        retry: u32,
+        method: &Method,
+        status: StatusCode,
+        headers: &HeaderMap,
+    ) -> Option<Duration> {
+        if retry >= self.max_retries {
+            return None;
+        }
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            Some(retry_after(headers).unwrap_or_else(|| self.backoff(retry)))
+        } else if status.is_server_error() && is_idempotent(method) {
+            Some(self.backoff(retry))
+        } else {
+            None
+        }
+    }
+
+    /// The exponential backoff with jitter, between half and the whole of the delay.
+    fn backoff(&self, retry: u32) -> Duration {
+        let delay = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff);
+        let half_delay = delay / 2;
+
+        half_delay + half_delay.mul_f64(fastrand::f64())
+    }
+}
+
+/// Whether sending the request again makes no other changes than sending it once.
+fn is_idempotent(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::DELETE)
+}
+
+/// The delay from the `Retry-After` header. Notion sends it as a number of seconds.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn backoff_grows_exponentially_up_to_the_limit() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(10),
+        };
+
+        for (retry, full_delay) in [(0, 1), (1, 2), (2, 4), (3, 8), (4, 10), (9, 10)] {
+            let delay = policy.backoff(retry);
+            let full_delay = Duration::from_secs(full_delay);
+            assert!(
+                delay >= full_delay / 2 && delay <= full_delay,
+                "retry {retry} waits {delay:?}, expected at most {full_delay:?}"
+            );
+        }
+    }
+
+    fn get_headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RETRY_AFTER, retry_after.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn retries_rate_limited_requests_after_the_requested_delay() {
+        let policy = RetryPolicy::default();
+
+        assert_eq!(
+            policy.retry_delay(
+                0,
+                &Method::GET,
+                StatusCode::TOO_MANY_REQUESTS,
+                &get_headers("7")
+            ),
+            Some(Duration::from_secs(7))
+        );
+        assert!(policy
+            .retry_delay(
+                0,
+                &Method::GET,
+                StatusCode::SERVICE_UNAVAILABLE,
+                &HeaderMap::new()
+            )
+            .is_some());
+    }
+
+    #[test]
+    fn does_not_retry_client_errors_or_after_max_retries() {
+        let policy = RetryPolicy::default();
+
+        assert_eq!(
+            policy.retry_delay(0, &Method::GET, StatusCode::BAD_REQUEST, &HeaderMap::new()),
+            None
+        );
+        assert_eq!(
+            policy.retry_delay(
+                0,
+                &Method::GET,
+                StatusCode::PAYLOAD_TOO_LARGE,
+                &HeaderMap::new()
+            ),
+            None
+        );
+        assert_eq!(
+            policy.retry_delay(
+                DEFAULT_MAX_RETRIES,
+                &Method::GET,
+                StatusCode::TOO_MANY_REQUESTS,
+                &get_headers("1")
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn retries_server_errors_only_for_idempotent_requests() {
+        let policy = RetryPolicy::default();
+
+        for method in [Method::GET, Method::DELETE] {
+            assert!(policy
+                .retry_delay(0, &method, StatusCode::BAD_GATEWAY, &HeaderMap::new())
+                .is_some());
+        }
+        for method in [Method::PATCH, Method::POST] {
+            assert_eq!(
+                policy.retry_delay(0, &method, StatusCode::BAD_GATEWAY, &HeaderMap::new()),
+                None
+            );
+            assert!(policy
+                .retry_delay(0, &method, StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new())
+                .is_some());
+        }
+    }
+}
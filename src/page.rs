@@ -0,0 +1,295 @@
+//! Fetching the content of pages as markdown and replacing it with markdown documents, the
+//! steps that the functions at the root of the crate and the commands of the CLI share.
+
+use std::collections::HashMap;
+use std::io;
+
+use notion::ids::{AsIdentifier, BlockId, PageId};
+use thiserror::Error;
+
+use crate::ids::page_url;
+use crate::markdown::anchors::{heading_anchors, qualify_heading_links, HeadingLinker};
+use crate::markdown::from_cmark::{ParseError, ParseOptions, PulldownCMarkEventParser};
+use crate::markdown::heading_offset::offset_headings;
+use crate::markdown::mentions::UserMentionMap;
+use crate::markdown::notion_interop::{NotionParseOptions, NotionToMarkdownParser};
+use crate::markdown::tag::Tag;
+use crate::markdown::to_cmark::{MarkdownWriter, SerializeOptions};
+use crate::markdown::warning::Warning;
+use crate::notion_api::client::{
+    back_up_page, erase_page, get_all_block_children, get_block_children_page, validate_blocks,
+    BackUpPageError, BlockWithChildrenToCreate, CheckAccessError, CreateBlocksError,
+    ErasePageError, GetBlockChildrenError, InvalidBlockError, NotionClient, UpdateBlockError,
+};
+use crate::notion_api::flatten;
+use crate::PushReport;
+
+/// Fetches the content of the page and converts it to markdown.
+pub async fn convert_page_to_markdown(
+    client: &NotionClient,
+    page_id: PageId,
+    parse_options: &NotionParseOptions,
+    serialize_options: &SerializeOptions,
+    user_mentions: &UserMentionMap,
+    warnings: &mut Vec<Warning>,
+) -> Result<String, GetBlockChildrenError> {
+    let mut buf = Vec::new();
+    let result = write_page_markdown(
+        client,
+        page_id,
+        parse_options,
+        serialize_options,
+        user_mentions,
+        warnings,
+        &mut buf,
+    )
+    .await;
+
+    match result {
+        Ok(()) => Ok(String::from_utf8(buf).expect("the markdown is UTF-8")),
+        Err(WriteMarkdownError::Fetch(error)) => Err(error),
+        Err(WriteMarkdownError::Write(error)) => {
+            unreachable!("writing to a Vec cannot fail: {error}")
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum WriteMarkdownError {
+    #[error("could not fetch the page: {0}")]
+    Fetch(#[from] GetBlockChildrenError),
+
+    #[error("could not write the markdown: {0}")]
+    Write(#[from] io::Error),
+}
+
+/// Fetches the page and writes it as markdown a page of blocks at a time, so long pages are
+/// written out while they are fetched instead of being held in memory as a whole.
+pub async fn write_page_markdown(
+    client: &NotionClient,
+    page_id: PageId,
+    parse_options: &NotionParseOptions,
+    serialize_options: &SerializeOptions,
+    user_mentions: &UserMentionMap,
+    warnings: &mut Vec<Warning>,
+    output: &mut impl io::Write,
+) -> Result<(), WriteMarkdownError> {
+    let mut parser = NotionToMarkdownParser::new(parse_options.clone());
+    let mut linker = HeadingLinker::new(&page_id.to_string());
+    let mut writer = MarkdownWriter::new(serialize_options.clone());
+    // NOTE: the warnings about the headings are reported after the ones about the blocks,
+    // the same as when the whole page is converted at once
+    let mut heading_warnings = Vec::new();
+
+    let block_id: BlockId = page_id.into();
+    let mut start_cursor = None;
+    let mut heading_count = 0;
+    loop {
+        let (blocks, next_cursor) =
+            get_block_children_page(client, &block_id, start_cursor.as_ref()).await?;
+        let mut tags = parser.parse_blocks(&blocks);
+        if next_cursor.is_none() {
+            tags.extend(parser.finish());
+        }
+
+        let heading_block_ids = &parser.heading_block_ids()[heading_count..];
+        heading_count += heading_block_ids.len();
+        let mut tags = linker.push(tags, heading_block_ids);
+        if next_cursor.is_none() {
+            tags.extend(linker.finish());
+        }
+        offset_headings(
+            &mut tags,
+            parse_options.heading_offset,
+            &mut heading_warnings,
+        );
+        user_mentions.render_user_mentions(&mut tags);
+
+        writer.write_tags(&tags);
+        output.write_all(writer.take_complete_lines().as_bytes())?;
+
+        match next_cursor {
+            Some(next_cursor) => start_cursor = Some(next_cursor),
+            None => break,
+        }
+    }
+    warnings.extend_from_slice(parser.warnings());
+    warnings.extend(heading_warnings);
+
+    let mut rest = writer.finish();
+    rest.push('\n');
+    output.write_all(rest.as_bytes())?;
+
+    Ok(())
+}
+
+#[derive(Error, Debug)]
+pub enum MarkdownToPageError {
+    #[error("{0}")]
+    CheckAccess(#[from] CheckAccessError),
+
+    #[error("cannot back up page")]
+    BackUpPage(#[from] BackUpPageError),
+
+    #[error("cannot erase page {0}")]
+    ErasePage(#[from] ErasePageError),
+
+    #[error("{} block(s) cannot be created, nothing was changed", .0.len())]
+    InvalidBlocks(Vec<InvalidBlockError>),
+
+    #[error("cannot create {} group(s) of blocks", .0.len())]
+    CreateBlocks(Vec<CreateBlocksError>),
+
+    #[error("cannot fetch page content")]
+    FetchPageContent(#[from] GetBlockChildrenError),
+
+    #[error("cannot link to the headings of the page: {0}")]
+    LinkHeadings(#[from] UpdateBlockError),
+}
+
+/// Parses the markdown document into tags, recreating the user mentions written as text.
+pub fn parse_markdown(
+    input: &str,
+    parse_options: ParseOptions,
+    user_mentions: &UserMentionMap,
+    warnings: &mut Vec<Warning>,
+) -> Result<Vec<Tag>, ParseError> {
+    let mut parser = PulldownCMarkEventParser::with_offsets(
+        pulldown_cmark::Parser::new_ext(input, parse_options.pulldown_cmark_options())
+            .into_offset_iter(),
+        input,
+        parse_options,
+    );
+    let mut markdown_tags = parser.parse()?;
+    warnings.extend_from_slice(parser.warnings());
+    user_mentions.recreate_user_mentions(&mut markdown_tags);
+
+    Ok(markdown_tags)
+}
+
+/// How the previous content of the page is removed before pushing.
+#[derive(Debug, Default, Clone)]
+pub struct EraseOptions {
+    pub keep_going: bool,
+    pub safe_erase: bool,
+    /// Blocks that are kept, like the child pages pushed along with the page.
+    pub kept_block_ids: Vec<BlockId>,
+}
+
+/// Replaces the contents of the page with the tags. The report has the created and the
+/// deleted blocks, and the page the content was backed up to with `safe_erase`.
+pub async fn replace_page_contents(
+    client: &NotionClient,
+    page_id: PageId,
+    mut tags: Vec<Tag>,
+    erase_options: &EraseOptions,
+) -> Result<PushReport, MarkdownToPageError> {
+    let link_headings = qualify_heading_links(&mut tags, &page_url(&page_id));
+    let blocks_to_create = blocks_to_create(tags)?;
+    // NOTE: an invalid API key is reported as such before anything is changed, instead of
+    // as a failure to erase the page
+    client.ensure_access().await?;
+
+    let backup_page = if erase_options.safe_erase {
+        Some(back_up_page(client, &page_id).await?)
+    } else {
+        None
+    };
+    let mut kept_block_ids = erase_options.kept_block_ids.clone();
+    kept_block_ids.extend(backup_page.as_ref().map(|page| page.id.clone().into()));
+    let deleted_blocks = erase_page(
+        client,
+        page_id.clone(),
+        erase_options.keep_going,
+        &kept_block_ids,
+    )
+    .await?;
+
+    let created_blocks = client
+        .create_blocks(page_id.as_id().clone().into(), blocks_to_create)
+        .await
+        .map_err(MarkdownToPageError::CreateBlocks)?;
+    if link_headings {
+        link_heading_anchors(client, &page_id, &page_id.clone().into()).await?;
+    }
+
+    Ok(PushReport {
+        created_blocks,
+        deleted_blocks,
+        backup_page_url: backup_page.map(|page| page.url),
+        ..Default::default()
+    })
+}
+
+/// The blocks to create for the tags, checked with [`validate_blocks`], so a push with
+/// blocks that Notion would reject fails before anything in the page is changed.
+pub fn blocks_to_create(
+    tags: Vec<Tag>,
+) -> Result<Vec<BlockWithChildrenToCreate>, MarkdownToPageError> {
+    let blocks: Vec<_> = tags
+        .into_iter()
+        .flat_map(BlockWithChildrenToCreate::from_markdown_tag)
+        .collect();
+    let errors = validate_blocks(&blocks);
+    if !errors.is_empty() {
+        return Err(MarkdownToPageError::InvalidBlocks(errors));
+    }
+
+    Ok(blocks)
+}
+
+/// Points the links to the anchors of the headings in the blocks under the parent, qualified
+/// by [`qualify_heading_links`], at the heading blocks. Notion has no anchors, so the links are
+/// updated once the headings are created.
+pub async fn link_heading_anchors(
+    client: &NotionClient,
+    page_id: &PageId,
+    parent_block_id: &BlockId,
+) -> Result<(), MarkdownToPageError> {
+    let blocks = get_all_block_children(client, parent_block_id).await?;
+    let mut parsed_tags =
+        NotionToMarkdownParser::new(NotionParseOptions::default()).feed(blocks.iter());
+    let tags: Vec<_> = parsed_tags.by_ref().collect();
+    let page_url = page_url(page_id);
+    let heading_links: HashMap<_, _> = heading_anchors(&tags)
+        .into_iter()
+        .zip(parsed_tags.heading_block_ids())
+        .map(|(anchor, block_id)| {
+            let link = format!("{page_url}#{}", block_id.replace('-', ""));
+            (format!("{page_url}#{anchor}"), link)
+        })
+        .collect();
+
+    for block in flatten(&blocks) {
+        let mut value = serde_json::to_value(&block.block).unwrap_or_default();
+        let Some(block_type) = value["type"].as_str().map(ToString::to_string) else {
+            continue;
+        };
+        let Some(rich_text) = value[&block_type]["rich_text"].as_array_mut() else {
+            continue;
+        };
+
+        let mut linked = false;
+        for text in rich_text.iter_mut() {
+            let link = text["text"]["link"]["url"]
+                .as_str()
+                .and_then(|url| heading_links.get(url));
+            if let Some(link) = link {
+                text["text"]["link"]["url"] = link.clone().into();
+                linked = true;
+            }
+            // NOTE: these fields are only in responses, the rest of the text is sent back
+            if let Some(text) = text.as_object_mut() {
+                text.remove("plain_text");
+                text.remove("href");
+            }
+        }
+        if linked {
+            client
+                .update_block_text(block.block.as_id(), &block_type, rich_text.clone().into())
+                .await?;
+        }
+    }
+
+    Ok(())
+}
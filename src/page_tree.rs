@@ -0,0 +1,564 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use notion::{ids::PageId, models::Block};
+use serde_yaml::Mapping;
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    files::{page_file_name, page_slug},
+    ids::page_url,
+    markdown::{frontmatter::FrontMatter, tag::Tag, warning::Warning},
+    notion_api::{client::PageSummary, flatten, BlockWithChildren},
+};
+
+/// A page fetched along with its child pages.
+pub struct FetchedPage {
+    pub id: PageId,
+    /// The file of the page, relative to the directory the pages are fetched into.
+    pub path: PathBuf,
+    pub page: PageSummary,
+    /// The properties of the page, as written in the front matter.
+    pub properties: Mapping,
+    pub tags: Vec<Tag>,
+}
+
+/// Names the files of the pages in a directory tree, where the child pages of `notes.md`
+/// go into `notes/`. Pages with the same title in a directory get a number after the title.
+#[derive(Debug, Default)]
+pub struct PagePaths {
+    used_paths: HashSet<PathBuf>,
+}
+
+impl PagePaths {
+    /// The file of the page with the `title`, next to the child pages of the page at
+    /// `parent_path`, or at the top of the tree for the root page.
+    pub fn assign(&mut self, parent_path: Option<&Path>, title: &str) -> PathBuf {
+        let directory = parent_path.map_or_else(PathBuf::new, |path| path.with_extension(""));
+
+        self.assign_file_name(&directory, &page_file_name(title))
+    }
+
+    /// The file with the `file_name` in the `directory`, or with a number after the name
+    /// when it is already used.
+    pub fn assign_file_name(&mut self, directory: &Path, file_name: &str) -> PathBuf {
+        let stem = file_name.trim_end_matches(".md");
+
+        let mut path = directory.join(file_name);
+        let mut number = 2;
+        while !self.used_paths.insert(path.clone()) {
+            path = directory.join(format!("{stem}-{number}.md"));
+            number += 1;
+        }
+
+        path
+    }
+}
+
+/// The file name of the database page from the `template`, where `{title}` is the slugified
+/// title (`untitled` for pages without one), `{id}` is the ID of the page and `{id8}` its first
+/// 8 characters.
+pub fn row_file_name(template: &str, page_id: &PageId, title: &str) -> String {
+    let id = page_id.to_string().replace('-', "");
+
+    template
+        .replace("{title}", &page_slug(title))
+        .replace("{id8}", &id[..8.min(id.len())])
+        .replace("{id}", &id)
+}
+
+/// The IDs of the child pages among the blocks and their children.
+pub fn child_page_ids(blocks: &[BlockWithChildren]) -> Vec<PageId> {
+    flatten(blocks)
+        .filter_map(|block| match &block.block {
+            Block::ChildPage { common, .. } => Some(
+                PageId::from_str(&common.id.to_string())
+                    .expect("notion crate PageId does not do any validation when parsing"),
+            ),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The files of the synced pages, to link between the files by relative path and between
+/// the pages by URL.
+#[derive(Debug, Clone, Default)]
+pub struct LinkResolver {
+    files: HashMap<String, PathBuf>,
+    pages: HashMap<PathBuf, PageId>,
+}
+
+impl LinkResolver {
+    /// Records that the page is synced to the file.
+    pub fn insert(&mut self, page_id: PageId, file: &Path) {
+        let file = absolute_path(file);
+        self.files.insert(page_id.to_string(), file.clone());
+        self.pages.insert(file, page_id);
+    }
+
+    /// The pages of the markdown files in the directory and its subdirectories, from the page
+    /// IDs in their front matter. Files without a page ID are skipped.
+    pub fn from_directory(directory: &Path) -> io::Result<Self> {
+        let mut resolver = Self::default();
+        for file in markdown_files(directory)? {
+            let path = directory.join(file);
+            let content = fs::read_to_string(&path)?;
+            let page_id = FrontMatter::split(&content)
+                .0
+                .and_then(|front_matter| front_matter.page_id().ok().flatten())
+                .and_then(|page_id| PageId::from_str(&page_id).ok());
+            if let Some(page_id) = page_id {
+                resolver.insert(page_id, &path);
+            }
+        }
+
+        Ok(resolver)
+    }
+
+    /// Points the links to the synced pages at their files, relative to the `file` with the
+    /// links, keeping the `#fragment` with the block they point to. Links to other Notion
+    /// pages are kept, with a warning.
+    pub fn link_files(&self, tags: &mut [Tag], file: &Path, warnings: &mut Vec<Warning>) {
+        let file = absolute_path(file);
+        let directory = file.parent().unwrap_or_else(|| Path::new(""));
+        let mut unresolved_links = Vec::new();
+        for tag in tags {
+            tag.visit_rich_text_mut(&mut |text| {
+                for link in text
+                    .iter_mut()
+                    .filter_map(|rich_text| rich_text.link.as_mut())
+                {
+                    let Some(page_id) = page_id_from_link(link) else {
+                        continue;
+                    };
+                    match self.files.get(&page_id) {
+                        Some(target) => {
+                            *link = relative_path(directory, target) + link_fragment(link)
+                        }
+                        None => unresolved_links.push(link.clone()),
+                    }
+                }
+            });
+        }
+        push_unresolved_links(unresolved_links, warnings);
+    }
+
+    /// Points the relative links to the synced files at their pages in Notion, keeping the
+    /// `#fragment`. Links to other markdown files are kept, with a warning.
+    pub fn link_pages(&self, tags: &mut [Tag], file: &Path, warnings: &mut Vec<Warning>) {
+        let file = absolute_path(file);
+        let directory = file.parent().unwrap_or_else(|| Path::new(""));
+        let mut unresolved_links = Vec::new();
+        for tag in tags {
+            tag.visit_rich_text_mut(&mut |text| {
+                for link in text
+                    .iter_mut()
+                    .filter_map(|rich_text| rich_text.link.as_mut())
+                {
+                    if Url::parse(link).is_ok() || link.starts_with('#') {
+                        continue;
+                    }
+                    let link_path = link.split('#').next().unwrap_or_default();
+                    match self.pages.get(&normalize_path(&directory.join(link_path))) {
+                        Some(page_id) => *link = page_url(page_id) + link_fragment(link),
+                        None if link_path.ends_with(".md") => unresolved_links.push(link.clone()),
+                        None => {}
+                    }
+                }
+            });
+        }
+        push_unresolved_links(unresolved_links, warnings);
+    }
+}
+
+/// The `#fragment` of the link, like the ID of a block in a link copied from Notion, which
+/// is kept when the rest of the link is replaced.
+fn link_fragment(link: &str) -> &str {
+    link.find('#').map_or("", |start| &link[start..])
+}
+
+fn push_unresolved_links(mut links: Vec<String>, warnings: &mut Vec<Warning>) {
+    if links.is_empty() {
+        return;
+    }
+    let mut seen = HashSet::new();
+    links.retain(|link| seen.insert(link.clone()));
+
+    warnings.push(Warning::LinksNotResolved { links });
+}
+
+/// The markdown files in the directory and its subdirectories, relative to the directory.
+/// Files closer to the top come first, so pages are visited before their child pages.
+/// Hidden files and directories are skipped.
+pub fn markdown_files(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut directories = vec![PathBuf::new()];
+    while let Some(relative_directory) = directories.pop() {
+        for entry in fs::read_dir(directory.join(&relative_directory))? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = relative_directory.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                directories.push(path);
+            } else if path.extension().is_some_and(|extension| extension == "md") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort_by(|a, b| {
+        a.components()
+            .count()
+            .cmp(&b.components().count())
+            .then_with(|| a.cmp(b))
+    });
+
+    Ok(files)
+}
+
+/// The path joined to the current directory when it is relative, without `.` and `..`.
+pub fn absolute_path(path: &Path) -> PathBuf {
+    let current_dir = std::env::current_dir().unwrap_or_default();
+    normalize_path(&current_dir.join(path))
+}
+
+/// Resolves `.` and `..` in the path without touching the filesystem.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// The hyphenated ID of the Notion page the link points to. Notion links end with the ID,
+/// possibly after the title of the page, like `https://www.notion.so/Notes-<id>` or `/<id>`.
+fn page_id_from_link(link: &str) -> Option<String> {
+    let path = if link.starts_with('/') {
+        link.split(['?', '#']).next()?.to_string()
+    } else {
+        let url = Url::parse(link).ok()?;
+        let host = url.host_str()?;
+        if !(host == "notion.so" || host.ends_with(".notion.so") || host.ends_with(".notion.site"))
+        {
+            return None;
+        }
+        url.path().to_string()
+    };
+
+    let last_segment = path.trim_end_matches('/').rsplit('/').next()?;
+    let uuid_candidate = last_segment.rsplit('-').next()?;
+    if uuid_candidate.len() != 32 {
+        return None;
+    }
+
+    Uuid::try_parse(uuid_candidate)
+        .ok()
+        .map(|uuid| uuid.hyphenated().to_string())
+}
+
+/// The path to `target` from the `directory`, with `/` as the separator, as used in links.
+pub fn relative_path(directory: &Path, target: &Path) -> String {
+    let directory: Vec<_> = directory.components().collect();
+    let target: Vec<_> = target.components().collect();
+    let common_length = directory
+        .iter()
+        .zip(&target)
+        .take_while(|(directory_component, target_component)| {
+            directory_component == target_component
+        })
+        .count();
+
+    let mut segments = vec!["..".to_string(); directory.len() - common_length];
+    segments.extend(
+        target[common_length..]
+            .iter()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned()),
+    );
+
+    segments.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::markdown::tag::{Paragraph, RichText};
+
+    use super::*;
+
+    const ROOT_ID: &str = "0b89a6e8-f006-4acc-8ec6-e6902b039e3a";
+    const CHILD_ID: &str = "ac32e025-6f9c-4fab-8b9d-dbb3c593ac46";
+
+    #[test]
+    fn assigns_files_next_to_the_child_pages() {
+        let mut page_paths = PagePaths::default();
+
+        let root = page_paths.assign(None, "Team notes");
+        assert_eq!(root, PathBuf::from("team-notes.md"));
+        assert_eq!(
+            page_paths.assign(Some(&root), "Meetings"),
+            PathBuf::from("team-notes/meetings.md")
+        );
+        assert_eq!(
+            page_paths.assign(Some(&root), "Meetings"),
+            PathBuf::from("team-notes/meetings-2.md")
+        );
+        assert_eq!(
+            page_paths.assign(Some(Path::new("team-notes/meetings.md")), "2022"),
+            PathBuf::from("team-notes/meetings/2022.md")
+        );
+    }
+
+    #[test]
+    fn names_database_pages_from_the_template() {
+        let page_id = PageId::from_str(ROOT_ID).unwrap();
+
+        assert_eq!(
+            row_file_name("{title}-{id8}.md", &page_id, "Launch plan: v2"),
+            "launch-plan-v2-0b89a6e8.md"
+        );
+        assert_eq!(
+            row_file_name("{title}-{id8}.md", &page_id, ""),
+            "untitled-0b89a6e8.md"
+        );
+        assert_eq!(
+            row_file_name("{id}.md", &page_id, "Launch"),
+            "0b89a6e8f0064acc8ec6e6902b039e3a.md"
+        );
+
+        let mut page_paths = PagePaths::default();
+        let directory = Path::new("rows");
+        assert_eq!(
+            page_paths.assign_file_name(directory, "untitled.md"),
+            PathBuf::from("rows/untitled.md")
+        );
+        assert_eq!(
+            page_paths.assign_file_name(directory, "untitled.md"),
+            PathBuf::from("rows/untitled-2.md")
+        );
+    }
+
+    #[test]
+    fn finds_page_ids_in_links() {
+        for link in [
+            "https://www.notion.so/0b89a6e8f0064acc8ec6e6902b039e3a",
+            "https://www.notion.so/gelio/Team-notes-0b89a6e8f0064acc8ec6e6902b039e3a?pvs=4",
+            "https://gelio.notion.site/Team-notes-0b89a6e8f0064acc8ec6e6902b039e3a",
+            "/0b89a6e8f0064acc8ec6e6902b039e3a#951424222c544b4896fc599a043f0c3d",
+        ] {
+            assert_eq!(page_id_from_link(link).as_deref(), Some(ROOT_ID), "{link}");
+        }
+
+        for link in [
+            "https://example.com/0b89a6e8f0064acc8ec6e6902b039e3a",
+            "https://www.notion.so/gelio",
+            "notes.md",
+        ] {
+            assert_eq!(page_id_from_link(link), None, "{link}");
+        }
+    }
+
+    fn get_page_with_link(id: &str, path: &str, link: &str) -> FetchedPage {
+        FetchedPage {
+            id: PageId::from_str(id).unwrap(),
+            path: PathBuf::from(path),
+            page: serde_json::from_value(json!({
+                "id": id,
+                "last_edited_time": "2022-08-01T12:00:00.000Z",
+                "properties": {}
+            }))
+            .unwrap(),
+            properties: Mapping::new(),
+            tags: vec![Tag::Paragraph(Paragraph {
+                text: vec![RichText {
+                    text: "Link".to_string(),
+                    link: Some(link.to_string()),
+                    mention: None,
+                }],
+            })],
+        }
+    }
+
+    fn get_link(page: &FetchedPage) -> Option<&str> {
+        match &page.tags[0] {
+            Tag::Paragraph(paragraph) => paragraph.text[0].link.as_deref(),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn lists_markdown_files_from_the_top() {
+        let directory = tempfile::tempdir().unwrap();
+        for file in [
+            "team-notes.md",
+            "team-notes/meetings.md",
+            "team-notes/draft.txt",
+            "archive/2021/retro.md",
+            ".notion-edit/state/last-sync.md",
+        ] {
+            let path = directory.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        assert_eq!(
+            markdown_files(directory.path()).unwrap(),
+            [
+                PathBuf::from("team-notes.md"),
+                PathBuf::from("team-notes/meetings.md"),
+                PathBuf::from("archive/2021/retro.md"),
+            ]
+        );
+    }
+
+    fn get_link_resolver() -> LinkResolver {
+        let mut links = LinkResolver::default();
+        links.insert(
+            PageId::from_str(ROOT_ID).unwrap(),
+            Path::new("/notes/team-notes.md"),
+        );
+        links.insert(
+            PageId::from_str(CHILD_ID).unwrap(),
+            Path::new("/notes/team-notes/meetings.md"),
+        );
+
+        links
+    }
+
+    #[test]
+    fn links_local_files_to_their_pages() {
+        let mut tags = [
+            get_page_with_link(CHILD_ID, "", "../team-notes.md").tags,
+            get_page_with_link(CHILD_ID, "", "./meetings.md#agenda").tags,
+            get_page_with_link(CHILD_ID, "", "other.md").tags,
+            get_page_with_link(CHILD_ID, "", "diagram.png").tags,
+            get_page_with_link(CHILD_ID, "", "https://example.com/meetings.md").tags,
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        let mut warnings = Vec::new();
+
+        get_link_resolver().link_pages(
+            &mut tags,
+            Path::new("/notes/team-notes/meetings.md"),
+            &mut warnings,
+        );
+
+        let links: Vec<_> = tags
+            .iter()
+            .map(|tag| match tag {
+                Tag::Paragraph(paragraph) => paragraph.text[0].link.as_deref(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            links,
+            [
+                Some("https://www.notion.so/0b89a6e8f0064acc8ec6e6902b039e3a"),
+                Some("https://www.notion.so/ac32e0256f9c4fab8b9ddbb3c593ac46#agenda"),
+                Some("other.md"),
+                Some("diagram.png"),
+                Some("https://example.com/meetings.md"),
+            ]
+        );
+        assert_eq!(
+            warnings,
+            [Warning::LinksNotResolved {
+                links: vec!["other.md".to_string()]
+            }]
+        );
+    }
+
+    #[test]
+    fn links_synced_pages_by_relative_paths() {
+        let mut pages = [
+            get_page_with_link(
+                ROOT_ID,
+                "/notes/team-notes.md",
+                "https://www.notion.so/ac32e0256f9c4fab8b9ddbb3c593ac46",
+            ),
+            get_page_with_link(
+                CHILD_ID,
+                "/notes/team-notes/meetings.md",
+                "/0b89a6e8f0064acc8ec6e6902b039e3a",
+            ),
+            get_page_with_link(
+                ROOT_ID,
+                "/notes/team-notes.md",
+                "https://www.notion.so/Meetings-ac32e0256f9c4fab8b9ddbb3c593ac46?pvs=4#951424222c544b4896fc599a043f0c3d",
+            ),
+            get_page_with_link(
+                "1b6f2c5f-9d1e-4d5b-8d6f-7e2a3c4b5d6e",
+                "/notes/team-notes/meetings/2022.md",
+                "https://www.notion.so/8d4e2f603c1b4a7e9f2d5b6a7c8d9e0f",
+            ),
+        ];
+        let links = get_link_resolver();
+        let mut warnings = Vec::new();
+
+        for page in &mut pages {
+            links.link_files(&mut page.tags, &page.path, &mut warnings);
+        }
+
+        assert_eq!(get_link(&pages[0]), Some("team-notes/meetings.md"));
+        assert_eq!(get_link(&pages[1]), Some("../team-notes.md"));
+        assert_eq!(
+            get_link(&pages[2]),
+            Some("team-notes/meetings.md#951424222c544b4896fc599a043f0c3d")
+        );
+        assert_eq!(
+            get_link(&pages[3]),
+            Some("https://www.notion.so/8d4e2f603c1b4a7e9f2d5b6a7c8d9e0f")
+        );
+        assert_eq!(
+            warnings,
+            [Warning::LinksNotResolved {
+                links: vec!["https://www.notion.so/8d4e2f603c1b4a7e9f2d5b6a7c8d9e0f".to_string()]
+            }]
+        );
+    }
+
+    #[test]
+    fn reads_the_pages_of_files_from_their_front_matter() {
+        let directory = tempfile::tempdir().unwrap();
+        fs::create_dir(directory.path().join("team-notes")).unwrap();
+        fs::write(
+            directory.path().join("team-notes.md"),
+            format!("---\nnotion_page_id: {ROOT_ID}\n---\n\nNotes\n"),
+        )
+        .unwrap();
+        fs::write(directory.path().join("team-notes/draft.md"), "Draft\n").unwrap();
+        let mut page = get_page_with_link(CHILD_ID, "", "../team-notes.md");
+        let mut warnings = Vec::new();
+
+        LinkResolver::from_directory(directory.path())
+            .unwrap()
+            .link_pages(
+                &mut page.tags,
+                &directory.path().join("team-notes/draft.md"),
+                &mut warnings,
+            );
+
+        assert_eq!(
+            get_link(&page),
+            Some("https://www.notion.so/0b89a6e8f0064acc8ec6e6902b039e3a")
+        );
+        assert!(warnings.is_empty());
+    }
+}
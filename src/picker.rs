@@ -0,0 +1,153 @@
+use std::io::{self, BufRead, Write};
+
+/// The most items listed at once. Typing a part of the label narrows the list down.
+const MAX_LISTED_ITEMS: usize = 20;
+
+/// Lets the user pick one of the `items` in the terminal, by typing a part of its label to
+/// filter the list and then the number of the item.
+///
+/// Returns `None` when the user enters an empty line or the input ends.
+pub fn pick<T>(
+    items: &[T],
+    label: impl Fn(&T) -> &str,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<Option<&T>> {
+    let mut matching_items: Vec<_> = items.iter().collect();
+
+    loop {
+        if matching_items.is_empty() {
+            writeln!(output, "No matches")?;
+        }
+        for (index, item) in matching_items.iter().take(MAX_LISTED_ITEMS).enumerate() {
+            writeln!(output, "{:>3}. {}", index + 1, label(item))?;
+        }
+        if matching_items.len() > MAX_LISTED_ITEMS {
+            writeln!(
+                output,
+                "     ... and {} more",
+                matching_items.len() - MAX_LISTED_ITEMS
+            )?;
+        }
+        write!(
+            output,
+            "Type to filter, or pick a number (empty to cancel): "
+        )?;
+        output.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        let listed_items = matching_items.len().min(MAX_LISTED_ITEMS);
+        match line.parse::<usize>() {
+            Ok(number) if (1..=listed_items).contains(&number) => {
+                return Ok(Some(matching_items[number - 1]));
+            }
+            _ => {
+                matching_items = items
+                    .iter()
+                    .filter(|item| fuzzy_matches(line, label(item)))
+                    .collect();
+            }
+        }
+    }
+}
+
+/// Asks the user a yes or no question in the terminal. Anything but `y` or `yes` is a no,
+/// including the end of the input.
+pub fn confirm(
+    question: &str,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<bool> {
+    write!(output, "{question} [y/N] ")?;
+    output.flush()?;
+
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+
+    Ok(matches!(line.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Whether the characters of the `query` appear in the `text` in the same order,
+/// ignoring case.
+fn fuzzy_matches(query: &str, text: &str) -> bool {
+    let mut text_chars = text.chars().flat_map(char::to_lowercase);
+
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter(|query_char| !query_char.is_whitespace())
+        .all(|query_char| text_chars.any(|text_char| text_char == query_char))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const PAGES: [&str; 4] = ["Meeting notes", "Reading list", "Roadmap", "Team meeting"];
+
+    fn pick_page(input: &str) -> (Option<&'static str>, String) {
+        let mut output = Vec::new();
+        let picked = pick(&PAGES, |page| page, input.as_bytes(), &mut output).unwrap();
+
+        (picked.copied(), String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn picks_by_number() {
+        let (picked, output) = pick_page("3\n");
+
+        assert_eq!(picked, Some("Roadmap"));
+        assert!(output.starts_with("  1. Meeting notes\n  2. Reading list\n  3. Roadmap\n"));
+    }
+
+    #[test]
+    fn numbers_the_filtered_items() {
+        let (picked, output) = pick_page("mtng\n2\n");
+
+        assert_eq!(picked, Some("Team meeting"));
+        assert!(output.contains("  1. Meeting notes\n  2. Team meeting\nType"));
+    }
+
+    #[test]
+    fn cancels_on_empty_input() {
+        assert_eq!(pick_page("\n").0, None);
+        assert_eq!(pick_page("road\n").0, None);
+    }
+
+    #[test]
+    fn confirms_only_with_yes() {
+        for (input, confirmed) in [
+            ("y\n", true),
+            ("YES\n", true),
+            ("n\n", false),
+            ("\n", false),
+            ("", false),
+        ] {
+            let mut output = Vec::new();
+
+            assert_eq!(
+                confirm("Push anyway?", input.as_bytes(), &mut output).unwrap(),
+                confirmed,
+                "{input:?}"
+            );
+            assert_eq!(String::from_utf8(output).unwrap(), "Push anyway? [y/N] ");
+        }
+    }
+
+    #[test]
+    fn matches_characters_in_order_ignoring_case() {
+        assert!(fuzzy_matches("rdmp", "Roadmap"));
+        assert!(fuzzy_matches("team MEET", "Team meeting"));
+        assert!(!fuzzy_matches("pmdr", "Roadmap"));
+    }
+}
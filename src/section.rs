@@ -0,0 +1,243 @@
+use std::ops::Range;
+
+use notion::{
+    ids::{AsIdentifier, BlockId},
+    models::{text::RichText, Block},
+};
+use thiserror::Error;
+
+/// A heading among the top-level blocks of a page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    pub id: BlockId,
+    pub level: u8,
+    pub text: String,
+}
+
+impl Heading {
+    fn from_block(block: &Block) -> Option<Self> {
+        let (level, rich_text) = match block {
+            Block::Heading1 { heading_1, .. } => (1, &heading_1.rich_text),
+            Block::Heading2 { heading_2, .. } => (2, &heading_2.rich_text),
+            Block::Heading3 { heading_3, .. } => (3, &heading_3.rich_text),
+            _ => return None,
+        };
+
+        Some(Self {
+            id: block.as_id().clone(),
+            level,
+            text: rich_text.iter().map(RichText::plain_text).collect(),
+        })
+    }
+}
+
+/// The blocks under a heading, up to the next heading of the same or a higher level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub heading: Heading,
+    /// The indices of the blocks of the section, without the heading.
+    pub blocks: Range<usize>,
+}
+
+#[derive(Error, Debug)]
+pub enum SectionError {
+    #[error("there is no heading \"{heading}\" in the page. {}", available_headings(.headings))]
+    NotFound {
+        heading: String,
+        headings: Vec<String>,
+    },
+
+    #[error("there are {count} headings \"{heading}\" in the page. {}", available_headings(.headings))]
+    Ambiguous {
+        heading: String,
+        count: usize,
+        headings: Vec<String>,
+    },
+}
+
+fn available_headings(headings: &[String]) -> String {
+    if headings.is_empty() {
+        return "The page has no headings".to_string();
+    }
+
+    let headings: Vec<_> = headings
+        .iter()
+        .map(|heading| format!("\"{heading}\""))
+        .collect();
+    format!("The headings of the page are: {}", headings.join(", "))
+}
+
+/// Finds the section under the heading with the text among the top-level blocks of a page.
+/// The text is compared exactly first, and ignoring the case when no heading matches exactly.
+pub fn find_section<'a>(
+    blocks: impl IntoIterator<Item = &'a Block>,
+    heading: &str,
+) -> Result<Section, SectionError> {
+    let blocks: Vec<_> = blocks.into_iter().collect();
+    let headings: Vec<_> = blocks
+        .iter()
+        .enumerate()
+        .filter_map(|(index, block)| Heading::from_block(block).map(|heading| (index, heading)))
+        .collect();
+
+    let mut matches: Vec<_> = (0..headings.len())
+        .filter(|&position| headings[position].1.text == heading)
+        .collect();
+    if matches.is_empty() {
+        let heading = heading.to_lowercase();
+        matches = (0..headings.len())
+            .filter(|&position| headings[position].1.text.to_lowercase() == heading)
+            .collect();
+    }
+
+    let heading_texts = || {
+        headings
+            .iter()
+            .map(|(_, heading)| heading.text.clone())
+            .collect()
+    };
+    let position = match matches.as_slice() {
+        [position] => *position,
+        [] => {
+            return Err(SectionError::NotFound {
+                heading: heading.to_string(),
+                headings: heading_texts(),
+            })
+        }
+        _ => {
+            return Err(SectionError::Ambiguous {
+                heading: heading.to_string(),
+                count: matches.len(),
+                headings: heading_texts(),
+            })
+        }
+    };
+
+    let (index, heading) = headings[position].clone();
+    let end = headings[position + 1..]
+        .iter()
+        .find(|(_, next_heading)| next_heading.level <= heading.level)
+        .map_or(blocks.len(), |(next_index, _)| *next_index);
+
+    Ok(Section {
+        heading,
+        blocks: index + 1..end,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn get_block(id: usize, block_type: &str, content: &str) -> Block {
+        let user = json!({ "object": "user", "id": "16d84278-ab0e-484c-9bdd-b35da3bd8905" });
+        let mut block = json!({
+            "object": "block",
+            "id": format!("00000000-0000-0000-0000-{id:012}"),
+            "type": block_type,
+            "created_time": "2022-08-01T12:00:00.000Z",
+            "last_edited_time": "2022-08-01T12:00:00.000Z",
+            "has_children": false,
+            "created_by": user,
+            "last_edited_by": user,
+        });
+        block[block_type] = json!({
+            "rich_text": [{
+                "type": "text",
+                "plain_text": content,
+                "href": null,
+                "text": { "content": content, "link": null }
+            }],
+            "color": "default"
+        });
+
+        serde_json::from_value::<Block>(block).unwrap()
+    }
+
+    fn get_page_blocks() -> Vec<Block> {
+        [
+            ("paragraph", "Intro"),
+            ("heading_1", "Plans"),
+            ("paragraph", "Plans intro"),
+            ("heading_2", "Launch"),
+            ("paragraph", "Launch details"),
+            ("heading_3", "Dates"),
+            ("paragraph", "Launch dates"),
+            ("heading_2", "Hiring"),
+            ("paragraph", "Hiring details"),
+            ("heading_1", "Notes"),
+            ("paragraph", "Last words"),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(id, (block_type, content))| get_block(id, block_type, content))
+        .collect()
+    }
+
+    #[test]
+    fn section_ends_at_the_next_heading_of_the_same_or_higher_level() {
+        let blocks = get_page_blocks();
+
+        for (heading, expected_blocks) in [
+            ("Plans", 2..9),
+            ("Launch", 4..7),
+            ("Dates", 6..7),
+            ("Hiring", 8..9),
+            ("Notes", 10..11),
+        ] {
+            let section = find_section(&blocks, heading).unwrap();
+
+            assert_eq!(section.heading.text, heading);
+            assert_eq!(section.blocks, expected_blocks, "{heading}");
+        }
+    }
+
+    #[test]
+    fn falls_back_to_headings_with_another_case() {
+        let mut blocks = get_page_blocks();
+
+        let section = find_section(&blocks, "launch").unwrap();
+        assert_eq!(section.blocks, 4..7);
+
+        blocks.push(get_block(11, "heading_2", "launch"));
+        let section = find_section(&blocks, "launch").unwrap();
+        assert_eq!(section.blocks, 12..12, "the exact match wins");
+    }
+
+    #[test]
+    fn lists_the_headings_when_the_heading_is_missing_or_ambiguous() {
+        let mut blocks = get_page_blocks();
+
+        let error = find_section(&blocks, "Budget").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "there is no heading \"Budget\" in the page. The headings of the page are: \"Plans\", \"Launch\", \"Dates\", \"Hiring\", \"Notes\""
+        );
+
+        blocks.push(get_block(11, "heading_3", "dates"));
+        let error = find_section(&blocks, "DATES").unwrap_err();
+        assert!(matches!(error, SectionError::Ambiguous { count: 2, .. }));
+
+        let error = find_section(&Vec::<Block>::new(), "Plans").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "there is no heading \"Plans\" in the page. The page has no headings"
+        );
+    }
+
+    #[test]
+    fn reads_the_text_of_headings_with_several_spans() {
+        let mut block: Value = serde_json::to_value(get_block(0, "heading_1", "Launch ")).unwrap();
+        let span = block["heading_1"]["rich_text"][0].clone();
+        let mut second_span = span.clone();
+        second_span["plain_text"] = json!("plan");
+        second_span["text"]["content"] = json!("plan");
+        block["heading_1"]["rich_text"] = json!([span, second_span]);
+        let blocks = vec![serde_json::from_value(block).unwrap()];
+
+        assert_eq!(find_section(&blocks, "Launch plan").unwrap().blocks, 1..1);
+    }
+}
@@ -0,0 +1,578 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use fs2::FileExt;
+use notion::ids::PageId;
+
+use crate::atomic_file::write_atomically;
+
+pub const DEFAULT_STATE_DIR: &str = ".notion-edit";
+pub const STATE_DIR_ENV_VAR: &str = "NOTION_EDIT_STATE_DIR";
+
+/// The file in the page's state directory with the content of the last successful fetch
+/// or push, used to tell if the local file has changes that were not synced yet.
+pub const LAST_SYNC_STATE_FILE: &str = "last-sync.md";
+/// The file in the page's state directory with the `last_edited_time` of the page after
+/// the last successful fetch or push, used to tell if the page was edited in Notion since.
+pub const LAST_EDITED_TIME_STATE_FILE: &str = "last-edited-time";
+/// The file in the page's state directory with the progress of a push that replaces the
+/// content of the page, used to resume it with `push --resume` when it is interrupted.
+pub const PUSH_JOURNAL_STATE_FILE: &str = "push-journal.json";
+/// How many local backups of each page are kept when the config does not say otherwise.
+pub const DEFAULT_MAX_BACKUPS: usize = 5;
+const BACKUP_EXTENSION: &str = "md";
+
+/// Kinds of files stored in the state directory. Each kind lives in its own subdirectory
+/// and is further namespaced by page ID.
+// NOTE: not every kind of state is used yet
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateKind {
+    Backups,
+    Cache,
+    Locks,
+    State,
+}
+
+impl StateKind {
+    fn directory_name(&self) -> &'static str {
+        match self {
+            StateKind::Backups => "backups",
+            StateKind::Cache => "cache",
+            StateKind::Locks => "locks",
+            StateKind::State => "state",
+        }
+    }
+}
+
+/// The directory holding all the files the tool persists between runs
+/// (backups, caches, locks, sync state).
+///
+/// Directories are created lazily, only when something is written to them.
+#[derive(Debug, Clone)]
+pub struct StateDir {
+    root: PathBuf,
+}
+
+impl StateDir {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// Resolves the state directory location. The explicit override (from the CLI)
+    /// takes precedence over the environment variable, which takes precedence over
+    /// the project-local `.notion-edit` directory.
+    pub fn resolve(explicit_root: Option<PathBuf>) -> Self {
+        let root = explicit_root
+            .or_else(|| std::env::var_os(STATE_DIR_ENV_VAR).map(PathBuf::from))
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_STATE_DIR));
+
+        Self::new(root)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The directory of the blocks cached between fetches, by block ID.
+    pub fn block_cache_dir(&self) -> PathBuf {
+        self.root
+            .join(StateKind::Cache.directory_name())
+            .join("blocks")
+    }
+
+    /// The directory for files of the given kind that belong to a single page.
+    /// Does not create the directory.
+    pub fn page_dir(&self, kind: StateKind, page_id: &PageId) -> PathBuf {
+        self.root
+            .join(kind.directory_name())
+            .join(page_id.to_string())
+    }
+
+    pub fn page_file_path(&self, kind: StateKind, page_id: &PageId, file_name: &str) -> PathBuf {
+        self.page_dir(kind, page_id).join(file_name)
+    }
+
+    /// Reads a page's state file. Returns `None` if the file does not exist.
+    pub fn read_page_file(
+        &self,
+        kind: StateKind,
+        page_id: &PageId,
+        file_name: &str,
+    ) -> io::Result<Option<String>> {
+        match fs::read_to_string(self.page_file_path(kind, page_id, file_name)) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Atomically writes a page's state file while holding the page's lock, so concurrent
+    /// writers never observe or produce partially written files.
+    #[allow(dead_code)]
+    pub fn write_page_file(
+        &self,
+        kind: StateKind,
+        page_id: &PageId,
+        file_name: &str,
+        contents: &[u8],
+    ) -> io::Result<PathBuf> {
+        let lock = self.lock_page(page_id)?;
+
+        self.write_page_file_locked(&lock, kind, page_id, file_name, contents)
+    }
+
+    /// Like [`StateDir::write_page_file`], for callers that already hold the page's lock.
+    // NOTE: locking again would block, as the lock is not reentrant
+    pub fn write_page_file_locked(
+        &self,
+        _lock: &PageLock,
+        kind: StateKind,
+        page_id: &PageId,
+        file_name: &str,
+        contents: &[u8],
+    ) -> io::Result<PathBuf> {
+        let directory = self.page_dir(kind, page_id);
+        create_private_dir(&directory)?;
+        let path = directory.join(file_name);
+        write_atomically(&path, |writer| writer.write_all(contents))?;
+
+        Ok(path)
+    }
+
+    /// Removes a page's state file if it exists. The caller must hold the page's lock.
+    pub fn remove_page_file_locked(
+        &self,
+        _lock: &PageLock,
+        kind: StateKind,
+        page_id: &PageId,
+        file_name: &str,
+    ) -> io::Result<()> {
+        match fs::remove_file(self.page_file_path(kind, page_id, file_name)) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes a backup of the page's content, named after the time it was taken, and removes
+    /// the oldest backups so at most `max_backups` are left. The caller must hold the page's
+    /// lock.
+    pub fn write_backup_locked(
+        &self,
+        lock: &PageLock,
+        page_id: &PageId,
+        time: SystemTime,
+        contents: &str,
+        max_backups: usize,
+    ) -> io::Result<PathBuf> {
+        // NOTE: colons are not allowed in file names on Windows
+        let timestamp = humantime::format_rfc3339_millis(time)
+            .to_string()
+            .replace(':', "-");
+        let path = self.write_page_file_locked(
+            lock,
+            StateKind::Backups,
+            page_id,
+            &format!("{timestamp}.{BACKUP_EXTENSION}"),
+            contents.as_bytes(),
+        )?;
+
+        let backups = self.backups(page_id)?;
+        for timestamp in &backups[..backups.len().saturating_sub(max_backups)] {
+            fs::remove_file(self.backup_path(page_id, timestamp))?;
+        }
+
+        Ok(path)
+    }
+
+    /// The timestamps of the page's backups, from the oldest to the newest.
+    pub fn backups(&self, page_id: &PageId) -> io::Result<Vec<String>> {
+        let mut timestamps = Vec::new();
+        for entry in read_dir_if_exists(&self.page_dir(StateKind::Backups, page_id))? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some(BACKUP_EXTENSION) {
+                continue;
+            }
+            if let Some(timestamp) = path.file_stem().and_then(|stem| stem.to_str()) {
+                timestamps.push(timestamp.to_string());
+            }
+        }
+        // NOTE: the timestamps have a fixed width, so they sort chronologically
+        timestamps.sort();
+
+        Ok(timestamps)
+    }
+
+    /// Reads the page's backup with the timestamp. Returns `None` if there is no such backup.
+    pub fn read_backup(&self, page_id: &PageId, timestamp: &str) -> io::Result<Option<String>> {
+        self.read_page_file(
+            StateKind::Backups,
+            page_id,
+            &format!("{timestamp}.{BACKUP_EXTENSION}"),
+        )
+    }
+
+    fn backup_path(&self, page_id: &PageId, timestamp: &str) -> PathBuf {
+        self.page_file_path(
+            StateKind::Backups,
+            page_id,
+            &format!("{timestamp}.{BACKUP_EXTENSION}"),
+        )
+    }
+
+    /// Acquires an exclusive lock for the page. Blocks until the lock is available.
+    /// The lock is released when the returned guard is dropped.
+    pub fn lock_page(&self, page_id: &PageId) -> io::Result<PageLock> {
+        let locks_dir = self.root.join(StateKind::Locks.directory_name());
+        create_private_dir(&locks_dir)?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(locks_dir.join(format!("{page_id}.lock")))?;
+        file.lock_exclusive()?;
+        // NOTE: bump the modification time so `gc` can tell stale locks apart
+        file.set_modified(SystemTime::now())?;
+
+        Ok(PageLock { file })
+    }
+
+    /// Removes backups older than the configured age and lock files that are both old and
+    /// not held by any process.
+    pub fn gc(&self, policy: &GcPolicy) -> io::Result<GcReport> {
+        let now = SystemTime::now();
+        let mut report = GcReport::default();
+
+        let backups_dir = self.root.join(StateKind::Backups.directory_name());
+        for page_dir in read_dir_if_exists(&backups_dir)? {
+            let page_dir = page_dir?.path();
+            if !page_dir.is_dir() {
+                continue;
+            }
+
+            for backup in fs::read_dir(&page_dir)? {
+                let backup = backup?.path();
+                if is_older_than(&backup, policy.max_backup_age, now)? {
+                    fs::remove_file(&backup)?;
+                    report.removed_backups.push(backup);
+                }
+            }
+
+            if fs::read_dir(&page_dir)?.next().is_none() {
+                fs::remove_dir(&page_dir)?;
+            }
+        }
+
+        let locks_dir = self.root.join(StateKind::Locks.directory_name());
+        for lock in read_dir_if_exists(&locks_dir)? {
+            let lock = lock?.path();
+            if !is_older_than(&lock, policy.max_lock_age, now)? {
+                continue;
+            }
+
+            let file = File::open(&lock)?;
+            if file.try_lock_exclusive().is_ok() {
+                fs::remove_file(&lock)?;
+                report.removed_locks.push(lock);
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// An exclusive lock on a page's state. Released on drop.
+#[derive(Debug)]
+pub struct PageLock {
+    file: File,
+}
+
+impl Drop for PageLock {
+    fn drop(&mut self) {
+        // NOTE: the lock is also released when the file is closed. Unlocking explicitly
+        // only makes the release happen as early as possible.
+        self.file.unlock().ok();
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GcPolicy {
+    pub max_backup_age: Duration,
+    pub max_lock_age: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct GcReport {
+    pub removed_backups: Vec<PathBuf>,
+    pub removed_locks: Vec<PathBuf>,
+}
+
+fn create_private_dir(path: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+
+    builder.create(path)
+}
+
+fn read_dir_if_exists(
+    path: &Path,
+) -> io::Result<Box<dyn Iterator<Item = io::Result<fs::DirEntry>>>> {
+    match fs::read_dir(path) {
+        Ok(entries) => Ok(Box::new(entries)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Box::new(std::iter::empty())),
+        Err(error) => Err(error),
+    }
+}
+
+fn is_older_than(path: &Path, max_age: Duration, now: SystemTime) -> io::Result<bool> {
+    let modified = fs::metadata(path)?.modified()?;
+
+    Ok(now.duration_since(modified).is_ok_and(|age| age > max_age))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc, thread};
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn get_page_id() -> PageId {
+        PageId::from_str("0b89a6e8-f006-4acc-8ec6-e6902b039e3a").unwrap()
+    }
+
+    fn set_age(path: &Path, age: Duration) {
+        let file = OpenOptions::new().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    #[test]
+    fn creates_directories_lazily() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(temp_dir.path().join(DEFAULT_STATE_DIR));
+
+        assert_eq!(
+            state_dir
+                .read_page_file(StateKind::State, &get_page_id(), "page.md")
+                .unwrap(),
+            None
+        );
+        assert!(
+            !state_dir.root().exists(),
+            "reading does not create anything"
+        );
+
+        let path = state_dir
+            .write_page_file(StateKind::State, &get_page_id(), "page.md", b"content")
+            .unwrap();
+
+        assert_eq!(
+            path,
+            temp_dir
+                .path()
+                .join(".notion-edit/state/0b89a6e8-f006-4acc-8ec6-e6902b039e3a/page.md")
+        );
+        assert_eq!(
+            state_dir
+                .read_page_file(StateKind::State, &get_page_id(), "page.md")
+                .unwrap()
+                .as_deref(),
+            Some("content")
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = fs::metadata(state_dir.root()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+    }
+
+    #[test]
+    fn writes_and_removes_files_while_holding_the_lock() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(temp_dir.path().to_path_buf());
+        let page_id = get_page_id();
+
+        let lock = state_dir.lock_page(&page_id).unwrap();
+        state_dir
+            .write_page_file_locked(&lock, StateKind::State, &page_id, "page.md", b"content")
+            .unwrap();
+        assert_eq!(
+            state_dir
+                .read_page_file(StateKind::State, &page_id, "page.md")
+                .unwrap()
+                .as_deref(),
+            Some("content")
+        );
+
+        for _ in 0..2 {
+            state_dir
+                .remove_page_file_locked(&lock, StateKind::State, &page_id, "page.md")
+                .unwrap();
+        }
+        assert_eq!(
+            state_dir
+                .read_page_file(StateKind::State, &page_id, "page.md")
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn explicit_root_takes_precedence() {
+        let state_dir = StateDir::resolve(Some(PathBuf::from("/tmp/custom")));
+
+        assert_eq!(state_dir.root(), Path::new("/tmp/custom"));
+    }
+
+    #[test]
+    fn concurrent_writers_do_not_interleave() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let state_dir = Arc::new(StateDir::new(temp_dir.path().to_path_buf()));
+        let contents: Vec<String> = (0..8)
+            .map(|writer| format!("writer {writer}\n").repeat(10_000))
+            .collect();
+
+        let handles: Vec<_> = contents
+            .iter()
+            .cloned()
+            .map(|content| {
+                let state_dir = Arc::clone(&state_dir);
+                thread::spawn(move || {
+                    state_dir
+                        .write_page_file(
+                            StateKind::Cache,
+                            &get_page_id(),
+                            "blocks.json",
+                            content.as_bytes(),
+                        )
+                        .unwrap();
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .for_each(|handle| handle.join().unwrap());
+
+        let final_content = state_dir
+            .read_page_file(StateKind::Cache, &get_page_id(), "blocks.json")
+            .unwrap()
+            .unwrap();
+        assert!(
+            contents.contains(&final_content),
+            "the file contains exactly one of the writes"
+        );
+        assert_eq!(
+            fs::read_dir(state_dir.page_dir(StateKind::Cache, &get_page_id()))
+                .unwrap()
+                .count(),
+            1,
+            "no temporary files are left behind"
+        );
+    }
+
+    #[test]
+    fn keeps_only_the_newest_backups() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(temp_dir.path().to_path_buf());
+        let page_id = get_page_id();
+        let lock = state_dir.lock_page(&page_id).unwrap();
+        let start = humantime::parse_rfc3339("2022-08-01T12:00:00Z").unwrap();
+
+        for minute in 0..4 {
+            state_dir
+                .write_backup_locked(
+                    &lock,
+                    &page_id,
+                    start + Duration::from_secs(minute * 60),
+                    &format!("content {minute}\n"),
+                    2,
+                )
+                .unwrap();
+        }
+
+        assert_eq!(
+            state_dir.backups(&page_id).unwrap(),
+            vec![
+                "2022-08-01T12-02-00.000Z".to_string(),
+                "2022-08-01T12-03-00.000Z".to_string(),
+            ]
+        );
+        assert_eq!(
+            state_dir
+                .read_backup(&page_id, "2022-08-01T12-03-00.000Z")
+                .unwrap()
+                .as_deref(),
+            Some("content 3\n")
+        );
+        assert_eq!(
+            state_dir
+                .read_backup(&page_id, "2022-08-01T12-00-00.000Z")
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn gc_removes_old_backups_only() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(temp_dir.path().to_path_buf());
+        let old_backup = state_dir
+            .write_page_file(StateKind::Backups, &get_page_id(), "old.md", b"old")
+            .unwrap();
+        let new_backup = state_dir
+            .write_page_file(StateKind::Backups, &get_page_id(), "new.md", b"new")
+            .unwrap();
+        set_age(&old_backup, Duration::from_secs(40 * 24 * 60 * 60));
+
+        let report = state_dir
+            .gc(&GcPolicy {
+                max_backup_age: Duration::from_secs(30 * 24 * 60 * 60),
+                max_lock_age: Duration::from_secs(60 * 60),
+            })
+            .unwrap();
+
+        assert_eq!(report.removed_backups, vec![old_backup.clone()]);
+        assert!(!old_backup.exists());
+        assert!(new_backup.exists());
+    }
+
+    #[test]
+    fn gc_removes_stale_locks_that_are_not_held() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(temp_dir.path().to_path_buf());
+        let policy = GcPolicy {
+            max_backup_age: Duration::from_secs(30 * 24 * 60 * 60),
+            max_lock_age: Duration::from_secs(60 * 60),
+        };
+        let lock_path = temp_dir
+            .path()
+            .join("locks/0b89a6e8-f006-4acc-8ec6-e6902b039e3a.lock");
+
+        let lock = state_dir.lock_page(&get_page_id()).unwrap();
+        set_age(&lock_path, Duration::from_secs(2 * 60 * 60));
+
+        let report = state_dir.gc(&policy).unwrap();
+        assert!(report.removed_locks.is_empty(), "held locks are kept");
+        assert!(lock_path.exists());
+
+        drop(lock);
+        set_age(&lock_path, Duration::from_secs(2 * 60 * 60));
+        let report = state_dir.gc(&policy).unwrap();
+        assert_eq!(report.removed_locks, vec![lock_path.clone()]);
+        assert!(!lock_path.exists());
+    }
+}
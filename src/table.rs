@@ -0,0 +1,57 @@
+/// Lays out the rows in columns separated by two spaces, under the `header`. The last
+/// column is not padded, so lines do not end with spaces.
+pub fn format_table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|cell| cell.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header = header.iter().map(|cell| cell.to_string()).collect();
+    let mut table = String::new();
+    for row in std::iter::once(&header).chain(rows) {
+        let last_column = row.len().saturating_sub(1);
+        for (column, (cell, width)) in row.iter().zip(&widths).enumerate() {
+            if column == last_column {
+                table.push_str(cell);
+            } else {
+                table.push_str(cell);
+                table.push_str(&" ".repeat(width - cell.chars().count() + 2));
+            }
+        }
+        table.push('\n');
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn pads_all_columns_but_the_last() {
+        let rows = vec![
+            vec![
+                "page".to_string(),
+                "Café menu".to_string(),
+                "workspace".to_string(),
+            ],
+            vec![
+                "database".to_string(),
+                "".to_string(),
+                "page 1b6f".to_string(),
+            ],
+        ];
+
+        assert_eq!(
+            format_table(&["TYPE", "TITLE", "PARENT"], &rows),
+            "TYPE      TITLE      PARENT\n\
+             page      Café menu  workspace\n\
+             database             page 1b6f\n"
+        );
+    }
+}
@@ -0,0 +1,164 @@
+//! Markdown templates with `{{variable}}` placeholders, for `new`.
+
+use std::collections::{BTreeSet, HashMap};
+
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TemplateError {
+    #[error("the template uses variables that were not given: {}", .0.join(", "))]
+    MissingVariables(Vec<String>),
+}
+
+/// The template with the placeholders replaced by the values of their variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedTemplate {
+    pub content: String,
+    /// The variables that were given, but are not used by the template, in order.
+    pub unused_variables: Vec<String>,
+}
+
+/// Replaces the `{{variable}}` placeholders of the template with the values of the variables.
+/// Spaces around the name are allowed, like `{{ date }}`. The substitution is done on the raw
+/// text, so placeholders in code blocks and headings are replaced too. `\{{literal}}` is kept
+/// as `{{literal}}`.
+pub fn render_template(
+    template: &str,
+    variables: &HashMap<String, String>,
+) -> Result<RenderedTemplate, TemplateError> {
+    let mut content = String::with_capacity(template.len());
+    let mut missing_variables = BTreeSet::new();
+    let mut used_variables = BTreeSet::new();
+
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        if rest[..start].ends_with('\\') {
+            content.push_str(&rest[..start - 1]);
+            content.push_str("{{");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        content.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let name = rest[2..]
+            .find("}}")
+            .map(|end| (rest[2..2 + end].trim(), end + 4))
+            .filter(|(name, _)| is_variable_name(name));
+        match name {
+            Some((name, placeholder_length)) => {
+                match variables.get(name) {
+                    Some(value) => {
+                        content.push_str(value);
+                        used_variables.insert(name);
+                    }
+                    None => {
+                        missing_variables.insert(name.to_string());
+                    }
+                }
+                rest = &rest[placeholder_length..];
+            }
+            None => {
+                content.push_str("{{");
+                rest = &rest[2..];
+            }
+        }
+    }
+    content.push_str(rest);
+
+    if !missing_variables.is_empty() {
+        return Err(TemplateError::MissingVariables(
+            missing_variables.into_iter().collect(),
+        ));
+    }
+    let mut unused_variables: Vec<_> = variables
+        .keys()
+        .filter(|name| !used_variables.contains(name.as_str()))
+        .cloned()
+        .collect();
+    unused_variables.sort();
+
+    Ok(RenderedTemplate {
+        content,
+        unused_variables,
+    })
+}
+
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn variables(variables: &[(&str, &str)]) -> HashMap<String, String> {
+        variables
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn replaces_placeholders_everywhere() {
+        let rendered = render_template(
+            "# Meeting {{date}}\n\nAttendees: {{ attendees }}\n\n```\n{{date}}\n```\n",
+            &variables(&[("date", "2024-05-02"), ("attendees", "A, B")]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            rendered,
+            RenderedTemplate {
+                content: "# Meeting 2024-05-02\n\nAttendees: A, B\n\n```\n2024-05-02\n```\n"
+                    .to_string(),
+                unused_variables: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn reports_all_the_missing_variables() {
+        assert_eq!(
+            render_template(
+                "{{title}} on {{date}} with {{attendees}} at {{date}}",
+                &variables(&[("date", "2024-05-02")])
+            ),
+            Err(TemplateError::MissingVariables(vec![
+                "attendees".to_string(),
+                "title".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn lists_the_unused_variables() {
+        let rendered = render_template(
+            "{{date}}",
+            &variables(&[("room", "B"), ("date", "2024-05-02"), ("agenda", "")]),
+        )
+        .unwrap();
+
+        assert_eq!(rendered.content, "2024-05-02");
+        assert_eq!(rendered.unused_variables, ["agenda", "room"]);
+    }
+
+    #[test]
+    fn keeps_escaped_and_invalid_placeholders() {
+        let rendered = render_template(
+            "\\{{date}} is {{date}}, {{ }}, {{not a name}} and {{date",
+            &variables(&[("date", "2024-05-02")]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            rendered.content,
+            "{{date}} is 2024-05-02, {{ }}, {{not a name}} and {{date"
+        );
+    }
+}
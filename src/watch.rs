@@ -0,0 +1,132 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use notify::{event::EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::{mpsc, oneshot};
+
+/// How long the file has to stay untouched before a change is reported. Editors often write
+/// a file in a few steps (a temporary file, a rename, a metadata update).
+pub const DEBOUNCE_DELAY: Duration = Duration::from_millis(500);
+
+/// Watches the file for changes. The changes are received until the watcher is dropped.
+pub fn watch_file(
+    path: &Path,
+) -> notify::Result<(RecommendedWatcher, mpsc::UnboundedReceiver<()>)> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let file_name: Option<OsString> = path.file_name().map(Into::into);
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let is_change = match event {
+            Ok(event) => {
+                !matches!(event.kind, EventKind::Access(_))
+                    && event
+                        .paths
+                        .iter()
+                        .any(|path| path.file_name().map(Into::into) == file_name)
+            }
+            // NOTE: an error may mean missed events, so the file is checked just in case
+            Err(_) => true,
+        };
+        if is_change {
+            // NOTE: the receiver is gone only when watching stopped
+            sender.send(()).ok();
+        }
+    })?;
+
+    // NOTE: the directory is watched, as editors often replace the file instead of writing it
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+
+    Ok((watcher, receiver))
+}
+
+/// Waits for the next change, and then for the changes to stop for the `delay`.
+/// Returns `None` when no more changes can be received.
+pub async fn next_change(changes: &mut mpsc::UnboundedReceiver<()>, delay: Duration) -> Option<()> {
+    changes.recv().await?;
+
+    loop {
+        match tokio::time::timeout(delay, changes.recv()).await {
+            Ok(Some(())) => {}
+            Ok(None) | Err(_) => return Some(()),
+        }
+    }
+}
+
+/// Resolves when Ctrl-C is pressed. Presses while something else is awaited are not missed,
+/// so the sync in progress finishes before stopping.
+pub fn on_ctrl_c() -> oneshot::Receiver<()> {
+    let (sender, receiver) = oneshot::channel();
+    tokio::spawn(async move {
+        match tokio::signal::ctrl_c().await {
+            Ok(()) => {
+                sender.send(()).ok();
+            }
+            // NOTE: without the signal handler, watching continues until the process is killed
+            Err(_) => {
+                futures::future::pending::<()>().await;
+                drop(sender);
+            }
+        }
+    });
+
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const TEST_DELAY: Duration = Duration::from_millis(50);
+
+    #[tokio::test]
+    async fn reports_a_burst_of_changes_once() {
+        let (sender, mut changes) = mpsc::unbounded_channel();
+        for _ in 0..3 {
+            sender.send(()).unwrap();
+        }
+
+        assert_eq!(next_change(&mut changes, TEST_DELAY).await, Some(()));
+        assert!(changes.try_recv().is_err());
+
+        drop(sender);
+        assert_eq!(next_change(&mut changes, TEST_DELAY).await, None);
+    }
+
+    #[tokio::test]
+    async fn notices_when_the_file_is_written() {
+        let directory = tempfile::tempdir().unwrap();
+        let file = directory.path().join("page.md");
+        fs::write(&file, "Before").unwrap();
+        let (_watcher, mut changes) = watch_file(&file).unwrap();
+
+        fs::write(directory.path().join("other.md"), "Unrelated").unwrap();
+        assert!(
+            tokio::time::timeout(TEST_DELAY * 4, next_change(&mut changes, TEST_DELAY))
+                .await
+                .is_err(),
+            "other files are not changes"
+        );
+
+        fs::write(&file, "After").unwrap();
+        assert_eq!(
+            tokio::time::timeout(
+                Duration::from_secs(5),
+                next_change(&mut changes, TEST_DELAY)
+            )
+            .await
+            .expect("change noticed"),
+            Some(())
+        );
+    }
+}